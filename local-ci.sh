@@ -108,6 +108,7 @@ echo "==================="
 
 # Standard tests
 run_check "Unit Tests" "cargo test --verbose"
+run_check "Unit Tests (no AMM)" "cargo test --no-default-features --verbose"
 
 # Build check
 run_check "Build Check" "cargo build --verbose"
@@ -0,0 +1,362 @@
+//! Global borrow index accrual with catch-up protection
+//!
+//! Long idle periods are accrued in capped chunks so a single call can never
+//! compound an enormous gap at once. Progress is persisted, and repeated calls
+//! to `accrue` finish the catch-up.
+
+use crate::capabilities::FeatureFlags;
+use crate::checkpoints::IndexCheckpoints;
+use crate::config;
+use crate::defaults;
+use crate::{
+    EventTracker, InterestRateState, InterestRateStorage, ProtocolConfig, ProtocolError,
+    ProtocolEvent, TokenRegistry,
+};
+use soroban_sdk::{contracttype, Address, Env, Symbol, Vec};
+
+/// Index scale (1.0 = 1e12)
+pub const INDEX_SCALE: i128 = 1_000_000_000_000;
+/// Chunks accrued per call, bounding the instruction cost of a catch-up
+pub const MAX_CHUNKS_PER_CALL: u32 = 12;
+/// Default maximum gap accrued in a single chunk (30 days)
+pub const DEFAULT_MAX_ACCRUAL_GAP_SECS: u64 = 30 * 24 * 60 * 60;
+/// Default age within which the global index counts as fresh for zero-debt
+/// positions (0 = accrued at the current timestamp)
+pub const DEFAULT_ACCRUAL_FRESH_SECS: u64 = 0;
+
+const SECONDS_PER_YEAR: i128 = 365 * 24 * 60 * 60;
+const RATE_SCALE: i128 = 100_000_000;
+
+/// Global accrual state
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct AccrualState {
+    /// Cumulative borrow index (scaled by 1e12)
+    pub borrow_index: i128,
+    /// Timestamp the index has been accrued up to
+    pub last_accrual_time: u64,
+}
+
+/// Accrual storage management
+pub struct AccrualStorage;
+
+impl AccrualStorage {
+    pub(crate) fn state_key(env: &Env) -> Symbol {
+        Symbol::new(env, "accrual_state")
+    }
+
+    pub(crate) fn supply_index_key(env: &Env) -> Symbol {
+        Symbol::new(env, "supply_index")
+    }
+
+    pub(crate) fn max_gap_key(env: &Env) -> Symbol {
+        Symbol::new(env, "max_accrual_gap")
+    }
+
+    pub(crate) fn fresh_window_key(env: &Env) -> Symbol {
+        Symbol::new(env, "accrual_fresh_secs")
+    }
+
+    pub(crate) fn rate_threshold_key(env: &Env) -> Symbol {
+        Symbol::new(env, "rate_event_threshold")
+    }
+
+    pub(crate) fn last_rate_key(env: &Env, asset: &Option<Address>) -> (Symbol, Option<Address>) {
+        (Symbol::new(env, "rate_event_last"), asset.clone())
+    }
+
+    pub fn get_state(env: &Env) -> AccrualState {
+        env.storage()
+            .instance()
+            .get(&Self::state_key(env))
+            .unwrap_or(AccrualState {
+                borrow_index: INDEX_SCALE,
+                last_accrual_time: 0,
+            })
+    }
+
+    pub fn save_state(env: &Env, state: &AccrualState) {
+        env.storage().instance().set(&Self::state_key(env), state);
+    }
+
+    /// Cumulative supply index (scaled by 1e12), grown alongside the borrow
+    /// index at the supply rate. Informational: supplier balances accrue
+    /// per position.
+    pub fn get_supply_index(env: &Env) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::supply_index_key(env))
+            .unwrap_or(INDEX_SCALE)
+    }
+
+    fn save_supply_index(env: &Env, index: i128) {
+        env.storage()
+            .instance()
+            .set(&Self::supply_index_key(env), &index);
+    }
+
+    pub fn get_max_gap(env: &Env) -> u64 {
+        env.storage()
+            .instance()
+            .get(&Self::max_gap_key(env))
+            .unwrap_or(DEFAULT_MAX_ACCRUAL_GAP_SECS)
+    }
+
+    pub fn set_max_gap(env: &Env, secs: u64) {
+        config::set_param(
+            env,
+            "accrual",
+            "max_accrual_gap",
+            &Self::max_gap_key(env),
+            None,
+            DEFAULT_MAX_ACCRUAL_GAP_SECS,
+            secs,
+        );
+    }
+
+    pub fn get_fresh_window(env: &Env) -> u64 {
+        env.storage()
+            .instance()
+            .get(&Self::fresh_window_key(env))
+            .unwrap_or(DEFAULT_ACCRUAL_FRESH_SECS)
+    }
+
+    pub fn set_fresh_window(env: &Env, secs: u64) {
+        config::set_param(
+            env,
+            "accrual",
+            "accrual_fresh_secs",
+            &Self::fresh_window_key(env),
+            None,
+            DEFAULT_ACCRUAL_FRESH_SECS,
+            secs,
+        );
+    }
+
+    /// Borrow rate move, in bps, past which `rate_changed` is emitted
+    pub fn get_rate_event_threshold(env: &Env) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::rate_threshold_key(env))
+            .unwrap_or(defaults::RATE_EVENT_THRESHOLD_BPS)
+    }
+
+    pub fn set_rate_event_threshold(env: &Env, bps: i128) -> Result<(), ProtocolError> {
+        if !(0..=10000).contains(&bps) {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        config::set_param(
+            env,
+            "accrual",
+            "rate_event_threshold",
+            &Self::rate_threshold_key(env),
+            None,
+            defaults::RATE_EVENT_THRESHOLD_BPS,
+            bps,
+        );
+        Ok(())
+    }
+
+    /// Borrow rate, in bps, last reported for `asset` (the primary asset)
+    pub fn get_last_reported_rate(env: &Env, asset: &Option<Address>) -> Option<i128> {
+        env.storage()
+            .instance()
+            .get(&Self::last_rate_key(env, asset))
+    }
+
+    fn set_last_reported_rate(env: &Env, asset: &Option<Address>, bps: i128) {
+        env.storage()
+            .instance()
+            .set(&Self::last_rate_key(env, asset), &bps);
+    }
+}
+
+/// Accrual logic
+pub struct AccrualManager;
+
+impl AccrualManager {
+    /// Grow an index by `rate` (annualized, 1e8 scale) over `elapsed` seconds
+    pub fn grow_index(index: i128, rate: i128, elapsed: u64) -> i128 {
+        let growth = index
+            .saturating_mul(rate.clamp(0, RATE_SCALE))
+            .saturating_mul(elapsed as i128)
+            / SECONDS_PER_YEAR.saturating_mul(RATE_SCALE);
+        index.saturating_add(growth)
+    }
+
+    /// Accrue the global indices towards now, at most `MAX_CHUNKS_PER_CALL`
+    /// chunks of `max_accrual_gap_secs` each, and record the day's index
+    /// checkpoint. Returns the updated state.
+    pub fn accrue(env: &Env) -> AccrualState {
+        let now = env.ledger().timestamp();
+        let rates = InterestRateStorage::get_state(env);
+        Self::report_rate_change(env, &rates);
+        let mut state = AccrualStorage::get_state(env);
+        if state.last_accrual_time == 0 {
+            state.last_accrual_time = now;
+            AccrualStorage::save_state(env, &state);
+            IndexCheckpoints::record(env, state.borrow_index, INDEX_SCALE, now);
+            return state;
+        }
+        if now <= state.last_accrual_time {
+            return state;
+        }
+
+        let mut supply_index = AccrualStorage::get_supply_index(env);
+        let max_gap = AccrualStorage::get_max_gap(env).max(1);
+        let mut chunks = 0;
+        while state.last_accrual_time < now && chunks < MAX_CHUNKS_PER_CALL {
+            let step = (now - state.last_accrual_time).min(max_gap);
+            state.borrow_index =
+                Self::grow_index(state.borrow_index, rates.current_borrow_rate, step);
+            supply_index = Self::grow_index(supply_index, rates.current_supply_rate, step);
+            state.last_accrual_time += step;
+            chunks += 1;
+        }
+        AccrualStorage::save_state(env, &state);
+        AccrualStorage::save_supply_index(env, supply_index);
+        IndexCheckpoints::record(
+            env,
+            state.borrow_index,
+            supply_index,
+            state.last_accrual_time,
+        );
+
+        if FeatureFlags::is_enabled(env, "diagnostics_enabled") {
+            Self::emit_index_advanced(env, &state, supply_index);
+        }
+        if state.last_accrual_time < now {
+            Self::emit_partial(env, &state, now);
+        }
+        state
+    }
+
+    /// Emit `rate_changed` when the borrow rate has moved more than the
+    /// configured threshold since the last reported value (any move with a
+    /// threshold of 0). The first rate seen only seeds the baseline.
+    fn report_rate_change(env: &Env, rates: &InterestRateState) {
+        let asset = TokenRegistry::primary_asset(env);
+        let new_bps = rates.current_borrow_rate.saturating_mul(10000) / RATE_SCALE;
+        let Some(old_bps) = AccrualStorage::get_last_reported_rate(env, &asset) else {
+            AccrualStorage::set_last_reported_rate(env, &asset, new_bps);
+            return;
+        };
+        let moved = new_bps.abs_diff(old_bps);
+        if moved == 0 || moved <= AccrualStorage::get_rate_event_threshold(env) as u128 {
+            return;
+        }
+        AccrualStorage::set_last_reported_rate(env, &asset, new_bps);
+
+        let event_type = Symbol::new(env, "rate_changed");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        EventTracker::record(
+            env,
+            event_type.clone(),
+            topics,
+            None,
+            asset.clone(),
+            new_bps,
+        );
+        env.events()
+            .publish((event_type, asset), (old_bps, new_bps));
+    }
+
+    /// Whether a catch-up is still outstanding
+    pub fn in_progress(env: &Env) -> bool {
+        let state = AccrualStorage::get_state(env);
+        state.last_accrual_time != 0 && state.last_accrual_time < env.ledger().timestamp()
+    }
+
+    /// Fail with `AccrualInProgress` while a catch-up is outstanding
+    pub fn ensure_caught_up(env: &Env) -> Result<(), ProtocolError> {
+        if Self::in_progress(env) {
+            return Err(ProtocolError::AccrualInProgress);
+        }
+        Ok(())
+    }
+
+    /// Fail with `StaleAccrual` if the global indices were last accrued
+    /// more than `max_age_secs` ago. Never-accrued state is not stale.
+    pub fn ensure_accrued_within(env: &Env, max_age_secs: u64) -> Result<(), ProtocolError> {
+        let state = AccrualStorage::get_state(env);
+        if state.last_accrual_time != 0
+            && env
+                .ledger()
+                .timestamp()
+                .saturating_sub(state.last_accrual_time)
+                > max_age_secs
+        {
+            return Err(ProtocolError::StaleAccrual);
+        }
+        Ok(())
+    }
+
+    /// Whether the global index was accrued within the freshness window
+    pub fn is_fresh(env: &Env) -> bool {
+        let state = AccrualStorage::get_state(env);
+        state.last_accrual_time != 0
+            && env
+                .ledger()
+                .timestamp()
+                .saturating_sub(state.last_accrual_time)
+                <= AccrualStorage::get_fresh_window(env)
+    }
+
+    /// Rates to accrue a position with `debt` at. A zero-debt position only
+    /// earns supply interest, so while the global index is fresh it reuses
+    /// the stored rates instead of re-running the global update.
+    pub fn rates_for_position(env: &Env, debt: i128) -> InterestRateState {
+        if debt <= 0 && Self::is_fresh(env) {
+            return InterestRateStorage::get_state(env);
+        }
+        InterestRateStorage::update_state(env)
+    }
+
+    /// Set the freshness window for the zero-debt fast path (admin only)
+    pub fn set_fresh_window(env: &Env, caller: &Address, secs: u64) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        AccrualStorage::set_fresh_window(env, secs);
+        Ok(())
+    }
+
+    /// Set the maximum gap accrued per chunk (admin only)
+    pub fn set_max_gap(env: &Env, caller: &Address, secs: u64) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        if secs == 0 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        AccrualStorage::set_max_gap(env, secs);
+        Ok(())
+    }
+
+    fn emit_index_advanced(env: &Env, state: &AccrualState, supply_index: i128) {
+        env.events().publish(
+            (
+                Symbol::new(env, "index_advanced"),
+                Symbol::new(env, "accrual"),
+            ),
+            (state.last_accrual_time, state.borrow_index, supply_index),
+        );
+    }
+
+    /// Set the borrow rate move that triggers a `rate_changed` event
+    /// (admin only)
+    pub fn set_rate_event_threshold(
+        env: &Env,
+        caller: &Address,
+        bps: i128,
+    ) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        AccrualStorage::set_rate_event_threshold(env, bps)
+    }
+
+    fn emit_partial(env: &Env, state: &AccrualState, target: u64) {
+        ProtocolEvent::AccrualCatchUpPartial(
+            state.last_accrual_time,
+            target - state.last_accrual_time,
+            state.borrow_index,
+        )
+        .emit(env);
+    }
+}
@@ -0,0 +1,204 @@
+//! Liquidation-threat alerts and risk snapshots
+//!
+//! A position is at risk while its collateral ratio is below maintenance.
+//! Every evaluation (each position save, plus keeper calls through
+//! `check_position_risk`) compares that against the stored state:
+//! - on the healthy -> at-risk transition, `position_at_risk` is emitted and a
+//!   snapshot of what the protocol saw is stored
+//! - while it stays at risk, a further snapshot is stored at most once per
+//!   snapshot interval
+//! - on recovery, `position_recovered` is emitted
+//!
+//! The last [`MAX_RISK_SNAPSHOTS`] snapshots per user are kept.
+
+use crate::oracle::OracleStorage;
+use crate::{config, defaults};
+use crate::{EventTracker, Position, ProtocolConfig, ProtocolError, TokenRegistry};
+use soroban_sdk::{contracttype, Address, Env, Symbol, Vec};
+
+/// Snapshots retained per user
+pub const MAX_RISK_SNAPSHOTS: u32 = 5;
+
+/// What the protocol saw when it flagged a position
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct RiskSnapshot {
+    pub collateral: i128,
+    pub debt: i128,
+    /// Collateral ratio in bps
+    pub collateral_ratio_bps: i128,
+    /// Collateral ratio relative to the liquidation threshold, in bps;
+    /// below 10000 is liquidatable
+    pub health_factor_bps: i128,
+    /// Liquidation threshold in effect
+    pub maintenance_ratio_bps: i128,
+    /// Cached price of the position's asset, if any
+    pub price: Option<i128>,
+    pub timestamp: u64,
+    pub ledger_seq: u32,
+}
+
+/// Last evaluated risk state of a user
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct RiskWatch {
+    pub at_risk: bool,
+    pub last_snapshot_at: u64,
+}
+
+/// Risk alert storage management
+pub struct AlertStorage;
+
+impl AlertStorage {
+    pub(crate) fn snapshots_key(env: &Env, user: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "risk_snapshots"), user.clone())
+    }
+
+    pub(crate) fn watch_key(env: &Env, user: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "risk_watch"), user.clone())
+    }
+
+    pub(crate) fn interval_key(env: &Env) -> Symbol {
+        Symbol::new(env, "risk_snapshot_interval")
+    }
+
+    /// Snapshots of `user`, oldest first
+    pub fn get_snapshots(env: &Env, user: &Address) -> Vec<RiskSnapshot> {
+        env.storage()
+            .instance()
+            .get(&Self::snapshots_key(env, user))
+            .unwrap_or_else(|| Vec::new(env))
+    }
+
+    fn push_snapshot(env: &Env, user: &Address, snapshot: RiskSnapshot) {
+        let mut snapshots = Self::get_snapshots(env, user);
+        snapshots.push_back(snapshot);
+        if snapshots.len() > MAX_RISK_SNAPSHOTS {
+            snapshots = snapshots.slice(snapshots.len() - MAX_RISK_SNAPSHOTS..);
+        }
+        env.storage()
+            .instance()
+            .set(&Self::snapshots_key(env, user), &snapshots);
+    }
+
+    pub fn get_watch(env: &Env, user: &Address) -> RiskWatch {
+        env.storage()
+            .instance()
+            .get(&Self::watch_key(env, user))
+            .unwrap_or(RiskWatch {
+                at_risk: false,
+                last_snapshot_at: 0,
+            })
+    }
+
+    fn save_watch(env: &Env, user: &Address, watch: &RiskWatch) {
+        env.storage()
+            .instance()
+            .set(&Self::watch_key(env, user), watch);
+    }
+
+    pub fn get_interval(env: &Env) -> u64 {
+        env.storage()
+            .instance()
+            .get(&Self::interval_key(env))
+            .unwrap_or(defaults::RISK_SNAPSHOT_INTERVAL_SECS)
+    }
+
+    pub fn set_interval(env: &Env, secs: u64) {
+        config::set_param(
+            env,
+            "alerts",
+            "risk_snapshot_interval",
+            &Self::interval_key(env),
+            None,
+            defaults::RISK_SNAPSHOT_INTERVAL_SECS,
+            secs,
+        );
+    }
+}
+
+/// Risk alert logic
+pub struct Alerts;
+
+impl Alerts {
+    fn snapshot(env: &Env, position: &Position) -> RiskSnapshot {
+        let maintenance_ratio_bps = ProtocolConfig::get_liquidation_threshold_bps(env);
+        let collateral_ratio_bps = if position.debt > 0 {
+            (position.collateral * 10000) / position.debt
+        } else {
+            0
+        };
+        let health_factor_bps = if position.debt == 0 || maintenance_ratio_bps <= 0 {
+            i128::MAX
+        } else {
+            collateral_ratio_bps * 10000 / maintenance_ratio_bps
+        };
+        let price = TokenRegistry::primary_asset(env)
+            .and_then(|a| OracleStorage::get_price_cache(env).get(a))
+            .map(|(p, _)| p);
+        RiskSnapshot {
+            collateral: position.collateral,
+            debt: position.debt,
+            collateral_ratio_bps,
+            health_factor_bps,
+            maintenance_ratio_bps,
+            price,
+            timestamp: env.ledger().timestamp(),
+            ledger_seq: env.ledger().sequence(),
+        }
+    }
+
+    /// Evaluate `position` and record any transition. Returns whether it is
+    /// at risk.
+    pub fn evaluate(env: &Env, position: &Position) -> bool {
+        let snapshot = Self::snapshot(env, position);
+        let at_risk = snapshot.health_factor_bps < 10000;
+        let mut watch = AlertStorage::get_watch(env, &position.user);
+        if !at_risk && !watch.at_risk {
+            return false;
+        }
+
+        let now = snapshot.timestamp;
+        if at_risk && !watch.at_risk {
+            Self::emit(env, "position_at_risk", &position.user, &snapshot);
+            watch.last_snapshot_at = now;
+            AlertStorage::push_snapshot(env, &position.user, snapshot);
+        } else if at_risk {
+            if now.saturating_sub(watch.last_snapshot_at) < AlertStorage::get_interval(env) {
+                return true;
+            }
+            watch.last_snapshot_at = now;
+            AlertStorage::push_snapshot(env, &position.user, snapshot);
+        } else {
+            Self::emit(env, "position_recovered", &position.user, &snapshot);
+        }
+        watch.at_risk = at_risk;
+        AlertStorage::save_watch(env, &position.user, &watch);
+        at_risk
+    }
+
+    /// Set the minimum time between snapshots of a position that stays at
+    /// risk (admin only)
+    pub fn set_interval(env: &Env, caller: &Address, secs: u64) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        AlertStorage::set_interval(env, secs);
+        Ok(())
+    }
+
+    fn emit(env: &Env, name: &str, user: &Address, snapshot: &RiskSnapshot) {
+        let event_type = Symbol::new(env, name);
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        topics.push_back(Symbol::new(env, "user"));
+        EventTracker::record(
+            env,
+            event_type.clone(),
+            topics,
+            Some(user.clone()),
+            None,
+            snapshot.health_factor_bps,
+        );
+        env.events()
+            .publish((event_type, user.clone()), snapshot.clone());
+    }
+}
@@ -5,11 +5,31 @@
 //! - Swap hooks for deleveraging and liquidation flows
 //! - Event emissions for AMM usage tracking
 //! - Integration with liquidation mechanisms
+use crate::config;
+use crate::pagination::{self, MAX_PAGE_LIMIT};
 #[cfg(not(test))]
 use crate::ProtocolEvent;
 #[allow(unused_imports)]
-use crate::{Position, ProtocolError, ReentrancyGuard, StateHelper};
-use soroban_sdk::{contracterror, contracttype, Address, Env, Map, Symbol, Vec};
+use crate::{
+    Deadline, EventTracker, Position, ProtocolError, ReentrancyGuard, StateHelper, TransferEnforcer,
+};
+use soroban_sdk::token::TokenClient;
+use soroban_sdk::{
+    contracterror, contracttype, vec, Address, Bytes, Env, IntoVal, Map, Symbol, Vec,
+};
+use stellarlend_core::math::{mul_div, Rounding};
+
+/// Number of swaps retained in the swap history
+pub const SWAP_HISTORY_CAP: u32 = 100;
+
+/// Swap fee charged by newly registered pairs (0.3%)
+pub const DEFAULT_PAIR_FEE_BPS: i128 = 30;
+
+/// Upper bound accepted by [`AMMRegistry::set_pair_fee`] (10%)
+pub const MAX_PAIR_FEE_BPS: i128 = 1000;
+
+/// Most hops a quoted route may take
+pub const MAX_ROUTE_HOPS: u32 = 4;
 
 /// AMM-specific error types
 #[contracterror]
@@ -32,6 +52,8 @@ pub enum AMMError {
     Unauthorized = 7007,
     /// Swap failed
     SwapFailed = 7008,
+    /// Pair is paused; only liquidity removal is allowed
+    PairPaused = 7009,
 }
 
 impl From<AMMError> for ProtocolError {
@@ -45,6 +67,7 @@ impl From<AMMError> for ProtocolError {
             AMMError::InvalidSwapParams => ProtocolError::InvalidParameters,
             AMMError::Unauthorized => ProtocolError::Unauthorized,
             AMMError::SwapFailed => ProtocolError::InvalidAmount,
+            AMMError::PairPaused => ProtocolError::ProtocolPaused,
         }
     }
 }
@@ -67,6 +90,16 @@ pub struct AssetPair {
     pub registered_at: u64,
     /// Last updated timestamp
     pub last_updated: u64,
+    /// Swap fee in basis points
+    pub fee_bps: i128,
+    /// Paused pairs reject swaps and liquidity additions
+    pub paused: bool,
+    /// Internal pool reserve of `asset_a`
+    pub reserve_a: i128,
+    /// Internal pool reserve of `asset_b`
+    pub reserve_b: i128,
+    /// Outstanding LP shares of the internal pool
+    pub total_shares: i128,
 }
 
 impl AssetPair {
@@ -79,6 +112,11 @@ impl AssetPair {
             is_active: true,
             registered_at: timestamp,
             last_updated: timestamp,
+            fee_bps: DEFAULT_PAIR_FEE_BPS,
+            paused: false,
+            reserve_a: 0,
+            reserve_b: 0,
+            total_shares: 0,
         }
     }
 
@@ -97,6 +135,11 @@ impl AssetPair {
             is_active: true,
             registered_at: timestamp,
             last_updated: timestamp,
+            fee_bps: DEFAULT_PAIR_FEE_BPS,
+            paused: false,
+            reserve_a: 0,
+            reserve_b: 0,
+            total_shares: 0,
         }
     }
 }
@@ -194,6 +237,28 @@ impl SwapResult {
     }
 }
 
+/// Quote for a swap at the pair's current state
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct QuoteResult {
+    pub amount_in: i128,
+    pub amount_out: i128,
+    /// Fee paid, in the input asset
+    pub fee_paid: i128,
+    /// Move of the pool's mid price caused by the swap, in bps (0 for pairs
+    /// without internal liquidity)
+    pub price_impact_bps: i128,
+    /// Whether the swap would fill right now: the pair is active and not
+    /// paused, and the pool can pay out a non-zero amount
+    pub sufficient_liquidity: bool,
+}
+
+define_page!(
+    /// Page of retained swap results, oldest first
+    SwapPage,
+    SwapResult
+);
+
 /// Pair key type for storage
 #[derive(Clone, Debug, Eq, PartialEq)]
 #[contracttype]
@@ -216,23 +281,166 @@ impl PairKey {
     }
 }
 
+impl AssetPair {
+    /// Whether the pair holds internal liquidity to price swaps against
+    pub fn has_liquidity(&self) -> bool {
+        self.reserve_a > 0 && self.reserve_b > 0
+    }
+
+    /// Reserves ordered as (reserve of `asset_in`, reserve of the other asset)
+    fn reserves_for(&self, asset_in: &Address) -> (i128, i128) {
+        if *asset_in == self.asset_a {
+            (self.reserve_a, self.reserve_b)
+        } else {
+            (self.reserve_b, self.reserve_a)
+        }
+    }
+
+    /// Output and fee of swapping `amount_in` of `asset_in`, exactly as
+    /// `execute_swap` computes them: against the internal pool when it has
+    /// liquidity, else at the simulated 1:1 external rate.
+    ///
+    /// Returns `(amount_out, fee_paid)`.
+    pub fn swap_out(&self, asset_in: &Address, amount_in: i128) -> (i128, i128) {
+        if self.has_liquidity() {
+            let (reserve_in, reserve_out) = self.reserves_for(asset_in);
+            AMMRegistry::compute_swap_out(amount_in, reserve_in, reserve_out, self.fee_bps)
+        } else {
+            let fee = (amount_in * self.fee_bps) / 10000;
+            (amount_in - fee, fee)
+        }
+    }
+
+    /// Smallest input of `asset_in` for which [`AssetPair::swap_out`] pays at
+    /// least `amount_out`, or `None` if the pool cannot pay it
+    pub fn swap_in_for(&self, asset_in: &Address, amount_out: i128) -> Option<i128> {
+        if amount_out <= 0 {
+            return None;
+        }
+        // Input needed after the fee: invert the constant product, rounding up
+        let after_fee = if self.has_liquidity() {
+            let (reserve_in, reserve_out) = self.reserves_for(asset_in);
+            if amount_out >= reserve_out {
+                return None;
+            }
+            let remaining = reserve_out - amount_out;
+            (amount_out.checked_mul(reserve_in)? + remaining - 1) / remaining
+        } else {
+            amount_out
+        };
+        let kept = 10000 - self.fee_bps;
+        let mut amount_in = (after_fee.checked_mul(10000)? + kept - 1) / kept;
+        // The fee is rounded down, so the estimate can be a unit off either way
+        while amount_in > 1 && self.swap_out(asset_in, amount_in - 1).0 >= amount_out {
+            amount_in -= 1;
+        }
+        while self.swap_out(asset_in, amount_in).0 < amount_out {
+            amount_in += 1;
+        }
+        Some(amount_in)
+    }
+
+    /// Quote swapping `amount_in` of `asset_in` for `amount_out`
+    fn quote(
+        &self,
+        asset_in: &Address,
+        amount_in: i128,
+        amount_out: i128,
+        fee: i128,
+    ) -> QuoteResult {
+        let price_impact_bps = if self.has_liquidity() {
+            // Mid price (out per in) falls from r_out / r_in to
+            // (r_out - out) / (r_in + in)
+            let (reserve_in, reserve_out) = self.reserves_for(asset_in);
+            let before = reserve_out.saturating_mul(reserve_in + amount_in);
+            let after = (reserve_out - amount_out).saturating_mul(reserve_in);
+            if before > 0 {
+                10000 - after.saturating_mul(10000) / before
+            } else {
+                0
+            }
+        } else {
+            0
+        };
+        let (_, reserve_out) = self.reserves_for(asset_in);
+        QuoteResult {
+            amount_in,
+            amount_out,
+            fee_paid: fee,
+            price_impact_bps,
+            sufficient_liquidity: self.is_active
+                && !self.paused
+                && amount_out > 0
+                && (!self.has_liquidity() || amount_out < reserve_out),
+        }
+    }
+}
+
+/// Pool state returned by [`AMMRegistry::get_pool_info`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PoolInfo {
+    pub pair_id: PairKey,
+    pub asset_a: Address,
+    pub asset_b: Address,
+    pub reserve_a: i128,
+    pub reserve_b: i128,
+    pub total_shares: i128,
+    pub fee_bps: i128,
+    pub paused: bool,
+    pub is_active: bool,
+}
+
+/// Amounts a provider paid into a pair's internal pool for the shares they
+/// still hold
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct LpDeposit {
+    pub amount_a: i128,
+    pub amount_b: i128,
+}
+
+/// A provider's stake in a pair, returned by [`AMMRegistry::get_lp_position`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct LpPosition {
+    pub pair_id: PairKey,
+    pub shares: i128,
+    /// What burning `shares` would pay out now
+    pub redeemable_a: i128,
+    pub redeemable_b: i128,
+    /// Summed across adds, scaled down pro rata on removals
+    pub deposited_a: i128,
+    pub deposited_b: i128,
+    /// Impermanent loss versus holding the deposits, from the entry reserve
+    /// ratio to the current one; 0 or negative, fees excluded
+    pub impermanent_loss_bps: i128,
+}
+
+/// Fixed-point scale of the price ratio behind the impermanent-loss estimate
+const IL_SCALE: i128 = 1_000_000_000;
+
 /// AMM registry storage management
 pub struct AMMStorage;
 
 impl AMMStorage {
     // Storage keys
-    fn pairs_key(env: &Env) -> Symbol {
+    pub(crate) fn pairs_key(env: &Env) -> Symbol {
         Symbol::new(env, "amm_pairs")
     }
 
-    fn pair_count_key(env: &Env) -> Symbol {
+    pub(crate) fn pair_count_key(env: &Env) -> Symbol {
         Symbol::new(env, "amm_pair_count")
     }
 
-    fn swap_history_key(env: &Env) -> Symbol {
+    pub(crate) fn swap_history_key(env: &Env) -> Symbol {
         Symbol::new(env, "amm_swap_history")
     }
 
+    pub(crate) fn pair_order_key(env: &Env) -> Symbol {
+        Symbol::new(env, "amm_pair_order")
+    }
+
     /// Get all registered pairs
     pub fn get_all_pairs(env: &Env) -> Map<PairKey, AssetPair> {
         env.storage()
@@ -261,6 +469,83 @@ impl AMMStorage {
         Self::save_all_pairs(env, &pairs);
     }
 
+    /// Get pair keys in registration order
+    pub fn get_pair_order(env: &Env) -> Vec<PairKey> {
+        env.storage()
+            .instance()
+            .get(&Self::pair_order_key(env))
+            .unwrap_or_else(|| Vec::new(env))
+    }
+
+    /// Append a newly registered pair key to the ordered index
+    pub fn append_pair_order(env: &Env, key: PairKey) {
+        let mut order = Self::get_pair_order(env);
+        order.push_back(key);
+        env.storage()
+            .instance()
+            .set(&Self::pair_order_key(env), &order);
+    }
+
+    pub(crate) fn lp_shares_key(
+        env: &Env,
+        pair_id: &PairKey,
+        provider: &Address,
+    ) -> (Symbol, PairKey, Address) {
+        (
+            Symbol::new(env, "amm_lp_shares"),
+            pair_id.clone(),
+            provider.clone(),
+        )
+    }
+
+    pub(crate) fn lp_deposit_key(
+        env: &Env,
+        pair_id: &PairKey,
+        provider: &Address,
+    ) -> (Symbol, PairKey, Address) {
+        (
+            Symbol::new(env, "amm_lp_deposit"),
+            pair_id.clone(),
+            provider.clone(),
+        )
+    }
+
+    pub fn get_lp_deposit(env: &Env, pair_id: &PairKey, provider: &Address) -> LpDeposit {
+        env.storage()
+            .instance()
+            .get(&Self::lp_deposit_key(env, pair_id, provider))
+            .unwrap_or(LpDeposit {
+                amount_a: 0,
+                amount_b: 0,
+            })
+    }
+
+    pub fn set_lp_deposit(env: &Env, pair_id: &PairKey, provider: &Address, deposit: &LpDeposit) {
+        let key = Self::lp_deposit_key(env, pair_id, provider);
+        if deposit.amount_a == 0 && deposit.amount_b == 0 {
+            env.storage().instance().remove(&key);
+        } else {
+            env.storage().instance().set(&key, deposit);
+        }
+    }
+
+    /// LP shares held by `provider` in a pair's internal pool
+    pub fn get_lp_shares(env: &Env, pair_id: &PairKey, provider: &Address) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::lp_shares_key(env, pair_id, provider))
+            .unwrap_or(0)
+    }
+
+    pub fn set_lp_shares(env: &Env, pair_id: &PairKey, provider: &Address, shares: i128) {
+        let key = Self::lp_shares_key(env, pair_id, provider);
+        if shares == 0 {
+            env.storage().instance().remove(&key);
+        } else {
+            env.storage().instance().set(&key, &shares);
+        }
+    }
+
     /// Get pair count
     pub fn get_pair_count(env: &Env) -> i128 {
         env.storage()
@@ -290,9 +575,9 @@ impl AMMStorage {
         let mut history = Self::get_swap_history(env);
         history.push_back(swap.clone());
 
-        // Keep only last 100 swaps to prevent storage bloat
-        if history.len() > 100 {
-            history = history.slice(history.len() - 100..);
+        // Keep only the most recent swaps to prevent storage bloat
+        if history.len() > SWAP_HISTORY_CAP {
+            history = history.slice(history.len() - SWAP_HISTORY_CAP..);
         }
 
         env.storage()
@@ -340,6 +625,7 @@ impl AMMRegistry {
 
         // Save the pair
         AMMStorage::save_pair(env, &pair);
+        AMMStorage::append_pair_order(env, PairKey::new(asset_a.clone(), asset_b.clone()));
         AMMStorage::increment_pair_count(env);
 
         // Emit registration event (only if we have a contract address)
@@ -410,6 +696,604 @@ impl AMMRegistry {
         Ok(())
     }
 
+    fn require_pair(env: &Env, pair_id: &PairKey) -> Result<AssetPair, ProtocolError> {
+        AMMStorage::get_pair(env, &pair_id.asset_a, &pair_id.asset_b)
+            .ok_or_else(|| AMMError::PairNotRegistered.into())
+    }
+
+    fn emit(env: &Env, name: &str, pair_id: &PairKey, user: Option<Address>, amount: i128) {
+        let event_type = Symbol::new(env, name);
+        let mut topics = Vec::new(env);
+        topics.push_back(Symbol::new(env, "pair"));
+        EventTracker::record(
+            env,
+            event_type.clone(),
+            topics,
+            user,
+            Some(pair_id.asset_a.clone()),
+            amount,
+        );
+        env.events().publish(
+            (event_type, Symbol::new(env, "pair")),
+            (
+                Symbol::new(env, "asset_a"),
+                pair_id.asset_a.clone(),
+                Symbol::new(env, "asset_b"),
+                pair_id.asset_b.clone(),
+                Symbol::new(env, "value"),
+                amount,
+            ),
+        );
+    }
+
+    /// Update the swap fee of a pair. Takes effect from the next swap.
+    pub fn set_pair_fee(env: &Env, pair_id: &PairKey, fee_bps: i128) -> Result<(), ProtocolError> {
+        if !(0..=MAX_PAIR_FEE_BPS).contains(&fee_bps) {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        let mut pair = Self::require_pair(env, pair_id)?;
+        let old = core::mem::replace(&mut pair.fee_bps, fee_bps);
+        pair.last_updated = env.ledger().timestamp();
+        AMMStorage::save_pair(env, &pair);
+        if old != fee_bps {
+            config::record_change(
+                env,
+                "amm",
+                "pair_fee_bps",
+                Some(&pair_id.asset_a),
+                old,
+                fee_bps,
+            );
+        }
+        Self::emit(env, "amm_pair_fee_set", pair_id, None, fee_bps);
+        Ok(())
+    }
+
+    /// Pause or unpause a pair
+    pub fn set_pair_paused(
+        env: &Env,
+        pair_id: &PairKey,
+        paused: bool,
+    ) -> Result<(), ProtocolError> {
+        let mut pair = Self::require_pair(env, pair_id)?;
+        let old = core::mem::replace(&mut pair.paused, paused);
+        pair.last_updated = env.ledger().timestamp();
+        AMMStorage::save_pair(env, &pair);
+        if old != paused {
+            config::record_change(
+                env,
+                "amm",
+                "pair_paused",
+                Some(&pair_id.asset_a),
+                old.into(),
+                paused.into(),
+            );
+        }
+        Self::emit(env, "amm_pair_paused", pair_id, None, paused as i128);
+        Ok(())
+    }
+
+    /// Current pool state of a pair
+    pub fn get_pool_info(env: &Env, pair_id: &PairKey) -> Result<PoolInfo, ProtocolError> {
+        let pair = Self::require_pair(env, pair_id)?;
+        Ok(PoolInfo {
+            pair_id: pair_id.clone(),
+            asset_a: pair.asset_a,
+            asset_b: pair.asset_b,
+            reserve_a: pair.reserve_a,
+            reserve_b: pair.reserve_b,
+            total_shares: pair.total_shares,
+            fee_bps: pair.fee_bps,
+            paused: pair.paused,
+            is_active: pair.is_active,
+        })
+    }
+
+    /// Constant-product output for `amount_in` against the given reserves.
+    /// Returns `(amount_out, fee_paid)`; the fee stays in the pool.
+    pub fn compute_swap_out(
+        amount_in: i128,
+        reserve_in: i128,
+        reserve_out: i128,
+        fee_bps: i128,
+    ) -> (i128, i128) {
+        let fee = (amount_in * fee_bps) / 10000;
+        let amount_after_fee = amount_in - fee;
+        let amount_out = (amount_after_fee * reserve_out) / (reserve_in + amount_after_fee);
+        (amount_out, fee)
+    }
+
+    fn require_pair_asset(
+        env: &Env,
+        pair_id: &PairKey,
+        asset: &Address,
+    ) -> Result<AssetPair, ProtocolError> {
+        let pair = Self::require_pair(env, pair_id)?;
+        if *asset != pair.asset_a && *asset != pair.asset_b {
+            return Err(AMMError::InvalidSwapParams.into());
+        }
+        Ok(pair)
+    }
+
+    fn other_asset(pair: &AssetPair, asset: &Address) -> Address {
+        if *asset == pair.asset_a {
+            pair.asset_b.clone()
+        } else {
+            pair.asset_a.clone()
+        }
+    }
+
+    /// Quote swapping exactly `amount_in` of `token_in`, using the same math
+    /// as `execute_swap`
+    pub fn quote_swap_in(
+        env: &Env,
+        pair_id: &PairKey,
+        token_in: &Address,
+        amount_in: i128,
+    ) -> Result<QuoteResult, ProtocolError> {
+        if amount_in <= 0 {
+            return Err(AMMError::InvalidSwapParams.into());
+        }
+        let pair = Self::require_pair_asset(env, pair_id, token_in)?;
+        let (amount_out, fee) = pair.swap_out(token_in, amount_in);
+        Ok(pair.quote(token_in, amount_in, amount_out, fee))
+    }
+
+    /// Quote the smallest input that buys at least `amount_out` of
+    /// `token_out`. If the pool cannot pay it, the quote has zero amounts
+    /// and `sufficient_liquidity` is false.
+    pub fn quote_swap_out(
+        env: &Env,
+        pair_id: &PairKey,
+        token_out: &Address,
+        amount_out: i128,
+    ) -> Result<QuoteResult, ProtocolError> {
+        if amount_out <= 0 {
+            return Err(AMMError::InvalidSwapParams.into());
+        }
+        let pair = Self::require_pair_asset(env, pair_id, token_out)?;
+        let token_in = Self::other_asset(&pair, token_out);
+        Ok(match pair.swap_in_for(&token_in, amount_out) {
+            Some(amount_in) => {
+                let (out, fee) = pair.swap_out(&token_in, amount_in);
+                pair.quote(&token_in, amount_in, out, fee)
+            }
+            None => QuoteResult {
+                amount_in: 0,
+                amount_out: 0,
+                fee_paid: 0,
+                price_impact_bps: 0,
+                sufficient_liquidity: false,
+            },
+        })
+    }
+
+    fn route_pairs(env: &Env, path: &Vec<Address>) -> Result<Vec<AssetPair>, ProtocolError> {
+        if path.len() < 2 || path.len() > MAX_ROUTE_HOPS + 1 {
+            return Err(AMMError::InvalidSwapParams.into());
+        }
+        let mut pairs = Vec::new(env);
+        for i in 0..path.len() - 1 {
+            let pair =
+                AMMStorage::get_pair(env, &path.get_unchecked(i), &path.get_unchecked(i + 1))
+                    .ok_or(AMMError::PairNotRegistered)?;
+            pairs.push_back(pair);
+        }
+        Ok(pairs)
+    }
+
+    /// Quote swapping exactly `amount_in` of `path[0]` along `path`, one
+    /// quote per hop; each hop spends the previous hop's output
+    pub fn quote_route_in(
+        env: &Env,
+        path: &Vec<Address>,
+        amount_in: i128,
+    ) -> Result<Vec<QuoteResult>, ProtocolError> {
+        if amount_in <= 0 {
+            return Err(AMMError::InvalidSwapParams.into());
+        }
+        let pairs = Self::route_pairs(env, path)?;
+        let mut quotes = Vec::new(env);
+        let mut amount = amount_in;
+        for (i, pair) in pairs.iter().enumerate() {
+            let token_in = path.get_unchecked(i as u32);
+            let (amount_out, fee) = pair.swap_out(&token_in, amount);
+            quotes.push_back(pair.quote(&token_in, amount, amount_out, fee));
+            amount = amount_out;
+        }
+        Ok(quotes)
+    }
+
+    /// Quote the smallest input of `path[0]` that buys at least `amount_out`
+    /// of the last asset along `path`, one quote per hop. Fails with
+    /// `InsufficientLiquidity` if any hop cannot pay what the next needs.
+    pub fn quote_route_out(
+        env: &Env,
+        path: &Vec<Address>,
+        amount_out: i128,
+    ) -> Result<Vec<QuoteResult>, ProtocolError> {
+        if amount_out <= 0 {
+            return Err(AMMError::InvalidSwapParams.into());
+        }
+        let pairs = Self::route_pairs(env, path)?;
+        // Work backwards for the input each hop needs, then forwards so every
+        // hop is quoted on exactly what the previous one delivers
+        let mut needed = amount_out;
+        for i in (0..pairs.len()).rev() {
+            needed = pairs
+                .get_unchecked(i)
+                .swap_in_for(&path.get_unchecked(i), needed)
+                .ok_or(ProtocolError::InsufficientLiquidity)?;
+        }
+        Self::quote_route_in(env, path, needed)
+    }
+
+    /// Swap `amount_in` of `asset_in` that the contract already holds
+    /// (e.g. protocol reserves) against a pair's internal pool, sending the
+    /// output to `recipient`. Fails before touching any state when the pool
+    /// cannot fill the swap or the output is below `min_amount_out`.
+    ///
+    /// Returns `(amount_out, fee_paid)`.
+    pub fn swap_held(
+        env: &Env,
+        pair_id: &PairKey,
+        asset_in: &Address,
+        amount_in: i128,
+        min_amount_out: i128,
+        recipient: &Address,
+    ) -> Result<(i128, i128), ProtocolError> {
+        if amount_in <= 0 || min_amount_out < 0 {
+            return Err(AMMError::InvalidSwapParams.into());
+        }
+        let mut pair = Self::require_pair(env, pair_id)?;
+        if *asset_in != pair.asset_a && *asset_in != pair.asset_b {
+            return Err(AMMError::InvalidSwapParams.into());
+        }
+        if !pair.is_active {
+            return Err(AMMError::PairNotRegistered.into());
+        }
+        if pair.paused {
+            return Err(AMMError::PairPaused.into());
+        }
+        if !pair.has_liquidity() {
+            return Err(ProtocolError::InsufficientLiquidity);
+        }
+
+        let (reserve_in, reserve_out) = pair.reserves_for(asset_in);
+        let (amount_out, fee) =
+            Self::compute_swap_out(amount_in, reserve_in, reserve_out, pair.fee_bps);
+        if amount_out <= 0 {
+            return Err(ProtocolError::InsufficientLiquidity);
+        }
+        if amount_out < min_amount_out {
+            return Err(ProtocolError::SlippageProtectionTriggered);
+        }
+
+        let asset_out = if *asset_in == pair.asset_a {
+            pair.reserve_a += amount_in;
+            pair.reserve_b -= amount_out;
+            pair.asset_b.clone()
+        } else {
+            pair.reserve_b += amount_in;
+            pair.reserve_a -= amount_out;
+            pair.asset_a.clone()
+        };
+        pair.last_updated = env.ledger().timestamp();
+        AMMStorage::save_pair(env, &pair);
+        TransferEnforcer::transfer(
+            env,
+            &asset_out,
+            &env.current_contract_address(),
+            recipient,
+            amount_out,
+            Symbol::new(env, "amm_swap_out"),
+        )?;
+        AMMStorage::add_swap_to_history(
+            env,
+            &SwapResult::new(amount_in, amount_out, fee, env.ledger().timestamp()),
+        );
+        Ok((amount_out, fee))
+    }
+
+    fn isqrt(value: i128) -> i128 {
+        if value < 2 {
+            return value;
+        }
+        let mut x = value;
+        let mut y = (x + 1) / 2;
+        while y < x {
+            x = y;
+            y = (x + value / x) / 2;
+        }
+        x
+    }
+
+    /// `provider`'s shares in a pair, what they redeem for now, what was paid
+    /// for them and the impermanent loss since
+    pub fn get_lp_position(
+        env: &Env,
+        pair_id: &PairKey,
+        provider: &Address,
+    ) -> Result<LpPosition, ProtocolError> {
+        let pair = Self::require_pair(env, pair_id)?;
+        let shares = AMMStorage::get_lp_shares(env, pair_id, provider);
+        let (redeemable_a, redeemable_b) = if shares > 0 {
+            (
+                shares * pair.reserve_a / pair.total_shares,
+                shares * pair.reserve_b / pair.total_shares,
+            )
+        } else {
+            (0, 0)
+        };
+        let deposit = AMMStorage::get_lp_deposit(env, pair_id, provider);
+        Ok(LpPosition {
+            pair_id: pair_id.clone(),
+            shares,
+            redeemable_a,
+            redeemable_b,
+            impermanent_loss_bps: Self::impermanent_loss_bps(
+                deposit.amount_a,
+                deposit.amount_b,
+                pair.reserve_a,
+                pair.reserve_b,
+            ),
+            deposited_a: deposit.amount_a,
+            deposited_b: deposit.amount_b,
+        })
+    }
+
+    /// `2 * sqrt(k) / (1 + k) - 1` in bps, where `k` is the pool price of
+    /// `asset_a` now over its price at entry. 0 when either ratio is
+    /// undefined or too large to evaluate.
+    pub fn impermanent_loss_bps(
+        entry_a: i128,
+        entry_b: i128,
+        reserve_a: i128,
+        reserve_b: i128,
+    ) -> i128 {
+        if entry_a <= 0 || entry_b <= 0 || reserve_a <= 0 || reserve_b <= 0 {
+            return 0;
+        }
+        let ratio = || -> Result<i128, ProtocolError> {
+            let entry_price = mul_div(entry_b, IL_SCALE, entry_a, Rounding::Down)?;
+            let price = mul_div(reserve_b, IL_SCALE, reserve_a, Rounding::Down)?;
+            if entry_price == 0 {
+                return Err(ProtocolError::InvalidAmount);
+            }
+            mul_div(price, IL_SCALE, entry_price, Rounding::Down)
+        };
+        let Ok(k) = ratio() else {
+            return 0;
+        };
+        let Some(scaled) = k.checked_mul(IL_SCALE) else {
+            return 0;
+        };
+        let root = Self::isqrt(scaled);
+        match mul_div(2 * root, 10000, IL_SCALE + k, Rounding::Down) {
+            Ok(value) => (value - 10000).min(0),
+            Err(_) => 0,
+        }
+    }
+
+    /// Deposit both assets into a pair's internal pool, minting LP shares
+    /// proportional to the smaller side of the contribution.
+    pub fn add_liquidity(
+        env: &Env,
+        provider: &Address,
+        pair_id: &PairKey,
+        amount_a: i128,
+        amount_b: i128,
+    ) -> Result<i128, ProtocolError> {
+        if amount_a <= 0 || amount_b <= 0 {
+            return Err(AMMError::InvalidSwapParams.into());
+        }
+        let mut pair = Self::require_pair(env, pair_id)?;
+        if !pair.is_active {
+            return Err(AMMError::PairNotRegistered.into());
+        }
+        if pair.paused {
+            return Err(AMMError::PairPaused.into());
+        }
+
+        let shares = if pair.total_shares == 0 {
+            Self::isqrt(amount_a * amount_b)
+        } else {
+            let by_a = amount_a * pair.total_shares / pair.reserve_a;
+            let by_b = amount_b * pair.total_shares / pair.reserve_b;
+            by_a.min(by_b)
+        };
+        if shares <= 0 {
+            return Err(AMMError::InsufficientLiquidity.into());
+        }
+
+        let this = env.current_contract_address();
+        let flow = Symbol::new(env, "amm_add_liquidity");
+        TransferEnforcer::transfer(env, &pair.asset_a, provider, &this, amount_a, flow.clone())?;
+        TransferEnforcer::transfer(env, &pair.asset_b, provider, &this, amount_b, flow)?;
+
+        pair.reserve_a += amount_a;
+        pair.reserve_b += amount_b;
+        pair.total_shares += shares;
+        pair.last_updated = env.ledger().timestamp();
+        AMMStorage::save_pair(env, &pair);
+        let held = AMMStorage::get_lp_shares(env, pair_id, provider);
+        AMMStorage::set_lp_shares(env, pair_id, provider, held + shares);
+        let mut deposit = AMMStorage::get_lp_deposit(env, pair_id, provider);
+        deposit.amount_a += amount_a;
+        deposit.amount_b += amount_b;
+        AMMStorage::set_lp_deposit(env, pair_id, provider, &deposit);
+
+        Self::emit(
+            env,
+            "amm_liquidity_added",
+            pair_id,
+            Some(provider.clone()),
+            shares,
+        );
+        Ok(shares)
+    }
+
+    /// Burn LP shares for a pro-rata slice of both reserves. Always allowed,
+    /// including while the pair is paused or deactivated.
+    pub fn remove_liquidity(
+        env: &Env,
+        provider: &Address,
+        pair_id: &PairKey,
+        shares: i128,
+    ) -> Result<(i128, i128), ProtocolError> {
+        if shares <= 0 {
+            return Err(AMMError::InvalidSwapParams.into());
+        }
+        let mut pair = Self::require_pair(env, pair_id)?;
+        let held = AMMStorage::get_lp_shares(env, pair_id, provider);
+        if shares > held {
+            return Err(AMMError::InsufficientLiquidity.into());
+        }
+
+        let amount_a = shares * pair.reserve_a / pair.total_shares;
+        let amount_b = shares * pair.reserve_b / pair.total_shares;
+        pair.reserve_a -= amount_a;
+        pair.reserve_b -= amount_b;
+        pair.total_shares -= shares;
+        pair.last_updated = env.ledger().timestamp();
+        AMMStorage::save_pair(env, &pair);
+        AMMStorage::set_lp_shares(env, pair_id, provider, held - shares);
+        // The remaining shares keep their share of the recorded cost
+        let deposit = AMMStorage::get_lp_deposit(env, pair_id, provider);
+        AMMStorage::set_lp_deposit(
+            env,
+            pair_id,
+            provider,
+            &LpDeposit {
+                amount_a: deposit.amount_a * (held - shares) / held,
+                amount_b: deposit.amount_b * (held - shares) / held,
+            },
+        );
+
+        let this = env.current_contract_address();
+        let flow = Symbol::new(env, "amm_remove_liquidity");
+        TransferEnforcer::transfer(env, &pair.asset_a, &this, provider, amount_a, flow.clone())?;
+        TransferEnforcer::transfer(env, &pair.asset_b, &this, provider, amount_b, flow)?;
+
+        Self::emit(
+            env,
+            "amm_liquidity_removed",
+            pair_id,
+            Some(provider.clone()),
+            shares,
+        );
+        Ok((amount_a, amount_b))
+    }
+
+    /// Optimistically send `amount_out_a`/`amount_out_b` (in `pair_id` order) to
+    /// `receiver`, invoke its `on_flash_swap(caller, pair_id, amount_out_a,
+    /// amount_out_b, data)`, then require that whatever came back keeps the
+    /// fee-adjusted constant product at or above its starting value.
+    ///
+    /// Returns the amounts of each asset paid back in.
+    #[allow(clippy::too_many_arguments)]
+    pub fn flash_swap(
+        env: &Env,
+        caller: &Address,
+        pair_id: &PairKey,
+        amount_out_a: i128,
+        amount_out_b: i128,
+        receiver: &Address,
+        data: Bytes,
+    ) -> Result<(i128, i128), ProtocolError> {
+        if amount_out_a < 0 || amount_out_b < 0 || (amount_out_a == 0 && amount_out_b == 0) {
+            return Err(AMMError::InvalidSwapParams.into());
+        }
+        let mut pair = Self::require_pair(env, pair_id)?;
+        if !pair.is_active {
+            return Err(AMMError::PairNotRegistered.into());
+        }
+        if pair.paused {
+            return Err(AMMError::PairPaused.into());
+        }
+        // Reserves in `pair_id` order
+        let (reserve_a, reserve_b) = pair.reserves_for(&pair_id.asset_a);
+        if amount_out_a >= reserve_a || amount_out_b >= reserve_b {
+            return Err(AMMError::InsufficientLiquidity.into());
+        }
+
+        ReentrancyGuard::enter(env)?;
+        let result = (|| -> Result<(i128, i128), ProtocolError> {
+            let this = env.current_contract_address();
+            let token_a = TokenClient::new(env, &pair_id.asset_a);
+            let token_b = TokenClient::new(env, &pair_id.asset_b);
+            let before_a = token_a.balance(&this);
+            let before_b = token_b.balance(&this);
+
+            let flow = Symbol::new(env, "flash_swap");
+            if amount_out_a > 0 {
+                TransferEnforcer::transfer(
+                    env,
+                    &pair_id.asset_a,
+                    &this,
+                    receiver,
+                    amount_out_a,
+                    flow.clone(),
+                )?;
+            }
+            if amount_out_b > 0 {
+                TransferEnforcer::transfer(
+                    env,
+                    &pair_id.asset_b,
+                    &this,
+                    receiver,
+                    amount_out_b,
+                    flow,
+                )?;
+            }
+            let args = vec![
+                env,
+                caller.clone().into_val(env),
+                pair_id.clone().into_val(env),
+                amount_out_a.into_val(env),
+                amount_out_b.into_val(env),
+                data.into_val(env),
+            ];
+            let _: () = env.invoke_contract(receiver, &Symbol::new(env, "on_flash_swap"), args);
+
+            let in_a = (token_a.balance(&this) - (before_a - amount_out_a)).max(0);
+            let in_b = (token_b.balance(&this) - (before_b - amount_out_b)).max(0);
+            let new_a = reserve_a - amount_out_a + in_a;
+            let new_b = reserve_b - amount_out_b + in_b;
+
+            // Fees are charged on whatever came back in
+            let adjusted_a = new_a * 10000 - in_a * pair.fee_bps;
+            let adjusted_b = new_b * 10000 - in_b * pair.fee_bps;
+            let lhs = adjusted_a.checked_mul(adjusted_b);
+            let rhs = (reserve_a * reserve_b).checked_mul(10000 * 10000);
+            match (lhs, rhs) {
+                (Some(l), Some(r)) if l >= r => {}
+                (Some(_), Some(_)) => return Err(ProtocolError::FlashSwapInvariantViolated),
+                _ => return Err(ProtocolError::InvalidAmount),
+            }
+
+            if pair.asset_a == pair_id.asset_a {
+                pair.reserve_a = new_a;
+                pair.reserve_b = new_b;
+            } else {
+                pair.reserve_a = new_b;
+                pair.reserve_b = new_a;
+            }
+            pair.last_updated = env.ledger().timestamp();
+            AMMStorage::save_pair(env, &pair);
+            Self::emit(
+                env,
+                "amm_flash_swap",
+                pair_id,
+                Some(caller.clone()),
+                amount_out_a + amount_out_b,
+            );
+            Ok((in_a, in_b))
+        })();
+        ReentrancyGuard::exit(env);
+        result
+    }
+
     /// Get total number of registered pairs
     pub fn get_total_pairs(env: &Env) -> i128 {
         AMMStorage::get_pair_count(env)
@@ -417,6 +1301,7 @@ impl AMMRegistry {
 
     /// Execute a swap through registered AMM
     pub fn execute_swap(env: &Env, params: SwapParams) -> Result<SwapResult, ProtocolError> {
+        Deadline::check(env, params.deadline)?;
         ReentrancyGuard::enter(env)?;
         let result = (|| -> Result<SwapResult, ProtocolError> {
             // Validate parameters
@@ -428,33 +1313,58 @@ impl AMMRegistry {
                 return Err(AMMError::InvalidSwapParams.into());
             }
 
-            // Check deadline
-            if params.deadline > 0 && env.ledger().timestamp() > params.deadline {
-                return Err(AMMError::SwapFailed.into());
-            }
-
             // Get the pair
-            let pair = AMMStorage::get_pair(env, &params.asset_in, &params.asset_out)
+            let mut pair = AMMStorage::get_pair(env, &params.asset_in, &params.asset_out)
                 .ok_or(AMMError::PairNotRegistered)?;
 
             if !pair.is_active {
                 return Err(AMMError::PairNotRegistered.into());
             }
+            if pair.paused {
+                return Err(AMMError::PairPaused.into());
+            }
 
-            // In a real implementation, this would call the actual AMM contract
-            // For now, we simulate the swap result
-            let fee_bps = 30; // 0.3% fee
-            let fee = (params.amount_in * fee_bps) / 10000;
-            let amount_after_fee = params.amount_in - fee;
-
-            // Simulated exchange rate (1:1 for simplicity - in production would call AMM)
-            let amount_out = amount_after_fee;
+            // Pairs without internal liquidity are routed to the external AMM;
+            // we simulate its result at a 1:1 rate
+            let (amount_out, fee) = pair.swap_out(&params.asset_in, params.amount_in);
 
             // Check slippage
             if amount_out < params.min_amount_out {
                 return Err(AMMError::SlippageExceeded.into());
             }
 
+            if pair.has_liquidity() {
+                if amount_out <= 0 {
+                    return Err(AMMError::InsufficientLiquidity.into());
+                }
+                let this = env.current_contract_address();
+                TransferEnforcer::transfer(
+                    env,
+                    &params.asset_in,
+                    &params.user,
+                    &this,
+                    params.amount_in,
+                    Symbol::new(env, "amm_swap_in"),
+                )?;
+                TransferEnforcer::transfer(
+                    env,
+                    &params.asset_out,
+                    &this,
+                    &params.user,
+                    amount_out,
+                    Symbol::new(env, "amm_swap_out"),
+                )?;
+                if params.asset_in == pair.asset_a {
+                    pair.reserve_a += params.amount_in;
+                    pair.reserve_b -= amount_out;
+                } else {
+                    pair.reserve_b += params.amount_in;
+                    pair.reserve_a -= amount_out;
+                }
+                pair.last_updated = env.ledger().timestamp();
+                AMMStorage::save_pair(env, &pair);
+            }
+
             let timestamp = env.ledger().timestamp();
             let swap_result = SwapResult::new(params.amount_in, amount_out, fee, timestamp);
 
@@ -522,6 +1432,7 @@ impl AMMRegistry {
         debt_asset: &Address,
         sell_amount: i128,
         min_debt_repayment: i128,
+        deadline_ts: u64,
     ) -> Result<SwapResult, ProtocolError> {
         // Create swap params
         let params = SwapParams::new(
@@ -531,7 +1442,8 @@ impl AMMRegistry {
             sell_amount,
             min_debt_repayment,
         )
-        .with_slippage(150); // 1.5% slippage tolerance for deleveraging
+        .with_slippage(150) // 1.5% slippage tolerance for deleveraging
+        .with_deadline(deadline_ts);
 
         // Execute the swap
         let swap_result = Self::execute_swap(env, params)?;
@@ -546,18 +1458,37 @@ impl AMMRegistry {
         Ok(swap_result)
     }
 
-    /// Get swap history for analytics
-    pub fn get_swap_history(env: &Env) -> Vec<SwapResult> {
-        AMMStorage::get_swap_history(env)
+    /// Page through the retained swap history, oldest first
+    pub fn get_swap_page(env: &Env, cursor: u32, limit: u32) -> SwapPage {
+        let history = AMMStorage::get_swap_history(env);
+        let total = history.len();
+        let (start, end) = pagination::bounds(total, cursor, limit, MAX_PAGE_LIMIT);
+        SwapPage {
+            items: history.slice(start..end),
+            total: total as u64,
+            cursor: end,
+        }
     }
 
-    /// Get all registered pairs
+    /// Get all registered pairs in registration order.
+    ///
+    /// Pairs registered before the ordered index existed are appended
+    /// afterwards in storage key order so listings stay deterministic.
     pub fn get_all_pairs(env: &Env) -> Vec<AssetPair> {
         let pairs_map = AMMStorage::get_all_pairs(env);
+        let order = AMMStorage::get_pair_order(env);
         let mut pairs_vec = Vec::new(env);
 
-        for (_, pair) in pairs_map.iter() {
-            pairs_vec.push_back(pair);
+        for key in order.iter() {
+            if let Some(pair) = pairs_map.get(key) {
+                pairs_vec.push_back(pair);
+            }
+        }
+
+        for (key, pair) in pairs_map.iter() {
+            if !order.contains(&key) {
+                pairs_vec.push_back(pair);
+            }
         }
 
         pairs_vec
@@ -604,6 +1535,39 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_get_all_pairs_registration_order() {
+        let (env, contract_id) = create_test_env();
+
+        let amm_address = Address::generate(&env);
+        let mut assets = Vec::new(&env);
+        for _ in 0..6 {
+            assets.push_back(Address::generate(&env));
+        }
+
+        env.as_contract(&contract_id, || {
+            // Register pairs in reverse address order so map order differs
+            for i in (0..3).rev() {
+                AMMRegistry::register_pair(
+                    &env,
+                    assets.get(i * 2 + 1).unwrap(),
+                    assets.get(i * 2).unwrap(),
+                    amm_address.clone(),
+                    None,
+                )
+                .unwrap();
+            }
+
+            let pairs = AMMRegistry::get_all_pairs(&env);
+            assert_eq!(pairs.len(), 3);
+            for (idx, i) in (0..3u32).rev().enumerate() {
+                let pair = pairs.get(idx as u32).unwrap();
+                assert_eq!(pair.asset_a, assets.get(i * 2 + 1).unwrap());
+                assert_eq!(pair.asset_b, assets.get(i * 2).unwrap());
+            }
+        });
+    }
+
     #[test]
     fn test_register_duplicate_pair_fails() {
         let (env, contract_id) = create_test_env();
@@ -784,8 +1748,9 @@ mod tests {
             }
 
             // Get swap history
-            let history = AMMRegistry::get_swap_history(&env);
-            assert_eq!(history.len(), 3);
+            let history = AMMRegistry::get_swap_page(&env, 0, 10);
+            assert_eq!(history.items.len(), 3);
+            assert_eq!(history.total, 3);
         });
     }
 }
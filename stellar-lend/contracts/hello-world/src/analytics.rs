@@ -11,7 +11,13 @@
 use core::cmp::min;
 use soroban_sdk::{contracterror, contracttype, vec, Address, Env, Map, String, Symbol, Vec};
 
-use crate::{ProtocolError, ProtocolEvent};
+use crate::oracle::{Oracle, PRICE_SCALE};
+use crate::privacy::Privacy;
+use crate::twa::{TimeWeighted, TwaAccumulator};
+use crate::{InterestRateStorage, Position, ProtocolError, ProtocolEvent, TokenRegistry};
+
+/// Largest page returned by `get_recent_activity` (also the activity log bound)
+pub const MAX_ACTIVITY_PAGE: u32 = 1000;
 
 /// Analytics-specific error types
 #[contracterror]
@@ -179,10 +185,82 @@ pub struct AssetAnalytics {
     pub volume_7d: i128,
     /// Volume in last 30d
     pub volume_30d: i128,
+    /// Collateral position counts by size bucket
+    pub collateral_distribution: SizeBuckets,
+    /// Debt position counts by size bucket
+    pub debt_distribution: SizeBuckets,
     /// Last update timestamp
     pub last_update: u64,
 }
 
+/// Position counts by size bucket (amounts in asset units)
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[contracttype]
+pub struct SizeBuckets {
+    /// Positions below 100
+    pub under_100: i128,
+    /// Positions from 100 up to 1k
+    pub to_1k: i128,
+    /// Positions from 1k up to 10k
+    pub to_10k: i128,
+    /// Positions from 10k up to 100k
+    pub to_100k: i128,
+    /// Positions of 100k and above
+    pub over_100k: i128,
+}
+
+impl SizeBuckets {
+    /// Bucket index for an amount: 0 for empty positions, 1..=5 otherwise
+    pub fn bucket_for(amount: i128) -> u32 {
+        if amount <= 0 {
+            0
+        } else if amount < 100 {
+            1
+        } else if amount < 1_000 {
+            2
+        } else if amount < 10_000 {
+            3
+        } else if amount < 100_000 {
+            4
+        } else {
+            5
+        }
+    }
+
+    fn adjust(&mut self, bucket: u32, delta: i128) {
+        match bucket {
+            1 => self.under_100 += delta,
+            2 => self.to_1k += delta,
+            3 => self.to_10k += delta,
+            4 => self.to_100k += delta,
+            5 => self.over_100k += delta,
+            _ => {}
+        }
+    }
+
+    /// Move one position from bucket `from` to bucket `to`
+    pub fn transition(&mut self, from: u32, to: u32) {
+        if from != to {
+            self.adjust(from, -1);
+            self.adjust(to, 1);
+        }
+    }
+}
+
+/// Position size distribution for an asset.
+///
+/// Buckets are updated when a position is written, so a position that
+/// crosses a boundary purely through price moves is only re-bucketed on
+/// its owner's next interaction.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct AssetDistribution {
+    pub asset: Address,
+    pub collateral: SizeBuckets,
+    pub debt: SizeBuckets,
+    pub last_update: u64,
+}
+
 impl AssetAnalytics {
     pub fn new(asset: Address) -> Self {
         Self {
@@ -196,6 +274,8 @@ impl AssetAnalytics {
             volume_24h: 0,
             volume_7d: 0,
             volume_30d: 0,
+            collateral_distribution: SizeBuckets::default(),
+            debt_distribution: SizeBuckets::default(),
             last_update: 0,
         }
     }
@@ -211,6 +291,14 @@ pub struct HistoricalDataPoint {
     pub metrics: ProtocolMetrics,
     /// Asset-specific data
     pub asset_data: Map<Address, AssetAnalytics>,
+    /// Time-weighted accumulator reading of the primary asset
+    pub twa: TwaAccumulator,
+    /// Time-weighted average utilization (scaled by 1e8) since the previous
+    /// day's snapshot
+    pub avg_utilization: i128,
+    /// Time-weighted average borrow rate (scaled by 1e8) since the previous
+    /// day's snapshot
+    pub avg_borrow_rate: i128,
 }
 
 /// Risk analytics
@@ -288,25 +376,25 @@ pub struct AnalyticsStorage;
 
 impl AnalyticsStorage {
     // Storage keys
-    fn protocol_metrics_key(env: &Env) -> Symbol {
+    pub(crate) fn protocol_metrics_key(env: &Env) -> Symbol {
         Symbol::new(env, "protocol_metrics")
     }
-    fn user_analytics_key(env: &Env) -> Symbol {
+    pub(crate) fn user_analytics_key(env: &Env) -> Symbol {
         Symbol::new(env, "user_analytics")
     }
-    fn asset_analytics_key(env: &Env) -> Symbol {
+    pub(crate) fn asset_analytics_key(env: &Env) -> Symbol {
         Symbol::new(env, "asset_analytics")
     }
-    fn historical_data_key(env: &Env) -> Symbol {
+    pub(crate) fn historical_data_key(env: &Env) -> Symbol {
         Symbol::new(env, "historical_data")
     }
-    fn risk_analytics_key(env: &Env) -> Symbol {
+    pub(crate) fn risk_analytics_key(env: &Env) -> Symbol {
         Symbol::new(env, "risk_analytics")
     }
-    fn performance_metrics_key(env: &Env) -> Symbol {
+    pub(crate) fn performance_metrics_key(env: &Env) -> Symbol {
         Symbol::new(env, "performance_metrics")
     }
-    fn activity_log_key(env: &Env) -> Symbol {
+    pub(crate) fn activity_log_key(env: &Env) -> Symbol {
         Symbol::new(env, "activity_log")
     }
 
@@ -376,6 +464,77 @@ impl AnalyticsStorage {
         Self::_put_asset_analytics(env, &analytics_map);
     }
 
+    pub(crate) fn collateral_exposure_key(env: &Env) -> Symbol {
+        Symbol::new(env, "collateral_exposure")
+    }
+    pub(crate) fn exposure_share_key(env: &Env, user: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "exposure_share"), user.clone())
+    }
+    pub(crate) fn collateral_mix_key(env: &Env, user: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "collateral_mix"), user.clone())
+    }
+
+    /// USD debt attributed to each collateral asset
+    pub fn get_collateral_exposure(env: &Env) -> Map<Address, i128> {
+        env.storage()
+            .instance()
+            .get(&Self::collateral_exposure_key(env))
+            .unwrap_or_else(|| Map::new(env))
+    }
+    fn put_collateral_exposure(env: &Env, exposure: &Map<Address, i128>) {
+        env.storage()
+            .instance()
+            .set(&Self::collateral_exposure_key(env), exposure);
+    }
+
+    /// A user's current contribution to each asset's exposure
+    pub fn get_exposure_share(env: &Env, user: &Address) -> Map<Address, i128> {
+        env.storage()
+            .instance()
+            .get(&Self::exposure_share_key(env, user))
+            .unwrap_or_else(|| Map::new(env))
+    }
+    fn put_exposure_share(env: &Env, user: &Address, share: &Map<Address, i128>) {
+        Self::put_or_remove(env, &Self::exposure_share_key(env, user), share);
+    }
+
+    /// Collateral a user deposited in assets other than the primary one
+    pub fn get_collateral_mix(env: &Env, user: &Address) -> Map<Address, i128> {
+        env.storage()
+            .instance()
+            .get(&Self::collateral_mix_key(env, user))
+            .unwrap_or_else(|| Map::new(env))
+    }
+    pub fn _put_collateral_mix(env: &Env, user: &Address, mix: &Map<Address, i128>) {
+        Self::put_or_remove(env, &Self::collateral_mix_key(env, user), mix);
+    }
+
+    fn put_or_remove(env: &Env, key: &(Symbol, Address), map: &Map<Address, i128>) {
+        if map.is_empty() {
+            env.storage().instance().remove(key);
+        } else {
+            env.storage().instance().set(key, map);
+        }
+    }
+
+    pub(crate) fn size_bucket_key(env: &Env, user: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "size_bucket"), user.clone())
+    }
+
+    /// Current (collateral, debt) size buckets for a user
+    pub fn get_size_bucket(env: &Env, user: &Address) -> (u32, u32) {
+        env.storage()
+            .instance()
+            .get(&Self::size_bucket_key(env, user))
+            .unwrap_or((0, 0))
+    }
+
+    pub fn put_size_bucket(env: &Env, user: &Address, buckets: (u32, u32)) {
+        env.storage()
+            .instance()
+            .set(&Self::size_bucket_key(env, user), &buckets);
+    }
+
     // Historical data
     pub fn get_historical_data(env: &Env) -> Map<u64, HistoricalDataPoint> {
         env.storage()
@@ -477,6 +636,11 @@ impl AnalyticsModule {
     ) -> Result<(), ProtocolError> {
         let timestamp = env.ledger().timestamp();
 
+        // Opted-out users only count toward protocol-wide totals
+        if !Privacy::tracks_analytics(env, user) {
+            return Self::update_protocol_metrics(env, activity_type, amount);
+        }
+
         // Create activity log entry
         let entry = ActivityLogEntry {
             timestamp,
@@ -625,10 +789,26 @@ impl AnalyticsModule {
 
         let mut historical_data = AnalyticsStorage::get_historical_data(env);
 
+        // Difference against the last snapshot of an earlier day, or against
+        // the start of accumulation for the first one
+        let twa = Self::primary_twa_reading(env);
+        let baseline = historical_data
+            .keys()
+            .iter()
+            .rev()
+            .find(|day| *day < day_bucket)
+            .and_then(|day| historical_data.get(day))
+            .map(|point| point.twa)
+            .unwrap_or_else(|| twa.origin());
+        let (avg_utilization, avg_borrow_rate) = Self::averages(env, &twa, &baseline);
+
         let data_point = HistoricalDataPoint {
             timestamp,
             metrics: metrics.clone(),
             asset_data: Map::new(env), // Will be populated separately
+            twa,
+            avg_utilization,
+            avg_borrow_rate,
         };
 
         historical_data.set(day_bucket, data_point);
@@ -637,6 +817,48 @@ impl AnalyticsModule {
         Ok(())
     }
 
+    fn primary_twa_reading(env: &Env) -> TwaAccumulator {
+        match TokenRegistry::primary_asset(env) {
+            Some(asset) => TimeWeighted::reading(env, &asset),
+            None => TwaAccumulator {
+                utilization_seconds: 0,
+                borrow_rate_seconds: 0,
+                updated_at: 0,
+                started_at: 0,
+            },
+        }
+    }
+
+    /// Averages between two readings, falling back to the spot values when
+    /// no time separates them
+    fn averages(env: &Env, twa: &TwaAccumulator, baseline: &TwaAccumulator) -> (i128, i128) {
+        twa.average_since(baseline).unwrap_or_else(|| {
+            let state = InterestRateStorage::get_state(env);
+            (state.utilization_rate, state.current_borrow_rate)
+        })
+    }
+
+    /// Time-weighted average utilization and borrow rate of the primary
+    /// asset over roughly the last 24 hours: since the latest daily snapshot
+    /// at least a day old, or since accumulation started if there is none
+    pub fn trailing_averages(env: &Env) -> (i128, i128) {
+        let twa = Self::primary_twa_reading(env);
+        let cutoff = env.ledger().timestamp().saturating_sub(86400);
+        let baseline = AnalyticsStorage::get_historical_data(env)
+            .values()
+            .iter()
+            .rev()
+            .map(|point| point.twa)
+            .find(|reading| reading.started_at != 0 && reading.updated_at <= cutoff)
+            .unwrap_or_else(|| twa.origin());
+        Self::averages(env, &twa, &baseline)
+    }
+
+    /// Get the daily snapshot for `day` (timestamp / 86400)
+    pub fn get_daily_snapshot(env: &Env, day: u64) -> Option<HistoricalDataPoint> {
+        AnalyticsStorage::get_historical_data(env).get(day)
+    }
+
     /// Get comprehensive protocol report
     pub fn get_protocol_report(env: &Env) -> Result<ProtocolReport, ProtocolError> {
         let protocol_metrics = AnalyticsStorage::get_protocol_metrics(env);
@@ -706,6 +928,158 @@ impl AnalyticsModule {
         })
     }
 
+    /// Re-bucket a user's position after it changed
+    pub fn update_size_distribution(
+        env: &Env,
+        asset: &Address,
+        user: &Address,
+        collateral: i128,
+        debt: i128,
+    ) {
+        if !Privacy::tracks_analytics(env, user) {
+            return;
+        }
+        let (old_collateral, old_debt) = AnalyticsStorage::get_size_bucket(env, user);
+        let new_collateral = SizeBuckets::bucket_for(collateral);
+        let new_debt = SizeBuckets::bucket_for(debt);
+        if old_collateral == new_collateral && old_debt == new_debt {
+            return;
+        }
+
+        let mut analytics = AnalyticsStorage::get_asset_analytics_for_asset(env, asset);
+        analytics
+            .collateral_distribution
+            .transition(old_collateral, new_collateral);
+        analytics.debt_distribution.transition(old_debt, new_debt);
+        analytics.last_update = env.ledger().timestamp();
+        AnalyticsStorage::_update_asset_analytics(env, asset, &analytics);
+        AnalyticsStorage::put_size_bucket(env, user, (new_collateral, new_debt));
+    }
+
+    /// Record `amount` of collateral deposited (negative: withdrawn) in a
+    /// non-primary asset, ahead of the position save that refreshes exposure
+    pub fn _record_collateral_asset(env: &Env, user: &Address, asset: &Address, amount: i128) {
+        let mut mix = AnalyticsStorage::get_collateral_mix(env, user);
+        let held = mix.get(asset.clone()).unwrap_or(0).saturating_add(amount);
+        if held > 0 {
+            mix.set(asset.clone(), held);
+        } else {
+            mix.remove(asset.clone());
+        }
+        AnalyticsStorage::_put_collateral_mix(env, user, &mix);
+    }
+
+    /// Collateral amounts of a position by asset. Non-primary deposits are
+    /// taken as recorded (capped at the position's collateral, oldest asset
+    /// first) and the primary asset holds the rest.
+    pub fn collateral_by_asset(env: &Env, position: &Position) -> Map<Address, i128> {
+        let mut amounts = Map::new(env);
+        let mut left = position.collateral.max(0);
+        for (asset, amount) in AnalyticsStorage::get_collateral_mix(env, &position.user).iter() {
+            let amount = min(amount, left);
+            if amount > 0 {
+                amounts.set(asset, amount);
+                left -= amount;
+            }
+        }
+        if let Some(primary) = TokenRegistry::primary_asset(env) {
+            if left > 0 {
+                let held = amounts.get(primary.clone()).unwrap_or(0);
+                amounts.set(primary, held + left);
+            }
+        }
+        amounts
+    }
+
+    /// USD value of `amount` at the asset's last aggregated price; assets
+    /// that were never priced count at par
+    fn usd_at_last_price(env: &Env, asset: &Address, amount: i128) -> i128 {
+        match Oracle::last_price(env, asset) {
+            Some((price, _)) => amount.saturating_mul(price) / PRICE_SCALE,
+            None => amount,
+        }
+    }
+
+    /// Re-attribute a position's debt to its collateral assets, pro rata by
+    /// the USD value of each, and move the per-asset exposure totals by the
+    /// difference from its previous attribution
+    pub fn update_collateral_exposure(env: &Env, position: &Position) {
+        let old_share = AnalyticsStorage::get_exposure_share(env, &position.user);
+        let mut new_share: Map<Address, i128> = Map::new(env);
+        let debt = position.debt.saturating_add(position.borrow_interest);
+        if let (true, Some(primary)) = (debt > 0, TokenRegistry::primary_asset(env)) {
+            let debt_usd = Self::usd_at_last_price(env, &primary, debt);
+            let mut values: Map<Address, i128> = Map::new(env);
+            let mut total: i128 = 0;
+            for (asset, amount) in Self::collateral_by_asset(env, position).iter() {
+                let value = Self::usd_at_last_price(env, &asset, amount);
+                total = total.saturating_add(value);
+                values.set(asset, value);
+            }
+            if total > 0 {
+                for (asset, value) in values.iter() {
+                    let share = debt_usd.saturating_mul(value) / total;
+                    if share > 0 {
+                        new_share.set(asset, share);
+                    }
+                }
+            }
+        }
+        if new_share == old_share {
+            return;
+        }
+
+        let mut exposure = AnalyticsStorage::get_collateral_exposure(env);
+        for (asset, share) in old_share.iter() {
+            let left = exposure.get(asset.clone()).unwrap_or(0) - share;
+            exposure.set(asset, left);
+        }
+        for (asset, share) in new_share.iter() {
+            let total = exposure.get(asset.clone()).unwrap_or(0) + share;
+            exposure.set(asset, total);
+        }
+        let mut kept = Map::new(env);
+        for (asset, total) in exposure.iter() {
+            if total > 0 {
+                kept.set(asset, total);
+            }
+        }
+        AnalyticsStorage::put_collateral_exposure(env, &kept);
+        AnalyticsStorage::put_exposure_share(env, &position.user, &new_share);
+    }
+
+    /// USD debt backed by `asset` as collateral
+    pub fn get_collateral_exposure(env: &Env, asset: &Address) -> i128 {
+        AnalyticsStorage::get_collateral_exposure(env)
+            .get(asset.clone())
+            .unwrap_or(0)
+    }
+
+    /// Largest single asset's share of all attributed exposure, in bps
+    pub fn collateral_concentration_bps(env: &Env) -> i128 {
+        let mut total: i128 = 0;
+        let mut largest: i128 = 0;
+        for (_, exposure) in AnalyticsStorage::get_collateral_exposure(env).iter() {
+            total = total.saturating_add(exposure);
+            largest = largest.max(exposure);
+        }
+        if total == 0 {
+            return 0;
+        }
+        largest.saturating_mul(10000) / total
+    }
+
+    /// Get the position size distribution for an asset
+    pub fn get_asset_distribution(env: &Env, asset: &Address) -> AssetDistribution {
+        let analytics = AnalyticsStorage::get_asset_analytics_for_asset(env, asset);
+        AssetDistribution {
+            asset: asset.clone(),
+            collateral: analytics.collateral_distribution,
+            debt: analytics.debt_distribution,
+            last_update: analytics.last_update,
+        }
+    }
+
     /// Calculate risk analytics
     pub fn calculate_risk_analytics(env: &Env) -> Result<RiskAnalytics, ProtocolError> {
         let mut risk_analytics = RiskAnalytics::new();
@@ -780,7 +1154,7 @@ impl AnalyticsModule {
         let log = AnalyticsStorage::get_activity_log(env);
         let len = log.len();
         let total_entries = len;
-        let max_window = min(limit, 1000);
+        let max_window = min(limit, MAX_ACTIVITY_PAGE);
         let take = min(max_window, len);
 
         let mut entries = Vec::new(env);
@@ -0,0 +1,226 @@
+//! Scheduled repayment plans
+//!
+//! A borrower schedules a fixed installment every `period_secs`. Once an
+//! installment is due, anyone (typically a keeper) may execute it: the
+//! installment is redeemed from the borrower's own supplied balance of the
+//! debt asset and netted against their debt, so no tokens move.
+//!
+//! A due installment the supplied balance cannot cover is skipped rather
+//! than failed, and the plan moves on to the next period. Plans end when
+//! the owner cancels them or when an execution finds the debt fully repaid
+//! (including debt cleared by other means since the last installment).
+
+use crate::analytics::AnalyticsModule;
+use crate::credit::Credit;
+use crate::penalty::WithdrawalPenalty;
+use crate::{
+    EmergencyManager, EventTracker, InterestRateManager, InterestRateStorage, OperationKind,
+    ProtocolError, ProtocolEvent, StateHelper, TokenRegistry,
+};
+use soroban_sdk::{contracttype, Address, Env, Symbol, Vec};
+
+/// A borrower's repayment schedule
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct RepayPlan {
+    pub user: Address,
+    pub asset: Address,
+    pub amount_per_period: i128,
+    pub period_secs: u64,
+    /// Earliest time the next installment may execute
+    pub next_due: u64,
+    pub installments_paid: u32,
+    pub installments_skipped: u32,
+}
+
+/// Result of executing a due installment
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum RepayPlanOutcome {
+    /// Installment repaid; debt remains
+    Repaid,
+    /// Supplied balance could not cover the installment
+    Skipped,
+    /// Debt is fully repaid and the plan has ended
+    Completed,
+}
+
+/// Repayment plan storage management
+pub struct RepayPlanStorage;
+
+impl RepayPlanStorage {
+    pub(crate) fn plan_key(env: &Env, user: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "repay_plan"), user.clone())
+    }
+
+    pub fn get(env: &Env, user: &Address) -> Option<RepayPlan> {
+        env.storage().instance().get(&Self::plan_key(env, user))
+    }
+
+    pub fn save(env: &Env, plan: &RepayPlan) {
+        env.storage()
+            .instance()
+            .set(&Self::plan_key(env, &plan.user), plan);
+    }
+
+    pub fn remove(env: &Env, user: &Address) {
+        env.storage().instance().remove(&Self::plan_key(env, user));
+    }
+}
+
+/// Repayment plan logic
+pub struct RepayPlans;
+
+impl RepayPlans {
+    /// Create or replace `user`'s plan. The first installment is due one
+    /// period from now.
+    pub fn create(
+        env: &Env,
+        user: &Address,
+        asset: &Address,
+        amount_per_period: i128,
+        period_secs: u64,
+    ) -> Result<RepayPlan, ProtocolError> {
+        if amount_per_period <= 0 {
+            return Err(ProtocolError::InvalidAmount);
+        }
+        if period_secs == 0 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        // Debt and supply are only tracked for the primary asset
+        if TokenRegistry::primary_asset(env).as_ref() != Some(asset) {
+            return Err(ProtocolError::AssetNotSupported);
+        }
+        let position =
+            StateHelper::get_position(env, user).ok_or(ProtocolError::PositionNotFound)?;
+        if position.debt <= 0 {
+            return Err(ProtocolError::InvalidOperation);
+        }
+
+        let plan = RepayPlan {
+            user: user.clone(),
+            asset: asset.clone(),
+            amount_per_period,
+            period_secs,
+            next_due: env.ledger().timestamp().saturating_add(period_secs),
+            installments_paid: 0,
+            installments_skipped: 0,
+        };
+        RepayPlanStorage::save(env, &plan);
+        Self::emit(env, "repay_plan_created", &plan, amount_per_period);
+        Ok(plan)
+    }
+
+    pub fn cancel(env: &Env, user: &Address) -> Result<(), ProtocolError> {
+        let plan = RepayPlanStorage::get(env, user).ok_or(ProtocolError::NotFound)?;
+        RepayPlanStorage::remove(env, user);
+        Self::emit(env, "repay_plan_canceled", &plan, 0);
+        Ok(())
+    }
+
+    /// Drop `user`'s plan, if any, as when their position moves to another
+    /// account
+    pub fn revoke(env: &Env, user: &Address) {
+        if let Some(plan) = RepayPlanStorage::get(env, user) {
+            RepayPlanStorage::remove(env, user);
+            Self::emit(env, "repay_plan_canceled", &plan, 0);
+        }
+    }
+
+    /// Execute `user`'s due installment (permissionless)
+    pub fn execute(env: &Env, user: &Address) -> Result<RepayPlanOutcome, ProtocolError> {
+        let mut plan = RepayPlanStorage::get(env, user).ok_or(ProtocolError::NotFound)?;
+        if env.ledger().timestamp() < plan.next_due {
+            return Err(ProtocolError::InvalidOperation);
+        }
+        EmergencyManager::ensure_operation_allowed(env, OperationKind::Repay)?;
+
+        let mut position =
+            StateHelper::get_position(env, user).ok_or(ProtocolError::PositionNotFound)?;
+        let state = InterestRateStorage::update_state(env);
+        InterestRateManager::accrue_interest_for_position(
+            env,
+            &mut position,
+            state.current_borrow_rate,
+            state.current_supply_rate,
+        );
+
+        if position.debt <= 0 {
+            StateHelper::save_position(env, &position);
+            RepayPlanStorage::remove(env, user);
+            Self::emit(env, "repay_plan_completed", &plan, 0);
+            return Ok(RepayPlanOutcome::Completed);
+        }
+
+        plan.next_due = plan.next_due.saturating_add(plan.period_secs);
+        let installment = plan.amount_per_period.min(position.debt);
+        if position.collateral < installment {
+            StateHelper::save_position(env, &position);
+            plan.installments_skipped = plan.installments_skipped.saturating_add(1);
+            RepayPlanStorage::save(env, &plan);
+            Self::emit(env, "repay_plan_skipped", &plan, installment);
+            return Ok(RepayPlanOutcome::Skipped);
+        }
+
+        // Redeem from supply without the early-withdrawal penalty, which only
+        // applies to positions with no debt
+        let settlement =
+            WithdrawalPenalty::plan_withdrawal(env, user, &plan.asset, installment, false);
+        WithdrawalPenalty::apply_withdrawal(env, user, &plan.asset, &settlement);
+        position.collateral -= installment;
+        position.debt -= installment;
+        StateHelper::save_position(env, &position);
+        InterestRateStorage::adjust_totals(env, -installment, -installment);
+        plan.installments_paid = plan.installments_paid.saturating_add(1);
+
+        let collateral_ratio = if position.debt > 0 {
+            (position.collateral * 100) / position.debt
+        } else {
+            0
+        };
+        ProtocolEvent::PositionUpdated(
+            user.clone(),
+            position.collateral,
+            position.debt,
+            collateral_ratio,
+        )
+        .emit(env);
+        AnalyticsModule::record_activity(env, user, "repay", installment, None)?;
+        Credit::record_repayment(env, user);
+        Self::emit(env, "repay_plan_executed", &plan, installment);
+
+        if position.debt == 0 {
+            RepayPlanStorage::remove(env, user);
+            Self::emit(env, "repay_plan_completed", &plan, 0);
+            return Ok(RepayPlanOutcome::Completed);
+        }
+        RepayPlanStorage::save(env, &plan);
+        Ok(RepayPlanOutcome::Repaid)
+    }
+
+    fn emit(env: &Env, name: &str, plan: &RepayPlan, amount: i128) {
+        let event_type = Symbol::new(env, name);
+        let mut topics = Vec::new(env);
+        topics.push_back(Symbol::new(env, "user"));
+        topics.push_back(Symbol::new(env, "asset"));
+        EventTracker::record(
+            env,
+            event_type.clone(),
+            topics,
+            Some(plan.user.clone()),
+            Some(plan.asset.clone()),
+            amount,
+        );
+        env.events().publish(
+            (event_type, Symbol::new(env, "user")),
+            (
+                Symbol::new(env, "user"),
+                plan.user.clone(),
+                Symbol::new(env, "amount"),
+                amount,
+                Symbol::new(env, "next_due"),
+                plan.next_due,
+            ),
+        );
+    }
+}
@@ -0,0 +1,169 @@
+//! Closed-beta access list
+//!
+//! A beta is configured at initialization with a hard expiry. While it runs,
+//! only allowlisted addresses may open or grow positions; once `expires_at`
+//! passes, or the admin disables it, every check passes without reading the
+//! list, so launch cannot stay gated by accident.
+//!
+//! Repay, withdraw and liquidation are never gated, so removing a user from
+//! the list cannot trap their funds.
+
+use crate::{EventTracker, ProtocolConfig, ProtocolError};
+use soroban_sdk::{contracttype, Address, Env, Symbol, Vec};
+
+/// Addresses one add or remove call may carry
+pub const MAX_BETA_BATCH: u32 = 50;
+
+/// Beta configuration, fixed at initialization apart from the user count
+/// and the admin's ability to end it early
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct BetaConfig {
+    pub enabled: bool,
+    pub expires_at: u64,
+    /// Addresses currently on the allowlist
+    pub users: u32,
+}
+
+pub struct BetaAccess;
+
+impl BetaAccess {
+    pub(crate) fn config_key(env: &Env) -> Symbol {
+        Symbol::new(env, "beta_config")
+    }
+
+    /// One entry per allowlisted address, so checks never load the whole list
+    pub(crate) fn user_key(env: &Env, user: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "beta_user"), user.clone())
+    }
+
+    pub fn get_config(env: &Env) -> Option<BetaConfig> {
+        env.storage().instance().get(&Self::config_key(env))
+    }
+
+    fn save_config(env: &Env, config: &BetaConfig) {
+        env.storage().instance().set(&Self::config_key(env), config);
+    }
+
+    /// Start a beta ending at `expires_at`; only called while initializing
+    pub fn configure(env: &Env, expires_at: u64) -> Result<(), ProtocolError> {
+        if expires_at <= env.ledger().timestamp() {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        Self::save_config(
+            env,
+            &BetaConfig {
+                enabled: true,
+                expires_at,
+                users: 0,
+            },
+        );
+        Ok(())
+    }
+
+    /// Whether the allowlist is currently enforced
+    pub fn is_active(env: &Env) -> bool {
+        Self::get_config(env)
+            .map(|config| config.enabled && env.ledger().timestamp() < config.expires_at)
+            .unwrap_or(false)
+    }
+
+    pub fn is_member(env: &Env, user: &Address) -> bool {
+        env.storage()
+            .instance()
+            .get(&Self::user_key(env, user))
+            .unwrap_or(false)
+    }
+
+    /// Fail with `Unauthorized` while the beta is active and `caller` is not
+    /// on the allowlist
+    pub fn require_beta_access(env: &Env, caller: &Address) -> Result<(), ProtocolError> {
+        if !Self::is_active(env) || Self::is_member(env, caller) {
+            return Ok(());
+        }
+        Err(ProtocolError::Unauthorized)
+    }
+
+    /// Allowlist `users` (admin only). Returns how many were newly added.
+    pub fn add_users(
+        env: &Env,
+        caller: &Address,
+        users: &Vec<Address>,
+    ) -> Result<u32, ProtocolError> {
+        let mut config = Self::require_batch(env, caller, users)?;
+        let mut added = 0;
+        for user in users.iter() {
+            if !Self::is_member(env, &user) {
+                env.storage()
+                    .instance()
+                    .set(&Self::user_key(env, &user), &true);
+                added += 1;
+            }
+        }
+        config.users += added;
+        Self::save_config(env, &config);
+        Self::emit(env, "beta_users_added", caller, added);
+        Ok(added)
+    }
+
+    /// Drop `users` from the allowlist (admin only). Returns how many were
+    /// removed.
+    pub fn remove_users(
+        env: &Env,
+        caller: &Address,
+        users: &Vec<Address>,
+    ) -> Result<u32, ProtocolError> {
+        let mut config = Self::require_batch(env, caller, users)?;
+        let mut removed = 0;
+        for user in users.iter() {
+            if Self::is_member(env, &user) {
+                env.storage().instance().remove(&Self::user_key(env, &user));
+                removed += 1;
+            }
+        }
+        config.users -= removed;
+        Self::save_config(env, &config);
+        Self::emit(env, "beta_users_removed", caller, removed);
+        Ok(removed)
+    }
+
+    /// End the beta before its expiry (admin only); it cannot be restarted
+    pub fn disable(env: &Env, caller: &Address) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        let mut config = Self::get_config(env).ok_or(ProtocolError::NotFound)?;
+        if !config.enabled {
+            return Err(ProtocolError::InvalidOperation);
+        }
+        config.enabled = false;
+        Self::save_config(env, &config);
+        Self::emit(env, "beta_disabled", caller, config.users);
+        Ok(())
+    }
+
+    fn require_batch(
+        env: &Env,
+        caller: &Address,
+        users: &Vec<Address>,
+    ) -> Result<BetaConfig, ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        if users.is_empty() || users.len() > MAX_BETA_BATCH {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        Self::get_config(env).ok_or(ProtocolError::NotFound)
+    }
+
+    fn emit(env: &Env, name: &str, actor: &Address, count: u32) {
+        let event_type = Symbol::new(env, name);
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        EventTracker::record(
+            env,
+            event_type.clone(),
+            topics,
+            Some(actor.clone()),
+            None,
+            count as i128,
+        );
+        env.events().publish((event_type, actor.clone()), count);
+    }
+}
@@ -1,10 +1,17 @@
 //! Borrow module for StellarLend protocol
 //! Handles borrowing functionality and related operations
 
+use crate::accrual::AccrualManager;
 use crate::analytics::AnalyticsModule;
+use crate::caps::{CapManager, CapStorage};
+use crate::liquidity::Liquidity;
+use crate::oracle::Oracle;
+use crate::surge::BorrowSurge;
+use crate::sweep::PriceSweep;
+use crate::transfer::PositionTransfer;
 use crate::{
     EmergencyManager, InterestRateManager, InterestRateStorage, OperationKind, ProtocolConfig,
-    ProtocolError, ProtocolEvent, ReentrancyGuard, RiskConfigStorage, StateHelper,
+    ProtocolError, ProtocolEvent, ReentrancyGuard, RiskConfigStorage, StateHelper, TokenRegistry,
     TransferEnforcer, UserManager,
 };
 use soroban_sdk::{contracterror, contracttype, Address, Env, String, Symbol};
@@ -69,8 +76,23 @@ impl BorrowParams {
 pub struct BorrowModule;
 
 impl BorrowModule {
-    /// Borrow assets from the protocol
+    /// Borrow assets from the protocol. The owner's own borrow cancels a
+    /// pending transfer of their position.
     pub fn borrow(env: &Env, borrower: &Address, amount: i128) -> Result<(), ProtocolError> {
+        Self::borrow_to(env, borrower, borrower, amount)?;
+        PositionTransfer::cancel_on_activity(env, borrower);
+        Ok(())
+    }
+
+    /// Borrow against `borrower`'s position, sending the funds to `recipient`.
+    /// Leaves a pending position transfer in place, since a credit delegate
+    /// borrowing on an allowance is not the owner's activity.
+    pub fn borrow_to(
+        env: &Env,
+        borrower: &Address,
+        recipient: &Address,
+        amount: i128,
+    ) -> Result<(), ProtocolError> {
         ReentrancyGuard::enter(env)?;
         let result = (|| -> Result<(), ProtocolError> {
             if amount <= 0 {
@@ -87,6 +109,11 @@ impl BorrowModule {
 
             UserManager::ensure_operation_allowed(env, borrower, OperationKind::Borrow, amount)?;
 
+            // Refuse to lend while the oracle sources disagree
+            if let Some(asset) = TokenRegistry::primary_asset(env) {
+                Oracle::require_agreement(env, &asset)?;
+            }
+
             // Load user position
             let mut position = match StateHelper::get_position(env, borrower) {
                 Some(pos) => pos,
@@ -95,6 +122,7 @@ impl BorrowModule {
 
             // Accrue interest
             let state = InterestRateStorage::update_state(env);
+            AccrualManager::ensure_caught_up(env)?;
             InterestRateManager::accrue_interest_for_position(
                 env,
                 &mut position,
@@ -102,8 +130,7 @@ impl BorrowModule {
                 state.current_supply_rate,
             );
 
-            // Check collateral ratio
-            let min_ratio = ProtocolConfig::get_min_collateral_ratio(env);
+            // New debt must clear the buffered origination threshold, not just maintenance
             let new_debt = position.debt + amount;
             let collateral_ratio = if new_debt > 0 {
                 (position.collateral * 100) / new_debt
@@ -111,14 +138,31 @@ impl BorrowModule {
                 0
             };
 
-            if collateral_ratio < min_ratio {
+            // Inside a multicall, health is checked once the batch completes
+            if !ReentrancyGuard::in_batch(env)
+                && !Self::meets_origination_ratio(env, position.collateral, new_debt)
+            {
                 return Err(BorrowError::InsufficientCollateralRatio.into());
             }
 
+            let primary = TokenRegistry::primary_asset(env);
+            if let Some(asset) = &primary {
+                CapManager::check_borrow(env, asset, amount)?;
+                BorrowSurge::check(env, asset)?;
+                Liquidity::require_available(env, asset, amount)?;
+            }
+
             // Update position
-            TransferEnforcer::transfer_out(env, borrower, amount, Symbol::new(env, "borrow"))?;
+            TransferEnforcer::transfer_out(env, recipient, amount, Symbol::new(env, "borrow"))?;
             position.debt = new_debt;
             StateHelper::save_position(env, &position);
+            if let Some(asset) = &primary {
+                PriceSweep::index_holder(env, asset, borrower);
+            }
+            InterestRateStorage::adjust_totals(env, 0, amount);
+            if let Some(asset) = &primary {
+                BorrowSurge::record(env, asset, amount)?;
+            }
 
             // Emit event
             ProtocolEvent::PositionUpdated(
@@ -141,7 +185,7 @@ impl BorrowModule {
     }
 
     /// Borrow a specific asset against total cross-asset collateral
-    pub fn _borrow_asset(
+    pub fn borrow_asset(
         env: &Env,
         user: &String,
         asset: &Address,
@@ -167,22 +211,47 @@ impl BorrowModule {
                 None => return Err(BorrowError::PositionNotFound.into()),
             };
 
-            // Check collateral ratio
-            let min_ratio = ProtocolConfig::get_min_collateral_ratio(env);
-            let new_debt = position.debt + amount;
-            let collateral_ratio = if new_debt > 0 {
-                (position.collateral * 100) / new_debt
-            } else {
-                0
-            };
+            // Accrue interest
+            let state = InterestRateStorage::update_state(env);
+            AccrualManager::ensure_caught_up(env)?;
+            InterestRateManager::accrue_interest_for_position(
+                env,
+                &mut position,
+                state.current_borrow_rate,
+                state.current_supply_rate,
+            );
 
-            if collateral_ratio < min_ratio {
+            // New debt must clear the buffered origination threshold, not just maintenance
+            let new_debt = position.debt + amount;
+            if !Self::meets_origination_ratio(env, position.collateral, new_debt) {
                 return Err(BorrowError::InsufficientCollateralRatio.into());
             }
 
+            CapManager::check_borrow(env, asset, amount)?;
+            BorrowSurge::check(env, asset)?;
+            if TokenRegistry::primary_asset(env).as_ref() == Some(asset) {
+                Liquidity::require_available(env, asset, amount)?;
+            }
+
             // Update position
+            TransferEnforcer::transfer(
+                env,
+                asset,
+                &env.current_contract_address(),
+                &user_addr,
+                amount,
+                Symbol::new(env, "borrow_asset"),
+            )?;
             position.debt = new_debt;
             StateHelper::save_position(env, &position);
+            PositionTransfer::cancel_on_activity(env, &position.user);
+            PriceSweep::index_holder(env, asset, &user_addr);
+            if TokenRegistry::primary_asset(env).as_ref() == Some(asset) {
+                InterestRateStorage::adjust_totals(env, 0, amount);
+            } else {
+                CapStorage::add_asset_borrowed(env, asset, amount);
+            }
+            BorrowSurge::record(env, asset, amount)?;
 
             // Emit cross-asset borrow event
             ProtocolEvent::CrossBorrow(user_addr, asset.clone(), amount).emit(env);
@@ -194,6 +263,14 @@ impl BorrowModule {
         result
     }
 
+    /// Whether a post-borrow position clears the origination threshold
+    pub fn meets_origination_ratio(env: &Env, collateral: i128, new_debt: i128) -> bool {
+        if new_debt <= 0 {
+            return true;
+        }
+        (collateral * 10000) / new_debt >= ProtocolConfig::get_origination_ratio_bps(env)
+    }
+
     /// Validate borrow parameters
     pub fn _validate_borrow_params(params: &BorrowParams) -> Result<(), BorrowError> {
         if params.amount <= 0 {
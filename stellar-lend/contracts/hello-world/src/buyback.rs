@@ -0,0 +1,121 @@
+//! Reserve-funded buy-back-and-burn
+//! Governance spends protocol reserves of a listed asset to buy the protocol
+//! token on the internal AMM and sends the proceeds to a configured burn
+//! address. Reserves and the contract balance move together, so the swap
+//! consumes tokens the contract already holds.
+
+use crate::amm::{AMMRegistry, PairKey};
+use crate::config;
+use crate::history::ParamHistory;
+use crate::reserves::ReserveStorage;
+use crate::{EventTracker, ProtocolConfig, ProtocolError};
+use soroban_sdk::{contracttype, Address, Env, Symbol, Vec};
+
+/// Parameters of [`crate::governance::Action::BuybackAndBurn`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct BuybackOrder {
+    /// Reserve asset spent on the buy-back
+    pub asset: Address,
+    /// Amount of `asset` taken from reserves
+    pub amount: i128,
+    /// Minimum protocol tokens the swap must return
+    pub min_tokens_out: i128,
+    /// Internal AMM pair pricing the swap
+    pub pair_id: PairKey,
+}
+
+pub struct Buyback;
+
+impl Buyback {
+    pub(crate) fn burn_address_key(env: &Env) -> Symbol {
+        Symbol::new(env, "burn_address")
+    }
+
+    pub fn get_burn_address(env: &Env) -> Option<Address> {
+        env.storage().instance().get(&Self::burn_address_key(env))
+    }
+
+    /// Set the address receiving bought-back tokens (admin only)
+    pub fn set_burn_address(
+        env: &Env,
+        caller: &Address,
+        burn_address: &Address,
+    ) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        config::set_address_param(
+            env,
+            "buyback",
+            "burn_address",
+            &Self::burn_address_key(env),
+            None,
+            burn_address,
+        );
+        ParamHistory::record(
+            env,
+            Self::burn_address_key(env),
+            "set",
+            caller,
+            Some(burn_address.clone()),
+            None,
+        );
+        Ok(())
+    }
+
+    /// Swap `order.amount` of reserves through `order.pair_id` and send the
+    /// output to the burn address. Leaves reserves untouched on any failure.
+    ///
+    /// Returns the amount of tokens burned.
+    pub fn execute(env: &Env, order: &BuybackOrder) -> Result<i128, ProtocolError> {
+        if order.amount <= 0 {
+            return Err(ProtocolError::InvalidAmount);
+        }
+        let burn_address = Self::get_burn_address(env).ok_or(ProtocolError::ConfigurationError)?;
+        if ReserveStorage::get(env, &order.asset) < order.amount {
+            return Err(ProtocolError::InsufficientLiquidity);
+        }
+
+        let (tokens_out, _fee) = AMMRegistry::swap_held(
+            env,
+            &order.pair_id,
+            &order.asset,
+            order.amount,
+            order.min_tokens_out,
+            &burn_address,
+        )?;
+        ReserveStorage::add(env, &order.asset, -order.amount);
+
+        let token = if order.asset == order.pair_id.asset_a {
+            order.pair_id.asset_b.clone()
+        } else {
+            order.pair_id.asset_a.clone()
+        };
+        let event_type = Symbol::new(env, "buyback_burn");
+        let mut topics = Vec::new(env);
+        topics.push_back(Symbol::new(env, "asset"));
+        EventTracker::record(
+            env,
+            event_type.clone(),
+            topics,
+            None,
+            Some(order.asset.clone()),
+            order.amount,
+        );
+        env.events().publish(
+            (event_type, Symbol::new(env, "asset")),
+            (
+                Symbol::new(env, "asset"),
+                order.asset.clone(),
+                Symbol::new(env, "amount"),
+                order.amount,
+                Symbol::new(env, "token"),
+                token,
+                Symbol::new(env, "burned"),
+                tokens_out,
+                Symbol::new(env, "burn_address"),
+                burn_address,
+            ),
+        );
+        Ok(tokens_out)
+    }
+}
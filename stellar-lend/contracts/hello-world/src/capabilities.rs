@@ -0,0 +1,126 @@
+//! Capability descriptor and runtime feature flags
+//! Lets SDKs discover what a given deployment supports
+
+#[cfg(feature = "amm")]
+use crate::amm::SWAP_HISTORY_CAP;
+use crate::analytics::MAX_ACTIVITY_PAGE;
+use crate::governance::Action;
+use crate::oracle::SUPPORTED_MODES;
+use crate::reasons::STRICT_REASONS_FLAG;
+use crate::{ProtocolConfig, ProtocolError};
+use soroban_sdk::{contracttype, Address, Env, Map, Symbol, Vec};
+
+/// Contract version reported to integrators
+pub const CONTRACT_VERSION: u32 = 1;
+
+/// Runtime feature flags that can be toggled by the admin
+pub const KNOWN_FLAGS: [&str; 2] = ["diagnostics_enabled", STRICT_REASONS_FLAG];
+
+/// Maximum events retained per event type in the event log
+pub const EVENT_LOG_CAP: u32 = 32;
+
+/// Maximum events retained in the cross-type feed read by `get_events_since`
+pub const EVENT_FEED_CAP: u32 = 64;
+
+/// Machine-readable description of this deployment
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct Capabilities {
+    pub version: u32,
+    /// Runtime feature flags currently enabled
+    pub enabled_flags: Vec<Symbol>,
+    /// Oracle aggregation modes, indexed by mode value
+    pub oracle_modes: Vec<Symbol>,
+    pub emode: bool,
+    pub isolation: bool,
+    pub stable_rate: bool,
+    pub amm: bool,
+    pub max_activity_page: u32,
+    pub swap_history_cap: u32,
+    pub event_log_cap: u32,
+    /// Governance action kinds the executor understands
+    pub governance_actions: Vec<Symbol>,
+}
+
+/// Feature flag storage
+pub struct FeatureFlags;
+
+impl FeatureFlags {
+    pub(crate) fn flags_key(env: &Env) -> Symbol {
+        Symbol::new(env, "feature_flags")
+    }
+
+    fn flags(env: &Env) -> Map<Symbol, bool> {
+        env.storage()
+            .instance()
+            .get(&Self::flags_key(env))
+            .unwrap_or_else(|| Map::new(env))
+    }
+
+    pub fn is_enabled(env: &Env, flag: &str) -> bool {
+        Self::flags(env)
+            .get(Symbol::new(env, flag))
+            .unwrap_or(false)
+    }
+
+    /// Toggle a known flag (admin only)
+    pub fn set(
+        env: &Env,
+        caller: &Address,
+        flag: Symbol,
+        enabled: bool,
+    ) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        if !KNOWN_FLAGS
+            .iter()
+            .any(|name| Symbol::new(env, name) == flag)
+        {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        let mut flags = Self::flags(env);
+        flags.set(flag, enabled);
+        env.storage().instance().set(&Self::flags_key(env), &flags);
+        Ok(())
+    }
+}
+
+/// Capability descriptor builder
+pub struct CapabilityDescriptor;
+
+impl CapabilityDescriptor {
+    pub fn get(env: &Env) -> Capabilities {
+        let mut enabled_flags = Vec::new(env);
+        for name in KNOWN_FLAGS.iter() {
+            if FeatureFlags::is_enabled(env, name) {
+                enabled_flags.push_back(Symbol::new(env, name));
+            }
+        }
+        let mut oracle_modes = Vec::new(env);
+        for name in SUPPORTED_MODES.iter() {
+            oracle_modes.push_back(Symbol::new(env, name));
+        }
+        let mut governance_actions = Vec::new(env);
+        for name in Action::KINDS.iter() {
+            if cfg!(feature = "amm") || !Action::AMM_KINDS.contains(name) {
+                governance_actions.push_back(Symbol::new(env, name));
+            }
+        }
+
+        Capabilities {
+            version: CONTRACT_VERSION,
+            enabled_flags,
+            oracle_modes,
+            emode: false,
+            isolation: false,
+            stable_rate: false,
+            amm: cfg!(feature = "amm"),
+            max_activity_page: MAX_ACTIVITY_PAGE,
+            #[cfg(feature = "amm")]
+            swap_history_cap: SWAP_HISTORY_CAP,
+            #[cfg(not(feature = "amm"))]
+            swap_history_cap: 0,
+            event_log_cap: EVENT_LOG_CAP,
+            governance_actions,
+        }
+    }
+}
@@ -0,0 +1,325 @@
+//! Supply and borrow caps
+//! Per-asset limits on total supplied and borrowed principal, cap groups
+//! that bound the combined USD borrow value of correlated assets, and a
+//! global USD ceiling on protocol TVL for guarded launches
+
+use crate::analytics::AnalyticsStorage;
+use crate::config;
+use crate::oracle::{Oracle, PRICE_SCALE};
+use crate::{InterestRateStorage, ProtocolConfig, ProtocolError, TokenRegistry};
+use soroban_sdk::{contracttype, Address, Env, Symbol, Vec};
+
+/// Correlated assets sharing one USD borrow cap
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct CapGroup {
+    pub id: Symbol,
+    pub members: Vec<Address>,
+    /// Combined borrow cap in USD (oracle price scale, 0 = unlimited)
+    pub usd_borrow_cap: i128,
+    /// Combined USD value of member borrows as of the last check
+    pub total_usd: i128,
+}
+
+/// Cap storage management
+pub struct CapStorage;
+
+impl CapStorage {
+    pub(crate) fn supply_cap_key(env: &Env) -> Symbol {
+        Symbol::new(env, "supply_cap")
+    }
+    pub(crate) fn borrow_cap_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "borrow_cap"), asset.clone())
+    }
+    pub(crate) fn borrowed_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "asset_borrowed"), asset.clone())
+    }
+    pub(crate) fn group_key(env: &Env, id: &Symbol) -> (Symbol, Symbol) {
+        (Symbol::new(env, "cap_group"), id.clone())
+    }
+    pub(crate) fn asset_group_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "asset_cap_group"), asset.clone())
+    }
+    pub(crate) fn global_tvl_cap_key(env: &Env) -> Symbol {
+        Symbol::new(env, "global_tvl_cap")
+    }
+
+    /// Supply cap for an asset (0 = unlimited)
+    pub fn get_supply_cap(env: &Env, asset: &Address) -> i128 {
+        env.storage()
+            .instance()
+            .get(&(Self::supply_cap_key(env), asset.clone()))
+            .unwrap_or(0)
+    }
+
+    pub fn set_supply_cap(env: &Env, asset: &Address, cap: i128) {
+        config::set_param(
+            env,
+            "caps",
+            "supply_cap",
+            &(Self::supply_cap_key(env), asset.clone()),
+            Some(asset),
+            0,
+            cap,
+        );
+    }
+
+    /// Borrow cap for an asset (0 = unlimited)
+    pub fn get_borrow_cap(env: &Env, asset: &Address) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::borrow_cap_key(env, asset))
+            .unwrap_or(0)
+    }
+
+    pub fn set_borrow_cap(env: &Env, asset: &Address, cap: i128) {
+        config::set_param(
+            env,
+            "caps",
+            "borrow_cap",
+            &Self::borrow_cap_key(env, asset),
+            Some(asset),
+            0,
+            cap,
+        );
+    }
+
+    /// Total borrowed for an asset. The primary asset uses the interest
+    /// state totals; other assets are tracked by the cross-asset borrow path.
+    pub fn get_asset_borrowed(env: &Env, asset: &Address) -> i128 {
+        if TokenRegistry::primary_asset(env).as_ref() == Some(asset) {
+            return InterestRateStorage::get_state(env).total_borrowed;
+        }
+        env.storage()
+            .instance()
+            .get(&Self::borrowed_key(env, asset))
+            .unwrap_or(0)
+    }
+
+    pub fn add_asset_borrowed(env: &Env, asset: &Address, amount: i128) {
+        let total = Self::get_asset_borrowed(env, asset).saturating_add(amount);
+        env.storage()
+            .instance()
+            .set(&Self::borrowed_key(env, asset), &total);
+    }
+
+    pub fn get_group(env: &Env, id: &Symbol) -> Option<CapGroup> {
+        env.storage().instance().get(&Self::group_key(env, id))
+    }
+
+    pub fn save_group(env: &Env, group: &CapGroup) {
+        env.storage()
+            .instance()
+            .set(&Self::group_key(env, &group.id), group);
+    }
+
+    /// Group an asset belongs to, if any
+    pub fn get_asset_group(env: &Env, asset: &Address) -> Option<Symbol> {
+        env.storage()
+            .instance()
+            .get(&Self::asset_group_key(env, asset))
+    }
+
+    /// Global TVL cap in USD (oracle price scale, 0 = disabled)
+    pub fn get_global_tvl_cap(env: &Env) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::global_tvl_cap_key(env))
+            .unwrap_or(0)
+    }
+
+    pub fn set_global_tvl_cap(env: &Env, cap: i128) -> Result<(), ProtocolError> {
+        if cap < 0 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        config::set_param(
+            env,
+            "caps",
+            "global_tvl_cap",
+            &Self::global_tvl_cap_key(env),
+            None,
+            0,
+            cap,
+        );
+        Ok(())
+    }
+
+    pub fn set_asset_group(env: &Env, asset: &Address, id: Option<Symbol>) {
+        let key = Self::asset_group_key(env, asset);
+        match id {
+            Some(id) => env.storage().instance().set(&key, &id),
+            None => env.storage().instance().remove(&key),
+        }
+    }
+}
+
+/// Cap enforcement
+pub struct CapManager;
+
+impl CapManager {
+    /// Set the supply cap for an asset (admin only, 0 = unlimited)
+    pub fn set_supply_cap(
+        env: &Env,
+        caller: &Address,
+        asset: &Address,
+        cap: i128,
+    ) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        if cap < 0 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        CapStorage::set_supply_cap(env, asset, cap);
+        Ok(())
+    }
+
+    /// Whether `amount` of new supply fits under the asset's cap
+    pub fn supply_fits(env: &Env, asset: &Address, amount: i128) -> bool {
+        let cap = CapStorage::get_supply_cap(env, asset);
+        if cap == 0 {
+            return true;
+        }
+        let total = InterestRateStorage::get_state(env).total_supplied;
+        total.saturating_add(amount) <= cap
+    }
+
+    /// Fail with `SupplyCapExceeded` when `amount` of new supply would breach the cap
+    pub fn check_supply(env: &Env, asset: &Address, amount: i128) -> Result<(), ProtocolError> {
+        if !Self::supply_fits(env, asset, amount) {
+            return Err(ProtocolError::SupplyCapExceeded);
+        }
+        Ok(())
+    }
+
+    /// USD value of the protocol TVL as tracked by the analytics aggregate.
+    /// Positions are denominated in `asset`, so the aggregate is priced with it.
+    /// Read-only: the price is peeked, not refreshed.
+    pub fn tvl_usd(env: &Env, asset: &Address) -> Result<i128, ProtocolError> {
+        let tvl = AnalyticsStorage::get_protocol_metrics(env).total_value_locked;
+        if tvl == 0 {
+            return Ok(0);
+        }
+        let price = Oracle::peek_price_detailed(env, asset)
+            .ok_or(ProtocolError::OracleFailure)?
+            .price;
+        Ok(tvl.saturating_mul(price) / PRICE_SCALE)
+    }
+
+    /// Fail with `GlobalCapExceeded` when depositing `amount` of `asset`
+    /// would push the USD value of protocol TVL past the global cap
+    pub fn check_global_tvl(env: &Env, asset: &Address, amount: i128) -> Result<(), ProtocolError> {
+        let cap = CapStorage::get_global_tvl_cap(env);
+        if cap == 0 {
+            return Ok(());
+        }
+        let tvl = AnalyticsStorage::get_protocol_metrics(env).total_value_locked;
+        if Self::usd_value(env, asset, tvl.saturating_add(amount))? > cap {
+            return Err(ProtocolError::GlobalCapExceeded);
+        }
+        Ok(())
+    }
+
+    /// Set the borrow cap for an asset (admin only, 0 = unlimited)
+    pub fn set_borrow_cap(
+        env: &Env,
+        caller: &Address,
+        asset: &Address,
+        cap: i128,
+    ) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        if cap < 0 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        CapStorage::set_borrow_cap(env, asset, cap);
+        Ok(())
+    }
+
+    /// Create or replace a cap group (admin only). Assets leaving the group are
+    /// released, and the group total is recomputed from member totals.
+    pub fn set_cap_group(
+        env: &Env,
+        caller: &Address,
+        id: Symbol,
+        members: Vec<Address>,
+        usd_borrow_cap: i128,
+    ) -> Result<CapGroup, ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        if usd_borrow_cap < 0 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        for asset in members.iter() {
+            if let Some(current) = CapStorage::get_asset_group(env, &asset) {
+                if current != id {
+                    return Err(ProtocolError::AlreadyExists);
+                }
+            }
+        }
+        if let Some(previous) = CapStorage::get_group(env, &id) {
+            for asset in previous.members.iter() {
+                if !members.contains(&asset) {
+                    CapStorage::set_asset_group(env, &asset, None);
+                }
+            }
+        }
+        for asset in members.iter() {
+            CapStorage::set_asset_group(env, &asset, Some(id.clone()));
+        }
+
+        let mut group = CapGroup {
+            id,
+            members,
+            usd_borrow_cap,
+            total_usd: 0,
+        };
+        group.total_usd = Self::group_usd_total(env, &group)?;
+        CapStorage::save_group(env, &group);
+        Ok(group)
+    }
+
+    /// USD value of `amount` of `asset` at the current oracle price, read
+    /// from the fallback oracle when aggregation fails
+    pub fn usd_value(env: &Env, asset: &Address, amount: i128) -> Result<i128, ProtocolError> {
+        if amount == 0 {
+            return Ok(0);
+        }
+        let price = Oracle::get_price_detailed(env, asset)
+            .ok_or(ProtocolError::OracleFailure)?
+            .price;
+        Ok(amount.saturating_mul(price) / PRICE_SCALE)
+    }
+
+    /// Combined USD value of all member borrows
+    pub fn group_usd_total(env: &Env, group: &CapGroup) -> Result<i128, ProtocolError> {
+        let mut total: i128 = 0;
+        for asset in group.members.iter() {
+            let borrowed = CapStorage::get_asset_borrowed(env, &asset);
+            total = total.saturating_add(Self::usd_value(env, &asset, borrowed)?);
+        }
+        Ok(total)
+    }
+
+    /// Check `amount` of new borrow against the asset cap and its group cap.
+    /// Refreshes the stored group total as a side effect.
+    pub fn check_borrow(env: &Env, asset: &Address, amount: i128) -> Result<(), ProtocolError> {
+        let cap = CapStorage::get_borrow_cap(env, asset);
+        let borrowed = CapStorage::get_asset_borrowed(env, asset);
+        if cap > 0 && borrowed.saturating_add(amount) > cap {
+            return Err(ProtocolError::BorrowCapExceeded);
+        }
+
+        let Some(id) = CapStorage::get_asset_group(env, asset) else {
+            return Ok(());
+        };
+        let Some(mut group) = CapStorage::get_group(env, &id) else {
+            return Ok(());
+        };
+        group.total_usd = Self::group_usd_total(env, &group)?;
+        CapStorage::save_group(env, &group);
+        if group.usd_borrow_cap > 0 {
+            let added = Self::usd_value(env, asset, amount)?;
+            if group.total_usd.saturating_add(added) > group.usd_borrow_cap {
+                return Err(ProtocolError::GroupCapExceeded);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+//! Daily interest index checkpoints
+//!
+//! The first accrual of each day records the primary asset's borrow and
+//! supply indices, so external accounting can read index values at past
+//! times without replaying every accrual. Only the most recent
+//! [`MAX_INDEX_CHECKPOINTS`] days are kept, oldest first.
+//!
+//! Values between two checkpoints can be estimated by linear interpolation.
+//! Indices compound, so an interpolated value slightly overstates the true
+//! one; the error grows with the rate and the gap between checkpoints.
+
+use crate::accrual::AccrualStorage;
+use crate::TokenRegistry;
+use soroban_sdk::{contracttype, Address, Env, Symbol, Vec};
+
+/// Number of daily checkpoints kept per asset
+pub const MAX_INDEX_CHECKPOINTS: u32 = 90;
+
+const SECONDS_PER_DAY: u64 = 86400;
+
+/// Index values recorded by the first accrual of a day
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct IndexCheckpoint {
+    /// Day bucket (timestamp / 86400)
+    pub day: u64,
+    /// Borrow index (scaled by 1e12)
+    pub borrow_index: i128,
+    /// Supply index (scaled by 1e12)
+    pub supply_index: i128,
+    /// Time the indices had been accrued to
+    pub timestamp: u64,
+}
+
+/// Checkpoint storage management
+pub struct IndexCheckpointStorage;
+
+impl IndexCheckpointStorage {
+    pub(crate) fn key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "index_checkpoints"), asset.clone())
+    }
+
+    /// Checkpoints for `asset`, oldest first
+    pub fn get(env: &Env, asset: &Address) -> Vec<IndexCheckpoint> {
+        env.storage()
+            .instance()
+            .get(&Self::key(env, asset))
+            .unwrap_or_else(|| Vec::new(env))
+    }
+
+    fn save(env: &Env, asset: &Address, checkpoints: &Vec<IndexCheckpoint>) {
+        env.storage()
+            .instance()
+            .set(&Self::key(env, asset), checkpoints);
+    }
+}
+
+/// Checkpoint logic
+pub struct IndexCheckpoints;
+
+impl IndexCheckpoints {
+    /// Record the indices for the primary asset unless today already has a
+    /// checkpoint. Called from the accrual write.
+    pub fn record(env: &Env, borrow_index: i128, supply_index: i128, timestamp: u64) {
+        let Some(asset) = TokenRegistry::primary_asset(env) else {
+            return;
+        };
+        let day = timestamp / SECONDS_PER_DAY;
+        let mut checkpoints = IndexCheckpointStorage::get(env, &asset);
+        if checkpoints.last().is_some_and(|last| last.day >= day) {
+            return;
+        }
+        checkpoints.push_back(IndexCheckpoint {
+            day,
+            borrow_index,
+            supply_index,
+            timestamp,
+        });
+        while checkpoints.len() > MAX_INDEX_CHECKPOINTS {
+            checkpoints.pop_front();
+        }
+        IndexCheckpointStorage::save(env, &asset, &checkpoints);
+    }
+
+    /// `(borrow_index, supply_index, timestamp)` recorded for `asset` on `day`
+    pub fn get(env: &Env, asset: &Address, day: u64) -> Option<(i128, i128, u64)> {
+        IndexCheckpointStorage::get(env, asset)
+            .iter()
+            .find(|c| c.day == day)
+            .map(|c| (c.borrow_index, c.supply_index, c.timestamp))
+    }
+
+    /// Estimate `(borrow_index, supply_index)` for `asset` at `ts` by linear
+    /// interpolation between the surrounding checkpoints. The live indices
+    /// serve as the last point for the primary asset. `None` outside the
+    /// covered range.
+    pub fn interpolate(env: &Env, asset: &Address, ts: u64) -> Option<(i128, i128)> {
+        let mut points = IndexCheckpointStorage::get(env, asset);
+        if TokenRegistry::primary_asset(env).as_ref() == Some(asset) {
+            let live = AccrualStorage::get_state(env);
+            if points
+                .last()
+                .is_some_and(|last| last.timestamp < live.last_accrual_time)
+            {
+                points.push_back(IndexCheckpoint {
+                    day: live.last_accrual_time / SECONDS_PER_DAY,
+                    borrow_index: live.borrow_index,
+                    supply_index: AccrualStorage::get_supply_index(env),
+                    timestamp: live.last_accrual_time,
+                });
+            }
+        }
+
+        let mut before: Option<IndexCheckpoint> = None;
+        for point in points.iter() {
+            if point.timestamp == ts {
+                return Some((point.borrow_index, point.supply_index));
+            }
+            if point.timestamp > ts {
+                let before = before?;
+                let span = (point.timestamp - before.timestamp) as i128;
+                let offset = (ts - before.timestamp) as i128;
+                let lerp = |from: i128, to: i128| {
+                    from.saturating_add(to.saturating_sub(from).saturating_mul(offset) / span)
+                };
+                return Some((
+                    lerp(before.borrow_index, point.borrow_index),
+                    lerp(before.supply_index, point.supply_index),
+                ));
+            }
+            before = Some(point);
+        }
+        None
+    }
+}
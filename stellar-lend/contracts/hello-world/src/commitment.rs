@@ -0,0 +1,142 @@
+//! Protocol state commitments
+//!
+//! A commitment is the sha256 of the XDR encoding of [`CommittedState`].
+//! `#[contracttype]` structs encode as an `ScMap` with keys in field-name
+//! order, so the encoding is canonical, and every field can be rebuilt from
+//! public getters:
+//! - `version` from `get_capabilities`
+//! - per asset, in `list_registered_assets` order with duplicates dropped:
+//!   totals from `get_asset_state` (zero for assets other than the primary
+//!   one) and reserves from `get_protocol_reserves`
+//! - `borrow_index` / `accrued_to` from `get_accrual_state`
+//! - `emergency_status` from `get_emergency_state`, pause switches from
+//!   `get_risk_config`
+//!
+//! Keepers call `commit_state` each epoch to store and emit the current
+//! commitment with its ledger sequence. The stored value is only as
+//! trustworthy as the contract's own storage; nothing is signed off-chain.
+
+use crate::accrual::AccrualStorage;
+use crate::capabilities::CONTRACT_VERSION;
+use crate::reserves::ReserveStorage;
+use crate::{
+    EmergencyStatus, EmergencyStorage, EventTracker, InterestRateStorage, RiskConfigStorage,
+    TokenRegistry,
+};
+use soroban_sdk::xdr::ToXdr;
+use soroban_sdk::{contracttype, Address, BytesN, Env, Symbol, Vec};
+
+/// Assets included in a commitment; later registrations are left out
+pub const MAX_COMMITTED_ASSETS: u32 = 16;
+
+/// Committed totals for one registered asset
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct AssetCommitment {
+    pub asset: Address,
+    pub total_supplied: i128,
+    pub total_borrowed: i128,
+    pub reserves: i128,
+}
+
+/// Preimage of a state commitment
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct CommittedState {
+    pub version: u32,
+    pub assets: Vec<AssetCommitment>,
+    pub borrow_index: i128,
+    pub accrued_to: u64,
+    pub emergency_status: EmergencyStatus,
+    pub pause_borrow: bool,
+    pub pause_deposit: bool,
+    pub pause_withdraw: bool,
+    pub pause_liquidate: bool,
+}
+
+/// The most recent commitment stored by `commit_state`
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct StoredCommitment {
+    pub hash: BytesN<32>,
+    pub ledger_seq: u32,
+    pub timestamp: u64,
+}
+
+impl CommittedState {
+    pub fn collect(env: &Env) -> Self {
+        let primary = TokenRegistry::primary_asset(env);
+        let rates = InterestRateStorage::get_state(env);
+        let mut assets: Vec<AssetCommitment> = Vec::new(env);
+        for (_, asset) in TokenRegistry::list_assets(env).iter() {
+            if assets.len() >= MAX_COMMITTED_ASSETS {
+                break;
+            }
+            if assets.iter().any(|a| a.asset == asset) {
+                continue;
+            }
+            let (total_supplied, total_borrowed) = if primary.as_ref() == Some(&asset) {
+                (rates.total_supplied, rates.total_borrowed)
+            } else {
+                (0, 0)
+            };
+            assets.push_back(AssetCommitment {
+                reserves: ReserveStorage::get(env, &asset),
+                asset,
+                total_supplied,
+                total_borrowed,
+            });
+        }
+
+        let accrual = AccrualStorage::get_state(env);
+        let risk = RiskConfigStorage::get(env);
+        Self {
+            version: CONTRACT_VERSION,
+            assets,
+            borrow_index: accrual.borrow_index,
+            accrued_to: accrual.last_accrual_time,
+            emergency_status: EmergencyStorage::get(env).status,
+            pause_borrow: risk.pause_borrow,
+            pause_deposit: risk.pause_deposit,
+            pause_withdraw: risk.pause_withdraw,
+            pause_liquidate: risk.pause_liquidate,
+        }
+    }
+
+    pub fn hash(&self, env: &Env) -> BytesN<32> {
+        env.crypto().sha256(&self.clone().to_xdr(env)).to_bytes()
+    }
+}
+
+/// State commitment logic
+pub struct StateCommitment;
+
+impl StateCommitment {
+    pub(crate) fn key(env: &Env) -> Symbol {
+        Symbol::new(env, "state_commitment")
+    }
+
+    pub fn compute(env: &Env) -> BytesN<32> {
+        CommittedState::collect(env).hash(env)
+    }
+
+    pub fn get_latest(env: &Env) -> Option<StoredCommitment> {
+        env.storage().instance().get(&Self::key(env))
+    }
+
+    /// Store and emit the current commitment (permissionless)
+    pub fn commit(env: &Env) -> StoredCommitment {
+        let stored = StoredCommitment {
+            hash: Self::compute(env),
+            ledger_seq: env.ledger().sequence(),
+            timestamp: env.ledger().timestamp(),
+        };
+        env.storage().instance().set(&Self::key(env), &stored);
+
+        let event_type = Symbol::new(env, "state_committed");
+        EventTracker::record(env, event_type.clone(), Vec::new(env), None, None, 0);
+        env.events()
+            .publish((event_type, Symbol::new(env, "commitment")), stored.clone());
+        stored
+    }
+}
@@ -0,0 +1,156 @@
+//! Standardized parameter change events
+//! Parameter setters write through [`set_param`] / [`set_address_param`]
+//! (or report struct-field changes through [`record_change`]), which publish
+//! one `config_changed` / `config_addr_changed` event shape for every
+//! parameter so indexers can rebuild configuration history without
+//! special-casing each setter. Setter-specific events are still emitted.
+//!
+//! Both carry the reason code or proposal of the current
+//! [`ChangeReason`] scope, if any.
+
+use crate::reasons::ChangeReason;
+use crate::EventTracker;
+use soroban_sdk::{contracttype, Address, Env, IntoVal, Symbol, TryFromVal, Val, Vec};
+
+/// Payload of a `config_changed` event
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct ConfigChanged {
+    /// Subsystem owning the parameter (e.g. `protocol`, `oracle`, `caps`)
+    pub scope: Symbol,
+    pub key: Symbol,
+    /// Asset the parameter applies to, for per-asset parameters
+    /// (`asset_a` of the pair for AMM pair parameters)
+    pub asset: Option<Address>,
+    pub old: i128,
+    pub new: i128,
+    /// Proposal that carried the change, when governance executed it
+    pub via_proposal: Option<u64>,
+    /// Registered reason code stated by the admin
+    pub reason: Option<Symbol>,
+}
+
+/// Payload of a `config_addr_changed` event, for address-valued parameters
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct ConfigAddressChanged {
+    pub scope: Symbol,
+    pub key: Symbol,
+    pub asset: Option<Address>,
+    pub old: Option<Address>,
+    pub new: Address,
+    pub via_proposal: Option<u64>,
+    pub reason: Option<Symbol>,
+}
+
+/// Write a numeric parameter stored directly under `storage_key` and emit
+/// `config_changed` when the value actually changes. Returns the previous
+/// value (or `default` if unset).
+pub fn set_param<K, V>(
+    env: &Env,
+    scope: &str,
+    key: &str,
+    storage_key: &K,
+    asset: Option<&Address>,
+    default: V,
+    new: V,
+) -> V
+where
+    K: IntoVal<Env, Val>,
+    V: IntoVal<Env, Val> + TryFromVal<Env, Val> + Into<i128> + Copy,
+{
+    let old: V = env.storage().instance().get(storage_key).unwrap_or(default);
+    env.storage().instance().set(storage_key, &new);
+    let (old_raw, new_raw): (i128, i128) = (old.into(), new.into());
+    if old_raw != new_raw {
+        record_change(env, scope, key, asset, old_raw, new_raw);
+    }
+    old
+}
+
+/// Write an address parameter stored directly under `storage_key` and emit
+/// `config_addr_changed`. Returns the previous value, if any.
+pub fn set_address_param<K>(
+    env: &Env,
+    scope: &str,
+    key: &str,
+    storage_key: &K,
+    asset: Option<&Address>,
+    new: &Address,
+) -> Option<Address>
+where
+    K: IntoVal<Env, Val>,
+{
+    let old: Option<Address> = env.storage().instance().get(storage_key);
+    env.storage().instance().set(storage_key, new);
+    record_address_change(env, scope, key, asset, old.clone(), new);
+    old
+}
+
+/// Emit `config_addr_changed` for an address parameter the caller stored
+/// itself
+pub fn record_address_change(
+    env: &Env,
+    scope: &str,
+    key: &str,
+    asset: Option<&Address>,
+    old: Option<Address>,
+    new: &Address,
+) {
+    let change = ConfigAddressChanged {
+        scope: Symbol::new(env, scope),
+        key: Symbol::new(env, key),
+        asset: asset.cloned(),
+        old,
+        new: new.clone(),
+        via_proposal: ChangeReason::proposal(env),
+        reason: ChangeReason::current(env),
+    };
+    track(env, "config_addr_changed", asset, 0);
+    env.events().publish(
+        (
+            Symbol::new(env, "config_addr_changed"),
+            change.scope.clone(),
+        ),
+        change,
+    );
+}
+
+/// Emit `config_changed` for a parameter the caller stored itself, such as a
+/// field of a config struct
+pub fn record_change(
+    env: &Env,
+    scope: &str,
+    key: &str,
+    asset: Option<&Address>,
+    old: i128,
+    new: i128,
+) {
+    let change = ConfigChanged {
+        scope: Symbol::new(env, scope),
+        key: Symbol::new(env, key),
+        asset: asset.cloned(),
+        old,
+        new,
+        via_proposal: ChangeReason::proposal(env),
+        reason: ChangeReason::current(env),
+    };
+    track(env, "config_changed", asset, new);
+    env.events().publish(
+        (Symbol::new(env, "config_changed"), change.scope.clone()),
+        change,
+    );
+}
+
+fn track(env: &Env, name: &str, asset: Option<&Address>, amount: i128) {
+    let mut topics = Vec::new(env);
+    topics.push_back(Symbol::new(env, "scope"));
+    EventTracker::record(
+        env,
+        Symbol::new(env, name),
+        topics,
+        None,
+        asset.cloned(),
+        amount,
+    );
+}
@@ -0,0 +1,280 @@
+//! Co-signed withdrawals
+//!
+//! A user may register a co-signer and a threshold: withdrawals above the
+//! threshold then need a one-time approval from the co-signer matching the
+//! asset and amount exactly, consumed by the withdrawal it authorizes.
+//!
+//! The first policy takes effect immediately. Changing or removing an
+//! existing policy only takes effect [`POLICY_CHANGE_DELAY_SECS`] later, so a
+//! compromised owner key cannot drop the policy and drain in one go.
+
+use crate::{EventTracker, ProtocolError, TokenRegistry};
+use soroban_sdk::{contracttype, Address, Env, Symbol, Vec};
+
+/// Delay before a change to an existing policy takes effect (24 hours)
+pub const POLICY_CHANGE_DELAY_SECS: u64 = 24 * 60 * 60;
+
+/// A user's withdrawal co-signing policy
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct WithdrawalPolicy {
+    /// Withdrawals strictly above this amount need approval
+    pub threshold: i128,
+    pub co_signer: Address,
+}
+
+/// A queued policy change
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PendingPolicyChange {
+    pub threshold: i128,
+    /// `None` removes the policy
+    pub co_signer: Option<Address>,
+    pub effective_at: u64,
+}
+
+/// A one-time co-signer approval
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct WithdrawalApproval {
+    pub asset: Address,
+    pub amount: i128,
+    pub expires_at: u64,
+}
+
+/// Co-signing storage management
+pub struct CoSignStorage;
+
+impl CoSignStorage {
+    pub(crate) fn policy_key(env: &Env, user: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "withdrawal_policy"), user.clone())
+    }
+
+    pub(crate) fn pending_key(env: &Env, user: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "withdrawal_policy_next"), user.clone())
+    }
+
+    pub(crate) fn approval_key(env: &Env, user: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "withdrawal_approval"), user.clone())
+    }
+
+    fn get_stored_policy(env: &Env, user: &Address) -> Option<WithdrawalPolicy> {
+        env.storage().instance().get(&Self::policy_key(env, user))
+    }
+
+    fn set_policy(env: &Env, user: &Address, policy: Option<&WithdrawalPolicy>) {
+        let key = Self::policy_key(env, user);
+        match policy {
+            Some(policy) => env.storage().instance().set(&key, policy),
+            None => env.storage().instance().remove(&key),
+        }
+    }
+
+    pub fn get_pending(env: &Env, user: &Address) -> Option<PendingPolicyChange> {
+        env.storage().instance().get(&Self::pending_key(env, user))
+    }
+
+    pub fn get_approval(env: &Env, user: &Address) -> Option<WithdrawalApproval> {
+        env.storage().instance().get(&Self::approval_key(env, user))
+    }
+}
+
+/// Co-signing logic
+pub struct CoSign;
+
+impl CoSign {
+    /// Policy in effect for `user`, counting a pending change whose delay
+    /// has passed
+    pub fn policy(env: &Env, user: &Address) -> Option<WithdrawalPolicy> {
+        match CoSignStorage::get_pending(env, user) {
+            Some(pending) if env.ledger().timestamp() >= pending.effective_at => {
+                pending.co_signer.map(|co_signer| WithdrawalPolicy {
+                    threshold: pending.threshold,
+                    co_signer,
+                })
+            }
+            _ => CoSignStorage::get_stored_policy(env, user),
+        }
+    }
+
+    /// Fold a due pending change into the stored policy
+    fn settle(env: &Env, user: &Address) {
+        if let Some(pending) = CoSignStorage::get_pending(env, user) {
+            if env.ledger().timestamp() >= pending.effective_at {
+                CoSignStorage::set_policy(env, user, Self::policy(env, user).as_ref());
+                env.storage()
+                    .instance()
+                    .remove(&CoSignStorage::pending_key(env, user));
+            }
+        }
+    }
+
+    /// Set, change, or (with `co_signer = None`) remove the caller's policy.
+    /// The caller must authorize.
+    pub fn set_policy(
+        env: &Env,
+        caller: &Address,
+        threshold: i128,
+        co_signer: Option<Address>,
+    ) -> Result<(), ProtocolError> {
+        caller.require_auth();
+        if co_signer.is_some() && threshold <= 0 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        if co_signer.as_ref() == Some(caller) {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        Self::settle(env, caller);
+
+        match (CoSignStorage::get_stored_policy(env, caller), &co_signer) {
+            (None, None) => return Err(ProtocolError::NotFound),
+            (None, Some(co_signer)) => {
+                let policy = WithdrawalPolicy {
+                    threshold,
+                    co_signer: co_signer.clone(),
+                };
+                CoSignStorage::set_policy(env, caller, Some(&policy));
+                Self::emit(env, "withdrawal_policy_set", caller, threshold, 0);
+            }
+            (Some(_), _) => {
+                let pending = PendingPolicyChange {
+                    threshold,
+                    co_signer,
+                    effective_at: env
+                        .ledger()
+                        .timestamp()
+                        .saturating_add(POLICY_CHANGE_DELAY_SECS),
+                };
+                env.storage()
+                    .instance()
+                    .set(&CoSignStorage::pending_key(env, caller), &pending);
+                Self::emit(
+                    env,
+                    "withdrawal_policy_queued",
+                    caller,
+                    threshold,
+                    pending.effective_at,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Record the co-signer's one-time approval for `owner` to withdraw
+    /// exactly `amount` of `asset` until `expires_at`. Replaces any unused one.
+    /// The co-signer must authorize.
+    pub fn approve(
+        env: &Env,
+        co_signer: &Address,
+        owner: &Address,
+        asset: &Address,
+        amount: i128,
+        expires_at: u64,
+    ) -> Result<(), ProtocolError> {
+        co_signer.require_auth();
+        let policy = Self::policy(env, owner).ok_or(ProtocolError::NotFound)?;
+        if policy.co_signer != *co_signer {
+            return Err(ProtocolError::Unauthorized);
+        }
+        if amount <= 0 {
+            return Err(ProtocolError::InvalidAmount);
+        }
+        if TokenRegistry::primary_asset(env).as_ref() != Some(asset) {
+            return Err(ProtocolError::AssetNotSupported);
+        }
+        if expires_at <= env.ledger().timestamp() {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        let approval = WithdrawalApproval {
+            asset: asset.clone(),
+            amount,
+            expires_at,
+        };
+        env.storage()
+            .instance()
+            .set(&CoSignStorage::approval_key(env, owner), &approval);
+        Self::emit(env, "withdrawal_approved", owner, amount, expires_at);
+        Ok(())
+    }
+
+    /// Check that withdrawing `amount` of `asset` is allowed under `user`'s
+    /// policy. Returns whether an approval backs it and must be consumed
+    /// once the withdrawal succeeds.
+    pub fn check_withdrawal(
+        env: &Env,
+        user: &Address,
+        asset: &Address,
+        amount: i128,
+    ) -> Result<bool, ProtocolError> {
+        let Some(policy) = Self::policy(env, user) else {
+            return Ok(false);
+        };
+        if amount <= policy.threshold {
+            return Ok(false);
+        }
+        match CoSignStorage::get_approval(env, user) {
+            Some(approval)
+                if approval.asset == *asset
+                    && approval.amount == amount
+                    && env.ledger().timestamp() <= approval.expires_at =>
+            {
+                Ok(true)
+            }
+            _ => Err(ProtocolError::CoSignerApprovalRequired),
+        }
+    }
+
+    /// Move `from`'s policy, with any queued change, onto `to` when the
+    /// position changes hands, so moving it cannot shed the co-signer. An
+    /// unused approval was given for `from` and is dropped.
+    pub fn transfer_policy(env: &Env, from: &Address, to: &Address) {
+        Self::consume_approval(env, from);
+        let stored = CoSignStorage::get_stored_policy(env, from);
+        let pending = CoSignStorage::get_pending(env, from);
+        if stored.is_none() && pending.is_none() {
+            return;
+        }
+        CoSignStorage::set_policy(env, to, stored.as_ref());
+        CoSignStorage::set_policy(env, from, None);
+        let (from_key, to_key) = (
+            CoSignStorage::pending_key(env, from),
+            CoSignStorage::pending_key(env, to),
+        );
+        match pending {
+            Some(pending) => env.storage().instance().set(&to_key, &pending),
+            None => env.storage().instance().remove(&to_key),
+        }
+        env.storage().instance().remove(&from_key);
+        Self::consume_approval(env, to);
+    }
+
+    pub fn consume_approval(env: &Env, user: &Address) {
+        env.storage()
+            .instance()
+            .remove(&CoSignStorage::approval_key(env, user));
+    }
+
+    fn emit(env: &Env, name: &str, user: &Address, amount: i128, at: u64) {
+        let event_type = Symbol::new(env, name);
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        topics.push_back(Symbol::new(env, "user"));
+        EventTracker::record(
+            env,
+            event_type.clone(),
+            topics,
+            Some(user.clone()),
+            None,
+            amount,
+        );
+        env.events().publish(
+            (event_type, user.clone()),
+            (
+                Symbol::new(env, "amount"),
+                amount,
+                Symbol::new(env, "at"),
+                at,
+            ),
+        );
+    }
+}
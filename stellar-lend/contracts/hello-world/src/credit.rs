@@ -0,0 +1,302 @@
+//! Borrower credit scores from on-chain history
+//!
+//! A score in `0..=1000` is the weighted sum of four components, each
+//! normalised to `[0, 1]` against an admin-set target:
+//! - account age, from the first analytics interaction
+//! - repayment record, repayments over repayments plus liquidations
+//! - average health-factor buffer above maintenance, sampled on every
+//!   position save while in debt
+//! - borrow interest accrued over the position's life
+//!
+//! Each liquidation then subtracts a flat penalty. When
+//! `RiskConfig::credit_discounts_enabled` is set, score bands map to a
+//! borrow-rate discount applied during accrual, capped at
+//! [`MAX_CREDIT_DISCOUNT_BPS`].
+
+use crate::analytics::AnalyticsStorage;
+use crate::defaults;
+use crate::{Position, ProtocolConfig, ProtocolError, RiskConfigStorage, StateHelper};
+use soroban_sdk::{contracttype, Address, Env, Symbol, Vec};
+
+/// Highest score a borrower can reach
+pub const MAX_CREDIT_SCORE: u32 = 1000;
+/// Largest borrow-rate discount any band may grant (2%)
+pub const MAX_CREDIT_DISCOUNT_BPS: i128 = 200;
+/// Score bands allowed at once
+pub const MAX_CREDIT_BANDS: u32 = 10;
+
+/// Score formula weights and normalisation targets
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct CreditWeights {
+    /// Points for reaching `age_target_secs`
+    pub account_age: i128,
+    /// Points for a clean repayment record
+    pub repayment: i128,
+    /// Points for averaging `buffer_target_bps` above maintenance
+    pub health_buffer: i128,
+    /// Points for accruing `interest_target` of borrow interest
+    pub interest_paid: i128,
+    /// Points removed per liquidation
+    pub liquidation_penalty: i128,
+    pub age_target_secs: u64,
+    pub buffer_target_bps: i128,
+    pub interest_target: i128,
+}
+
+impl Default for CreditWeights {
+    fn default() -> Self {
+        Self {
+            account_age: defaults::CREDIT_AGE_WEIGHT,
+            repayment: defaults::CREDIT_REPAYMENT_WEIGHT,
+            health_buffer: defaults::CREDIT_BUFFER_WEIGHT,
+            interest_paid: defaults::CREDIT_INTEREST_WEIGHT,
+            liquidation_penalty: defaults::CREDIT_LIQUIDATION_PENALTY,
+            age_target_secs: defaults::CREDIT_AGE_TARGET_SECS,
+            buffer_target_bps: defaults::CREDIT_BUFFER_TARGET_BPS,
+            interest_target: defaults::CREDIT_INTEREST_TARGET,
+        }
+    }
+}
+
+impl CreditWeights {
+    /// Component weights are non-negative and sum to [`MAX_CREDIT_SCORE`];
+    /// targets are positive
+    pub fn validate(&self) -> Result<(), ProtocolError> {
+        let parts = [
+            self.account_age,
+            self.repayment,
+            self.health_buffer,
+            self.interest_paid,
+            self.liquidation_penalty,
+        ];
+        if parts.iter().any(|w| *w < 0)
+            || self.account_age + self.repayment + self.health_buffer + self.interest_paid
+                != MAX_CREDIT_SCORE as i128
+            || self.age_target_secs == 0
+            || self.buffer_target_bps <= 0
+            || self.interest_target <= 0
+        {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        Ok(())
+    }
+}
+
+/// Borrow-rate discount for scores at or above `min_score`
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct CreditBand {
+    pub min_score: u32,
+    pub discount_bps: i128,
+}
+
+/// Credit events not captured by the analytics counters
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct CreditHistory {
+    pub repayments: u32,
+    pub liquidations: u32,
+    /// Sum of sampled buffers above maintenance, in bps
+    pub buffer_sum_bps: i128,
+    pub buffer_samples: u32,
+}
+
+/// Credit storage management
+pub struct CreditStorage;
+
+impl CreditStorage {
+    pub(crate) fn weights_key(env: &Env) -> Symbol {
+        Symbol::new(env, "credit_weights")
+    }
+
+    pub(crate) fn bands_key(env: &Env) -> Symbol {
+        Symbol::new(env, "credit_bands")
+    }
+
+    pub(crate) fn history_key(env: &Env, user: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "credit_history"), user.clone())
+    }
+
+    pub fn get_weights(env: &Env) -> CreditWeights {
+        env.storage()
+            .instance()
+            .get(&Self::weights_key(env))
+            .unwrap_or_default()
+    }
+
+    pub fn set_weights(env: &Env, weights: &CreditWeights) {
+        env.storage()
+            .instance()
+            .set(&Self::weights_key(env), weights);
+    }
+
+    /// Discount bands, ordered by increasing `min_score`
+    pub fn get_bands(env: &Env) -> Vec<CreditBand> {
+        env.storage()
+            .instance()
+            .get(&Self::bands_key(env))
+            .unwrap_or_else(|| Vec::new(env))
+    }
+
+    pub fn set_bands(env: &Env, bands: &Vec<CreditBand>) {
+        env.storage().instance().set(&Self::bands_key(env), bands);
+    }
+
+    pub fn get_history(env: &Env, user: &Address) -> CreditHistory {
+        env.storage()
+            .instance()
+            .get(&Self::history_key(env, user))
+            .unwrap_or(CreditHistory {
+                repayments: 0,
+                liquidations: 0,
+                buffer_sum_bps: 0,
+                buffer_samples: 0,
+            })
+    }
+
+    fn save_history(env: &Env, user: &Address, history: &CreditHistory) {
+        env.storage()
+            .instance()
+            .set(&Self::history_key(env, user), history);
+    }
+}
+
+/// Credit scoring logic
+pub struct Credit;
+
+impl Credit {
+    pub fn set_weights(
+        env: &Env,
+        caller: &Address,
+        weights: CreditWeights,
+    ) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        weights.validate()?;
+        CreditStorage::set_weights(env, &weights);
+        Ok(())
+    }
+
+    /// Replace the discount bands. `min_score` must strictly increase and
+    /// every discount must be within [`MAX_CREDIT_DISCOUNT_BPS`].
+    pub fn set_bands(
+        env: &Env,
+        caller: &Address,
+        bands: Vec<CreditBand>,
+    ) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        if bands.len() > MAX_CREDIT_BANDS {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        let mut prev: Option<u32> = None;
+        for band in bands.iter() {
+            if band.min_score > MAX_CREDIT_SCORE
+                || !(0..=MAX_CREDIT_DISCOUNT_BPS).contains(&band.discount_bps)
+                || prev.is_some_and(|p| band.min_score <= p)
+            {
+                return Err(ProtocolError::InvalidParameters);
+            }
+            prev = Some(band.min_score);
+        }
+        CreditStorage::set_bands(env, &bands);
+        Ok(())
+    }
+
+    pub fn record_repayment(env: &Env, user: &Address) {
+        let mut history = CreditStorage::get_history(env, user);
+        history.repayments = history.repayments.saturating_add(1);
+        CreditStorage::save_history(env, user, &history);
+    }
+
+    pub fn record_liquidation(env: &Env, user: &Address) {
+        let mut history = CreditStorage::get_history(env, user);
+        history.liquidations = history.liquidations.saturating_add(1);
+        CreditStorage::save_history(env, user, &history);
+    }
+
+    /// Sample the buffer above the liquidation threshold of an indebted position
+    pub fn sample_buffer(env: &Env, position: &Position) {
+        if position.debt <= 0 {
+            return;
+        }
+        let ratio_bps = position.collateral.saturating_mul(10_000) / position.debt;
+        let buffer = (ratio_bps - ProtocolConfig::get_liquidation_threshold_bps(env)).max(0);
+        let mut history = CreditStorage::get_history(env, &position.user);
+        history.buffer_sum_bps = history.buffer_sum_bps.saturating_add(buffer);
+        history.buffer_samples = history.buffer_samples.saturating_add(1);
+        CreditStorage::save_history(env, &position.user, &history);
+    }
+
+    /// `points * min(value / target, 1)`
+    fn component(points: i128, value: i128, target: i128) -> i128 {
+        points.saturating_mul(value.clamp(0, target)) / target
+    }
+
+    /// Score of `user`, reading interest from `position` when given (so
+    /// accrual can score the in-flight position) or from storage otherwise
+    pub fn score_with(env: &Env, user: &Address, position: Option<&Position>) -> u32 {
+        let weights = CreditStorage::get_weights(env);
+        let history = CreditStorage::get_history(env, user);
+        let analytics = AnalyticsStorage::get_user_analytics_for_user(env, user);
+
+        let age = if analytics.first_interaction == 0 {
+            0
+        } else {
+            env.ledger()
+                .timestamp()
+                .saturating_sub(analytics.first_interaction)
+        };
+        let outcomes = history.repayments as i128 + history.liquidations as i128;
+        let avg_buffer = if history.buffer_samples == 0 {
+            0
+        } else {
+            history.buffer_sum_bps / history.buffer_samples as i128
+        };
+        let interest = match position {
+            Some(p) => p.borrow_interest,
+            None => StateHelper::get_position(env, user)
+                .map(|p| p.borrow_interest)
+                .unwrap_or(0),
+        };
+
+        let mut score = Self::component(
+            weights.account_age,
+            age as i128,
+            weights.age_target_secs as i128,
+        );
+        if outcomes > 0 {
+            score += Self::component(weights.repayment, history.repayments as i128, outcomes);
+        }
+        score += Self::component(weights.health_buffer, avg_buffer, weights.buffer_target_bps);
+        score += Self::component(weights.interest_paid, interest, weights.interest_target);
+        score -= weights
+            .liquidation_penalty
+            .saturating_mul(history.liquidations as i128);
+        score.clamp(0, MAX_CREDIT_SCORE as i128) as u32
+    }
+
+    pub fn score(env: &Env, user: &Address) -> u32 {
+        Self::score_with(env, user, None)
+    }
+
+    /// Borrow-rate discount for a score: the highest band it reaches
+    pub fn discount_for_score(env: &Env, score: u32) -> i128 {
+        let mut discount = 0;
+        for band in CreditStorage::get_bands(env).iter() {
+            if score >= band.min_score {
+                discount = band.discount_bps;
+            }
+        }
+        discount.min(MAX_CREDIT_DISCOUNT_BPS)
+    }
+
+    /// Borrow-rate discount applied to `position`'s accrual, or 0 when
+    /// discounts are disabled
+    pub fn discount_bps(env: &Env, position: &Position) -> i128 {
+        if !RiskConfigStorage::get(env).credit_discounts_enabled {
+            return 0;
+        }
+        let score = Self::score_with(env, &position.user, Some(position));
+        Self::discount_for_score(env, score)
+    }
+}
@@ -0,0 +1,105 @@
+//! Protocol defaults
+//! Every fallback value used by a storage getter lives here. `initialize`
+//! persists all of them, so after initialization storage is the single
+//! source of truth and pre-init reads can never disagree with it.
+
+use crate::accrual::AccrualStorage;
+use crate::alerts::AlertStorage;
+use crate::credit::{CreditStorage, CreditWeights};
+use crate::govbudget::{BudgetStorage, CATEGORIES};
+use crate::governance::GovStorage;
+use crate::oracle::OracleStorage;
+use crate::payouts::PayoutStorage;
+use crate::sweep::SweepStorage;
+use crate::transfer::TransferStorage;
+use crate::{ProtocolConfig, ProtocolError, RiskConfigStorage};
+use soroban_sdk::{Address, Env, Symbol};
+
+/// Loan-to-value bound as a minimum collateral ratio, in percent; the
+/// liquidation threshold follows it until configured
+pub const MIN_COLLATERAL_RATIO: i128 = 150;
+/// Flash loan fee in bps (0.05%)
+pub const FLASH_LOAN_FEE_BPS: i128 = 5;
+/// Extra ratio in bps required when a borrow increases debt
+pub const ORIGINATION_BUFFER_BPS: i128 = 0;
+// Proposal lifecycle defaults live with the governance crate
+pub use stellarlend_governance::defaults::*;
+/// Governance change budget per epoch
+pub const GOV_EPOCH_BUDGET: u32 = 12;
+/// Budget consumed by a risk parameter change
+pub const GOV_RISK_ACTION_WEIGHT: u32 = 3;
+/// Budget consumed by a market listing or incentive change
+pub const GOV_MARKET_ACTION_WEIGHT: u32 = 2;
+/// Budget consumed by a governance process tweak
+pub const GOV_GOVERNANCE_ACTION_WEIGHT: u32 = 1;
+// Oracle defaults live with the oracle crate
+pub use stellarlend_oracle::defaults::*;
+/// Minimum time between snapshots of a position that stays at risk
+pub const RISK_SNAPSHOT_INTERVAL_SECS: u64 = 60 * 60;
+/// Pushed price move in bps that starts a holder health sweep
+pub const PRICE_SWEEP_DEVIATION_BPS: i128 = 1000;
+/// Largest pool price impact, in bps, a payout settlement swap may cause
+pub const PAYOUT_MAX_SLIPPAGE_BPS: i128 = 100;
+/// Widest oracle source disagreement borrows and liquidations act on, in
+/// bps of the median (0 = unchecked)
+pub const MAX_PRICE_SPREAD_BPS: i128 = 0;
+/// Time a receiver has to complete a position transfer
+pub const POSITION_TRANSFER_WINDOW_SECS: u64 = 24 * 60 * 60;
+/// Borrow rate move, in bps, that emits a `rate_changed` event (0 = every move)
+pub const RATE_EVENT_THRESHOLD_BPS: i128 = 25;
+/// Credit score points for account age
+pub const CREDIT_AGE_WEIGHT: i128 = 250;
+/// Credit score points for the repayment record
+pub const CREDIT_REPAYMENT_WEIGHT: i128 = 350;
+/// Credit score points for the average health buffer
+pub const CREDIT_BUFFER_WEIGHT: i128 = 250;
+/// Credit score points for borrow interest accrued
+pub const CREDIT_INTEREST_WEIGHT: i128 = 150;
+/// Credit score points removed per liquidation
+pub const CREDIT_LIQUIDATION_PENALTY: i128 = 200;
+/// Account age earning the full age weight
+pub const CREDIT_AGE_TARGET_SECS: u64 = 180 * 24 * 60 * 60;
+/// Average buffer above maintenance, in bps, earning the full buffer weight
+pub const CREDIT_BUFFER_TARGET_BPS: i128 = 5000;
+/// Borrow interest earning the full interest weight
+pub const CREDIT_INTEREST_TARGET: i128 = 10_000;
+
+/// Write every default to storage. Called once from `initialize`, right
+/// after the admin is set.
+pub fn persist_all(env: &Env, admin: &Address) -> Result<(), ProtocolError> {
+    ProtocolConfig::set_min_collateral_ratio(env, admin, MIN_COLLATERAL_RATIO)?;
+    ProtocolConfig::set_flash_loan_fee_bps(env, admin, FLASH_LOAN_FEE_BPS)?;
+    ProtocolConfig::set_origination_buffer_bps(env, admin, ORIGINATION_BUFFER_BPS)?;
+    GovStorage::set_quorum_bps(env, GOV_QUORUM_BPS)?;
+    GovStorage::set_timelock(env, GOV_TIMELOCK_SECS)?;
+    GovStorage::set_min_voting_period(env, GOV_MIN_VOTING_PERIOD_SECS)?;
+    GovStorage::set_proposer_cooldown(env, GOV_PROPOSER_COOLDOWN_SECS)?;
+    GovStorage::set_max_title_len(env, GOV_MAX_TITLE_LEN)?;
+    BudgetStorage::set_epoch_budget(env, GOV_EPOCH_BUDGET)?;
+    for category in CATEGORIES {
+        let category = Symbol::new(env, category);
+        BudgetStorage::set_weight(env, &category, BudgetStorage::get_weight(env, &category))?;
+    }
+    OracleStorage::set_heartbeat_ttl(env, admin, ORACLE_HEARTBEAT_TTL_SECS)?;
+    OracleStorage::set_mode(env, admin, ORACLE_MODE)?;
+    OracleStorage::set_deviation_bps(env, ORACLE_DEVIATION_BPS);
+    OracleStorage::set_trim_count(env, ORACLE_TRIM_COUNT);
+    OracleStorage::set_twap_window(env, ORACLE_TWAP_WINDOW);
+    OracleStorage::set_twap_buffer_len(env, ORACLE_TWAP_BUFFER_LEN);
+    OracleStorage::set_ema_alpha_bps(env, ORACLE_EMA_ALPHA_BPS);
+    OracleStorage::set_min_sources(env, ORACLE_MIN_SOURCES);
+    OracleStorage::set_max_sources_per_asset(env, ORACLE_MAX_SOURCES_PER_ASSET);
+    OracleStorage::set_max_deviation_bps(env, ORACLE_MAX_DEVIATION_BPS);
+    OracleStorage::set_max_source_failures(env, ORACLE_MAX_SOURCE_FAILURES);
+    OracleStorage::set_price_cache_ttl(env, ORACLE_PRICE_CACHE_TTL_SECS);
+    OracleStorage::set_block_liquidation_on_clamp(env, ORACLE_BLOCK_LIQUIDATION_ON_CLAMP);
+    OracleStorage::set_max_freeze_secs(env, ORACLE_MAX_FREEZE_SECS);
+    CreditStorage::set_weights(env, &CreditWeights::default());
+    AlertStorage::set_interval(env, RISK_SNAPSHOT_INTERVAL_SECS);
+    SweepStorage::set_deviation_bps(env, PRICE_SWEEP_DEVIATION_BPS);
+    PayoutStorage::set_max_slippage_bps(env, PAYOUT_MAX_SLIPPAGE_BPS)?;
+    RiskConfigStorage::set_max_price_spread_bps(env, MAX_PRICE_SPREAD_BPS)?;
+    TransferStorage::set_window(env, POSITION_TRANSFER_WINDOW_SECS)?;
+    AccrualStorage::set_rate_event_threshold(env, RATE_EVENT_THRESHOLD_BPS)?;
+    Ok(())
+}
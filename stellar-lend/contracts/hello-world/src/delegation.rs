@@ -0,0 +1,194 @@
+//! Credit delegation
+//!
+//! An owner may grant a spender, typically a vault contract, a borrow
+//! allowance against their position. The spender draws debt up to the
+//! allowance: the debt is recorded on the owner, whose collateral must cover
+//! it as for any borrow, and the funds go to a recipient of the spender's
+//! choosing. The owner can revoke what is left at any time.
+
+use crate::borrow::BorrowModule;
+use crate::{EventTracker, ProtocolError, TokenRegistry};
+use soroban_sdk::{Address, Env, Symbol, Vec};
+
+/// Borrow allowance storage management
+pub struct DelegationStorage;
+
+impl DelegationStorage {
+    pub(crate) fn allowance_key(
+        env: &Env,
+        owner: &Address,
+        spender: &Address,
+        asset: &Address,
+    ) -> (Symbol, Address, Address, Address) {
+        (
+            Symbol::new(env, "credit_allowance"),
+            owner.clone(),
+            spender.clone(),
+            asset.clone(),
+        )
+    }
+
+    pub(crate) fn grants_key(env: &Env, owner: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "credit_grants"), owner.clone())
+    }
+
+    /// (spender, asset) pairs holding a non-zero allowance from `owner`
+    pub fn get_grants(env: &Env, owner: &Address) -> Vec<(Address, Address)> {
+        env.storage()
+            .instance()
+            .get(&Self::grants_key(env, owner))
+            .unwrap_or_else(|| Vec::new(env))
+    }
+
+    /// Debt `spender` may still draw against `owner`'s position
+    pub fn get_allowance(env: &Env, owner: &Address, spender: &Address, asset: &Address) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::allowance_key(env, owner, spender, asset))
+            .unwrap_or(0)
+    }
+
+    fn set_allowance(env: &Env, owner: &Address, spender: &Address, asset: &Address, amount: i128) {
+        let key = Self::allowance_key(env, owner, spender, asset);
+        if amount == 0 {
+            env.storage().instance().remove(&key);
+        } else {
+            env.storage().instance().set(&key, &amount);
+        }
+
+        let grant = (spender.clone(), asset.clone());
+        let mut grants = Self::get_grants(env, owner);
+        match (grants.first_index_of(&grant), amount == 0) {
+            (Some(index), true) => {
+                grants.remove(index);
+            }
+            (None, false) => grants.push_back(grant),
+            _ => return,
+        }
+        let grants_key = Self::grants_key(env, owner);
+        if grants.is_empty() {
+            env.storage().instance().remove(&grants_key);
+        } else {
+            env.storage().instance().set(&grants_key, &grants);
+        }
+    }
+}
+
+/// Credit delegation logic
+pub struct CreditDelegation;
+
+impl CreditDelegation {
+    /// Let `spender` borrow up to `amount` of `asset` against the owner's
+    /// position, replacing any previous allowance (0 revokes it). The owner
+    /// must authorize.
+    pub fn approve(
+        env: &Env,
+        owner: &Address,
+        spender: &Address,
+        asset: &Address,
+        amount: i128,
+    ) -> Result<(), ProtocolError> {
+        owner.require_auth();
+        if amount < 0 {
+            return Err(ProtocolError::InvalidAmount);
+        }
+        if spender == owner {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        if TokenRegistry::primary_asset(env).as_ref() != Some(asset) {
+            return Err(ProtocolError::AssetNotSupported);
+        }
+        DelegationStorage::set_allowance(env, owner, spender, asset, amount);
+        Self::emit(env, "credit_approved", owner, spender, None, amount, amount);
+        Ok(())
+    }
+
+    /// Drop the remaining allowance of `spender` (owner only)
+    pub fn revoke(
+        env: &Env,
+        owner: &Address,
+        spender: &Address,
+        asset: &Address,
+    ) -> Result<(), ProtocolError> {
+        owner.require_auth();
+        if DelegationStorage::get_allowance(env, owner, spender, asset) == 0 {
+            return Err(ProtocolError::NotFound);
+        }
+        DelegationStorage::set_allowance(env, owner, spender, asset, 0);
+        Self::emit(env, "credit_revoked", owner, spender, None, 0, 0);
+        Ok(())
+    }
+
+    /// Drop every allowance `owner` has granted, as when their position
+    /// moves to another account
+    pub fn revoke_all(env: &Env, owner: &Address) {
+        for (spender, asset) in DelegationStorage::get_grants(env, owner).iter() {
+            DelegationStorage::set_allowance(env, owner, &spender, &asset, 0);
+            Self::emit(env, "credit_revoked", owner, &spender, None, 0, 0);
+        }
+    }
+
+    /// Borrow `amount` of `asset` against `owner`'s position on their
+    /// allowance, sending the funds to `recipient`. The spender must
+    /// authorize.
+    pub fn borrow_from(
+        env: &Env,
+        spender: &Address,
+        owner: &Address,
+        asset: &Address,
+        amount: i128,
+        recipient: &Address,
+    ) -> Result<(), ProtocolError> {
+        spender.require_auth();
+        if amount <= 0 {
+            return Err(ProtocolError::InvalidAmount);
+        }
+        let allowance = DelegationStorage::get_allowance(env, owner, spender, asset);
+        if amount > allowance {
+            return Err(ProtocolError::Unauthorized);
+        }
+        if TokenRegistry::primary_asset(env).as_ref() != Some(asset) {
+            return Err(ProtocolError::AssetNotSupported);
+        }
+        BorrowModule::borrow_to(env, owner, recipient, amount)?;
+        let remaining = allowance - amount;
+        DelegationStorage::set_allowance(env, owner, spender, asset, remaining);
+        Self::emit(
+            env,
+            "credit_drawn",
+            owner,
+            spender,
+            Some(recipient),
+            amount,
+            remaining,
+        );
+        Ok(())
+    }
+
+    fn emit(
+        env: &Env,
+        name: &str,
+        owner: &Address,
+        spender: &Address,
+        recipient: Option<&Address>,
+        amount: i128,
+        remaining: i128,
+    ) {
+        let event_type = Symbol::new(env, name);
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        topics.push_back(Symbol::new(env, "user"));
+        EventTracker::record(
+            env,
+            event_type.clone(),
+            topics,
+            Some(owner.clone()),
+            None,
+            amount,
+        );
+        env.events().publish(
+            (event_type, owner.clone(), spender.clone()),
+            (recipient.cloned(), amount, remaining),
+        );
+    }
+}
@@ -1,10 +1,15 @@
 //! Deposit module for StellarLend protocol
 //! Handles collateral deposits and related functionality
 
+use crate::accrual::AccrualManager;
 use crate::analytics::AnalyticsModule;
+use crate::caps::CapManager;
+use crate::penalty::WithdrawalPenalty;
+use crate::sweep::PriceSweep;
+use crate::transfer::PositionTransfer;
 use crate::{
     EmergencyManager, InterestRateManager, InterestRateStorage, OperationKind, Position,
-    ProtocolError, ProtocolEvent, ReentrancyGuard, RiskConfigStorage, StateHelper,
+    ProtocolError, ProtocolEvent, ReentrancyGuard, RiskConfigStorage, StateHelper, TokenRegistry,
     TransferEnforcer, UserManager,
 };
 use soroban_sdk::{contracterror, contracttype, Address, Env, String, Symbol};
@@ -84,7 +89,12 @@ impl DepositModule {
 
             UserManager::ensure_operation_allowed(env, depositor, OperationKind::Deposit, amount)?;
 
+            let asset = TokenRegistry::require_primary_asset(env)?;
+            CapManager::check_supply(env, &asset, amount)?;
+            CapManager::check_global_tvl(env, &asset, amount)?;
+
             TransferEnforcer::transfer_in(env, depositor, amount, Symbol::new(env, "deposit"))?;
+            WithdrawalPenalty::record_deposit(env, depositor, amount);
 
             // Load user position with error handling
             let mut position = match StateHelper::get_position(env, depositor) {
@@ -92,8 +102,9 @@ impl DepositModule {
                 None => Position::new(depositor.clone(), 0, 0),
             };
 
-            // Accrue interest before updating position
-            let state = InterestRateStorage::update_state(env);
+            // Accrue interest before updating position; no pricing is needed
+            // since a deposit can only improve health
+            let state = AccrualManager::rates_for_position(env, position.debt);
             InterestRateManager::accrue_interest_for_position(
                 env,
                 &mut position,
@@ -106,6 +117,9 @@ impl DepositModule {
 
             // Save position
             StateHelper::save_position(env, &position);
+            PositionTransfer::cancel_on_activity(env, &position.user);
+            PriceSweep::index_holder(env, &asset, depositor);
+            InterestRateStorage::adjust_totals(env, amount, 0);
 
             // Emit event
             let collateral_ratio = if position.debt > 0 {
@@ -162,7 +176,12 @@ impl DepositModule {
 
             // Update position
             position.collateral += amount;
+            if TokenRegistry::primary_asset(env).as_ref() != Some(asset) {
+                AnalyticsModule::_record_collateral_asset(env, &user_addr, asset, amount);
+            }
             StateHelper::save_position(env, &position);
+            PositionTransfer::cancel_on_activity(env, &position.user);
+            PriceSweep::index_holder(env, asset, &user_addr);
 
             // Emit cross-asset deposit event
             ProtocolEvent::CrossDeposit(user_addr, asset.clone(), amount).emit(env);
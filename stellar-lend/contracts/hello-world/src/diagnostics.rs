@@ -0,0 +1,191 @@
+//! Storage self-diagnostics
+//! Detects storage layout drift after upgrades by decoding known entries with the current types,
+//! and keeps optional per-entrypoint call counters for debugging live deployments
+
+#[cfg(feature = "amm")]
+use crate::amm::{AssetPair, PairKey};
+use crate::analytics::{PerformanceMetrics, ProtocolMetrics, RiskAnalytics};
+use crate::capabilities::FeatureFlags;
+use crate::credit::{CreditBand, CreditWeights};
+use crate::forgiveness::PendingForgiveness;
+use crate::history::ParamChange;
+use crate::penalty::{DepositLot, PenaltyStorage};
+use crate::recovery::ForceCloseRecord;
+use crate::{
+    EmergencyState, EventSummary, InterestRateConfig, InterestRateState, Position, ProtocolConfig,
+    ProtocolError, RiskConfig, StateHelper, UserProfile, UserStorageKey,
+};
+use soroban_sdk::{contracttype, Address, Env, IntoVal, Map, Symbol, TryFromVal, Val, Vec};
+
+/// Entrypoints counted by [`CallTracker`]; anything else is ignored so the map stays bounded
+pub const TRACKED_ENTRYPOINTS: [&str; 9] = [
+    "deposit_collateral",
+    "borrow",
+    "borrow_asset",
+    "repay",
+    "withdraw",
+    "liquidate",
+    "execute_amm_swap",
+    "propose_listing",
+    "claim_expired_listing",
+];
+
+/// Call statistics for one entrypoint
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct CallStats {
+    pub count: u64,
+    pub last_caller: Address,
+    pub last_ts: u64,
+}
+
+/// A storage entry that could not be decoded with the current types
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct StorageIssue {
+    /// Storage key (or key prefix for per-user entries)
+    pub key: Symbol,
+    /// Sample user the entry belongs to, for per-user entries
+    pub user: Option<Address>,
+}
+
+/// Storage diagnostics helper
+pub struct StorageDiagnostics;
+
+impl StorageDiagnostics {
+    /// Returns false when an entry exists but does not decode as `T`.
+    /// Missing entries are treated as healthy since every getter has a default.
+    pub fn decodes<K, T>(env: &Env, key: &K) -> bool
+    where
+        K: IntoVal<Env, Val>,
+        T: TryFromVal<Env, Val>,
+    {
+        match env.storage().instance().get::<K, Val>(key) {
+            Some(raw) => T::try_from_val(env, &raw).is_ok(),
+            None => true,
+        }
+    }
+
+    fn check<T: TryFromVal<Env, Val>>(env: &Env, issues: &mut Vec<StorageIssue>, name: &str) {
+        let key = Symbol::new(env, name);
+        if !Self::decodes::<Symbol, T>(env, &key) {
+            issues.push_back(StorageIssue { key, user: None });
+        }
+    }
+
+    /// Attempt to decode every known configuration entry and the per-user
+    /// entries of `sample_users`, returning the entries that failed.
+    ///
+    /// Intended to run as the last step of any storage migration.
+    pub fn verify(env: &Env, sample_users: &Vec<Address>) -> Vec<StorageIssue> {
+        let mut issues = Vec::new(env);
+
+        Self::check::<Address>(env, &mut issues, "admin");
+        Self::check::<Address>(env, &mut issues, "oracle");
+        Self::check::<i128>(env, &mut issues, "min_ratio");
+        Self::check::<i128>(env, &mut issues, "origination_buffer_bps");
+        Self::check::<i128>(env, &mut issues, "flash_fee_bps");
+        Self::check::<bool>(env, &mut issues, "reentrancy");
+        Self::check::<EmergencyState>(env, &mut issues, "emergency_state");
+        Self::check::<InterestRateConfig>(env, &mut issues, "interest_config");
+        Self::check::<InterestRateState>(env, &mut issues, "interest_state");
+        Self::check::<RiskConfig>(env, &mut issues, "risk_config");
+        Self::check::<Map<Symbol, Address>>(env, &mut issues, "token_registry");
+        Self::check::<Vec<Symbol>>(env, &mut issues, "token_registry_order");
+        Self::check::<EventSummary>(env, &mut issues, "event_summary");
+        #[cfg(feature = "amm")]
+        {
+            Self::check::<Map<PairKey, AssetPair>>(env, &mut issues, "amm_pairs");
+            Self::check::<Vec<PairKey>>(env, &mut issues, "amm_pair_order");
+            Self::check::<Address>(env, &mut issues, "burn_address");
+        }
+        Self::check::<ProtocolMetrics>(env, &mut issues, "protocol_metrics");
+        Self::check::<RiskAnalytics>(env, &mut issues, "risk_analytics");
+        Self::check::<PerformanceMetrics>(env, &mut issues, "performance_metrics");
+        Self::check::<Map<Symbol, bool>>(env, &mut issues, "feature_flags");
+        Self::check::<Map<Symbol, CallStats>>(env, &mut issues, "call_stats");
+        Self::check::<Address>(env, &mut issues, "guardian");
+        Self::check::<Address>(env, &mut issues, "treasury");
+        Self::check::<Vec<ParamChange>>(env, &mut issues, "param_history");
+        Self::check::<Vec<Address>>(env, &mut issues, "position_index");
+        Self::check::<PendingForgiveness>(env, &mut issues, "pending_forgiveness");
+        Self::check::<i128>(env, &mut issues, "global_tvl_cap");
+        Self::check::<CreditWeights>(env, &mut issues, "credit_weights");
+        Self::check::<Vec<CreditBand>>(env, &mut issues, "credit_bands");
+        Self::check::<Vec<ForceCloseRecord>>(env, &mut issues, "force_close_audit");
+
+        for user in sample_users.iter() {
+            let position_key = StateHelper::position_key(env, &user);
+            if !Self::decodes::<(Symbol, Address), Position>(env, &position_key) {
+                issues.push_back(StorageIssue {
+                    key: Symbol::new(env, "position"),
+                    user: Some(user.clone()),
+                });
+            }
+            let profile_key = UserStorageKey::Profile(user.clone());
+            if !Self::decodes::<UserStorageKey, UserProfile>(env, &profile_key) {
+                issues.push_back(StorageIssue {
+                    key: Symbol::new(env, "user_profile"),
+                    user: Some(user.clone()),
+                });
+            }
+            let lots_key = (PenaltyStorage::lots_key(env), user.clone());
+            if !Self::decodes::<(Symbol, Address), Vec<DepositLot>>(env, &lots_key) {
+                issues.push_back(StorageIssue {
+                    key: Symbol::new(env, "deposit_lots"),
+                    user: Some(user.clone()),
+                });
+            }
+        }
+
+        issues
+    }
+
+    /// Admin entrypoint wrapper around [`StorageDiagnostics::verify`]
+    pub fn verify_storage(
+        env: &Env,
+        caller: &Address,
+        sample_users: &Vec<Address>,
+    ) -> Result<Vec<StorageIssue>, ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        Ok(Self::verify(env, sample_users))
+    }
+}
+
+/// Per-entrypoint call counters, active only while `diagnostics_enabled` is set
+pub struct CallTracker;
+
+impl CallTracker {
+    pub(crate) fn stats_key(env: &Env) -> Symbol {
+        Symbol::new(env, "call_stats")
+    }
+
+    /// Count a call to `name` by `caller`. With the flag off this only reads
+    /// the flag map and never touches the stats entry.
+    pub fn track_call(env: &Env, name: &str, caller: &Address) {
+        if !TRACKED_ENTRYPOINTS.contains(&name)
+            || !FeatureFlags::is_enabled(env, "diagnostics_enabled")
+        {
+            return;
+        }
+        let mut stats = Self::get_call_stats(env);
+        let entry = Symbol::new(env, name);
+        let count = stats.get(entry.clone()).map(|s| s.count).unwrap_or(0);
+        stats.set(
+            entry,
+            CallStats {
+                count: count + 1,
+                last_caller: caller.clone(),
+                last_ts: env.ledger().timestamp(),
+            },
+        );
+        env.storage().instance().set(&Self::stats_key(env), &stats);
+    }
+
+    pub fn get_call_stats(env: &Env) -> Map<Symbol, CallStats> {
+        env.storage()
+            .instance()
+            .get(&Self::stats_key(env))
+            .unwrap_or_else(|| Map::new(env))
+    }
+}
@@ -1,4 +1,5 @@
-use crate::{ProtocolError, ProtocolEvent, ReentrancyGuard};
+use crate::liquidity::Liquidity;
+use crate::{ProtocolError, ProtocolEvent, ReentrancyGuard, TokenRegistry};
 use soroban_sdk::{vec, Address, Env, IntoVal, Symbol};
 
 #[allow(dead_code)]
@@ -16,6 +17,9 @@ impl FlashLoan {
         if amount <= 0 {
             return Err(ProtocolError::InvalidAmount);
         }
+        if TokenRegistry::primary_asset(env).as_ref() == Some(asset) {
+            Liquidity::require_available(env, asset, amount)?;
+        }
         ReentrancyGuard::enter(env)?;
         let result = {
             let fee = (amount * fee_bps) / 10000;
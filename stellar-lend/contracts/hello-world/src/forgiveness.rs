@@ -0,0 +1,154 @@
+//! Interest forgiveness
+//!
+//! Remediation for unfair interest accrual (e.g. after an oracle failure).
+//! Forgiveness only reduces the accrued-interest portion of a position,
+//! never its principal, and is funded from protocol reserves: the forgiven
+//! amount is released from reserves into supplier liquidity so suppliers
+//! are made whole rather than silently losing the yield.
+//!
+//! Governance executes it directly through `Action::ForgiveInterest`; the
+//! admin may also schedule it as an emergency measure, which only becomes
+//! executable after `EMERGENCY_FORGIVENESS_DELAY_SECS`.
+
+use crate::reserves::ReserveStorage;
+use crate::{
+    EventTracker, InterestRateManager, InterestRateStorage, ProtocolConfig, ProtocolError,
+    StateHelper, TokenRegistry,
+};
+use soroban_sdk::{contracttype, Address, Env, Symbol, Vec};
+
+/// Delay before a scheduled emergency forgiveness can execute (7 days)
+pub const EMERGENCY_FORGIVENESS_DELAY_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// An admin-scheduled forgiveness waiting for its delay to pass
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PendingForgiveness {
+    pub asset: Address,
+    pub users: Vec<Address>,
+    pub bps: i128,
+    pub scheduled_by: Address,
+    pub executable_at: u64,
+}
+
+pub struct InterestForgiveness;
+
+impl InterestForgiveness {
+    pub(crate) fn pending_key(env: &Env) -> Symbol {
+        Symbol::new(env, "pending_forgiveness")
+    }
+
+    pub fn get_pending(env: &Env) -> Option<PendingForgiveness> {
+        env.storage().instance().get(&Self::pending_key(env))
+    }
+
+    /// Forgive `bps` of the accrued interest of each listed user. Fails
+    /// without touching any position if reserves cannot cover the total.
+    ///
+    /// Returns the total amount forgiven.
+    pub fn forgive(
+        env: &Env,
+        asset: &Address,
+        users: &Vec<Address>,
+        bps: i128,
+    ) -> Result<i128, ProtocolError> {
+        if bps <= 0 || bps > 10000 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        if TokenRegistry::primary_asset(env).as_ref() != Some(asset) {
+            return Err(ProtocolError::AssetNotSupported);
+        }
+
+        let state = InterestRateStorage::update_state(env);
+        let mut positions = Vec::new(env);
+        let mut total = 0i128;
+        for user in users.iter() {
+            let mut position =
+                StateHelper::get_position(env, &user).ok_or(ProtocolError::PositionNotFound)?;
+            InterestRateManager::accrue_interest_for_position(
+                env,
+                &mut position,
+                state.current_borrow_rate,
+                state.current_supply_rate,
+            );
+            let forgiven = position.borrow_interest * bps / 10000;
+            total += forgiven;
+            positions.push_back((position, forgiven));
+        }
+
+        if ReserveStorage::get(env, asset) < total {
+            return Err(ProtocolError::InsufficientLiquidity);
+        }
+        ReserveStorage::add(env, asset, -total);
+
+        for (mut position, forgiven) in positions.iter() {
+            position.borrow_interest -= forgiven;
+            StateHelper::save_position(env, &position);
+            Self::emit(env, "interest_forgiven", &position.user, asset, forgiven);
+        }
+        Ok(total)
+    }
+
+    /// Schedule an emergency forgiveness (admin only). Replaces any pending one.
+    pub fn schedule_emergency(
+        env: &Env,
+        caller: &Address,
+        asset: Address,
+        users: Vec<Address>,
+        bps: i128,
+    ) -> Result<PendingForgiveness, ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        if bps <= 0 || bps > 10000 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        let pending = PendingForgiveness {
+            asset,
+            users,
+            bps,
+            scheduled_by: caller.clone(),
+            executable_at: env.ledger().timestamp() + EMERGENCY_FORGIVENESS_DELAY_SECS,
+        };
+        env.storage()
+            .instance()
+            .set(&Self::pending_key(env), &pending);
+        Ok(pending)
+    }
+
+    /// Execute the scheduled emergency forgiveness once its delay has passed (admin only)
+    pub fn execute_emergency(env: &Env, caller: &Address) -> Result<i128, ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        let pending = Self::get_pending(env).ok_or(ProtocolError::NotFound)?;
+        if env.ledger().timestamp() < pending.executable_at {
+            return Err(ProtocolError::InvalidOperation);
+        }
+        let total = Self::forgive(env, &pending.asset, &pending.users, pending.bps)?;
+        env.storage().instance().remove(&Self::pending_key(env));
+        Ok(total)
+    }
+
+    fn emit(env: &Env, name: &str, user: &Address, asset: &Address, amount: i128) {
+        let event_type = Symbol::new(env, name);
+        let mut topics = Vec::new(env);
+        topics.push_back(Symbol::new(env, "user"));
+        topics.push_back(Symbol::new(env, "asset"));
+        EventTracker::record(
+            env,
+            event_type.clone(),
+            topics,
+            Some(user.clone()),
+            Some(asset.clone()),
+            amount,
+        );
+        env.events().publish(
+            (event_type, Symbol::new(env, "user")),
+            (
+                Symbol::new(env, "user"),
+                user.clone(),
+                Symbol::new(env, "asset"),
+                asset.clone(),
+                Symbol::new(env, "amount"),
+                amount,
+            ),
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! Per-epoch budget for governance parameter changes
+//!
+//! Every executed governance action consumes budget according to the weight
+//! of its category, so a captured or hasty governance cannot rewrite the risk
+//! configuration in one go. Budget resets each epoch (one week). Actions only
+//! run as part of the proposal that stored them; those that do not fit in
+//! what is left are carried over and may be executed once the epoch rolls.
+//!
+//! The budget size and the category weights are governance actions of their
+//! own. They cost no budget, but only a proposal passed by a super-majority
+//! may execute them.
+
+use crate::config;
+use crate::defaults;
+use crate::governance::{Action, GovStorage, Governance};
+use crate::{EventTracker, ProtocolConfig, ProtocolError};
+use soroban_sdk::{contracttype, Address, Env, Symbol, Vec};
+
+/// Length of a budget epoch (one week)
+pub const EPOCH_SECS: u64 = 7 * 24 * 60 * 60;
+/// Share of votes in bps a proposal needs in favour to change the budget
+pub const SUPERMAJORITY_BPS: i128 = 6667;
+/// Upper bound for a category weight and the epoch budget
+pub const MAX_BUDGET_UNITS: u32 = 1000;
+/// Categories actions are weighted by
+pub const CATEGORIES: [&str; 3] = ["risk", "market", "governance"];
+
+/// Budget consumed in an epoch
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct EpochUsage {
+    pub epoch: u64,
+    pub used: u32,
+}
+
+/// Actions of a proposal deferred to a later epoch, in execution order
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct Carryover {
+    pub actions: Vec<Action>,
+    /// First epoch the actions may be executed in
+    pub not_before_epoch: u64,
+}
+
+/// Change budget storage management
+pub struct BudgetStorage;
+
+impl BudgetStorage {
+    pub(crate) fn budget_key(env: &Env) -> Symbol {
+        Symbol::new(env, "gov_epoch_budget")
+    }
+    pub(crate) fn weight_key(env: &Env, category: &Symbol) -> (Symbol, Symbol) {
+        (Symbol::new(env, "gov_action_weight"), category.clone())
+    }
+    pub(crate) fn usage_key(env: &Env) -> Symbol {
+        Symbol::new(env, "gov_epoch_usage")
+    }
+    pub(crate) fn carryover_key(env: &Env, proposal_id: u64) -> (Symbol, u64) {
+        (Symbol::new(env, "gov_carryover"), proposal_id)
+    }
+
+    pub fn get_epoch_budget(env: &Env) -> u32 {
+        env.storage()
+            .instance()
+            .get(&Self::budget_key(env))
+            .unwrap_or(defaults::GOV_EPOCH_BUDGET)
+    }
+    pub fn set_epoch_budget(env: &Env, budget: u32) -> Result<(), ProtocolError> {
+        if budget == 0 || budget > MAX_BUDGET_UNITS {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        config::set_param(
+            env,
+            "governance",
+            "epoch_budget",
+            &Self::budget_key(env),
+            None,
+            defaults::GOV_EPOCH_BUDGET,
+            budget,
+        );
+        Ok(())
+    }
+
+    /// Budget an action of `category` consumes
+    pub fn get_weight(env: &Env, category: &Symbol) -> u32 {
+        env.storage()
+            .instance()
+            .get(&Self::weight_key(env, category))
+            .unwrap_or_else(|| Self::default_weight(env, category))
+    }
+    pub fn set_weight(env: &Env, category: &Symbol, weight: u32) -> Result<(), ProtocolError> {
+        if !CATEGORIES.iter().any(|c| Symbol::new(env, c) == *category) || weight > MAX_BUDGET_UNITS
+        {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        let default = Self::default_weight(env, category);
+        config::set_param(
+            env,
+            "governance",
+            "action_weight",
+            &Self::weight_key(env, category),
+            None,
+            default,
+            weight,
+        );
+        Ok(())
+    }
+    fn default_weight(env: &Env, category: &Symbol) -> u32 {
+        if *category == Symbol::new(env, "risk") {
+            defaults::GOV_RISK_ACTION_WEIGHT
+        } else if *category == Symbol::new(env, "market") {
+            defaults::GOV_MARKET_ACTION_WEIGHT
+        } else {
+            defaults::GOV_GOVERNANCE_ACTION_WEIGHT
+        }
+    }
+
+    /// Budget consumed so far in the current epoch
+    pub fn get_usage(env: &Env) -> EpochUsage {
+        let epoch = ChangeBudget::current_epoch(env);
+        env.storage()
+            .instance()
+            .get(&Self::usage_key(env))
+            .filter(|usage: &EpochUsage| usage.epoch == epoch)
+            .unwrap_or(EpochUsage { epoch, used: 0 })
+    }
+    fn set_usage(env: &Env, usage: &EpochUsage) {
+        env.storage().instance().set(&Self::usage_key(env), usage);
+    }
+
+    pub fn get_carryover(env: &Env, proposal_id: u64) -> Option<Carryover> {
+        env.storage()
+            .instance()
+            .get(&Self::carryover_key(env, proposal_id))
+    }
+    fn set_carryover(env: &Env, proposal_id: u64, carryover: Option<&Carryover>) {
+        let key = Self::carryover_key(env, proposal_id);
+        match carryover {
+            Some(c) => env.storage().instance().set(&key, c),
+            None => env.storage().instance().remove(&key),
+        }
+    }
+}
+
+/// Budgeted execution of governance actions
+pub struct ChangeBudget;
+
+impl ChangeBudget {
+    pub fn current_epoch(env: &Env) -> u64 {
+        env.ledger().timestamp() / EPOCH_SECS
+    }
+
+    /// Budget left in the current epoch
+    pub fn remaining(env: &Env) -> u32 {
+        BudgetStorage::get_epoch_budget(env).saturating_sub(BudgetStorage::get_usage(env).used)
+    }
+
+    /// Weight category of an action; `None` for the budget's own settings
+    pub fn category(action: &Action) -> Option<&'static str> {
+        match action {
+            Action::ForgiveInterest(..)
+            | Action::SetGlobalTvlCap(_)
+            | Action::ApproveForceClose(..)
+            | Action::SetMigrationTarget(..)
+            | Action::SetMinCollateralRatio(_)
+            | Action::SetOracleHeartbeatTtl(_)
+            | Action::InvokeContract(..) => Some(CATEGORIES[0]),
+            #[cfg(feature = "amm")]
+            Action::BuybackAndBurn(_) => Some(CATEGORIES[0]),
+            Action::ApproveListing(_)
+            | Action::RejectListing(_)
+            | Action::SetEmissionSchedule(..)
+            | Action::SetFlashLoanFeeBps(_) => Some(CATEGORIES[1]),
+            #[cfg(feature = "amm")]
+            Action::SetPairFee(..) | Action::SetPairPaused(..) => Some(CATEGORIES[1]),
+            Action::SetMinVotingPeriod(_)
+            | Action::SetProposerCooldown(_)
+            | Action::SetMaxTitleLength(_)
+            | Action::SetQuorumBps(_)
+            | Action::SetTimelock(_) => Some(CATEGORIES[2]),
+            Action::SetEpochBudget(_) | Action::SetActionWeight(..) => None,
+        }
+    }
+
+    /// Budget `action` consumes when executed
+    pub fn weight(env: &Env, action: &Action) -> u32 {
+        match Self::category(action) {
+            Some(category) => BudgetStorage::get_weight(env, &Symbol::new(env, category)),
+            None => 0,
+        }
+    }
+
+    fn has_supermajority(env: &Env, proposal_id: u64) -> bool {
+        let Some(proposal) = GovStorage::get_proposal(env, proposal_id) else {
+            return false;
+        };
+        let total = proposal.for_votes + proposal.against_votes;
+        total > 0 && proposal.for_votes * 10000 / total >= SUPERMAJORITY_BPS
+    }
+
+    /// Execute `action` of proposal `id` within the epoch budget (admin
+    /// only). An action that does not fit, or follows one carried over, is
+    /// carried over to the next epoch and `false` is returned.
+    fn execute(
+        env: &Env,
+        caller: &Address,
+        action: Action,
+        id: u64,
+    ) -> Result<bool, ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        let _reason = action.enter_proposal_scope(env, caller, id)?;
+        let weight = Self::weight(env, &action);
+        if Self::category(&action).is_none() && !Self::has_supermajority(env, id) {
+            return Err(ProtocolError::Unauthorized);
+        }
+
+        if weight > Self::remaining(env) || BudgetStorage::get_carryover(env, id).is_some() {
+            Self::defer(env, id, action, weight);
+            return Ok(false);
+        }
+
+        action.execute(env, caller)?;
+        let mut usage = BudgetStorage::get_usage(env);
+        usage.used += weight;
+        BudgetStorage::set_usage(env, &usage);
+        Ok(true)
+    }
+
+    /// Execute actions stored by executed proposal `id` in order until the
+    /// budget runs out, carrying the rest over to the next epoch (admin
+    /// only). Returns the number of actions deferred.
+    pub(crate) fn execute_batch(
+        env: &Env,
+        caller: &Address,
+        id: u64,
+        actions: Vec<Action>,
+    ) -> Result<u32, ProtocolError> {
+        if actions.is_empty() {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        // Position of the first action within the proposal's action list
+        let first = GovStorage::get_actions(env, id)
+            .len()
+            .saturating_sub(actions.len());
+        let mut deferred = 0;
+        for (index, action) in actions.iter().enumerate() {
+            let kind = action.kind();
+            if Self::execute(env, caller, action, id)? {
+                Governance::emit_action_executed(env, id, first + index as u32, kind);
+            } else {
+                deferred += 1;
+            }
+        }
+        Ok(deferred)
+    }
+
+    /// Execute the actions proposal `id` carried over, once their epoch has
+    /// come (admin only). Returns the number still deferred.
+    pub fn execute_carryover(env: &Env, caller: &Address, id: u64) -> Result<u32, ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        let carryover = BudgetStorage::get_carryover(env, id).ok_or(ProtocolError::NotFound)?;
+        if Self::current_epoch(env) < carryover.not_before_epoch {
+            return Err(ProtocolError::InvalidOperation);
+        }
+        BudgetStorage::set_carryover(env, id, None);
+        Self::execute_batch(env, caller, id, carryover.actions)
+    }
+
+    fn defer(env: &Env, id: u64, action: Action, weight: u32) {
+        let not_before_epoch = Self::current_epoch(env) + 1;
+        let mut carryover = BudgetStorage::get_carryover(env, id).unwrap_or(Carryover {
+            actions: Vec::new(env),
+            not_before_epoch,
+        });
+        carryover.actions.push_back(action);
+        carryover.not_before_epoch = not_before_epoch;
+        BudgetStorage::set_carryover(env, id, Some(&carryover));
+
+        let event_type = Symbol::new(env, "gov_action_deferred");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        EventTracker::record(env, event_type.clone(), topics, None, None, weight as i128);
+        env.events().publish(
+            (event_type, Symbol::new(env, "governance")),
+            (id, carryover.actions.len(), not_before_epoch),
+        );
+    }
+}
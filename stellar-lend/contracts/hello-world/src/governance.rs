@@ -1,201 +1,288 @@
+//! Governance bindings
+//! Proposals, voting and the timelock live in the `stellarlend-governance`
+//! crate, generic over the services they need from the contract. [`Host`]
+//! supplies them from the contract's config, voting power, accrual and
+//! event analytics, and applies executed proposals' [`Action`]s through the
+//! change budget. [`Governance`] / [`GovStorage`] are governance bound to
+//! it, used everywhere else in the contract.
+
 #![allow(dead_code)]
-use soroban_sdk::{contracttype, Address, Env, Map, Symbol};
-
-#[derive(Clone, Debug, Eq, PartialEq)]
-#[contracttype]
-pub struct Proposal {
-    pub id: u64,
-    pub proposer: Address,
-    pub title: soroban_sdk::String,
-    pub created: u64,
-    pub voting_ends: u64,
-    pub queued_until: u64,
-    pub for_votes: i128,
-    pub against_votes: i128,
-    pub executed: bool,
-}
+use crate::accrual::AccrualManager;
+#[cfg(feature = "amm")]
+use crate::amm::{AMMRegistry, PairKey};
+#[cfg(feature = "amm")]
+use crate::buyback::{Buyback, BuybackOrder};
+use crate::caps::CapStorage;
+use crate::config;
+use crate::forgiveness::InterestForgiveness;
+use crate::govbudget::{BudgetStorage, ChangeBudget};
+use crate::listing::Listing;
+use crate::migration::MigrationStorage;
+use crate::oracle::OracleStorage;
+use crate::reasons::ChangeReason;
+use crate::recovery::{ForceCloseResolution, Recovery};
+use crate::rewards::{EmissionSegment, Rewards};
+use crate::voting::VotingStorage;
+use crate::{EventTracker, ProtocolConfig, ProtocolError};
+use soroban_sdk::{contracttype, Address, Env, Symbol, Val, Vec};
+pub use stellarlend_governance::*;
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-#[contracttype]
-pub struct VoteReceipt {
-    pub voter: Address,
-    pub support: bool,
-    pub weight: i128,
-}
+/// Governance as wired into this contract
+pub type Governance = stellarlend_governance::Governance<Host>;
 
-pub struct GovStorage;
+/// Governance storage as wired into this contract
+pub type GovStorage = stellarlend_governance::GovStorage<Host>;
 
-impl GovStorage {
-    fn proposals_key(env: &Env) -> Symbol {
-        Symbol::new(env, "gov_proposals")
-    }
-    fn receipts_key(env: &Env) -> Symbol {
-        Symbol::new(env, "gov_receipts")
-    }
-    fn counter_key(env: &Env) -> Symbol {
-        Symbol::new(env, "gov_counter")
-    }
-    fn quorum_bps_key(env: &Env) -> Symbol {
-        Symbol::new(env, "gov_quorum_bps")
-    }
-    fn timelock_key(env: &Env) -> Symbol {
-        Symbol::new(env, "gov_timelock")
-    }
-    fn delegation_key(env: &Env) -> Symbol {
-        Symbol::new(env, "gov_delegation")
-    }
+/// Contract services exposed to governance
+pub struct Host;
 
-    pub fn next_id(env: &Env) -> u64 {
-        let id: u64 = env
-            .storage()
-            .instance()
-            .get(&Self::counter_key(env))
-            .unwrap_or(0);
-        env.storage()
-            .instance()
-            .set(&Self::counter_key(env), &(id + 1));
-        id + 1
-    }
+impl GovernanceHost for Host {
+    type Action = Action;
 
-    pub fn save_proposal(env: &Env, p: &Proposal) {
-        let mut map: Map<u64, Proposal> = env
-            .storage()
-            .instance()
-            .get(&Self::proposals_key(env))
-            .unwrap_or_else(|| Map::new(env));
-        map.set(p.id, p.clone());
-        env.storage()
-            .instance()
-            .set(&Self::proposals_key(env), &map);
+    fn require_admin(env: &Env, caller: &Address) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)
     }
 
-    pub fn get_proposal(env: &Env, id: u64) -> Option<Proposal> {
-        let map: Map<u64, Proposal> = env
-            .storage()
-            .instance()
-            .get(&Self::proposals_key(env))
-            .unwrap_or_else(|| Map::new(env));
-        map.get(id)
+    fn admin(env: &Env) -> Option<Address> {
+        ProtocolConfig::get_admin(env)
     }
 
-    pub fn save_receipt(env: &Env, id: u64, r: &VoteReceipt) {
-        let key = (Self::receipts_key(env), id);
-        let mut map: Map<Address, VoteReceipt> = env
-            .storage()
-            .instance()
-            .get(&key)
-            .unwrap_or_else(|| Map::new(env));
-        map.set(r.voter.clone(), r.clone());
-        env.storage().instance().set(&key, &map);
+    fn guardian(env: &Env) -> Option<Address> {
+        ProtocolConfig::get_guardian(env)
     }
 
-    pub fn get_quorum_bps(env: &Env) -> i128 {
-        env.storage()
-            .instance()
-            .get(&Self::quorum_bps_key(env))
-            .unwrap_or(1000)
-    }
-    pub fn set_quorum_bps(env: &Env, bps: i128) {
-        env.storage()
-            .instance()
-            .set(&Self::quorum_bps_key(env), &bps);
-    }
-    pub fn get_timelock(env: &Env) -> u64 {
-        env.storage()
-            .instance()
-            .get(&Self::timelock_key(env))
-            .unwrap_or(60)
+    fn voting_power(env: &Env, account: &Address) -> i128 {
+        VotingStorage::get_power(env, account)
     }
-    pub fn set_timelock(env: &Env, secs: u64) {
-        env.storage()
-            .instance()
-            .set(&Self::timelock_key(env), &secs);
+
+    fn ensure_accrued_within(env: &Env, max_age_secs: u64) -> Result<(), ProtocolError> {
+        AccrualManager::ensure_accrued_within(env, max_age_secs)
     }
-}
 
-pub struct Governance;
+    fn execute_actions(
+        env: &Env,
+        executor: &Address,
+        id: u64,
+        actions: Vec<Action>,
+    ) -> Result<(), ProtocolError> {
+        ChangeBudget::execute_batch(env, executor, id, actions).map(|_| ())
+    }
 
-impl Governance {
-    pub fn propose(
+    fn track_event(
         env: &Env,
-        proposer: &Address,
-        title: soroban_sdk::String,
-        voting_period_secs: u64,
-    ) -> Proposal {
-        let now = env.ledger().timestamp();
-        let id = GovStorage::next_id(env);
-        let p = Proposal {
-            id,
-            proposer: proposer.clone(),
-            title,
-            created: now,
-            voting_ends: now + voting_period_secs,
-            queued_until: 0,
-            for_votes: 0,
-            against_votes: 0,
-            executed: false,
-        };
-        GovStorage::save_proposal(env, &p);
-        p
+        event_type: Symbol,
+        topics: Vec<Symbol>,
+        user: Option<Address>,
+        asset: Option<Address>,
+        amount: i128,
+    ) {
+        EventTracker::record(env, event_type, topics, user, asset, amount);
     }
 
-    pub fn vote(env: &Env, id: u64, voter: &Address, support: bool, weight: i128) -> Proposal {
-        let mut p = GovStorage::get_proposal(env, id).unwrap();
-        if env.ledger().timestamp() > p.voting_ends {
-            return p;
-        }
-        if support {
-            p.for_votes += weight;
-        } else {
-            p.against_votes += weight;
-        }
-        GovStorage::save_receipt(
-            env,
-            id,
-            &VoteReceipt {
-                voter: voter.clone(),
-                support,
-                weight,
-            },
-        );
-        GovStorage::save_proposal(env, &p);
-        p
+    fn record_change(
+        env: &Env,
+        scope: &str,
+        key: &str,
+        asset: Option<&Address>,
+        old: i128,
+        new: i128,
+    ) {
+        config::record_change(env, scope, key, asset, old, new);
     }
+}
 
-    pub fn queue(env: &Env, id: u64) -> Proposal {
-        let mut p = GovStorage::get_proposal(env, id).unwrap();
-        let now = env.ledger().timestamp();
-        let quorum = GovStorage::get_quorum_bps(env);
-        let total = p.for_votes + p.against_votes;
-        let have_quorum = if total == 0 {
-            false
-        } else {
-            (p.for_votes * 10000 / total) >= quorum
-        };
-        if have_quorum && now >= p.voting_ends {
-            p.queued_until = now + GovStorage::get_timelock(env);
+/// Declares [`Action`] with the always-available variants followed by the
+/// given feature-gated ones; `#[contracttype]` does not honour `#[cfg]` on
+/// individual variants.
+macro_rules! define_action {
+    ($($gated:tt)*) => {
+        /// Actions the governance executor understands
+        #[derive(Clone, Debug, Eq, PartialEq)]
+        #[contracttype]
+        pub enum Action {
+            ApproveListing(u64),
+            RejectListing(u64),
+            SetEmissionSchedule(Address, Vec<EmissionSegment>),
+            SetMigrationTarget(Address, bool),
+            /// Forgive bps of accrued interest for (asset, users, bps), funded from reserves
+            ForgiveInterest(Address, Vec<Address>, i128),
+            /// Shortest voting period a proposal may request, in seconds
+            SetMinVotingPeriod(u64),
+            /// Time a non-admin proposer must wait between proposals, in seconds
+            SetProposerCooldown(u64),
+            /// Longest proposal title, in bytes
+            SetMaxTitleLength(u32),
+            /// Global TVL cap in USD (oracle price scale, 0 = disabled)
+            SetGlobalTvlCap(i128),
+            /// Approve the admin force-closing a user's corrupted position
+            /// with this exact resolution
+            ApproveForceClose(Address, ForceCloseResolution),
+            /// Governance change budget per epoch (super-majority only)
+            SetEpochBudget(u32),
+            /// Budget consumed by actions of a category (super-majority only)
+            SetActionWeight(Symbol, u32),
+            /// Minimum collateral ratio, in percent
+            SetMinCollateralRatio(i128),
+            SetFlashLoanFeeBps(i128),
+            /// Oracle heartbeat TTL, in seconds
+            SetOracleHeartbeatTtl(u64),
+            /// Share of votes in favour, in bps, a proposal needs to pass
+            SetQuorumBps(i128),
+            /// Delay between queueing and executing a proposal, in seconds
+            SetTimelock(u64),
+            /// Call `function` on a contract with the given arguments; the
+            /// action fails if the call does
+            InvokeContract(Address, Symbol, Vec<Val>),
+            $($gated)*
         }
-        GovStorage::save_proposal(env, &p);
-        p
-    }
+    };
+}
 
-    pub fn execute(env: &Env, id: u64) -> Proposal {
-        let mut p = GovStorage::get_proposal(env, id).unwrap();
-        let now = env.ledger().timestamp();
-        if now >= p.queued_until && p.queued_until != 0 {
-            p.executed = true;
+#[cfg(feature = "amm")]
+define_action! {
+    SetPairFee(PairKey, i128),
+    SetPairPaused(PairKey, bool),
+    /// Spend reserves to buy the protocol token on the internal AMM and burn it
+    BuybackAndBurn(BuybackOrder),
+}
+
+#[cfg(not(feature = "amm"))]
+define_action! {}
+
+impl Action {
+    /// Names of every action kind, in declaration order
+    pub const KINDS: [&'static str; 21] = [
+        "approve_listing",
+        "reject_listing",
+        "set_emission_schedule",
+        "set_migration_target",
+        "forgive_interest",
+        "set_min_voting_period",
+        "set_proposer_cooldown",
+        "set_max_title_length",
+        "set_global_tvl_cap",
+        "approve_force_close",
+        "set_epoch_budget",
+        "set_action_weight",
+        "set_min_collateral_ratio",
+        "set_flash_loan_fee_bps",
+        "set_oracle_heartbeat_ttl",
+        "set_quorum_bps",
+        "set_timelock",
+        "invoke_contract",
+        "set_pair_fee",
+        "set_pair_paused",
+        "buyback_and_burn",
+    ];
+
+    /// Kinds that only exist when the `amm` feature is enabled
+    pub const AMM_KINDS: [&'static str; 3] =
+        ["set_pair_fee", "set_pair_paused", "buyback_and_burn"];
+
+    /// Name of this action's kind; the match keeps `KINDS` honest
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Action::ApproveListing(_) => Self::KINDS[0],
+            Action::RejectListing(_) => Self::KINDS[1],
+            Action::SetEmissionSchedule(..) => Self::KINDS[2],
+            Action::SetMigrationTarget(..) => Self::KINDS[3],
+            Action::ForgiveInterest(..) => Self::KINDS[4],
+            Action::SetMinVotingPeriod(_) => Self::KINDS[5],
+            Action::SetProposerCooldown(_) => Self::KINDS[6],
+            Action::SetMaxTitleLength(_) => Self::KINDS[7],
+            Action::SetGlobalTvlCap(_) => Self::KINDS[8],
+            Action::ApproveForceClose(..) => Self::KINDS[9],
+            Action::SetEpochBudget(_) => Self::KINDS[10],
+            Action::SetActionWeight(..) => Self::KINDS[11],
+            Action::SetMinCollateralRatio(_) => Self::KINDS[12],
+            Action::SetFlashLoanFeeBps(_) => Self::KINDS[13],
+            Action::SetOracleHeartbeatTtl(_) => Self::KINDS[14],
+            Action::SetQuorumBps(_) => Self::KINDS[15],
+            Action::SetTimelock(_) => Self::KINDS[16],
+            Action::InvokeContract(..) => Self::KINDS[17],
+            #[cfg(feature = "amm")]
+            Action::SetPairFee(..) => Self::KINDS[18],
+            #[cfg(feature = "amm")]
+            Action::SetPairPaused(..) => Self::KINDS[19],
+            #[cfg(feature = "amm")]
+            Action::BuybackAndBurn(_) => Self::KINDS[20],
         }
-        GovStorage::save_proposal(env, &p);
-        p
     }
+}
 
-    pub fn delegate(env: &Env, from: &Address, to: &Address) {
-        let key = (GovStorage::delegation_key(env), from.clone());
-        env.storage().instance().set(&key, to);
+impl Action {
+    /// Attribute the changes this action makes to executed proposal `id`
+    pub fn enter_proposal_scope<'a>(
+        &self,
+        env: &'a Env,
+        caller: &Address,
+        id: u64,
+    ) -> Result<ChangeReason<'a>, ProtocolError> {
+        let proposal = GovStorage::get_proposal(env, id).ok_or(ProtocolError::NotFound)?;
+        if !proposal.executed {
+            return Err(ProtocolError::InvalidOperation);
+        }
+        Ok(ChangeReason::for_proposal(env, caller, self.kind(), id))
     }
 
-    pub fn get_delegate(env: &Env, from: &Address) -> Option<Address> {
-        let key = (GovStorage::delegation_key(env), from.clone());
-        env.storage().instance().get(&key)
+    /// Apply this action. The admin acts as the executor; actions only
+    /// reach this from an executed proposal, through
+    /// [`crate::govbudget::ChangeBudget::execute_batch`], which charges the
+    /// epoch budget.
+    pub fn execute(self, env: &Env, caller: &Address) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        match self {
+            Action::ApproveListing(id) => Listing::approve(env, id).map(|_| ()),
+            Action::RejectListing(id) => Listing::reject(env, id).map(|_| ()),
+            Action::SetEmissionSchedule(asset, schedule) => {
+                Rewards::set_schedule(env, &asset, schedule)
+            }
+            Action::SetMigrationTarget(target, allowed) => {
+                MigrationStorage::set_allowed(env, &target, allowed);
+                Ok(())
+            }
+            #[cfg(feature = "amm")]
+            Action::SetPairFee(pair_id, fee_bps) => {
+                AMMRegistry::set_pair_fee(env, &pair_id, fee_bps)
+            }
+            #[cfg(feature = "amm")]
+            Action::SetPairPaused(pair_id, paused) => {
+                AMMRegistry::set_pair_paused(env, &pair_id, paused)
+            }
+            #[cfg(feature = "amm")]
+            Action::BuybackAndBurn(order) => Buyback::execute(env, &order).map(|_| ()),
+            Action::ForgiveInterest(asset, users, bps) => {
+                InterestForgiveness::forgive(env, &asset, &users, bps).map(|_| ())
+            }
+            Action::SetMinVotingPeriod(secs) => GovStorage::set_min_voting_period(env, secs),
+            Action::SetProposerCooldown(secs) => GovStorage::set_proposer_cooldown(env, secs),
+            Action::SetMaxTitleLength(len) => GovStorage::set_max_title_len(env, len),
+            Action::SetGlobalTvlCap(cap) => CapStorage::set_global_tvl_cap(env, cap),
+            Action::ApproveForceClose(user, resolution) => {
+                Recovery::approve(env, &user, resolution)
+            }
+            Action::SetEpochBudget(budget) => BudgetStorage::set_epoch_budget(env, budget),
+            Action::SetActionWeight(category, weight) => {
+                BudgetStorage::set_weight(env, &category, weight)
+            }
+            Action::SetMinCollateralRatio(ratio) => {
+                ProtocolConfig::set_min_collateral_ratio(env, caller, ratio)
+            }
+            Action::SetFlashLoanFeeBps(bps) => {
+                ProtocolConfig::set_flash_loan_fee_bps(env, caller, bps)
+            }
+            Action::SetOracleHeartbeatTtl(ttl) => {
+                OracleStorage::set_heartbeat_ttl(env, caller, ttl)
+            }
+            Action::SetQuorumBps(bps) => GovStorage::set_quorum_bps(env, bps),
+            Action::SetTimelock(secs) => GovStorage::set_timelock(env, secs),
+            Action::InvokeContract(contract, function, args) => {
+                match env.try_invoke_contract::<Val, soroban_sdk::Error>(&contract, &function, args)
+                {
+                    Ok(Ok(_)) => Ok(()),
+                    _ => Err(ProtocolError::InvalidOperation),
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,80 @@
+//! Parameter change history
+//! A bounded ring of recent configuration changes for auditors and operators
+
+use crate::reasons::ChangeReason;
+use soroban_sdk::{contracttype, Address, Env, Symbol, TryFromVal, Val, Vec};
+
+/// Number of parameter changes retained
+pub const PARAM_HISTORY_CAP: u32 = 32;
+
+/// One recorded configuration change
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct ParamChange {
+    /// Parameter that changed
+    pub param: Symbol,
+    /// What happened, e.g. `proposed`, `applied`, `cancelled`, `set`
+    pub action: Symbol,
+    pub actor: Address,
+    /// New value for address-typed parameters
+    pub address_value: Option<Address>,
+    /// New value for numeric parameters
+    pub int_value: Option<i128>,
+    pub timestamp: u64,
+    /// Registered reason code stated by the admin
+    pub reason: Option<Symbol>,
+    /// Proposal that carried the change, when governance executed it
+    pub via_proposal: Option<u64>,
+}
+
+/// Parameter history storage
+pub struct ParamHistory;
+
+impl ParamHistory {
+    pub(crate) fn key(env: &Env) -> Symbol {
+        Symbol::new(env, "param_history")
+    }
+
+    /// Recorded changes, oldest first. Entries written before changes
+    /// carried a reason no longer decode and are skipped.
+    pub fn get(env: &Env) -> Vec<ParamChange> {
+        let raw: Vec<Val> = env
+            .storage()
+            .instance()
+            .get(&Self::key(env))
+            .unwrap_or_else(|| Vec::new(env));
+        let mut history = Vec::new(env);
+        for entry in raw.iter() {
+            if let Ok(change) = ParamChange::try_from_val(env, &entry) {
+                history.push_back(change);
+            }
+        }
+        history
+    }
+
+    /// Append a change, dropping the oldest entries beyond the cap
+    pub fn record(
+        env: &Env,
+        param: Symbol,
+        action: &str,
+        actor: &Address,
+        address_value: Option<Address>,
+        int_value: Option<i128>,
+    ) {
+        let mut history = Self::get(env);
+        history.push_back(ParamChange {
+            param,
+            action: Symbol::new(env, action),
+            actor: actor.clone(),
+            address_value,
+            int_value,
+            timestamp: env.ledger().timestamp(),
+            reason: ChangeReason::current(env),
+            via_proposal: ChangeReason::proposal(env),
+        });
+        if history.len() > PARAM_HISTORY_CAP {
+            history = history.slice(history.len() - PARAM_HISTORY_CAP..);
+        }
+        env.storage().instance().set(&Self::key(env), &history);
+    }
+}
@@ -0,0 +1,329 @@
+//! Partner-funded supply incentives
+//!
+//! A sponsor escrows `amount` of a reward token for `duration_secs`. The
+//! program streams it linearly to suppliers of the supplied asset, pro-rata
+//! by time-weighted supply, through a per-program reward index (reward per
+//! unit of supply, scaled by [`INDEX_SCALE`]).
+//!
+//! Each program keeps its own tally of participating supply, fed by
+//! [`Incentives::on_balance_change`] whenever a position is saved. Suppliers
+//! whose balance has not changed since a program started join it on their
+//! first claim, with no retroactive credit. Emissions during periods with no
+//! participating supply are not distributed and go back to the sponsor when
+//! the program is swept after expiry.
+
+use crate::{EventTracker, ProtocolError, TokenRegistry, TransferEnforcer};
+use soroban_sdk::{contracttype, Address, Env, Symbol, Vec};
+
+/// Fixed-point scale of program reward indexes
+pub const INDEX_SCALE: i128 = 1_000_000_000_000;
+/// Concurrent programs per supplied asset, bounding the per-save checkpoint cost
+pub const MAX_PROGRAMS_PER_ASSET: u32 = 4;
+
+/// An external incentive program
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct IncentiveProgram {
+    pub id: u64,
+    pub sponsor: Address,
+    pub supplied_asset: Address,
+    pub reward_asset: Address,
+    /// Total rewards escrowed
+    pub amount: i128,
+    pub start_ts: u64,
+    pub end_ts: u64,
+    /// Cumulative rewards per unit of supply (scaled by [`INDEX_SCALE`])
+    pub reward_index: i128,
+    /// Supply currently participating in the program
+    pub total_supply: i128,
+    /// Rewards emitted while supply was participating
+    pub distributed: i128,
+    pub claimed: i128,
+    pub last_update: u64,
+    /// Set once the sponsor has recovered the undistributed remainder
+    pub swept: bool,
+}
+
+/// A supplier's standing in one program
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct IncentiveAccount {
+    pub supply: i128,
+    /// Program index at the last checkpoint
+    pub index: i128,
+    /// Rewards earned and not yet claimed
+    pub accrued: i128,
+}
+
+/// Incentive storage management
+pub struct IncentiveStorage;
+
+impl IncentiveStorage {
+    pub(crate) fn counter_key(env: &Env) -> Symbol {
+        Symbol::new(env, "incentive_counter")
+    }
+    pub(crate) fn program_key(env: &Env, id: u64) -> (Symbol, u64) {
+        (Symbol::new(env, "incentive_program"), id)
+    }
+
+    pub(crate) fn account_key(env: &Env, id: u64, user: &Address) -> (Symbol, u64, Address) {
+        (Symbol::new(env, "incentive_account"), id, user.clone())
+    }
+
+    pub(crate) fn active_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "incentive_active"), asset.clone())
+    }
+
+    pub fn get_program(env: &Env, id: u64) -> Option<IncentiveProgram> {
+        env.storage().instance().get(&Self::program_key(env, id))
+    }
+
+    pub fn save_program(env: &Env, program: &IncentiveProgram) {
+        env.storage()
+            .instance()
+            .set(&Self::program_key(env, program.id), program);
+    }
+
+    pub fn get_account(env: &Env, id: u64, user: &Address) -> IncentiveAccount {
+        env.storage()
+            .instance()
+            .get(&Self::account_key(env, id, user))
+            .unwrap_or(IncentiveAccount {
+                supply: 0,
+                index: 0,
+                accrued: 0,
+            })
+    }
+
+    pub fn save_account(env: &Env, id: u64, user: &Address, account: &IncentiveAccount) {
+        env.storage()
+            .instance()
+            .set(&Self::account_key(env, id, user), account);
+    }
+
+    /// Unswept programs on a supplied asset
+    pub fn get_active(env: &Env, asset: &Address) -> Vec<u64> {
+        env.storage()
+            .instance()
+            .get(&Self::active_key(env, asset))
+            .unwrap_or_else(|| Vec::new(env))
+    }
+
+    pub fn set_active(env: &Env, asset: &Address, ids: &Vec<u64>) {
+        env.storage()
+            .instance()
+            .set(&Self::active_key(env, asset), ids);
+    }
+
+    pub fn next_id(env: &Env) -> u64 {
+        let key = Self::counter_key(env);
+        let id: u64 = env.storage().instance().get(&key).unwrap_or(0) + 1;
+        env.storage().instance().set(&key, &id);
+        id
+    }
+}
+
+/// Incentive program logic
+pub struct Incentives;
+
+impl Incentives {
+    /// Escrow `amount` of `reward_asset` from `sponsor` and start streaming it
+    /// to suppliers of `supplied_asset` over `duration_secs`
+    pub fn fund(
+        env: &Env,
+        sponsor: &Address,
+        supplied_asset: &Address,
+        reward_asset: &Address,
+        amount: i128,
+        duration_secs: u64,
+    ) -> Result<u64, ProtocolError> {
+        if amount <= 0 {
+            return Err(ProtocolError::InvalidAmount);
+        }
+        if duration_secs == 0 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        // Supply positions are only tracked for the primary asset
+        if TokenRegistry::primary_asset(env).as_ref() != Some(supplied_asset) {
+            return Err(ProtocolError::AssetNotSupported);
+        }
+        let mut active = IncentiveStorage::get_active(env, supplied_asset);
+        if active.len() >= MAX_PROGRAMS_PER_ASSET {
+            return Err(ProtocolError::StorageLimitExceeded);
+        }
+
+        TransferEnforcer::transfer(
+            env,
+            reward_asset,
+            sponsor,
+            &env.current_contract_address(),
+            amount,
+            Symbol::new(env, "incentive_fund"),
+        )?;
+
+        let now = env.ledger().timestamp();
+        let program = IncentiveProgram {
+            id: IncentiveStorage::next_id(env),
+            sponsor: sponsor.clone(),
+            supplied_asset: supplied_asset.clone(),
+            reward_asset: reward_asset.clone(),
+            amount,
+            start_ts: now,
+            end_ts: now.saturating_add(duration_secs),
+            reward_index: 0,
+            total_supply: 0,
+            distributed: 0,
+            claimed: 0,
+            last_update: now,
+            swept: false,
+        };
+        IncentiveStorage::save_program(env, &program);
+        active.push_back(program.id);
+        IncentiveStorage::set_active(env, supplied_asset, &active);
+        Self::emit(env, "incentive_funded", &program, sponsor, amount);
+        Ok(program.id)
+    }
+
+    /// Rewards released by the linear stream up to `ts`
+    fn released_at(program: &IncentiveProgram, ts: u64) -> i128 {
+        let elapsed = ts.clamp(program.start_ts, program.end_ts) - program.start_ts;
+        let duration = program.end_ts - program.start_ts;
+        program.amount.saturating_mul(elapsed as i128) / duration as i128
+    }
+
+    /// Advance a program's index to now
+    fn accrue(env: &Env, program: &mut IncentiveProgram) {
+        let now = env.ledger().timestamp();
+        if now <= program.last_update {
+            return;
+        }
+        let emitted =
+            Self::released_at(program, now) - Self::released_at(program, program.last_update);
+        if emitted > 0 && program.total_supply > 0 {
+            program.reward_index = program
+                .reward_index
+                .saturating_add(emitted.saturating_mul(INDEX_SCALE) / program.total_supply);
+            program.distributed = program.distributed.saturating_add(emitted);
+        }
+        program.last_update = now;
+    }
+
+    /// Credit `user` up to the program's current index and set their
+    /// participating supply to `supply`
+    fn checkpoint(env: &Env, program: &mut IncentiveProgram, user: &Address, supply: i128) {
+        Self::accrue(env, program);
+        let mut account = IncentiveStorage::get_account(env, program.id, user);
+        let earned = account
+            .supply
+            .saturating_mul(program.reward_index - account.index)
+            / INDEX_SCALE;
+        account.accrued = account.accrued.saturating_add(earned);
+        account.index = program.reward_index;
+        program.total_supply = program.total_supply.saturating_add(supply - account.supply);
+        account.supply = supply;
+        IncentiveStorage::save_account(env, program.id, user, &account);
+    }
+
+    /// Checkpoint `user` in every active program on `asset` after their
+    /// supply changed to `supply`
+    pub fn on_balance_change(env: &Env, asset: &Address, user: &Address, supply: i128) {
+        for id in IncentiveStorage::get_active(env, asset).iter() {
+            if let Some(mut program) = IncentiveStorage::get_program(env, id) {
+                Self::checkpoint(env, &mut program, user, supply);
+                IncentiveStorage::save_program(env, &program);
+            }
+        }
+    }
+
+    /// Pay out `user`'s accrued rewards from a program, joining them to it
+    /// with their current supply if they are not yet participating
+    pub fn claim(env: &Env, user: &Address, id: u64) -> Result<i128, ProtocolError> {
+        let mut program = IncentiveStorage::get_program(env, id).ok_or(ProtocolError::NotFound)?;
+        let supply = crate::StateHelper::get_position(env, user)
+            .map(|p| p.collateral)
+            .unwrap_or(0);
+        Self::checkpoint(env, &mut program, user, supply);
+
+        let mut account = IncentiveStorage::get_account(env, id, user);
+        let amount = account.accrued;
+        account.accrued = 0;
+        IncentiveStorage::save_account(env, id, user, &account);
+        program.claimed = program.claimed.saturating_add(amount);
+        IncentiveStorage::save_program(env, &program);
+
+        if amount > 0 {
+            TransferEnforcer::transfer(
+                env,
+                &program.reward_asset,
+                &env.current_contract_address(),
+                user,
+                amount,
+                Symbol::new(env, "incentive_claim"),
+            )?;
+            Self::emit(env, "incentive_claimed", &program, user, amount);
+        }
+        Ok(amount)
+    }
+
+    /// Return the undistributed remainder of an expired program to its sponsor
+    pub fn sweep(env: &Env, caller: &Address, id: u64) -> Result<i128, ProtocolError> {
+        let mut program = IncentiveStorage::get_program(env, id).ok_or(ProtocolError::NotFound)?;
+        if program.sponsor != *caller {
+            return Err(ProtocolError::Unauthorized);
+        }
+        if program.swept || env.ledger().timestamp() < program.end_ts {
+            return Err(ProtocolError::InvalidOperation);
+        }
+        Self::accrue(env, &mut program);
+        let leftover = program.amount - program.distributed;
+        program.swept = true;
+        IncentiveStorage::save_program(env, &program);
+
+        // The index no longer moves, so accounts keep their claimable balance
+        // without further checkpoints
+        let mut active = IncentiveStorage::get_active(env, &program.supplied_asset);
+        if let Some(pos) = active.first_index_of(id) {
+            active.remove(pos);
+            IncentiveStorage::set_active(env, &program.supplied_asset, &active);
+        }
+
+        if leftover > 0 {
+            TransferEnforcer::transfer(
+                env,
+                &program.reward_asset,
+                &env.current_contract_address(),
+                caller,
+                leftover,
+                Symbol::new(env, "incentive_sweep"),
+            )?;
+        }
+        Self::emit(env, "incentive_swept", &program, caller, leftover);
+        Ok(leftover)
+    }
+
+    fn emit(env: &Env, name: &str, program: &IncentiveProgram, user: &Address, amount: i128) {
+        let event_type = Symbol::new(env, name);
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        topics.push_back(Symbol::new(env, "user"));
+        EventTracker::record(
+            env,
+            event_type.clone(),
+            topics,
+            Some(user.clone()),
+            Some(program.reward_asset.clone()),
+            amount,
+        );
+        env.events().publish(
+            (event_type, Symbol::new(env, "program")),
+            (
+                Symbol::new(env, "program_id"),
+                program.id,
+                Symbol::new(env, "user"),
+                user.clone(),
+                Symbol::new(env, "amount"),
+                amount,
+            ),
+        );
+    }
+}
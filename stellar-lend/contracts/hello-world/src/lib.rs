@@ -6,14 +6,17 @@
 #![no_std]
 extern crate alloc;
 
-use alloc::format;
-use alloc::string::ToString;
 use soroban_sdk::token::TokenClient;
 use soroban_sdk::{
-    contract, contracterror, contractimpl, contracttype, Address, Env, Map, String, Symbol, Vec,
+    contract, contractimpl, contracttype, Address, Bytes, BytesN, Env, Map, String, Symbol, Vec,
 };
+pub use stellarlend_core::ProtocolError;
+
+#[macro_use]
+mod pagination;
 mod flash_loan;
 mod governance;
+mod history;
 mod oracle;
 
 // Global allocator for Soroban contracts
@@ -96,12 +99,53 @@ impl AddressHelper {
 mod test;
 
 // Core protocol modules
+mod accrual;
+mod alerts;
+#[cfg(feature = "amm")]
 mod amm;
 mod analytics;
+mod autorepay;
+mod beta;
 mod borrow;
+#[cfg(feature = "amm")]
+mod buyback;
+mod capabilities;
+mod caps;
+mod checkpoints;
+mod commitment;
+mod config;
+mod cosign;
+mod credit;
+mod defaults;
+mod delegation;
 mod deposit;
+mod diagnostics;
+mod forgiveness;
+mod govbudget;
+mod incentives;
 mod liquidate;
+mod liquidity;
+mod listing;
+mod migration;
+mod multicall;
+mod payouts;
+mod penalty;
+mod privacy;
+mod reasons;
+mod reconcile;
+mod recovery;
 mod repay;
+mod reserves;
+mod rewards;
+mod roles;
+mod scan;
+mod sep40;
+mod shares;
+mod surge;
+mod sweep;
+mod transfer;
+mod twa;
+mod voting;
 mod withdraw;
 
 /// Supported emergency lifecycle states for the protocol
@@ -308,20 +352,14 @@ impl UserLimits {
         }
 
         match operation {
-            OperationKind::Deposit => {
-                if amount > self.max_deposit {
-                    return Err(ProtocolError::UserLimitExceeded);
-                }
+            OperationKind::Deposit if amount > self.max_deposit => {
+                return Err(ProtocolError::UserLimitExceeded);
             }
-            OperationKind::Borrow => {
-                if amount > self.max_borrow {
-                    return Err(ProtocolError::UserLimitExceeded);
-                }
+            OperationKind::Borrow if amount > self.max_borrow => {
+                return Err(ProtocolError::UserLimitExceeded);
             }
-            OperationKind::Withdraw => {
-                if amount > self.max_withdraw {
-                    return Err(ProtocolError::UserLimitExceeded);
-                }
+            OperationKind::Withdraw if amount > self.max_withdraw => {
+                return Err(ProtocolError::UserLimitExceeded);
             }
             _ => {}
         }
@@ -353,6 +391,21 @@ impl UserLimits {
     }
 }
 
+/// Maximum users accepted by one batch freeze/unfreeze call
+pub const MAX_FREEZE_BATCH: u32 = 20;
+
+/// Why and by whom a user was frozen, so integrators can surface a reason
+/// and an appeal pointer
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct FreezeInfo {
+    pub reason_code: u32,
+    /// Reference to the compliance case, if any
+    pub case_ref: Option<BytesN<32>>,
+    pub frozen_by: Address,
+    pub frozen_at: u64,
+}
+
 /// On-ledger user profile capturing role, verification and activity metrics
 #[derive(Clone, Debug, Eq, PartialEq)]
 #[contracttype]
@@ -364,6 +417,11 @@ pub struct UserProfile {
     pub last_active: u64,
     pub activity_score: i128,
     pub is_frozen: bool,
+    /// Freeze details, set by `freeze_user` and cleared whenever the user is unfrozen
+    pub freeze_reason_code: u32,
+    pub freeze_case_ref: Option<BytesN<32>>,
+    pub frozen_by: Option<Address>,
+    pub frozen_at: u64,
 }
 
 impl UserProfile {
@@ -376,8 +434,33 @@ impl UserProfile {
             last_active: env.ledger().timestamp(),
             activity_score: 0,
             is_frozen: false,
+            freeze_reason_code: 0,
+            freeze_case_ref: None,
+            frozen_by: None,
+            frozen_at: 0,
         }
     }
+
+    /// Freeze details, if the user is currently frozen
+    pub fn freeze_info(&self) -> Option<FreezeInfo> {
+        if !self.is_frozen {
+            return None;
+        }
+        self.frozen_by.clone().map(|frozen_by| FreezeInfo {
+            reason_code: self.freeze_reason_code,
+            case_ref: self.freeze_case_ref.clone(),
+            frozen_by,
+            frozen_at: self.frozen_at,
+        })
+    }
+
+    fn clear_freeze(&mut self) {
+        self.is_frozen = false;
+        self.freeze_reason_code = 0;
+        self.freeze_case_ref = None;
+        self.frozen_by = None;
+        self.frozen_at = 0;
+    }
 }
 
 /// Storage key namespace for user profiles
@@ -385,6 +468,8 @@ impl UserProfile {
 #[contracttype]
 pub enum UserStorageKey {
     Profile(Address),
+    /// Privacy flag bits, see `privacy`
+    PrivacyFlags(Address),
 }
 
 /// Centralized user management helper
@@ -478,7 +563,7 @@ impl UserManager {
         let mut profile = Self::ensure_profile(env, admin);
         profile.role = UserRole::Admin;
         profile.verification = VerificationStatus::Verified;
-        profile.is_frozen = false;
+        profile.clear_freeze();
         profile.last_active = env.ledger().timestamp();
         Self::save_profile(env, &profile);
         env.events().publish(
@@ -514,7 +599,7 @@ impl UserManager {
         if matches!(role, UserRole::Suspended) {
             profile.is_frozen = true;
         } else {
-            profile.is_frozen = false;
+            profile.clear_freeze();
         }
         if matches!(
             role,
@@ -553,7 +638,7 @@ impl UserManager {
             profile.is_frozen = true;
         }
         if status == VerificationStatus::Verified {
-            profile.is_frozen = false;
+            profile.clear_freeze();
         }
         let status_symbol = Self::verification_symbol(env, &status);
         Self::save_profile(env, &profile);
@@ -621,6 +706,7 @@ impl UserManager {
         let profile = Self::ensure_profile(env, user);
 
         if profile.is_frozen || profile.role == UserRole::Suspended {
+            Self::emit_blocked(env, &profile, operation);
             return Err(ProtocolError::UserSuspended);
         }
 
@@ -690,10 +776,75 @@ impl UserManager {
         Self::ensure_profile(env, user)
     }
 
-    pub fn freeze_user(env: &Env, caller: &Address, user: &Address) -> Result<(), ProtocolError> {
+    pub fn freeze_user(
+        env: &Env,
+        caller: &Address,
+        user: &Address,
+        reason_code: u32,
+        case_ref: Option<BytesN<32>>,
+    ) -> Result<(), ProtocolError> {
+        Self::ensure_can_manage(env, caller, UserRole::Manager)?;
+        Self::apply_freeze(env, caller, user, reason_code, case_ref);
+        Ok(())
+    }
+
+    pub fn unfreeze_user(env: &Env, caller: &Address, user: &Address) -> Result<(), ProtocolError> {
+        Self::ensure_can_manage(env, caller, UserRole::Manager)?;
+        Self::apply_unfreeze(env, user);
+        Ok(())
+    }
+
+    /// Freeze up to `MAX_FREEZE_BATCH` users with the same reason, for incident response
+    pub fn freeze_users(
+        env: &Env,
+        caller: &Address,
+        users: &Vec<Address>,
+        reason_code: u32,
+        case_ref: Option<BytesN<32>>,
+    ) -> Result<(), ProtocolError> {
+        Self::ensure_can_manage(env, caller, UserRole::Manager)?;
+        if users.len() > MAX_FREEZE_BATCH {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        for user in users.iter() {
+            Self::apply_freeze(env, caller, &user, reason_code, case_ref.clone());
+        }
+        Ok(())
+    }
+
+    /// Unfreeze up to `MAX_FREEZE_BATCH` users
+    pub fn unfreeze_users(
+        env: &Env,
+        caller: &Address,
+        users: &Vec<Address>,
+    ) -> Result<(), ProtocolError> {
         Self::ensure_can_manage(env, caller, UserRole::Manager)?;
+        if users.len() > MAX_FREEZE_BATCH {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        for user in users.iter() {
+            Self::apply_unfreeze(env, &user);
+        }
+        Ok(())
+    }
+
+    pub fn get_freeze_info(env: &Env, user: &Address) -> Option<FreezeInfo> {
+        Self::ensure_profile(env, user).freeze_info()
+    }
+
+    fn apply_freeze(
+        env: &Env,
+        caller: &Address,
+        user: &Address,
+        reason_code: u32,
+        case_ref: Option<BytesN<32>>,
+    ) {
         let mut profile = Self::ensure_profile(env, user);
         profile.is_frozen = true;
+        profile.freeze_reason_code = reason_code;
+        profile.freeze_case_ref = case_ref;
+        profile.frozen_by = Some(caller.clone());
+        profile.frozen_at = env.ledger().timestamp();
         Self::save_profile(env, &profile);
         env.events().publish(
             (
@@ -707,13 +858,11 @@ impl UserManager {
                 UserRole::Suspended.as_symbol(env),
             ),
         );
-        Ok(())
     }
 
-    pub fn unfreeze_user(env: &Env, caller: &Address, user: &Address) -> Result<(), ProtocolError> {
-        Self::ensure_can_manage(env, caller, UserRole::Manager)?;
+    fn apply_unfreeze(env: &Env, user: &Address) {
         let mut profile = Self::ensure_profile(env, user);
-        profile.is_frozen = false;
+        profile.clear_freeze();
         if profile.role == UserRole::Suspended {
             profile.role = UserRole::Standard;
         }
@@ -730,7 +879,24 @@ impl UserManager {
                 profile.role.as_symbol(env),
             ),
         );
-        Ok(())
+    }
+
+    /// Diagnostic event for an operation rejected because the user is frozen,
+    /// carrying the freeze reason so integrators can explain the rejection
+    fn emit_blocked(env: &Env, profile: &UserProfile, operation: OperationKind) {
+        env.events().publish(
+            (Symbol::new(env, "user_blocked"), Symbol::new(env, "user")),
+            (
+                Symbol::new(env, "user"),
+                profile.user.clone(),
+                Symbol::new(env, "operation"),
+                Self::operation_symbol(env, operation),
+                Symbol::new(env, "reason_code"),
+                profile.freeze_reason_code,
+                Symbol::new(env, "case_ref"),
+                profile.freeze_case_ref.clone(),
+            ),
+        );
     }
 
     fn operation_symbol(env: &Env, operation: OperationKind) -> Symbol {
@@ -766,6 +932,12 @@ pub struct EventRecord {
     pub asset: Option<Address>,
     pub amount: i128,
     pub timestamp: u64,
+    /// Position in the global event sequence, assigned when the record is
+    /// stored (starts at 1)
+    pub event_id: u64,
+    /// Ledger the event was emitted in, so indexers can detect records
+    /// from a ledger that was later replaced
+    pub ledger_seq: u32,
 }
 
 impl EventRecord {
@@ -784,6 +956,8 @@ impl EventRecord {
             asset,
             amount,
             timestamp: env.ledger().timestamp(),
+            event_id: 0,
+            ledger_seq: env.ledger().sequence(),
         }
     }
 }
@@ -848,6 +1022,37 @@ impl EventStorage {
         Symbol::new(env, "event_summary")
     }
 
+    fn feed_key(env: &Env) -> Symbol {
+        Symbol::new(env, "event_feed")
+    }
+
+    fn sequence_key(env: &Env) -> Symbol {
+        Symbol::new(env, "event_sequence")
+    }
+
+    /// Id of the most recently stored event (0 before any event)
+    pub fn last_event_id(env: &Env) -> u64 {
+        env.storage()
+            .instance()
+            .get(&Self::sequence_key(env))
+            .unwrap_or(0)
+    }
+
+    fn next_event_id(env: &Env) -> u64 {
+        let id = Self::last_event_id(env) + 1;
+        env.storage().instance().set(&Self::sequence_key(env), &id);
+        id
+    }
+
+    /// Latest events of every type in `event_id` order, capped at
+    /// [`capabilities::EVENT_FEED_CAP`]. Ids are contiguous within the feed.
+    pub fn get_feed(env: &Env) -> Vec<EventRecord> {
+        env.storage()
+            .instance()
+            .get(&Self::feed_key(env))
+            .unwrap_or_else(|| Vec::new(env))
+    }
+
     pub fn get_aggregates(env: &Env) -> Map<Symbol, EventAggregate> {
         env.storage()
             .instance()
@@ -886,14 +1091,24 @@ impl EventStorage {
     }
 
     pub fn append_event(env: &Env, record: &EventRecord) {
+        let mut record = record.clone();
+        record.event_id = Self::next_event_id(env);
+
+        let mut feed = Self::get_feed(env);
+        feed.push_back(record.clone());
+        if feed.len() > capabilities::EVENT_FEED_CAP {
+            feed = feed.slice(feed.len() - capabilities::EVENT_FEED_CAP..);
+        }
+        env.storage().instance().set(&Self::feed_key(env), &feed);
+
         let mut logs = Self::get_logs(env);
         let mut events = logs
             .get(record.event_type.clone())
             .unwrap_or_else(|| Vec::new(env));
         events.push_back(record.clone());
-        // Keep only the latest 32 events per type to cap storage use
-        if events.len() > 32 {
-            events = events.slice(events.len() - 32..);
+        // Keep only the latest events per type to cap storage use
+        if events.len() > capabilities::EVENT_LOG_CAP {
+            events = events.slice(events.len() - capabilities::EVENT_LOG_CAP..);
         }
         logs.set(record.event_type.clone(), events);
         Self::save_logs(env, &logs);
@@ -1277,6 +1492,11 @@ impl EventTracker {
                 user = Some(manager.clone());
                 amount = if *flag { 1 } else { 0 };
             }
+            ProtocolEvent::AccrualCatchUpPartial(_, remaining, _) => {
+                event_type = Symbol::new(env, "accrual_catch_up_partial");
+                topics = Self::base_topics(env, &event_type);
+                amount = *remaining as i128;
+            }
             _ => {}
         }
 
@@ -1305,6 +1525,17 @@ impl TokenRegistry {
             .set(&Self::registry_key(env), assets);
     }
 
+    fn order_key(env: &Env) -> Symbol {
+        Symbol::new(env, "token_registry_order")
+    }
+
+    fn order(env: &Env) -> Vec<Symbol> {
+        env.storage()
+            .instance()
+            .get(&Self::order_key(env))
+            .unwrap_or_else(|| Vec::new(env))
+    }
+
     fn primary_key(env: &Env) -> Symbol {
         Symbol::new(env, "primary_asset")
     }
@@ -1316,10 +1547,39 @@ impl TokenRegistry {
         token: Address,
     ) -> Result<(), ProtocolError> {
         ProtocolConfig::require_admin(env, caller)?;
+        Self::register(env, key, token);
+        Ok(())
+    }
+
+    /// Write a registry entry without an admin check (callers authorize)
+    pub(crate) fn register(env: &Env, key: Symbol, token: Address) {
         let mut assets = Self::assets(env);
+        if !assets.contains_key(key.clone()) {
+            let mut order = Self::order(env);
+            order.push_back(key.clone());
+            env.storage().instance().set(&Self::order_key(env), &order);
+        }
         assets.set(key, token);
         Self::save_assets(env, &assets);
-        Ok(())
+    }
+
+    /// Registered assets in first-registration order; entries written before
+    /// the order index existed follow in key order.
+    pub fn list_assets(env: &Env) -> Vec<(Symbol, Address)> {
+        let assets = Self::assets(env);
+        let order = Self::order(env);
+        let mut out = Vec::new(env);
+        for key in order.iter() {
+            if let Some(token) = assets.get(key.clone()) {
+                out.push_back((key, token));
+            }
+        }
+        for (key, token) in assets.iter() {
+            if !order.contains(&key) {
+                out.push_back((key, token));
+            }
+        }
+        out
     }
 
     pub fn get_asset(env: &Env, key: Symbol) -> Option<Address> {
@@ -1334,6 +1594,10 @@ impl TokenRegistry {
         Self::set_asset(env, caller, Self::primary_key(env), token)
     }
 
+    pub fn primary_asset(env: &Env) -> Option<Address> {
+        Self::get_asset(env, Self::primary_key(env))
+    }
+
     pub fn require_primary_asset(env: &Env) -> Result<Address, ProtocolError> {
         Self::get_asset(env, Self::primary_key(env)).ok_or(ProtocolError::AssetNotSupported)
     }
@@ -1352,6 +1616,47 @@ impl TransferEnforcer {
         env.current_contract_address()
     }
 
+    /// Move `amount` of `asset` from `from` to `to`, turning any failure in
+    /// the token contract (frozen or deauthorized account, short balance)
+    /// into [`ProtocolError::TokenTransferFailed`] with a `transfer_failure`
+    /// event instead of a host panic
+    pub(crate) fn transfer(
+        env: &Env,
+        asset: &Address,
+        from: &Address,
+        to: &Address,
+        amount: i128,
+        flow: Symbol,
+    ) -> Result<(), ProtocolError> {
+        Self::try_transfer(
+            env,
+            &TokenClient::new(env, asset),
+            asset,
+            from,
+            to,
+            amount,
+            &flow,
+        )
+    }
+
+    fn try_transfer(
+        env: &Env,
+        client: &TokenClient,
+        asset: &Address,
+        from: &Address,
+        to: &Address,
+        amount: i128,
+        flow: &Symbol,
+    ) -> Result<(), ProtocolError> {
+        match client.try_transfer(from, to, &amount) {
+            Ok(Ok(())) => Ok(()),
+            _ => {
+                Self::emit_failure(env, from, to, asset, amount, flow, "transfer_failed");
+                Err(ProtocolError::TokenTransferFailed)
+            }
+        }
+    }
+
     fn emit_attempt(
         env: &Env,
         from: &Address,
@@ -1479,7 +1784,7 @@ impl TransferEnforcer {
 
         Self::emit_attempt(env, user, &contract, &asset, amount, &flow);
 
-        client.transfer(user, &contract, &amount);
+        Self::try_transfer(env, &client, &asset, user, &contract, amount, &flow)?;
 
         let after_contract = client.balance(&contract);
         let after_user = client.balance(user);
@@ -1533,7 +1838,7 @@ impl TransferEnforcer {
 
         Self::emit_attempt(env, &contract, user, &asset, amount, &flow);
 
-        client.transfer(&contract, user, &amount);
+        Self::try_transfer(env, &client, &asset, &contract, user, amount, &flow)?;
 
         let after_contract = client.balance(&contract);
         let after_user = client.balance(user);
@@ -1758,6 +2063,7 @@ impl EmergencyManager {
         let key_rate_ceiling = Symbol::new(env, "rate_ceiling");
         let key_rate_floor = Symbol::new(env, "rate_floor");
         let key_flash_fee = Symbol::new(env, "flash_fee_bps");
+        let key_util_ema_alpha = Symbol::new(env, "util_ema_alpha_bps");
 
         if update.key == key_min_collateral {
             let admin = ProtocolConfig::get_admin(env).ok_or(ProtocolError::ConfigurationError)?;
@@ -1766,27 +2072,37 @@ impl EmergencyManager {
         }
 
         let mut config = InterestRateStorage::get_config(env);
-        if update.key == key_reserve_factor {
-            config.reserve_factor = update.value;
+        let (field, name) = if update.key == key_reserve_factor {
+            (&mut config.reserve_factor, "reserve_factor")
         } else if update.key == key_base_rate {
-            config.base_rate = update.value;
+            (&mut config.base_rate, "base_rate")
         } else if update.key == key_kink_util {
-            config.kink_utilization = update.value;
+            (&mut config.kink_utilization, "kink_utilization")
         } else if update.key == key_multiplier {
-            config.multiplier = update.value;
+            (&mut config.multiplier, "multiplier")
         } else if update.key == key_rate_ceiling {
-            config.rate_ceiling = update.value;
+            (&mut config.rate_ceiling, "rate_ceiling")
         } else if update.key == key_rate_floor {
-            config.rate_floor = update.value;
+            (&mut config.rate_floor, "rate_floor")
+        } else if update.key == key_util_ema_alpha {
+            if update.value <= 0 || update.value > 10000 {
+                return Err(ProtocolError::InvalidParameters);
+            }
+            (
+                &mut config.utilization_ema_alpha_bps,
+                "utilization_ema_alpha_bps",
+            )
         } else if update.key == key_flash_fee {
             let admin = ProtocolConfig::get_admin(env).ok_or(ProtocolError::ConfigurationError)?;
             ProtocolConfig::set_flash_loan_fee_bps(env, &admin, update.value)?;
             return Ok(());
         } else {
             return Err(ProtocolError::InvalidParameters);
-        }
+        };
+        let old = core::mem::replace(field, update.value);
 
         InterestRateStorage::save_config(env, &config);
+        config::record_change(env, "interest", name, None, old, update.value);
         Ok(())
     }
 
@@ -1822,6 +2138,16 @@ impl EmergencyManager {
         ProtocolEvent::EmergencyFundUpdated(caller.clone(), delta, reserve_delta).emit(env);
         Ok(())
     }
+
+    /// Credit protocol income already held by the contract to the insurance fund
+    pub(crate) fn credit_fund(env: &Env, token: &Address, amount: i128) {
+        let mut state = EmergencyStorage::get(env);
+        state.fund.balance += amount;
+        state.fund.token = Some(token.clone());
+        state.fund.last_update = env.ledger().timestamp();
+        EmergencyStorage::save(env, &state);
+        ProtocolEvent::EmergencyFundUpdated(env.current_contract_address(), amount, 0).emit(env);
+    }
 }
 
 /// Reentrancy guard for security
@@ -1831,7 +2157,23 @@ impl ReentrancyGuard {
     fn key(env: &Env) -> Symbol {
         Symbol::new(env, "reentrancy")
     }
+    /// Present while a batch holds the guard; true while one of its
+    /// operations is running
+    fn batch_key(env: &Env) -> Symbol {
+        Symbol::new(env, "reentrancy_batch")
+    }
     pub fn enter(env: &Env) -> Result<(), ProtocolError> {
+        if let Some(op_running) = env
+            .storage()
+            .instance()
+            .get::<Symbol, bool>(&Self::batch_key(env))
+        {
+            if op_running {
+                return Err(ProtocolError::ReentrancyDetected);
+            }
+            env.storage().instance().set(&Self::batch_key(env), &true);
+            return Ok(());
+        }
         let entered = env
             .storage()
             .instance()
@@ -1845,8 +2187,27 @@ impl ReentrancyGuard {
         Ok(())
     }
     pub fn exit(env: &Env) {
+        if env.storage().instance().has(&Self::batch_key(env)) {
+            env.storage().instance().set(&Self::batch_key(env), &false);
+            return;
+        }
         env.storage().instance().set(&Self::key(env), &false);
     }
+    /// Hold the guard for a batch of operations, each of which may enter it
+    /// once at a time
+    pub fn enter_batch(env: &Env) -> Result<(), ProtocolError> {
+        Self::enter(env)?;
+        env.storage().instance().set(&Self::batch_key(env), &false);
+        Ok(())
+    }
+    pub fn exit_batch(env: &Env) {
+        env.storage().instance().remove(&Self::batch_key(env));
+        Self::exit(env);
+    }
+    /// Whether a batch currently holds the guard
+    pub fn in_batch(env: &Env) -> bool {
+        env.storage().instance().has(&Self::batch_key(env))
+    }
 }
 
 /// RAII helper to ensure reentrancy guard exit on scope drop
@@ -1867,6 +2228,20 @@ impl<'a> Drop for ReentrancyScope<'a> {
     }
 }
 
+/// Execution deadlines for user-facing entrypoints
+pub struct Deadline;
+
+impl Deadline {
+    /// Fail with `DeadlineExceeded` once the ledger is past `deadline_ts`
+    /// (0 = no deadline). Call before any state change or transfer.
+    pub fn check(env: &Env, deadline_ts: u64) -> Result<(), ProtocolError> {
+        if deadline_ts > 0 && env.ledger().timestamp() > deadline_ts {
+            return Err(ProtocolError::DeadlineExceeded);
+        }
+        Ok(())
+    }
+}
+
 /// The main contract struct for StellarLend
 #[contract]
 pub struct Contract;
@@ -1925,6 +2300,9 @@ pub struct InterestRateConfig {
     pub smoothing_bps: i128,
     /// Volatility sensitivity in bps (impact of utilization change)
     pub util_sensitivity_bps: i128,
+    /// Weight in bps given to spot utilization per `UTILIZATION_EMA_PERIOD_SECS`
+    /// when updating the utilization EMA (1..=10000)
+    pub utilization_ema_alpha_bps: i128,
 }
 
 impl Default for InterestRateConfig {
@@ -1937,8 +2315,9 @@ impl Default for InterestRateConfig {
             rate_ceiling: 50000000,     // 50%
             rate_floor: 100000,         // 0.1%
             last_update: 0,
-            smoothing_bps: 2000,       // 20% smoothing by default
-            util_sensitivity_bps: 100, // 1% per 1% util change
+            smoothing_bps: 2000,             // 20% smoothing by default
+            util_sensitivity_bps: 100,       // 1% per 1% util change
+            utilization_ema_alpha_bps: 1000, // 10% per period
         }
     }
 }
@@ -1961,6 +2340,10 @@ pub struct InterestRateState {
     pub last_accrual_time: u64,
     /// Smoothed borrow rate
     pub smoothed_borrow_rate: i128,
+    /// Exponential moving average of utilization (scaled by 1e8), used for rates
+    pub utilization_ema: i128,
+    /// Last time the utilization EMA was updated (0 = not yet seeded)
+    pub utilization_ema_updated_at: u64,
 }
 
 impl InterestRateState {
@@ -1974,10 +2357,15 @@ impl InterestRateState {
             total_supplied: 0,
             last_accrual_time: 0,
             smoothed_borrow_rate: 0,
+            utilization_ema: 0,
+            utilization_ema_updated_at: 0,
         }
     }
 }
 
+/// Period over which `utilization_ema_alpha_bps` applies in full
+pub const UTILIZATION_EMA_PERIOD_SECS: u64 = 60 * 60;
+
 /// Risk management configuration
 #[derive(Clone, Debug, Eq, PartialEq)]
 #[contracttype]
@@ -1986,11 +2374,16 @@ pub struct RiskConfig {
     pub close_factor: i128,
     /// % bonus collateral given to liquidators (scaled by 1e8)
     pub liquidation_incentive: i128,
+    /// Share of the repaid amount, in bps, taken out of the bonus and sent to
+    /// the insurance fund; never exceeds `liquidation_incentive`
+    pub liquidation_protocol_fee_bps: i128,
     /// Pause switches for protocol actions
     pub pause_borrow: bool,
     pub pause_deposit: bool,
     pub pause_withdraw: bool,
     pub pause_liquidate: bool,
+    /// Apply credit score band discounts to borrow accrual
+    pub credit_discounts_enabled: bool,
     /// Last time config was updated
     pub last_update: u64,
 }
@@ -2007,6 +2400,11 @@ impl RiskConfig {
             _ => Ok(()),
         }
     }
+
+    /// Whether a protocol fee of `fee_bps` fits inside `liquidation_incentive`
+    pub fn protocol_fee_fits(&self, fee_bps: i128) -> bool {
+        fee_bps >= 0 && fee_bps * 10_000 <= self.liquidation_incentive
+    }
 }
 
 impl Default for RiskConfig {
@@ -2014,10 +2412,12 @@ impl Default for RiskConfig {
         Self {
             close_factor: 50000000,          // 50%
             liquidation_incentive: 10000000, // 10%
+            liquidation_protocol_fee_bps: 0,
             pause_borrow: false,
             pause_deposit: false,
             pause_withdraw: false,
             pause_liquidate: false,
+            credit_discounts_enabled: false,
             last_update: 0,
         }
     }
@@ -2040,6 +2440,35 @@ impl RiskConfigStorage {
             .get(&Self::key(env))
             .unwrap_or_default()
     }
+
+    fn max_spread_key(env: &Env) -> Symbol {
+        Symbol::new(env, "risk_max_spread_bps")
+    }
+
+    /// Widest disagreement between oracle sources, in bps of the median,
+    /// that borrows and liquidations act on; 0 disables the check
+    pub fn get_max_price_spread_bps(env: &Env) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::max_spread_key(env))
+            .unwrap_or(defaults::MAX_PRICE_SPREAD_BPS)
+    }
+
+    pub fn set_max_price_spread_bps(env: &Env, bps: i128) -> Result<(), ProtocolError> {
+        if bps < 0 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        config::set_param(
+            env,
+            "risk",
+            "max_price_spread_bps",
+            &Self::max_spread_key(env),
+            None,
+            defaults::MAX_PRICE_SPREAD_BPS,
+            bps,
+        );
+        Ok(())
+    }
 }
 
 /// Interest rate storage helper
@@ -2076,8 +2505,20 @@ impl InterestRateStorage {
             .unwrap_or_else(InterestRateState::initial)
     }
 
+    /// Apply supplied/borrowed principal deltas to the global totals
+    pub fn adjust_totals(env: &Env, supplied_delta: i128, borrowed_delta: i128) {
+        let mut state = Self::get_state(env);
+        twa::TimeWeighted::touch(env, &state);
+        state.total_supplied = state.total_supplied.saturating_add(supplied_delta);
+        state.total_borrowed = state.total_borrowed.saturating_add(borrowed_delta);
+        Self::save_state(env, &state);
+    }
+
     pub fn update_state(env: &Env) -> InterestRateState {
+        // Advance the global index first, at the rate in effect since the last update
+        accrual::AccrualManager::accrue(env);
         let mut state = Self::get_state(env);
+        twa::TimeWeighted::touch(env, &state);
         let config = Self::get_config(env);
 
         // Units and scales:
@@ -2095,8 +2536,29 @@ impl InterestRateStorage {
             state.utilization_rate = 0;
         }
 
-        // Calculate borrow rate based on utilization
-        let u = state.utilization_rate.clamp(0, 100000000);
+        // Fold spot utilization into the EMA, weighted by the time since the last
+        // update so a single-block spike barely registers while long gaps
+        // converge toward spot
+        let now = env.ledger().timestamp();
+        if state.utilization_ema_updated_at == 0 {
+            state.utilization_ema = state.utilization_rate;
+        } else {
+            let elapsed = now.saturating_sub(state.utilization_ema_updated_at) as i128;
+            let weight = config
+                .utilization_ema_alpha_bps
+                .saturating_mul(elapsed)
+                .saturating_div(UTILIZATION_EMA_PERIOD_SECS as i128)
+                .clamp(0, 10000);
+            state.utilization_ema = state
+                .utilization_rate
+                .saturating_mul(weight)
+                .saturating_add(state.utilization_ema.saturating_mul(10000 - weight))
+                .saturating_div(10000);
+        }
+        state.utilization_ema_updated_at = now;
+
+        // Calculate borrow rate based on smoothed utilization
+        let u = state.utilization_ema.clamp(0, 100000000);
         if u <= config.kink_utilization {
             state.current_borrow_rate = config
                 .base_rate
@@ -2176,8 +2638,10 @@ impl InterestRateManager {
         let br = borrow_rate.clamp(0, SCALE);
         let sr = supply_rate.clamp(0, SCALE);
 
-        // Accrue borrow interest
+        // Accrue borrow interest, less any credit score discount
         if position.debt > 0 {
+            let discount = credit::Credit::discount_bps(env, position);
+            let br = br * (10_000 - discount) / 10_000;
             let numerator = position
                 .debt
                 .saturating_mul(br)
@@ -2214,18 +2678,68 @@ impl InterestRateManager {
 pub struct StateHelper;
 
 impl StateHelper {
-    fn position_key(env: &Env, _user: &Address) -> Symbol {
-        Symbol::new(env, &format!("position_{}", "user"))
+    pub(crate) fn position_key(env: &Env, user: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "position"), user.clone())
+    }
+
+    fn position_index_key(env: &Env) -> Symbol {
+        Symbol::new(env, "position_index")
+    }
+
+    fn indexed_marker_key(env: &Env, user: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "position_indexed"), user.clone())
+    }
+
+    /// Every user that has ever had a position saved, in first-save order
+    pub fn get_position_index(env: &Env) -> Vec<Address> {
+        env.storage()
+            .instance()
+            .get(&Self::position_index_key(env))
+            .unwrap_or_else(|| Vec::new(env))
+    }
+
+    fn index_position(env: &Env, user: &Address) {
+        let marker = Self::indexed_marker_key(env, user);
+        if env.storage().instance().has(&marker) {
+            return;
+        }
+        let mut index = Self::get_position_index(env);
+        index.push_back(user.clone());
+        env.storage()
+            .instance()
+            .set(&Self::position_index_key(env), &index);
+        env.storage().instance().set(&marker, &true);
     }
 
     pub fn save_position(env: &Env, position: &Position) {
         let key = Self::position_key(env, &position.user);
         env.storage().instance().set(&key, position);
+        Self::index_position(env, &position.user);
+        credit::Credit::sample_buffer(env, position);
+        alerts::Alerts::evaluate(env, position);
+        analytics::AnalyticsModule::update_collateral_exposure(env, position);
+        if let Some(asset) = TokenRegistry::primary_asset(env) {
+            analytics::AnalyticsModule::update_size_distribution(
+                env,
+                &asset,
+                &position.user,
+                position.collateral,
+                position.debt,
+            );
+            incentives::Incentives::on_balance_change(
+                env,
+                &asset,
+                &position.user,
+                position.collateral,
+            );
+        }
     }
 
     pub fn get_position(env: &Env, user: &Address) -> Option<Position> {
         let key = Self::position_key(env, user);
-        env.storage().instance().get::<Symbol, Position>(&key)
+        env.storage()
+            .instance()
+            .get::<(Symbol, Address), Position>(&key)
     }
 }
 
@@ -2249,6 +2763,98 @@ impl ProtocolConfig {
         Symbol::new(env, "flash_fee_bps")
     }
 
+    fn treasury_key(env: &Env) -> Symbol {
+        Symbol::new(env, "treasury")
+    }
+
+    fn origination_buffer_key(env: &Env) -> Symbol {
+        Symbol::new(env, "origination_buffer_bps")
+    }
+
+    fn liquidation_threshold_key(env: &Env) -> Symbol {
+        Symbol::new(env, "liq_threshold_bps")
+    }
+
+    pub fn get_treasury(env: &Env) -> Option<Address> {
+        env.storage().instance().get(&Self::treasury_key(env))
+    }
+
+    /// Set the initial treasury (admin only). Once set, changes go through
+    /// the timelocked role change flow.
+    pub fn set_treasury(
+        env: &Env,
+        caller: &Address,
+        treasury: &Address,
+    ) -> Result<(), ProtocolError> {
+        Self::require_admin(env, caller)?;
+        if Self::get_treasury(env).is_some() {
+            return Err(ProtocolError::InvalidOperation);
+        }
+        Self::store_treasury(env, treasury);
+        history::ParamHistory::record(
+            env,
+            Self::treasury_key(env),
+            "set",
+            caller,
+            Some(treasury.clone()),
+            None,
+        );
+        Ok(())
+    }
+
+    pub(crate) fn store_treasury(env: &Env, treasury: &Address) {
+        config::set_address_param(
+            env,
+            "protocol",
+            "treasury",
+            &Self::treasury_key(env),
+            None,
+            treasury,
+        );
+    }
+
+    fn guardian_key(env: &Env) -> Symbol {
+        Symbol::new(env, "guardian")
+    }
+
+    pub fn get_guardian(env: &Env) -> Option<Address> {
+        env.storage().instance().get(&Self::guardian_key(env))
+    }
+
+    /// Set the initial guardian (admin only). Once set, changes go through
+    /// the timelocked role change flow.
+    pub fn set_guardian(
+        env: &Env,
+        caller: &Address,
+        guardian: &Address,
+    ) -> Result<(), ProtocolError> {
+        Self::require_admin(env, caller)?;
+        if Self::get_guardian(env).is_some() {
+            return Err(ProtocolError::InvalidOperation);
+        }
+        Self::store_guardian(env, guardian);
+        history::ParamHistory::record(
+            env,
+            Self::guardian_key(env),
+            "set",
+            caller,
+            Some(guardian.clone()),
+            None,
+        );
+        Ok(())
+    }
+
+    pub(crate) fn store_guardian(env: &Env, guardian: &Address) {
+        config::set_address_param(
+            env,
+            "protocol",
+            "guardian",
+            &Self::guardian_key(env),
+            None,
+            guardian,
+        );
+    }
+
     pub fn set_admin(env: &Env, admin: &Address) {
         env.storage().instance().set(&Self::admin_key(env), admin);
     }
@@ -2269,10 +2875,19 @@ impl ProtocolConfig {
 
     pub fn set_oracle(env: &Env, caller: &Address, oracle: &Address) -> Result<(), ProtocolError> {
         Self::require_admin(env, caller)?;
-        env.storage().instance().set(&Self::oracle_key(env), oracle);
+        config::set_address_param(
+            env,
+            "protocol",
+            "oracle",
+            &Self::oracle_key(env),
+            None,
+            oracle,
+        );
         Ok(())
     }
 
+    /// Set the loan-to-value bound, as a minimum collateral ratio in percent.
+    /// It may not drop below a configured liquidation threshold.
     pub fn set_min_collateral_ratio(
         env: &Env,
         caller: &Address,
@@ -2282,9 +2897,20 @@ impl ProtocolConfig {
         if ratio <= 0 {
             return Err(ProtocolError::InvalidInput);
         }
-        env.storage()
-            .instance()
-            .set(&Self::min_collateral_ratio_key(env), &ratio);
+        if Self::get_stored_liquidation_threshold_bps(env)
+            .is_some_and(|threshold| ratio.saturating_mul(100) < threshold)
+        {
+            return Err(ProtocolError::InvalidInput);
+        }
+        config::set_param(
+            env,
+            "protocol",
+            "min_ratio",
+            &Self::min_collateral_ratio_key(env),
+            None,
+            defaults::MIN_COLLATERAL_RATIO,
+            ratio,
+        );
         Ok(())
     }
 
@@ -2292,7 +2918,7 @@ impl ProtocolConfig {
         env.storage()
             .instance()
             .get::<Symbol, i128>(&Self::min_collateral_ratio_key(env))
-            .unwrap_or(150)
+            .unwrap_or(defaults::MIN_COLLATERAL_RATIO)
     }
 
     pub fn set_flash_loan_fee_bps(
@@ -2304,9 +2930,15 @@ impl ProtocolConfig {
         if !(0..=10000).contains(&bps) {
             return Err(ProtocolError::InvalidInput);
         }
-        env.storage()
-            .instance()
-            .set(&Self::flash_fee_bps_key(env), &bps);
+        config::set_param(
+            env,
+            "protocol",
+            "flash_fee_bps",
+            &Self::flash_fee_bps_key(env),
+            None,
+            defaults::FLASH_LOAN_FEE_BPS,
+            bps,
+        );
         Ok(())
     }
 
@@ -2314,51 +2946,91 @@ impl ProtocolConfig {
         env.storage()
             .instance()
             .get::<Symbol, i128>(&Self::flash_fee_bps_key(env))
-            .unwrap_or(5) // 0.05%
-    }
-}
-
-/// Protocol errors
-#[contracterror]
-#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
-#[repr(u32)]
-pub enum ProtocolError {
-    Unauthorized = 1,
-    InsufficientCollateral = 2,
-    InsufficientCollateralRatio = 3,
-    InvalidAmount = 4,
-    InvalidAddress = 5,
-    PositionNotFound = 6,
-    AlreadyInitialized = 7,
-    NotInitialized = 8,
-    InvalidInput = 9,
-    NotEligibleForLiquidation = 10,
-    ProtocolPaused = 11,
-    AssetNotSupported = 12,
-    OracleFailure = 13,
-    ReentrancyDetected = 14,
-    StorageError = 15,
-    ConfigurationError = 16,
-    NotFound = 17,
-    AlreadyExists = 18,
-    InvalidOperation = 19,
-    RecoveryFailed = 20,
-    InvalidParameters = 21,
-    StorageLimitExceeded = 22,
-    RecoveryModeRestricted = 23,
-    EmergencyFundInsufficient = 24,
-    UserNotVerified = 25,
-    UserSuspended = 26,
-    UserLimitExceeded = 27,
-    UserRoleViolation = 28,
-    BalanceInvariantViolation = 29,
-    InsufficientLiquidity = 30,
-    SlippageProtectionTriggered = 31,
-}
-
-/// Protocol events
+            .unwrap_or(defaults::FLASH_LOAN_FEE_BPS)
+    }
+
+    /// Extra collateral ratio (in bps) required only when a borrow increases debt
+    pub fn set_origination_buffer_bps(
+        env: &Env,
+        caller: &Address,
+        bps: i128,
+    ) -> Result<(), ProtocolError> {
+        Self::require_admin(env, caller)?;
+        if !(0..=10000).contains(&bps) {
+            return Err(ProtocolError::InvalidInput);
+        }
+        config::set_param(
+            env,
+            "protocol",
+            "origination_buffer_bps",
+            &Self::origination_buffer_key(env),
+            None,
+            defaults::ORIGINATION_BUFFER_BPS,
+            bps,
+        );
+        Ok(())
+    }
+
+    pub fn get_origination_buffer_bps(env: &Env) -> i128 {
+        env.storage()
+            .instance()
+            .get::<Symbol, i128>(&Self::origination_buffer_key(env))
+            .unwrap_or(defaults::ORIGINATION_BUFFER_BPS)
+    }
+
+    /// Loan-to-value bound in bps, expressed like every ratio here as
+    /// collateral over debt: withdrawals must keep the position at or above
+    /// it, and borrows above it plus the origination buffer
+    pub fn get_ltv_bps(env: &Env) -> i128 {
+        Self::get_min_collateral_ratio(env) * 100
+    }
+
+    /// Set the collateral ratio in bps below which a position may be
+    /// liquidated (admin only). It must not exceed the LTV bound, so there
+    /// is a band where borrowing is blocked but liquidation is not allowed.
+    pub fn set_liquidation_threshold_bps(
+        env: &Env,
+        caller: &Address,
+        bps: i128,
+    ) -> Result<(), ProtocolError> {
+        Self::require_admin(env, caller)?;
+        if bps <= 0 || bps > Self::get_ltv_bps(env) {
+            return Err(ProtocolError::InvalidInput);
+        }
+        config::set_param(
+            env,
+            "protocol",
+            "liquidation_threshold_bps",
+            &Self::liquidation_threshold_key(env),
+            None,
+            Self::get_liquidation_threshold_bps(env),
+            bps,
+        );
+        Ok(())
+    }
+
+    fn get_stored_liquidation_threshold_bps(env: &Env) -> Option<i128> {
+        env.storage()
+            .instance()
+            .get::<Symbol, i128>(&Self::liquidation_threshold_key(env))
+    }
+
+    /// Collateral ratio in bps below which a position may be liquidated;
+    /// follows the LTV bound until configured
+    pub fn get_liquidation_threshold_bps(env: &Env) -> i128 {
+        Self::get_stored_liquidation_threshold_bps(env).unwrap_or_else(|| Self::get_ltv_bps(env))
+    }
+
+    /// Origination threshold in bps: the LTV bound plus the origination buffer
+    pub fn get_origination_ratio_bps(env: &Env) -> i128 {
+        Self::get_ltv_bps(env) + Self::get_origination_buffer_bps(env)
+    }
+}
+
+/// Protocol events. Each variant is published field by field in
+/// [`ProtocolEvent::emit`] and never stored, so the enum is not a
+/// `#[contracttype]` and is not bound by its variant limit.
 #[derive(Clone, Debug, Eq, PartialEq)]
-#[contracttype]
 pub enum ProtocolEvent {
     PositionUpdated(Address, i128, i128, i128), // user, collateral, debt, collateral_ratio
     InterestAccrued(Address, i128, i128),       // user, borrow_interest, supply_interest
@@ -2428,6 +3100,8 @@ pub enum ProtocolEvent {
     EmergencyParamUpdateApplied(Symbol, i128),
     EmergencyFundUpdated(Address, i128, i128),
     EmergencyManagerUpdated(Address, bool),
+    // Accrual
+    AccrualCatchUpPartial(u64, u64, i128), // accrued_to, remaining_secs, borrow_index
 }
 
 impl ProtocolEvent {
@@ -2652,6 +3326,22 @@ impl ProtocolEvent {
                     ),
                 );
             }
+            ProtocolEvent::AccrualCatchUpPartial(accrued_to, remaining, borrow_index) => {
+                env.events().publish(
+                    (
+                        Symbol::new(env, "accrual_catch_up_partial"),
+                        Symbol::new(env, "accrual"),
+                    ),
+                    (
+                        Symbol::new(env, "accrued_to"),
+                        *accrued_to,
+                        Symbol::new(env, "remaining_secs"),
+                        *remaining,
+                        Symbol::new(env, "borrow_index"),
+                        *borrow_index,
+                    ),
+                );
+            }
             ProtocolEvent::FlashLoanInitiated(initiator, asset, amount, fee) => {
                 env.events().publish(
                     (
@@ -3021,438 +3711,3357 @@ pub fn deposit_collateral(env: Env, depositor: String, amount: i128) -> Result<(
     risk_config.ensure_not_paused(OperationKind::Deposit)?;
 
     let depositor_addr = AddressHelper::require_valid_address(&env, &depositor)?;
+    beta::BetaAccess::require_beta_access(&env, &depositor_addr)?;
+    diagnostics::CallTracker::track_call(&env, "deposit_collateral", &depositor_addr);
     deposit::DepositModule::deposit_collateral(&env, &depositor_addr, amount)
 }
 
-pub fn borrow(env: Env, borrower: String, amount: i128) -> Result<(), ProtocolError> {
+pub fn borrow(
+    env: Env,
+    borrower: String,
+    amount: i128,
+    deadline_ts: u64,
+) -> Result<(), ProtocolError> {
+    Deadline::check(&env, deadline_ts)?;
     // Check pause state first
     let risk_config = RiskConfigStorage::get(&env);
     risk_config.ensure_not_paused(OperationKind::Borrow)?;
 
     let borrower_addr = AddressHelper::require_valid_address(&env, &borrower)?;
+    beta::BetaAccess::require_beta_access(&env, &borrower_addr)?;
+    diagnostics::CallTracker::track_call(&env, "borrow", &borrower_addr);
     borrow::BorrowModule::borrow(&env, &borrower_addr, amount)
 }
 
+pub fn borrow_asset(
+    env: Env,
+    borrower: String,
+    asset: Address,
+    amount: i128,
+    deadline_ts: u64,
+) -> Result<(), ProtocolError> {
+    Deadline::check(&env, deadline_ts)?;
+    let risk_config = RiskConfigStorage::get(&env);
+    risk_config.ensure_not_paused(OperationKind::Borrow)?;
+    let borrower_addr = AddressHelper::require_valid_address(&env, &borrower)?;
+    beta::BetaAccess::require_beta_access(&env, &borrower_addr)?;
+    diagnostics::CallTracker::track_call(&env, "borrow_asset", &borrower_addr);
+    borrow::BorrowModule::borrow_asset(&env, &borrower, &asset, amount)
+}
+
+pub fn set_borrow_cap(
+    env: Env,
+    caller: String,
+    asset: Address,
+    cap: i128,
+) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    caps::CapManager::set_borrow_cap(&env, &caller_addr, &asset, cap)
+}
+
+pub fn set_cap_group(
+    env: Env,
+    caller: String,
+    group_id: Symbol,
+    members: Vec<Address>,
+    usd_borrow_cap: i128,
+) -> Result<caps::CapGroup, ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    caps::CapManager::set_cap_group(&env, &caller_addr, group_id, members, usd_borrow_cap)
+}
+
+pub fn set_borrow_surge_limit(
+    env: Env,
+    caller: String,
+    asset: Option<Address>,
+    limit: i128,
+) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    surge::BorrowSurge::set_limit(&env, &caller_addr, asset, limit)
+}
+
+pub fn clear_borrow_surge(
+    env: Env,
+    caller: String,
+    asset: Option<Address>,
+) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    surge::BorrowSurge::clear(&env, &caller_addr, asset)
+}
+
+pub fn get_cap_group(env: Env, group_id: Symbol) -> Option<caps::CapGroup> {
+    caps::CapStorage::get_group(&env, &group_id)
+}
+
 pub fn repay(env: Env, repayer: String, amount: i128) -> Result<(), ProtocolError> {
     // Check pause state first
     let risk_config = RiskConfigStorage::get(&env);
     risk_config.ensure_not_paused(OperationKind::Repay)?;
     let repayer_addr = AddressHelper::require_valid_address(&env, &repayer)?;
+    diagnostics::CallTracker::track_call(&env, "repay", &repayer_addr);
     repay::RepayModule::repay(&env, &repayer_addr, amount)
 }
 
-pub fn withdraw(env: Env, withdrawer: String, amount: i128) -> Result<(), ProtocolError> {
+pub fn withdraw(
+    env: Env,
+    withdrawer: String,
+    amount: i128,
+    deadline_ts: u64,
+) -> Result<(), ProtocolError> {
+    Deadline::check(&env, deadline_ts)?;
     // Check pause state first
     let risk_config = RiskConfigStorage::get(&env);
     risk_config.ensure_not_paused(OperationKind::Withdraw)?;
     let withdrawer_addr = AddressHelper::require_valid_address(&env, &withdrawer)?;
+    diagnostics::CallTracker::track_call(&env, "withdraw", &withdrawer_addr);
     withdraw::WithdrawModule::withdraw(&env, &withdrawer_addr, amount)
 }
 
+pub fn multicall(
+    env: Env,
+    caller: String,
+    ops: Vec<multicall::UserOp>,
+    deadline_ts: u64,
+) -> Result<(), ProtocolError> {
+    Deadline::check(&env, deadline_ts)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    diagnostics::CallTracker::track_call(&env, "multicall", &caller_addr);
+    multicall::Multicall::execute(&env, &caller_addr, &ops)
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn liquidate(
     env: Env,
     liquidator: String,
     user: String,
     amount: i128,
     min_out: i128,
-) -> Result<(), ProtocolError> {
+    receive_as_shares: bool,
+    deadline_ts: u64,
+    acknowledge_override: bool,
+) -> Result<liquidate::LiquidationResult, ProtocolError> {
+    Deadline::check(&env, deadline_ts)?;
     // Check pause state first
     let risk_config = RiskConfigStorage::get(&env);
     risk_config.ensure_not_paused(OperationKind::Liquidate)?;
     let liquidator_addr = AddressHelper::require_valid_address(&env, &liquidator)?;
+    diagnostics::CallTracker::track_call(&env, "liquidate", &liquidator_addr);
     UserManager::ensure_operation_allowed(
         &env,
         &liquidator_addr,
         OperationKind::Liquidate,
         amount,
     )?;
-    liquidate::LiquidationModule::liquidate(&env, &liquidator, &user, amount, min_out)?;
+    let mut result = liquidate::LiquidationModule::liquidate(
+        &env,
+        &liquidator,
+        &user,
+        amount,
+        min_out,
+        receive_as_shares,
+        acknowledge_override,
+    )?;
+    result.deadline_ts = deadline_ts;
     UserManager::record_activity(&env, &liquidator_addr, OperationKind::Liquidate, amount)?;
-    Ok(())
+    Ok(result)
 }
 
-pub fn get_position(env: Env, user: String) -> Result<(i128, i128, i128), ProtocolError> {
-    let user_addr = AddressHelper::require_valid_address(&env, &user)?;
-    match StateHelper::get_position(&env, &user_addr) {
-        Some(position) => {
-            let collateral_ratio = if position.debt > 0 {
-                (position.collateral * 100) / position.debt
-            } else {
-                0
-            };
-            Ok((position.collateral, position.debt, collateral_ratio))
-        }
-        None => Err(ProtocolError::PositionNotFound),
+#[allow(clippy::too_many_arguments)]
+pub fn liquidate_exact_collateral(
+    env: Env,
+    liquidator: String,
+    borrower: String,
+    collateral_asset: Address,
+    collateral_amount: i128,
+    debt_asset: Address,
+    max_repay: i128,
+    acknowledge_override: bool,
+) -> Result<liquidate::LiquidationResult, ProtocolError> {
+    let borrower_addr = AddressHelper::require_valid_address(&env, &borrower)?;
+    let quote = liquidate::LiquidationModule::quote_for_collateral(
+        &env,
+        &borrower_addr,
+        &collateral_asset,
+        &debt_asset,
+        collateral_amount,
+    )?;
+    if quote.debt_repaid > max_repay {
+        return Err(ProtocolError::SlippageProtectionTriggered);
+    }
+    let result = liquidate(
+        env,
+        liquidator,
+        borrower,
+        quote.debt_repaid,
+        0,
+        false,
+        0,
+        acknowledge_override,
+    )?;
+    // `liquidate` prices the seizure again; it must stay within the request
+    if result.collateral_seized > collateral_amount {
+        return Err(ProtocolError::SlippageProtectionTriggered);
     }
+    Ok(result)
 }
 
-pub fn set_risk_params(
+pub fn preview_liquidation(
     env: Env,
-    caller: String,
-    close_factor: i128,
-    liquidation_incentive: i128,
-) -> Result<(), ProtocolError> {
-    let _guard = ReentrancyScope::enter(&env)?;
-    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
-    ProtocolConfig::require_admin(&env, &caller_addr)?;
-
-    let mut config = RiskConfigStorage::get(&env);
-    config.close_factor = close_factor;
-    config.liquidation_incentive = liquidation_incentive;
-    config.last_update = env.ledger().timestamp();
-    RiskConfigStorage::save(&env, &config);
-
-    ProtocolEvent::RiskParamsUpdated(close_factor, liquidation_incentive).emit(&env);
-    Ok(())
+    user: Address,
+    amount: i128,
+) -> Result<liquidate::LiquidationQuote, ProtocolError> {
+    liquidate::LiquidationModule::preview(&env, &user, amount)
 }
 
-pub fn set_pause_switches(
+pub fn set_liquidation_protocol_fee(
     env: Env,
     caller: String,
-    pause_borrow: bool,
-    pause_deposit: bool,
-    pause_withdraw: bool,
-    pause_liquidate: bool,
+    fee_bps: i128,
+    change_reason: Option<Symbol>,
 ) -> Result<(), ProtocolError> {
-    let _guard = ReentrancyScope::enter(&env)?;
     let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    let _reason = reasons::ChangeReason::enter(
+        &env,
+        &caller_addr,
+        "liquidation_protocol_fee",
+        change_reason,
+    )?;
     ProtocolConfig::require_admin(&env, &caller_addr)?;
-
     let mut config = RiskConfigStorage::get(&env);
-    config.pause_borrow = pause_borrow;
-    config.pause_deposit = pause_deposit;
-    config.pause_withdraw = pause_withdraw;
-    config.pause_liquidate = pause_liquidate;
+    if !config.protocol_fee_fits(fee_bps) {
+        return Err(ProtocolError::InvalidParameters);
+    }
+    let old = core::mem::replace(&mut config.liquidation_protocol_fee_bps, fee_bps);
     config.last_update = env.ledger().timestamp();
     RiskConfigStorage::save(&env, &config);
-
-    ProtocolEvent::PauseSwitchesUpdated(
-        pause_borrow,
-        pause_deposit,
-        pause_withdraw,
-        pause_liquidate,
-    )
-    .emit(&env);
+    config::record_change(
+        &env,
+        "risk",
+        "liquidation_protocol_fee_bps",
+        None,
+        old,
+        fee_bps,
+    );
     Ok(())
 }
 
-pub fn get_protocol_params(
+pub fn set_emergency_price(
     env: Env,
-) -> Result<(i128, i128, i128, i128, i128, i128), ProtocolError> {
-    let config = InterestRateStorage::get_config(&env);
-    let risk_config = RiskConfigStorage::get(&env);
-
-    Ok((
-        config.base_rate,                  // 2000000 (2%)
-        config.kink_utilization,           // 80000000 (80%)
-        config.multiplier,                 // 10000000 (10x)
-        config.reserve_factor,             // 10000000 (10%)
-        risk_config.close_factor,          // 50000000 (50%)
-        risk_config.liquidation_incentive, // 10000000 (10%)
-    ))
+    caller: String,
+    asset: Address,
+    price: i128,
+    expires_at: u64,
+) -> Result<oracle::EmergencyPrice, ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    oracle::Oracle::set_emergency_price(&env, &caller_addr, &asset, price, expires_at)
 }
 
-pub fn get_risk_config(env: Env) -> Result<(i128, i128, bool, bool, bool, bool), ProtocolError> {
-    let config = RiskConfigStorage::get(&env);
-    Ok((
-        config.close_factor,
-        config.liquidation_incentive,
-        config.pause_borrow,
-        config.pause_deposit,
-        config.pause_withdraw,
-        config.pause_liquidate,
-    ))
+pub fn set_oracle_price_route(
+    env: Env,
+    caller: String,
+    base: Address,
+    quote: Address,
+    via: Option<Address>,
+) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    oracle::Oracle::set_price_route(&env, &caller_addr, &base, &quote, via)
 }
 
-pub fn get_system_stats(env: Env) -> Result<(i128, i128, i128, i128), ProtocolError> {
-    let state = InterestRateStorage::get_state(&env);
-
-    Ok((
-        state.total_supplied,
-        state.total_borrowed,
-        state.utilization_rate,
-        0, // active_users - simplified for now
-    ))
+pub fn set_oracle_reading_bounds(
+    env: Env,
+    caller: String,
+    asset: Address,
+    min: i128,
+    max: i128,
+) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    oracle::Oracle::set_reading_bounds(&env, &caller_addr, &asset, min, max)
 }
 
-pub fn set_emergency_manager(
+pub fn set_price_bounds(
     env: Env,
     caller: String,
-    manager: String,
-    enabled: bool,
+    asset: Address,
+    min: i128,
+    max: i128,
 ) -> Result<(), ProtocolError> {
-    let _guard = ReentrancyScope::enter(&env)?;
     let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
-    let manager_addr = AddressHelper::require_valid_address(&env, &manager)?;
-    EmergencyManager::set_manager(&env, &caller_addr, &manager_addr, enabled)
+    oracle::Oracle::set_price_bounds(&env, &caller_addr, &asset, min, max)
 }
 
-pub fn trigger_emergency_pause(
+pub fn freeze_oracle(
     env: Env,
     caller: String,
-    reason: Option<String>,
-) -> Result<(), ProtocolError> {
-    let _guard = ReentrancyScope::enter(&env)?;
+    asset: Option<Address>,
+    change_reason: Option<Symbol>,
+) -> Result<oracle::OracleFreeze, ProtocolError> {
     let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
-    EmergencyManager::pause(&env, &caller_addr, reason)
+    let _reason = reasons::ChangeReason::enter(&env, &caller_addr, "freeze_oracle", change_reason)?;
+    oracle::Oracle::freeze(&env, &caller_addr, asset)
 }
 
-pub fn enter_recovery_mode(
+pub fn unfreeze_oracle(
     env: Env,
     caller: String,
-    plan: Option<String>,
+    asset: Option<Address>,
+    change_reason: Option<Symbol>,
 ) -> Result<(), ProtocolError> {
-    let _guard = ReentrancyScope::enter(&env)?;
     let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
-    EmergencyManager::enter_recovery(&env, &caller_addr, plan)
+    let _reason =
+        reasons::ChangeReason::enter(&env, &caller_addr, "unfreeze_oracle", change_reason)?;
+    oracle::Oracle::unfreeze(&env, &caller_addr, asset)
 }
 
-pub fn resume_operations(env: Env, caller: String) -> Result<(), ProtocolError> {
-    let _guard = ReentrancyScope::enter(&env)?;
+pub fn set_oracle_max_freeze(env: Env, caller: String, secs: u64) -> Result<(), ProtocolError> {
     let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
-    EmergencyManager::resume(&env, &caller_addr)
+    oracle::Oracle::set_max_freeze_secs(&env, &caller_addr, secs)
 }
 
-pub fn record_recovery_step(env: Env, caller: String, step: String) -> Result<(), ProtocolError> {
-    let _guard = ReentrancyScope::enter(&env)?;
+pub fn set_oracle_twap_buffer_len(env: Env, caller: String, len: u32) -> Result<(), ProtocolError> {
     let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
-    EmergencyManager::record_recovery_step(&env, &caller_addr, step)
+    oracle::Oracle::set_twap_buffer_len(&env, &caller_addr, len)
 }
 
-pub fn queue_emergency_param_update(
+pub fn set_oracle_ema_alpha(
     env: Env,
     caller: String,
-    parameter: Symbol,
-    value: i128,
+    alpha_bps: i128,
 ) -> Result<(), ProtocolError> {
-    let _guard = ReentrancyScope::enter(&env)?;
-    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
-    EmergencyManager::queue_param_update(&env, &caller_addr, parameter, value)
-}
-
-pub fn apply_emergency_param_updates(env: Env, caller: String) -> Result<(), ProtocolError> {
-    let _guard = ReentrancyScope::enter(&env)?;
     let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
-    EmergencyManager::apply_param_updates(&env, &caller_addr)
+    oracle::Oracle::set_ema_alpha_bps(&env, &caller_addr, alpha_bps)
 }
 
-pub fn adjust_emergency_fund(
+pub fn set_oracle_asset_mode(
     env: Env,
     caller: String,
-    token: Option<Address>,
-    delta: i128,
-    reserve_delta: i128,
+    asset: Address,
+    mode: i128,
 ) -> Result<(), ProtocolError> {
-    let _guard = ReentrancyScope::enter(&env)?;
     let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
-    EmergencyManager::adjust_fund(&env, &caller_addr, token, delta, reserve_delta)
+    oracle::Oracle::set_asset_mode(&env, &caller_addr, &asset, mode)
 }
 
-pub fn get_emergency_state(env: Env) -> Result<EmergencyState, ProtocolError> {
-    Ok(EmergencyStorage::get(&env))
+pub fn set_oracle_min_sources(env: Env, caller: String, count: u32) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    oracle::Oracle::set_min_sources(&env, &caller_addr, count)
 }
 
-pub fn get_event_summary(env: Env) -> Result<EventSummary, ProtocolError> {
-    Ok(EventStorage::get_summary(&env))
+pub fn set_oracle_max_sources(env: Env, caller: String, count: u32) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    oracle::Oracle::set_max_sources_per_asset(&env, &caller_addr, count)
 }
 
-pub fn get_event_aggregates(env: Env) -> Result<Map<Symbol, EventAggregate>, ProtocolError> {
-    Ok(EventStorage::get_aggregates(&env))
+pub fn set_oracle_max_deviation(env: Env, caller: String, bps: i128) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    oracle::Oracle::set_max_deviation_bps(&env, &caller_addr, bps)
 }
 
-pub fn get_events_for_type(
+pub fn set_oracle_max_source_failures(
     env: Env,
-    event_type: Symbol,
-    limit: u32,
-) -> Result<Vec<EventRecord>, ProtocolError> {
-    let logs = EventStorage::get_logs(&env);
-    let mut events = logs
-        .get(event_type.clone())
-        .unwrap_or_else(|| Vec::new(&env));
-    if limit > 0 && events.len() > limit {
-        let start = events.len() - limit;
-        events = events.slice(start..);
+    caller: String,
+    failures: u32,
+) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    oracle::Oracle::set_max_source_failures(&env, &caller_addr, failures)
+}
+
+pub fn reactivate_oracle_source(
+    env: Env,
+    caller: String,
+    asset: Address,
+    source: Address,
+) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    oracle::Oracle::reactivate_source(&env, &caller_addr, &asset, &source)
+}
+
+pub fn set_oracle_source_decimals(
+    env: Env,
+    caller: String,
+    asset: Address,
+    source: Address,
+    decimals: u32,
+) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    oracle::Oracle::set_source_decimals(&env, &caller_addr, &asset, &source, decimals)
+}
+
+pub fn set_oracle_admin(
+    env: Env,
+    caller: String,
+    new_admin: Option<Address>,
+) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    oracle::Oracle::set_oracle_admin(&env, &caller_addr, new_admin)
+}
+
+pub fn set_oracle_source(
+    env: Env,
+    caller: String,
+    asset: Address,
+    source: Address,
+    weight: i128,
+    quote_asset: Option<Address>,
+) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    let mut entry = oracle::OracleSource::new(source, weight, env.ledger().timestamp());
+    entry.quote_asset = quote_asset;
+    oracle::Oracle::set_source(&env, &caller_addr, &asset, entry)
+}
+
+pub fn remove_oracle_source(
+    env: Env,
+    caller: String,
+    asset: Address,
+    source: Address,
+) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    oracle::Oracle::remove_source(&env, &caller_addr, &asset, &source)
+}
+
+pub fn set_oracle_heartbeat_ttl(env: Env, caller: String, ttl: u64) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    oracle::OracleStorage::set_heartbeat_ttl(&env, &caller_addr, ttl)
+}
+
+pub fn set_oracle_mode(env: Env, caller: String, mode: i128) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    oracle::OracleStorage::set_mode(&env, &caller_addr, mode)
+}
+
+pub fn oracle_heartbeat(env: Env, source: Address, asset: Address) -> Result<u64, ProtocolError> {
+    oracle::Oracle::heartbeat(&env, &source, &asset)
+}
+
+pub fn submit_oracle_price(
+    env: Env,
+    source: Address,
+    asset: Address,
+    price: i128,
+    timestamp: u64,
+) -> Result<(), ProtocolError> {
+    let previous = oracle::OracleStorage::get_submission(&env, &asset, &source).map(|s| s.price);
+    oracle::Oracle::submit_price(&env, &source, &asset, price, timestamp)?;
+    sweep::PriceSweep::on_price_pushed(&env, &asset, previous, price);
+    Ok(())
+}
+
+pub fn set_price_sweep_deviation(env: Env, caller: String, bps: i128) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    sweep::PriceSweep::set_deviation_bps(&env, &caller_addr, bps)
+}
+
+pub fn set_oracle_push_mode(
+    env: Env,
+    caller: String,
+    asset: Address,
+    enabled: bool,
+) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    oracle::Oracle::set_push_mode(&env, &caller_addr, &asset, enabled)
+}
+
+pub fn set_oracle_fallback(
+    env: Env,
+    caller: String,
+    asset: Address,
+    fallback: Option<Address>,
+) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    oracle::Oracle::set_fallback(&env, &caller_addr, &asset, fallback)
+}
+
+pub fn get_oracle_twap(env: Env, asset: Address, window_secs: u64) -> Option<i128> {
+    oracle::Oracle::twap(&env, &asset, window_secs)
+}
+
+pub fn set_block_liquidation_on_clamp(
+    env: Env,
+    caller: String,
+    enabled: bool,
+) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    ProtocolConfig::require_admin(&env, &caller_addr)?;
+    oracle::OracleStorage::set_block_liquidation_on_clamp(&env, enabled);
+    Ok(())
+}
+
+pub fn get_liquidation_record(
+    env: Env,
+    liquidation_id: u64,
+) -> Option<liquidate::LiquidationRecord> {
+    liquidate::LiquidationRecordStorage::get(&env, liquidation_id)
+}
+
+pub fn get_last_liquidation_record(
+    env: Env,
+    borrower: Address,
+) -> Option<liquidate::LiquidationRecord> {
+    liquidate::LiquidationRecordStorage::get_last_for(&env, &borrower)
+}
+
+pub fn get_position(env: Env, user: String) -> Result<(i128, i128, i128), ProtocolError> {
+    let user_addr = AddressHelper::require_valid_address(&env, &user)?;
+    match StateHelper::get_position(&env, &user_addr) {
+        Some(position) => {
+            let collateral_ratio = if position.debt > 0 {
+                (position.collateral * 100) / position.debt
+            } else {
+                0
+            };
+            Ok((position.collateral, position.debt, collateral_ratio))
+        }
+        None => Err(ProtocolError::PositionNotFound),
     }
-    Ok(events)
 }
 
-pub fn get_recent_event_types(env: Env) -> Result<Vec<Symbol>, ProtocolError> {
-    Ok(EventStorage::get_summary(&env).recent_types)
+/// Account summary with both the maintenance and origination thresholds
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct UserAccountData {
+    pub collateral: i128,
+    pub debt: i128,
+    /// Current collateral ratio in bps (0 when there is no debt)
+    pub collateral_ratio_bps: i128,
+    /// Ratio a withdrawal must keep the position at or above
+    pub ltv_bps: i128,
+    /// Ratio below which the position becomes liquidatable
+    pub liquidation_threshold_bps: i128,
+    /// Ratio a new borrow must keep the position at or above
+    pub origination_ratio_bps: i128,
+    /// Collateral ratio relative to the LTV bound, in bps; below 10000 the
+    /// position can neither borrow nor withdraw. `i128::MAX` without debt.
+    pub ltv_health_bps: i128,
+    /// Collateral ratio relative to the liquidation threshold, in bps;
+    /// below 10000 it is liquidatable. `i128::MAX` without debt.
+    pub health_factor_bps: i128,
 }
 
-pub fn register_token_asset(
+pub fn get_user_account_data(env: Env, user: String) -> Result<UserAccountData, ProtocolError> {
+    let user_addr = AddressHelper::require_valid_address(&env, &user)?;
+    let position =
+        StateHelper::get_position(&env, &user_addr).ok_or(ProtocolError::PositionNotFound)?;
+    let collateral_ratio_bps = if position.debt > 0 {
+        (position.collateral * 10000) / position.debt
+    } else {
+        0
+    };
+    let ltv_bps = ProtocolConfig::get_ltv_bps(&env);
+    let liquidation_threshold_bps = ProtocolConfig::get_liquidation_threshold_bps(&env);
+    let margin = |bound: i128| {
+        if position.debt > 0 && bound > 0 {
+            collateral_ratio_bps * 10000 / bound
+        } else {
+            i128::MAX
+        }
+    };
+    Ok(UserAccountData {
+        collateral: position.collateral,
+        debt: position.debt,
+        collateral_ratio_bps,
+        ltv_bps,
+        liquidation_threshold_bps,
+        origination_ratio_bps: ProtocolConfig::get_origination_ratio_bps(&env),
+        ltv_health_bps: margin(ltv_bps),
+        health_factor_bps: margin(liquidation_threshold_bps),
+    })
+}
+
+pub fn set_withdrawal_policy(
+    env: Env,
+    caller: Address,
+    threshold: i128,
+    co_signer: Option<Address>,
+) -> Result<(), ProtocolError> {
+    cosign::CoSign::set_policy(&env, &caller, threshold, co_signer)
+}
+
+pub fn approve_withdrawal(
+    env: Env,
+    co_signer: Address,
+    owner: Address,
+    asset: Address,
+    amount: i128,
+    expires_at: u64,
+) -> Result<(), ProtocolError> {
+    cosign::CoSign::approve(&env, &co_signer, &owner, &asset, amount, expires_at)
+}
+
+pub fn approve_credit(
+    env: Env,
+    owner: Address,
+    spender: Address,
+    asset: Address,
+    amount: i128,
+) -> Result<(), ProtocolError> {
+    delegation::CreditDelegation::approve(&env, &owner, &spender, &asset, amount)
+}
+
+pub fn revoke_credit(
+    env: Env,
+    owner: Address,
+    spender: Address,
+    asset: Address,
+) -> Result<(), ProtocolError> {
+    delegation::CreditDelegation::revoke(&env, &owner, &spender, &asset)
+}
+
+pub fn borrow_from(
+    env: Env,
+    spender: Address,
+    owner: Address,
+    asset: Address,
+    amount: i128,
+    recipient: Address,
+) -> Result<(), ProtocolError> {
+    let risk_config = RiskConfigStorage::get(&env);
+    risk_config.ensure_not_paused(OperationKind::Borrow)?;
+    beta::BetaAccess::require_beta_access(&env, &spender)?;
+    diagnostics::CallTracker::track_call(&env, "borrow_from", &spender);
+    delegation::CreditDelegation::borrow_from(&env, &spender, &owner, &asset, amount, &recipient)
+}
+
+pub fn set_privacy_flags(env: Env, caller: String, flags: u32) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    privacy::Privacy::set_flags(&env, &caller_addr, flags)
+}
+
+pub fn check_position_risk(env: Env, user: Address) -> Result<bool, ProtocolError> {
+    let position = StateHelper::get_position(&env, &user).ok_or(ProtocolError::PositionNotFound)?;
+    Ok(alerts::Alerts::evaluate(&env, &position))
+}
+
+pub fn set_risk_snapshot_interval(
     env: Env,
     caller: String,
-    key: Symbol,
-    token: Address,
+    secs: u64,
 ) -> Result<(), ProtocolError> {
-    let _guard = ReentrancyScope::enter(&env)?;
     let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
-    TokenRegistry::set_asset(&env, &caller_addr, key, token)
+    alerts::Alerts::set_interval(&env, &caller_addr, secs)
 }
 
-pub fn set_primary_asset(env: Env, caller: String, token: Address) -> Result<(), ProtocolError> {
-    let _guard = ReentrancyScope::enter(&env)?;
+pub fn get_credit_score(env: Env, user: Address) -> u32 {
+    credit::Credit::score(&env, &user)
+}
+
+pub fn set_credit_weights(
+    env: Env,
+    caller: String,
+    weights: credit::CreditWeights,
+) -> Result<(), ProtocolError> {
     let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
-    TokenRegistry::set_primary_asset(&env, &caller_addr, token)
+    credit::Credit::set_weights(&env, &caller_addr, weights)
 }
 
-pub fn get_registered_asset(env: Env, key: Symbol) -> Result<Option<Address>, ProtocolError> {
-    Ok(TokenRegistry::get_asset(&env, key))
+pub fn set_credit_bands(
+    env: Env,
+    caller: String,
+    bands: Vec<credit::CreditBand>,
+) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    credit::Credit::set_bands(&env, &caller_addr, bands)
 }
 
-pub fn set_user_role(
+pub fn set_credit_discounts(env: Env, caller: String, enabled: bool) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    ProtocolConfig::require_admin(&env, &caller_addr)?;
+    // Settle accrual at the old rates before switching
+    InterestRateStorage::update_state(&env);
+    let mut config = RiskConfigStorage::get(&env);
+    let old = core::mem::replace(&mut config.credit_discounts_enabled, enabled);
+    config.last_update = env.ledger().timestamp();
+    RiskConfigStorage::save(&env, &config);
+    if old != enabled {
+        config::record_change(
+            &env,
+            "risk",
+            "credit_discounts_enabled",
+            None,
+            old.into(),
+            enabled.into(),
+        );
+    }
+    Ok(())
+}
+
+pub fn set_origination_buffer(env: Env, caller: String, bps: i128) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    ProtocolConfig::set_origination_buffer_bps(&env, &caller_addr, bps)
+}
+
+pub fn set_max_price_spread(env: Env, caller: String, bps: i128) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    ProtocolConfig::require_admin(&env, &caller_addr)?;
+    RiskConfigStorage::set_max_price_spread_bps(&env, bps)
+}
+
+pub fn set_risk_params(
     env: Env,
     caller: String,
-    user: Address,
-    role: UserRole,
+    close_factor: i128,
+    liquidation_incentive: i128,
 ) -> Result<(), ProtocolError> {
     let _guard = ReentrancyScope::enter(&env)?;
     let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
-    UserManager::set_role(&env, &caller_addr, &user, role)
+    ProtocolConfig::require_admin(&env, &caller_addr)?;
+
+    let mut config = RiskConfigStorage::get(&env);
+    let old_close_factor = core::mem::replace(&mut config.close_factor, close_factor);
+    let old_incentive =
+        core::mem::replace(&mut config.liquidation_incentive, liquidation_incentive);
+    if !config.protocol_fee_fits(config.liquidation_protocol_fee_bps) {
+        return Err(ProtocolError::InvalidParameters);
+    }
+    config.last_update = env.ledger().timestamp();
+    RiskConfigStorage::save(&env, &config);
+    config::record_change(
+        &env,
+        "risk",
+        "close_factor",
+        None,
+        old_close_factor,
+        close_factor,
+    );
+    config::record_change(
+        &env,
+        "risk",
+        "liquidation_incentive",
+        None,
+        old_incentive,
+        liquidation_incentive,
+    );
+
+    ProtocolEvent::RiskParamsUpdated(close_factor, liquidation_incentive).emit(&env);
+    Ok(())
 }
 
-pub fn set_user_verification(
+pub fn set_withdrawal_penalty(
     env: Env,
     caller: String,
-    user: Address,
-    status: VerificationStatus,
+    asset: Address,
+    penalty_bps: i128,
+    window_secs: u64,
 ) -> Result<(), ProtocolError> {
     let _guard = ReentrancyScope::enter(&env)?;
     let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
-    UserManager::set_verification_status(&env, &caller_addr, &user, status)
+    penalty::WithdrawalPenalty::set_config(&env, &caller_addr, &asset, penalty_bps, window_secs)
 }
 
-pub fn set_user_limits(
+pub fn get_withdrawal_penalty(env: Env, asset: Address) -> Option<penalty::PenaltyConfig> {
+    penalty::PenaltyStorage::get_config(&env, &asset)
+}
+
+pub fn get_protocol_reserves(env: Env, asset: Address) -> i128 {
+    reserves::ReserveStorage::get(&env, &asset)
+}
+
+pub fn set_reward_settlement(
     env: Env,
     caller: String,
-    user: Address,
-    max_deposit: i128,
-    max_borrow: i128,
-    max_withdraw: i128,
-    daily_limit: i128,
+    asset: Address,
+    max_slippage_bps: i128,
 ) -> Result<(), ProtocolError> {
-    let _guard = ReentrancyScope::enter(&env)?;
     let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
-    UserManager::set_limits(
-        &env,
-        &caller_addr,
-        &user,
-        max_deposit,
-        max_borrow,
-        max_withdraw,
-        daily_limit,
-    )
+    payouts::Payouts::set_settlement(&env, &caller_addr, &asset, max_slippage_bps)
+}
+
+pub fn fund_payout(
+    env: Env,
+    caller: String,
+    recipient: Address,
+    asset: Address,
+    amount: i128,
+) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    payouts::Payouts::fund(&env, &caller_addr, &recipient, &asset, amount)
+}
+
+pub fn claim_payout(env: Env, recipient: Address, asset: Address) -> Result<i128, ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    payouts::Payouts::claim(&env, &recipient, &asset)
+}
+
+pub fn get_available_liquidity(env: Env, asset: Address) -> liquidity::LiquidityBreakdown {
+    liquidity::Liquidity::breakdown(&env, &asset)
 }
 
-pub fn freeze_user(env: Env, caller: String, user: Address) -> Result<(), ProtocolError> {
+pub fn force_close_position(
+    env: Env,
+    caller: String,
+    user: Address,
+    resolution: recovery::ForceCloseResolution,
+) -> Result<recovery::ForceCloseRecord, ProtocolError> {
     let _guard = ReentrancyScope::enter(&env)?;
     let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
-    UserManager::freeze_user(&env, &caller_addr, &user)
+    recovery::Recovery::force_close(&env, &caller_addr, &user, &resolution)
+}
+
+pub fn schedule_emergency_forgiveness(
+    env: Env,
+    caller: String,
+    asset: Address,
+    users: Vec<Address>,
+    bps: i128,
+) -> Result<forgiveness::PendingForgiveness, ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    forgiveness::InterestForgiveness::schedule_emergency(&env, &caller_addr, asset, users, bps)
 }
 
-pub fn unfreeze_user(env: Env, caller: String, user: Address) -> Result<(), ProtocolError> {
+pub fn execute_emergency_forgiveness(env: Env, caller: String) -> Result<i128, ProtocolError> {
     let _guard = ReentrancyScope::enter(&env)?;
     let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
-    UserManager::unfreeze_user(&env, &caller_addr, &user)
+    forgiveness::InterestForgiveness::execute_emergency(&env, &caller_addr)
+}
+
+pub fn get_capabilities(env: Env) -> capabilities::Capabilities {
+    capabilities::CapabilityDescriptor::get(&env)
+}
+
+pub fn set_feature_flag(
+    env: Env,
+    caller: String,
+    flag: Symbol,
+    enabled: bool,
+) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    capabilities::FeatureFlags::set(&env, &caller_addr, flag, enabled)
+}
+
+pub fn set_treasury(env: Env, caller: String, treasury: Address) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    ProtocolConfig::set_treasury(&env, &caller_addr, &treasury)
+}
+
+pub fn set_guardian(env: Env, caller: String, guardian: Address) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    ProtocolConfig::set_guardian(&env, &caller_addr, &guardian)
+}
+
+pub fn propose_role_address_change(
+    env: Env,
+    caller: String,
+    role: roles::RoleKind,
+    new_address: Address,
+) -> Result<roles::PendingRoleChange, ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    roles::RoleManager::propose(&env, &caller_addr, role, new_address)
 }
 
-pub fn get_user_profile(env: Env, user: Address) -> Result<UserProfile, ProtocolError> {
-    Ok(UserManager::get_profile(&env, &user))
-}
+pub fn apply_role_address_change(
+    env: Env,
+    caller: String,
+    role: roles::RoleKind,
+) -> Result<Address, ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    roles::RoleManager::apply(&env, &caller_addr, role)
+}
+
+pub fn cancel_role_address_change(
+    env: Env,
+    caller: String,
+    role: roles::RoleKind,
+) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    roles::RoleManager::cancel(&env, &caller_addr, role)
+}
+
+pub fn get_pending_role_change(
+    env: Env,
+    role: roles::RoleKind,
+) -> Option<roles::PendingRoleChange> {
+    roles::RoleChangeStorage::get(&env, role)
+}
+
+pub fn get_param_history(env: Env) -> Vec<history::ParamChange> {
+    history::ParamHistory::get(&env)
+}
+
+pub fn set_listing_config(
+    env: Env,
+    caller: String,
+    config: listing::ListingConfig,
+) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    listing::Listing::set_config(&env, &caller_addr, config)
+}
+
+pub fn propose_listing(
+    env: Env,
+    caller: String,
+    token_info: listing::ListingTokenInfo,
+    risk_params: listing::ListingRiskParams,
+    bond_amount: i128,
+) -> Result<u64, ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    diagnostics::CallTracker::track_call(&env, "propose_listing", &caller_addr);
+    listing::Listing::propose(&env, &caller_addr, token_info, risk_params, bond_amount)
+}
+
+pub fn claim_expired_listing(
+    env: Env,
+    caller: String,
+    listing_id: u64,
+) -> Result<listing::ListingProposal, ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    diagnostics::CallTracker::track_call(&env, "claim_expired_listing", &caller_addr);
+    let proposal = listing::ListingStorage::get(&env, listing_id).ok_or(ProtocolError::NotFound)?;
+    if proposal.proposer != caller_addr {
+        return Err(ProtocolError::Unauthorized);
+    }
+    listing::Listing::claim_expired(&env, listing_id)
+}
+
+pub fn fund_incentive(
+    env: Env,
+    sponsor: String,
+    supplied_asset: Address,
+    reward_asset: Address,
+    amount: i128,
+    duration_secs: u64,
+) -> Result<u64, ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let sponsor_addr = AddressHelper::require_valid_address(&env, &sponsor)?;
+    incentives::Incentives::fund(
+        &env,
+        &sponsor_addr,
+        &supplied_asset,
+        &reward_asset,
+        amount,
+        duration_secs,
+    )
+}
+
+pub fn claim_incentive(env: Env, caller: String, program_id: u64) -> Result<i128, ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    incentives::Incentives::claim(&env, &caller_addr, program_id)
+}
+
+pub fn sweep_incentive(env: Env, caller: String, program_id: u64) -> Result<i128, ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    incentives::Incentives::sweep(&env, &caller_addr, program_id)
+}
+
+pub fn create_repay_plan(
+    env: Env,
+    caller: String,
+    asset: Address,
+    amount_per_period: i128,
+    period_secs: u64,
+) -> Result<autorepay::RepayPlan, ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    autorepay::RepayPlans::create(&env, &caller_addr, &asset, amount_per_period, period_secs)
+}
+
+pub fn cancel_repay_plan(env: Env, caller: String) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    autorepay::RepayPlans::cancel(&env, &caller_addr)
+}
+
+pub fn execute_repay_plan(
+    env: Env,
+    user: Address,
+) -> Result<autorepay::RepayPlanOutcome, ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    autorepay::RepayPlans::execute(&env, &user)
+}
+
+pub fn migrate_position_out(
+    env: Env,
+    caller: String,
+    target_contract: Address,
+) -> Result<(migration::CollateralSpec, migration::DebtSpec), ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    migration::Migration::migrate_out(&env, &caller_addr, &target_contract)
+}
+
+pub fn initiate_transfer(
+    env: Env,
+    from: Address,
+    to: Address,
+    nonce: u64,
+) -> Result<transfer::PendingTransfer, ProtocolError> {
+    transfer::PositionTransfer::initiate(&env, &from, &to, nonce)
+}
+
+pub fn complete_transfer(
+    env: Env,
+    to: Address,
+    from: Address,
+    nonce: u64,
+) -> Result<(), ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    transfer::PositionTransfer::complete(&env, &to, &from, nonce)
+}
+
+pub fn set_transfer_window(env: Env, caller: String, secs: u64) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    transfer::PositionTransfer::set_window(&env, &caller_addr, secs)
+}
+
+pub fn get_listing(env: Env, listing_id: u64) -> Option<listing::ListingProposal> {
+    listing::ListingStorage::get(&env, listing_id)
+}
+
+pub fn execute_proposal(env: Env, proposal_id: u64) -> Result<governance::Proposal, ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    governance::Governance::execute(&env, proposal_id)
+}
+
+pub fn vote_by_sig(
+    env: Env,
+    relayer: Address,
+    proposal_id: u64,
+    voter_pubkey: BytesN<32>,
+    choice: bool,
+    expiry: u64,
+    signature: BytesN<64>,
+) -> Result<governance::Proposal, ProtocolError> {
+    diagnostics::CallTracker::track_call(&env, "vote_by_sig", &relayer);
+    governance::Governance::vote_by_sig(
+        &env,
+        &relayer,
+        proposal_id,
+        &voter_pubkey,
+        choice,
+        expiry,
+        &signature,
+    )
+}
+
+pub fn cancel_proposal(
+    env: Env,
+    caller: String,
+    proposal_id: u64,
+) -> Result<governance::Proposal, ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    governance::Governance::cancel(&env, &caller_addr, proposal_id)
+}
+
+pub fn migrate_gov_receipts(
+    env: Env,
+    caller: String,
+    proposal_id: u64,
+    max_entries: u32,
+) -> Result<governance::MigrationProgress, ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    governance::Governance::migrate_receipts(&env, &caller_addr, proposal_id, max_entries)
+}
+
+pub fn execute_carryover(env: Env, caller: String, proposal_id: u64) -> Result<u32, ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    govbudget::ChangeBudget::execute_carryover(&env, &caller_addr, proposal_id)
+}
+
+pub fn register_reason_code(env: Env, caller: String, code: Symbol) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    reasons::ReasonCodes::register(&env, &caller_addr, code)
+}
+
+pub fn remove_reason_code(env: Env, caller: String, code: Symbol) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    reasons::ReasonCodes::remove(&env, &caller_addr, &code)
+}
+
+pub fn set_supply_cap(
+    env: Env,
+    caller: String,
+    asset: Address,
+    cap: i128,
+) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    caps::CapManager::set_supply_cap(&env, &caller_addr, &asset, cap)
+}
+
+pub fn accrue(env: Env) -> accrual::AccrualState {
+    if let Some(asset) = TokenRegistry::primary_asset(&env) {
+        rewards::Rewards::accrue(&env, &asset);
+    }
+    accrual::AccrualManager::accrue(&env)
+}
+
+pub fn sync_positions(env: Env, users: Vec<Address>) -> Result<i128, ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    voting::VotingPower::sync_positions(&env, &users)
+}
+
+pub fn accrue_rewards(env: Env, asset: Address) -> rewards::RewardState {
+    rewards::Rewards::accrue(&env, &asset)
+}
+
+pub fn get_emission_schedule(env: Env, asset: Address) -> Vec<rewards::EmissionSegment> {
+    rewards::RewardStorage::get_schedule(&env, &asset)
+}
+
+pub fn set_max_accrual_gap(env: Env, caller: String, secs: u64) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    accrual::AccrualManager::set_max_gap(&env, &caller_addr, secs)
+}
+
+pub fn set_rate_event_threshold(env: Env, caller: String, bps: i128) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    accrual::AccrualManager::set_rate_event_threshold(&env, &caller_addr, bps)
+}
+
+pub fn set_accrual_fresh_window(env: Env, caller: String, secs: u64) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    accrual::AccrualManager::set_fresh_window(&env, &caller_addr, secs)
+}
+
+pub fn reconcile(env: Env, asset: Address) -> Result<reconcile::ReconcileReport, ProtocolError> {
+    reconcile::Reconciler::reconcile(&env, &asset)
+}
+
+pub fn get_last_reconcile(env: Env, asset: Address) -> Option<reconcile::ReconcileReport> {
+    reconcile::ReconcileStorage::get_last(&env, &asset)
+}
+
+pub fn set_reconcile_tolerance(
+    env: Env,
+    caller: String,
+    tolerance: i128,
+) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    reconcile::Reconciler::set_tolerance(&env, &caller_addr, tolerance)
+}
+
+pub fn verify_storage(
+    env: Env,
+    caller: String,
+    sample_users: Vec<Address>,
+) -> Result<Vec<diagnostics::StorageIssue>, ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    diagnostics::StorageDiagnostics::verify_storage(&env, &caller_addr, &sample_users)
+}
+
+pub fn get_call_stats(env: Env) -> Map<Symbol, diagnostics::CallStats> {
+    diagnostics::CallTracker::get_call_stats(&env)
+}
+
+pub fn set_pause_switches(
+    env: Env,
+    caller: String,
+    pause_borrow: bool,
+    pause_deposit: bool,
+    pause_withdraw: bool,
+    pause_liquidate: bool,
+    change_reason: Option<Symbol>,
+) -> Result<(), ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    let _reason =
+        reasons::ChangeReason::enter(&env, &caller_addr, "pause_switches", change_reason)?;
+    ProtocolConfig::require_admin(&env, &caller_addr)?;
+
+    let mut config = RiskConfigStorage::get(&env);
+    let switches = [
+        ("pause_borrow", config.pause_borrow, pause_borrow),
+        ("pause_deposit", config.pause_deposit, pause_deposit),
+        ("pause_withdraw", config.pause_withdraw, pause_withdraw),
+        ("pause_liquidate", config.pause_liquidate, pause_liquidate),
+    ];
+    config.pause_borrow = pause_borrow;
+    config.pause_deposit = pause_deposit;
+    config.pause_withdraw = pause_withdraw;
+    config.pause_liquidate = pause_liquidate;
+    config.last_update = env.ledger().timestamp();
+    RiskConfigStorage::save(&env, &config);
+    for (key, old, new) in switches {
+        if old != new {
+            config::record_change(&env, "risk", key, None, old.into(), new.into());
+        }
+    }
+
+    ProtocolEvent::PauseSwitchesUpdated(
+        pause_borrow,
+        pause_deposit,
+        pause_withdraw,
+        pause_liquidate,
+    )
+    .emit(&env);
+    Ok(())
+}
+
+pub fn get_protocol_params(
+    env: Env,
+) -> Result<(i128, i128, i128, i128, i128, i128), ProtocolError> {
+    let config = InterestRateStorage::get_config(&env);
+    let risk_config = RiskConfigStorage::get(&env);
+
+    Ok((
+        config.base_rate,                  // 2000000 (2%)
+        config.kink_utilization,           // 80000000 (80%)
+        config.multiplier,                 // 10000000 (10x)
+        config.reserve_factor,             // 10000000 (10%)
+        risk_config.close_factor,          // 50000000 (50%)
+        risk_config.liquidation_incentive, // 10000000 (10%)
+    ))
+}
+
+pub fn get_risk_config(env: Env) -> Result<(i128, i128, bool, bool, bool, bool), ProtocolError> {
+    let config = RiskConfigStorage::get(&env);
+    Ok((
+        config.close_factor,
+        config.liquidation_incentive,
+        config.pause_borrow,
+        config.pause_deposit,
+        config.pause_withdraw,
+        config.pause_liquidate,
+    ))
+}
+
+/// Supply, borrow and rate state of a listed asset
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct AssetState {
+    pub total_supplied: i128,
+    pub total_borrowed: i128,
+    /// Instantaneous utilization (scaled by 1e8), used for cap checks
+    pub spot_utilization: i128,
+    /// Utilization EMA (scaled by 1e8), used for rate computation
+    pub smoothed_utilization: i128,
+    pub borrow_rate: i128,
+    pub supply_rate: i128,
+    /// Time-weighted average utilization (scaled by 1e8) over roughly the
+    /// last 24 hours
+    pub avg_utilization: i128,
+    /// Time-weighted average borrow rate over roughly the last 24 hours
+    pub avg_borrow_rate: i128,
+}
+
+pub fn get_asset_state(env: Env, asset: Address) -> Result<AssetState, ProtocolError> {
+    if TokenRegistry::primary_asset(&env).as_ref() != Some(&asset) {
+        return Err(ProtocolError::AssetNotSupported);
+    }
+    let state = InterestRateStorage::get_state(&env);
+    let (avg_utilization, avg_borrow_rate) = analytics::AnalyticsModule::trailing_averages(&env);
+    Ok(AssetState {
+        total_supplied: state.total_supplied,
+        total_borrowed: state.total_borrowed,
+        spot_utilization: state.utilization_rate,
+        smoothed_utilization: state.utilization_ema,
+        borrow_rate: state.current_borrow_rate,
+        supply_rate: state.current_supply_rate,
+        avg_utilization,
+        avg_borrow_rate,
+    })
+}
+
+pub fn set_utilization_ema_alpha(
+    env: Env,
+    caller: String,
+    alpha_bps: i128,
+) -> Result<(), ProtocolError> {
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    ProtocolConfig::require_admin(&env, &caller_addr)?;
+    if alpha_bps <= 0 || alpha_bps > 10000 {
+        return Err(ProtocolError::InvalidParameters);
+    }
+    // Close out the current period at the old alpha before switching
+    InterestRateStorage::update_state(&env);
+    let mut config = InterestRateStorage::get_config(&env);
+    let old = core::mem::replace(&mut config.utilization_ema_alpha_bps, alpha_bps);
+    config.last_update = env.ledger().timestamp();
+    InterestRateStorage::save_config(&env, &config);
+    config::record_change(
+        &env,
+        "interest",
+        "utilization_ema_alpha_bps",
+        None,
+        old,
+        alpha_bps,
+    );
+    Ok(())
+}
+
+/// Protocol-wide status, including TVL against the guarded-launch cap
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct SystemStatus {
+    pub emergency_status: EmergencyStatus,
+    pub total_supplied: i128,
+    pub total_borrowed: i128,
+    /// TVL in asset units, from the analytics aggregate
+    pub total_value_locked: i128,
+    /// TVL in USD (oracle price scale)
+    pub tvl_usd: i128,
+    /// Global TVL cap in USD (0 = disabled)
+    pub global_tvl_cap: i128,
+    /// USD left under the cap; `i128::MAX` when the cap is disabled
+    pub tvl_cap_headroom: i128,
+    /// Largest collateral asset's share of the debt exposure, in bps
+    pub collateral_concentration_bps: i128,
+}
+
+pub fn get_system_status(env: Env) -> Result<SystemStatus, ProtocolError> {
+    let state = InterestRateStorage::get_state(&env);
+    let total_value_locked =
+        analytics::AnalyticsStorage::get_protocol_metrics(&env).total_value_locked;
+    let tvl_usd = match TokenRegistry::primary_asset(&env) {
+        Some(asset) => caps::CapManager::tvl_usd(&env, &asset)?,
+        None => 0,
+    };
+    let global_tvl_cap = caps::CapStorage::get_global_tvl_cap(&env);
+    let tvl_cap_headroom = if global_tvl_cap == 0 {
+        i128::MAX
+    } else {
+        (global_tvl_cap - tvl_usd).max(0)
+    };
+    Ok(SystemStatus {
+        emergency_status: EmergencyStorage::get(&env).status,
+        total_supplied: state.total_supplied,
+        total_borrowed: state.total_borrowed,
+        total_value_locked,
+        tvl_usd,
+        global_tvl_cap,
+        tvl_cap_headroom,
+        collateral_concentration_bps: analytics::AnalyticsModule::collateral_concentration_bps(
+            &env,
+        ),
+    })
+}
+
+pub fn get_system_stats(env: Env) -> Result<(i128, i128, i128, i128), ProtocolError> {
+    let state = InterestRateStorage::get_state(&env);
+
+    Ok((
+        state.total_supplied,
+        state.total_borrowed,
+        state.utilization_rate,
+        0, // active_users - simplified for now
+    ))
+}
+
+pub fn set_emergency_manager(
+    env: Env,
+    caller: String,
+    manager: String,
+    enabled: bool,
+) -> Result<(), ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    let manager_addr = AddressHelper::require_valid_address(&env, &manager)?;
+    EmergencyManager::set_manager(&env, &caller_addr, &manager_addr, enabled)
+}
+
+pub fn trigger_emergency_pause(
+    env: Env,
+    caller: String,
+    reason: Option<String>,
+    change_reason: Option<Symbol>,
+) -> Result<(), ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    let _reason =
+        reasons::ChangeReason::enter(&env, &caller_addr, "emergency_pause", change_reason)?;
+    EmergencyManager::pause(&env, &caller_addr, reason)
+}
+
+pub fn enter_recovery_mode(
+    env: Env,
+    caller: String,
+    plan: Option<String>,
+    change_reason: Option<Symbol>,
+) -> Result<(), ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    let _reason =
+        reasons::ChangeReason::enter(&env, &caller_addr, "enter_recovery", change_reason)?;
+    EmergencyManager::enter_recovery(&env, &caller_addr, plan)
+}
+
+pub fn resume_operations(
+    env: Env,
+    caller: String,
+    change_reason: Option<Symbol>,
+) -> Result<(), ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    let _reason =
+        reasons::ChangeReason::enter(&env, &caller_addr, "resume_operations", change_reason)?;
+    EmergencyManager::resume(&env, &caller_addr)
+}
+
+pub fn record_recovery_step(env: Env, caller: String, step: String) -> Result<(), ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    EmergencyManager::record_recovery_step(&env, &caller_addr, step)
+}
+
+pub fn queue_emergency_param_update(
+    env: Env,
+    caller: String,
+    parameter: Symbol,
+    value: i128,
+) -> Result<(), ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    EmergencyManager::queue_param_update(&env, &caller_addr, parameter, value)
+}
+
+pub fn apply_emergency_param_updates(env: Env, caller: String) -> Result<(), ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    EmergencyManager::apply_param_updates(&env, &caller_addr)
+}
+
+pub fn adjust_emergency_fund(
+    env: Env,
+    caller: String,
+    token: Option<Address>,
+    delta: i128,
+    reserve_delta: i128,
+) -> Result<(), ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    EmergencyManager::adjust_fund(&env, &caller_addr, token, delta, reserve_delta)
+}
+
+pub fn get_emergency_state(env: Env) -> Result<EmergencyState, ProtocolError> {
+    Ok(EmergencyStorage::get(&env))
+}
+
+pub fn get_event_summary(env: Env) -> Result<EventSummary, ProtocolError> {
+    Ok(EventStorage::get_summary(&env))
+}
+
+pub fn get_event_aggregates(env: Env) -> Result<Map<Symbol, EventAggregate>, ProtocolError> {
+    Ok(EventStorage::get_aggregates(&env))
+}
+
+define_page!(
+    /// Page of retained events of one type, oldest first
+    EventPage,
+    EventRecord
+);
+
+pub fn get_events_for_type(
+    env: Env,
+    event_type: Symbol,
+    cursor: u32,
+    limit: u32,
+) -> Result<EventPage, ProtocolError> {
+    let events = EventStorage::get_logs(&env)
+        .get(event_type)
+        .unwrap_or_else(|| Vec::new(&env));
+    let total = events.len();
+    let (start, end) = pagination::bounds(total, cursor, limit, pagination::MAX_PAGE_LIMIT);
+    Ok(EventPage {
+        items: events.slice(start..end),
+        total: total as u64,
+        cursor: end,
+    })
+}
+
+pub fn get_events_since(
+    env: Env,
+    min_event_id: u64,
+    limit: u32,
+) -> Result<Vec<EventRecord>, ProtocolError> {
+    let feed = EventStorage::get_feed(&env);
+    let first_id = match feed.first() {
+        Some(first) => first.event_id,
+        None => return Ok(feed),
+    };
+    // Feed ids are contiguous, so the start index follows from the first id
+    let offset = min_event_id.saturating_sub(first_id).min(feed.len() as u64) as u32;
+    let (start, end) = pagination::bounds(feed.len(), offset, limit, pagination::MAX_PAGE_LIMIT);
+    Ok(feed.slice(start..end))
+}
+
+pub fn get_recent_event_types(env: Env) -> Result<Vec<Symbol>, ProtocolError> {
+    Ok(EventStorage::get_summary(&env).recent_types)
+}
+
+pub fn register_token_asset(
+    env: Env,
+    caller: String,
+    key: Symbol,
+    token: Address,
+) -> Result<(), ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    TokenRegistry::set_asset(&env, &caller_addr, key, token)
+}
+
+pub fn set_primary_asset(env: Env, caller: String, token: Address) -> Result<(), ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    TokenRegistry::set_primary_asset(&env, &caller_addr, token)
+}
+
+pub fn get_registered_asset(env: Env, key: Symbol) -> Result<Option<Address>, ProtocolError> {
+    Ok(TokenRegistry::get_asset(&env, key))
+}
+
+pub fn list_registered_assets(env: Env) -> Vec<(Symbol, Address)> {
+    TokenRegistry::list_assets(&env)
+}
+
+pub fn set_user_role(
+    env: Env,
+    caller: String,
+    user: Address,
+    role: UserRole,
+) -> Result<(), ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    UserManager::set_role(&env, &caller_addr, &user, role)
+}
+
+pub fn set_user_verification(
+    env: Env,
+    caller: String,
+    user: Address,
+    status: VerificationStatus,
+) -> Result<(), ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    UserManager::set_verification_status(&env, &caller_addr, &user, status)
+}
+
+pub fn set_user_limits(
+    env: Env,
+    caller: String,
+    user: Address,
+    max_deposit: i128,
+    max_borrow: i128,
+    max_withdraw: i128,
+    daily_limit: i128,
+) -> Result<(), ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    UserManager::set_limits(
+        &env,
+        &caller_addr,
+        &user,
+        max_deposit,
+        max_borrow,
+        max_withdraw,
+        daily_limit,
+    )
+}
+
+pub fn freeze_user(
+    env: Env,
+    caller: String,
+    user: Address,
+    reason_code: u32,
+    case_ref: Option<BytesN<32>>,
+    change_reason: Option<Symbol>,
+) -> Result<(), ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    let _reason = reasons::ChangeReason::enter(&env, &caller_addr, "freeze_user", change_reason)?;
+    UserManager::freeze_user(&env, &caller_addr, &user, reason_code, case_ref)
+}
+
+pub fn unfreeze_user(
+    env: Env,
+    caller: String,
+    user: Address,
+    change_reason: Option<Symbol>,
+) -> Result<(), ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    let _reason = reasons::ChangeReason::enter(&env, &caller_addr, "unfreeze_user", change_reason)?;
+    UserManager::unfreeze_user(&env, &caller_addr, &user)
+}
+
+pub fn freeze_users(
+    env: Env,
+    caller: String,
+    users: Vec<Address>,
+    reason_code: u32,
+    case_ref: Option<BytesN<32>>,
+    change_reason: Option<Symbol>,
+) -> Result<(), ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    let _reason = reasons::ChangeReason::enter(&env, &caller_addr, "freeze_users", change_reason)?;
+    UserManager::freeze_users(&env, &caller_addr, &users, reason_code, case_ref)
+}
+
+pub fn unfreeze_users(
+    env: Env,
+    caller: String,
+    users: Vec<Address>,
+    change_reason: Option<Symbol>,
+) -> Result<(), ProtocolError> {
+    let _guard = ReentrancyScope::enter(&env)?;
+    let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+    let _reason =
+        reasons::ChangeReason::enter(&env, &caller_addr, "unfreeze_users", change_reason)?;
+    UserManager::unfreeze_users(&env, &caller_addr, &users)
+}
+
+pub fn get_freeze_info(env: Env, user: Address) -> Option<FreezeInfo> {
+    UserManager::get_freeze_info(&env, &user)
+}
+
+pub fn get_user_profile(env: Env, user: Address) -> Result<UserProfile, ProtocolError> {
+    Ok(UserManager::get_profile(&env, &user))
+}
+
+#[contractimpl]
+impl Contract {
+    /// Initializes the contract and sets the admin address
+    pub fn initialize(env: Env, admin: String) -> Result<(), ProtocolError> {
+        let _guard = ReentrancyScope::enter(&env)?;
+        let admin_addr = AddressHelper::require_valid_address(&env, &admin)?;
+        if env
+            .storage()
+            .instance()
+            .has(&ProtocolConfig::admin_key(&env))
+        {
+            return Err(ProtocolError::AlreadyInitialized);
+        }
+        ProtocolConfig::set_admin(&env, &admin_addr);
+        UserManager::bootstrap_admin(&env, &admin_addr);
+        defaults::persist_all(&env, &admin_addr)?;
+
+        // Initialize interest rate system with default configuration
+        let config = InterestRateConfig::default();
+        InterestRateStorage::save_config(&env, &config);
+
+        let state = InterestRateState::initial();
+        InterestRateStorage::save_state(&env, &state);
+
+        // Initialize risk management system with default configuration
+        let risk_config = RiskConfig::default();
+        RiskConfigStorage::save(&env, &risk_config);
+
+        Ok(())
+    }
+
+    /// Initialize the contract with a closed beta: until `expires_at` (or
+    /// until the admin ends it) only allowlisted addresses may deposit,
+    /// borrow or use the AMM. Repay, withdraw and liquidation stay open.
+    pub fn initialize_with_beta(
+        env: Env,
+        admin: String,
+        expires_at: u64,
+    ) -> Result<(), ProtocolError> {
+        if expires_at <= env.ledger().timestamp() {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        Self::initialize(env.clone(), admin)?;
+        beta::BetaAccess::configure(&env, expires_at)
+    }
+
+    /// Allowlist up to 50 beta users (admin only); returns how many were new
+    pub fn add_beta_users(
+        env: Env,
+        caller: String,
+        users: Vec<Address>,
+    ) -> Result<u32, ProtocolError> {
+        let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+        beta::BetaAccess::add_users(&env, &caller_addr, &users)
+    }
+
+    /// Remove up to 50 beta users (admin only); their existing positions can
+    /// still be repaid and withdrawn
+    pub fn remove_beta_users(
+        env: Env,
+        caller: String,
+        users: Vec<Address>,
+    ) -> Result<u32, ProtocolError> {
+        let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+        beta::BetaAccess::remove_users(&env, &caller_addr, &users)
+    }
+
+    /// Open the protocol to everyone before the beta expires (admin only)
+    pub fn disable_beta(env: Env, caller: String) -> Result<(), ProtocolError> {
+        let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+        beta::BetaAccess::disable(&env, &caller_addr)
+    }
+
+    /// Get the beta configuration, if the contract was launched with one
+    pub fn get_beta_config(env: Env) -> Option<beta::BetaConfig> {
+        beta::BetaAccess::get_config(&env)
+    }
+
+    /// Whether `user` is on the beta allowlist
+    pub fn is_beta_user(env: Env, user: Address) -> bool {
+        beta::BetaAccess::is_member(&env, &user)
+    }
+
+    /// Set the minimum collateral ratio (admin only)
+    pub fn set_min_collateral_ratio(
+        env: Env,
+        caller: String,
+        ratio: i128,
+    ) -> Result<(), ProtocolError> {
+        let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+        ProtocolConfig::set_min_collateral_ratio(&env, &caller_addr, ratio)?;
+        Ok(())
+    }
+
+    /// Set the collateral ratio, in bps, below which positions may be
+    /// liquidated (admin only)
+    ///
+    /// Must not exceed the LTV bound set by `set_min_collateral_ratio`;
+    /// until set it follows that bound.
+    pub fn set_liquidation_threshold(
+        env: Env,
+        caller: String,
+        bps: i128,
+    ) -> Result<(), ProtocolError> {
+        let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+        ProtocolConfig::set_liquidation_threshold_bps(&env, &caller_addr, bps)
+    }
+
+    /// Deposit collateral into the protocol
+    pub fn deposit_collateral(
+        env: Env,
+        depositor: String,
+        amount: i128,
+    ) -> Result<(), ProtocolError> {
+        deposit_collateral(env, depositor, amount)
+    }
+
+    /// Borrow assets from the protocol
+    ///
+    /// Fails with `DeadlineExceeded` if executed after `deadline_ts` (0 = no deadline).
+    pub fn borrow(
+        env: Env,
+        borrower: String,
+        amount: i128,
+        deadline_ts: u64,
+    ) -> Result<(), ProtocolError> {
+        borrow(env, borrower, amount, deadline_ts)
+    }
+
+    /// Borrow a specific asset against the caller's collateral
+    ///
+    /// Fails with `DeadlineExceeded` if executed after `deadline_ts` (0 = no deadline).
+    pub fn borrow_asset(
+        env: Env,
+        borrower: String,
+        asset: Address,
+        amount: i128,
+        deadline_ts: u64,
+    ) -> Result<(), ProtocolError> {
+        borrow_asset(env, borrower, asset, amount, deadline_ts)
+    }
+
+    /// Set the borrow cap for an asset (admin only, 0 = unlimited)
+    pub fn set_borrow_cap(
+        env: Env,
+        caller: String,
+        asset: Address,
+        cap: i128,
+    ) -> Result<(), ProtocolError> {
+        set_borrow_cap(env, caller, asset, cap)
+    }
+
+    /// Assign assets to a cap group with a combined USD borrow cap (admin only)
+    ///
+    /// # Arguments
+    /// * `group_id` - Name of the group
+    /// * `members` - Assets in the group; each asset belongs to at most one group
+    /// * `usd_borrow_cap` - Combined borrow cap in USD at oracle price scale (0 = unlimited)
+    pub fn set_cap_group(
+        env: Env,
+        caller: String,
+        group_id: Symbol,
+        members: Vec<Address>,
+        usd_borrow_cap: i128,
+    ) -> Result<caps::CapGroup, ProtocolError> {
+        set_cap_group(env, caller, group_id, members, usd_borrow_cap)
+    }
+
+    /// Limit how much new borrowing `asset` may take on per 24h window, in
+    /// units of the asset, or with `None` the whole protocol, in USD at the
+    /// oracle price scale (admin only, 0 = disabled). The borrow crossing a
+    /// limit trips it and later borrows in that scope fail with
+    /// `ProtocolPaused` until the window rolls or it is cleared.
+    pub fn set_borrow_surge_limit(
+        env: Env,
+        caller: String,
+        asset: Option<Address>,
+        limit: i128,
+    ) -> Result<(), ProtocolError> {
+        set_borrow_surge_limit(env, caller, asset, limit)
+    }
+
+    /// Reset a tripped borrow growth limit early (guardian or admin)
+    pub fn clear_borrow_surge(
+        env: Env,
+        caller: String,
+        asset: Option<Address>,
+    ) -> Result<(), ProtocolError> {
+        clear_borrow_surge(env, caller, asset)
+    }
+
+    /// Get the borrow growth limit of an asset, or the global one with `None`
+    pub fn get_borrow_surge_limit(env: Env, asset: Option<Address>) -> i128 {
+        surge::SurgeStorage::get_limit(&env, &asset)
+    }
+
+    /// Get the current borrow growth window of an asset, or the global one
+    /// with `None`
+    pub fn get_borrow_surge_window(env: Env, asset: Option<Address>) -> surge::BorrowWindow {
+        surge::SurgeStorage::get_window(&env, &asset)
+    }
+
+    /// Get a cap group and its last recorded USD borrow total
+    pub fn get_cap_group(env: Env, group_id: Symbol) -> Option<caps::CapGroup> {
+        get_cap_group(env, group_id)
+    }
+
+    /// Repay borrowed assets
+    pub fn repay(env: Env, repayer: String, amount: i128) -> Result<(), ProtocolError> {
+        repay(env, repayer, amount)
+    }
+
+    /// Withdraw collateral from the protocol
+    ///
+    /// Fails with `DeadlineExceeded` if executed after `deadline_ts` (0 = no deadline).
+    pub fn withdraw(
+        env: Env,
+        withdrawer: String,
+        amount: i128,
+        deadline_ts: u64,
+    ) -> Result<(), ProtocolError> {
+        withdraw(env, withdrawer, amount, deadline_ts)
+    }
+
+    /// Run up to 6 operations on the caller's own position in one call
+    ///
+    /// Health is checked once after the last operation rather than after
+    /// each one; any failure reverts the whole batch. Fails with
+    /// `DeadlineExceeded` if executed after `deadline_ts` (0 = no deadline).
+    pub fn multicall(
+        env: Env,
+        caller: String,
+        ops: Vec<multicall::UserOp>,
+        deadline_ts: u64,
+    ) -> Result<(), ProtocolError> {
+        multicall(env, caller, ops, deadline_ts)
+    }
+
+    /// Liquidate an undercollateralized position
+    ///
+    /// When `receive_as_shares` is set, seized collateral is credited to the
+    /// liquidator's position instead of transferred, unless the supply cap is
+    /// already exceeded, in which case it falls back to a token transfer.
+    /// Fails with `DeadlineExceeded` if executed after `deadline_ts` (0 = no
+    /// deadline); the result echoes the deadline. While an emergency price
+    /// override is active, fails with `InvalidOperation` unless
+    /// `acknowledge_override` is set.
+    #[allow(clippy::too_many_arguments)]
+    pub fn liquidate(
+        env: Env,
+        liquidator: String,
+        user: String,
+        amount: i128,
+        min_out: i128,
+        receive_as_shares: bool,
+        deadline_ts: u64,
+        acknowledge_override: bool,
+    ) -> Result<liquidate::LiquidationResult, ProtocolError> {
+        liquidate(
+            env,
+            liquidator,
+            user,
+            amount,
+            min_out,
+            receive_as_shares,
+            deadline_ts,
+            acknowledge_override,
+        )
+    }
+
+    /// Liquidate by the amount of collateral to acquire instead of the debt
+    /// to repay
+    ///
+    /// The implied repayment is derived from `collateral_amount` and the
+    /// liquidation bonus (rounded so no more than `collateral_amount` is
+    /// seized), clamped to the borrower's collateral and the close factor,
+    /// and then executed exactly like `liquidate` without a deadline, with
+    /// `max_repay` bounding what the liquidator pays. Fails with
+    /// `SlippageProtectionTriggered` if it exceeds `max_repay` or the
+    /// executed liquidation would seize more than `collateral_amount`, and
+    /// with `AssetNotSupported` unless both assets are the primary asset.
+    #[allow(clippy::too_many_arguments)]
+    pub fn liquidate_exact_collateral(
+        env: Env,
+        liquidator: String,
+        borrower: String,
+        collateral_asset: Address,
+        collateral_amount: i128,
+        debt_asset: Address,
+        max_repay: i128,
+        acknowledge_override: bool,
+    ) -> Result<liquidate::LiquidationResult, ProtocolError> {
+        liquidate_exact_collateral(
+            env,
+            liquidator,
+            borrower,
+            collateral_asset,
+            collateral_amount,
+            debt_asset,
+            max_repay,
+            acknowledge_override,
+        )
+    }
+
+    /// Quote how a liquidation of `amount` would split the seized collateral
+    /// between the liquidator and the insurance fund
+    pub fn preview_liquidation(
+        env: Env,
+        user: Address,
+        amount: i128,
+    ) -> Result<liquidate::LiquidationQuote, ProtocolError> {
+        preview_liquidation(env, user, amount)
+    }
+
+    /// Set the share of the liquidation bonus diverted to the insurance fund,
+    /// in bps of the repaid amount (admin only, bounded by the incentive)
+    /// `change_reason` is a registered reason code, required in strict mode.
+    pub fn set_liquidation_protocol_fee(
+        env: Env,
+        caller: String,
+        fee_bps: i128,
+        change_reason: Option<Symbol>,
+    ) -> Result<(), ProtocolError> {
+        set_liquidation_protocol_fee(env, caller, fee_bps, change_reason)
+    }
+
+    /// Propose or confirm an emergency price override for an asset (guardian
+    /// and admin). The override takes effect once both have made the same
+    /// call, and lasts until `expires_at`, at most 24 hours away.
+    pub fn set_emergency_price(
+        env: Env,
+        caller: String,
+        asset: Address,
+        price: i128,
+        expires_at: u64,
+    ) -> Result<oracle::EmergencyPrice, ProtocolError> {
+        set_emergency_price(env, caller, asset, price, expires_at)
+    }
+
+    /// Get the emergency price override in effect for an asset, if any
+    pub fn get_emergency_price(env: Env, asset: Address) -> Option<oracle::EmergencyPrice> {
+        oracle::Oracle::active_override(&env, &asset, false)
+    }
+
+    /// Route `base`/`quote` prices through an intermediate asset (admin only)
+    ///
+    /// # Arguments
+    /// * `via` - Asset both legs are quoted against; `None` removes the route
+    pub fn set_oracle_price_route(
+        env: Env,
+        caller: String,
+        base: Address,
+        quote: Address,
+        via: Option<Address>,
+    ) -> Result<(), ProtocolError> {
+        set_oracle_price_route(env, caller, base, quote, via)
+    }
+
+    /// Get the price of one unit of `base` in `quote`, derived from the
+    /// stored aggregates; `None` when no route applies
+    pub fn get_derived_price(env: Env, base: Address, quote: Address) -> Option<i128> {
+        oracle::Oracle::derive_price(&env, &base, &quote)
+    }
+
+    /// Set the range single oracle source readings must fall in to be
+    /// aggregated (admin only)
+    ///
+    /// # Arguments
+    /// * `min` - Positive floor, in 8 decimals
+    /// * `max` - Ceiling above `min`; (0, i128::MAX) removes the bounds
+    pub fn set_oracle_reading_bounds(
+        env: Env,
+        caller: String,
+        asset: Address,
+        min: i128,
+        max: i128,
+    ) -> Result<(), ProtocolError> {
+        set_oracle_reading_bounds(env, caller, asset, min, max)
+    }
+
+    /// Get the range oracle source readings of an asset must fall in, and how
+    /// many readings were dropped for falling outside it
+    pub fn get_oracle_reading_bounds(env: Env, asset: Address) -> (oracle::PriceBounds, u64) {
+        (
+            oracle::Oracle::get_reading_bounds(&env, &asset),
+            oracle::OracleStorage::get_rejected_readings(&env, &asset),
+        )
+    }
+
+    /// Set hard price bounds for an asset (admin only)
+    ///
+    /// # Arguments
+    /// * `asset` - Asset to bound
+    /// * `min` - Floor applied to the aggregated price
+    /// * `max` - Ceiling applied to the aggregated price; (0, 0) disables bounds
+    pub fn set_price_bounds(
+        env: Env,
+        caller: String,
+        asset: Address,
+        min: i128,
+        max: i128,
+    ) -> Result<(), ProtocolError> {
+        set_price_bounds(env, caller, asset, min, max)
+    }
+
+    /// Freeze oracle prices at their last aggregated value (guardian or admin)
+    ///
+    /// # Arguments
+    /// * `asset` - Asset to freeze, or `None` for every asset
+    ///
+    /// Frozen prices are served for up to the configured maximum freeze
+    /// duration, after which pricing fails closed until `unfreeze_oracle`.
+    /// Liquidations refuse to run while a freeze covers the asset.
+    /// `change_reason` is a registered reason code, required in strict mode.
+    pub fn freeze_oracle(
+        env: Env,
+        caller: String,
+        asset: Option<Address>,
+        change_reason: Option<Symbol>,
+    ) -> Result<oracle::OracleFreeze, ProtocolError> {
+        freeze_oracle(env, caller, asset, change_reason)
+    }
+
+    /// Lift an oracle freeze and resume live aggregation (admin only)
+    /// `change_reason` is a registered reason code, required in strict mode.
+    pub fn unfreeze_oracle(
+        env: Env,
+        caller: String,
+        asset: Option<Address>,
+        change_reason: Option<Symbol>,
+    ) -> Result<(), ProtocolError> {
+        unfreeze_oracle(env, caller, asset, change_reason)
+    }
+
+    /// Set how long frozen oracle prices stay usable (admin only)
+    pub fn set_oracle_max_freeze(env: Env, caller: String, secs: u64) -> Result<(), ProtocolError> {
+        set_oracle_max_freeze(env, caller, secs)
+    }
+
+    /// Set how many aggregated prices are kept per asset for TWAP (admin only)
+    pub fn set_oracle_twap_buffer_len(
+        env: Env,
+        caller: String,
+        len: u32,
+    ) -> Result<(), ProtocolError> {
+        set_oracle_twap_buffer_len(env, caller, len)
+    }
+
+    /// Set the weight of each new price in the oracle EMA (mode 2), in bps
+    /// within (0, 10000] (admin only)
+    pub fn set_oracle_ema_alpha(
+        env: Env,
+        caller: String,
+        alpha_bps: i128,
+    ) -> Result<(), ProtocolError> {
+        set_oracle_ema_alpha(env, caller, alpha_bps)
+    }
+
+    /// Get the EMA of an asset's price, maintained while it aggregates in
+    /// mode 2; `None` until the first such aggregation
+    pub fn get_oracle_ema(env: Env, asset: Address) -> Option<i128> {
+        oracle::Oracle::get_ema(&env, &asset)
+    }
+
+    /// Override the oracle aggregation mode for one asset (admin only)
+    ///
+    /// # Arguments
+    /// * `mode` - 0 = median, 1 = twap, -1 = use the global mode
+    pub fn set_oracle_asset_mode(
+        env: Env,
+        caller: String,
+        asset: Address,
+        mode: i128,
+    ) -> Result<(), ProtocolError> {
+        set_oracle_asset_mode(env, caller, asset, mode)
+    }
+
+    /// Get the aggregation mode in effect for an asset
+    pub fn get_oracle_effective_mode(env: Env, asset: Address) -> i128 {
+        oracle::Oracle::get_effective_mode(&env, &asset)
+    }
+
+    /// SEP-40: most recent aggregated price of an asset
+    ///
+    /// # Returns
+    /// * Price scaled by `decimals()` and the time it was aggregated, or
+    ///   `None` for assets without a cached price
+    pub fn lastprice(env: Env, asset: sep40::Asset) -> Option<sep40::PriceData> {
+        sep40::PriceFeed::lastprice(&env, &asset)
+    }
+
+    /// SEP-40: price of an asset in effect at a timestamp
+    ///
+    /// # Returns
+    /// * The latest recorded observation at or before `timestamp`, or `None`
+    ///   if the observation buffer holds none
+    pub fn price(env: Env, asset: sep40::Asset, timestamp: u64) -> Option<sep40::PriceData> {
+        sep40::PriceFeed::price(&env, &asset, timestamp)
+    }
+
+    /// SEP-40: up to `records` most recent price observations of an asset,
+    /// newest first
+    pub fn prices(env: Env, asset: sep40::Asset, records: u32) -> Option<Vec<sep40::PriceData>> {
+        sep40::PriceFeed::prices(&env, &asset, records)
+    }
+
+    /// SEP-40: asset prices are quoted in (USD)
+    pub fn base(env: Env) -> sep40::Asset {
+        sep40::PriceFeed::base(&env)
+    }
+
+    /// SEP-40: nominal seconds between price observations
+    pub fn resolution(env: Env) -> u32 {
+        sep40::PriceFeed::resolution(&env)
+    }
+
+    /// SEP-40: decimal places of prices returned by `lastprice`
+    pub fn decimals(_env: Env) -> u32 {
+        sep40::PriceFeed::decimals()
+    }
+
+    /// SEP-40: assets with registered oracle sources (at most 64)
+    pub fn assets(env: Env) -> Vec<sep40::Asset> {
+        sep40::PriceFeed::assets(&env)
+    }
+
+    /// Set how many healthy oracle sources an aggregated price needs (admin only)
+    pub fn set_oracle_min_sources(
+        env: Env,
+        caller: String,
+        count: u32,
+    ) -> Result<(), ProtocolError> {
+        set_oracle_min_sources(env, caller, count)
+    }
+
+    /// Set how many oracle sources one asset may register (admin only)
+    pub fn set_oracle_max_sources(
+        env: Env,
+        caller: String,
+        count: u32,
+    ) -> Result<(), ProtocolError> {
+        set_oracle_max_sources(env, caller, count)
+    }
+
+    /// Get the number of oracle sources registered for an asset
+    pub fn get_oracle_source_count(env: Env, asset: Address) -> u32 {
+        oracle::Oracle::get_source_count(&env, &asset)
+    }
+
+    /// Appoint the oracle admin, who may manage sources, the heartbeat TTL
+    /// and the aggregation mode alongside the protocol admin; `None`
+    /// revokes the role (admin only)
+    pub fn set_oracle_admin(
+        env: Env,
+        caller: String,
+        new_admin: Option<Address>,
+    ) -> Result<(), ProtocolError> {
+        set_oracle_admin(env, caller, new_admin)
+    }
+
+    /// Aggregate prices for up to 20 assets in one call; assets without a
+    /// healthy price are omitted. Refreshes the price cache like any other
+    /// aggregation.
+    pub fn aggregate_oracle_prices(
+        env: Env,
+        assets: Vec<Address>,
+    ) -> Result<Map<Address, i128>, ProtocolError> {
+        oracle::Oracle::aggregate_prices(&env, assets)
+    }
+
+    /// Get the oracle admin, if one is appointed
+    pub fn get_oracle_admin(env: Env) -> Option<Address> {
+        oracle::OracleStorage::get_oracle_admin(&env)
+    }
+
+    /// Register or update an oracle source for an asset, heartbeating it
+    /// now (oracle admin or admin)
+    pub fn set_oracle_source(
+        env: Env,
+        caller: String,
+        asset: Address,
+        source: Address,
+        weight: i128,
+        quote_asset: Option<Address>,
+    ) -> Result<(), ProtocolError> {
+        set_oracle_source(env, caller, asset, source, weight, quote_asset)
+    }
+
+    /// Remove an oracle source from an asset (oracle admin or admin)
+    pub fn remove_oracle_source(
+        env: Env,
+        caller: String,
+        asset: Address,
+        source: Address,
+    ) -> Result<(), ProtocolError> {
+        remove_oracle_source(env, caller, asset, source)
+    }
+
+    /// Set how long a source heartbeat stays fresh, in seconds (oracle
+    /// admin or admin)
+    pub fn set_oracle_heartbeat_ttl(
+        env: Env,
+        caller: String,
+        ttl: u64,
+    ) -> Result<(), ProtocolError> {
+        set_oracle_heartbeat_ttl(env, caller, ttl)
+    }
+
+    /// Set the price aggregation mode (oracle admin or admin)
+    pub fn set_oracle_mode(env: Env, caller: String, mode: i128) -> Result<(), ProtocolError> {
+        set_oracle_mode(env, caller, mode)
+    }
+
+    /// Set the largest move of an aggregated price from the last good one,
+    /// in bps (admin only); larger moves are rejected
+    pub fn set_oracle_max_deviation(
+        env: Env,
+        caller: String,
+        bps: i128,
+    ) -> Result<(), ProtocolError> {
+        set_oracle_max_deviation(env, caller, bps)
+    }
+
+    /// Get the oracle's largest accepted move from the last good price, in bps
+    pub fn get_oracle_max_deviation(env: Env) -> i128 {
+        oracle::OracleStorage::get_max_deviation_bps(&env)
+    }
+
+    /// Set how many consecutive failed fetches deactivate an oracle source (admin only)
+    pub fn set_oracle_max_source_failures(
+        env: Env,
+        caller: String,
+        failures: u32,
+    ) -> Result<(), ProtocolError> {
+        set_oracle_max_source_failures(env, caller, failures)
+    }
+
+    /// Return a deactivated oracle source to aggregation (admin only)
+    ///
+    /// # Arguments
+    /// * `asset` - Asset the source prices
+    /// * `source` - Source contract address
+    pub fn reactivate_oracle_source(
+        env: Env,
+        caller: String,
+        asset: Address,
+        source: Address,
+    ) -> Result<(), ProtocolError> {
+        reactivate_oracle_source(env, caller, asset, source)
+    }
+
+    /// Report an oracle source as live, refreshing its heartbeat (source
+    /// auth required)
+    ///
+    /// # Returns
+    /// * The new heartbeat timestamp; `NotFound` if the source is not
+    ///   registered for `asset`
+    pub fn oracle_heartbeat(
+        env: Env,
+        source: Address,
+        asset: Address,
+    ) -> Result<u64, ProtocolError> {
+        oracle_heartbeat(env, source, asset)
+    }
+
+    /// Push a price to the contract as a registered oracle source
+    ///
+    /// # Arguments
+    /// * `source` - The registered source reporting; must authorize
+    /// * `price` - Positive price, in the source's declared decimals
+    /// * `timestamp` - When the price was observed; checked against the
+    ///   heartbeat TTL while the asset is in push mode
+    pub fn submit_oracle_price(
+        env: Env,
+        source: Address,
+        asset: Address,
+        price: i128,
+        timestamp: u64,
+    ) -> Result<(), ProtocolError> {
+        submit_oracle_price(env, source, asset, price, timestamp)
+    }
+
+    /// Set the move in bps between a source's consecutive pushed prices
+    /// that starts a health sweep of the asset's holders (admin only,
+    /// 0 = disabled)
+    pub fn set_price_sweep_deviation(
+        env: Env,
+        caller: String,
+        bps: i128,
+    ) -> Result<(), ProtocolError> {
+        set_price_sweep_deviation(env, caller, bps)
+    }
+
+    /// Price move in bps that starts a health sweep
+    pub fn get_price_sweep_deviation(env: Env) -> i128 {
+        sweep::SweepStorage::get_deviation_bps(&env)
+    }
+
+    /// Cursor and outstanding holders of an asset's health sweep
+    pub fn get_price_sweep(env: Env, asset: Address) -> sweep::SweepState {
+        sweep::SweepStorage::get_state(&env, &asset)
+    }
+
+    /// Choose whether an asset aggregates submitted prices instead of
+    /// calling its sources (admin only)
+    pub fn set_oracle_push_mode(
+        env: Env,
+        caller: String,
+        asset: Address,
+        enabled: bool,
+    ) -> Result<(), ProtocolError> {
+        set_oracle_push_mode(env, caller, asset, enabled)
+    }
+
+    /// Declare the decimal places an oracle source reports with (admin only)
+    ///
+    /// # Arguments
+    /// * `decimals` - 0 to 18; prices are normalized to 8 decimals
+    ///   before aggregation, the precision sources are assumed to use
+    ///   until declared otherwise
+    pub fn set_oracle_source_decimals(
+        env: Env,
+        caller: String,
+        asset: Address,
+        source: Address,
+        decimals: u32,
+    ) -> Result<(), ProtocolError> {
+        set_oracle_source_decimals(env, caller, asset, source, decimals)
+    }
+
+    /// Get the failure counts of an asset's oracle sources that have failed
+    /// since their last successful fetch
+    pub fn get_oracle_source_health(
+        env: Env,
+        asset: Address,
+    ) -> Map<Address, oracle::SourceHealth> {
+        oracle::OracleStorage::get_source_health(&env, &asset)
+    }
+
+    /// List an asset's oracle sources with their weight, last heartbeat and
+    /// whether they are stale under the current TTL
+    ///
+    /// # Arguments
+    /// * `asset` - Asset whose sources to list
+    /// * `dry_run` - Also ask each live source for its current price
+    pub fn get_oracle_source_status(
+        env: Env,
+        asset: Address,
+        dry_run: bool,
+    ) -> Vec<oracle::SourceStatus> {
+        oracle::Oracle::get_source_status(&env, &asset, dry_run)
+    }
+
+    /// Get the heartbeat TTL, mode, minimum sources and deviation limits of
+    /// the oracle
+    pub fn get_oracle_config(env: Env) -> oracle::OracleConfig {
+        oracle::Oracle::get_config(&env)
+    }
+
+    /// Time-weighted average of an asset's recorded prices
+    ///
+    /// # Arguments
+    /// * `asset` - Asset to average
+    /// * `window_secs` - How far back to look
+    ///
+    /// `None` when no price was recorded inside the window.
+    pub fn get_oracle_twap(env: Env, asset: Address, window_secs: u64) -> Option<i128> {
+        get_oracle_twap(env, asset, window_secs)
+    }
+
+    /// Set the oracle queried when aggregation yields no price for an asset
+    /// (admin only); `None` clears it
+    pub fn set_oracle_fallback(
+        env: Env,
+        caller: String,
+        asset: Address,
+        fallback: Option<Address>,
+    ) -> Result<(), ProtocolError> {
+        set_oracle_fallback(env, caller, asset, fallback)
+    }
+
+    /// Get an asset's price and whether it came from aggregation or the
+    /// fallback oracle. Read-only: a stale price is aggregated but not cached
+    pub fn get_price_detailed(env: Env, asset: Address) -> Option<oracle::PricePoint> {
+        oracle::Oracle::peek_price_detailed(&env, &asset)
+    }
+
+    /// Choose whether liquidations refuse to run on a clamped collateral price (admin only)
+    pub fn set_block_liquidation_on_clamp(
+        env: Env,
+        caller: String,
+        enabled: bool,
+    ) -> Result<(), ProtocolError> {
+        set_block_liquidation_on_clamp(env, caller, enabled)
+    }
+
+    /// Get a retained liquidation record with the prices it used
+    ///
+    /// # Arguments
+    /// * `liquidation_id` - Id returned in the liquidation result
+    ///
+    /// # Returns
+    /// * The record, or None once it has rotated out of the retained window
+    pub fn get_liquidation_record(
+        env: Env,
+        liquidation_id: u64,
+    ) -> Option<liquidate::LiquidationRecord> {
+        get_liquidation_record(env, liquidation_id)
+    }
+
+    /// Get the most recent liquidation record for a borrower
+    pub fn get_last_liquidation_record(
+        env: Env,
+        borrower: Address,
+    ) -> Option<liquidate::LiquidationRecord> {
+        get_last_liquidation_record(env, borrower)
+    }
+
+    /// Get user position
+    pub fn get_position(env: Env, user: String) -> Result<(i128, i128, i128), ProtocolError> {
+        get_position(env, user)
+    }
+
+    /// Get a user's account data along with maintenance and origination thresholds
+    pub fn get_user_account_data(env: Env, user: String) -> Result<UserAccountData, ProtocolError> {
+        get_user_account_data(env, user)
+    }
+
+    /// Require a co-signer's approval for withdrawals above `threshold`
+    ///
+    /// # Arguments
+    /// * `co_signer` - Approving address, or `None` to remove the policy
+    ///
+    /// A first policy applies immediately; changes to an existing one take
+    /// effect after 24 hours.
+    pub fn set_withdrawal_policy(
+        env: Env,
+        caller: Address,
+        threshold: i128,
+        co_signer: Option<Address>,
+    ) -> Result<(), ProtocolError> {
+        set_withdrawal_policy(env, caller, threshold, co_signer)
+    }
+
+    /// Approve one withdrawal of exactly `amount` by `owner`, usable until
+    /// `expires_at` (co-signer only)
+    pub fn approve_withdrawal(
+        env: Env,
+        co_signer: Address,
+        owner: Address,
+        asset: Address,
+        amount: i128,
+        expires_at: u64,
+    ) -> Result<(), ProtocolError> {
+        approve_withdrawal(env, co_signer, owner, asset, amount, expires_at)
+    }
+
+    /// Let `spender` borrow up to `amount` against the caller's position,
+    /// replacing any previous allowance (0 revokes it)
+    pub fn approve_credit(
+        env: Env,
+        owner: Address,
+        spender: Address,
+        asset: Address,
+        amount: i128,
+    ) -> Result<(), ProtocolError> {
+        approve_credit(env, owner, spender, asset, amount)
+    }
+
+    /// Revoke the remaining borrow allowance of `spender`
+    pub fn revoke_credit(
+        env: Env,
+        owner: Address,
+        spender: Address,
+        asset: Address,
+    ) -> Result<(), ProtocolError> {
+        revoke_credit(env, owner, spender, asset)
+    }
+
+    /// Borrow against `owner`'s position on the caller's allowance
+    ///
+    /// # Arguments
+    /// * `recipient` - Address receiving the borrowed funds
+    ///
+    /// The debt is recorded on `owner`, whose collateral ratio must hold as
+    /// for any borrow; the allowance is reduced by `amount`.
+    pub fn borrow_from(
+        env: Env,
+        spender: Address,
+        owner: Address,
+        asset: Address,
+        amount: i128,
+        recipient: Address,
+    ) -> Result<(), ProtocolError> {
+        borrow_from(env, spender, owner, asset, amount, recipient)
+    }
+
+    /// Get the debt `spender` may still draw against `owner`'s position
+    pub fn get_credit_allowance(
+        env: Env,
+        owner: Address,
+        spender: Address,
+        asset: Address,
+    ) -> i128 {
+        delegation::DelegationStorage::get_allowance(&env, &owner, &spender, &asset)
+    }
+
+    /// Get the withdrawal policy in effect for a user
+    pub fn get_withdrawal_policy(env: Env, user: Address) -> Option<cosign::WithdrawalPolicy> {
+        cosign::CoSign::policy(&env, &user)
+    }
+
+    /// Replace the caller's privacy flags
+    ///
+    /// # Arguments
+    /// * `flags` - Bit set: 1 = analytics opt-out, 2 = referral opt-out
+    ///
+    /// Opting out of analytics also clears the caller's recorded activity.
+    pub fn set_privacy_flags(env: Env, caller: String, flags: u32) -> Result<(), ProtocolError> {
+        set_privacy_flags(env, caller, flags)
+    }
+
+    /// Get a user's privacy flags
+    pub fn get_privacy_flags(env: Env, user: Address) -> u32 {
+        privacy::Privacy::get_flags(&env, &user)
+    }
+
+    /// Re-evaluate a position's liquidation risk, recording a snapshot if it
+    /// just became at risk or has stayed at risk for a snapshot interval
+    /// (permissionless keeper job). Returns whether it is at risk.
+    pub fn check_position_risk(env: Env, user: Address) -> Result<bool, ProtocolError> {
+        check_position_risk(env, user)
+    }
+
+    /// Get a user's most recent risk snapshots, oldest first (at most 5)
+    pub fn get_risk_snapshots(env: Env, user: Address) -> Vec<alerts::RiskSnapshot> {
+        alerts::AlertStorage::get_snapshots(&env, &user)
+    }
+
+    /// Set the minimum time between snapshots of a position that stays at
+    /// risk (admin only)
+    pub fn set_risk_snapshot_interval(
+        env: Env,
+        caller: String,
+        secs: u64,
+    ) -> Result<(), ProtocolError> {
+        set_risk_snapshot_interval(env, caller, secs)
+    }
+
+    /// Credit score (0-1000) derived from the user's on-chain history
+    pub fn get_credit_score(env: Env, user: Address) -> u32 {
+        get_credit_score(env, user)
+    }
+
+    /// Set the credit score formula weights and targets (admin only)
+    pub fn set_credit_weights(
+        env: Env,
+        caller: String,
+        weights: credit::CreditWeights,
+    ) -> Result<(), ProtocolError> {
+        set_credit_weights(env, caller, weights)
+    }
+
+    /// Set the score bands mapped to borrow-rate discounts (admin only)
+    pub fn set_credit_bands(
+        env: Env,
+        caller: String,
+        bands: Vec<credit::CreditBand>,
+    ) -> Result<(), ProtocolError> {
+        set_credit_bands(env, caller, bands)
+    }
+
+    /// Enable or disable credit score discounts on borrow accrual (admin only)
+    pub fn set_credit_discounts(
+        env: Env,
+        caller: String,
+        enabled: bool,
+    ) -> Result<(), ProtocolError> {
+        set_credit_discounts(env, caller, enabled)
+    }
+
+    /// Page through indexed positions with health factors priced from the
+    /// oracle cache, for liquidation bots. At most 10 entries per call.
+    ///
+    /// # Returns
+    /// * Entries, the number of indexed positions, and the cursor to pass on the next call
+    pub fn scan_positions(env: Env, cursor: u32, limit: u32) -> scan::ScanPage {
+        scan::PositionScanner::scan(&env, cursor, limit)
+    }
+
+    /// Set the origination buffer in bps added to the minimum ratio for new borrows (admin only)
+    pub fn set_origination_buffer(
+        env: Env,
+        caller: String,
+        bps: i128,
+    ) -> Result<(), ProtocolError> {
+        set_origination_buffer(env, caller, bps)
+    }
+
+    /// Set the widest disagreement between oracle sources, in bps of their
+    /// median, that borrows and liquidations act on (admin only, 0 = off)
+    pub fn set_max_price_spread(env: Env, caller: String, bps: i128) -> Result<(), ProtocolError> {
+        set_max_price_spread(env, caller, bps)
+    }
+
+    /// Set risk parameters (admin only)
+    pub fn set_risk_params(
+        env: Env,
+        caller: String,
+        close_factor: i128,
+        liquidation_incentive: i128,
+    ) -> Result<(), ProtocolError> {
+        set_risk_params(env, caller, close_factor, liquidation_incentive)
+    }
+
+    /// Configure the early withdrawal penalty for an asset (admin only)
+    pub fn set_withdrawal_penalty(
+        env: Env,
+        caller: String,
+        asset: Address,
+        penalty_bps: i128,
+        window_secs: u64,
+    ) -> Result<(), ProtocolError> {
+        set_withdrawal_penalty(env, caller, asset, penalty_bps, window_secs)
+    }
+
+    /// Get the early withdrawal penalty configuration for an asset
+    pub fn get_withdrawal_penalty(env: Env, asset: Address) -> Option<penalty::PenaltyConfig> {
+        get_withdrawal_penalty(env, asset)
+    }
+
+    /// Get protocol reserves accumulated for an asset
+    pub fn get_protocol_reserves(env: Env, asset: Address) -> i128 {
+        get_protocol_reserves(env, asset)
+    }
+
+    /// Set the asset referral and keeper payouts are converted to, and the
+    /// largest pool price impact, in bps, a conversion may cause (admin only)
+    pub fn set_reward_settlement(
+        env: Env,
+        caller: String,
+        asset: Address,
+        max_slippage_bps: i128,
+    ) -> Result<(), ProtocolError> {
+        set_reward_settlement(env, caller, asset, max_slippage_bps)
+    }
+
+    /// Asset payouts are converted to, if configured
+    pub fn get_reward_settlement_asset(env: Env) -> Option<Address> {
+        payouts::PayoutStorage::get_settlement_asset(&env)
+    }
+
+    /// Largest pool price impact, in bps, a payout conversion may cause
+    pub fn get_reward_max_slippage(env: Env) -> i128 {
+        payouts::PayoutStorage::get_max_slippage_bps(&env)
+    }
+
+    /// Pay reserves of an asset out as a referral or keeper reward (admin
+    /// only). The reward is queued for conversion when the asset is not the
+    /// settlement asset, and skipped when the recipient opted out of
+    /// referrals.
+    pub fn fund_payout(
+        env: Env,
+        caller: String,
+        recipient: Address,
+        asset: Address,
+        amount: i128,
+    ) -> Result<(), ProtocolError> {
+        fund_payout(env, caller, recipient, asset, amount)
+    }
+
+    /// Claim a recipient's whole payout balance in an asset (requires the
+    /// recipient's auth). Returns the amount paid.
+    pub fn claim_payout(
+        env: Env,
+        recipient: Address,
+        asset: Address,
+    ) -> Result<i128, ProtocolError> {
+        claim_payout(env, recipient, asset)
+    }
+
+    /// Payout a recipient may claim in an asset
+    pub fn get_payout_balance(env: Env, recipient: Address, asset: Address) -> i128 {
+        payouts::PayoutStorage::get_balance(&env, &recipient, &asset)
+    }
+
+    /// Payouts in an asset waiting for conversion, oldest first
+    pub fn get_payout_queue(env: Env, asset: Address) -> Vec<payouts::PayoutShare> {
+        payouts::PayoutStorage::get_queue(&env, &asset)
+    }
+
+    /// Get an asset's token balance split into earmarked funds (reserves,
+    /// insurance, pending claims) and liquidity available to users
+    pub fn get_available_liquidity(env: Env, asset: Address) -> liquidity::LiquidityBreakdown {
+        get_available_liquidity(env, asset)
+    }
+
+    /// Force-close a position whose stored entry no longer decodes (admin only)
+    ///
+    /// Governance must first approve the same `resolution` for `user` via
+    /// `Action::ApproveForceClose`. Fails with `InvalidOperation` if the
+    /// position decodes normally.
+    pub fn force_close_position(
+        env: Env,
+        caller: String,
+        user: Address,
+        resolution: recovery::ForceCloseResolution,
+    ) -> Result<recovery::ForceCloseRecord, ProtocolError> {
+        force_close_position(env, caller, user, resolution)
+    }
+
+    /// Get every executed force-close, oldest first
+    pub fn get_force_close_audit(env: Env) -> Vec<recovery::ForceCloseRecord> {
+        recovery::RecoveryStorage::get_audit(&env)
+    }
+
+    /// Get the debt written off for an asset
+    pub fn get_bad_debt(env: Env, asset: Address) -> i128 {
+        recovery::RecoveryStorage::get_bad_debt(&env, &asset)
+    }
+
+    /// Schedule an emergency interest forgiveness funded from reserves (admin only).
+    /// It becomes executable after a 7 day delay.
+    pub fn schedule_emergency_forgiveness(
+        env: Env,
+        caller: String,
+        asset: Address,
+        users: Vec<Address>,
+        bps: i128,
+    ) -> Result<forgiveness::PendingForgiveness, ProtocolError> {
+        schedule_emergency_forgiveness(env, caller, asset, users, bps)
+    }
+
+    /// Execute the scheduled emergency forgiveness once its delay has passed (admin only)
+    ///
+    /// # Returns
+    /// * Total interest forgiven
+    pub fn execute_emergency_forgiveness(env: Env, caller: String) -> Result<i128, ProtocolError> {
+        execute_emergency_forgiveness(env, caller)
+    }
+
+    /// Get the scheduled emergency forgiveness, if any
+    pub fn get_pending_forgiveness(env: Env) -> Option<forgiveness::PendingForgiveness> {
+        forgiveness::InterestForgiveness::get_pending(&env)
+    }
+
+    /// Describe the features, limits and governance actions this deployment supports
+    ///
+    /// # Returns
+    /// * Capability descriptor derived from compiled constants and runtime flags
+    pub fn get_capabilities(env: Env) -> capabilities::Capabilities {
+        get_capabilities(env)
+    }
+
+    /// Toggle a runtime feature flag (admin only)
+    pub fn set_feature_flag(
+        env: Env,
+        caller: String,
+        flag: Symbol,
+        enabled: bool,
+    ) -> Result<(), ProtocolError> {
+        set_feature_flag(env, caller, flag, enabled)
+    }
+
+    /// Set the initial treasury address receiving slashed bonds and protocol fees (admin only)
+    ///
+    /// Later changes must use `propose_role_address_change`.
+    pub fn set_treasury(env: Env, caller: String, treasury: Address) -> Result<(), ProtocolError> {
+        set_treasury(env, caller, treasury)
+    }
+
+    /// Set the initial guardian (admin only)
+    ///
+    /// Later changes must use `propose_role_address_change`.
+    pub fn set_guardian(env: Env, caller: String, guardian: Address) -> Result<(), ProtocolError> {
+        set_guardian(env, caller, guardian)
+    }
+
+    /// Propose a timelocked change of the guardian or treasury address (admin only)
+    ///
+    /// # Arguments
+    /// * `role` - Role being changed
+    /// * `new_address` - Address taking over the role
+    ///
+    /// # Returns
+    /// * The pending change, including when it can be applied
+    pub fn propose_role_address_change(
+        env: Env,
+        caller: String,
+        role: roles::RoleKind,
+        new_address: Address,
+    ) -> Result<roles::PendingRoleChange, ProtocolError> {
+        propose_role_address_change(env, caller, role, new_address)
+    }
+
+    /// Apply a pending role change after its delay (admin only)
+    pub fn apply_role_address_change(
+        env: Env,
+        caller: String,
+        role: roles::RoleKind,
+    ) -> Result<Address, ProtocolError> {
+        apply_role_address_change(env, caller, role)
+    }
+
+    /// Cancel a pending role change (admin, or the guardian for guardian changes)
+    pub fn cancel_role_address_change(
+        env: Env,
+        caller: String,
+        role: roles::RoleKind,
+    ) -> Result<(), ProtocolError> {
+        cancel_role_address_change(env, caller, role)
+    }
+
+    /// Get the pending change for a role, if any
+    pub fn get_pending_role_change(
+        env: Env,
+        role: roles::RoleKind,
+    ) -> Option<roles::PendingRoleChange> {
+        get_pending_role_change(env, role)
+    }
+
+    /// Get recent configuration changes, oldest first
+    pub fn get_param_history(env: Env) -> Vec<history::ParamChange> {
+        get_param_history(env)
+    }
+
+    /// Configure listing bonds (admin only)
+    pub fn set_listing_config(
+        env: Env,
+        caller: String,
+        config: listing::ListingConfig,
+    ) -> Result<(), ProtocolError> {
+        set_listing_config(env, caller, config)
+    }
+
+    /// Propose a new asset listing backed by a bond
+    ///
+    /// # Arguments
+    /// * `caller` - Proposer posting the bond
+    /// * `token_info` - Registry key and token address to list
+    /// * `risk_params` - Initial risk parameters applied on approval
+    /// * `bond_amount` - Bond locked until the proposal is decided
+    ///
+    /// # Returns
+    /// * Listing proposal id
+    pub fn propose_listing(
+        env: Env,
+        caller: String,
+        token_info: listing::ListingTokenInfo,
+        risk_params: listing::ListingRiskParams,
+        bond_amount: i128,
+    ) -> Result<u64, ProtocolError> {
+        propose_listing(env, caller, token_info, risk_params, bond_amount)
+    }
+
+    /// Close an expired listing proposal and reclaim the unslashed bond
+    pub fn claim_expired_listing(
+        env: Env,
+        caller: String,
+        listing_id: u64,
+    ) -> Result<listing::ListingProposal, ProtocolError> {
+        claim_expired_listing(env, caller, listing_id)
+    }
+
+    /// Get a listing proposal
+    pub fn get_listing(env: Env, listing_id: u64) -> Option<listing::ListingProposal> {
+        get_listing(env, listing_id)
+    }
+
+    /// Escrow partner rewards streamed to suppliers of an asset
+    ///
+    /// # Arguments
+    /// * `sponsor` - Account funding the program
+    /// * `supplied_asset` - Asset whose suppliers earn the rewards
+    /// * `reward_asset` - Token paid out
+    /// * `amount` - Total rewards escrowed
+    /// * `duration_secs` - Length of the linear emission stream
+    ///
+    /// # Returns
+    /// * Incentive program id
+    pub fn fund_incentive(
+        env: Env,
+        sponsor: String,
+        supplied_asset: Address,
+        reward_asset: Address,
+        amount: i128,
+        duration_secs: u64,
+    ) -> Result<u64, ProtocolError> {
+        fund_incentive(
+            env,
+            sponsor,
+            supplied_asset,
+            reward_asset,
+            amount,
+            duration_secs,
+        )
+    }
+
+    /// Claim accrued rewards from an incentive program
+    pub fn claim_incentive(
+        env: Env,
+        caller: String,
+        program_id: u64,
+    ) -> Result<i128, ProtocolError> {
+        claim_incentive(env, caller, program_id)
+    }
+
+    /// Return the undistributed remainder of an expired program to its sponsor
+    pub fn sweep_incentive(
+        env: Env,
+        caller: String,
+        program_id: u64,
+    ) -> Result<i128, ProtocolError> {
+        sweep_incentive(env, caller, program_id)
+    }
+
+    /// Get an incentive program
+    pub fn get_incentive_program(
+        env: Env,
+        program_id: u64,
+    ) -> Option<incentives::IncentiveProgram> {
+        incentives::IncentiveStorage::get_program(&env, program_id)
+    }
+
+    /// Schedule automatic repayment of `amount_per_period` every `period_secs`
+    /// from the caller's supplied balance, replacing any existing plan
+    pub fn create_repay_plan(
+        env: Env,
+        caller: String,
+        asset: Address,
+        amount_per_period: i128,
+        period_secs: u64,
+    ) -> Result<autorepay::RepayPlan, ProtocolError> {
+        create_repay_plan(env, caller, asset, amount_per_period, period_secs)
+    }
+
+    /// Cancel the caller's repayment plan
+    pub fn cancel_repay_plan(env: Env, caller: String) -> Result<(), ProtocolError> {
+        cancel_repay_plan(env, caller)
+    }
+
+    /// Execute a user's due installment (permissionless keeper job)
+    pub fn execute_repay_plan(
+        env: Env,
+        user: Address,
+    ) -> Result<autorepay::RepayPlanOutcome, ProtocolError> {
+        execute_repay_plan(env, user)
+    }
+
+    /// Get a user's repayment plan
+    pub fn get_repay_plan(env: Env, user: Address) -> Option<autorepay::RepayPlan> {
+        autorepay::RepayPlanStorage::get(&env, &user)
+    }
+
+    /// Move the caller's position to a governance-approved contract deployment
+    ///
+    /// # Arguments
+    /// * `caller` - Position owner
+    /// * `target_contract` - Allowlisted contract implementing `receive_migrated_position`
+    ///
+    /// # Returns
+    /// * The collateral and debt handed to the target, accrued interest included
+    pub fn migrate_position_out(
+        env: Env,
+        caller: String,
+        target_contract: Address,
+    ) -> Result<(migration::CollateralSpec, migration::DebtSpec), ProtocolError> {
+        migrate_position_out(env, caller, target_contract)
+    }
+
+    /// Offer the caller's position to another account they control, such as
+    /// a new smart wallet. The receiver completes the transfer in its own
+    /// transaction before the window ends; the owner depositing,
+    /// withdrawing, borrowing or repaying before then cancels the offer.
+    ///
+    /// # Arguments
+    /// * `from` - Current owner; must authorize
+    /// * `to` - Receiving account
+    /// * `nonce` - The owner's next transfer nonce, starting at 0
+    pub fn initiate_transfer(
+        env: Env,
+        from: Address,
+        to: Address,
+        nonce: u64,
+    ) -> Result<transfer::PendingTransfer, ProtocolError> {
+        initiate_transfer(env, from, to, nonce)
+    }
+
+    /// Take over a position offered to the caller
+    ///
+    /// # Arguments
+    /// * `to` - Receiver named by the offer; must authorize and hold no
+    ///   open position
+    /// * `from` - Current owner
+    /// * `nonce` - Nonce the offer was made with
+    ///
+    /// Voting power and any co-signing policy move with the position; credit
+    /// allowances granted by the old account are dropped.
+    pub fn complete_transfer(
+        env: Env,
+        to: Address,
+        from: Address,
+        nonce: u64,
+    ) -> Result<(), ProtocolError> {
+        complete_transfer(env, to, from, nonce)
+    }
+
+    /// Set how long receivers have to complete a position transfer (admin only)
+    pub fn set_transfer_window(env: Env, caller: String, secs: u64) -> Result<(), ProtocolError> {
+        set_transfer_window(env, caller, secs)
+    }
+
+    /// Get the pending transfer of a user's position
+    pub fn get_pending_transfer(env: Env, from: Address) -> Option<transfer::PendingTransfer> {
+        transfer::TransferStorage::get_pending(&env, &from)
+    }
+
+    /// Execute a queued proposal once its timelock has passed, applying its
+    /// actions in order; if any action fails nothing is applied. Actions past
+    /// the epoch's change budget are carried over to the next epoch. Anyone
+    /// may call this.
+    pub fn execute_proposal(
+        env: Env,
+        proposal_id: u64,
+    ) -> Result<governance::Proposal, ProtocolError> {
+        execute_proposal(env, proposal_id)
+    }
+
+    /// Execute the actions a proposal carried over once their epoch has come
+    /// (admin only). Returns the number still deferred.
+    pub fn execute_carryover(
+        env: Env,
+        caller: String,
+        proposal_id: u64,
+    ) -> Result<u32, ProtocolError> {
+        execute_carryover(env, caller, proposal_id)
+    }
+
+    /// Get the actions a proposal carried over to a later epoch
+    pub fn get_carryover(env: Env, proposal_id: u64) -> Option<govbudget::Carryover> {
+        govbudget::BudgetStorage::get_carryover(&env, proposal_id)
+    }
+
+    /// Get the governance change budget left in the current epoch
+    pub fn get_change_budget_remaining(env: Env) -> u32 {
+        govbudget::ChangeBudget::remaining(&env)
+    }
+
+    /// Register a reason code admins may state for manual changes (admin only)
+    pub fn register_reason_code(
+        env: Env,
+        caller: String,
+        code: Symbol,
+    ) -> Result<(), ProtocolError> {
+        register_reason_code(env, caller, code)
+    }
+
+    /// Remove a registered reason code (admin only)
+    pub fn remove_reason_code(env: Env, caller: String, code: Symbol) -> Result<(), ProtocolError> {
+        remove_reason_code(env, caller, code)
+    }
+
+    /// Get the registered reason codes
+    pub fn get_reason_codes(env: Env) -> Vec<Symbol> {
+        reasons::ReasonCodes::get(&env)
+    }
+
+    /// Set the supply cap for an asset (admin only, 0 = unlimited)
+    pub fn set_supply_cap(
+        env: Env,
+        caller: String,
+        asset: Address,
+        cap: i128,
+    ) -> Result<(), ProtocolError> {
+        set_supply_cap(env, caller, asset, cap)
+    }
+
+    /// Accrue the global borrow index and primary asset reward emissions (permissionless)
+    ///
+    /// # Returns
+    /// * Accrual state after this call; repeated calls finish a long catch-up
+    pub fn accrue(env: Env) -> accrual::AccrualState {
+        accrue(env)
+    }
+
+    /// Accrue interest on up to 10 positions and refresh their voting power (permissionless)
+    ///
+    /// # Returns
+    /// * Total voting power after the sync
+    pub fn sync_positions(env: Env, users: Vec<Address>) -> Result<i128, ProtocolError> {
+        sync_positions(env, users)
+    }
+
+    /// Get the weight a user votes with: their power as of their last sync
+    /// unless delegated away, plus the synced power delegated to them
+    pub fn get_voting_power(env: Env, user: Address) -> i128 {
+        governance::Governance::get_voting_power(&env, &user)
+    }
+
+    /// Delegate the delegator's voting power to another account (requires
+    /// the delegator's auth). Refused while the delegator or its current
+    /// delegate has voted on a proposal still open for votes.
+    pub fn delegate_votes(
+        env: Env,
+        delegator: Address,
+        delegatee: Address,
+    ) -> Result<(), ProtocolError> {
+        governance::Governance::delegate(&env, &delegator, &delegatee)
+    }
+
+    /// Account a user delegated their voting power to, if any
+    pub fn get_vote_delegate(env: Env, delegator: Address) -> Option<Address> {
+        governance::Governance::get_delegate(&env, &delegator)
+    }
+
+    /// Get the total voting power across synced positions
+    pub fn get_total_voting_power(env: Env) -> i128 {
+        voting::VotingStorage::get_total_power(&env)
+    }
+
+    /// Get a proposal with its current vote counts
+    pub fn get_proposal(env: Env, id: u64) -> Option<governance::Proposal> {
+        governance::GovStorage::get_proposal(&env, id)
+    }
+
+    /// Get the votes cast on a proposal
+    pub fn get_proposal_tally(env: Env, id: u64) -> governance::Tally {
+        governance::GovStorage::get_tally(&env, id)
+    }
+
+    /// Link `voter` to the ed25519 key that signs its relayed votes
+    /// (requires the voter's auth and `signature`, by that key, over
+    /// `get_vote_key_message`)
+    pub fn register_vote_key(
+        env: Env,
+        voter: Address,
+        pubkey: BytesN<32>,
+        signature: BytesN<64>,
+    ) -> Result<(), ProtocolError> {
+        governance::Governance::register_vote_key(&env, &voter, &pubkey, &signature)
+    }
+
+    /// Submit a vote signed offline by a registered vote key
+    ///
+    /// # Arguments
+    /// * `relayer` - Account submitting the transaction, named in the signed
+    ///   message; must authorize but needs no voting rights
+    /// * `choice` - `true` to vote for, `false` against
+    /// * `expiry` - Last ledger timestamp the signature is valid for
+    /// * `signature` - ed25519 signature over `get_vote_message`
+    ///
+    /// # Returns
+    /// * The proposal with updated vote counts
+    pub fn vote_by_sig(
+        env: Env,
+        relayer: Address,
+        proposal_id: u64,
+        voter_pubkey: BytesN<32>,
+        choice: bool,
+        expiry: u64,
+        signature: BytesN<64>,
+    ) -> Result<governance::Proposal, ProtocolError> {
+        vote_by_sig(
+            env,
+            relayer,
+            proposal_id,
+            voter_pubkey,
+            choice,
+            expiry,
+            signature,
+        )
+    }
+
+    /// Vote key registered to an account, if any
+    pub fn get_vote_key(env: Env, voter: Address) -> Option<BytesN<32>> {
+        governance::GovStorage::get_vote_key(&env, &voter)
+    }
 
-#[contractimpl]
-impl Contract {
-    /// Initializes the contract and sets the admin address
-    pub fn initialize(env: Env, admin: String) -> Result<(), ProtocolError> {
-        let _guard = ReentrancyScope::enter(&env)?;
-        let admin_addr = AddressHelper::require_valid_address(&env, &admin)?;
-        if env
-            .storage()
-            .instance()
-            .has(&ProtocolConfig::admin_key(&env))
-        {
-            return Err(ProtocolError::AlreadyInitialized);
-        }
-        ProtocolConfig::set_admin(&env, &admin_addr);
-        UserManager::bootstrap_admin(&env, &admin_addr);
+    /// Nonce the account's next signed vote must carry
+    pub fn get_vote_nonce(env: Env, voter: Address) -> u64 {
+        governance::GovStorage::get_vote_nonce(&env, &voter)
+    }
 
-        // Initialize interest rate system with default configuration
-        let config = InterestRateConfig::default();
-        InterestRateStorage::save_config(&env, &config);
+    /// Message an account's vote key must sign for `relayer` to submit its
+    /// vote through `vote_by_sig`, at its current nonce
+    pub fn get_vote_message(
+        env: Env,
+        proposal_id: u64,
+        voter: Address,
+        choice: bool,
+        expiry: u64,
+        relayer: Address,
+    ) -> Bytes {
+        governance::Governance::vote_message(&env, proposal_id, &voter, choice, expiry, &relayer)
+    }
 
-        let state = InterestRateState::initial();
-        InterestRateStorage::save_state(&env, &state);
+    /// Message a key must sign to be registered to an account through
+    /// `register_vote_key`, at the account's current nonce
+    pub fn get_vote_key_message(env: Env, voter: Address) -> Bytes {
+        governance::Governance::vote_key_message(&env, &voter)
+    }
 
-        // Initialize risk management system with default configuration
-        let risk_config = RiskConfig::default();
-        RiskConfigStorage::save(&env, &risk_config);
+    /// Lifecycle state of a proposal, if it exists
+    pub fn get_proposal_state(env: Env, id: u64) -> Option<governance::ProposalState> {
+        governance::Governance::state(&env, id)
+    }
 
-        Ok(())
+    /// Actions a proposal applies when executed
+    pub fn get_proposal_actions(env: Env, id: u64) -> Vec<governance::Action> {
+        governance::GovStorage::get_actions(&env, id)
     }
 
-    /// Set the minimum collateral ratio (admin only)
-    pub fn set_min_collateral_ratio(
+    /// Whether a proposal has been cancelled
+    pub fn is_proposal_cancelled(env: Env, id: u64) -> bool {
+        governance::GovStorage::is_cancelled(&env, id)
+    }
+
+    /// Cancel a proposal: by its proposer until voting ends, or by the
+    /// guardian or admin at any time before execution
+    pub fn cancel_proposal(
         env: Env,
         caller: String,
-        ratio: i128,
-    ) -> Result<(), ProtocolError> {
-        let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
-        ProtocolConfig::set_min_collateral_ratio(&env, &caller_addr, ratio)?;
-        Ok(())
+        proposal_id: u64,
+    ) -> Result<governance::Proposal, ProtocolError> {
+        cancel_proposal(env, caller, proposal_id)
     }
 
-    /// Deposit collateral into the protocol
-    pub fn deposit_collateral(
+    /// Get a voter's receipt on a proposal, wherever it is currently stored
+    pub fn get_vote_receipt(env: Env, id: u64, voter: Address) -> Option<governance::VoteReceipt> {
+        governance::GovStorage::get_receipt(&env, id, &voter)
+    }
+
+    /// Move up to `max_entries` vote receipts of a proposal from the legacy
+    /// instance map into per-voter persistent entries (admin only)
+    ///
+    /// # Returns
+    /// * How many moved, how many remain, and whether the proposal is done
+    pub fn migrate_gov_receipts(
         env: Env,
-        depositor: String,
-        amount: i128,
-    ) -> Result<(), ProtocolError> {
-        deposit_collateral(env, depositor, amount)
+        caller: String,
+        proposal_id: u64,
+        max_entries: u32,
+    ) -> Result<governance::MigrationProgress, ProtocolError> {
+        migrate_gov_receipts(env, caller, proposal_id, max_entries)
     }
 
-    /// Borrow assets from the protocol
-    pub fn borrow(env: Env, borrower: String, amount: i128) -> Result<(), ProtocolError> {
-        borrow(env, borrower, amount)
+    /// Get the global accrual state (borrow index and accrued-to time)
+    pub fn get_accrual_state(env: Env) -> accrual::AccrualState {
+        accrual::AccrualStorage::get_state(&env)
     }
 
-    /// Repay borrowed assets
-    pub fn repay(env: Env, repayer: String, amount: i128) -> Result<(), ProtocolError> {
-        repay(env, repayer, amount)
+    /// Hash committing to version, per-asset totals and reserves, the borrow
+    /// index, and emergency flags (see the `commitment` module for the encoding)
+    pub fn state_commitment(env: Env) -> BytesN<32> {
+        commitment::StateCommitment::compute(&env)
     }
 
-    /// Withdraw collateral from the protocol
-    pub fn withdraw(env: Env, withdrawer: String, amount: i128) -> Result<(), ProtocolError> {
-        withdraw(env, withdrawer, amount)
+    /// Store and emit the current state commitment with its ledger sequence
+    /// (permissionless keeper job)
+    pub fn commit_state(env: Env) -> commitment::StoredCommitment {
+        commitment::StateCommitment::commit(&env)
     }
 
-    /// Liquidate an undercollateralized position
-    pub fn liquidate(
+    /// Get the most recently stored state commitment
+    pub fn get_state_commitment(env: Env) -> Option<commitment::StoredCommitment> {
+        commitment::StateCommitment::get_latest(&env)
+    }
+
+    /// Accrue scheduled reward emissions for an asset up to now
+    pub fn accrue_rewards(env: Env, asset: Address) -> rewards::RewardState {
+        accrue_rewards(env, asset)
+    }
+
+    /// Get the reward emission schedule for an asset
+    pub fn get_emission_schedule(env: Env, asset: Address) -> Vec<rewards::EmissionSegment> {
+        get_emission_schedule(env, asset)
+    }
+
+    /// Set the maximum gap accrued per chunk (admin only)
+    pub fn set_max_accrual_gap(env: Env, caller: String, secs: u64) -> Result<(), ProtocolError> {
+        set_max_accrual_gap(env, caller, secs)
+    }
+
+    /// Set how far, in bps, the borrow rate must move before a
+    /// `rate_changed` event is emitted (admin only, 0 = every move)
+    pub fn set_rate_event_threshold(
         env: Env,
-        liquidator: String,
-        user: String,
-        amount: i128,
-        min_out: i128,
+        caller: String,
+        bps: i128,
     ) -> Result<(), ProtocolError> {
-        liquidate(env, liquidator, user, amount, min_out)
+        set_rate_event_threshold(env, caller, bps)
     }
 
-    /// Get user position
-    pub fn get_position(env: Env, user: String) -> Result<(i128, i128, i128), ProtocolError> {
-        get_position(env, user)
+    /// Set how long after a global accrual zero-debt deposits and
+    /// withdrawals may skip re-accruing (admin only, 0 = same timestamp)
+    pub fn set_accrual_fresh_window(
+        env: Env,
+        caller: String,
+        secs: u64,
+    ) -> Result<(), ProtocolError> {
+        set_accrual_fresh_window(env, caller, secs)
     }
 
-    /// Set risk parameters (admin only)
-    pub fn set_risk_params(
+    /// Reconcile the contract's token balance against internal accounting
+    ///
+    /// # Arguments
+    /// * `asset` - The asset to reconcile
+    ///
+    /// # Returns
+    /// * Report with actual and expected balances and their delta
+    pub fn reconcile(
+        env: Env,
+        asset: Address,
+    ) -> Result<reconcile::ReconcileReport, ProtocolError> {
+        reconcile(env, asset)
+    }
+
+    /// Get the most recent reconciliation report for an asset
+    pub fn get_last_reconcile(env: Env, asset: Address) -> Option<reconcile::ReconcileReport> {
+        get_last_reconcile(env, asset)
+    }
+
+    /// Set the reconciliation delta tolerance (admin only)
+    pub fn set_reconcile_tolerance(
         env: Env,
         caller: String,
-        close_factor: i128,
-        liquidation_incentive: i128,
+        tolerance: i128,
     ) -> Result<(), ProtocolError> {
-        set_risk_params(env, caller, close_factor, liquidation_incentive)
+        set_reconcile_tolerance(env, caller, tolerance)
+    }
+
+    /// Decode known storage entries and report any that fail (admin only)
+    ///
+    /// # Arguments
+    /// * `caller` - Admin address
+    /// * `sample_users` - Users whose per-user entries should also be checked
+    ///
+    /// # Returns
+    /// * Entries that could not be decoded with the current types
+    pub fn verify_storage(
+        env: Env,
+        caller: String,
+        sample_users: Vec<Address>,
+    ) -> Result<Vec<diagnostics::StorageIssue>, ProtocolError> {
+        verify_storage(env, caller, sample_users)
+    }
+
+    /// Get per-entrypoint call counters (populated only while `diagnostics_enabled` is set)
+    pub fn get_call_stats(env: Env) -> Map<Symbol, diagnostics::CallStats> {
+        get_call_stats(env)
     }
 
     /// Set pause switches (admin only)
+    /// `change_reason` is a registered reason code, required in strict mode.
     pub fn set_pause_switches(
         env: Env,
         caller: String,
@@ -3460,6 +7069,7 @@ impl Contract {
         pause_deposit: bool,
         pause_withdraw: bool,
         pause_liquidate: bool,
+        change_reason: Option<Symbol>,
     ) -> Result<(), ProtocolError> {
         set_pause_switches(
             env,
@@ -3468,6 +7078,7 @@ impl Contract {
             pause_deposit,
             pause_withdraw,
             pause_liquidate,
+            change_reason,
         )
     }
 
@@ -3490,6 +7101,31 @@ impl Contract {
         get_system_stats(env)
     }
 
+    /// Emergency status, totals, and TVL against the global cap
+    pub fn get_system_status(env: Env) -> Result<SystemStatus, ProtocolError> {
+        get_system_status(env)
+    }
+
+    /// Get the USD debt backed by an asset as collateral, each position's
+    /// debt attributed pro rata to the value of its collateral assets
+    pub fn get_collateral_exposure(env: Env, asset: Address) -> i128 {
+        analytics::AnalyticsModule::get_collateral_exposure(&env, &asset)
+    }
+
+    /// Get spot and smoothed utilization along with current rates for an asset
+    pub fn get_asset_state(env: Env, asset: Address) -> Result<AssetState, ProtocolError> {
+        get_asset_state(env, asset)
+    }
+
+    /// Set the utilization EMA weight in bps per hour (admin only, 1..=10000)
+    pub fn set_utilization_ema_alpha(
+        env: Env,
+        caller: String,
+        alpha_bps: i128,
+    ) -> Result<(), ProtocolError> {
+        set_utilization_ema_alpha(env, caller, alpha_bps)
+    }
+
     pub fn set_emergency_manager(
         env: Env,
         caller: String,
@@ -3503,20 +7139,26 @@ impl Contract {
         env: Env,
         caller: String,
         reason: Option<String>,
+        change_reason: Option<Symbol>,
     ) -> Result<(), ProtocolError> {
-        trigger_emergency_pause(env, caller, reason)
+        trigger_emergency_pause(env, caller, reason, change_reason)
     }
 
     pub fn enter_recovery_mode(
         env: Env,
         caller: String,
         plan: Option<String>,
+        change_reason: Option<Symbol>,
     ) -> Result<(), ProtocolError> {
-        enter_recovery_mode(env, caller, plan)
+        enter_recovery_mode(env, caller, plan, change_reason)
     }
 
-    pub fn resume_operations(env: Env, caller: String) -> Result<(), ProtocolError> {
-        resume_operations(env, caller)
+    pub fn resume_operations(
+        env: Env,
+        caller: String,
+        change_reason: Option<Symbol>,
+    ) -> Result<(), ProtocolError> {
+        resume_operations(env, caller, change_reason)
     }
 
     pub fn record_recovery_step(
@@ -3562,12 +7204,26 @@ impl Contract {
         get_event_aggregates(env)
     }
 
+    /// Page through the retained events of one type, oldest first (at most 50 per call)
     pub fn get_events_for_type(
         env: Env,
         event_type: Symbol,
+        cursor: u32,
+        limit: u32,
+    ) -> Result<EventPage, ProtocolError> {
+        get_events_for_type(env, event_type, cursor, limit)
+    }
+
+    /// Events with `event_id >= min_event_id`, in id order (at most 50 per
+    /// call). Resume from the last returned id plus one; if the first
+    /// returned id is above `min_event_id`, the missing events have aged out
+    /// of the feed.
+    pub fn get_events_since(
+        env: Env,
+        min_event_id: u64,
         limit: u32,
     ) -> Result<Vec<EventRecord>, ProtocolError> {
-        get_events_for_type(env, event_type, limit)
+        get_events_since(env, min_event_id, limit)
     }
 
     pub fn get_recent_event_types(env: Env) -> Result<Vec<Symbol>, ProtocolError> {
@@ -3595,6 +7251,11 @@ impl Contract {
         get_registered_asset(env, key)
     }
 
+    /// List registered assets in first-registration order
+    pub fn list_registered_assets(env: Env) -> Vec<(Symbol, Address)> {
+        list_registered_assets(env)
+    }
+
     pub fn set_user_role(
         env: Env,
         caller: String,
@@ -3633,12 +7294,55 @@ impl Contract {
         )
     }
 
-    pub fn freeze_user(env: Env, caller: String, user: Address) -> Result<(), ProtocolError> {
-        freeze_user(env, caller, user)
+    /// Freeze a user, recording a reason code and optional compliance case reference
+    /// `change_reason` is a registered reason code, required in strict mode.
+    pub fn freeze_user(
+        env: Env,
+        caller: String,
+        user: Address,
+        reason_code: u32,
+        case_ref: Option<BytesN<32>>,
+        change_reason: Option<Symbol>,
+    ) -> Result<(), ProtocolError> {
+        freeze_user(env, caller, user, reason_code, case_ref, change_reason)
+    }
+
+    pub fn unfreeze_user(
+        env: Env,
+        caller: String,
+        user: Address,
+        change_reason: Option<Symbol>,
+    ) -> Result<(), ProtocolError> {
+        unfreeze_user(env, caller, user, change_reason)
+    }
+
+    /// Freeze up to 20 users at once with a shared reason
+    /// `change_reason` is a registered reason code, required in strict mode.
+    pub fn freeze_users(
+        env: Env,
+        caller: String,
+        users: Vec<Address>,
+        reason_code: u32,
+        case_ref: Option<BytesN<32>>,
+        change_reason: Option<Symbol>,
+    ) -> Result<(), ProtocolError> {
+        freeze_users(env, caller, users, reason_code, case_ref, change_reason)
+    }
+
+    /// Unfreeze up to 20 users at once
+    /// `change_reason` is a registered reason code, required in strict mode.
+    pub fn unfreeze_users(
+        env: Env,
+        caller: String,
+        users: Vec<Address>,
+        change_reason: Option<Symbol>,
+    ) -> Result<(), ProtocolError> {
+        unfreeze_users(env, caller, users, change_reason)
     }
 
-    pub fn unfreeze_user(env: Env, caller: String, user: Address) -> Result<(), ProtocolError> {
-        unfreeze_user(env, caller, user)
+    /// Reason and case reference of a user's current freeze, if frozen
+    pub fn get_freeze_info(env: Env, user: Address) -> Option<FreezeInfo> {
+        get_freeze_info(env, user)
     }
 
     pub fn get_user_profile(env: Env, user: Address) -> Result<UserProfile, ProtocolError> {
@@ -3662,6 +7366,34 @@ impl Contract {
         analytics::AnalyticsModule::get_asset_report(&env, &asset)
     }
 
+    /// Get the daily analytics snapshot for `day` (timestamp / 86400)
+    pub fn get_daily_snapshot(env: Env, day: u64) -> Option<analytics::HistoricalDataPoint> {
+        analytics::AnalyticsModule::get_daily_snapshot(&env, day)
+    }
+
+    /// Get the `(borrow_index, supply_index, timestamp)` checkpoint recorded
+    /// for `asset` on `day` (timestamp / 86400). The last 90 days are kept.
+    pub fn get_index_checkpoint(env: Env, asset: Address, day: u64) -> Option<(i128, i128, u64)> {
+        checkpoints::IndexCheckpoints::get(&env, &asset, day)
+    }
+
+    /// Estimate `(borrow_index, supply_index)` for `asset` at `ts` by linear
+    /// interpolation between daily checkpoints
+    pub fn interpolate_index(env: Env, asset: Address, ts: u64) -> Option<(i128, i128)> {
+        checkpoints::IndexCheckpoints::interpolate(&env, &asset, ts)
+    }
+
+    /// Get position size distribution for an asset
+    ///
+    /// # Arguments
+    /// * `asset` - The asset address
+    ///
+    /// # Returns
+    /// * Collateral and debt position counts by size bucket
+    pub fn get_asset_distribution(env: Env, asset: Address) -> analytics::AssetDistribution {
+        analytics::AnalyticsModule::get_asset_distribution(&env, &asset)
+    }
+
     pub fn calculate_risk_analytics(env: Env) -> Result<analytics::RiskAnalytics, ProtocolError> {
         analytics::AnalyticsModule::calculate_risk_analytics(&env)
     }
@@ -3689,18 +7421,24 @@ impl Contract {
         asset: Option<Address>,
     ) -> Result<(), ProtocolError> {
         let user_addr = AddressHelper::require_valid_address(&env, &user)?;
-        let activity = activity_type.to_string();
-        analytics::AnalyticsModule::record_activity(
-            &env,
-            &user_addr,
-            activity.as_str(),
-            amount,
-            asset,
-        )
+        // soroban_sdk::String has no `to_string` outside test builds
+        let mut buf = [0u8; 64];
+        let len = activity_type.len() as usize;
+        if len > buf.len() {
+            return Err(ProtocolError::StringTooLong);
+        }
+        activity_type.copy_into_slice(&mut buf[..len]);
+        let activity =
+            core::str::from_utf8(&buf[..len]).map_err(|_| ProtocolError::InvalidInput)?;
+        analytics::AnalyticsModule::record_activity(&env, &user_addr, activity, amount, asset)
     }
+}
 
-    // ==================== AMM Registry and Swap Hooks ====================
+// ==================== AMM Registry and Swap Hooks ====================
 
+#[cfg(feature = "amm")]
+#[contractimpl]
+impl Contract {
     /// Register a new AMM asset pair for swap operations
     ///
     /// # Arguments
@@ -3770,7 +7508,9 @@ impl Contract {
         env: Env,
         params: amm::SwapParams,
     ) -> Result<amm::SwapResult, ProtocolError> {
-        let _guard = ReentrancyScope::enter(&env)?;
+        // `execute_swap` holds the reentrancy guard itself
+        beta::BetaAccess::require_beta_access(&env, &params.user)?;
+        diagnostics::CallTracker::track_call(&env, "execute_amm_swap", &params.user);
         amm::AMMRegistry::execute_swap(&env, params)
     }
 
@@ -3796,8 +7536,7 @@ impl Contract {
         collateral_amount: i128,
         min_debt_amount: i128,
     ) -> Result<amm::SwapResult, ProtocolError> {
-        let _guard = ReentrancyScope::enter(&env)?;
-
+        // `execute_swap` holds the reentrancy guard itself
         amm::AMMRegistry::liquidation_swap_hook(
             &env,
             &liquidator,
@@ -3817,6 +7556,7 @@ impl Contract {
     /// * `debt_asset` - Debt asset to repay
     /// * `sell_amount` - Amount to sell
     /// * `min_debt_repayment` - Minimum debt repayment expected
+    /// * `deadline_ts` - Latest execution time (0 = no deadline)
     ///
     /// # Returns
     /// * Swap result with actual amounts
@@ -3828,9 +7568,9 @@ impl Contract {
         debt_asset: Address,
         sell_amount: i128,
         min_debt_repayment: i128,
+        deadline_ts: u64,
     ) -> Result<amm::SwapResult, ProtocolError> {
-        let _guard = ReentrancyScope::enter(&env)?;
-
+        // `execute_swap` holds the reentrancy guard itself
         amm::AMMRegistry::deleverage_swap_hook(
             &env,
             &user,
@@ -3838,6 +7578,7 @@ impl Contract {
             &debt_asset,
             sell_amount,
             min_debt_repayment,
+            deadline_ts,
         )
     }
 
@@ -3857,12 +7598,12 @@ impl Contract {
         amm::AMMRegistry::get_all_pairs(&env)
     }
 
-    /// Get AMM swap history for analytics
+    /// Get AMM swap history for analytics, oldest first
     ///
     /// # Returns
-    /// * Vector of recent swap results (last 100)
-    pub fn get_amm_swap_history(env: Env) -> Vec<amm::SwapResult> {
-        amm::AMMRegistry::get_swap_history(&env)
+    /// * Page of retained swap results (the last 100 are kept, at most 50 per call)
+    pub fn get_amm_swap_history(env: Env, cursor: u32, limit: u32) -> amm::SwapPage {
+        amm::AMMRegistry::get_swap_page(&env, cursor, limit)
     }
 
     /// Deactivate an AMM pair
@@ -3906,4 +7647,269 @@ impl Contract {
 
         amm::AMMRegistry::activate_pair(&env, &asset_a, &asset_b)
     }
+
+    /// Set the swap fee of an AMM pair (admin only, at most 10%)
+    /// `change_reason` is a registered reason code, required in strict mode.
+    pub fn set_pair_fee(
+        env: Env,
+        caller: Address,
+        pair_id: amm::PairKey,
+        fee_bps: i128,
+        change_reason: Option<Symbol>,
+    ) -> Result<(), ProtocolError> {
+        let _reason = reasons::ChangeReason::enter(&env, &caller, "pair_fee", change_reason)?;
+        ProtocolConfig::require_admin(&env, &caller)?;
+        amm::AMMRegistry::set_pair_fee(&env, &pair_id, fee_bps)
+    }
+
+    /// Pause or unpause an AMM pair (admin only).
+    /// A paused pair rejects swaps and liquidity additions; removals stay open.
+    /// `change_reason` is a registered reason code, required in strict mode.
+    pub fn set_pair_paused(
+        env: Env,
+        caller: Address,
+        pair_id: amm::PairKey,
+        paused: bool,
+        change_reason: Option<Symbol>,
+    ) -> Result<(), ProtocolError> {
+        let _reason = reasons::ChangeReason::enter(&env, &caller, "pair_paused", change_reason)?;
+        ProtocolConfig::require_admin(&env, &caller)?;
+        amm::AMMRegistry::set_pair_paused(&env, &pair_id, paused)
+    }
+
+    /// Add liquidity to an AMM pair's internal pool
+    ///
+    /// # Returns
+    /// * LP shares minted to `provider`
+    pub fn add_amm_liquidity(
+        env: Env,
+        provider: Address,
+        pair_id: amm::PairKey,
+        amount_a: i128,
+        amount_b: i128,
+    ) -> Result<i128, ProtocolError> {
+        let _guard = ReentrancyScope::enter(&env)?;
+        beta::BetaAccess::require_beta_access(&env, &provider)?;
+        amm::AMMRegistry::add_liquidity(&env, &provider, &pair_id, amount_a, amount_b)
+    }
+
+    /// Burn LP shares and withdraw the matching reserves
+    ///
+    /// # Returns
+    /// * `(amount_a, amount_b)` paid out to `provider`
+    pub fn remove_amm_liquidity(
+        env: Env,
+        provider: Address,
+        pair_id: amm::PairKey,
+        shares: i128,
+    ) -> Result<(i128, i128), ProtocolError> {
+        let _guard = ReentrancyScope::enter(&env)?;
+        amm::AMMRegistry::remove_liquidity(&env, &provider, &pair_id, shares)
+    }
+
+    /// Flash swap: receive pool outputs first, pay back within `on_flash_swap`.
+    /// Reverts with `FlashSwapInvariantViolated` if the fee-adjusted constant
+    /// product ends up below where it started. Fails with `DeadlineExceeded`
+    /// if executed after `deadline_ts` (0 = no deadline).
+    ///
+    /// # Returns
+    /// * Amounts of each pair asset paid back in
+    #[allow(clippy::too_many_arguments)]
+    pub fn flash_swap(
+        env: Env,
+        caller: Address,
+        pair_id: amm::PairKey,
+        amount_out_a: i128,
+        amount_out_b: i128,
+        receiver: Address,
+        data: soroban_sdk::Bytes,
+        deadline_ts: u64,
+    ) -> Result<(i128, i128), ProtocolError> {
+        Deadline::check(&env, deadline_ts)?;
+        beta::BetaAccess::require_beta_access(&env, &caller)?;
+        amm::AMMRegistry::flash_swap(
+            &env,
+            &caller,
+            &pair_id,
+            amount_out_a,
+            amount_out_b,
+            &receiver,
+            data,
+        )
+    }
+
+    /// A provider's LP shares in a pair with their current redeemable
+    /// amounts, the deposits paid for them and the impermanent loss in bps
+    /// (0 or negative) since entry
+    pub fn get_lp_position(
+        env: Env,
+        pair_id: amm::PairKey,
+        provider: Address,
+    ) -> Result<amm::LpPosition, ProtocolError> {
+        amm::AMMRegistry::get_lp_position(&env, &pair_id, &provider)
+    }
+
+    /// Reserves, fee and pause state of an AMM pair
+    pub fn get_pool_info(env: Env, pair_id: amm::PairKey) -> Result<amm::PoolInfo, ProtocolError> {
+        amm::AMMRegistry::get_pool_info(&env, &pair_id)
+    }
+
+    /// Quote swapping exactly `amount_in` of `token_in` without executing.
+    /// Uses the same math as `execute_amm_swap`, so at the same state the
+    /// quoted output is what the swap pays.
+    pub fn quote_swap_in(
+        env: Env,
+        pair_id: amm::PairKey,
+        token_in: Address,
+        amount_in: i128,
+    ) -> Result<amm::QuoteResult, ProtocolError> {
+        amm::AMMRegistry::quote_swap_in(&env, &pair_id, &token_in, amount_in)
+    }
+
+    /// Quote the smallest input that buys at least `amount_out` of
+    /// `token_out` without executing
+    pub fn quote_swap_out(
+        env: Env,
+        pair_id: amm::PairKey,
+        token_out: Address,
+        amount_out: i128,
+    ) -> Result<amm::QuoteResult, ProtocolError> {
+        amm::AMMRegistry::quote_swap_out(&env, &pair_id, &token_out, amount_out)
+    }
+
+    /// Quote an exact-input swap along `path` (assets, first to last), one
+    /// quote per hop
+    pub fn quote_route_in(
+        env: Env,
+        path: Vec<Address>,
+        amount_in: i128,
+    ) -> Result<Vec<amm::QuoteResult>, ProtocolError> {
+        amm::AMMRegistry::quote_route_in(&env, &path, amount_in)
+    }
+
+    /// Quote an exact-output swap along `path` (assets, first to last), one
+    /// quote per hop
+    pub fn quote_route_out(
+        env: Env,
+        path: Vec<Address>,
+        amount_out: i128,
+    ) -> Result<Vec<amm::QuoteResult>, ProtocolError> {
+        amm::AMMRegistry::quote_route_out(&env, &path, amount_out)
+    }
+
+    /// Set the address receiving tokens bought back from reserves (admin only)
+    pub fn set_burn_address(
+        env: Env,
+        caller: Address,
+        burn_address: Address,
+    ) -> Result<(), ProtocolError> {
+        buyback::Buyback::set_burn_address(&env, &caller, &burn_address)
+    }
+
+    /// Address receiving tokens bought back from reserves, if configured
+    pub fn get_burn_address(env: Env) -> Option<Address> {
+        buyback::Buyback::get_burn_address(&env)
+    }
+
+    /// Convert queued payouts in an asset to the settlement asset through
+    /// the AMM (permissionless keeper job)
+    ///
+    /// Takes at most `max_amount` and a bounded number of shares per call.
+    /// A batch the pool cannot fill within the max slippage is credited in
+    /// kind instead.
+    pub fn settle_rewards(
+        env: Env,
+        asset: Address,
+        max_amount: i128,
+    ) -> Result<payouts::SettlementResult, ProtocolError> {
+        let _guard = ReentrancyScope::enter(&env)?;
+        payouts::Payouts::settle(&env, &asset, max_amount)
+    }
+
+    /// Repay debt in one call by swapping `pay_amount` of `pay_asset` into
+    /// the primary asset through their AMM pair
+    ///
+    /// # Arguments
+    /// * `caller` - Borrower repaying and paying in `pay_asset`
+    /// * `debt_asset` - Asset the debt is denominated in; must be the primary asset
+    /// * `min_debt_repaid` - Least debt asset the swap must deliver
+    /// * `deadline_ts` - Fails with `DeadlineExceeded` if executed later (0 = none)
+    ///
+    /// # Returns
+    /// * Swap output, debt repaid, and the surplus refunded in the debt asset
+    pub fn repay_with(
+        env: Env,
+        caller: String,
+        pay_asset: Address,
+        pay_amount: i128,
+        debt_asset: Address,
+        min_debt_repaid: i128,
+        deadline_ts: u64,
+    ) -> Result<repay::SwapRepayResult, ProtocolError> {
+        Deadline::check(&env, deadline_ts)?;
+        RiskConfigStorage::get(&env).ensure_not_paused(OperationKind::Repay)?;
+        let caller_addr = AddressHelper::require_valid_address(&env, &caller)?;
+        diagnostics::CallTracker::track_call(&env, "repay_with", &caller_addr);
+        repay::RepayModule::repay_with(
+            &env,
+            &caller_addr,
+            &pay_asset,
+            pay_amount,
+            &debt_asset,
+            min_debt_repaid,
+        )
+    }
+}
+
+/// Swap hooks kept in builds without the `amm` feature so callers get a
+/// clear error instead of a missing function
+#[cfg(not(feature = "amm"))]
+#[contractimpl]
+impl Contract {
+    /// Swap hook for liquidation flows; unavailable without the AMM
+    pub fn liquidation_swap_hook(
+        _env: Env,
+        _liquidator: Address,
+        _collateral_asset: Address,
+        _debt_asset: Address,
+        _collateral_amount: i128,
+        _min_debt_amount: i128,
+    ) -> Result<(), ProtocolError> {
+        Err(ProtocolError::FeatureDisabled)
+    }
+
+    /// Convert queued payouts; unavailable without the AMM
+    pub fn settle_rewards(
+        _env: Env,
+        _asset: Address,
+        _max_amount: i128,
+    ) -> Result<(), ProtocolError> {
+        Err(ProtocolError::FeatureDisabled)
+    }
+
+    /// Repay through an AMM swap; unavailable without the AMM
+    pub fn repay_with(
+        _env: Env,
+        _caller: String,
+        _pay_asset: Address,
+        _pay_amount: i128,
+        _debt_asset: Address,
+        _min_debt_repaid: i128,
+        _deadline_ts: u64,
+    ) -> Result<(), ProtocolError> {
+        Err(ProtocolError::FeatureDisabled)
+    }
+
+    /// Swap hook for deleveraging flows; unavailable without the AMM
+    pub fn deleverage_swap_hook(
+        _env: Env,
+        _user: Address,
+        _asset_to_sell: Address,
+        _debt_asset: Address,
+        _sell_amount: i128,
+        _min_debt_repayment: i128,
+        _deadline_ts: u64,
+    ) -> Result<(), ProtocolError> {
+        Err(ProtocolError::FeatureDisabled)
+    }
 }
@@ -1,12 +1,20 @@
 //! Liquidation module for StellarLend protocol
 //! Handles liquidation functionality and related operations
 
+use crate::accrual::AccrualManager;
 use crate::analytics::AnalyticsModule;
+use crate::caps::CapManager;
+use crate::credit::Credit;
+use crate::oracle::{Oracle, OracleStorage, PriceSample};
 use crate::{
-    EmergencyManager, OperationKind, ProtocolConfig, ProtocolError, ProtocolEvent, ReentrancyGuard,
-    RiskConfigStorage, StateHelper,
+    EmergencyManager, EventTracker, InterestRateManager, InterestRateStorage, OperationKind,
+    Position, ProtocolConfig, ProtocolError, ProtocolEvent, ReentrancyGuard, RiskConfig,
+    RiskConfigStorage, StateHelper, TokenRegistry, TransferEnforcer,
 };
-use soroban_sdk::{contracterror, contracttype, Address, Env, String};
+use soroban_sdk::{contracterror, contracttype, Address, Env, Map, String, Symbol, Vec};
+
+/// Number of liquidation records retained globally
+pub const LIQUIDATION_RECORD_CAP: u64 = 50;
 
 /// Liquidation-specific errors
 #[contracterror]
@@ -71,6 +79,133 @@ pub struct LiquidationResult {
     pub collateral_seized: i128,
     pub debt_repaid: i128,
     pub liquidation_incentive: i128,
+    /// Part of `collateral_seized` that went to the liquidator
+    pub liquidator_collateral: i128,
+    /// Part of `collateral_seized` diverted to the insurance fund
+    pub protocol_fee: i128,
+    /// Seized collateral was credited to the liquidator's position
+    pub received_as_shares: bool,
+    /// Shares were requested but the supply cap forced a token transfer
+    pub share_cap_fallback: bool,
+    /// Id of the stored liquidation record
+    pub record_id: u64,
+    /// Deadline the liquidation was submitted with (0 = none)
+    pub deadline_ts: u64,
+}
+
+/// How a liquidation splits the borrower's seized collateral:
+/// `collateral_seized == liquidator_collateral + protocol_fee`
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct LiquidationQuote {
+    pub debt_repaid: i128,
+    pub collateral_seized: i128,
+    pub liquidator_collateral: i128,
+    pub protocol_fee: i128,
+}
+
+impl LiquidationQuote {
+    /// Apply the close factor to `amount` and split the resulting seizure.
+    /// The protocol fee is carved out of the bonus only.
+    pub fn compute(risk_config: &RiskConfig, debt: i128, amount: i128) -> Self {
+        let max_liquidation = (debt * risk_config.close_factor) / 100000000;
+        let debt_repaid = amount.min(max_liquidation);
+        let collateral_seized =
+            (debt_repaid * (100000000 + risk_config.liquidation_incentive)) / 100000000;
+        let bonus = collateral_seized - debt_repaid;
+        let protocol_fee =
+            (debt_repaid * risk_config.liquidation_protocol_fee_bps / 10000).min(bonus);
+        Self {
+            debt_repaid,
+            collateral_seized,
+            liquidator_collateral: collateral_seized - protocol_fee,
+            protocol_fee,
+        }
+    }
+
+    /// Quote the liquidation seizing `collateral`: the repayment whose
+    /// seizure is `collateral`, rounded down so the seizure never exceeds
+    /// it, then run through [`Self::compute`]
+    pub fn for_collateral(risk_config: &RiskConfig, debt: i128, collateral: i128) -> Self {
+        let amount = (collateral * 100000000) / (100000000 + risk_config.liquidation_incentive);
+        Self::compute(risk_config, debt, amount)
+    }
+}
+
+/// Prices used by a liquidation, kept for dispute resolution
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct LiquidationRecord {
+    pub id: u64,
+    pub liquidator: Address,
+    pub borrower: Address,
+    pub debt_asset: Address,
+    pub collateral_asset: Address,
+    /// Aggregated prices at liquidation time (None when no oracle source is configured)
+    pub debt_price: Option<i128>,
+    pub collateral_price: Option<i128>,
+    /// Source samples that contributed to the prices
+    pub samples: Vec<PriceSample>,
+    pub debt_repaid: i128,
+    pub collateral_seized: i128,
+    pub timestamp: u64,
+}
+
+/// Liquidation record storage: a ring of the latest records plus the last record per borrower
+pub struct LiquidationRecordStorage;
+
+impl LiquidationRecordStorage {
+    pub(crate) fn records_key(env: &Env) -> Symbol {
+        Symbol::new(env, "liq_records")
+    }
+
+    pub(crate) fn counter_key(env: &Env) -> Symbol {
+        Symbol::new(env, "liq_record_counter")
+    }
+
+    pub(crate) fn last_key(env: &Env, borrower: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "liq_last_record"), borrower.clone())
+    }
+
+    fn records(env: &Env) -> Map<u64, LiquidationRecord> {
+        env.storage()
+            .instance()
+            .get(&Self::records_key(env))
+            .unwrap_or_else(|| Map::new(env))
+    }
+
+    pub fn next_id(env: &Env) -> u64 {
+        let id: u64 = env
+            .storage()
+            .instance()
+            .get(&Self::counter_key(env))
+            .unwrap_or(0)
+            + 1;
+        env.storage().instance().set(&Self::counter_key(env), &id);
+        id
+    }
+
+    pub fn save(env: &Env, record: &LiquidationRecord) {
+        let mut records = Self::records(env);
+        records.set(record.id, record.clone());
+        if record.id > LIQUIDATION_RECORD_CAP {
+            records.remove(record.id - LIQUIDATION_RECORD_CAP);
+        }
+        env.storage()
+            .instance()
+            .set(&Self::records_key(env), &records);
+        env.storage()
+            .instance()
+            .set(&Self::last_key(env, &record.borrower), record);
+    }
+
+    pub fn get(env: &Env, id: u64) -> Option<LiquidationRecord> {
+        Self::records(env).get(id)
+    }
+
+    pub fn get_last_for(env: &Env, borrower: &Address) -> Option<LiquidationRecord> {
+        env.storage().instance().get(&Self::last_key(env, borrower))
+    }
 }
 
 impl LiquidationResult {
@@ -79,6 +214,12 @@ impl LiquidationResult {
             collateral_seized,
             debt_repaid,
             liquidation_incentive,
+            liquidator_collateral: collateral_seized,
+            protocol_fee: 0,
+            received_as_shares: false,
+            share_cap_fallback: false,
+            record_id: 0,
+            deadline_ts: 0,
         }
     }
 }
@@ -94,6 +235,8 @@ impl LiquidationModule {
         user: &String,
         amount: i128,
         min_out: i128,
+        receive_as_shares: bool,
+        acknowledge_override: bool,
     ) -> Result<LiquidationResult, ProtocolError> {
         ReentrancyGuard::enter(env)?;
         let result = (|| -> Result<LiquidationResult, ProtocolError> {
@@ -122,32 +265,25 @@ impl LiquidationModule {
                 None => return Err(LiquidationError::PositionNotFound.into()),
             };
 
-            // Check if position is eligible for liquidation
-            let min_ratio = ProtocolConfig::get_min_collateral_ratio(env);
-            let collateral_ratio = if position.debt > 0 {
-                (position.collateral * 100) / position.debt
+            // Check if position is below the liquidation threshold
+            let threshold_bps = ProtocolConfig::get_liquidation_threshold_bps(env);
+            let collateral_ratio_bps = if position.debt > 0 {
+                (position.collateral * 10000) / position.debt
             } else {
                 0
             };
 
-            if collateral_ratio >= min_ratio {
+            if collateral_ratio_bps >= threshold_bps {
                 return Err(LiquidationError::NotEligibleForLiquidation.into());
             }
 
-            // Calculate liquidation amount
-            let max_liquidation = (position.debt * risk_config.close_factor) / 100000000;
-            let liquidation_amount = if amount > max_liquidation {
-                max_liquidation
-            } else {
-                amount
-            };
-
-            // Calculate collateral to seize
-            let collateral_seized =
-                (liquidation_amount * (100000000 + risk_config.liquidation_incentive)) / 100000000;
+            // Calculate liquidation amount and the collateral split
+            let quote = LiquidationQuote::compute(&risk_config, position.debt, amount);
+            let liquidation_amount = quote.debt_repaid;
+            let collateral_seized = quote.collateral_seized;
 
             // Slippage protection: ensure the liquidator receives at least `min_out` collateral
-            if min_out > 0 && collateral_seized < min_out {
+            if min_out > 0 && quote.liquidator_collateral < min_out {
                 // Emit an analytics/event record so indexers can surface the slippage protection trigger
                 // Use the EventTracker available from the main crate to record structured analytics
                 soroban_sdk::Env::events(env); // no-op to satisfy borrow checker usage
@@ -162,32 +298,124 @@ impl LiquidationModule {
                     },
                     Some(liquidator_addr.clone()),
                     Some(user_addr.clone()),
-                    collateral_seized,
+                    quote.liquidator_collateral,
                 );
 
                 return Err(ProtocolError::SlippageProtectionTriggered);
             }
 
-            // Update position
-            position.debt -= liquidation_amount;
-            position.collateral -= collateral_seized;
-            StateHelper::save_position(env, &position);
+            // Price the position before any effects. Debt and collateral are
+            // both the primary asset, so one aggregation covers both sides.
+            let asset = TokenRegistry::require_primary_asset(env)?;
+            // Liquidations never run on frozen prices, inside the window or not
+            if Oracle::is_frozen(env, &asset) {
+                return Err(ProtocolError::OracleFrozen);
+            }
+            // A pinned price is only used with the liquidator's explicit consent
+            if let Some(pinned) = Oracle::active_override(env, &asset, true) {
+                if !acknowledge_override {
+                    return Err(ProtocolError::InvalidOperation);
+                }
+                Oracle::note_override_used(env, &asset, &liquidator_addr, pinned.price);
+            }
+            let details = Oracle::aggregate_price_detailed(env, &asset);
+            if details.as_ref().is_some_and(|d| d.clamped)
+                && OracleStorage::get_block_liquidation_on_clamp(env)
+            {
+                return Err(ProtocolError::OracleFailure);
+            }
+            if let Some(details) = &details {
+                Oracle::check_spread(env, &asset, Oracle::spread_bps(env, &details.samples))?;
+            }
 
-            let result = LiquidationResult::new(
+            let mut result = LiquidationResult::new(
                 collateral_seized,
                 liquidation_amount,
                 risk_config.liquidation_incentive,
             );
+            result.liquidator_collateral = quote.liquidator_collateral;
+            result.protocol_fee = quote.protocol_fee;
+
+            // Liquidator repays the debt portion
+            TransferEnforcer::transfer_in(
+                env,
+                &liquidator_addr,
+                liquidation_amount,
+                Symbol::new(env, "liquidate_repay"),
+            )?;
+
+            // Update position
+            position.debt -= liquidation_amount;
+            position.collateral -= collateral_seized;
+            StateHelper::save_position(env, &position);
+            InterestRateStorage::adjust_totals(env, -collateral_seized, -liquidation_amount);
+
+            // The protocol fee stays in the contract as insurance fund balance
+            if quote.protocol_fee > 0 {
+                EmergencyManager::credit_fund(env, &asset, quote.protocol_fee);
+            }
+
+            // The liquidator's share is credited as supply (1:1 with collateral
+            // units) when requested and the caps allow, otherwise transferred out
+            let liquidator_collateral = quote.liquidator_collateral;
+            if receive_as_shares
+                && CapManager::supply_fits(env, &asset, liquidator_collateral)
+                && CapManager::check_global_tvl(env, &asset, liquidator_collateral).is_ok()
+            {
+                let mut liquidator_position = StateHelper::get_position(env, &liquidator_addr)
+                    .unwrap_or_else(|| Position::new(liquidator_addr.clone(), 0, 0));
+                // Accrue the liquidator's existing position before crediting it
+                let state = AccrualManager::rates_for_position(env, liquidator_position.debt);
+                InterestRateManager::accrue_interest_for_position(
+                    env,
+                    &mut liquidator_position,
+                    state.current_borrow_rate,
+                    state.current_supply_rate,
+                );
+                liquidator_position.collateral += liquidator_collateral;
+                StateHelper::save_position(env, &liquidator_position);
+                InterestRateStorage::adjust_totals(env, liquidator_collateral, 0);
+                result.received_as_shares = true;
+            } else {
+                result.share_cap_fallback = receive_as_shares;
+                TransferEnforcer::transfer_out(
+                    env,
+                    &liquidator_addr,
+                    liquidator_collateral,
+                    Symbol::new(env, "liquidate_seize"),
+                )?;
+            }
+
+            Credit::record_liquidation(env, &user_addr);
 
             // Emit liquidation event
             ProtocolEvent::LiquidationExecuted(
                 liquidator_addr.clone(),
-                user_addr,
+                user_addr.clone(),
                 collateral_seized,
                 liquidation_amount,
             )
             .emit(env);
 
+            // Record the prices in force for later disputes
+            let price = details.as_ref().map(|d| d.price);
+            let record = LiquidationRecord {
+                id: LiquidationRecordStorage::next_id(env),
+                liquidator: liquidator_addr.clone(),
+                borrower: user_addr,
+                debt_asset: asset.clone(),
+                collateral_asset: asset,
+                debt_price: price,
+                collateral_price: price,
+                samples: details.map(|d| d.samples).unwrap_or_else(|| Vec::new(env)),
+                debt_repaid: liquidation_amount,
+                collateral_seized,
+                timestamp: env.ledger().timestamp(),
+            };
+            LiquidationRecordStorage::save(env, &record);
+            Self::emit_record(env, &record);
+            result.record_id = record.id;
+
             // Analytics
             AnalyticsModule::record_activity(
                 env,
@@ -204,6 +432,81 @@ impl LiquidationModule {
         result
     }
 
+    fn emit_record(env: &Env, record: &LiquidationRecord) {
+        let event_type = Symbol::new(env, "liquidation_prices");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        topics.push_back(Symbol::new(env, "borrower"));
+        EventTracker::record(
+            env,
+            event_type.clone(),
+            topics,
+            Some(record.borrower.clone()),
+            Some(record.debt_asset.clone()),
+            record.debt_price.unwrap_or(0),
+        );
+        env.events().publish(
+            (event_type, record.id),
+            (
+                Symbol::new(env, "debt_price"),
+                record.debt_price,
+                Symbol::new(env, "collateral_price"),
+                record.collateral_price,
+                Symbol::new(env, "samples"),
+                record.samples.clone(),
+            ),
+        );
+    }
+
+    /// Quote a liquidation of `amount` against `user`'s current position
+    /// without executing it
+    pub fn preview(
+        env: &Env,
+        user: &Address,
+        amount: i128,
+    ) -> Result<LiquidationQuote, ProtocolError> {
+        if amount <= 0 {
+            return Err(LiquidationError::InvalidAmount.into());
+        }
+        let position =
+            StateHelper::get_position(env, user).ok_or(LiquidationError::PositionNotFound)?;
+        if !Self::_is_eligible_for_liquidation(env, user)? {
+            return Err(LiquidationError::NotEligibleForLiquidation.into());
+        }
+        Ok(LiquidationQuote::compute(
+            &RiskConfigStorage::get(env),
+            position.debt,
+            amount,
+        ))
+    }
+
+    /// Quote a liquidation of `user` sized by the collateral to seize rather
+    /// than the debt to repay. The request is clamped to the borrower's
+    /// collateral and, through the implied repayment, to the close factor.
+    /// Debt and collateral are both the primary asset, so their prices cancel.
+    pub fn quote_for_collateral(
+        env: &Env,
+        user: &Address,
+        collateral_asset: &Address,
+        debt_asset: &Address,
+        collateral_amount: i128,
+    ) -> Result<LiquidationQuote, ProtocolError> {
+        if collateral_amount <= 0 {
+            return Err(LiquidationError::InvalidAmount.into());
+        }
+        let asset = TokenRegistry::require_primary_asset(env)?;
+        if *collateral_asset != asset || *debt_asset != asset {
+            return Err(ProtocolError::AssetNotSupported);
+        }
+        let position =
+            StateHelper::get_position(env, user).ok_or(LiquidationError::PositionNotFound)?;
+        Ok(LiquidationQuote::for_collateral(
+            &RiskConfigStorage::get(env),
+            position.debt,
+            collateral_amount.min(position.collateral),
+        ))
+    }
+
     /// Check if a position is eligible for liquidation
     pub fn _is_eligible_for_liquidation(env: &Env, user: &Address) -> Result<bool, ProtocolError> {
         let position = match StateHelper::get_position(env, user) {
@@ -211,14 +514,14 @@ impl LiquidationModule {
             None => return Err(LiquidationError::PositionNotFound.into()),
         };
 
-        let min_ratio = ProtocolConfig::get_min_collateral_ratio(env);
-        let collateral_ratio = if position.debt > 0 {
-            (position.collateral * 100) / position.debt
+        let threshold_bps = ProtocolConfig::get_liquidation_threshold_bps(env);
+        let collateral_ratio_bps = if position.debt > 0 {
+            (position.collateral * 10000) / position.debt
         } else {
             0
         };
 
-        Ok(collateral_ratio < min_ratio)
+        Ok(collateral_ratio_bps < threshold_bps)
     }
 
     /// Calculate maximum liquidation amount for a position
@@ -272,16 +575,16 @@ impl LiquidationModule {
             None => return Err(LiquidationError::PositionNotFound.into()),
         };
 
-        let min_ratio = ProtocolConfig::get_min_collateral_ratio(env);
-        let collateral_ratio = if position.debt > 0 {
-            (position.collateral * 100) / position.debt
+        let threshold_bps = ProtocolConfig::get_liquidation_threshold_bps(env);
+        let collateral_ratio_bps = if position.debt > 0 {
+            (position.collateral * 10000) / position.debt
         } else {
             0
         };
 
-        // Health factor = collateral_ratio / min_ratio
-        if min_ratio > 0 {
-            Ok((collateral_ratio * 100) / min_ratio)
+        // Health factor = collateral_ratio / liquidation threshold
+        if threshold_bps > 0 {
+            Ok((collateral_ratio_bps * 100) / threshold_bps)
         } else {
             Ok(0)
         }
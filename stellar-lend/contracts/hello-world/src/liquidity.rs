@@ -0,0 +1,87 @@
+//! Available liquidity
+//! The contract's token balance pools supplier liquidity with funds earmarked
+//! for the protocol: reserves (penalties, slashed bonds, recovered surplus)
+//! and the insurance fund. Supplier-facing payouts may only draw on what is
+//! left once the earmarked components are netted out.
+
+use crate::reserves::ReserveStorage;
+use crate::{EmergencyStorage, ProtocolError};
+use soroban_sdk::token::TokenClient;
+use soroban_sdk::{contracttype, Address, Env};
+
+/// Components of an asset's token balance
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct LiquidityBreakdown {
+    pub asset: Address,
+    /// Token balance reported by the token contract
+    pub balance: i128,
+    /// Protocol reserves, which include accrued protocol fees
+    pub reserves: i128,
+    /// Insurance fund balance held in the asset
+    pub insurance: i128,
+    /// Claims queued for later payout; no withdrawal queue exists yet, so
+    /// always 0
+    pub pending_claims: i128,
+    /// balance minus every earmarked component, floored at 0
+    pub available: i128,
+}
+
+impl LiquidityBreakdown {
+    /// Sum of the earmarked components
+    pub fn earmarked(&self) -> i128 {
+        self.reserves
+            .saturating_add(self.insurance)
+            .saturating_add(self.pending_claims)
+    }
+}
+
+/// Available liquidity logic
+pub struct Liquidity;
+
+impl Liquidity {
+    /// Split `asset`'s token balance into earmarked funds and liquidity
+    /// available to suppliers and borrowers
+    pub fn breakdown(env: &Env, asset: &Address) -> LiquidityBreakdown {
+        let balance = TokenClient::new(env, asset).balance(&env.current_contract_address());
+        let fund = EmergencyStorage::get(env).fund;
+        let insurance = if fund.token.as_ref() == Some(asset) {
+            fund.balance.max(0)
+        } else {
+            0
+        };
+        let mut breakdown = LiquidityBreakdown {
+            asset: asset.clone(),
+            balance,
+            reserves: ReserveStorage::get(env, asset).max(0),
+            insurance,
+            pending_claims: 0,
+            available: 0,
+        };
+        breakdown.available = balance.saturating_sub(breakdown.earmarked()).max(0);
+        breakdown
+    }
+
+    /// Fail with `InsufficientLiquidity` when paying out `amount` of `asset`
+    /// would dip into earmarked funds
+    pub fn require_available(
+        env: &Env,
+        asset: &Address,
+        amount: i128,
+    ) -> Result<(), ProtocolError> {
+        if amount > Self::breakdown(env, asset).available {
+            return Err(ProtocolError::InsufficientLiquidity);
+        }
+        Ok(())
+    }
+
+    /// Fail with `BalanceInvariantViolation` when the earmarked components
+    /// of `asset` exceed its actual token balance
+    pub fn check_invariant(env: &Env, asset: &Address) -> Result<(), ProtocolError> {
+        let breakdown = Self::breakdown(env, asset);
+        if breakdown.earmarked() > breakdown.balance {
+            return Err(ProtocolError::BalanceInvariantViolation);
+        }
+        Ok(())
+    }
+}
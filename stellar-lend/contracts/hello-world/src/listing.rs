@@ -0,0 +1,324 @@
+//! Governance-approved asset listings backed by a proposer bond
+//!
+//! Anyone may propose a new asset by locking a bond. Governance approves
+//! (listing the asset and returning the bond) or rejects (slashing part of
+//! the bond to the treasury). Proposals left undecided past the expiry can be
+//! closed by the proposer on the same slashing terms as a rejection.
+
+use crate::caps::CapStorage;
+use crate::config;
+use crate::reserves::ReserveStorage;
+use crate::{EventTracker, ProtocolConfig, ProtocolError, TokenRegistry, TransferEnforcer};
+use soroban_sdk::{contracttype, Address, Env, Map, Symbol, Vec};
+
+/// Token being proposed for listing
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct ListingTokenInfo {
+    /// Registry key the asset will be listed under
+    pub key: Symbol,
+    /// Token contract address
+    pub token: Address,
+}
+
+/// Initial risk parameters for a proposed asset
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct ListingRiskParams {
+    /// Collateral factor (scaled by 1e8)
+    pub collateral_factor: i128,
+    /// Supply cap applied on approval (0 = unlimited)
+    pub supply_cap: i128,
+}
+
+/// Lifecycle of a listing proposal
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum ListingStatus {
+    Pending,
+    Approved,
+    Rejected,
+    Expired,
+}
+
+/// Listing proposal with its locked bond
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct ListingProposal {
+    pub id: u64,
+    pub proposer: Address,
+    pub token_info: ListingTokenInfo,
+    pub risk_params: ListingRiskParams,
+    pub bond_asset: Address,
+    pub bond_amount: i128,
+    pub created_at: u64,
+    pub status: ListingStatus,
+}
+
+/// Listing bond configuration
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct ListingConfig {
+    /// Asset bonds are posted in
+    pub bond_asset: Address,
+    /// Minimum bond per proposal
+    pub min_bond: i128,
+    /// Portion of the bond slashed on rejection or expiry (bps)
+    pub slash_bps: i128,
+    /// Seconds after which an undecided proposal expires
+    pub expiry_secs: u64,
+}
+
+/// Listing storage management
+pub struct ListingStorage;
+
+impl ListingStorage {
+    pub(crate) fn proposals_key(env: &Env) -> Symbol {
+        Symbol::new(env, "listing_proposals")
+    }
+
+    pub(crate) fn counter_key(env: &Env) -> Symbol {
+        Symbol::new(env, "listing_counter")
+    }
+
+    pub(crate) fn config_key(env: &Env) -> Symbol {
+        Symbol::new(env, "listing_config")
+    }
+
+    pub fn get_config(env: &Env) -> Option<ListingConfig> {
+        env.storage().instance().get(&Self::config_key(env))
+    }
+
+    pub fn save_config(env: &Env, config: &ListingConfig) {
+        env.storage().instance().set(&Self::config_key(env), config);
+    }
+
+    fn proposals(env: &Env) -> Map<u64, ListingProposal> {
+        env.storage()
+            .instance()
+            .get(&Self::proposals_key(env))
+            .unwrap_or_else(|| Map::new(env))
+    }
+
+    pub fn get(env: &Env, id: u64) -> Option<ListingProposal> {
+        Self::proposals(env).get(id)
+    }
+
+    pub fn save(env: &Env, proposal: &ListingProposal) {
+        let mut map = Self::proposals(env);
+        map.set(proposal.id, proposal.clone());
+        env.storage()
+            .instance()
+            .set(&Self::proposals_key(env), &map);
+    }
+
+    pub fn next_id(env: &Env) -> u64 {
+        let id: u64 = env
+            .storage()
+            .instance()
+            .get(&Self::counter_key(env))
+            .unwrap_or(0)
+            + 1;
+        env.storage().instance().set(&Self::counter_key(env), &id);
+        id
+    }
+}
+
+/// Listing proposal logic
+pub struct Listing;
+
+impl Listing {
+    /// Configure listing bonds (admin only)
+    pub fn set_config(
+        env: &Env,
+        caller: &Address,
+        config: ListingConfig,
+    ) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        if config.min_bond < 0 || !(0..=10000).contains(&config.slash_bps) {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        let old = ListingStorage::get_config(env);
+        ListingStorage::save_config(env, &config);
+        let (old_bond, old_slash, old_expiry) = old
+            .map(|c| (c.min_bond, c.slash_bps, c.expiry_secs))
+            .unwrap_or((0, 0, 0));
+        let fields = [
+            ("min_bond", old_bond, config.min_bond),
+            ("slash_bps", old_slash, config.slash_bps),
+            ("expiry_secs", old_expiry.into(), config.expiry_secs.into()),
+        ];
+        for (key, old, new) in fields {
+            if old != new {
+                config::record_change(env, "listing", key, None, old, new);
+            }
+        }
+        Ok(())
+    }
+
+    /// Propose a new asset listing, locking `bond_amount` of the bond asset
+    pub fn propose(
+        env: &Env,
+        proposer: &Address,
+        token_info: ListingTokenInfo,
+        risk_params: ListingRiskParams,
+        bond_amount: i128,
+    ) -> Result<u64, ProtocolError> {
+        let config = ListingStorage::get_config(env).ok_or(ProtocolError::ConfigurationError)?;
+        if bond_amount < config.min_bond || bond_amount <= 0 {
+            return Err(ProtocolError::InvalidAmount);
+        }
+        if TokenRegistry::get_asset(env, token_info.key.clone()).is_some() {
+            return Err(ProtocolError::AlreadyExists);
+        }
+
+        TransferEnforcer::transfer(
+            env,
+            &config.bond_asset,
+            proposer,
+            &env.current_contract_address(),
+            bond_amount,
+            Symbol::new(env, "listing_bond"),
+        )?;
+
+        let proposal = ListingProposal {
+            id: ListingStorage::next_id(env),
+            proposer: proposer.clone(),
+            token_info,
+            risk_params,
+            bond_asset: config.bond_asset,
+            bond_amount,
+            created_at: env.ledger().timestamp(),
+            status: ListingStatus::Pending,
+        };
+        ListingStorage::save(env, &proposal);
+        Self::emit(env, "listing_proposed", &proposal, bond_amount);
+        Ok(proposal.id)
+    }
+
+    /// Approve a pending listing: list the asset and return the full bond
+    pub fn approve(env: &Env, id: u64) -> Result<ListingProposal, ProtocolError> {
+        let mut proposal = Self::pending(env, id)?;
+        TokenRegistry::register(
+            env,
+            proposal.token_info.key.clone(),
+            proposal.token_info.token.clone(),
+        );
+        if proposal.risk_params.supply_cap > 0 {
+            CapStorage::set_supply_cap(
+                env,
+                &proposal.token_info.token,
+                proposal.risk_params.supply_cap,
+            );
+        }
+        TransferEnforcer::transfer(
+            env,
+            &proposal.bond_asset,
+            &env.current_contract_address(),
+            &proposal.proposer,
+            proposal.bond_amount,
+            Symbol::new(env, "listing_refund"),
+        )?;
+        proposal.status = ListingStatus::Approved;
+        ListingStorage::save(env, &proposal);
+        Self::emit(env, "listing_approved", &proposal, proposal.bond_amount);
+        Ok(proposal)
+    }
+
+    /// Reject a pending listing, slashing part of the bond
+    pub fn reject(env: &Env, id: u64) -> Result<ListingProposal, ProtocolError> {
+        let proposal = Self::pending(env, id)?;
+        Self::settle_slashed(env, proposal, ListingStatus::Rejected)
+    }
+
+    /// Close an expired listing; the proposer reclaims the unslashed remainder
+    pub fn claim_expired(env: &Env, id: u64) -> Result<ListingProposal, ProtocolError> {
+        let proposal = Self::pending(env, id)?;
+        let config = ListingStorage::get_config(env).ok_or(ProtocolError::ConfigurationError)?;
+        if env.ledger().timestamp() < proposal.created_at.saturating_add(config.expiry_secs) {
+            return Err(ProtocolError::InvalidOperation);
+        }
+        Self::settle_slashed(env, proposal, ListingStatus::Expired)
+    }
+
+    fn pending(env: &Env, id: u64) -> Result<ListingProposal, ProtocolError> {
+        let proposal = ListingStorage::get(env, id).ok_or(ProtocolError::NotFound)?;
+        if proposal.status != ListingStatus::Pending {
+            return Err(ProtocolError::InvalidOperation);
+        }
+        Ok(proposal)
+    }
+
+    fn settle_slashed(
+        env: &Env,
+        mut proposal: ListingProposal,
+        status: ListingStatus,
+    ) -> Result<ListingProposal, ProtocolError> {
+        let slash_bps = ListingStorage::get_config(env)
+            .map(|c| c.slash_bps)
+            .unwrap_or(0);
+        let slashed = proposal.bond_amount * slash_bps / 10000;
+        let refund = proposal.bond_amount - slashed;
+        let contract = env.current_contract_address();
+
+        if slashed > 0 {
+            match ProtocolConfig::get_treasury(env) {
+                Some(treasury) => TransferEnforcer::transfer(
+                    env,
+                    &proposal.bond_asset,
+                    &contract,
+                    &treasury,
+                    slashed,
+                    Symbol::new(env, "listing_slash"),
+                )?,
+                None => ReserveStorage::add(env, &proposal.bond_asset, slashed),
+            }
+        }
+        if refund > 0 {
+            TransferEnforcer::transfer(
+                env,
+                &proposal.bond_asset,
+                &contract,
+                &proposal.proposer,
+                refund,
+                Symbol::new(env, "listing_refund"),
+            )?;
+        }
+
+        proposal.status = status;
+        ListingStorage::save(env, &proposal);
+        let name = if status == ListingStatus::Rejected {
+            "listing_rejected"
+        } else {
+            "listing_expired"
+        };
+        Self::emit(env, name, &proposal, slashed);
+        Ok(proposal)
+    }
+
+    fn emit(env: &Env, name: &str, proposal: &ListingProposal, amount: i128) {
+        let event_type = Symbol::new(env, name);
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        topics.push_back(Symbol::new(env, "proposer"));
+        EventTracker::record(
+            env,
+            event_type.clone(),
+            topics,
+            Some(proposal.proposer.clone()),
+            Some(proposal.token_info.token.clone()),
+            amount,
+        );
+        env.events().publish(
+            (event_type, proposal.id),
+            (
+                Symbol::new(env, "proposer"),
+                proposal.proposer.clone(),
+                Symbol::new(env, "token"),
+                proposal.token_info.token.clone(),
+                Symbol::new(env, "amount"),
+                amount,
+            ),
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! Position migration to a newer contract deployment
+//!
+//! Users can move a position to a governance-approved target contract without
+//! unwinding it. Collateral tokens are sent to the target, which must accept
+//! the position (including the debt obligation) through its
+//! `receive_migrated_position` hook. Any failure returns an error so the whole
+//! invocation, including the token transfer, is rolled back.
+//!
+//! Interest is accrued first and carried with the position: the debt handed
+//! over includes the borrow interest owed, and the collateral sent includes
+//! the supply interest earned.
+
+use crate::accrual::AccrualManager;
+use crate::penalty::PenaltyStorage;
+use crate::{
+    EventTracker, InterestRateManager, InterestRateStorage, ProtocolError, StateHelper,
+    TokenRegistry, TransferEnforcer,
+};
+use soroban_sdk::{contracttype, vec, Address, Env, IntoVal, Symbol, Vec};
+
+/// Collateral handed to the target contract, supply interest included
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct CollateralSpec {
+    pub asset: Address,
+    pub amount: i128,
+}
+
+/// Debt obligation the target must acknowledge, either by pulling matching
+/// funds or by minting equivalent debt on its side; includes borrow interest
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct DebtSpec {
+    pub asset: Address,
+    pub amount: i128,
+}
+
+/// Migration storage management
+pub struct MigrationStorage;
+
+impl MigrationStorage {
+    pub(crate) fn target_key(env: &Env, target: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "migration_target"), target.clone())
+    }
+
+    pub fn is_allowed(env: &Env, target: &Address) -> bool {
+        env.storage()
+            .instance()
+            .get(&Self::target_key(env, target))
+            .unwrap_or(false)
+    }
+
+    pub fn set_allowed(env: &Env, target: &Address, allowed: bool) {
+        let key = Self::target_key(env, target);
+        if allowed {
+            env.storage().instance().set(&key, &true);
+        } else {
+            env.storage().instance().remove(&key);
+        }
+    }
+}
+
+/// Migration logic
+pub struct Migration;
+
+impl Migration {
+    /// Move `user`'s position to `target`. Callers must hold the reentrancy guard.
+    pub fn migrate_out(
+        env: &Env,
+        user: &Address,
+        target: &Address,
+    ) -> Result<(CollateralSpec, DebtSpec), ProtocolError> {
+        if !MigrationStorage::is_allowed(env, target) {
+            return Err(ProtocolError::Unauthorized);
+        }
+        let mut position =
+            StateHelper::get_position(env, user).ok_or(ProtocolError::PositionNotFound)?;
+        if position.collateral <= 0 && position.debt <= 0 {
+            return Err(ProtocolError::InvalidOperation);
+        }
+        let asset = TokenRegistry::require_primary_asset(env)?;
+
+        let state = InterestRateStorage::update_state(env);
+        AccrualManager::ensure_caught_up(env)?;
+        InterestRateManager::accrue_interest_for_position(
+            env,
+            &mut position,
+            state.current_borrow_rate,
+            state.current_supply_rate,
+        );
+
+        let collateral = CollateralSpec {
+            asset: asset.clone(),
+            amount: position.collateral.saturating_add(position.supply_interest),
+        };
+        let debt = DebtSpec {
+            asset,
+            amount: position.debt.saturating_add(position.borrow_interest),
+        };
+
+        if collateral.amount > 0 {
+            TransferEnforcer::transfer_out(
+                env,
+                target,
+                collateral.amount,
+                Symbol::new(env, "migrate_out"),
+            )?;
+        }
+
+        let args = vec![
+            env,
+            user.into_val(env),
+            collateral.clone().into_val(env),
+            debt.clone().into_val(env),
+        ];
+        let accepted = matches!(
+            env.try_invoke_contract::<bool, soroban_sdk::Error>(
+                target,
+                &Symbol::new(env, "receive_migrated_position"),
+                args,
+            ),
+            Ok(Ok(true))
+        );
+        if !accepted {
+            return Err(ProtocolError::MigrationRejected);
+        }
+
+        InterestRateStorage::adjust_totals(env, -position.collateral, -position.debt);
+        position.collateral = 0;
+        position.debt = 0;
+        position.borrow_interest = 0;
+        position.supply_interest = 0;
+        StateHelper::save_position(env, &position);
+        PenaltyStorage::save_lots(env, user, &Vec::new(env));
+
+        Self::emit(env, user, target, &collateral, &debt);
+        Ok((collateral, debt))
+    }
+
+    fn emit(
+        env: &Env,
+        user: &Address,
+        target: &Address,
+        collateral: &CollateralSpec,
+        debt: &DebtSpec,
+    ) {
+        let event_type = Symbol::new(env, "position_migrated");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        topics.push_back(Symbol::new(env, "user"));
+        EventTracker::record(
+            env,
+            event_type.clone(),
+            topics,
+            Some(user.clone()),
+            Some(collateral.asset.clone()),
+            collateral.amount,
+        );
+        env.events().publish(
+            (event_type, Symbol::new(env, "user")),
+            (
+                Symbol::new(env, "user"),
+                user.clone(),
+                Symbol::new(env, "target"),
+                target.clone(),
+                Symbol::new(env, "collateral"),
+                collateral.amount,
+                Symbol::new(env, "debt"),
+                debt.amount,
+            ),
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! Batched position management
+//!
+//! `multicall` runs up to [`MAX_BATCH_OPS`] of the caller's own operations in
+//! order under a single reentrancy-guard scope. Per-operation health checks
+//! are skipped while the batch runs, so intermediate steps may dip below the
+//! required ratio; the caller's position is checked once at the end. Any
+//! failure, including that final check, fails the whole invocation and
+//! reverts every step.
+//!
+//! Only operations acting on the caller are offered; on-behalf variants
+//! (liquidations, sponsored repayments) are deliberately excluded.
+
+#[cfg(feature = "amm")]
+use crate::amm::{AMMRegistry, SwapParams};
+use crate::beta::BetaAccess;
+use crate::borrow::BorrowModule;
+use crate::deposit::DepositModule;
+use crate::repay::RepayModule;
+use crate::withdraw::WithdrawModule;
+use crate::{
+    OperationKind, ProtocolConfig, ProtocolError, ReentrancyGuard, RiskConfigStorage, StateHelper,
+};
+use soroban_sdk::{contracttype, Address, Env, Vec};
+
+/// Maximum operations in one batch
+pub const MAX_BATCH_OPS: u32 = 6;
+
+/// A user-facing operation on the caller's own position
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum UserOp {
+    Deposit(i128),
+    Withdraw(i128),
+    Borrow(i128),
+    Repay(i128),
+    /// Swap on the internal AMM: (asset_in, asset_out, amount_in, min_amount_out).
+    /// Fails with `FeatureDisabled` without the `amm` feature.
+    Swap(Address, Address, i128, i128),
+}
+
+/// Holds the reentrancy guard for a batch; released on drop
+struct BatchScope<'a> {
+    env: &'a Env,
+}
+
+impl<'a> BatchScope<'a> {
+    fn enter(env: &'a Env) -> Result<Self, ProtocolError> {
+        ReentrancyGuard::enter_batch(env)?;
+        Ok(Self { env })
+    }
+}
+
+impl<'a> Drop for BatchScope<'a> {
+    fn drop(&mut self) {
+        ReentrancyGuard::exit_batch(self.env);
+    }
+}
+
+/// Multicall logic
+pub struct Multicall;
+
+impl Multicall {
+    /// Run `ops` for `caller`, who authorizes the batch as a whole, in
+    /// order, then check the caller's health once
+    pub fn execute(env: &Env, caller: &Address, ops: &Vec<UserOp>) -> Result<(), ProtocolError> {
+        caller.require_auth();
+        if ops.is_empty() || ops.len() > MAX_BATCH_OPS {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        let _batch = BatchScope::enter(env)?;
+        let mut borrowed = false;
+        for op in ops.iter() {
+            borrowed |= matches!(op, UserOp::Borrow(_));
+            Self::run(env, caller, op)?;
+        }
+        Self::check_health(env, caller, borrowed)
+    }
+
+    fn run(env: &Env, caller: &Address, op: UserOp) -> Result<(), ProtocolError> {
+        let risk_config = RiskConfigStorage::get(env);
+        match op {
+            UserOp::Deposit(amount) => {
+                risk_config.ensure_not_paused(OperationKind::Deposit)?;
+                BetaAccess::require_beta_access(env, caller)?;
+                DepositModule::deposit_collateral(env, caller, amount)
+            }
+            UserOp::Withdraw(amount) => {
+                risk_config.ensure_not_paused(OperationKind::Withdraw)?;
+                WithdrawModule::withdraw(env, caller, amount)
+            }
+            UserOp::Borrow(amount) => {
+                risk_config.ensure_not_paused(OperationKind::Borrow)?;
+                BetaAccess::require_beta_access(env, caller)?;
+                BorrowModule::borrow(env, caller, amount)
+            }
+            UserOp::Repay(amount) => {
+                risk_config.ensure_not_paused(OperationKind::Repay)?;
+                RepayModule::repay(env, caller, amount)
+            }
+            #[cfg(feature = "amm")]
+            UserOp::Swap(asset_in, asset_out, amount_in, min_amount_out) => {
+                BetaAccess::require_beta_access(env, caller)?;
+                let params = SwapParams::new(
+                    caller.clone(),
+                    asset_in,
+                    asset_out,
+                    amount_in,
+                    min_amount_out,
+                );
+                AMMRegistry::execute_swap(env, params).map(|_| ())
+            }
+            #[cfg(not(feature = "amm"))]
+            UserOp::Swap(..) => Err(ProtocolError::FeatureDisabled),
+        }
+    }
+
+    /// The checks skipped during the batch: the minimum collateral ratio, or
+    /// the origination ratio if the batch borrowed
+    fn check_health(env: &Env, caller: &Address, borrowed: bool) -> Result<(), ProtocolError> {
+        let Some(position) = StateHelper::get_position(env, caller) else {
+            return Ok(());
+        };
+        if position.debt <= 0 {
+            return Ok(());
+        }
+        let healthy = if borrowed {
+            BorrowModule::meets_origination_ratio(env, position.collateral, position.debt)
+        } else {
+            position.collateral * 100 / position.debt
+                >= ProtocolConfig::get_min_collateral_ratio(env)
+        };
+        if !healthy {
+            return Err(ProtocolError::InsufficientCollateralRatio);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,25 @@
+//! Paginated getter responses
+//! Every cursor-paged getter returns a page carrying the collection total,
+//! read from the maintained counter or length rather than by iterating, so
+//! clients never need a second call to learn how much is left.
+//!
+//! `#[contracttype]` does not support generics, so each item type gets its
+//! own page struct through [`define_page!`].
+
+pub use stellarlend_core::pagination::*;
+
+/// Declares a `#[contracttype]` page of `$item` named `$name`
+macro_rules! define_page {
+    ($(#[$meta:meta])* $name:ident, $item:ident) => {
+        $(#[$meta])*
+        #[derive(Clone, Debug, Eq, PartialEq)]
+        #[contracttype]
+        pub struct $name {
+            pub items: Vec<$item>,
+            /// Size of the whole collection when the page was read
+            pub total: u64,
+            /// Cursor for the next page; equals `total` once exhausted
+            pub cursor: u32,
+        }
+    };
+}
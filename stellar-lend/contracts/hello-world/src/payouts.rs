@@ -0,0 +1,377 @@
+//! Referral and keeper payout accounting
+//!
+//! Fee shares set aside for referrers and keeper bounties are credited to a
+//! payout balance per recipient and asset, which the recipient claims. With
+//! a reward settlement asset configured, shares in any other asset are
+//! queued instead of credited, and the permissionless `settle_rewards` keeper
+//! job converts them in bounded batches through the internal AMM, so user
+//! operations never pay for a swap and recipients end up holding the
+//! settlement asset. A batch that has no pool to swap through, would move
+//! the pool by more than the configured max slippage, or fails to swap is
+//! credited in kind instead.
+
+#[cfg(feature = "amm")]
+use crate::amm::{AMMRegistry, PairKey};
+use crate::config;
+use crate::defaults;
+use crate::privacy::Privacy;
+use crate::reserves::ReserveStorage;
+use crate::{EventTracker, ProtocolConfig, ProtocolError, TransferEnforcer};
+use soroban_sdk::{contracttype, Address, Env, IntoVal, Symbol, Val, Vec};
+
+/// Most shares waiting for settlement per asset; later shares are credited
+/// in kind
+pub const MAX_QUEUED_SHARES: u32 = 50;
+/// Most queued shares a single `settle_rewards` call converts
+#[cfg(feature = "amm")]
+pub const MAX_SETTLE_SHARES: u32 = 10;
+
+/// A fee share waiting to be converted to the settlement asset
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PayoutShare {
+    pub recipient: Address,
+    pub amount: i128,
+}
+
+/// Result of one `settle_rewards` call
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct SettlementResult {
+    /// Queued amount taken off the queue
+    pub amount_in: i128,
+    /// Settlement asset credited (0 when credited in kind)
+    pub amount_out: i128,
+    /// Whether the batch was credited in kind instead of swapped
+    pub in_kind: bool,
+}
+
+/// Payout storage management
+pub struct PayoutStorage;
+
+impl PayoutStorage {
+    pub(crate) fn settlement_asset_key(env: &Env) -> Symbol {
+        Symbol::new(env, "payout_settle_asset")
+    }
+    pub(crate) fn max_slippage_key(env: &Env) -> Symbol {
+        Symbol::new(env, "payout_max_slip_bps")
+    }
+    pub(crate) fn queue_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "payout_queue"), asset.clone())
+    }
+    pub(crate) fn balance_key(
+        env: &Env,
+        recipient: &Address,
+        asset: &Address,
+    ) -> (Symbol, Address, Address) {
+        (
+            Symbol::new(env, "payout_balance"),
+            recipient.clone(),
+            asset.clone(),
+        )
+    }
+
+    /// Asset payouts are converted to, if any
+    pub fn get_settlement_asset(env: &Env) -> Option<Address> {
+        env.storage()
+            .instance()
+            .get(&Self::settlement_asset_key(env))
+    }
+
+    /// Largest pool price impact, in bps, a settlement swap may cause
+    pub fn get_max_slippage_bps(env: &Env) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::max_slippage_key(env))
+            .unwrap_or(defaults::PAYOUT_MAX_SLIPPAGE_BPS)
+    }
+
+    pub fn set_max_slippage_bps(env: &Env, bps: i128) -> Result<(), ProtocolError> {
+        if !(0..=10000).contains(&bps) {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        config::set_param(
+            env,
+            "payouts",
+            "max_slippage_bps",
+            &Self::max_slippage_key(env),
+            None,
+            defaults::PAYOUT_MAX_SLIPPAGE_BPS,
+            bps,
+        );
+        Ok(())
+    }
+
+    /// Shares in `asset` waiting for settlement, oldest first
+    pub fn get_queue(env: &Env, asset: &Address) -> Vec<PayoutShare> {
+        env.storage()
+            .instance()
+            .get(&Self::queue_key(env, asset))
+            .unwrap_or_else(|| Vec::new(env))
+    }
+
+    fn set_queue(env: &Env, asset: &Address, queue: &Vec<PayoutShare>) {
+        let key = Self::queue_key(env, asset);
+        if queue.is_empty() {
+            env.storage().instance().remove(&key);
+        } else {
+            env.storage().instance().set(&key, queue);
+        }
+    }
+
+    /// Payout `recipient` may claim in `asset`
+    pub fn get_balance(env: &Env, recipient: &Address, asset: &Address) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::balance_key(env, recipient, asset))
+            .unwrap_or(0)
+    }
+
+    fn set_balance(env: &Env, recipient: &Address, asset: &Address, amount: i128) {
+        let key = Self::balance_key(env, recipient, asset);
+        if amount == 0 {
+            env.storage().instance().remove(&key);
+        } else {
+            env.storage().instance().set(&key, &amount);
+        }
+    }
+
+    fn credit(env: &Env, recipient: &Address, asset: &Address, amount: i128) {
+        let balance = Self::get_balance(env, recipient, asset).saturating_add(amount);
+        Self::set_balance(env, recipient, asset, balance);
+    }
+}
+
+/// Referral and keeper payouts
+pub struct Payouts;
+
+impl Payouts {
+    /// Set the reward settlement asset and the slippage its conversions may
+    /// cause (admin only)
+    pub fn set_settlement(
+        env: &Env,
+        caller: &Address,
+        asset: &Address,
+        max_slippage_bps: i128,
+    ) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        PayoutStorage::set_max_slippage_bps(env, max_slippage_bps)?;
+        config::set_address_param(
+            env,
+            "payouts",
+            "settlement_asset",
+            &PayoutStorage::settlement_asset_key(env),
+            None,
+            asset,
+        );
+        Ok(())
+    }
+
+    /// Pay `amount` of `asset` reserves out as a referral or keeper reward
+    /// to `recipient` (admin only). Reserves are left untouched when the
+    /// recipient opted out of referrals.
+    pub fn fund(
+        env: &Env,
+        caller: &Address,
+        recipient: &Address,
+        asset: &Address,
+        amount: i128,
+    ) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        if amount <= 0 {
+            return Err(ProtocolError::InvalidAmount);
+        }
+        if ReserveStorage::get(env, asset) < amount {
+            return Err(ProtocolError::InsufficientLiquidity);
+        }
+        if Self::accrue(env, recipient, asset, amount) {
+            ReserveStorage::add(env, asset, -amount);
+        }
+        Ok(())
+    }
+
+    /// Credit a fee share to `recipient`. Shares in the settlement asset,
+    /// or with none configured, are credited in kind right away; others are
+    /// queued for `settle_rewards` while the queue has room. Returns `false`
+    /// without accruing anything if `recipient` opted out of referrals, in
+    /// which case the share stays with the caller.
+    pub fn accrue(env: &Env, recipient: &Address, asset: &Address, amount: i128) -> bool {
+        if !Privacy::accepts_referrals(env, recipient) {
+            return false;
+        }
+        let mut queue = PayoutStorage::get_queue(env, asset);
+        let queued = match PayoutStorage::get_settlement_asset(env) {
+            Some(settlement) => settlement != *asset && queue.len() < MAX_QUEUED_SHARES,
+            None => false,
+        };
+        if queued {
+            queue.push_back(PayoutShare {
+                recipient: recipient.clone(),
+                amount,
+            });
+            PayoutStorage::set_queue(env, asset, &queue);
+        } else {
+            PayoutStorage::credit(env, recipient, asset, amount);
+        }
+        Self::emit(
+            env,
+            "payout_accrued",
+            asset,
+            amount,
+            (recipient.clone(), queued),
+        );
+        true
+    }
+
+    /// Convert up to `max_amount` of the shares queued in `asset` (and at
+    /// most [`MAX_SETTLE_SHARES`] of them) to the settlement asset, oldest
+    /// first, and credit each recipient their pro rata part of the output.
+    /// Permissionless.
+    #[cfg(feature = "amm")]
+    pub fn settle(
+        env: &Env,
+        asset: &Address,
+        max_amount: i128,
+    ) -> Result<SettlementResult, ProtocolError> {
+        if max_amount <= 0 {
+            return Err(ProtocolError::InvalidAmount);
+        }
+        let settlement =
+            PayoutStorage::get_settlement_asset(env).ok_or(ProtocolError::ConfigurationError)?;
+        if settlement == *asset {
+            return Err(ProtocolError::InvalidParameters);
+        }
+
+        let mut queue = PayoutStorage::get_queue(env, asset);
+        let mut batch: Vec<PayoutShare> = Vec::new(env);
+        let mut amount_in = 0i128;
+        while batch.len() < MAX_SETTLE_SHARES && amount_in < max_amount {
+            let Some(mut share) = queue.first() else {
+                break;
+            };
+            let take = share.amount.min(max_amount - amount_in);
+            if take == share.amount {
+                queue.pop_front();
+            } else {
+                share.amount -= take;
+                queue.set(0, share.clone());
+            }
+            amount_in += take;
+            batch.push_back(PayoutShare {
+                recipient: share.recipient,
+                amount: take,
+            });
+        }
+        PayoutStorage::set_queue(env, asset, &queue);
+        if batch.is_empty() {
+            return Ok(SettlementResult {
+                amount_in: 0,
+                amount_out: 0,
+                in_kind: false,
+            });
+        }
+
+        let amount_out = Self::swap(env, asset, &settlement, amount_in);
+        let result = match amount_out {
+            Some(amount_out) => {
+                let mut paid = 0i128;
+                for (i, share) in batch.iter().enumerate() {
+                    let part = if i as u32 + 1 == batch.len() {
+                        amount_out - paid
+                    } else {
+                        amount_out * share.amount / amount_in
+                    };
+                    paid += part;
+                    PayoutStorage::credit(env, &share.recipient, &settlement, part);
+                }
+                SettlementResult {
+                    amount_in,
+                    amount_out,
+                    in_kind: false,
+                }
+            }
+            None => {
+                for share in batch.iter() {
+                    PayoutStorage::credit(env, &share.recipient, asset, share.amount);
+                }
+                SettlementResult {
+                    amount_in,
+                    amount_out: 0,
+                    in_kind: true,
+                }
+            }
+        };
+        Self::emit(
+            env,
+            "rewards_settled",
+            asset,
+            amount_in,
+            (result.amount_out, result.in_kind),
+        );
+        Ok(result)
+    }
+
+    /// Swap held `amount_in` of `asset` into `settlement` within the max
+    /// slippage; `None` if there is no pool or the swap would not fill
+    #[cfg(feature = "amm")]
+    fn swap(env: &Env, asset: &Address, settlement: &Address, amount_in: i128) -> Option<i128> {
+        let pair_id = PairKey::new(asset.clone(), settlement.clone());
+        let quote = AMMRegistry::quote_swap_in(env, &pair_id, asset, amount_in).ok()?;
+        if !quote.sufficient_liquidity
+            || quote.price_impact_bps > PayoutStorage::get_max_slippage_bps(env)
+        {
+            return None;
+        }
+        AMMRegistry::swap_held(
+            env,
+            &pair_id,
+            asset,
+            amount_in,
+            quote.amount_out,
+            &env.current_contract_address(),
+        )
+        .ok()
+        .map(|(amount_out, _fee)| amount_out)
+    }
+
+    /// Transfer `recipient`'s whole payout balance in `asset` to them
+    /// (requires their auth). Returns the amount paid.
+    pub fn claim(env: &Env, recipient: &Address, asset: &Address) -> Result<i128, ProtocolError> {
+        recipient.require_auth();
+        let amount = PayoutStorage::get_balance(env, recipient, asset);
+        if amount <= 0 {
+            return Err(ProtocolError::InvalidAmount);
+        }
+        PayoutStorage::set_balance(env, recipient, asset, 0);
+        TransferEnforcer::transfer(
+            env,
+            asset,
+            &env.current_contract_address(),
+            recipient,
+            amount,
+            Symbol::new(env, "payout_claim"),
+        )?;
+        Self::emit(env, "payout_claimed", asset, amount, recipient.clone());
+        Ok(amount)
+    }
+
+    fn emit<D>(env: &Env, name: &str, asset: &Address, amount: i128, data: D)
+    where
+        D: IntoVal<Env, Val>,
+    {
+        let data: Val = data.into_val(env);
+        let event_type = Symbol::new(env, name);
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        EventTracker::record(
+            env,
+            event_type.clone(),
+            topics,
+            None,
+            Some(asset.clone()),
+            amount,
+        );
+        env.events()
+            .publish((event_type, asset.clone()), (amount, data));
+    }
+}
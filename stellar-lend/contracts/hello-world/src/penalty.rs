@@ -0,0 +1,248 @@
+//! Early withdrawal penalty module
+//! Deters yield sniping by charging a penalty on supply withdrawn shortly after it was deposited
+
+use crate::config;
+use crate::reserves::ReserveStorage;
+use crate::{EventTracker, ProtocolConfig, ProtocolError};
+use soroban_sdk::{contracttype, Address, Env, Symbol, Vec};
+
+/// Maximum number of open deposit lots tracked per user
+const MAX_LOTS: u32 = 16;
+
+/// Per-asset early withdrawal penalty configuration
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PenaltyConfig {
+    /// Penalty charged on amounts withdrawn inside the window (bps)
+    pub early_withdrawal_penalty_bps: i128,
+    /// Window after a deposit during which the penalty applies
+    pub penalty_window_secs: u64,
+}
+
+/// Deposit lot used for FIFO matching of withdrawals
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct DepositLot {
+    pub amount: i128,
+    pub deposited_at: u64,
+}
+
+/// Penalty storage management
+pub struct PenaltyStorage;
+
+impl PenaltyStorage {
+    pub(crate) fn config_key(env: &Env) -> Symbol {
+        Symbol::new(env, "penalty_config")
+    }
+
+    pub(crate) fn lots_key(env: &Env) -> Symbol {
+        Symbol::new(env, "deposit_lots")
+    }
+
+    pub fn get_config(env: &Env, asset: &Address) -> Option<PenaltyConfig> {
+        env.storage()
+            .instance()
+            .get(&(Self::config_key(env), asset.clone()))
+    }
+
+    pub fn save_config(env: &Env, asset: &Address, config: &PenaltyConfig) {
+        env.storage()
+            .instance()
+            .set(&(Self::config_key(env), asset.clone()), config);
+    }
+
+    pub fn get_lots(env: &Env, user: &Address) -> Vec<DepositLot> {
+        env.storage()
+            .instance()
+            .get(&(Self::lots_key(env), user.clone()))
+            .unwrap_or_else(|| Vec::new(env))
+    }
+
+    pub fn save_lots(env: &Env, user: &Address, lots: &Vec<DepositLot>) {
+        env.storage()
+            .instance()
+            .set(&(Self::lots_key(env), user.clone()), lots);
+    }
+}
+
+/// Outcome of consuming deposit lots for a withdrawal
+pub struct WithdrawalSettlement {
+    pub penalty: i128,
+    /// Lots left after the withdrawal
+    pub lots: Vec<DepositLot>,
+}
+
+/// Early withdrawal penalty logic
+pub struct WithdrawalPenalty;
+
+impl WithdrawalPenalty {
+    /// Configure the penalty for an asset (admin only). Zero values disable it.
+    pub fn set_config(
+        env: &Env,
+        caller: &Address,
+        asset: &Address,
+        penalty_bps: i128,
+        window_secs: u64,
+    ) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        if !(0..=10000).contains(&penalty_bps) {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        let old = PenaltyStorage::get_config(env, asset).unwrap_or(PenaltyConfig {
+            early_withdrawal_penalty_bps: 0,
+            penalty_window_secs: 0,
+        });
+        PenaltyStorage::save_config(
+            env,
+            asset,
+            &PenaltyConfig {
+                early_withdrawal_penalty_bps: penalty_bps,
+                penalty_window_secs: window_secs,
+            },
+        );
+        if old.early_withdrawal_penalty_bps != penalty_bps {
+            config::record_change(
+                env,
+                "penalty",
+                "penalty_bps",
+                Some(asset),
+                old.early_withdrawal_penalty_bps,
+                penalty_bps,
+            );
+        }
+        if old.penalty_window_secs != window_secs {
+            config::record_change(
+                env,
+                "penalty",
+                "penalty_window_secs",
+                Some(asset),
+                old.penalty_window_secs.into(),
+                window_secs.into(),
+            );
+        }
+        Self::emit(
+            env,
+            "withdrawal_penalty_set",
+            None,
+            asset,
+            penalty_bps,
+            window_secs,
+        );
+        Ok(())
+    }
+
+    /// Record a new deposit lot for a user
+    pub fn record_deposit(env: &Env, user: &Address, amount: i128) {
+        let mut lots = PenaltyStorage::get_lots(env, user);
+        lots.push_back(DepositLot {
+            amount,
+            deposited_at: env.ledger().timestamp(),
+        });
+        // Merge the two oldest lots when full, keeping the later timestamp so
+        // merging can never shorten a lot's penalty window
+        if lots.len() > MAX_LOTS {
+            let first = lots.pop_front_unchecked();
+            let mut second = lots.get_unchecked(0);
+            second.amount += first.amount;
+            lots.set(0, second);
+        }
+        PenaltyStorage::save_lots(env, user, &lots);
+    }
+
+    /// Consume deposit lots FIFO for a withdrawal and work out the penalty
+    /// owed, without writing anything. Apply the result with
+    /// [`WithdrawalPenalty::apply_withdrawal`] once the payout has succeeded.
+    ///
+    /// Lots are always consumed so they stay in step with the position, but a
+    /// penalty is only charged when `charge` is set (pure supply withdrawals).
+    pub fn plan_withdrawal(
+        env: &Env,
+        user: &Address,
+        asset: &Address,
+        amount: i128,
+        charge: bool,
+    ) -> WithdrawalSettlement {
+        let config = PenaltyStorage::get_config(env, asset);
+        let now = env.ledger().timestamp();
+        let mut lots = PenaltyStorage::get_lots(env, user);
+        let mut remaining = amount;
+        let mut penalized = 0i128;
+
+        while remaining > 0 && !lots.is_empty() {
+            let mut lot = lots.get_unchecked(0);
+            let take = remaining.min(lot.amount);
+            if let Some(cfg) = &config {
+                if now < lot.deposited_at.saturating_add(cfg.penalty_window_secs) {
+                    penalized += take;
+                }
+            }
+            remaining -= take;
+            lot.amount -= take;
+            if lot.amount == 0 {
+                lots.pop_front_unchecked();
+            } else {
+                lots.set(0, lot);
+            }
+        }
+
+        let penalty = match config {
+            Some(cfg) if charge => penalized * cfg.early_withdrawal_penalty_bps / 10000,
+            _ => 0,
+        };
+        WithdrawalSettlement { penalty, lots }
+    }
+
+    /// Persist a settlement from [`WithdrawalPenalty::plan_withdrawal`]
+    pub fn apply_withdrawal(
+        env: &Env,
+        user: &Address,
+        asset: &Address,
+        settlement: &WithdrawalSettlement,
+    ) {
+        PenaltyStorage::save_lots(env, user, &settlement.lots);
+        if settlement.penalty > 0 {
+            ReserveStorage::add(env, asset, settlement.penalty);
+            Self::emit(
+                env,
+                "withdrawal_penalty",
+                Some(user),
+                asset,
+                settlement.penalty,
+                env.ledger().timestamp(),
+            );
+        }
+    }
+
+    fn emit(
+        env: &Env,
+        name: &str,
+        user: Option<&Address>,
+        asset: &Address,
+        amount: i128,
+        value: u64,
+    ) {
+        let event_type = Symbol::new(env, name);
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        topics.push_back(Symbol::new(env, "asset"));
+        EventTracker::record(
+            env,
+            event_type.clone(),
+            topics,
+            user.cloned(),
+            Some(asset.clone()),
+            amount,
+        );
+        env.events().publish(
+            (event_type, asset.clone()),
+            (
+                Symbol::new(env, "user"),
+                user.cloned(),
+                Symbol::new(env, "amount"),
+                amount,
+                Symbol::new(env, "value"),
+                value,
+            ),
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! Per-user privacy flags
+//!
+//! Flags are stored next to the user profile under
+//! `UserStorageKey::PrivacyFlags`:
+//! - [`PRIVACY_ANALYTICS_OPT_OUT`]: the analytics hook keeps the user out of
+//!   the activity log, per-user analytics and the size distribution, while
+//!   protocol-wide totals still count their activity. Setting it clears
+//!   what was already recorded.
+//! - [`PRIVACY_REFERRAL_OPT_OUT`]: the user may not be named as a referrer
+//!   and accrues no referral rewards; payouts to them are skipped and stay
+//!   with the protocol.
+
+use crate::analytics::AnalyticsStorage;
+use crate::{EventTracker, ProtocolError, TokenRegistry, UserStorageKey};
+use soroban_sdk::{Address, Env, Symbol, Vec};
+
+/// Keep the user out of per-user analytics
+pub const PRIVACY_ANALYTICS_OPT_OUT: u32 = 1;
+/// Exclude the user from the referral program
+pub const PRIVACY_REFERRAL_OPT_OUT: u32 = 2;
+
+const PRIVACY_FLAGS_MASK: u32 = PRIVACY_ANALYTICS_OPT_OUT | PRIVACY_REFERRAL_OPT_OUT;
+
+/// Privacy flag logic
+pub struct Privacy;
+
+impl Privacy {
+    pub fn get_flags(env: &Env, user: &Address) -> u32 {
+        env.storage()
+            .instance()
+            .get(&UserStorageKey::PrivacyFlags(user.clone()))
+            .unwrap_or(0)
+    }
+
+    /// Whether per-user analytics may be recorded for `user`
+    pub fn tracks_analytics(env: &Env, user: &Address) -> bool {
+        Self::get_flags(env, user) & PRIVACY_ANALYTICS_OPT_OUT == 0
+    }
+
+    /// Whether `user` may accrue referral rewards
+    pub fn accepts_referrals(env: &Env, user: &Address) -> bool {
+        Self::get_flags(env, user) & PRIVACY_REFERRAL_OPT_OUT == 0
+    }
+
+    /// Replace the caller's flags
+    pub fn set_flags(env: &Env, user: &Address, flags: u32) -> Result<(), ProtocolError> {
+        if flags & !PRIVACY_FLAGS_MASK != 0 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        let key = UserStorageKey::PrivacyFlags(user.clone());
+        if flags == 0 {
+            env.storage().instance().remove(&key);
+        } else {
+            env.storage().instance().set(&key, &flags);
+        }
+        if flags & PRIVACY_ANALYTICS_OPT_OUT != 0 {
+            Self::clear_analytics(env, user);
+        }
+
+        let event_type = Symbol::new(env, "privacy_flags_set");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        topics.push_back(Symbol::new(env, "user"));
+        EventTracker::record(
+            env,
+            event_type.clone(),
+            topics,
+            Some(user.clone()),
+            None,
+            flags as i128,
+        );
+        env.events().publish((event_type, user.clone()), flags);
+        Ok(())
+    }
+
+    /// Drop everything per-user analytics recorded about `user`
+    fn clear_analytics(env: &Env, user: &Address) {
+        let log = AnalyticsStorage::get_activity_log(env);
+        let mut kept = Vec::new(env);
+        for entry in log.iter() {
+            if entry.user != *user {
+                kept.push_back(entry);
+            }
+        }
+        if kept.len() != log.len() {
+            AnalyticsStorage::put_activity_log(env, &kept);
+        }
+
+        let mut users = AnalyticsStorage::get_user_analytics(env);
+        if users.contains_key(user.clone()) {
+            users.remove(user.clone());
+            AnalyticsStorage::put_user_analytics(env, &users);
+        }
+
+        let (old_collateral, old_debt) = AnalyticsStorage::get_size_bucket(env, user);
+        if old_collateral == 0 && old_debt == 0 {
+            return;
+        }
+        if let Some(asset) = TokenRegistry::primary_asset(env) {
+            let mut analytics = AnalyticsStorage::get_asset_analytics_for_asset(env, &asset);
+            analytics
+                .collateral_distribution
+                .transition(old_collateral, 0);
+            analytics.debt_distribution.transition(old_debt, 0);
+            analytics.last_update = env.ledger().timestamp();
+            AnalyticsStorage::_update_asset_analytics(env, &asset, &analytics);
+        }
+        AnalyticsStorage::put_size_bucket(env, user, (0, 0));
+    }
+}
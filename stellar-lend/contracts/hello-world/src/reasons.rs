@@ -0,0 +1,162 @@
+//! Stated reasons for manual admin changes
+//!
+//! Direct admin setters for pauses, fees and freezes accept a reason code
+//! drawn from an admin-managed registry. With the `strict_admin_reasons`
+//! feature flag enabled the reason is mandatory; a reason that is not
+//! registered is always rejected.
+//!
+//! The reason is held for the duration of the call by a [`ChangeReason`]
+//! scope, so the shared event helpers in `config` and the parameter history
+//! pick it up without every setter passing it along. Changes executed by
+//! governance for a proposal record the proposal id instead.
+
+use crate::capabilities::FeatureFlags;
+use crate::{EventTracker, ProtocolConfig, ProtocolError};
+use soroban_sdk::{Address, Env, Symbol, Vec};
+
+/// Feature flag making a reason mandatory for direct admin setters
+pub const STRICT_REASONS_FLAG: &str = "strict_admin_reasons";
+/// Maximum number of registered reason codes
+pub const MAX_REASON_CODES: u32 = 32;
+
+/// Reason code registry
+pub struct ReasonCodes;
+
+impl ReasonCodes {
+    pub(crate) fn key(env: &Env) -> Symbol {
+        Symbol::new(env, "admin_reason_codes")
+    }
+
+    pub fn get(env: &Env) -> Vec<Symbol> {
+        env.storage()
+            .instance()
+            .get(&Self::key(env))
+            .unwrap_or_else(|| Vec::new(env))
+    }
+
+    pub fn is_registered(env: &Env, code: &Symbol) -> bool {
+        Self::get(env).contains(code)
+    }
+
+    /// Register a reason code (admin only)
+    pub fn register(env: &Env, caller: &Address, code: Symbol) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        let mut codes = Self::get(env);
+        if codes.contains(&code) {
+            return Ok(());
+        }
+        if codes.len() >= MAX_REASON_CODES {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        codes.push_back(code);
+        env.storage().instance().set(&Self::key(env), &codes);
+        Ok(())
+    }
+
+    /// Remove a reason code (admin only)
+    pub fn remove(env: &Env, caller: &Address, code: &Symbol) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        let mut codes = Self::get(env);
+        let index = codes.first_index_of(code).ok_or(ProtocolError::NotFound)?;
+        codes.remove(index);
+        env.storage().instance().set(&Self::key(env), &codes);
+        Ok(())
+    }
+}
+
+/// Reason attached to the changes made by the current call; cleared on drop
+pub struct ChangeReason<'a> {
+    env: &'a Env,
+}
+
+impl<'a> ChangeReason<'a> {
+    pub(crate) fn reason_key(env: &Env) -> Symbol {
+        Symbol::new(env, "change_reason")
+    }
+
+    pub(crate) fn proposal_key(env: &Env) -> Symbol {
+        Symbol::new(env, "change_proposal")
+    }
+
+    /// Scope for a direct admin setter named `action`. Fails with
+    /// `ReasonRequired` if `reason` is not registered, or is missing while
+    /// strict mode is on.
+    pub fn enter(
+        env: &'a Env,
+        caller: &Address,
+        action: &str,
+        reason: Option<Symbol>,
+    ) -> Result<Self, ProtocolError> {
+        match reason {
+            Some(reason) => {
+                if !ReasonCodes::is_registered(env, &reason) {
+                    return Err(ProtocolError::ReasonRequired);
+                }
+                env.storage()
+                    .instance()
+                    .set(&Self::reason_key(env), &reason);
+                Self::emit(env, caller, action, Some(reason), None);
+            }
+            None if FeatureFlags::is_enabled(env, STRICT_REASONS_FLAG) => {
+                return Err(ProtocolError::ReasonRequired);
+            }
+            None => {}
+        }
+        Ok(Self { env })
+    }
+
+    /// Scope for governance executing an action of proposal `id`
+    pub fn for_proposal(env: &'a Env, caller: &Address, action: &str, id: u64) -> Self {
+        env.storage().instance().set(&Self::proposal_key(env), &id);
+        Self::emit(env, caller, action, None, Some(id));
+        Self { env }
+    }
+
+    /// Reason code of the current call, if any
+    pub fn current(env: &Env) -> Option<Symbol> {
+        env.storage().instance().get(&Self::reason_key(env))
+    }
+
+    /// Proposal the current call executes, if any
+    pub fn proposal(env: &Env) -> Option<u64> {
+        env.storage().instance().get(&Self::proposal_key(env))
+    }
+
+    fn emit(
+        env: &Env,
+        caller: &Address,
+        action: &str,
+        reason: Option<Symbol>,
+        proposal: Option<u64>,
+    ) {
+        let event_type = Symbol::new(env, "admin_change_reason");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        topics.push_back(Symbol::new(env, action));
+        EventTracker::record(
+            env,
+            event_type.clone(),
+            topics,
+            Some(caller.clone()),
+            None,
+            proposal.unwrap_or(0) as i128,
+        );
+        env.events().publish(
+            (event_type, Symbol::new(env, action)),
+            (caller.clone(), reason, proposal),
+        );
+    }
+}
+
+impl<'a> Drop for ChangeReason<'a> {
+    fn drop(&mut self) {
+        self.env
+            .storage()
+            .instance()
+            .remove(&Self::reason_key(self.env));
+        self.env
+            .storage()
+            .instance()
+            .remove(&Self::proposal_key(self.env));
+    }
+}
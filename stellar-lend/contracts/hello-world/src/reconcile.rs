@@ -0,0 +1,145 @@
+//! Token balance reconciliation
+//! Compares the contract's actual token balance against internal accounting totals
+
+use crate::config;
+use crate::liquidity::Liquidity;
+use crate::{EventTracker, InterestRateStorage, ProtocolConfig, ProtocolError, TokenRegistry};
+use soroban_sdk::{contracttype, Address, Env, Symbol, Vec};
+
+/// Result of a reconciliation run
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct ReconcileReport {
+    pub asset: Address,
+    /// Token balance reported by the token contract
+    pub actual_balance: i128,
+    /// Balance implied by accounting: supplied - borrowed + reserves +
+    /// insurance + pending claims
+    pub expected_balance: i128,
+    /// actual - expected; positive means unaccounted surplus
+    pub delta: i128,
+    pub timestamp: u64,
+}
+
+/// Reconciliation storage management
+pub struct ReconcileStorage;
+
+impl ReconcileStorage {
+    pub(crate) fn last_key(env: &Env) -> Symbol {
+        Symbol::new(env, "last_reconcile")
+    }
+
+    pub(crate) fn tolerance_key(env: &Env) -> Symbol {
+        Symbol::new(env, "reconcile_tolerance")
+    }
+
+    pub fn get_last(env: &Env, asset: &Address) -> Option<ReconcileReport> {
+        env.storage()
+            .instance()
+            .get(&(Self::last_key(env), asset.clone()))
+    }
+
+    pub fn save_last(env: &Env, report: &ReconcileReport) {
+        env.storage()
+            .instance()
+            .set(&(Self::last_key(env), report.asset.clone()), report);
+    }
+
+    pub fn get_tolerance(env: &Env) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::tolerance_key(env))
+            .unwrap_or(0)
+    }
+
+    pub fn set_tolerance(env: &Env, tolerance: i128) {
+        config::set_param(
+            env,
+            "reconcile",
+            "tolerance",
+            &Self::tolerance_key(env),
+            None,
+            0,
+            tolerance,
+        );
+    }
+}
+
+/// Reconciliation logic
+pub struct Reconciler;
+
+impl Reconciler {
+    /// Compare the actual balance of `asset` held by the contract with the
+    /// balance implied by accounting and record the result
+    pub fn reconcile(env: &Env, asset: &Address) -> Result<ReconcileReport, ProtocolError> {
+        // Accounting totals are only maintained for the primary asset
+        let primary = TokenRegistry::require_primary_asset(env)?;
+        if primary != *asset {
+            return Err(ProtocolError::AssetNotSupported);
+        }
+
+        // Earmarked funds (reserves with protocol fees, insurance, pending
+        // claims) are counted the same way available liquidity nets them
+        let state = InterestRateStorage::get_state(env);
+        let breakdown = Liquidity::breakdown(env, asset);
+        let expected_balance = state
+            .total_supplied
+            .saturating_sub(state.total_borrowed)
+            .saturating_add(breakdown.earmarked());
+        let actual_balance = breakdown.balance;
+        let delta = actual_balance.saturating_sub(expected_balance);
+        // Earmarked funds must always be backed by tokens actually held
+        Liquidity::check_invariant(env, asset)?;
+
+        let report = ReconcileReport {
+            asset: asset.clone(),
+            actual_balance,
+            expected_balance,
+            delta,
+            timestamp: env.ledger().timestamp(),
+        };
+        ReconcileStorage::save_last(env, &report);
+
+        if delta.abs() > ReconcileStorage::get_tolerance(env) {
+            let event_type = Symbol::new(env, "reconciliation_delta");
+            let mut topics = Vec::new(env);
+            topics.push_back(event_type.clone());
+            topics.push_back(Symbol::new(env, "asset"));
+            EventTracker::record(
+                env,
+                event_type.clone(),
+                topics,
+                None,
+                Some(asset.clone()),
+                delta,
+            );
+            env.events().publish(
+                (event_type, asset.clone()),
+                (
+                    Symbol::new(env, "actual"),
+                    actual_balance,
+                    Symbol::new(env, "expected"),
+                    expected_balance,
+                    Symbol::new(env, "delta"),
+                    delta,
+                ),
+            );
+        }
+
+        Ok(report)
+    }
+
+    /// Set the absolute delta tolerated before an event is emitted (admin only)
+    pub fn set_tolerance(
+        env: &Env,
+        caller: &Address,
+        tolerance: i128,
+    ) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        if tolerance < 0 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        ReconcileStorage::set_tolerance(env, tolerance);
+        Ok(())
+    }
+}
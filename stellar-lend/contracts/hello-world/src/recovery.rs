@@ -0,0 +1,201 @@
+//! Force-closing corrupted positions
+//!
+//! Last-resort recovery for a position entry that no longer decodes as
+//! [`Position`] (e.g. after a faulty storage migration), which would
+//! otherwise block its owner forever. It needs two approvals:
+//! - governance approves the exact [`ForceCloseResolution`] for the user
+//!   through `Action::ApproveForceClose`
+//! - the admin then executes it with `force_close_position`
+//!
+//! The raw balances cannot be read on-chain, so the resolution states them.
+//! The position's collateral and debt are removed from the global totals,
+//! `collateral_returned` is paid to the user, and the retained collateral
+//! offsets the debt: any shortfall is recorded as bad debt, any excess goes
+//! to reserves. Every force-close is appended to a permanent audit list.
+
+use crate::diagnostics::StorageDiagnostics;
+use crate::reserves::ReserveStorage;
+use crate::{
+    EventTracker, InterestRateStorage, Position, ProtocolConfig, ProtocolError, StateHelper,
+    TokenRegistry, TransferEnforcer,
+};
+use soroban_sdk::{contracttype, Address, Env, Symbol, Vec};
+
+/// How to dispose of a corrupted position's raw balances
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct ForceCloseResolution {
+    /// Collateral recorded in the corrupted entry
+    pub collateral: i128,
+    /// Debt (principal and interest) recorded in the corrupted entry
+    pub debt: i128,
+    /// Collateral paid back to the user (at most `collateral`)
+    pub collateral_returned: i128,
+}
+
+/// Audit record of an executed force-close
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct ForceCloseRecord {
+    pub user: Address,
+    pub resolution: ForceCloseResolution,
+    /// Debt not covered by retained collateral
+    pub bad_debt: i128,
+    pub closed_by: Address,
+    pub timestamp: u64,
+    pub ledger_seq: u32,
+}
+
+/// Recovery storage management
+pub struct RecoveryStorage;
+
+impl RecoveryStorage {
+    pub(crate) fn approval_key(env: &Env, user: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "force_close_approval"), user.clone())
+    }
+
+    pub(crate) fn audit_key(env: &Env) -> Symbol {
+        Symbol::new(env, "force_close_audit")
+    }
+
+    pub(crate) fn bad_debt_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "bad_debt"), asset.clone())
+    }
+
+    pub fn get_approval(env: &Env, user: &Address) -> Option<ForceCloseResolution> {
+        env.storage().instance().get(&Self::approval_key(env, user))
+    }
+
+    /// Every executed force-close, oldest first
+    pub fn get_audit(env: &Env) -> Vec<ForceCloseRecord> {
+        env.storage()
+            .instance()
+            .get(&Self::audit_key(env))
+            .unwrap_or_else(|| Vec::new(env))
+    }
+
+    /// Debt written off for an asset
+    pub fn get_bad_debt(env: &Env, asset: &Address) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::bad_debt_key(env, asset))
+            .unwrap_or(0)
+    }
+
+    fn add_bad_debt(env: &Env, asset: &Address, amount: i128) {
+        let total = Self::get_bad_debt(env, asset).saturating_add(amount);
+        env.storage()
+            .instance()
+            .set(&Self::bad_debt_key(env, asset), &total);
+    }
+}
+
+/// Force-close logic
+pub struct Recovery;
+
+impl Recovery {
+    /// Whether `user` has a position entry that exists but does not decode
+    pub fn is_corrupted(env: &Env, user: &Address) -> bool {
+        let key = StateHelper::position_key(env, user);
+        env.storage().instance().has(&key)
+            && !StorageDiagnostics::decodes::<(Symbol, Address), Position>(env, &key)
+    }
+
+    /// Governance approval of a resolution for `user`'s corrupted position.
+    /// Replaces any earlier approval.
+    pub fn approve(
+        env: &Env,
+        user: &Address,
+        resolution: ForceCloseResolution,
+    ) -> Result<(), ProtocolError> {
+        if resolution.collateral < 0
+            || resolution.debt < 0
+            || resolution.collateral_returned < 0
+            || resolution.collateral_returned > resolution.collateral
+        {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        if !Self::is_corrupted(env, user) {
+            return Err(ProtocolError::InvalidOperation);
+        }
+        env.storage()
+            .instance()
+            .set(&RecoveryStorage::approval_key(env, user), &resolution);
+        Ok(())
+    }
+
+    /// Execute the governance-approved resolution (admin only). Refuses to
+    /// touch a position that decodes.
+    pub fn force_close(
+        env: &Env,
+        caller: &Address,
+        user: &Address,
+        resolution: &ForceCloseResolution,
+    ) -> Result<ForceCloseRecord, ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        if !Self::is_corrupted(env, user) {
+            return Err(ProtocolError::InvalidOperation);
+        }
+        let approved = RecoveryStorage::get_approval(env, user).ok_or(ProtocolError::NotFound)?;
+        if approved != *resolution {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        let asset = TokenRegistry::require_primary_asset(env)?;
+
+        let retained = resolution.collateral - resolution.collateral_returned;
+        let bad_debt = (resolution.debt - retained).max(0);
+        let surplus = (retained - resolution.debt).max(0);
+
+        env.storage()
+            .instance()
+            .remove(&StateHelper::position_key(env, user));
+        env.storage()
+            .instance()
+            .remove(&RecoveryStorage::approval_key(env, user));
+        InterestRateStorage::adjust_totals(env, -resolution.collateral, -resolution.debt);
+        if bad_debt > 0 {
+            RecoveryStorage::add_bad_debt(env, &asset, bad_debt);
+        }
+        if surplus > 0 {
+            ReserveStorage::add(env, &asset, surplus);
+        }
+        if resolution.collateral_returned > 0 {
+            TransferEnforcer::transfer_out(
+                env,
+                user,
+                resolution.collateral_returned,
+                Symbol::new(env, "force_close"),
+            )?;
+        }
+
+        let record = ForceCloseRecord {
+            user: user.clone(),
+            resolution: resolution.clone(),
+            bad_debt,
+            closed_by: caller.clone(),
+            timestamp: env.ledger().timestamp(),
+            ledger_seq: env.ledger().sequence(),
+        };
+        let mut audit = RecoveryStorage::get_audit(env);
+        audit.push_back(record.clone());
+        env.storage()
+            .instance()
+            .set(&RecoveryStorage::audit_key(env), &audit);
+
+        let event_type = Symbol::new(env, "position_force_closed");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        topics.push_back(Symbol::new(env, "user"));
+        EventTracker::record(
+            env,
+            event_type.clone(),
+            topics,
+            Some(user.clone()),
+            Some(asset),
+            resolution.collateral_returned,
+        );
+        env.events()
+            .publish((event_type, user.clone()), record.clone());
+        Ok(record)
+    }
+}
@@ -1,7 +1,13 @@
 //! Repay module for StellarLend protocol
 //! Handles debt repayment functionality and related operations
 
+#[cfg(feature = "amm")]
+use crate::amm::{AMMRegistry, PairKey};
 use crate::analytics::AnalyticsModule;
+use crate::credit::Credit;
+use crate::transfer::PositionTransfer;
+#[cfg(feature = "amm")]
+use crate::TokenRegistry;
 use crate::{
     EmergencyManager, InterestRateManager, InterestRateStorage, OperationKind, ProtocolError,
     ProtocolEvent, ReentrancyGuard, StateHelper, TransferEnforcer, UserManager,
@@ -71,6 +77,18 @@ impl RepayParams {
     }
 }
 
+/// Outcome of a repayment funded by swapping another asset
+#[cfg(feature = "amm")]
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct SwapRepayResult {
+    /// Debt asset the swap delivered
+    pub swapped_out: i128,
+    pub repaid: i128,
+    /// Swap output above the outstanding debt, returned to the caller
+    pub refunded: i128,
+}
+
 /// Repay module implementation
 pub struct RepayModule;
 
@@ -114,6 +132,8 @@ impl RepayModule {
 
             position.debt -= repay_amount;
             StateHelper::save_position(env, &position);
+            PositionTransfer::cancel_on_activity(env, &position.user);
+            InterestRateStorage::adjust_totals(env, 0, -repay_amount);
 
             // Emit event
             let collateral_ratio = if position.debt > 0 {
@@ -133,6 +153,7 @@ impl RepayModule {
             // Analytics
             AnalyticsModule::record_activity(env, repayer, "repay", repay_amount, None)?;
             UserManager::record_activity(env, repayer, OperationKind::Repay, repay_amount)?;
+            Credit::record_repayment(env, repayer);
 
             Ok(())
         })();
@@ -141,6 +162,125 @@ impl RepayModule {
         result
     }
 
+    /// Repay debt with `pay_amount` of `pay_asset`, swapped into
+    /// `debt_asset` through their AMM pair. The swap must deliver at least
+    /// `min_debt_repaid`; anything beyond the outstanding debt is sent back
+    /// to `caller` in the debt asset. Fails before pulling funds when the
+    /// two assets have no pair, and with `AssetNotSupported` unless
+    /// `debt_asset` is the primary asset the debt is denominated in.
+    #[cfg(feature = "amm")]
+    pub fn repay_with(
+        env: &Env,
+        caller: &Address,
+        pay_asset: &Address,
+        pay_amount: i128,
+        debt_asset: &Address,
+        min_debt_repaid: i128,
+    ) -> Result<SwapRepayResult, ProtocolError> {
+        ReentrancyGuard::enter(env)?;
+        let result = (|| -> Result<SwapRepayResult, ProtocolError> {
+            if pay_amount <= 0 || min_debt_repaid < 0 {
+                return Err(RepayError::InvalidAmount.into());
+            }
+            if pay_asset == debt_asset {
+                return Err(ProtocolError::InvalidParameters);
+            }
+            if TokenRegistry::primary_asset(env).as_ref() != Some(debt_asset) {
+                return Err(ProtocolError::AssetNotSupported);
+            }
+
+            EmergencyManager::ensure_operation_allowed(env, OperationKind::Repay)?;
+            UserManager::ensure_operation_allowed(env, caller, OperationKind::Repay, pay_amount)?;
+
+            // Resolve the route before any funds move
+            let pair_id = PairKey::new(pay_asset.clone(), debt_asset.clone());
+            AMMRegistry::quote_swap_in(env, &pair_id, pay_asset, pay_amount)?;
+
+            let mut position = match StateHelper::get_position(env, caller) {
+                Some(pos) => pos,
+                None => return Err(RepayError::PositionNotFound.into()),
+            };
+            let state = InterestRateStorage::update_state(env);
+            InterestRateManager::accrue_interest_for_position(
+                env,
+                &mut position,
+                state.current_borrow_rate,
+                state.current_supply_rate,
+            );
+            if position.debt == 0 {
+                return Err(RepayError::InvalidOperation.into());
+            }
+
+            let this = env.current_contract_address();
+            TransferEnforcer::transfer(
+                env,
+                pay_asset,
+                caller,
+                &this,
+                pay_amount,
+                Symbol::new(env, "repay_with_in"),
+            )?;
+            let (swapped_out, _fee) = AMMRegistry::swap_held(
+                env,
+                &pair_id,
+                pay_asset,
+                pay_amount,
+                min_debt_repaid,
+                &this,
+            )?;
+
+            let repaid = core::cmp::min(swapped_out, position.debt);
+            position.debt -= repaid;
+            StateHelper::save_position(env, &position);
+            PositionTransfer::cancel_on_activity(env, &position.user);
+            InterestRateStorage::adjust_totals(env, 0, -repaid);
+
+            let refunded = swapped_out - repaid;
+            if refunded > 0 {
+                TransferEnforcer::transfer(
+                    env,
+                    debt_asset,
+                    &this,
+                    caller,
+                    refunded,
+                    Symbol::new(env, "repay_with_refund"),
+                )?;
+            }
+
+            let collateral_ratio = if position.debt > 0 {
+                (position.collateral * 100) / position.debt
+            } else {
+                0
+            };
+            ProtocolEvent::PositionUpdated(
+                caller.clone(),
+                position.collateral,
+                position.debt,
+                collateral_ratio,
+            )
+            .emit(env);
+
+            AnalyticsModule::record_activity(
+                env,
+                caller,
+                "repay",
+                repaid,
+                Some(debt_asset.clone()),
+            )?;
+            UserManager::record_activity(env, caller, OperationKind::Repay, repaid)?;
+            Credit::record_repayment(env, caller);
+
+            Ok(SwapRepayResult {
+                swapped_out,
+                repaid,
+                refunded,
+            })
+        })();
+
+        ReentrancyGuard::exit(env);
+        result
+    }
+
     /// Repay debt for a specific asset
     pub fn _repay_asset(
         env: &Env,
@@ -179,6 +319,7 @@ impl RepayModule {
             };
             position.debt -= repay_amount;
             StateHelper::save_position(env, &position);
+            PositionTransfer::cancel_on_activity(env, &position.user);
 
             // Emit cross-asset repay event
             ProtocolEvent::CrossRepay(user_addr, asset.clone(), repay_amount).emit(env);
@@ -223,6 +364,7 @@ impl RepayModule {
             // Clear all debt
             position.debt = 0;
             StateHelper::save_position(env, &position);
+            PositionTransfer::cancel_on_activity(env, &position.user);
 
             // Emit event
             ProtocolEvent::PositionUpdated(
@@ -0,0 +1,29 @@
+//! Protocol reserves accounting
+//! Tracks the share of each asset's contract balance that belongs to the protocol
+
+use soroban_sdk::{Address, Env, Symbol};
+
+/// Reserve storage management
+pub struct ReserveStorage;
+
+impl ReserveStorage {
+    pub(crate) fn reserves_key(env: &Env) -> Symbol {
+        Symbol::new(env, "protocol_reserves")
+    }
+
+    /// Reserves held for an asset
+    pub fn get(env: &Env, asset: &Address) -> i128 {
+        env.storage()
+            .instance()
+            .get(&(Self::reserves_key(env), asset.clone()))
+            .unwrap_or(0)
+    }
+
+    /// Credit reserves for an asset
+    pub fn add(env: &Env, asset: &Address, amount: i128) {
+        let total = Self::get(env, asset).saturating_add(amount);
+        env.storage()
+            .instance()
+            .set(&(Self::reserves_key(env), asset.clone()), &total);
+    }
+}
@@ -0,0 +1,149 @@
+//! Reward emissions driven by a per-asset emission schedule
+//!
+//! A schedule is a list of `(start_ts, rate_per_sec)` segments with strictly
+//! increasing start times. Each segment runs until the next one starts, and
+//! nothing is emitted before the first segment. Accrual integrates the
+//! schedule piecewise, so a single accrual spanning a rate change credits
+//! exactly the per-segment amounts.
+
+use crate::ProtocolError;
+use soroban_sdk::{contracttype, Address, Env, Symbol, Vec};
+
+/// One segment of an emission schedule
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct EmissionSegment {
+    pub start_ts: u64,
+    pub rate_per_sec: i128,
+}
+
+/// Emission progress for an asset
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct RewardState {
+    /// Total rewards emitted so far
+    pub emitted: i128,
+    /// Timestamp emissions have been accrued up to
+    pub last_update: u64,
+}
+
+/// Reward storage management
+pub struct RewardStorage;
+
+impl RewardStorage {
+    pub(crate) fn schedule_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "emission_schedule"), asset.clone())
+    }
+
+    pub(crate) fn state_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "reward_state"), asset.clone())
+    }
+
+    pub fn get_schedule(env: &Env, asset: &Address) -> Vec<EmissionSegment> {
+        env.storage()
+            .instance()
+            .get(&Self::schedule_key(env, asset))
+            .unwrap_or_else(|| Vec::new(env))
+    }
+
+    pub fn set_schedule(env: &Env, asset: &Address, schedule: &Vec<EmissionSegment>) {
+        env.storage()
+            .instance()
+            .set(&Self::schedule_key(env, asset), schedule);
+    }
+
+    pub fn get_state(env: &Env, asset: &Address) -> RewardState {
+        env.storage()
+            .instance()
+            .get(&Self::state_key(env, asset))
+            .unwrap_or(RewardState {
+                emitted: 0,
+                last_update: 0,
+            })
+    }
+
+    pub fn save_state(env: &Env, asset: &Address, state: &RewardState) {
+        env.storage()
+            .instance()
+            .set(&Self::state_key(env, asset), state);
+    }
+}
+
+/// Reward emission logic
+pub struct Rewards;
+
+impl Rewards {
+    /// Replace an asset's emission schedule. Emissions under the old schedule
+    /// are accrued first so the change only applies from now on.
+    pub fn set_schedule(
+        env: &Env,
+        asset: &Address,
+        schedule: Vec<EmissionSegment>,
+    ) -> Result<(), ProtocolError> {
+        Self::validate(&schedule)?;
+        Self::accrue(env, asset);
+        RewardStorage::set_schedule(env, asset, &schedule);
+        Ok(())
+    }
+
+    /// Segments must start at strictly increasing times with non-negative rates
+    pub fn validate(schedule: &Vec<EmissionSegment>) -> Result<(), ProtocolError> {
+        let mut prev: Option<u64> = None;
+        for segment in schedule.iter() {
+            if segment.rate_per_sec < 0 {
+                return Err(ProtocolError::InvalidParameters);
+            }
+            if let Some(prev_start) = prev {
+                if segment.start_ts <= prev_start {
+                    return Err(ProtocolError::InvalidParameters);
+                }
+            }
+            prev = Some(segment.start_ts);
+        }
+        Ok(())
+    }
+
+    /// Amount emitted by `schedule` over `[from, to)`
+    pub fn emitted_between(schedule: &Vec<EmissionSegment>, from: u64, to: u64) -> i128 {
+        let mut total: i128 = 0;
+        let len = schedule.len();
+        for i in 0..len {
+            let segment = schedule.get(i).unwrap();
+            let segment_end = if i + 1 < len {
+                schedule.get(i + 1).unwrap().start_ts
+            } else {
+                u64::MAX
+            };
+            let start = segment.start_ts.max(from);
+            let end = segment_end.min(to);
+            if end > start {
+                total = total
+                    .saturating_add(segment.rate_per_sec.saturating_mul((end - start) as i128));
+            }
+        }
+        total
+    }
+
+    /// Accrue emissions for an asset up to now
+    pub fn accrue(env: &Env, asset: &Address) -> RewardState {
+        let now = env.ledger().timestamp();
+        let mut state = RewardStorage::get_state(env, asset);
+        if state.last_update == 0 {
+            state.last_update = now;
+            RewardStorage::save_state(env, asset, &state);
+            return state;
+        }
+        if now <= state.last_update {
+            return state;
+        }
+
+        let schedule = RewardStorage::get_schedule(env, asset);
+        state.emitted =
+            state
+                .emitted
+                .saturating_add(Self::emitted_between(&schedule, state.last_update, now));
+        state.last_update = now;
+        RewardStorage::save_state(env, asset, &state);
+        state
+    }
+}
@@ -0,0 +1,168 @@
+//! Timelocked changes to sensitive role addresses
+//!
+//! The guardian and treasury can only be replaced through a two-step flow:
+//! the admin proposes a new address, and the change can be applied once
+//! `ROLE_CHANGE_DELAY_SECS` has passed. The admin may cancel at any time, and
+//! the current guardian may cancel a pending guardian change, so a
+//! compromised admin key cannot silently replace it.
+
+use crate::history::ParamHistory;
+use crate::{EventTracker, ProtocolConfig, ProtocolError};
+use soroban_sdk::{contracttype, Address, Env, Symbol, Vec};
+
+/// Delay between proposing and applying a role change (48 hours)
+pub const ROLE_CHANGE_DELAY_SECS: u64 = 48 * 60 * 60;
+
+/// Roles whose address changes are timelocked
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum RoleKind {
+    Guardian,
+    Treasury,
+}
+
+impl RoleKind {
+    fn name(&self, env: &Env) -> Symbol {
+        match self {
+            RoleKind::Guardian => Symbol::new(env, "guardian"),
+            RoleKind::Treasury => Symbol::new(env, "treasury"),
+        }
+    }
+}
+
+/// A proposed role change waiting for its delay to pass
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PendingRoleChange {
+    pub role: RoleKind,
+    pub new_address: Address,
+    pub proposed_by: Address,
+    pub effective_at: u64,
+}
+
+/// Pending role change storage
+pub struct RoleChangeStorage;
+
+impl RoleChangeStorage {
+    pub(crate) fn key(env: &Env, role: RoleKind) -> (Symbol, RoleKind) {
+        (Symbol::new(env, "pending_role_change"), role)
+    }
+
+    pub fn get(env: &Env, role: RoleKind) -> Option<PendingRoleChange> {
+        env.storage().instance().get(&Self::key(env, role))
+    }
+
+    pub fn save(env: &Env, change: &PendingRoleChange) {
+        env.storage()
+            .instance()
+            .set(&Self::key(env, change.role), change);
+    }
+
+    pub fn remove(env: &Env, role: RoleKind) {
+        env.storage().instance().remove(&Self::key(env, role));
+    }
+}
+
+/// Role change flow
+pub struct RoleManager;
+
+impl RoleManager {
+    /// Propose a new address for `role` (admin only). Replaces any pending change.
+    pub fn propose(
+        env: &Env,
+        caller: &Address,
+        role: RoleKind,
+        new_address: Address,
+    ) -> Result<PendingRoleChange, ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        let change = PendingRoleChange {
+            role,
+            new_address: new_address.clone(),
+            proposed_by: caller.clone(),
+            effective_at: env.ledger().timestamp() + ROLE_CHANGE_DELAY_SECS,
+        };
+        RoleChangeStorage::save(env, &change);
+        ParamHistory::record(
+            env,
+            role.name(env),
+            "proposed",
+            caller,
+            Some(new_address),
+            None,
+        );
+        Self::emit(env, "role_change_proposed", &change, caller);
+        Ok(change)
+    }
+
+    /// Apply a pending change once its delay has passed (admin only)
+    pub fn apply(env: &Env, caller: &Address, role: RoleKind) -> Result<Address, ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        let change = RoleChangeStorage::get(env, role).ok_or(ProtocolError::NotFound)?;
+        if env.ledger().timestamp() < change.effective_at {
+            return Err(ProtocolError::InvalidOperation);
+        }
+        match role {
+            RoleKind::Guardian => ProtocolConfig::store_guardian(env, &change.new_address),
+            RoleKind::Treasury => ProtocolConfig::store_treasury(env, &change.new_address),
+        }
+        RoleChangeStorage::remove(env, role);
+        ParamHistory::record(
+            env,
+            role.name(env),
+            "applied",
+            caller,
+            Some(change.new_address.clone()),
+            None,
+        );
+        Self::emit(env, "role_change_applied", &change, caller);
+        Ok(change.new_address)
+    }
+
+    /// Cancel a pending change. The admin may cancel any change; the current
+    /// guardian may cancel a pending guardian change.
+    pub fn cancel(env: &Env, caller: &Address, role: RoleKind) -> Result<(), ProtocolError> {
+        let guardian_self_defense = role == RoleKind::Guardian
+            && ProtocolConfig::get_guardian(env).as_ref() == Some(caller);
+        if !guardian_self_defense {
+            ProtocolConfig::require_admin(env, caller)?;
+        }
+        let change = RoleChangeStorage::get(env, role).ok_or(ProtocolError::NotFound)?;
+        RoleChangeStorage::remove(env, role);
+        ParamHistory::record(
+            env,
+            role.name(env),
+            "cancelled",
+            caller,
+            Some(change.new_address.clone()),
+            None,
+        );
+        Self::emit(env, "role_change_cancelled", &change, caller);
+        Ok(())
+    }
+
+    fn emit(env: &Env, name: &str, change: &PendingRoleChange, actor: &Address) {
+        let event_type = Symbol::new(env, name);
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        topics.push_back(change.role.name(env));
+        EventTracker::record(
+            env,
+            event_type.clone(),
+            topics,
+            Some(actor.clone()),
+            None,
+            change.effective_at as i128,
+        );
+        env.events().publish(
+            (event_type, change.role.name(env)),
+            (
+                Symbol::new(env, "new_address"),
+                change.new_address.clone(),
+                Symbol::new(env, "effective_at"),
+                change.effective_at,
+                Symbol::new(env, "actor"),
+                actor.clone(),
+            ),
+        );
+    }
+}
@@ -0,0 +1,98 @@
+//! Batch position reads for liquidation bots
+//! Pages through the position index and prices each entry from the oracle
+//! cache, so a scan never fans out to live oracle sources
+
+use crate::oracle::OracleStorage;
+use crate::pagination;
+use crate::{ProtocolConfig, StateHelper, TokenRegistry};
+use soroban_sdk::{contracttype, Address, Env, Vec};
+
+/// Maximum entries returned by one [`PositionScanner::scan`] call
+pub const MAX_SCAN_LIMIT: u32 = 10;
+
+/// One borrower's position as seen by a scan
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct ScanEntry {
+    pub borrower: Address,
+    /// Asset both collateral and debt are denominated in
+    pub asset: Option<Address>,
+    pub collateral: i128,
+    pub debt: i128,
+    /// Last cached price of `asset`, if any
+    pub price: Option<i128>,
+    /// Collateral ratio in bps (0 when there is no debt)
+    pub collateral_ratio_bps: i128,
+    /// Collateral ratio relative to the liquidation threshold, in bps;
+    /// below 10000 is liquidatable. `i128::MAX` when there is no debt.
+    pub health_factor_bps: i128,
+    /// Age in seconds of the oldest cached price used, `None` when no
+    /// cached price was available
+    pub oldest_price_age: Option<u64>,
+}
+
+define_page!(
+    /// Page of scanned positions in position index order
+    ScanPage,
+    ScanEntry
+);
+
+pub struct PositionScanner;
+
+impl PositionScanner {
+    /// Return up to `limit` (capped at [`MAX_SCAN_LIMIT`]) entries starting at
+    /// `cursor`. Users whose position has since been removed are skipped but
+    /// still advance the cursor.
+    pub fn scan(env: &Env, cursor: u32, limit: u32) -> ScanPage {
+        let index = StateHelper::get_position_index(env);
+        let (start, end) = pagination::bounds(index.len(), cursor, limit, MAX_SCAN_LIMIT);
+        let asset = TokenRegistry::primary_asset(env);
+        let cached = asset
+            .as_ref()
+            .and_then(|a| OracleStorage::get_price_cache(env).get(a.clone()))
+            .filter(|(p, _)| *p > 0);
+        let now = env.ledger().timestamp();
+        let threshold_bps = ProtocolConfig::get_liquidation_threshold_bps(env);
+
+        let mut entries = Vec::new(env);
+        let mut next = start;
+        while next < end {
+            let user = index.get(next).unwrap();
+            next += 1;
+            let position = match StateHelper::get_position(env, &user) {
+                Some(p) => p,
+                None => continue,
+            };
+            // Collateral and debt share one asset, so the cached price scales
+            // both sides; it is applied anyway so the ratio tracks valuation.
+            let price = cached.map(|(p, _)| p).unwrap_or(1);
+            let collateral_ratio_bps = if position.debt > 0 {
+                (position.collateral * price * 10000) / (position.debt * price)
+            } else {
+                0
+            };
+            let health_factor_bps = if position.debt == 0 {
+                i128::MAX
+            } else if threshold_bps > 0 {
+                collateral_ratio_bps * 10000 / threshold_bps
+            } else {
+                i128::MAX
+            };
+            entries.push_back(ScanEntry {
+                borrower: user,
+                asset: asset.clone(),
+                collateral: position.collateral,
+                debt: position.debt,
+                price: cached.map(|(p, _)| p),
+                collateral_ratio_bps,
+                health_factor_bps,
+                oldest_price_age: cached.map(|(_, ts)| now.saturating_sub(ts)),
+            });
+        }
+        ScanPage {
+            items: entries,
+            total: index.len() as u64,
+            cursor: next,
+        }
+    }
+}
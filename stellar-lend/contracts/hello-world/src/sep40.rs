@@ -0,0 +1,105 @@
+//! SEP-40 price feed interface
+//!
+//! Serves the protocol's aggregated oracle prices through the standard
+//! `lastprice` / `price` / `prices` / `decimals` / `resolution` / `assets`
+//! shapes, so other Soroban contracts can consume the curated feed like any
+//! SEP-40 provider. Prices come from the aggregation cache as last computed
+//! and history from the TWAP observation buffer; nothing is fetched on read.
+//! Only Stellar assets are priced, in USD.
+
+use crate::oracle::{Oracle, OracleStorage, PRICE_SCALE};
+use soroban_sdk::{contracttype, Address, Env, Symbol, Vec};
+
+/// An asset as identified by SEP-40
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum Asset {
+    Stellar(Address),
+    Other(Symbol),
+}
+
+/// A price with the time it was computed
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PriceData {
+    pub price: i128,
+    pub timestamp: u64,
+}
+
+/// SEP-40 read views over the oracle
+pub struct PriceFeed;
+
+impl PriceFeed {
+    /// Most recent aggregated price of `asset`
+    pub fn lastprice(env: &Env, asset: &Asset) -> Option<PriceData> {
+        let Asset::Stellar(addr) = asset else {
+            return None;
+        };
+        Oracle::last_price(env, addr).map(|(price, timestamp)| PriceData { price, timestamp })
+    }
+
+    /// Price of `asset` in effect at `timestamp`: the latest buffered
+    /// observation at or before it
+    pub fn price(env: &Env, asset: &Asset, timestamp: u64) -> Option<PriceData> {
+        let Asset::Stellar(addr) = asset else {
+            return None;
+        };
+        let buffer = OracleStorage::get_twap_buffer(env, addr);
+        buffer
+            .iter()
+            .rev()
+            .find(|obs| obs.timestamp <= timestamp)
+            .map(|obs| PriceData {
+                price: obs.price,
+                timestamp: obs.timestamp,
+            })
+    }
+
+    /// Up to `records` most recent observations of `asset`, newest first;
+    /// `None` when there are none
+    pub fn prices(env: &Env, asset: &Asset, records: u32) -> Option<Vec<PriceData>> {
+        let Asset::Stellar(addr) = asset else {
+            return None;
+        };
+        let mut out = Vec::new(env);
+        for obs in OracleStorage::get_twap_buffer(env, addr).iter().rev() {
+            if out.len() >= records {
+                break;
+            }
+            out.push_back(PriceData {
+                price: obs.price,
+                timestamp: obs.timestamp,
+            });
+        }
+        if out.is_empty() {
+            None
+        } else {
+            Some(out)
+        }
+    }
+
+    /// Asset every price is quoted in
+    pub fn base(env: &Env) -> Asset {
+        Asset::Other(Symbol::new(env, "USD"))
+    }
+
+    /// Decimal places of every price
+    pub fn decimals() -> u32 {
+        PRICE_SCALE.ilog10()
+    }
+
+    /// Nominal seconds between observations. Prices are re-aggregated once
+    /// the cache expires, so this is the cache TTL (at least 1).
+    pub fn resolution(env: &Env) -> u32 {
+        OracleStorage::get_price_cache_ttl(env).clamp(1, u32::MAX as u64) as u32
+    }
+
+    /// Assets with registered oracle sources
+    pub fn assets(env: &Env) -> Vec<Asset> {
+        let mut assets = Vec::new(env);
+        for addr in OracleStorage::get_assets(env).iter() {
+            assets.push_back(Asset::Stellar(addr));
+        }
+        assets
+    }
+}
@@ -0,0 +1,145 @@
+//! Share math for supply accounting
+//!
+//! Every conversion between underlying amounts and shares goes through
+//! [`to_shares`] and [`to_underlying`]. Both add a virtual share supply and a
+//! virtual underlying balance to the pool, which makes the first-depositor
+//! inflation attack (mint a dust share, donate underlying, round the next
+//! depositor down to zero) unprofitable: the donation is mostly captured by
+//! the virtual shares, and a victim's rounding loss is bounded by roughly
+//! `total_underlying / VIRTUAL_SHARES`.
+//!
+//! Callers pick the rounding direction explicitly. The rule is to always
+//! round against the user: down when minting shares for a deposit or paying
+//! out a withdrawal, up when computing shares to burn for a requested amount.
+//!
+//! Supply is still tracked 1:1 in underlying today; these helpers are the
+//! single place any share-denominated accounting must convert through.
+#![allow(dead_code)]
+
+use crate::ProtocolError;
+use stellarlend_core::math::mul_div;
+pub use stellarlend_core::math::Rounding;
+
+/// Virtual share supply added to every conversion (six decimals of offset)
+pub const VIRTUAL_SHARES: i128 = 1_000_000;
+/// Virtual underlying balance added to every conversion
+pub const VIRTUAL_ASSETS: i128 = 1;
+
+fn check_inputs(
+    value: i128,
+    total_shares: i128,
+    total_underlying: i128,
+) -> Result<(), ProtocolError> {
+    if value < 0 || total_shares < 0 || total_underlying < 0 {
+        return Err(ProtocolError::InvalidAmount);
+    }
+    Ok(())
+}
+
+/// Shares worth `amount` of underlying
+pub fn to_shares(
+    amount: i128,
+    total_shares: i128,
+    total_underlying: i128,
+    rounding: Rounding,
+) -> Result<i128, ProtocolError> {
+    check_inputs(amount, total_shares, total_underlying)?;
+    mul_div(
+        amount,
+        total_shares.saturating_add(VIRTUAL_SHARES),
+        total_underlying.saturating_add(VIRTUAL_ASSETS),
+        rounding,
+    )
+}
+
+/// Underlying worth `shares`
+pub fn to_underlying(
+    shares: i128,
+    total_shares: i128,
+    total_underlying: i128,
+    rounding: Rounding,
+) -> Result<i128, ProtocolError> {
+    check_inputs(shares, total_shares, total_underlying)?;
+    mul_div(
+        shares,
+        total_underlying.saturating_add(VIRTUAL_ASSETS),
+        total_shares.saturating_add(VIRTUAL_SHARES),
+        rounding,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_depositor_inflation_attack_is_bounded() {
+        let mut total_shares = 0;
+        let mut total_underlying = 0;
+
+        // Attacker front-runs with a 1-unit supply, then donates directly
+        let attacker_deposit = 1;
+        let attacker_shares = to_shares(
+            attacker_deposit,
+            total_shares,
+            total_underlying,
+            Rounding::Down,
+        )
+        .unwrap();
+        total_shares += attacker_shares;
+        total_underlying += attacker_deposit;
+        let donation = 1_000_000_000_000;
+        total_underlying += donation;
+
+        // Victim deposits a smaller amount than the donation
+        let victim_deposit = 100_000_000_000;
+        let victim_shares = to_shares(
+            victim_deposit,
+            total_shares,
+            total_underlying,
+            Rounding::Down,
+        )
+        .unwrap();
+        assert!(victim_shares > 0);
+        total_shares += victim_shares;
+        total_underlying += victim_deposit;
+
+        let victim_out = to_underlying(
+            victim_shares,
+            total_shares,
+            total_underlying,
+            Rounding::Down,
+        )
+        .unwrap();
+        let victim_loss = victim_deposit - victim_out;
+        assert!(victim_loss <= (attacker_deposit + donation) / VIRTUAL_SHARES);
+        assert!(victim_loss <= victim_deposit / 100_000);
+
+        // Most of the donation is captured by the virtual shares
+        let attacker_out = to_underlying(
+            attacker_shares,
+            total_shares,
+            total_underlying,
+            Rounding::Down,
+        )
+        .unwrap();
+        let attacker_loss = attacker_deposit + donation - attacker_out;
+        assert!(attacker_loss > 1000 * victim_loss);
+    }
+
+    #[test]
+    fn test_rounding_direction_favours_pool() {
+        let (total_shares, total_underlying) = (3_000_000, 2_000_000);
+        let down = to_shares(10, total_shares, total_underlying, Rounding::Down).unwrap();
+        let up = to_shares(10, total_shares, total_underlying, Rounding::Up).unwrap();
+        assert_eq!(up, down + 1);
+
+        // Round-tripping with the user-unfavourable direction never creates value
+        let back = to_underlying(down, total_shares, total_underlying, Rounding::Down).unwrap();
+        assert!(back <= 10);
+        assert_eq!(
+            to_shares(-1, total_shares, total_underlying, Rounding::Down),
+            Err(ProtocolError::InvalidAmount)
+        );
+    }
+}
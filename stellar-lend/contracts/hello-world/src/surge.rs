@@ -0,0 +1,209 @@
+//! Borrow growth circuit breaker
+//!
+//! Caps how much new borrowing one asset, or the protocol as a whole, may
+//! take on within a [`SURGE_WINDOW_SECS`] window. The borrow that pushes a
+//! window past its limit still settles (a failed call would roll the trip
+//! back with it), but trips the breaker: later borrows in that scope fail
+//! with `ProtocolPaused` until the window rolls over or the guardian clears
+//! it. Repayments and liquidations are never affected.
+//!
+//! Per-asset limits are in units of the asset; the global limit is in USD
+//! at the oracle price scale. A limit of 0 disables the check, the default.
+
+use crate::caps::CapManager;
+use crate::config;
+use crate::{EventTracker, ProtocolConfig, ProtocolError};
+use soroban_sdk::{contracttype, Address, Env, Symbol, Vec};
+
+/// Length of a borrow growth window (24 hours)
+pub const SURGE_WINDOW_SECS: u64 = 24 * 60 * 60;
+
+/// Borrowing accumulated in the current window of one scope
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct BorrowWindow {
+    pub started_at: u64,
+    /// New borrows since `started_at`, in the scope's limit unit
+    pub borrowed: i128,
+    pub tripped: bool,
+}
+
+/// Borrow growth storage; `None` scopes the global limit
+pub struct SurgeStorage;
+
+impl SurgeStorage {
+    pub(crate) fn limit_key(env: &Env, asset: &Option<Address>) -> (Symbol, Option<Address>) {
+        (Symbol::new(env, "surge_limit"), asset.clone())
+    }
+    pub(crate) fn window_key(env: &Env, asset: &Option<Address>) -> (Symbol, Option<Address>) {
+        (Symbol::new(env, "surge_window"), asset.clone())
+    }
+
+    /// Growth allowed per window (0 = disabled)
+    pub fn get_limit(env: &Env, asset: &Option<Address>) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::limit_key(env, asset))
+            .unwrap_or(0)
+    }
+
+    pub fn set_limit(env: &Env, asset: &Option<Address>, limit: i128) {
+        config::set_param(
+            env,
+            "surge",
+            "borrow_growth_limit",
+            &Self::limit_key(env, asset),
+            asset.as_ref(),
+            0,
+            limit,
+        );
+    }
+
+    /// The scope's window as of now; an expired window reads as a fresh one
+    pub fn get_window(env: &Env, asset: &Option<Address>) -> BorrowWindow {
+        let now = env.ledger().timestamp();
+        let fresh = BorrowWindow {
+            started_at: now,
+            borrowed: 0,
+            tripped: false,
+        };
+        match env
+            .storage()
+            .instance()
+            .get::<_, BorrowWindow>(&Self::window_key(env, asset))
+        {
+            Some(window) if now.saturating_sub(window.started_at) < SURGE_WINDOW_SECS => window,
+            _ => fresh,
+        }
+    }
+
+    fn save_window(env: &Env, asset: &Option<Address>, window: &BorrowWindow) {
+        env.storage()
+            .instance()
+            .set(&Self::window_key(env, asset), window);
+    }
+}
+
+/// Borrow growth enforcement
+pub struct BorrowSurge;
+
+impl BorrowSurge {
+    /// Set the growth limit of `asset`, or the global USD one with `None`
+    /// (admin only, 0 = disabled)
+    pub fn set_limit(
+        env: &Env,
+        caller: &Address,
+        asset: Option<Address>,
+        limit: i128,
+    ) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        if limit < 0 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        SurgeStorage::set_limit(env, &asset, limit);
+        Ok(())
+    }
+
+    /// Fail with `ProtocolPaused` while the breaker of `asset` or the global
+    /// one is tripped
+    pub fn check(env: &Env, asset: &Address) -> Result<(), ProtocolError> {
+        for scope in [Some(asset.clone()), None] {
+            if SurgeStorage::get_limit(env, &scope) > 0
+                && SurgeStorage::get_window(env, &scope).tripped
+            {
+                return Err(ProtocolError::ProtocolPaused);
+            }
+        }
+        Ok(())
+    }
+
+    /// Count a settled borrow of `amount` of `asset`, tripping any scope it
+    /// takes past its limit. The global scope prices it only when enabled.
+    pub fn record(env: &Env, asset: &Address, amount: i128) -> Result<(), ProtocolError> {
+        for scope in [Some(asset.clone()), None] {
+            let limit = SurgeStorage::get_limit(env, &scope);
+            if limit == 0 {
+                continue;
+            }
+            let added = match scope {
+                Some(_) => amount,
+                None => CapManager::usd_value(env, asset, amount)?,
+            };
+            let mut window = SurgeStorage::get_window(env, &scope);
+            window.borrowed = window.borrowed.saturating_add(added);
+            if window.borrowed > limit && !window.tripped {
+                window.tripped = true;
+                Self::emit(
+                    env,
+                    "borrow_surge_limited",
+                    &scope,
+                    None,
+                    window.borrowed,
+                    limit,
+                );
+            }
+            SurgeStorage::save_window(env, &scope, &window);
+        }
+        Ok(())
+    }
+
+    /// Reset a tripped breaker before its window rolls (guardian or admin),
+    /// starting a new window from now
+    pub fn clear(env: &Env, caller: &Address, asset: Option<Address>) -> Result<(), ProtocolError> {
+        if ProtocolConfig::get_guardian(env).as_ref() != Some(caller) {
+            ProtocolConfig::require_admin(env, caller)?;
+        }
+        let window = SurgeStorage::get_window(env, &asset);
+        if !window.tripped {
+            return Err(ProtocolError::InvalidOperation);
+        }
+        SurgeStorage::save_window(
+            env,
+            &asset,
+            &BorrowWindow {
+                started_at: env.ledger().timestamp(),
+                borrowed: 0,
+                tripped: false,
+            },
+        );
+        Self::emit(
+            env,
+            "borrow_surge_cleared",
+            &asset,
+            Some(caller.clone()),
+            window.borrowed,
+            SurgeStorage::get_limit(env, &asset),
+        );
+        Ok(())
+    }
+
+    fn emit(
+        env: &Env,
+        name: &str,
+        scope: &Option<Address>,
+        actor: Option<Address>,
+        borrowed: i128,
+        limit: i128,
+    ) {
+        let event_type = Symbol::new(env, name);
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        EventTracker::record(
+            env,
+            event_type.clone(),
+            topics,
+            actor,
+            scope.clone(),
+            borrowed,
+        );
+        env.events().publish(
+            (event_type, scope.clone()),
+            (
+                Symbol::new(env, "borrowed"),
+                borrowed,
+                Symbol::new(env, "limit"),
+                limit,
+            ),
+        );
+    }
+}
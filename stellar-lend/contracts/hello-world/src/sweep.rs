@@ -0,0 +1,258 @@
+//! Position health re-checks after pushed price moves
+//!
+//! Every asset keeps an index of the users that deposited or borrowed it.
+//! When a pushed price (`submit_oracle_price`) moves further from the
+//! source's previous submission than the configured deviation, a sweep of
+//! that index starts. Each push after that, large move or not, re-evaluates
+//! the next [`MAX_SWEEP_POSITIONS`] holders through [`Alerts::evaluate`],
+//! which emits `position_at_risk` for any that crossed, until every holder
+//! has been covered once. The cursor carries over, so a new sweep resumes
+//! where the last one stopped rather than rechecking the head of the index.
+//!
+//! The index lives in persistent storage, one entry per slot, so a push
+//! only reads the slots it evaluates however many holders there are.
+//! Holders whose position has been closed out are pruned as the sweep
+//! reaches them; depositing or borrowing again re-indexes them.
+//!
+//! Collateral and debt are both denominated in the primary asset, so a price
+//! move alone does not change a stored ratio; what a sweep catches are
+//! positions that crossed since their last save, e.g. after a liquidation
+//! threshold change.
+
+use crate::alerts::Alerts;
+use crate::{config, defaults};
+use crate::{EventTracker, ProtocolConfig, ProtocolError, StateHelper};
+use soroban_sdk::{contracttype, Address, Env, Symbol, Vec};
+
+/// Holders re-evaluated per push
+pub const MAX_SWEEP_POSITIONS: u32 = 5;
+
+/// Progress of an asset's health sweep
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct SweepState {
+    /// Index position of the next holder to evaluate
+    pub cursor: u32,
+    /// Holders still to evaluate in the current sweep (0 = idle)
+    pub remaining: u32,
+}
+
+/// Holder index and sweep storage
+pub struct SweepStorage;
+
+impl SweepStorage {
+    pub(crate) fn holder_count_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "asset_holders"), asset.clone())
+    }
+
+    pub(crate) fn holder_slot_key(env: &Env, asset: &Address, slot: u32) -> (Symbol, Address, u32) {
+        (Symbol::new(env, "asset_holder_at"), asset.clone(), slot)
+    }
+
+    pub(crate) fn holder_marker_key(
+        env: &Env,
+        asset: &Address,
+        user: &Address,
+    ) -> (Symbol, Address, Address) {
+        (
+            Symbol::new(env, "asset_holder"),
+            asset.clone(),
+            user.clone(),
+        )
+    }
+
+    pub(crate) fn state_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "price_sweep"), asset.clone())
+    }
+
+    pub(crate) fn deviation_key(env: &Env) -> Symbol {
+        Symbol::new(env, "sweep_deviation_bps")
+    }
+
+    /// Number of indexed holders of `asset`
+    pub fn get_holder_count(env: &Env, asset: &Address) -> u32 {
+        env.storage()
+            .persistent()
+            .get(&Self::holder_count_key(env, asset))
+            .unwrap_or(0)
+    }
+
+    /// Holder in index slot `slot`, if within the index
+    pub fn get_holder(env: &Env, asset: &Address, slot: u32) -> Option<Address> {
+        env.storage()
+            .persistent()
+            .get(&Self::holder_slot_key(env, asset, slot))
+    }
+
+    fn set_holder_count(env: &Env, asset: &Address, count: u32) {
+        let key = Self::holder_count_key(env, asset);
+        if count == 0 {
+            env.storage().persistent().remove(&key);
+        } else {
+            env.storage().persistent().set(&key, &count);
+        }
+    }
+
+    fn put_holder(env: &Env, asset: &Address, slot: u32, user: &Address) {
+        env.storage()
+            .persistent()
+            .set(&Self::holder_slot_key(env, asset, slot), user);
+        env.storage()
+            .persistent()
+            .set(&Self::holder_marker_key(env, asset, user), &slot);
+    }
+
+    /// Drop the holder in `slot`, moving the last holder into its place
+    fn remove_holder(env: &Env, asset: &Address, slot: u32) {
+        let count = Self::get_holder_count(env, asset);
+        let Some(user) = Self::get_holder(env, asset, slot) else {
+            return;
+        };
+        env.storage()
+            .persistent()
+            .remove(&Self::holder_marker_key(env, asset, &user));
+        let last = count - 1;
+        if slot != last {
+            if let Some(moved) = Self::get_holder(env, asset, last) {
+                Self::put_holder(env, asset, slot, &moved);
+            }
+        }
+        env.storage()
+            .persistent()
+            .remove(&Self::holder_slot_key(env, asset, last));
+        Self::set_holder_count(env, asset, last);
+    }
+
+    pub fn get_state(env: &Env, asset: &Address) -> SweepState {
+        env.storage()
+            .instance()
+            .get(&Self::state_key(env, asset))
+            .unwrap_or(SweepState {
+                cursor: 0,
+                remaining: 0,
+            })
+    }
+
+    fn save_state(env: &Env, asset: &Address, state: &SweepState) {
+        env.storage()
+            .instance()
+            .set(&Self::state_key(env, asset), state);
+    }
+
+    /// Price move in bps that starts a sweep (0 = disabled)
+    pub fn get_deviation_bps(env: &Env) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::deviation_key(env))
+            .unwrap_or(defaults::PRICE_SWEEP_DEVIATION_BPS)
+    }
+
+    pub fn set_deviation_bps(env: &Env, bps: i128) {
+        config::set_param(
+            env,
+            "sweep",
+            "deviation_bps",
+            &Self::deviation_key(env),
+            None,
+            defaults::PRICE_SWEEP_DEVIATION_BPS,
+            bps,
+        );
+    }
+}
+
+/// Pushed-price health sweeps
+pub struct PriceSweep;
+
+impl PriceSweep {
+    /// Add `user` to the holder index of `asset` if not already there
+    pub fn index_holder(env: &Env, asset: &Address, user: &Address) {
+        let marker = SweepStorage::holder_marker_key(env, asset, user);
+        if env.storage().persistent().has(&marker) {
+            return;
+        }
+        let count = SweepStorage::get_holder_count(env, asset);
+        SweepStorage::put_holder(env, asset, count, user);
+        SweepStorage::set_holder_count(env, asset, count + 1);
+    }
+
+    /// Set the price move that starts a sweep (admin only, 0 = disabled)
+    pub fn set_deviation_bps(env: &Env, caller: &Address, bps: i128) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        if bps < 0 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        SweepStorage::set_deviation_bps(env, bps);
+        Ok(())
+    }
+
+    /// Called after a price for `asset` was pushed; `previous` is the same
+    /// source's prior submission. Starts a sweep on a large enough move and
+    /// advances any running one, pruning closed-out holders it meets.
+    /// Returns how many holders were visited.
+    pub fn on_price_pushed(env: &Env, asset: &Address, previous: Option<i128>, price: i128) -> u32 {
+        let count = SweepStorage::get_holder_count(env, asset);
+        if count == 0 {
+            return 0;
+        }
+        let mut state = SweepStorage::get_state(env, asset);
+        let threshold = SweepStorage::get_deviation_bps(env);
+        if let Some(previous) = previous.filter(|p| *p > 0) {
+            let moved_bps = (price - previous).abs() * 10000 / previous;
+            if threshold > 0 && moved_bps > threshold {
+                state.remaining = count;
+                Self::emit(env, asset, moved_bps, count);
+            }
+        }
+        if state.remaining == 0 {
+            return 0;
+        }
+
+        let mut visited = 0;
+        while visited < MAX_SWEEP_POSITIONS && state.remaining > 0 {
+            let count = SweepStorage::get_holder_count(env, asset);
+            if count == 0 {
+                state.remaining = 0;
+                break;
+            }
+            if state.cursor >= count {
+                state.cursor = 0;
+            }
+            let user = SweepStorage::get_holder(env, asset, state.cursor);
+            match user.and_then(|user| StateHelper::get_position(env, &user)) {
+                Some(position) if position.collateral > 0 || position.debt > 0 => {
+                    Alerts::evaluate(env, &position);
+                    state.cursor += 1;
+                }
+                // The last holder moves into this slot, so the cursor stays
+                _ => SweepStorage::remove_holder(env, asset, state.cursor),
+            }
+            state.remaining -= 1;
+            visited += 1;
+        }
+        SweepStorage::save_state(env, asset, &state);
+        visited
+    }
+
+    fn emit(env: &Env, asset: &Address, moved_bps: i128, holders: u32) {
+        let event_type = Symbol::new(env, "price_sweep_started");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        EventTracker::record(
+            env,
+            event_type.clone(),
+            topics,
+            None,
+            Some(asset.clone()),
+            moved_bps,
+        );
+        env.events().publish(
+            (event_type, asset.clone()),
+            (
+                Symbol::new(env, "moved_bps"),
+                moved_bps,
+                Symbol::new(env, "holders"),
+                holders,
+            ),
+        );
+    }
+}
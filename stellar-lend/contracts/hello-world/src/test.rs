@@ -1,4 +1,5 @@
 use super::*;
+use alloc::string::ToString;
 use soroban_sdk::{
     contract, contractimpl, testutils::Address as _, testutils::Ledger, Address, Env, Map, String,
     Symbol,
@@ -26,6 +27,14 @@ impl MockToken {
     }
 
     pub fn transfer(env: Env, from: Address, to: Address, amount: i128) {
+        if env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, "failing"))
+            .unwrap_or(false)
+        {
+            panic!("transfer blocked");
+        }
         Self::deduct_balance(&env, &from, amount);
         Self::add_balance(&env, &to, amount);
     }
@@ -33,6 +42,13 @@ impl MockToken {
     pub fn balance(env: Env, id: Address) -> i128 {
         Self::get_balance(&env, &id)
     }
+
+    /// Make every transfer fail, as a frozen or deauthorized account would
+    pub fn set_failing(env: Env, failing: bool) {
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, "failing"), &failing);
+    }
 }
 
 impl MockToken {
@@ -90,6 +106,95 @@ impl FlashLoanReceiver {
     }
 }
 
+#[contract]
+pub struct MockPriceOracle;
+
+#[contractimpl]
+impl MockPriceOracle {
+    pub fn set_price(env: Env, asset: Address, price: i128) {
+        env.storage().instance().set(&asset, &price);
+    }
+
+    /// A negative price stands for an outage: the call traps
+    pub fn get_price(env: Env, asset: Address) -> i128 {
+        let price = env.storage().instance().get(&asset).unwrap_or(0);
+        if price < 0 {
+            panic!("oracle unavailable");
+        }
+        price
+    }
+}
+
+/// Stand-in for a v2 deployment; rejects migrations unless told to accept
+#[contract]
+pub struct MockMigrationTarget;
+
+#[contractimpl]
+impl MockMigrationTarget {
+    pub fn set_accepting(env: Env, accepting: bool) {
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, "accepting"), &accepting);
+    }
+
+    pub fn receive_migrated_position(
+        env: Env,
+        user: Address,
+        collateral: crate::migration::CollateralSpec,
+        debt: crate::migration::DebtSpec,
+    ) -> bool {
+        let accepting: bool = env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, "accepting"))
+            .unwrap_or(false);
+        if accepting {
+            // Mint equivalent debt on this side
+            env.storage()
+                .instance()
+                .set(&user, &(collateral.amount, debt.amount));
+        }
+        accepting
+    }
+}
+
+/// Flash swap receiver that pays back preconfigured amounts of each asset
+#[cfg(feature = "amm")]
+#[contract]
+pub struct MockFlashSwapReceiver;
+
+#[cfg(feature = "amm")]
+#[contractimpl]
+impl MockFlashSwapReceiver {
+    pub fn configure(env: Env, pool: Address, repay_a: i128, repay_b: i128) {
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, "repay"), &(pool, repay_a, repay_b));
+    }
+
+    pub fn on_flash_swap(
+        env: Env,
+        _caller: Address,
+        pair_id: crate::amm::PairKey,
+        _amount_out_a: i128,
+        _amount_out_b: i128,
+        _data: soroban_sdk::Bytes,
+    ) {
+        let (pool, repay_a, repay_b): (Address, i128, i128) = env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, "repay"))
+            .unwrap();
+        let this = env.current_contract_address();
+        if repay_a > 0 {
+            TokenClient::new(&env, &pair_id.asset_a).transfer(&this, &pool, &repay_a);
+        }
+        if repay_b > 0 {
+            TokenClient::new(&env, &pair_id.asset_b).transfer(&this, &pool, &repay_b);
+        }
+    }
+}
+
 /// Test utilities for creating test environments and addresses
 pub struct TestUtils;
 
@@ -303,7 +408,7 @@ fn test_borrow_success() {
         Contract::deposit_collateral(env.clone(), user.to_string(), 2000).unwrap();
 
         // Test successful borrow
-        let result = Contract::borrow(env.clone(), user.to_string(), 1000);
+        let result = Contract::borrow(env.clone(), user.to_string(), 1000, 0);
         assert!(result.is_ok());
 
         // Verify position
@@ -329,7 +434,7 @@ fn test_borrow_insufficient_collateral_ratio() {
         Contract::deposit_collateral(env.clone(), user.to_string(), 100).unwrap();
 
         // Try to borrow too much (should fail due to insufficient collateral ratio)
-        let result = Contract::borrow(env.clone(), user.to_string(), 1000);
+        let result = Contract::borrow(env.clone(), user.to_string(), 1000, 0);
         assert!(result.is_err());
         assert_eq!(
             result.unwrap_err(),
@@ -351,13 +456,13 @@ fn test_emergency_pause_blocks_deposit() {
         TestUtils::verify_user(&env, &admin, &user);
 
         let reason = Some(String::from_str(&env, "halt"));
-        Contract::trigger_emergency_pause(env.clone(), admin.to_string(), reason).unwrap();
+        Contract::trigger_emergency_pause(env.clone(), admin.to_string(), reason, None).unwrap();
 
         let result = Contract::deposit_collateral(env.clone(), user.to_string(), 1000);
         assert!(result.is_err());
         assert_eq!(result.unwrap_err(), ProtocolError::ProtocolPaused);
 
-        Contract::resume_operations(env.clone(), admin.to_string()).unwrap();
+        Contract::resume_operations(env.clone(), admin.to_string(), None).unwrap();
         let result = Contract::deposit_collateral(env.clone(), user.to_string(), 1000);
         assert!(result.is_ok());
     });
@@ -376,10 +481,10 @@ fn test_recovery_mode_allows_repay_blocks_borrow() {
         TestUtils::verify_user(&env, &admin, &user);
 
         Contract::deposit_collateral(env.clone(), user.to_string(), 2000).unwrap();
-        Contract::borrow(env.clone(), user.to_string(), 500).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 500, 0).unwrap();
 
         let plan = Some(String::from_str(&env, "staged restart"));
-        Contract::enter_recovery_mode(env.clone(), admin.to_string(), plan).unwrap();
+        Contract::enter_recovery_mode(env.clone(), admin.to_string(), plan, None).unwrap();
 
         Contract::record_recovery_step(
             env.clone(),
@@ -393,7 +498,7 @@ fn test_recovery_mode_allows_repay_blocks_borrow() {
         assert!(repay_result.is_ok());
 
         // Borrow should be restricted while in recovery
-        let borrow_result = Contract::borrow(env.clone(), user.to_string(), 100);
+        let borrow_result = Contract::borrow(env.clone(), user.to_string(), 100, 0);
         assert!(borrow_result.is_err());
         assert_eq!(
             borrow_result.unwrap_err(),
@@ -483,7 +588,7 @@ fn test_repay_success() {
 
         // Deposit and borrow
         Contract::deposit_collateral(env.clone(), user.to_string(), 2000).unwrap();
-        Contract::borrow(env.clone(), user.to_string(), 1000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 1000, 0).unwrap();
 
         // Test successful repayment
         let result = Contract::repay(env.clone(), user.to_string(), 500);
@@ -510,7 +615,7 @@ fn test_repay_full_amount() {
 
         // Deposit and borrow
         Contract::deposit_collateral(env.clone(), user.to_string(), 2000).unwrap();
-        Contract::borrow(env.clone(), user.to_string(), 1000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 1000, 0).unwrap();
 
         // Test full repayment
         let result = Contract::repay(env.clone(), user.to_string(), 1000);
@@ -539,7 +644,7 @@ fn test_withdraw_success() {
         Contract::deposit_collateral(env.clone(), user.to_string(), 2000).unwrap();
 
         // Test successful withdrawal
-        let result = Contract::withdraw(env.clone(), user.to_string(), 1000);
+        let result = Contract::withdraw(env.clone(), user.to_string(), 1000, 0);
         assert!(result.is_ok());
 
         // Verify position
@@ -562,7 +667,7 @@ fn test_event_summary_updates() {
         TestUtils::verify_user(&env, &admin, &user);
 
         Contract::deposit_collateral(env.clone(), user.to_string(), 1200).unwrap();
-        Contract::withdraw(env.clone(), user.to_string(), 200).unwrap();
+        Contract::withdraw(env.clone(), user.to_string(), 200, 0).unwrap();
 
         let summary = Contract::get_event_summary(env.clone()).unwrap();
         let totals = summary.totals;
@@ -574,15 +679,65 @@ fn test_event_summary_updates() {
         assert!(!recent_types.is_empty());
 
         let events =
-            Contract::get_events_for_type(env.clone(), Symbol::new(&env, "position_updated"), 5)
+            Contract::get_events_for_type(env.clone(), Symbol::new(&env, "position_updated"), 0, 5)
                 .unwrap();
-        assert!(!events.is_empty());
+        assert!(!events.items.is_empty());
+        assert!(events.total >= events.items.len() as u64);
 
         let aggregates = Contract::get_event_aggregates(env.clone()).unwrap();
         assert!(aggregates.len() >= totals.len());
     });
 }
 
+#[test]
+fn test_events_since_resumes_across_ledgers() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, _token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        let start = EventStorage::last_event_id(&env) + 1;
+
+        for (i, seq) in [100u32, 100, 101, 105, 106].iter().enumerate() {
+            env.ledger().with_mut(|l| l.sequence_number = *seq);
+            if i % 2 == 0 {
+                Contract::deposit_collateral(env.clone(), user.to_string(), 500).unwrap();
+            } else {
+                Contract::withdraw(env.clone(), user.to_string(), 100, 0).unwrap();
+            }
+        }
+        let last = EventStorage::last_event_id(&env);
+        assert!(last > start);
+
+        // Page through in small chunks, resuming from the last id seen
+        let mut next = start;
+        let mut seen = 0u64;
+        let mut prev_seq = 0u32;
+        loop {
+            let page = Contract::get_events_since(env.clone(), next, 3).unwrap();
+            if page.is_empty() {
+                break;
+            }
+            for record in page.iter() {
+                assert_eq!(record.event_id, next);
+                assert!(record.ledger_seq >= prev_seq);
+                assert!(record.ledger_seq >= 100);
+                prev_seq = record.ledger_seq;
+                next += 1;
+                seen += 1;
+            }
+        }
+        assert_eq!(seen, last - start + 1);
+        assert_eq!(prev_seq, 106);
+        assert!(Contract::get_events_since(env.clone(), last + 1, 10)
+            .unwrap()
+            .is_empty());
+    });
+}
+
 #[test]
 fn test_deposit_reentrancy_blocked() {
     let env = Env::default();
@@ -616,7 +771,7 @@ fn test_withdraw_insufficient_collateral() {
         Contract::deposit_collateral(env.clone(), user.to_string(), 100).unwrap();
 
         // Try to withdraw more than deposited
-        let result = Contract::withdraw(env.clone(), user.to_string(), 200);
+        let result = Contract::withdraw(env.clone(), user.to_string(), 200, 0);
         assert!(result.is_err());
         assert_eq!(result.unwrap_err(), ProtocolError::InsufficientCollateral);
     });
@@ -636,10 +791,10 @@ fn test_withdraw_insufficient_collateral_ratio() {
 
         // Deposit and borrow
         Contract::deposit_collateral(env.clone(), user.to_string(), 2000).unwrap();
-        Contract::borrow(env.clone(), user.to_string(), 1000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 1000, 0).unwrap();
 
         // Try to withdraw too much (would make collateral ratio too low)
-        let result = Contract::withdraw(env.clone(), user.to_string(), 1500);
+        let result = Contract::withdraw(env.clone(), user.to_string(), 1500, 0);
         assert!(result.is_err());
         assert_eq!(
             result.unwrap_err(),
@@ -667,7 +822,7 @@ fn test_liquidate_success() {
 
         // Deposit collateral and borrow to create undercollateralized position
         Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
-        Contract::borrow(env.clone(), user.to_string(), 1000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 1000, 0).unwrap();
 
         // Now set the minimum ratio back to a higher value to make the position undercollateralized
         Contract::set_min_collateral_ratio(env.clone(), admin.to_string(), 150).unwrap();
@@ -679,11 +834,91 @@ fn test_liquidate_success() {
             user.to_string(),
             500,
             0,
+            false,
+            0,
+            false,
         );
         assert!(result.is_ok());
     });
 }
 
+#[test]
+fn test_liquidate_exact_collateral_matches_liquidate() {
+    use crate::liquidate::LiquidationResult;
+
+    // Run one liquidation against a fresh undercollateralized position and
+    // return its result with the borrower's remaining position
+    fn run(
+        exact: bool,
+        amount: i128,
+        max_repay: i128,
+    ) -> Result<(LiquidationResult, Position), ProtocolError> {
+        let env = Env::default();
+        env.mock_all_auths();
+        let user = TestUtils::create_user_address(&env, 0);
+        let liquidator = TestUtils::create_user_address(&env, 1);
+        let (admin, contract_id, token) =
+            TestUtils::setup_contract_with_token(&env, &[user.clone(), liquidator.clone()]);
+        env.as_contract(&contract_id, || {
+            TestUtils::verify_user(&env, &admin, &user);
+            TestUtils::verify_user(&env, &admin, &liquidator);
+            Contract::set_min_collateral_ratio(env.clone(), admin.to_string(), 50).unwrap();
+            Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+            Contract::borrow(env.clone(), user.to_string(), 1000, 0).unwrap();
+            Contract::set_min_collateral_ratio(env.clone(), admin.to_string(), 150).unwrap();
+
+            let result = if exact {
+                Contract::liquidate_exact_collateral(
+                    env.clone(),
+                    liquidator.to_string(),
+                    user.to_string(),
+                    token.clone(),
+                    amount,
+                    token.clone(),
+                    max_repay,
+                    false,
+                )
+            } else {
+                Contract::liquidate(
+                    env.clone(),
+                    liquidator.to_string(),
+                    user.to_string(),
+                    amount,
+                    0,
+                    false,
+                    0,
+                    false,
+                )
+            }?;
+            Ok((result, StateHelper::get_position(&env, &user).unwrap()))
+        })
+    }
+
+    let (by_debt, position) = run(false, 400, 0).unwrap();
+    let (by_collateral, same_position) =
+        run(true, by_debt.collateral_seized, by_debt.debt_repaid).unwrap();
+    assert_eq!(by_collateral, by_debt);
+    assert_eq!(same_position, position);
+
+    // Never more than the requested collateral, even where the bonus rounds
+    for amount in [7, 99, 250, 333] {
+        let (result, _) = run(true, amount, i128::MAX).unwrap();
+        assert!(result.collateral_seized <= amount);
+    }
+
+    // Oversized requests clamp to the close factor on both paths
+    let (by_debt, _) = run(false, 10_000, 0).unwrap();
+    let (by_collateral, _) = run(true, 10_000, i128::MAX).unwrap();
+    assert_eq!(by_collateral, by_debt);
+    assert!(by_collateral.collateral_seized <= 10_000);
+
+    // The implied repayment is bounded by the caller
+    assert_eq!(
+        run(true, by_debt.collateral_seized, by_debt.debt_repaid - 1).unwrap_err(),
+        ProtocolError::SlippageProtectionTriggered
+    );
+}
+
 #[test]
 fn test_liquidate_not_eligible() {
     let env = Env::default();
@@ -700,7 +935,7 @@ fn test_liquidate_not_eligible() {
 
         // Deposit large amount and borrow small amount (healthy position)
         Contract::deposit_collateral(env.clone(), user.to_string(), 2000).unwrap();
-        Contract::borrow(env.clone(), user.to_string(), 1000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 1000, 0).unwrap();
 
         // Try to liquidate (should fail)
         let result = Contract::liquidate(
@@ -709,6 +944,9 @@ fn test_liquidate_not_eligible() {
             user.to_string(),
             500,
             0,
+            false,
+            0,
+            false,
         );
         assert!(result.is_err());
         assert_eq!(
@@ -737,7 +975,7 @@ fn test_liquidate_slippage_protection_triggers() {
 
         // Deposit collateral and borrow to create undercollateralized position
         Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
-        Contract::borrow(env.clone(), user.to_string(), 1000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 1000, 0).unwrap();
 
         // Now set the minimum ratio back to a higher value to make the position undercollateralized
         Contract::set_min_collateral_ratio(env.clone(), admin.to_string(), 150).unwrap();
@@ -750,6 +988,9 @@ fn test_liquidate_slippage_protection_triggers() {
             user.to_string(),
             500,
             1_000_000, // very high min_out
+            false,
+            0,
+            false,
         );
         assert!(result.is_err());
         assert_eq!(
@@ -841,6 +1082,7 @@ fn test_set_pause_switches() {
             false, // pause_deposit
             true,  // pause_withdraw
             false, // pause_liquidate
+            None,
         );
         assert!(result.is_ok());
 
@@ -892,7 +1134,7 @@ fn test_recent_activity_feed_ordering_and_limit() {
         Contract::deposit_collateral(env.clone(), user.to_string(), 500).unwrap();
 
         env.ledger().with_mut(|l| l.timestamp = 200);
-        Contract::borrow(env.clone(), user.to_string(), 200).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 200, 0).unwrap();
 
         env.ledger().with_mut(|l| l.timestamp = 300);
         Contract::repay(env.clone(), user.to_string(), 50).unwrap();
@@ -982,7 +1224,7 @@ fn test_protocol_and_user_reports_reflect_activity() {
         Contract::deposit_collateral(env.clone(), secondary_user.to_string(), 200).unwrap();
 
         env.ledger().with_mut(|l| l.timestamp = 1_100);
-        Contract::borrow(env.clone(), primary_user.to_string(), 400).unwrap();
+        Contract::borrow(env.clone(), primary_user.to_string(), 400, 0).unwrap();
 
         env.ledger().with_mut(|l| l.timestamp = 1_200);
         let protocol_report = Contract::get_protocol_report(env.clone()).unwrap();
@@ -1069,8 +1311,45 @@ fn test_oracle_set_heartbeat_ttl_admin_only() {
         // Initialize contract
         Contract::initialize(env.clone(), admin.to_string()).unwrap();
 
-        // Test admin can set heartbeat_ttl (this would need oracle functions in main contract)
-        // This test would require Oracle functions to be exposed through Contract interface
+        let oracle_admin = TestUtils::create_user_address(&env, 0);
+        let outsider = TestUtils::create_user_address(&env, 1);
+
+        assert_eq!(
+            Contract::set_oracle_heartbeat_ttl(env.clone(), outsider.to_string(), 60),
+            Err(ProtocolError::Unauthorized)
+        );
+        assert_eq!(
+            Contract::set_oracle_admin(
+                env.clone(),
+                oracle_admin.to_string(),
+                Some(oracle_admin.clone())
+            ),
+            Err(ProtocolError::Unauthorized)
+        );
+
+        Contract::set_oracle_admin(env.clone(), admin.to_string(), Some(oracle_admin.clone()))
+            .unwrap();
+        assert_eq!(
+            Contract::get_oracle_admin(env.clone()),
+            Some(oracle_admin.clone())
+        );
+
+        Contract::set_oracle_heartbeat_ttl(env.clone(), oracle_admin.to_string(), 60).unwrap();
+        assert_eq!(crate::oracle::OracleStorage::get_heartbeat_ttl(&env), 60);
+        Contract::set_oracle_heartbeat_ttl(env.clone(), admin.to_string(), 120).unwrap();
+        assert_eq!(crate::oracle::OracleStorage::get_heartbeat_ttl(&env), 120);
+
+        // Revoking the role leaves only the protocol admin
+        Contract::set_oracle_admin(env.clone(), admin.to_string(), None).unwrap();
+        assert_eq!(Contract::get_oracle_admin(env.clone()), None);
+        assert_eq!(
+            Contract::set_oracle_heartbeat_ttl(env.clone(), oracle_admin.to_string(), 60),
+            Err(ProtocolError::Unauthorized)
+        );
+        assert_eq!(
+            Contract::set_oracle_admin(env.clone(), admin.to_string(), None),
+            Err(ProtocolError::NotFound)
+        );
     });
 }
 
@@ -1086,366 +1365,1183 @@ fn test_oracle_set_mode_admin_only() {
         // Initialize contract
         Contract::initialize(env.clone(), admin.to_string()).unwrap();
 
-        // Test admin can set mode (this would need oracle functions in main contract)
-        // This test would require Oracle functions to be exposed through Contract interface
+        let oracle_admin = TestUtils::create_user_address(&env, 0);
+        let outsider = TestUtils::create_user_address(&env, 1);
+        let asset = Address::generate(&env);
+        let feed = Address::generate(&env);
+        Contract::set_oracle_admin(env.clone(), admin.to_string(), Some(oracle_admin.clone()))
+            .unwrap();
+
+        assert_eq!(
+            Contract::set_oracle_mode(env.clone(), outsider.to_string(), 1),
+            Err(ProtocolError::Unauthorized)
+        );
+        assert_eq!(
+            Contract::set_oracle_source(
+                env.clone(),
+                outsider.to_string(),
+                asset.clone(),
+                feed.clone(),
+                1,
+                None
+            ),
+            Err(ProtocolError::Unauthorized)
+        );
+
+        Contract::set_oracle_mode(env.clone(), oracle_admin.to_string(), 1).unwrap();
+        assert_eq!(crate::oracle::OracleStorage::get_mode(&env), 1);
+        Contract::set_oracle_mode(env.clone(), admin.to_string(), 0).unwrap();
+        assert_eq!(crate::oracle::OracleStorage::get_mode(&env), 0);
+
+        Contract::set_oracle_source(
+            env.clone(),
+            oracle_admin.to_string(),
+            asset.clone(),
+            feed.clone(),
+            1,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            Contract::get_oracle_source_count(env.clone(), asset.clone()),
+            1
+        );
+        assert_eq!(
+            Contract::remove_oracle_source(
+                env.clone(),
+                outsider.to_string(),
+                asset.clone(),
+                feed.clone()
+            ),
+            Err(ProtocolError::Unauthorized)
+        );
+        Contract::remove_oracle_source(env.clone(), oracle_admin.to_string(), asset.clone(), feed)
+            .unwrap();
+        assert_eq!(Contract::get_oracle_source_count(env.clone(), asset), 0);
     });
 }
 
 #[test]
-fn test_admin_role_validation() {
+fn test_weighted_median_favours_heavy_source() {
+    use crate::oracle::{Oracle, OracleSource, OracleStorage};
+
     let env = Env::default();
     env.mock_all_auths();
 
-    let admin = TestUtils::create_admin_address(&env);
-    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let asset = Address::generate(&env);
+    let oracle_at = |price: i128| {
+        let oracle = env.register(MockPriceOracle, ());
+        env.as_contract(&oracle, || {
+            MockPriceOracle::set_price(env.clone(), asset.clone(), price);
+        });
+        oracle
+    };
+    let heavy = oracle_at(100);
+    let light_a = oracle_at(150);
+    let light_b = oracle_at(160);
+    let low = oracle_at(60);
+    let muted = oracle_at(1);
 
-    let contract_id = env.register(Contract, ());
     env.as_contract(&contract_id, || {
-        // Initialize contract
-        Contract::initialize(env.clone(), admin.to_string()).unwrap();
+        let add = |oracle: &Address, weight: i128| {
+            Oracle::set_source(
+                &env,
+                &admin,
+                &asset,
+                OracleSource::new(oracle.clone(), weight, 0),
+            )
+            .unwrap();
+        };
+        let price = || {
+            Oracle::aggregate_price_detailed(&env, &asset)
+                .unwrap()
+                .price
+        };
+
+        // Without trim or deviation filter the heavy source outvotes both
+        // divergent light ones; a plain median would give 150
+        // Deliberate price jumps: keep the deviation breaker out of the way
+        OracleStorage::set_max_deviation_bps(&env, 1_000_000);
+        OracleStorage::set_trim_count(&env, 0);
+        OracleStorage::set_deviation_bps(&env, 10_000);
+        add(&heavy, 10);
+        add(&light_a, 1);
+        add(&light_b, 1);
+        assert_eq!(price(), 100);
+
+        // Zero-weight sources are skipped entirely
+        add(&muted, 0);
+        let details = Oracle::aggregate_price_detailed(&env, &asset).unwrap();
+        assert_eq!(details.price, 100);
+        assert_eq!(details.samples.len(), 3);
+
+        // Trim drops the extremes (60 and 160), the heavy source still
+        // wins and the deviation filter drops 150
+        OracleStorage::set_trim_count(&env, 1);
+        OracleStorage::set_deviation_bps(&env, 500);
+        add(&low, 1);
+        assert_eq!(price(), 100);
+
+        // Equal weights reduce to the plain median, averaging the middle pair
+        for oracle in [&heavy, &light_a, &light_b, &low] {
+            add(oracle, 1);
+        }
+        OracleStorage::set_deviation_bps(&env, 10_000);
+        assert_eq!(price(), 125);
+    });
+}
 
-        // Bootstrap users with different roles
-        UserManager::bootstrap_admin(&env, &admin);
+#[test]
+fn test_twap_time_weights_recorded_prices() {
+    use crate::oracle::{Oracle, OracleSource, OracleStorage};
 
-        // Test admin can perform admin-only operations
-        let result = Contract::set_min_collateral_ratio(env.clone(), admin.to_string(), 150);
-        assert!(result.is_ok());
+    let env = Env::default();
+    env.mock_all_auths();
 
-        // Test non-admin cannot perform admin-only operations
-        let result = Contract::set_min_collateral_ratio(env.clone(), user.to_string(), 200);
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), ProtocolError::Unauthorized);
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let asset = Address::generate(&env);
+    let oracle = env.register(MockPriceOracle, ());
+    let set_price = |price: i128| {
+        env.as_contract(&oracle, || {
+            MockPriceOracle::set_price(env.clone(), asset.clone(), price);
+        });
+    };
+    let at = |timestamp: u64| env.ledger().with_mut(|l| l.timestamp = timestamp);
+
+    env.as_contract(&contract_id, || {
+        OracleStorage::set_heartbeat_ttl(&env, &admin, 1_000_000).unwrap();
+        // Deliberate price jumps: keep the deviation breaker out of the way
+        OracleStorage::set_max_deviation_bps(&env, 1_000_000);
+        OracleStorage::set_mode(&env, &admin, 1).unwrap();
+        Oracle::set_source(
+            &env,
+            &admin,
+            &asset,
+            OracleSource::new(oracle.clone(), 1, 0),
+        )
+        .unwrap();
+    });
+    let aggregate = || {
+        env.as_contract(&contract_id, || {
+            Oracle::aggregate_price_detailed(&env, &asset)
+                .unwrap()
+                .price
+        })
+    };
+    let twap = |window: u64| env.as_contract(&contract_id, || Oracle::twap(&env, &asset, window));
+
+    assert_eq!(twap(600), None);
+
+    // A single observation is its own average
+    at(1000);
+    set_price(100);
+    assert_eq!(aggregate(), 100);
+    assert_eq!(twap(600), Some(100));
+
+    // 100 held for 100s, then 200 for 300s
+    at(1100);
+    set_price(200);
+    assert_eq!(aggregate(), 100);
+    at(1400);
+    assert_eq!(twap(600), Some(175));
+    // Only the 200 observation falls inside a 350s window
+    assert_eq!(twap(350), Some(200));
+    assert_eq!(twap(100), None);
+
+    // The buffer keeps the configured number of observations
+    env.as_contract(&contract_id, || {
+        assert_eq!(
+            Oracle::set_twap_buffer_len(&env, &admin, 0),
+            Err(ProtocolError::InvalidParameters)
+        );
+        Oracle::set_twap_buffer_len(&env, &admin, 2).unwrap();
+    });
+    set_price(400);
+    aggregate();
+    env.as_contract(&contract_id, || {
+        let buffer = OracleStorage::get_twap_buffer(&env, &asset);
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.get(0).unwrap().timestamp, 1100);
     });
 }
-// Address validation tests
+
 #[test]
-fn test_address_helper_valid_address() {
+fn test_asset_mode_overrides_global_mode() {
+    use crate::oracle::{Oracle, OracleSource, OracleStorage, USE_GLOBAL_MODE};
+
     let env = Env::default();
+    env.mock_all_auths();
 
-    // Test with a valid Stellar address
-    let valid_address = String::from_str(
-        &env,
-        "GCAZYE3EB54VKP3UQBX3H73VQO3SIWTZNR7NJQKJFZZ6XLADWA4C3SOC",
-    );
-    let result = AddressHelper::require_valid_address(&env, &valid_address);
-    assert!(result.is_ok());
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let stable = Address::generate(&env);
+    let volatile = Address::generate(&env);
+    let oracle = env.register(MockPriceOracle, ());
+    let set_prices = |price: i128| {
+        env.as_contract(&oracle, || {
+            MockPriceOracle::set_price(env.clone(), stable.clone(), price);
+            MockPriceOracle::set_price(env.clone(), volatile.clone(), price);
+        });
+    };
+
+    env.as_contract(&contract_id, || {
+        OracleStorage::set_heartbeat_ttl(&env, &admin, 1_000_000).unwrap();
+        // Deliberate price jump: keep the deviation breaker out of the way
+        OracleStorage::set_max_deviation_bps(&env, 1_000_000);
+        for asset in [&stable, &volatile] {
+            Oracle::set_source(&env, &admin, asset, OracleSource::new(oracle.clone(), 1, 0))
+                .unwrap();
+        }
+        Contract::set_oracle_asset_mode(env.clone(), admin.to_string(), stable.clone(), 1).unwrap();
+        assert_eq!(
+            Contract::get_oracle_effective_mode(env.clone(), stable.clone()),
+            1
+        );
+        assert_eq!(
+            Contract::get_oracle_effective_mode(env.clone(), volatile.clone()),
+            0
+        );
+        assert_eq!(
+            Contract::set_oracle_asset_mode(env.clone(), admin.to_string(), stable.clone(), 3),
+            Err(ProtocolError::InvalidParameters)
+        );
+    });
+    let aggregate = |asset: &Address| {
+        env.as_contract(&contract_id, || {
+            Oracle::aggregate_price_detailed(&env, asset).unwrap().price
+        })
+    };
+
+    env.ledger().with_mut(|l| l.timestamp = 1000);
+    set_prices(100);
+    aggregate(&stable);
+    aggregate(&volatile);
+    env.ledger().with_mut(|l| l.timestamp = 1100);
+    set_prices(200);
+    // The stable asset averages over time; the volatile one follows the median
+    assert_eq!(aggregate(&stable), 100);
+    assert_eq!(aggregate(&volatile), 200);
+
+    // Clearing the override falls back to the global mode
+    env.as_contract(&contract_id, || {
+        Contract::set_oracle_asset_mode(
+            env.clone(),
+            admin.to_string(),
+            stable.clone(),
+            USE_GLOBAL_MODE,
+        )
+        .unwrap();
+        assert_eq!(
+            Contract::get_oracle_effective_mode(env.clone(), stable.clone()),
+            0
+        );
+    });
+    assert_eq!(aggregate(&stable), 200);
 }
 
 #[test]
-fn test_address_helper_empty_address() {
+fn test_failing_oracle_source_is_deactivated() {
+    use crate::oracle::{Oracle, OracleSource, OracleStorage};
+
     let env = Env::default();
+    env.mock_all_auths();
 
-    // Test with empty string
-    let empty_address = String::from_str(&env, "");
-    let result = AddressHelper::require_valid_address(&env, &empty_address);
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let asset = Address::generate(&env);
+    let steady = env.register(MockPriceOracle, ());
+    let flaky = env.register(MockPriceOracle, ());
+    let set_price = |oracle: &Address, price: i128| {
+        env.as_contract(oracle, || {
+            MockPriceOracle::set_price(env.clone(), asset.clone(), price);
+        });
+    };
+    set_price(&steady, 100);
+    set_price(&flaky, -1);
+
+    env.as_contract(&contract_id, || {
+        OracleStorage::set_trim_count(&env, 0);
+        for oracle in [&steady, &flaky] {
+            Oracle::set_source(
+                &env,
+                &admin,
+                &asset,
+                OracleSource::new(oracle.clone(), 1, 0),
+            )
+            .unwrap();
+        }
+        let price = || {
+            Oracle::aggregate_price_detailed(&env, &asset)
+                .unwrap()
+                .price
+        };
+        let failures = || {
+            OracleStorage::get_source_health(&env, &asset)
+                .get(flaky.clone())
+                .map(|h| (h.consecutive_failures, h.deactivated))
+        };
+
+        // A trapping source no longer takes the aggregation down with it
+        assert_eq!(price(), 100);
+        assert_eq!(failures(), Some((1, false)));
+
+        // A successful fetch resets the count
+        set_price(&flaky, 120);
+        assert_eq!(price(), 110);
+        assert_eq!(failures(), None);
+
+        set_price(&flaky, -1);
+        for _ in 0..3 {
+            assert_eq!(price(), 100);
+        }
+        assert_eq!(failures(), Some((3, true)));
+
+        // Deactivated sources are skipped even once they recover
+        set_price(&flaky, 120);
+        assert_eq!(price(), 100);
+        assert_eq!(failures(), Some((3, true)));
+
+        Oracle::reactivate_source(&env, &admin, &asset, &flaky).unwrap();
+        assert_eq!(failures(), None);
+        assert_eq!(price(), 110);
+        assert_eq!(
+            Oracle::reactivate_source(&env, &admin, &asset, &flaky),
+            Err(ProtocolError::NotFound)
+        );
+    });
 }
 
 #[test]
-#[should_panic(expected = "HostError: Error(Value, InvalidInput)")]
-fn test_address_helper_malformed_address() {
+fn test_price_deviation_breaker_rejects_spikes() {
+    use crate::oracle::{Oracle, OracleSource, OracleStorage};
+
     let env = Env::default();
+    env.mock_all_auths();
 
-    // Test with malformed address (too short)
-    // Note: This test demonstrates the original problem - malformed addresses cause panics
-    // Our validation catches some cases but Address::from_string still panics on others
-    // This test documents that malformed addresses still cause panics, which is the
-    // original issue we're addressing with safe wrappers
-    let malformed_address = String::from_str(&env, "invalid");
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let asset = Address::generate(&env);
+    let oracle = env.register(MockPriceOracle, ());
+    let set_price = |price: i128| {
+        env.as_contract(&oracle, || {
+            MockPriceOracle::set_price(env.clone(), asset.clone(), price);
+        });
+    };
 
-    // This will panic because Address::from_string doesn't handle malformed addresses gracefully
-    // This demonstrates why we need the AddressHelper for safer address handling
-    let _result = AddressHelper::require_valid_address(&env, &malformed_address);
+    env.as_contract(&contract_id, || {
+        Oracle::set_source(
+            &env,
+            &admin,
+            &asset,
+            OracleSource::new(oracle.clone(), 1, 0),
+        )
+        .unwrap();
+    });
+    let aggregate = || {
+        env.as_contract(&contract_id, || {
+            Oracle::try_aggregate_price_detailed(&env, &asset).map(|details| details.price)
+        })
+    };
+
+    // The first price has no baseline
+    set_price(100);
+    assert_eq!(aggregate(), Ok(100));
+
+    // Exactly the default 20% passes
+    set_price(120);
+    assert_eq!(aggregate(), Ok(120));
+
+    // A 50% spike is rejected and leaves the baseline alone
+    set_price(180);
+    assert_eq!(aggregate(), Err(ProtocolError::PriceDeviationTooHigh));
+    env.as_contract(&contract_id, || {
+        assert_eq!(Oracle::aggregate_price_detailed(&env, &asset), None);
+        assert_eq!(OracleStorage::get_last_good_price(&env, &asset), Some(120));
+    });
+
+    env.as_contract(&contract_id, || {
+        assert_eq!(
+            Contract::set_oracle_max_deviation(env.clone(), admin.to_string(), 0),
+            Err(ProtocolError::InvalidParameters)
+        );
+        Contract::set_oracle_max_deviation(env.clone(), admin.to_string(), 5000).unwrap();
+    });
+    assert_eq!(aggregate(), Ok(180));
 }
 
 #[test]
-#[should_panic(expected = "HostError: Error(Value, InvalidInput)")]
-fn test_address_helper_null_bytes() {
+fn test_sep40_feed_matches_oracle_last_price() {
+    use crate::oracle::{Oracle, OracleSource, PRICE_SCALE};
+    use crate::sep40::{Asset, PriceData};
+
     let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1000);
 
-    // Test with address containing null bytes
-    // Note: This test demonstrates the original problem - addresses with null bytes cause panics
-    // Our current validation doesn't catch null bytes in the middle of strings
-    let null_address = String::from_str(
-        &env,
-        "GCAZYE3EB54VKP3UQBX3H73VQO3SIWTZNR7NJQKJFZZ6XLADWA4C3SOC\0",
+    let (admin, contract_id, token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let other = Address::generate(&env);
+    let oracle = env.register(MockPriceOracle, ());
+    env.as_contract(&oracle, || {
+        MockPriceOracle::set_price(env.clone(), token.clone(), 2 * PRICE_SCALE);
+    });
+    env.as_contract(&contract_id, || {
+        for asset in [&token, &other] {
+            Oracle::set_source(
+                &env,
+                &admin,
+                asset,
+                OracleSource::new(oracle.clone(), 1, 1000),
+            )
+            .unwrap();
+        }
+        Oracle::aggregate_price(&env, &token).unwrap();
+    });
+
+    let client = ContractClient::new(&env, &contract_id);
+    let last = env.as_contract(&contract_id, || Oracle::last_price(&env, &token));
+    assert_eq!(last, Some((2 * PRICE_SCALE, 1000)));
+    assert_eq!(
+        client.lastprice(&Asset::Stellar(token.clone())),
+        Some(PriceData {
+            price: 2 * PRICE_SCALE,
+            timestamp: 1000,
+        })
+    );
+    assert_eq!(client.lastprice(&Asset::Stellar(other.clone())), None);
+    assert_eq!(
+        client.lastprice(&Asset::Other(Symbol::new(&env, "USD"))),
+        None
+    );
+    assert_eq!(10i128.pow(client.decimals()), PRICE_SCALE);
+    assert_eq!(
+        client.assets(),
+        Vec::from_array(
+            &env,
+            [Asset::Stellar(token.clone()), Asset::Stellar(other.clone())]
+        )
     );
 
-    // This will panic because Address::from_string doesn't handle null bytes gracefully
-    // This demonstrates the limitation of our current validation and why more sophisticated
-    // validation would be needed for production use
-    let _result = AddressHelper::require_valid_address(&env, &null_address);
+    // An asset leaves the list with its last source
+    env.as_contract(&contract_id, || {
+        Oracle::remove_source(&env, &admin, &other, &oracle).unwrap();
+    });
+    assert_eq!(
+        client.assets(),
+        Vec::from_array(&env, [Asset::Stellar(token)])
+    );
 }
 
 #[test]
-fn test_address_helper_too_long_address() {
+fn test_sep40_price_history_from_observation_buffer() {
+    use crate::oracle::{Oracle, OracleSource, PRICE_SCALE};
+    use crate::sep40::{Asset, PriceData};
+
     let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1000);
 
-    // Test with excessively long string (over 256 characters)
-    let long_string = "A".repeat(300);
-    let long_address = String::from_str(&env, &long_string);
-    let result = AddressHelper::require_valid_address(&env, &long_address);
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
-}
-
-#[test]
-fn test_address_helper_validate_format() {
-    let env = Env::default();
+    let (admin, contract_id, token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let oracle = env.register(MockPriceOracle, ());
+    env.as_contract(&contract_id, || {
+        Oracle::set_source(
+            &env,
+            &admin,
+            &token,
+            OracleSource::new(oracle.clone(), 1, 1000),
+        )
+        .unwrap();
+        crate::oracle::OracleStorage::set_max_deviation_bps(&env, 1_000_000);
+    });
+    // Observations at 1000, 1100 and 1200, each past the cache TTL
+    for (step, price) in [(0, 2), (1, 3), (2, 4)] {
+        env.ledger().with_mut(|l| l.timestamp = 1000 + step * 100);
+        env.as_contract(&oracle, || {
+            MockPriceOracle::set_price(env.clone(), token.clone(), price * PRICE_SCALE);
+        });
+        env.as_contract(&contract_id, || {
+            Oracle::aggregate_price(&env, &token).unwrap();
+        });
+    }
 
-    // Test valid format
-    let valid_address = String::from_str(
-        &env,
-        "GCAZYE3EB54VKP3UQBX3H73VQO3SIWTZNR7NJQKJFZZ6XLADWA4C3SOC",
+    let client = ContractClient::new(&env, &contract_id);
+    let asset = Asset::Stellar(token.clone());
+    let at = |price: i128, timestamp: u64| PriceData {
+        price: price * PRICE_SCALE,
+        timestamp,
+    };
+    assert_eq!(client.price(&asset, &1150), Some(at(3, 1100)));
+    assert_eq!(client.price(&asset, &1200), Some(at(4, 1200)));
+    assert_eq!(client.price(&asset, &999), None);
+    assert_eq!(
+        client.prices(&asset, &2),
+        Some(Vec::from_array(&env, [at(4, 1200), at(3, 1100)]))
     );
-    let result = AddressHelper::validate_address_format(&valid_address);
-    assert!(result.is_ok());
-
-    // Test empty format
-    let empty_address = String::from_str(&env, "");
-    let result = AddressHelper::validate_address_format(&empty_address);
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
-}
-
-#[test]
-fn test_address_helper_is_valid_address_string() {
-    let env = Env::default();
-
-    // Test valid address string
-    let valid_address = String::from_str(
-        &env,
-        "GCAZYE3EB54VKP3UQBX3H73VQO3SIWTZNR7NJQKJFZZ6XLADWA4C3SOC",
+    assert_eq!(client.prices(&asset, &10).unwrap().len(), 3);
+    assert_eq!(
+        client.prices(&Asset::Stellar(Address::generate(&env)), &5),
+        None
     );
-    assert!(AddressHelper::is_valid_address_string(&valid_address));
-
-    // Test invalid address string
-    let invalid_address = String::from_str(&env, "");
-    assert!(!AddressHelper::is_valid_address_string(&invalid_address));
+    assert_eq!(client.base(), Asset::Other(Symbol::new(&env, "USD")));
+    assert_eq!(client.resolution(), 30);
 }
 
 #[test]
-fn test_address_helper_from_strings_safe() {
+fn test_aggregate_requires_min_healthy_sources() {
+    use crate::oracle::{Oracle, OracleSource};
+
     let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1000);
+
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let asset = Address::generate(&env);
+    let oracle_at = |price: i128| {
+        let oracle = env.register(MockPriceOracle, ());
+        env.as_contract(&oracle, || {
+            MockPriceOracle::set_price(env.clone(), asset.clone(), price);
+        });
+        oracle
+    };
+    let (fresh_a, fresh_b, stale) = (oracle_at(100), oracle_at(102), oracle_at(500));
 
-    let addr1 = String::from_str(
-        &env,
-        "GCAZYE3EB54VKP3UQBX3H73VQO3SIWTZNR7NJQKJFZZ6XLADWA4C3SOC",
-    );
-    let addr2 = String::from_str(
-        &env,
-        "GCXOTMMXRS24MYZI5FJPUCOEOFNWSR4XX7UXIK3NDGGE6A5QMJ5FF2FS",
-    );
+    env.as_contract(&contract_id, || {
+        assert_eq!(Oracle::fetch_prices(&env, &asset).1, 0);
+        for (oracle, heartbeat) in [(&fresh_a, 1000), (&fresh_b, 1000), (&stale, 0)] {
+            Oracle::set_source(
+                &env,
+                &admin,
+                &asset,
+                OracleSource::new(oracle.clone(), 1, heartbeat),
+            )
+            .unwrap();
+        }
+        let (prices, skipped) = Oracle::fetch_prices(&env, &asset);
+        assert_eq!((prices.len(), skipped), (2, 1));
 
-    // Test with valid addresses
-    let mut addresses = Vec::new(&env);
-    addresses.push_back(addr1.clone());
-    addresses.push_back(addr2.clone());
-    let result = AddressHelper::from_strings_safe(&env, addresses);
-    assert!(result.is_ok());
-    let parsed_addresses = result.unwrap();
-    assert_eq!(parsed_addresses.len(), 2);
+        // Exactly the minimum responding is enough
+        Contract::set_oracle_min_sources(env.clone(), admin.to_string(), 2).unwrap();
+        assert_eq!(
+            Oracle::aggregate_price_detailed(&env, &asset)
+                .unwrap()
+                .price,
+            101
+        );
 
-    // Test with one invalid address
-    let invalid_addr = String::from_str(&env, "");
-    let mut addresses_with_invalid = Vec::new(&env);
-    addresses_with_invalid.push_back(addr1);
-    addresses_with_invalid.push_back(invalid_addr);
-    let result = AddressHelper::from_strings_safe(&env, addresses_with_invalid);
-    assert!(result.is_err());
-    assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
+        // The stale source does not count towards the minimum
+        Contract::set_oracle_min_sources(env.clone(), admin.to_string(), 3).unwrap();
+        assert_eq!(Oracle::aggregate_price_detailed(&env, &asset), None);
+        assert_eq!(
+            Contract::set_oracle_min_sources(env.clone(), admin.to_string(), 0),
+            Err(ProtocolError::InvalidParameters)
+        );
+    });
+
+    // Neither does a source reporting a non-positive price
+    env.as_contract(&fresh_b, || {
+        MockPriceOracle::set_price(env.clone(), asset.clone(), 0);
+    });
+    env.as_contract(&contract_id, || {
+        Contract::set_oracle_min_sources(env.clone(), admin.to_string(), 2).unwrap();
+        assert_eq!(Oracle::aggregate_price_detailed(&env, &asset), None);
+    });
 }
 
-// Integration tests for public API functions with invalid addresses
 #[test]
-fn test_initialize_invalid_admin_address() {
+fn test_source_prices_are_normalized_before_aggregation() {
+    use crate::oracle::{Oracle, OracleSource, PRICE_SCALE};
+
     let env = Env::default();
     env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1000);
+
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let asset = Address::generate(&env);
+    let oracle_at = |price: i128| {
+        let oracle = env.register(MockPriceOracle, ());
+        env.as_contract(&oracle, || {
+            MockPriceOracle::set_price(env.clone(), asset.clone(), price);
+        });
+        oracle
+    };
+    // $1.00 with 7 decimals and $1.02 with 18 decimals
+    let seven = oracle_at(10_000_000);
+    let eighteen = oracle_at(1_020_000_000_000_000_000);
 
-    let contract_id = env.register(Contract, ());
     env.as_contract(&contract_id, || {
-        // Test initialization with empty admin address
-        let result = Contract::initialize(env.clone(), String::from_str(&env, ""));
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
+        for oracle in [&seven, &eighteen] {
+            Oracle::set_source(
+                &env,
+                &admin,
+                &asset,
+                OracleSource::new(oracle.clone(), 1, 1000),
+            )
+            .unwrap();
+        }
+        for (oracle, decimals) in [(&seven, 7), (&eighteen, 18)] {
+            Contract::set_oracle_source_decimals(
+                env.clone(),
+                admin.to_string(),
+                asset.clone(),
+                oracle.clone(),
+                decimals,
+            )
+            .unwrap();
+        }
+        assert_eq!(
+            Contract::set_oracle_source_decimals(
+                env.clone(),
+                admin.to_string(),
+                asset.clone(),
+                seven.clone(),
+                19,
+            ),
+            Err(ProtocolError::InvalidInput)
+        );
+        assert_eq!(
+            Contract::set_oracle_source_decimals(
+                env.clone(),
+                admin.to_string(),
+                asset.clone(),
+                Address::generate(&env),
+                7,
+            ),
+            Err(ProtocolError::NotFound)
+        );
 
-        // Note: Testing malformed addresses that cause panics is commented out
-        // as they demonstrate the original problem we're solving
-        // let result = Contract::initialize(env.clone(), String::from_str(&env, "invalid"));
-        // assert!(result.is_err());
-        // assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
+        let (prices, _) = Oracle::fetch_prices(&env, &asset);
+        assert_eq!(
+            prices,
+            soroban_sdk::vec![&env, PRICE_SCALE, PRICE_SCALE * 102 / 100]
+        );
+        assert_eq!(
+            Oracle::aggregate_price_detailed(&env, &asset)
+                .unwrap()
+                .price,
+            PRICE_SCALE * 101 / 100
+        );
     });
 }
 
 #[test]
-fn test_manager_role_validation() {
+fn test_oracle_heartbeat_keeps_source_fresh() {
+    use crate::oracle::{Oracle, OracleSource};
+    use soroban_sdk::testutils::Events;
+    use soroban_sdk::IntoVal;
+
     let env = Env::default();
     env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1000);
 
-    let admin = TestUtils::create_admin_address(&env);
-    let manager = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let asset = Address::generate(&env);
+    let oracle = env.register(MockPriceOracle, ());
+    env.as_contract(&oracle, || {
+        MockPriceOracle::set_price(env.clone(), asset.clone(), 100);
+    });
 
-    let contract_id = env.register(Contract, ());
     env.as_contract(&contract_id, || {
-        // Initialize contract
-        Contract::initialize(env.clone(), admin.to_string()).unwrap();
+        Oracle::set_source(
+            &env,
+            &admin,
+            &asset,
+            OracleSource::new(oracle.clone(), 1, 1000),
+        )
+        .unwrap();
+    });
 
-        // Bootstrap users with different roles
-        UserManager::bootstrap_admin(&env, &admin);
+    // Past the TTL the source is skipped as stale
+    env.ledger().with_mut(|l| l.timestamp = 1400);
+    let fetch = || env.as_contract(&contract_id, || Oracle::fetch_prices(&env, &asset));
+    assert_eq!(fetch().1, 1);
 
-        // Set manager role for manager user
-        UserManager::set_role(&env, &admin, &manager, UserRole::Manager).unwrap();
+    let client = ContractClient::new(&env, &contract_id);
+    assert_eq!(client.oracle_heartbeat(&oracle, &asset), 1400);
+    let (_, topics, _) = env.events().all().last().unwrap();
+    let name: Symbol = topics.get(0).unwrap().into_val(&env);
+    assert_eq!(name, Symbol::new(&env, "oracle_heartbeat"));
+    let (prices, stale) = fetch();
+    assert_eq!((prices.len(), stale), (1, 0));
+
+    assert_eq!(
+        client.try_oracle_heartbeat(&oracle, &Address::generate(&env)),
+        Err(Ok(ProtocolError::NotFound))
+    );
+}
 
-        // Test manager can perform manager-level operations (user management)
-        let result = Contract::set_user_role(
-            env.clone(),
-            manager.to_string(),
-            manager.clone(),
-            UserRole::Standard,
-        );
-        assert!(result.is_ok());
+#[test]
+fn test_oracle_source_status_and_config_views() {
+    use crate::oracle::{Oracle, OracleSource, OracleStorage};
 
-        // Test manager cannot escalate to admin role
-        let result = Contract::set_user_role(
-            env.clone(),
-            manager.to_string(),
-            manager.clone(),
-            UserRole::Admin,
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1000);
+
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let asset = Address::generate(&env);
+    let fresh = env.register(MockPriceOracle, ());
+    let stale = env.register(MockPriceOracle, ());
+    for oracle in [&fresh, &stale] {
+        env.as_contract(oracle, || {
+            MockPriceOracle::set_price(env.clone(), asset.clone(), 100);
+        });
+    }
+    env.as_contract(&contract_id, || {
+        let ttl = OracleStorage::get_heartbeat_ttl(&env);
+        Oracle::set_source(
+            &env,
+            &admin,
+            &asset,
+            OracleSource::new(fresh.clone(), 3, 1000),
+        )
+        .unwrap();
+        Oracle::set_source(
+            &env,
+            &admin,
+            &asset,
+            OracleSource::new(stale.clone(), 1, 1000 - ttl - 1),
+        )
+        .unwrap();
+    });
+
+    let client = ContractClient::new(&env, &contract_id);
+    let statuses = client.get_oracle_source_status(&asset, &false);
+    assert_eq!(statuses.len(), 2);
+    let first = statuses.get(0).unwrap();
+    assert_eq!((first.addr, first.weight), (fresh.clone(), 3));
+    assert!(!first.is_stale && first.last_price.is_none());
+    assert!(statuses.get(1).unwrap().is_stale);
+
+    // A dry run only asks live sources
+    let statuses = client.get_oracle_source_status(&asset, &true);
+    assert_eq!(statuses.get(0).unwrap().last_price, Some(100));
+    assert_eq!(statuses.get(1).unwrap().last_price, None);
+
+    client.set_oracle_min_sources(&admin.to_string(), &2);
+    let config = client.get_oracle_config();
+    assert_eq!(config.min_sources, 2);
+    env.as_contract(&contract_id, || {
+        assert_eq!(config.heartbeat_ttl, OracleStorage::get_heartbeat_ttl(&env));
+        assert_eq!(config.mode, OracleStorage::get_mode(&env));
+        assert_eq!(config.deviation_bps, OracleStorage::get_deviation_bps(&env));
+        assert_eq!(
+            config.max_deviation_bps,
+            OracleStorage::get_max_deviation_bps(&env)
         );
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), ProtocolError::Unauthorized);
     });
 }
 
 #[test]
-fn test_deposit_collateral_invalid_depositor() {
+fn test_oracle_sources_per_asset_are_bounded() {
+    use crate::defaults::ORACLE_MAX_SOURCES_PER_ASSET;
+    use crate::oracle::{Oracle, OracleSource};
+
     let env = Env::default();
     env.mock_all_auths();
 
-    let admin = TestUtils::create_admin_address(&env);
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let client = ContractClient::new(&env, &contract_id);
+    let asset = Address::generate(&env);
+    let set_source = |addr: &Address, weight: i128| {
+        env.as_contract(&contract_id, || {
+            Oracle::set_source(
+                &env,
+                &admin,
+                &asset,
+                OracleSource::new(addr.clone(), weight, 0),
+            )
+        })
+    };
+
+    let mut sources = alloc::vec::Vec::new();
+    for _ in 0..ORACLE_MAX_SOURCES_PER_ASSET {
+        let addr = Address::generate(&env);
+        set_source(&addr, 1).unwrap();
+        sources.push(addr);
+    }
+    assert_eq!(
+        client.get_oracle_source_count(&asset),
+        ORACLE_MAX_SOURCES_PER_ASSET
+    );
+    let extra = Address::generate(&env);
+    assert_eq!(set_source(&extra, 1), Err(ProtocolError::TooManySources));
+
+    // Replacing a registered source at the limit is not an addition
+    set_source(&sources[0], 5).unwrap();
+    assert_eq!(
+        client.get_oracle_source_count(&asset),
+        ORACLE_MAX_SOURCES_PER_ASSET
+    );
 
-    let contract_id = env.register(Contract, ());
+    // Removing an unknown source fails loudly; removing a known one frees a slot
     env.as_contract(&contract_id, || {
-        Contract::initialize(env.clone(), admin.to_string()).unwrap();
-
-        // Test deposit with empty depositor address
-        let result = Contract::deposit_collateral(env.clone(), String::from_str(&env, ""), 1000);
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
-
-        // Note: Testing malformed addresses that cause panics is commented out
-        // as they demonstrate the original problem we're solving
-        // let result = Contract::deposit_collateral(env.clone(), String::from_str(&env, "bad_addr"), 1000);
-        // assert!(result.is_err());
-        // assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
+        assert_eq!(
+            Oracle::remove_source(&env, &admin, &asset, &extra),
+            Err(ProtocolError::NotFound)
+        );
+        Oracle::remove_source(&env, &admin, &asset, &sources[1]).unwrap();
     });
+    set_source(&extra, 1).unwrap();
+
+    client.set_oracle_max_sources(&admin.to_string(), &2);
+    assert_eq!(
+        set_source(&Address::generate(&env), 1),
+        Err(ProtocolError::TooManySources)
+    );
+    assert_eq!(
+        client.try_set_oracle_max_sources(&admin.to_string(), &0),
+        Err(Ok(ProtocolError::InvalidParameters))
+    );
 }
 
 #[test]
-fn test_borrow_invalid_borrower() {
+fn test_fallback_oracle_used_when_aggregation_fails() {
+    use crate::oracle::{Oracle, OracleSource, OracleStorage, PriceSource};
+    use soroban_sdk::testutils::Events;
+    use soroban_sdk::IntoVal;
+
     let env = Env::default();
     env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1000);
+
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let asset = Address::generate(&env);
+    let primary = env.register(MockPriceOracle, ());
+    let fallback = env.register(MockPriceOracle, ());
+    let set_price = |oracle: &Address, price: i128| {
+        env.as_contract(oracle, || {
+            MockPriceOracle::set_price(env.clone(), asset.clone(), price);
+        });
+    };
+    // The primary source is down
+    set_price(&primary, -1);
+    set_price(&fallback, 150);
 
-    let admin = TestUtils::create_admin_address(&env);
-    let contract_id = env.register(Contract, ());
     env.as_contract(&contract_id, || {
-        Contract::initialize(env.clone(), admin.to_string()).unwrap();
+        // Keep the failing primary registered through the outage
+        OracleStorage::set_max_source_failures(&env, 10);
+        Oracle::set_source(
+            &env,
+            &admin,
+            &asset,
+            OracleSource::new(primary.clone(), 1, 1000),
+        )
+        .unwrap();
+        assert_eq!(
+            Contract::get_price_detailed(env.clone(), asset.clone()),
+            None
+        );
 
-        // Test borrow with empty borrower address
-        let result = Contract::borrow(env.clone(), String::from_str(&env, ""), 1000);
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
+        Contract::set_oracle_fallback(
+            env.clone(),
+            admin.to_string(),
+            asset.clone(),
+            Some(fallback.clone()),
+        )
+        .unwrap();
+        let point = Contract::get_price_detailed(env.clone(), asset.clone()).unwrap();
+        assert_eq!((point.price, point.source), (150, PriceSource::Fallback));
+        let (_, topics, _) = env.events().all().last().unwrap();
+        let name: Symbol = topics.get(0).unwrap().into_val(&env);
+        assert_eq!(name, Symbol::new(&env, "oracle_fallback_used"));
+    });
+
+    // Fallback prices must be positive too
+    set_price(&fallback, 0);
+    env.as_contract(&contract_id, || {
+        assert_eq!(
+            Contract::get_price_detailed(env.clone(), asset.clone()),
+            None
+        );
     });
-}
 
+    // Once the primary answers again it takes precedence
+    set_price(&primary, 100);
+    env.as_contract(&contract_id, || {
+        let point = Contract::get_price_detailed(env.clone(), asset.clone()).unwrap();
+        assert_eq!((point.price, point.source), (100, PriceSource::Primary));
+        assert_eq!(point.timestamp, 1000);
+    });
+}
 #[test]
-fn test_repay_invalid_repayer() {
+fn test_push_mode_aggregates_submitted_prices() {
+    use crate::oracle::{Oracle, OracleSource, OracleStorage};
+
     let env = Env::default();
     env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1000);
 
-    let admin = TestUtils::create_admin_address(&env);
-    let contract_id = env.register(Contract, ());
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let client = ContractClient::new(&env, &contract_id);
+    let asset = Address::generate(&env);
+    // Calling the source would trap; push mode must not call it
+    let source = env.register(MockPriceOracle, ());
+    env.as_contract(&source, || {
+        MockPriceOracle::set_price(env.clone(), asset.clone(), -1);
+    });
     env.as_contract(&contract_id, || {
-        Contract::initialize(env.clone(), admin.to_string()).unwrap();
+        Oracle::set_source(
+            &env,
+            &admin,
+            &asset,
+            OracleSource::new(source.clone(), 1, 1000),
+        )
+        .unwrap();
+        Contract::set_oracle_push_mode(env.clone(), admin.to_string(), asset.clone(), true)
+            .unwrap();
+    });
 
-        // Test repay with empty repayer address
-        let result = Contract::repay(env.clone(), String::from_str(&env, ""), 1000);
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
+    assert_eq!(
+        client.try_submit_oracle_price(&source, &asset, &0, &1000),
+        Err(Ok(ProtocolError::InvalidAmount))
+    );
+    let stranger = Address::generate(&env);
+    assert_eq!(
+        client.try_submit_oracle_price(&stranger, &asset, &150, &1000),
+        Err(Ok(ProtocolError::NotFound))
+    );
+    client.submit_oracle_price(&source, &asset, &150, &900);
+    assert_eq!(
+        client.try_submit_oracle_price(&source, &asset, &160, &800),
+        Err(Ok(ProtocolError::InvalidInput))
+    );
+
+    env.as_contract(&contract_id, || {
+        assert_eq!(
+            Oracle::aggregate_price_detailed(&env, &asset)
+                .unwrap()
+                .price,
+            150
+        );
+        assert!(OracleStorage::get_source_health(&env, &asset).is_empty());
+    });
+
+    // The heartbeat TTL runs from the submitted timestamp
+    env.ledger().with_mut(|l| l.timestamp = 1201);
+    env.as_contract(&contract_id, || {
+        assert_eq!(Oracle::aggregate_price_detailed(&env, &asset), None);
     });
 }
 
 #[test]
-fn test_withdraw_invalid_withdrawer() {
+fn test_pushed_price_crash_sweeps_holders_across_pushes() {
+    use crate::alerts::AlertStorage;
+    use crate::oracle::{Oracle, OracleSource};
+    use crate::sweep::{SweepState, MAX_SWEEP_POSITIONS};
+
     let env = Env::default();
     env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1000);
 
-    let admin = TestUtils::create_admin_address(&env);
-    let contract_id = env.register(Contract, ());
+    let users: alloc::vec::Vec<Address> = (0..7).map(|_| Address::generate(&env)).collect();
+    let (admin, contract_id, token) = TestUtils::setup_contract_with_token(&env, &users);
+    let client = ContractClient::new(&env, &contract_id);
+    let source = env.register(MockPriceOracle, ());
     env.as_contract(&contract_id, || {
-        Contract::initialize(env.clone(), admin.to_string()).unwrap();
-
-        // Test withdraw with empty withdrawer address
-        let result = Contract::withdraw(env.clone(), String::from_str(&env, ""), 1000);
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
+        Oracle::set_source(
+            &env,
+            &admin,
+            &token,
+            OracleSource::new(source.clone(), 1, 1000),
+        )
+        .unwrap();
     });
+    for user in users.iter() {
+        client.set_user_verification(&admin.to_string(), user, &VerificationStatus::Verified);
+        client.deposit_collateral(&user.to_string(), &2000);
+        client.borrow(&user.to_string(), &1000, &0);
+    }
+    client.submit_oracle_price(&source, &token, &100, &1000);
+    assert_eq!(
+        client.get_price_sweep(&token),
+        SweepState {
+            cursor: 0,
+            remaining: 0
+        }
+    );
+
+    // Every position (200%) now sits under the threshold, but none has been
+    // re-saved, so none is flagged yet
+    client.set_min_collateral_ratio(&admin.to_string(), &300);
+    client.set_liquidation_threshold(&admin.to_string(), &25000);
+    let flagged = |env: &Env| {
+        env.as_contract(&contract_id, || {
+            users
+                .iter()
+                .filter(|u| AlertStorage::get_watch(env, u).at_risk)
+                .count() as u32
+        })
+    };
+    assert_eq!(flagged(&env), 0);
+
+    client.submit_oracle_price(&source, &token, &50, &1000);
+    assert_eq!(flagged(&env), MAX_SWEEP_POSITIONS);
+    assert_eq!(
+        client.get_price_sweep(&token),
+        SweepState {
+            cursor: 5,
+            remaining: 2
+        }
+    );
+
+    // A small follow-up move still advances the running sweep
+    client.submit_oracle_price(&source, &token, &49, &1000);
+    assert_eq!(flagged(&env), 7);
+    assert_eq!(
+        client.get_price_sweep(&token),
+        SweepState {
+            cursor: 7,
+            remaining: 0
+        }
+    );
 }
 
 #[test]
-fn test_liquidate_invalid_addresses() {
+fn test_price_sweep_prunes_closed_positions() {
+    use crate::oracle::{Oracle, OracleSource};
+    use crate::sweep::{PriceSweep, SweepStorage};
+
     let env = Env::default();
     env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1000);
 
-    let admin = TestUtils::create_admin_address(&env);
-    let valid_user = TestUtils::create_user_address(&env, 0);
-    let contract_id = env.register(Contract, ());
+    let users: alloc::vec::Vec<Address> = (0..3).map(|_| Address::generate(&env)).collect();
+    let (admin, contract_id, token) = TestUtils::setup_contract_with_token(&env, &users);
+    let client = ContractClient::new(&env, &contract_id);
+    let source = env.register(MockPriceOracle, ());
     env.as_contract(&contract_id, || {
-        Contract::initialize(env.clone(), admin.to_string()).unwrap();
+        Oracle::set_source(
+            &env,
+            &admin,
+            &token,
+            OracleSource::new(source.clone(), 1, 1000),
+        )
+        .unwrap();
+    });
+    for user in users.iter() {
+        client.set_user_verification(&admin.to_string(), user, &VerificationStatus::Verified);
+        client.deposit_collateral(&user.to_string(), &2000);
+    }
+    // The first holder closes out their position
+    client.withdraw(&users[0].to_string(), &2000, &0);
+    let holders = || {
+        env.as_contract(&contract_id, || {
+            let count = SweepStorage::get_holder_count(&env, &token);
+            (0..count)
+                .map(|slot| SweepStorage::get_holder(&env, &token, slot).unwrap())
+                .collect::<alloc::vec::Vec<_>>()
+        })
+    };
+    assert_eq!(holders().len(), 3);
+
+    client.submit_oracle_price(&source, &token, &100, &1000);
+    client.submit_oracle_price(&source, &token, &50, &1000);
+    // The last holder took the closed position's slot
+    assert_eq!(holders(), [users[2].clone(), users[1].clone()]);
+
+    // Coming back re-indexes them
+    client.deposit_collateral(&users[0].to_string(), &500);
+    assert_eq!(holders().len(), 3);
+    env.as_contract(&contract_id, || {
+        PriceSweep::index_holder(&env, &token, &users[0]);
+        assert_eq!(SweepStorage::get_holder_count(&env, &token), 3);
+    });
+}
+#[test]
+fn test_aggregate_with_confidence_reports_source_spread() {
+    use crate::oracle::{Oracle, OracleSource};
 
-        // Test liquidate with empty liquidator address
-        let result = Contract::liquidate(
-            env.clone(),
-            String::from_str(&env, ""),
-            valid_user.to_string(),
-            1000,
-            0, // min_out parameter
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let asset = Address::generate(&env);
+    let oracle_at = |price: i128| {
+        let oracle = env.register(MockPriceOracle, ());
+        env.as_contract(&oracle, || {
+            MockPriceOracle::set_price(env.clone(), asset.clone(), price);
+        });
+        oracle
+    };
+    let sources = [oracle_at(100), oracle_at(110), oracle_at(120)];
+
+    env.as_contract(&contract_id, || {
+        let add = |oracle: &Address| {
+            Oracle::set_source(
+                &env,
+                &admin,
+                &asset,
+                OracleSource::new(oracle.clone(), 1, 0),
+            )
+            .unwrap();
+        };
+        // A single source cannot disagree with itself
+        add(&sources[0]);
+        let single = Oracle::aggregate_with_confidence(&env, &asset).unwrap();
+        assert_eq!(
+            (single.price, single.spread_bps, single.num_sources),
+            (100, 0, 1)
         );
-        assert!(result.is_err());
-        // The empty string should be caught by our address validation
-        assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
 
-        // Test liquidate with empty user address
-        // First verify the liquidator so we can test the user address validation
-        TestUtils::verify_user(&env, &admin, &valid_user);
+        add(&sources[1]);
+        add(&sources[2]);
+        let details = Oracle::aggregate_price_detailed(&env, &asset).unwrap();
+        assert_eq!(Oracle::spread_bps(&env, &details.samples), 20 * 10000 / 110);
 
-        let result = Contract::liquidate(
-            env.clone(),
-            valid_user.to_string(),
-            String::from_str(&env, ""),
-            1000,
-            0, // min_out parameter
+        // The cached price reports the spread it was aggregated with
+        let cached = Oracle::aggregate_with_confidence(&env, &asset).unwrap();
+        assert_eq!((cached.spread_bps, cached.num_sources), (1818, 3));
+        assert_eq!(Oracle::aggregate_price(&env, &asset), Some(cached.price));
+    });
+}
+
+#[test]
+fn test_borrow_refused_when_price_spread_too_wide() {
+    use crate::oracle::{Oracle, OracleSource};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    let oracle_at = |price: i128| {
+        let oracle = env.register(MockPriceOracle, ());
+        env.as_contract(&oracle, || {
+            MockPriceOracle::set_price(env.clone(), token.clone(), price);
+        });
+        oracle
+    };
+    let (low, high) = (oracle_at(100), oracle_at(120));
+
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+        for oracle in [&low, &high] {
+            Oracle::set_source(
+                &env,
+                &admin,
+                &token,
+                OracleSource::new(oracle.clone(), 1, 0),
+            )
+            .unwrap();
+        }
+
+        Contract::set_max_price_spread(env.clone(), admin.to_string(), 1000).unwrap();
+        assert_eq!(
+            Contract::borrow(env.clone(), user.to_string(), 100, 0),
+            Err(ProtocolError::PriceDeviationTooHigh)
         );
-        assert!(result.is_err());
-        // This should fail when the liquidation module tries to parse the empty user string
-        // The exact error depends on where the validation happens first
-        assert!(matches!(
-            result.unwrap_err(),
-            ProtocolError::InvalidAddress
-                | ProtocolError::UserNotVerified
-                | ProtocolError::PositionNotFound
-        ));
+
+        Contract::set_max_price_spread(env.clone(), admin.to_string(), 2000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 100, 0).unwrap();
     });
 }
 
 #[test]
-fn test_analyst_role_validation() {
+fn test_admin_role_validation() {
     let env = Env::default();
     env.mock_all_auths();
 
     let admin = TestUtils::create_admin_address(&env);
-    let analyst = TestUtils::create_user_address(&env, 0);
+    let user = TestUtils::create_user_address(&env, 0);
 
     let contract_id = env.register(Contract, ());
     env.as_contract(&contract_id, || {
@@ -1455,44 +2551,179 @@ fn test_analyst_role_validation() {
         // Bootstrap users with different roles
         UserManager::bootstrap_admin(&env, &admin);
 
-        // Set analyst role for analyst user
-        UserManager::set_role(&env, &admin, &analyst, UserRole::Analyst).unwrap();
-
-        // Test analyst can perform verification operations
-        let result = Contract::set_user_verification(
-            env.clone(),
-            analyst.to_string(),
-            analyst.clone(),
-            VerificationStatus::Verified,
-        );
+        // Test admin can perform admin-only operations
+        let result = Contract::set_min_collateral_ratio(env.clone(), admin.to_string(), 150);
         assert!(result.is_ok());
 
-        // Test analyst cannot perform admin operations
-        let result = Contract::set_min_collateral_ratio(env.clone(), analyst.to_string(), 200);
+        // Test non-admin cannot perform admin-only operations
+        let result = Contract::set_min_collateral_ratio(env.clone(), user.to_string(), 200);
         assert!(result.is_err());
         assert_eq!(result.unwrap_err(), ProtocolError::Unauthorized);
     });
 }
+// Address validation tests
+#[test]
+fn test_address_helper_valid_address() {
+    let env = Env::default();
+
+    // Test with a valid Stellar address
+    let valid_address = String::from_str(
+        &env,
+        "GCAZYE3EB54VKP3UQBX3H73VQO3SIWTZNR7NJQKJFZZ6XLADWA4C3SOC",
+    );
+    let result = AddressHelper::require_valid_address(&env, &valid_address);
+    assert!(result.is_ok());
+}
 
 #[test]
-fn test_get_position_invalid_user() {
+fn test_address_helper_empty_address() {
+    let env = Env::default();
+
+    // Test with empty string
+    let empty_address = String::from_str(&env, "");
+    let result = AddressHelper::require_valid_address(&env, &empty_address);
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Value, InvalidInput)")]
+fn test_address_helper_malformed_address() {
+    let env = Env::default();
+
+    // Test with malformed address (too short)
+    // Note: This test demonstrates the original problem - malformed addresses cause panics
+    // Our validation catches some cases but Address::from_string still panics on others
+    // This test documents that malformed addresses still cause panics, which is the
+    // original issue we're addressing with safe wrappers
+    let malformed_address = String::from_str(&env, "invalid");
+
+    // This will panic because Address::from_string doesn't handle malformed addresses gracefully
+    // This demonstrates why we need the AddressHelper for safer address handling
+    let _result = AddressHelper::require_valid_address(&env, &malformed_address);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Value, InvalidInput)")]
+fn test_address_helper_null_bytes() {
+    let env = Env::default();
+
+    // Test with address containing null bytes
+    // Note: This test demonstrates the original problem - addresses with null bytes cause panics
+    // Our current validation doesn't catch null bytes in the middle of strings
+    let null_address = String::from_str(
+        &env,
+        "GCAZYE3EB54VKP3UQBX3H73VQO3SIWTZNR7NJQKJFZZ6XLADWA4C3SOC\0",
+    );
+
+    // This will panic because Address::from_string doesn't handle null bytes gracefully
+    // This demonstrates the limitation of our current validation and why more sophisticated
+    // validation would be needed for production use
+    let _result = AddressHelper::require_valid_address(&env, &null_address);
+}
+
+#[test]
+fn test_address_helper_too_long_address() {
+    let env = Env::default();
+
+    // Test with excessively long string (over 256 characters)
+    let long_string = "A".repeat(300);
+    let long_address = String::from_str(&env, &long_string);
+    let result = AddressHelper::require_valid_address(&env, &long_address);
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
+}
+
+#[test]
+fn test_address_helper_validate_format() {
+    let env = Env::default();
+
+    // Test valid format
+    let valid_address = String::from_str(
+        &env,
+        "GCAZYE3EB54VKP3UQBX3H73VQO3SIWTZNR7NJQKJFZZ6XLADWA4C3SOC",
+    );
+    let result = AddressHelper::validate_address_format(&valid_address);
+    assert!(result.is_ok());
+
+    // Test empty format
+    let empty_address = String::from_str(&env, "");
+    let result = AddressHelper::validate_address_format(&empty_address);
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
+}
+
+#[test]
+fn test_address_helper_is_valid_address_string() {
+    let env = Env::default();
+
+    // Test valid address string
+    let valid_address = String::from_str(
+        &env,
+        "GCAZYE3EB54VKP3UQBX3H73VQO3SIWTZNR7NJQKJFZZ6XLADWA4C3SOC",
+    );
+    assert!(AddressHelper::is_valid_address_string(&valid_address));
+
+    // Test invalid address string
+    let invalid_address = String::from_str(&env, "");
+    assert!(!AddressHelper::is_valid_address_string(&invalid_address));
+}
+
+#[test]
+fn test_address_helper_from_strings_safe() {
+    let env = Env::default();
+
+    let addr1 = String::from_str(
+        &env,
+        "GCAZYE3EB54VKP3UQBX3H73VQO3SIWTZNR7NJQKJFZZ6XLADWA4C3SOC",
+    );
+    let addr2 = String::from_str(
+        &env,
+        "GCXOTMMXRS24MYZI5FJPUCOEOFNWSR4XX7UXIK3NDGGE6A5QMJ5FF2FS",
+    );
+
+    // Test with valid addresses
+    let mut addresses = Vec::new(&env);
+    addresses.push_back(addr1.clone());
+    addresses.push_back(addr2.clone());
+    let result = AddressHelper::from_strings_safe(&env, addresses);
+    assert!(result.is_ok());
+    let parsed_addresses = result.unwrap();
+    assert_eq!(parsed_addresses.len(), 2);
+
+    // Test with one invalid address
+    let invalid_addr = String::from_str(&env, "");
+    let mut addresses_with_invalid = Vec::new(&env);
+    addresses_with_invalid.push_back(addr1);
+    addresses_with_invalid.push_back(invalid_addr);
+    let result = AddressHelper::from_strings_safe(&env, addresses_with_invalid);
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
+}
+
+// Integration tests for public API functions with invalid addresses
+#[test]
+fn test_initialize_invalid_admin_address() {
     let env = Env::default();
     env.mock_all_auths();
 
-    let admin = TestUtils::create_admin_address(&env);
     let contract_id = env.register(Contract, ());
     env.as_contract(&contract_id, || {
-        Contract::initialize(env.clone(), admin.to_string()).unwrap();
-
-        // Test get_position with empty user address
-        let result = Contract::get_position(env.clone(), String::from_str(&env, ""));
+        // Test initialization with empty admin address
+        let result = Contract::initialize(env.clone(), String::from_str(&env, ""));
         assert!(result.is_err());
         assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
+
+        // Note: Testing malformed addresses that cause panics is commented out
+        // as they demonstrate the original problem we're solving
+        // let result = Contract::initialize(env.clone(), String::from_str(&env, "invalid"));
+        // assert!(result.is_err());
+        // assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
     });
 }
 
 #[test]
-fn test_role_escalation_prevention() {
+fn test_manager_role_validation() {
     let env = Env::default();
     env.mock_all_auths();
 
@@ -1510,25 +2741,244 @@ fn test_role_escalation_prevention() {
         // Set manager role for manager user
         UserManager::set_role(&env, &admin, &manager, UserRole::Manager).unwrap();
 
-        // Test manager cannot escalate user to admin role (only admin can set admin)
+        // Test manager can perform manager-level operations (user management)
         let result = Contract::set_user_role(
             env.clone(),
             manager.to_string(),
             manager.clone(),
-            UserRole::Admin,
+            UserRole::Standard,
         );
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), ProtocolError::Unauthorized);
+        assert!(result.is_ok());
 
-        // Only admin can set admin role
+        // Test manager cannot escalate to admin role
         let result = Contract::set_user_role(
             env.clone(),
-            admin.to_string(),
+            manager.to_string(),
             manager.clone(),
             UserRole::Admin,
         );
-        assert!(result.is_ok());
-    });
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err(), ProtocolError::Unauthorized);
+    });
+}
+
+#[test]
+fn test_deposit_collateral_invalid_depositor() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = TestUtils::create_admin_address(&env);
+
+    let contract_id = env.register(Contract, ());
+    env.as_contract(&contract_id, || {
+        Contract::initialize(env.clone(), admin.to_string()).unwrap();
+
+        // Test deposit with empty depositor address
+        let result = Contract::deposit_collateral(env.clone(), String::from_str(&env, ""), 1000);
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
+
+        // Note: Testing malformed addresses that cause panics is commented out
+        // as they demonstrate the original problem we're solving
+        // let result = Contract::deposit_collateral(env.clone(), String::from_str(&env, "bad_addr"), 1000);
+        // assert!(result.is_err());
+        // assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
+    });
+}
+
+#[test]
+fn test_borrow_invalid_borrower() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = TestUtils::create_admin_address(&env);
+    let contract_id = env.register(Contract, ());
+    env.as_contract(&contract_id, || {
+        Contract::initialize(env.clone(), admin.to_string()).unwrap();
+
+        // Test borrow with empty borrower address
+        let result = Contract::borrow(env.clone(), String::from_str(&env, ""), 1000, 0);
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
+    });
+}
+
+#[test]
+fn test_repay_invalid_repayer() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = TestUtils::create_admin_address(&env);
+    let contract_id = env.register(Contract, ());
+    env.as_contract(&contract_id, || {
+        Contract::initialize(env.clone(), admin.to_string()).unwrap();
+
+        // Test repay with empty repayer address
+        let result = Contract::repay(env.clone(), String::from_str(&env, ""), 1000);
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
+    });
+}
+
+#[test]
+fn test_withdraw_invalid_withdrawer() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = TestUtils::create_admin_address(&env);
+    let contract_id = env.register(Contract, ());
+    env.as_contract(&contract_id, || {
+        Contract::initialize(env.clone(), admin.to_string()).unwrap();
+
+        // Test withdraw with empty withdrawer address
+        let result = Contract::withdraw(env.clone(), String::from_str(&env, ""), 1000, 0);
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
+    });
+}
+
+#[test]
+fn test_liquidate_invalid_addresses() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = TestUtils::create_admin_address(&env);
+    let valid_user = TestUtils::create_user_address(&env, 0);
+    let contract_id = env.register(Contract, ());
+    env.as_contract(&contract_id, || {
+        Contract::initialize(env.clone(), admin.to_string()).unwrap();
+
+        // Test liquidate with empty liquidator address
+        let result = Contract::liquidate(
+            env.clone(),
+            String::from_str(&env, ""),
+            valid_user.to_string(),
+            1000,
+            0, // min_out parameter
+            false,
+            0,
+            false,
+        );
+        assert!(result.is_err());
+        // The empty string should be caught by our address validation
+        assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
+
+        // Test liquidate with empty user address
+        // First verify the liquidator so we can test the user address validation
+        TestUtils::verify_user(&env, &admin, &valid_user);
+
+        let result = Contract::liquidate(
+            env.clone(),
+            valid_user.to_string(),
+            String::from_str(&env, ""),
+            1000,
+            0, // min_out parameter
+            false,
+            0,
+            false,
+        );
+        assert!(result.is_err());
+        // This should fail when the liquidation module tries to parse the empty user string
+        // The exact error depends on where the validation happens first
+        assert!(matches!(
+            result.unwrap_err(),
+            ProtocolError::InvalidAddress
+                | ProtocolError::UserNotVerified
+                | ProtocolError::PositionNotFound
+        ));
+    });
+}
+
+#[test]
+fn test_analyst_role_validation() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = TestUtils::create_admin_address(&env);
+    let analyst = TestUtils::create_user_address(&env, 0);
+
+    let contract_id = env.register(Contract, ());
+    env.as_contract(&contract_id, || {
+        // Initialize contract
+        Contract::initialize(env.clone(), admin.to_string()).unwrap();
+
+        // Bootstrap users with different roles
+        UserManager::bootstrap_admin(&env, &admin);
+
+        // Set analyst role for analyst user
+        UserManager::set_role(&env, &admin, &analyst, UserRole::Analyst).unwrap();
+
+        // Test analyst can perform verification operations
+        let result = Contract::set_user_verification(
+            env.clone(),
+            analyst.to_string(),
+            analyst.clone(),
+            VerificationStatus::Verified,
+        );
+        assert!(result.is_ok());
+
+        // Test analyst cannot perform admin operations
+        let result = Contract::set_min_collateral_ratio(env.clone(), analyst.to_string(), 200);
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err(), ProtocolError::Unauthorized);
+    });
+}
+
+#[test]
+fn test_get_position_invalid_user() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = TestUtils::create_admin_address(&env);
+    let contract_id = env.register(Contract, ());
+    env.as_contract(&contract_id, || {
+        Contract::initialize(env.clone(), admin.to_string()).unwrap();
+
+        // Test get_position with empty user address
+        let result = Contract::get_position(env.clone(), String::from_str(&env, ""));
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
+    });
+}
+
+#[test]
+fn test_role_escalation_prevention() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = TestUtils::create_admin_address(&env);
+    let manager = TestUtils::create_user_address(&env, 0);
+
+    let contract_id = env.register(Contract, ());
+    env.as_contract(&contract_id, || {
+        // Initialize contract
+        Contract::initialize(env.clone(), admin.to_string()).unwrap();
+
+        // Bootstrap users with different roles
+        UserManager::bootstrap_admin(&env, &admin);
+
+        // Set manager role for manager user
+        UserManager::set_role(&env, &admin, &manager, UserRole::Manager).unwrap();
+
+        // Test manager cannot escalate user to admin role (only admin can set admin)
+        let result = Contract::set_user_role(
+            env.clone(),
+            manager.to_string(),
+            manager.clone(),
+            UserRole::Admin,
+        );
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err(), ProtocolError::Unauthorized);
+
+        // Only admin can set admin role
+        let result = Contract::set_user_role(
+            env.clone(),
+            admin.to_string(),
+            manager.clone(),
+            UserRole::Admin,
+        );
+        assert!(result.is_ok());
+    });
 }
 
 #[test]
@@ -1571,6 +3021,7 @@ fn test_emergency_functions_invalid_caller() {
             env.clone(),
             String::from_str(&env, ""),
             Some(String::from_str(&env, "test")),
+            None,
         );
         assert!(result.is_err());
         assert_eq!(result.unwrap_err(), ProtocolError::InvalidAddress);
@@ -1629,6 +3080,7 @@ fn test_pause_controls() {
         &true,  // deposit
         &false, // withdraw
         &false, // liquidate
+        &None,
     );
 
     // Attempt deposit while paused
@@ -1643,3 +3095,6894 @@ fn create_token_contract<'a>(env: &Env, admin: &Address) -> MockTokenClient<'a>
     token.initialize(admin);
     token
 }
+
+#[test]
+fn test_registered_assets_listed_in_registration_order() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = TestUtils::create_admin_address(&env);
+    let contract_id = env.register(Contract, ());
+    env.as_contract(&contract_id, || {
+        Contract::initialize(env.clone(), admin.to_string()).unwrap();
+
+        let zeta = Address::generate(&env);
+        let alpha = Address::generate(&env);
+        let mid = Address::generate(&env);
+        for (key, token) in [("zeta", &zeta), ("alpha", &alpha), ("mid", &mid)] {
+            Contract::register_token_asset(
+                env.clone(),
+                admin.to_string(),
+                Symbol::new(&env, key),
+                token.clone(),
+            )
+            .unwrap();
+        }
+        // Re-registering an existing key keeps its original slot
+        Contract::register_token_asset(
+            env.clone(),
+            admin.to_string(),
+            Symbol::new(&env, "zeta"),
+            mid.clone(),
+        )
+        .unwrap();
+
+        let listed = Contract::list_registered_assets(env.clone());
+        assert_eq!(listed.len(), 3);
+        assert_eq!(
+            listed.get(0).unwrap(),
+            (Symbol::new(&env, "zeta"), mid.clone())
+        );
+        assert_eq!(listed.get(1).unwrap(), (Symbol::new(&env, "alpha"), alpha));
+        assert_eq!(listed.get(2).unwrap(), (Symbol::new(&env, "mid"), mid));
+    });
+}
+
+#[test]
+fn test_governance_listings_are_ordered() {
+    use crate::governance::{GovStorage, Governance};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(Contract, ());
+    env.as_contract(&contract_id, || {
+        for title in ["first", "second", "third"] {
+            let proposer = Address::generate(&env);
+            Governance::propose(
+                &env,
+                &proposer,
+                String::from_str(&env, title),
+                gov_actions(&env),
+                100,
+            )
+            .unwrap();
+        }
+        let proposals = GovStorage::list_proposals(&env, 0, 10).items;
+        assert_eq!(proposals.len(), 3);
+        for (i, p) in proposals.iter().enumerate() {
+            assert_eq!(p.id, i as u64 + 1);
+        }
+    });
+
+    let voters = [
+        Address::generate(&env),
+        Address::generate(&env),
+        Address::generate(&env),
+    ];
+    env.as_contract(&contract_id, || {
+        for voter in voters.iter().rev() {
+            cast_vote(&env, 2, voter, true, 10).unwrap();
+        }
+    });
+    // A repeat vote is its own invocation; it updates the receipt without
+    // moving the voter
+    env.as_contract(&contract_id, || {
+        cast_vote(&env, 2, &voters[2], false, 5).unwrap();
+
+        let receipts = GovStorage::list_receipts(&env, 2);
+        assert_eq!(receipts.len(), 3);
+        assert_eq!(receipts.get(0).unwrap().voter, voters[2]);
+        assert!(!receipts.get(0).unwrap().support);
+        assert_eq!(receipts.get(1).unwrap().voter, voters[1]);
+        assert_eq!(receipts.get(2).unwrap().voter, voters[0]);
+    });
+}
+
+#[test]
+fn test_gov_receipts_migrate_in_bounded_batches() {
+    use crate::governance::{GovStorage, Governance, VoteReceipt};
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let client = ContractClient::new(&env, &contract_id);
+    let admin_str = admin.to_string();
+
+    // Seed 30 receipts in the pre-migration layout
+    let mut voters = Vec::new(&env);
+    env.as_contract(&contract_id, || {
+        let proposer = Address::generate(&env);
+        Governance::propose(
+            &env,
+            &proposer,
+            String::from_str(&env, "legacy"),
+            gov_actions(&env),
+            100,
+        )
+        .unwrap();
+        let mut map = Map::new(&env);
+        for i in 0..30 {
+            let voter = Address::generate(&env);
+            map.set(
+                voter.clone(),
+                VoteReceipt {
+                    voter: voter.clone(),
+                    support: i % 2 == 0,
+                    weight: i as i128 + 1,
+                },
+            );
+            voters.push_back(voter);
+        }
+        env.storage()
+            .instance()
+            .set(&(GovStorage::receipts_key(&env), 1u64), &map);
+        env.storage()
+            .instance()
+            .set(&(GovStorage::voters_key(&env), 1u64), &voters);
+    });
+
+    let check_reads = || {
+        env.as_contract(&contract_id, || {
+            let receipts = GovStorage::list_receipts(&env, 1);
+            assert_eq!(receipts.len(), 30);
+            for (i, r) in receipts.iter().enumerate() {
+                assert_eq!(r.voter, voters.get(i as u32).unwrap());
+                assert_eq!(r.weight, i as i128 + 1);
+            }
+        });
+        for (i, voter) in voters.iter().enumerate() {
+            let receipt = client.get_vote_receipt(&1, &voter).unwrap();
+            assert_eq!(receipt.support, i % 2 == 0);
+        }
+    };
+
+    check_reads();
+    assert_eq!(
+        client.try_migrate_gov_receipts(&admin_str, &1, &0),
+        Err(Ok(ProtocolError::InvalidParameters))
+    );
+    assert_eq!(
+        client.try_migrate_gov_receipts(&admin_str, &9, &10),
+        Err(Ok(ProtocolError::NotFound))
+    );
+
+    for (step, remaining) in [20u32, 10, 0].into_iter().enumerate() {
+        let progress = client.migrate_gov_receipts(&admin_str, &1, &10);
+        assert_eq!(progress.migrated, 10, "step {step}");
+        assert_eq!(progress.remaining, remaining);
+        assert_eq!(progress.complete, remaining == 0);
+        check_reads();
+    }
+
+    env.as_contract(&contract_id, || {
+        assert!(GovStorage::receipts_migrated(&env, 1));
+        assert!(!env
+            .storage()
+            .instance()
+            .has(&(GovStorage::receipts_key(&env), 1u64)));
+    });
+}
+
+#[test]
+fn test_guardian_cancels_queued_proposal() {
+    use crate::governance::Governance;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let guardian = Address::generate(&env);
+    let outsider = Address::generate(&env);
+
+    env.as_contract(&contract_id, || {
+        Contract::set_guardian(env.clone(), admin.to_string(), guardian.clone()).unwrap();
+        let proposer = Address::generate(&env);
+        let p = Governance::propose(
+            &env,
+            &proposer,
+            String::from_str(&env, "queued"),
+            gov_actions(&env),
+            100,
+        )
+        .unwrap();
+        cast_vote(&env, p.id, &proposer, true, 10).unwrap();
+        env.ledger().with_mut(|l| l.timestamp += 100);
+        assert!(Governance::queue(&env, p.id).unwrap().queued_until > 0);
+
+        assert_eq!(
+            Contract::cancel_proposal(env.clone(), outsider.to_string(), p.id),
+            Err(ProtocolError::Unauthorized)
+        );
+        Contract::cancel_proposal(env.clone(), guardian.to_string(), p.id).unwrap();
+        assert!(Contract::is_proposal_cancelled(env.clone(), p.id));
+
+        env.ledger().with_mut(|l| l.timestamp += 100);
+        assert_eq!(
+            Governance::execute(&env, p.id),
+            Err(ProtocolError::ProposalCancelled)
+        );
+        assert_eq!(
+            cast_vote(&env, p.id, &outsider, true, 1),
+            Err(ProtocolError::ProposalCancelled)
+        );
+        assert_eq!(
+            Governance::queue(&env, p.id),
+            Err(ProtocolError::ProposalCancelled)
+        );
+        assert_eq!(
+            Governance::cancel(&env, &admin, p.id),
+            Err(ProtocolError::ProposalCancelled)
+        );
+    });
+}
+
+#[test]
+fn test_proposer_cannot_cancel_after_voting_ends() {
+    use crate::governance::Governance;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let proposer = Address::generate(&env);
+
+    env.as_contract(&contract_id, || {
+        let open = Governance::propose(
+            &env,
+            &proposer,
+            String::from_str(&env, "open"),
+            gov_actions(&env),
+            100,
+        )
+        .unwrap();
+        env.ledger().with_mut(|l| l.timestamp += 50);
+        Governance::cancel(&env, &proposer, open.id).unwrap();
+
+        env.ledger().with_mut(|l| l.timestamp += 1_000_000);
+        let ended = Governance::propose(
+            &env,
+            &proposer,
+            String::from_str(&env, "ended"),
+            gov_actions(&env),
+            100,
+        )
+        .unwrap();
+        env.ledger().with_mut(|l| l.timestamp += 101);
+        assert_eq!(
+            Governance::cancel(&env, &proposer, ended.id),
+            Err(ProtocolError::Unauthorized)
+        );
+        assert!(!Contract::is_proposal_cancelled(env.clone(), ended.id));
+        assert_eq!(
+            Contract::cancel_proposal(env.clone(), proposer.to_string(), 99),
+            Err(ProtocolError::NotFound)
+        );
+    });
+}
+
+#[test]
+fn test_repeat_vote_replaces_previous_weight() {
+    use crate::governance::{GovStorage, Governance};
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register(Contract, ());
+    let proposer = Address::generate(&env);
+    let voter = Address::generate(&env);
+    let id = env.as_contract(&contract_id, || {
+        Governance::propose(
+            &env,
+            &proposer,
+            String::from_str(&env, "vote"),
+            gov_actions(&env),
+            100,
+        )
+        .unwrap()
+        .id
+    });
+    // Each vote is a separate invocation, as it would be on chain
+    let vote = |voter: &Address, support: bool, weight: i128| {
+        env.as_contract(&contract_id, || cast_vote(&env, id, voter, support, weight))
+    };
+
+    for _ in 0..3 {
+        vote(&voter, true, 10).unwrap();
+    }
+    let tally = env.as_contract(&contract_id, || GovStorage::get_tally(&env, id));
+    assert_eq!((tally.for_votes, tally.against_votes), (10, 0));
+
+    // Changing sides moves the weight over
+    let updated = vote(&voter, false, 7).unwrap();
+    assert_eq!((updated.for_votes, updated.against_votes), (0, 7));
+    vote(&proposer, true, 4).unwrap();
+    env.as_contract(&contract_id, || {
+        let tally = GovStorage::get_tally(&env, id);
+        assert_eq!((tally.for_votes, tally.against_votes), (4, 7));
+        assert_eq!(GovStorage::list_receipts(&env, id).len(), 2);
+    });
+
+    env.ledger().with_mut(|l| l.timestamp += 101);
+    assert_eq!(vote(&voter, true, 7), Err(ProtocolError::DeadlineExceeded));
+}
+
+#[test]
+#[should_panic(expected = "Error(Auth, InvalidAction)")]
+fn test_vote_requires_voter_auth() {
+    use crate::governance::Governance;
+
+    let env = Env::default();
+    let contract_id = env.register(Contract, ());
+    env.as_contract(&contract_id, || {
+        let proposer = Address::generate(&env);
+        let p = Governance::propose(
+            &env,
+            &proposer,
+            String::from_str(&env, "auth"),
+            gov_actions(&env),
+            100,
+        )
+        .unwrap();
+        let _ = cast_vote(&env, p.id, &Address::generate(&env), true, 10);
+    });
+}
+
+#[test]
+fn test_proposal_state_machine_transitions() {
+    use crate::governance::{Governance, ProposalState, EXECUTION_GRACE_SECS};
+    use ProposalState::*;
+
+    enum Step {
+        Wait(u64),
+        Vote(bool),
+        Queue,
+        Execute,
+        Cancel,
+    }
+    use Step::*;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1_000);
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+
+    let passed: &[(Step, Result<(), ProtocolError>, ProposalState)] = &[
+        (Vote(true), Ok(()), Active),
+        (Queue, Err(ProtocolError::ProposalNotSucceeded), Active),
+        (Execute, Err(ProtocolError::ProposalNotSucceeded), Active),
+        (Wait(101), Ok(()), Succeeded),
+        (Vote(true), Err(ProtocolError::DeadlineExceeded), Succeeded),
+        (Execute, Err(ProtocolError::TimelockNotElapsed), Succeeded),
+        (Queue, Ok(()), Queued),
+        (Queue, Err(ProtocolError::ProposalNotSucceeded), Queued),
+        (Execute, Err(ProtocolError::TimelockNotElapsed), Queued),
+        (Wait(60), Ok(()), Queued),
+        (Execute, Ok(()), Executed),
+        (Execute, Err(ProtocolError::AlreadyExecuted), Executed),
+        (Queue, Err(ProtocolError::AlreadyExecuted), Executed),
+        (Cancel, Err(ProtocolError::AlreadyExecuted), Executed),
+    ];
+    let defeated: &[(Step, Result<(), ProtocolError>, ProposalState)] = &[
+        (Vote(true), Ok(()), Active),
+        (Vote(false), Ok(()), Active),
+        (Vote(false), Ok(()), Active),
+        (Wait(101), Ok(()), Defeated),
+        (Queue, Err(ProtocolError::ProposalNotSucceeded), Defeated),
+        (Execute, Err(ProtocolError::ProposalNotSucceeded), Defeated),
+    ];
+    let expired: &[(Step, Result<(), ProtocolError>, ProposalState)] = &[
+        (Vote(true), Ok(()), Active),
+        (Wait(101), Ok(()), Succeeded),
+        (Queue, Ok(()), Queued),
+        (Wait(60 + EXECUTION_GRACE_SECS), Ok(()), Expired),
+        (Execute, Err(ProtocolError::DeadlineExceeded), Expired),
+    ];
+    let cancelled: &[(Step, Result<(), ProtocolError>, ProposalState)] = &[
+        (Vote(true), Ok(()), Active),
+        (Cancel, Ok(()), Cancelled),
+        (Vote(true), Err(ProtocolError::ProposalCancelled), Cancelled),
+        (Cancel, Err(ProtocolError::ProposalCancelled), Cancelled),
+        (Wait(101), Ok(()), Cancelled),
+        (Queue, Err(ProtocolError::ProposalCancelled), Cancelled),
+        (Execute, Err(ProtocolError::ProposalCancelled), Cancelled),
+    ];
+
+    for walk in [passed, defeated, expired, cancelled] {
+        let id = env.as_contract(&contract_id, || {
+            Governance::propose(
+                &env,
+                &admin,
+                String::from_str(&env, "walk"),
+                gov_actions(&env),
+                100,
+            )
+            .unwrap()
+            .id
+        });
+        for (i, (step, expected, state)) in walk.iter().enumerate() {
+            // Each step is its own invocation, as it would be on chain
+            let result = env.as_contract(&contract_id, || match step {
+                Wait(secs) => {
+                    env.ledger().with_mut(|l| l.timestamp += secs);
+                    Ok(())
+                }
+                Vote(support) => {
+                    let weight = if *support { 10 } else { 50 };
+                    cast_vote(&env, id, &Address::generate(&env), *support, weight).map(|_| ())
+                }
+                Queue => Governance::queue(&env, id).map(|_| ()),
+                Execute => Governance::execute(&env, id).map(|_| ()),
+                Cancel => Governance::cancel(&env, &admin, id).map(|_| ()),
+            });
+            assert_eq!(result, *expected, "proposal {id} step {i}");
+            assert_eq!(
+                env.as_contract(&contract_id, || Governance::state(&env, id)),
+                Some(*state),
+                "proposal {id} step {i}"
+            );
+        }
+    }
+
+    // Proposals open at creation, so Pending only shows with the ledger behind
+    let id = env.as_contract(&contract_id, || {
+        Governance::propose(
+            &env,
+            &admin,
+            String::from_str(&env, "early"),
+            gov_actions(&env),
+            100,
+        )
+        .unwrap()
+        .id
+    });
+    env.ledger().with_mut(|l| l.timestamp -= 1);
+    let client = ContractClient::new(&env, &contract_id);
+    assert_eq!(client.get_proposal_state(&id), Some(Pending));
+    assert_eq!(client.get_proposal_state(&(id + 1)), None);
+}
+
+/// Sign the vote message the contract expects from `voter`, relayed by
+/// `relayer`, with `key`
+fn sign_vote(
+    client: &ContractClient,
+    key: &ed25519_dalek::SigningKey,
+    proposal_id: u64,
+    voter: &Address,
+    choice: bool,
+    expiry: u64,
+    relayer: &Address,
+) -> BytesN<64> {
+    use ed25519_dalek::Signer;
+
+    let message: alloc::vec::Vec<u8> = client
+        .get_vote_message(&proposal_id, voter, &choice, &expiry, relayer)
+        .iter()
+        .collect();
+    BytesN::from_array(&client.env, &key.sign(&message).to_bytes())
+}
+
+/// Register `key` as `voter`'s vote key, proving possession of it
+fn register_key(client: &ContractClient, key: &ed25519_dalek::SigningKey, voter: &Address) {
+    use ed25519_dalek::Signer;
+
+    let message: alloc::vec::Vec<u8> = client.get_vote_key_message(voter).iter().collect();
+    let pubkey = BytesN::from_array(&client.env, &key.verifying_key().to_bytes());
+    let signature = BytesN::from_array(&client.env, &key.sign(&message).to_bytes());
+    client.register_vote_key(voter, &pubkey, &signature);
+}
+
+#[test]
+fn test_vote_by_sig_records_relayed_vote_once() {
+    use crate::governance::{GovStorage, Governance};
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let voter = TestUtils::create_user_address(&env, 0);
+    let relayer = TestUtils::create_user_address(&env, 1);
+    let (admin, contract_id, _token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&voter));
+    let client = ContractClient::new(&env, &contract_id);
+
+    let id = env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &voter);
+        Contract::deposit_collateral(env.clone(), voter.to_string(), 2000).unwrap();
+        Contract::sync_positions(env.clone(), Vec::from_array(&env, [voter.clone()])).unwrap();
+        Governance::propose(
+            &env,
+            &admin,
+            String::from_str(&env, "relayed"),
+            gov_actions(&env),
+            100,
+        )
+        .unwrap()
+        .id
+    });
+
+    let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+    let pubkey = BytesN::from_array(&env, &key.verifying_key().to_bytes());
+    // Unregistered keys cannot vote
+    let signature = sign_vote(&client, &key, id, &voter, true, 50, &relayer);
+    assert_eq!(
+        client.try_vote_by_sig(&relayer, &id, &pubkey, &true, &50, &signature),
+        Err(Ok(ProtocolError::NotFound))
+    );
+
+    register_key(&client, &key, &voter);
+    assert_eq!(client.get_vote_key(&voter), Some(pubkey.clone()));
+    assert_eq!(client.get_vote_nonce(&voter), 1);
+    let signature = sign_vote(&client, &key, id, &voter, true, 50, &relayer);
+
+    // The signature names its relayer; anyone else holding it cannot submit it
+    let stranger = Address::generate(&env);
+    assert!(client
+        .try_vote_by_sig(&stranger, &id, &pubkey, &true, &50, &signature)
+        .is_err());
+
+    let proposal = client.vote_by_sig(&relayer, &id, &pubkey, &true, &50, &signature);
+    assert_eq!(proposal.for_votes, 2000);
+    assert_eq!(client.get_vote_nonce(&voter), 2);
+    env.as_contract(&contract_id, || {
+        assert!(GovStorage::get_receipt(&env, id, &voter).unwrap().support);
+    });
+
+    // Replaying the same signature fails against the consumed nonce
+    assert!(client
+        .try_vote_by_sig(&relayer, &id, &pubkey, &true, &50, &signature)
+        .is_err());
+
+    // Signed past its expiry
+    env.ledger().with_mut(|l| l.timestamp = 51);
+    let late = sign_vote(&client, &key, id, &voter, false, 50, &relayer);
+    assert_eq!(
+        client.try_vote_by_sig(&relayer, &id, &pubkey, &false, &50, &late),
+        Err(Ok(ProtocolError::DeadlineExceeded))
+    );
+    assert_eq!(client.get_proposal_tally(&id).for_votes, 2000);
+}
+
+#[test]
+fn test_vote_by_sig_rejects_wrong_key() {
+    use crate::governance::Governance;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let voter = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, _token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&voter));
+    let client = ContractClient::new(&env, &contract_id);
+    let id = env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &voter);
+        Contract::deposit_collateral(env.clone(), voter.to_string(), 2000).unwrap();
+        Contract::sync_positions(env.clone(), Vec::from_array(&env, [voter.clone()])).unwrap();
+        Governance::propose(
+            &env,
+            &admin,
+            String::from_str(&env, "keys"),
+            gov_actions(&env),
+            100,
+        )
+        .unwrap()
+        .id
+    });
+
+    let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+    let pubkey = BytesN::from_array(&env, &key.verifying_key().to_bytes());
+    let other = ed25519_dalek::SigningKey::from_bytes(&[9; 32]);
+
+    // Registering a key takes a signature from that key
+    let claim = {
+        use ed25519_dalek::Signer;
+        let message: alloc::vec::Vec<u8> = client.get_vote_key_message(&voter).iter().collect();
+        BytesN::from_array(&env, &other.sign(&message).to_bytes())
+    };
+    assert!(client
+        .try_register_vote_key(&voter, &pubkey, &claim)
+        .is_err());
+    assert_eq!(client.get_vote_key(&voter), None);
+    register_key(&client, &key, &voter);
+
+    let forged = sign_vote(&client, &other, id, &voter, true, 50, &admin);
+    assert!(client
+        .try_vote_by_sig(&admin, &id, &pubkey, &true, &50, &forged)
+        .is_err());
+    assert_eq!(client.get_vote_nonce(&voter), 1);
+    assert_eq!(client.get_proposal_tally(&id).for_votes, 0);
+
+    // A key already linked to one account cannot be claimed by another
+    let signature = BytesN::from_array(&env, &[0; 64]);
+    assert_eq!(
+        client.try_register_vote_key(&admin, &pubkey, &signature),
+        Err(Ok(ProtocolError::AlreadyExists))
+    );
+}
+
+#[test]
+fn test_delegated_power_votes_through_delegate() {
+    use crate::governance::{GovStorage, Governance};
+    use crate::voting::VotingStorage;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1000);
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let client = ContractClient::new(&env, &contract_id);
+    let [a, b, c] = [0; 3].map(|_| Address::generate(&env));
+    let id = env.as_contract(&contract_id, || {
+        VotingStorage::set_power(&env, &a, 30);
+        VotingStorage::set_power(&env, &b, 50);
+        VotingStorage::set_power(&env, &c, 7);
+        let title = String::from_str(&env, "delegated");
+        Governance::propose(&env, &admin, title, gov_actions(&env), 100)
+            .unwrap()
+            .id
+    });
+
+    client.delegate_votes(&a, &b);
+    assert_eq!(client.get_vote_delegate(&a), Some(b.clone()));
+    assert_eq!(client.get_voting_power(&a), 0);
+    assert_eq!(client.get_voting_power(&b), 80);
+    env.as_contract(&contract_id, || {
+        assert_eq!(
+            Governance::vote(&env, id, &a, true),
+            Err(ProtocolError::InvalidOperation)
+        );
+    });
+    env.as_contract(&contract_id, || {
+        assert_eq!(Governance::vote(&env, id, &b, true).unwrap().for_votes, 80);
+    });
+
+    // Moving the delegation moves the power with it, once the power counted
+    // in b's vote is no longer open to voting
+    assert_eq!(
+        client.try_delegate_votes(&a, &c),
+        Err(Ok(ProtocolError::ProposalCooldown))
+    );
+    env.ledger().with_mut(|l| l.timestamp = 1100);
+    client.delegate_votes(&a, &c);
+    assert_eq!(client.get_voting_power(&b), 50);
+    assert_eq!(client.get_voting_power(&c), 37);
+    env.as_contract(&contract_id, || {
+        assert!(GovStorage::get_delegators(&env, &b).is_empty());
+    });
+}
+
+#[test]
+fn test_delegation_cannot_recount_cast_votes() {
+    use crate::governance::Governance;
+    use crate::voting::VotingStorage;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1000);
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let client = ContractClient::new(&env, &contract_id);
+    let [a, b] = [0; 2].map(|_| Address::generate(&env));
+    let id = env.as_contract(&contract_id, || {
+        VotingStorage::set_power(&env, &a, 30);
+        VotingStorage::set_power(&env, &b, 50);
+        let title = String::from_str(&env, "recount");
+        Governance::propose(&env, &admin, title, gov_actions(&env), 100)
+            .unwrap()
+            .id
+    });
+
+    // a votes, then tries to hand the same power to b for a second vote
+    env.as_contract(&contract_id, || {
+        Governance::vote(&env, id, &a, true).unwrap();
+    });
+    assert_eq!(
+        client.try_delegate_votes(&a, &b),
+        Err(Ok(ProtocolError::ProposalCooldown))
+    );
+    env.as_contract(&contract_id, || {
+        assert_eq!(Governance::vote(&env, id, &b, true).unwrap().for_votes, 80);
+    });
+    assert_eq!(client.get_proposal_tally(&id).for_votes, 80);
+
+    // Once voting has ended the power is free to move
+    env.ledger().with_mut(|l| l.timestamp = 1100);
+    client.delegate_votes(&a, &b);
+    assert_eq!(client.get_voting_power(&b), 80);
+}
+
+#[test]
+fn test_delegation_loops_are_rejected() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let client = ContractClient::new(&env, &contract_id);
+    let [a, b, c] = [0; 3].map(|_| Address::generate(&env));
+
+    assert_eq!(
+        client.try_delegate_votes(&a, &a),
+        Err(Ok(ProtocolError::DelegationLoop))
+    );
+    client.delegate_votes(&a, &b);
+    assert_eq!(
+        client.try_delegate_votes(&b, &a),
+        Err(Ok(ProtocolError::DelegationLoop))
+    );
+    client.delegate_votes(&b, &c);
+    assert_eq!(
+        client.try_delegate_votes(&c, &a),
+        Err(Ok(ProtocolError::DelegationLoop))
+    );
+    assert_eq!(client.get_vote_delegate(&c), None);
+}
+
+#[test]
+fn test_withdrawal_penalty_inside_and_outside_window() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::set_withdrawal_penalty(env.clone(), admin.to_string(), token.clone(), 100, 1000)
+            .unwrap();
+
+        env.ledger().with_mut(|l| l.timestamp = 10_000);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 2000).unwrap();
+
+        // Inside the window: 1% of 1000 goes to reserves
+        env.ledger().with_mut(|l| l.timestamp = 10_500);
+        Contract::withdraw(env.clone(), user.to_string(), 1000, 0).unwrap();
+        assert_eq!(
+            Contract::get_protocol_reserves(env.clone(), token.clone()),
+            10
+        );
+
+        // Outside the window: no further penalty
+        env.ledger().with_mut(|l| l.timestamp = 11_000);
+        Contract::withdraw(env.clone(), user.to_string(), 1000, 0).unwrap();
+        assert_eq!(
+            Contract::get_protocol_reserves(env.clone(), token.clone()),
+            10
+        );
+    });
+
+    env.as_contract(&token, || {
+        assert_eq!(
+            MockToken::balance(env.clone(), user.clone()),
+            1_000_000 - 10
+        );
+    });
+}
+
+#[test]
+fn test_withdrawal_penalty_fifo_across_deposits() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::set_withdrawal_penalty(env.clone(), admin.to_string(), token.clone(), 500, 1000)
+            .unwrap();
+
+        env.ledger().with_mut(|l| l.timestamp = 10_000);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+        env.ledger().with_mut(|l| l.timestamp = 10_800);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+
+        // First lot has aged out; only the 400 matched against the second lot pays
+        env.ledger().with_mut(|l| l.timestamp = 11_200);
+        Contract::withdraw(env.clone(), user.to_string(), 1400, 0).unwrap();
+        assert_eq!(
+            Contract::get_protocol_reserves(env.clone(), token.clone()),
+            20
+        );
+
+        let lots = crate::penalty::PenaltyStorage::get_lots(&env, &user);
+        assert_eq!(lots.len(), 1);
+        assert_eq!(lots.get(0).unwrap().amount, 600);
+    });
+}
+
+/// One representative of every storage key the contract can produce,
+/// labelled with the constructor that builds it. Keys extended at the call
+/// site (e.g. `(config_key, asset)`) are registered in their stored shape.
+fn storage_key_registry(
+    env: &Env,
+    user: &Address,
+    asset: &Address,
+) -> alloc::vec::Vec<(&'static str, soroban_sdk::Val)> {
+    use crate::accrual::AccrualStorage;
+    use crate::alerts::AlertStorage;
+    use crate::analytics::AnalyticsStorage;
+    use crate::autorepay::RepayPlanStorage;
+    use crate::beta::BetaAccess;
+    use crate::capabilities::FeatureFlags;
+    use crate::caps::CapStorage;
+    use crate::checkpoints::IndexCheckpointStorage;
+    use crate::commitment::StateCommitment;
+    use crate::cosign::CoSignStorage;
+    use crate::credit::CreditStorage;
+    use crate::delegation::DelegationStorage;
+    use crate::diagnostics::CallTracker;
+    use crate::forgiveness::InterestForgiveness;
+    use crate::govbudget::BudgetStorage;
+    use crate::governance::GovStorage;
+    use crate::history::ParamHistory;
+    use crate::incentives::IncentiveStorage;
+    use crate::liquidate::LiquidationRecordStorage;
+    use crate::listing::ListingStorage;
+    use crate::migration::MigrationStorage;
+    use crate::payouts::PayoutStorage;
+    use crate::penalty::PenaltyStorage;
+    use crate::reasons::{ChangeReason, ReasonCodes};
+    use crate::reconcile::ReconcileStorage;
+    use crate::recovery::RecoveryStorage;
+    use crate::reserves::ReserveStorage;
+    use crate::rewards::RewardStorage;
+    use crate::roles::{RoleChangeStorage, RoleKind};
+    use crate::surge::SurgeStorage;
+    use crate::sweep::SweepStorage;
+    use crate::transfer::TransferStorage;
+    use crate::twa::TwaStorage;
+    use crate::voting::VotingStorage;
+    use soroban_sdk::IntoVal;
+
+    let other = Address::generate(env);
+    let id = 1u64;
+    let pubkey = BytesN::from_array(env, &[0; 32]);
+    let mut keys: alloc::vec::Vec<(&'static str, soroban_sdk::Val)> = alloc::vec::Vec::new();
+    macro_rules! register {
+        ($($name:literal => $key:expr),* $(,)?) => {
+            $(keys.push(($name, $key.into_val(env)));)*
+        };
+    }
+
+    // Exhaustive so that a new variant fails to compile until registered
+    for key in [
+        UserStorageKey::Profile(user.clone()),
+        UserStorageKey::PrivacyFlags(user.clone()),
+    ] {
+        let name = match key {
+            UserStorageKey::Profile(_) => "UserStorageKey::Profile",
+            UserStorageKey::PrivacyFlags(_) => "UserStorageKey::PrivacyFlags",
+        };
+        keys.push((name, key.into_val(env)));
+    }
+    for role in [RoleKind::Guardian, RoleKind::Treasury] {
+        let name = match role {
+            RoleKind::Guardian => "RoleChangeStorage::key(Guardian)",
+            RoleKind::Treasury => "RoleChangeStorage::key(Treasury)",
+        };
+        keys.push((name, RoleChangeStorage::key(env, role).into_val(env)));
+    }
+
+    register! {
+        "EmergencyStorage::key" => EmergencyStorage::key(env),
+        "BetaAccess::config_key" => BetaAccess::config_key(env),
+        "SurgeStorage::limit_key(asset)" => SurgeStorage::limit_key(env, &Some(asset.clone())),
+        "SurgeStorage::limit_key(global)" => SurgeStorage::limit_key(env, &None),
+        "SurgeStorage::window_key(asset)" => SurgeStorage::window_key(env, &Some(asset.clone())),
+        "SurgeStorage::window_key(global)" => SurgeStorage::window_key(env, &None),
+        "SweepStorage::holder_count_key" => SweepStorage::holder_count_key(env, asset),
+        "SweepStorage::holder_slot_key" => SweepStorage::holder_slot_key(env, asset, 0),
+        "SweepStorage::holder_marker_key" => SweepStorage::holder_marker_key(env, asset, user),
+        "SweepStorage::state_key" => SweepStorage::state_key(env, asset),
+        "SweepStorage::deviation_key" => SweepStorage::deviation_key(env),
+        "PayoutStorage::settlement_asset_key" => PayoutStorage::settlement_asset_key(env),
+        "PayoutStorage::max_slippage_key" => PayoutStorage::max_slippage_key(env),
+        "PayoutStorage::queue_key" => PayoutStorage::queue_key(env, asset),
+        "PayoutStorage::balance_key" => PayoutStorage::balance_key(env, user, asset),
+        "BetaAccess::user_key" => BetaAccess::user_key(env, user),
+        "EventStorage::aggregates_key" => EventStorage::aggregates_key(env),
+        "EventStorage::logs_key" => EventStorage::logs_key(env),
+        "EventStorage::summary_key" => EventStorage::summary_key(env),
+        "EventStorage::feed_key" => EventStorage::feed_key(env),
+        "EventStorage::sequence_key" => EventStorage::sequence_key(env),
+        "TokenRegistry::registry_key" => TokenRegistry::registry_key(env),
+        "TokenRegistry::order_key" => TokenRegistry::order_key(env),
+        "TokenRegistry::primary_key" => TokenRegistry::primary_key(env),
+        "ReentrancyGuard::key" => ReentrancyGuard::key(env),
+        "ReentrancyGuard::batch_key" => ReentrancyGuard::batch_key(env),
+        "RiskConfigStorage::key" => RiskConfigStorage::key(env),
+        "RiskConfigStorage::max_spread_key" => RiskConfigStorage::max_spread_key(env),
+        "InterestRateStorage::config_key" => InterestRateStorage::config_key(env),
+        "InterestRateStorage::state_key" => InterestRateStorage::state_key(env),
+        "StateHelper::position_key" => StateHelper::position_key(env, user),
+        "StateHelper::position_index_key" => StateHelper::position_index_key(env),
+        "StateHelper::indexed_marker_key" => StateHelper::indexed_marker_key(env, user),
+        "ProtocolConfig::admin_key" => ProtocolConfig::admin_key(env),
+        "ProtocolConfig::oracle_key" => ProtocolConfig::oracle_key(env),
+        "ProtocolConfig::min_collateral_ratio_key" => ProtocolConfig::min_collateral_ratio_key(env),
+        "ProtocolConfig::flash_fee_bps_key" => ProtocolConfig::flash_fee_bps_key(env),
+        "ProtocolConfig::treasury_key" => ProtocolConfig::treasury_key(env),
+        "ProtocolConfig::origination_buffer_key" => ProtocolConfig::origination_buffer_key(env),
+        "ProtocolConfig::liquidation_threshold_key" => ProtocolConfig::liquidation_threshold_key(env),
+        "ProtocolConfig::guardian_key" => ProtocolConfig::guardian_key(env),
+        "AccrualStorage::state_key" => AccrualStorage::state_key(env),
+        "AccrualStorage::supply_index_key" => AccrualStorage::supply_index_key(env),
+        "AccrualStorage::max_gap_key" => AccrualStorage::max_gap_key(env),
+        "AccrualStorage::fresh_window_key" => AccrualStorage::fresh_window_key(env),
+        "AccrualStorage::rate_threshold_key" => AccrualStorage::rate_threshold_key(env),
+        "AccrualStorage::last_rate_key" => AccrualStorage::last_rate_key(env, &Some(asset.clone())),
+        "AlertStorage::snapshots_key" => AlertStorage::snapshots_key(env, user),
+        "AlertStorage::watch_key" => AlertStorage::watch_key(env, user),
+        "AlertStorage::interval_key" => AlertStorage::interval_key(env),
+        "AnalyticsStorage::protocol_metrics_key" => AnalyticsStorage::protocol_metrics_key(env),
+        "AnalyticsStorage::user_analytics_key" => AnalyticsStorage::user_analytics_key(env),
+        "AnalyticsStorage::asset_analytics_key" => AnalyticsStorage::asset_analytics_key(env),
+        "AnalyticsStorage::historical_data_key" => AnalyticsStorage::historical_data_key(env),
+        "AnalyticsStorage::risk_analytics_key" => AnalyticsStorage::risk_analytics_key(env),
+        "AnalyticsStorage::performance_metrics_key" => AnalyticsStorage::performance_metrics_key(env),
+        "AnalyticsStorage::activity_log_key" => AnalyticsStorage::activity_log_key(env),
+        "AnalyticsStorage::collateral_exposure_key" => AnalyticsStorage::collateral_exposure_key(env),
+        "AnalyticsStorage::exposure_share_key" => AnalyticsStorage::exposure_share_key(env, user),
+        "AnalyticsStorage::collateral_mix_key" => AnalyticsStorage::collateral_mix_key(env, user),
+        "AnalyticsStorage::size_bucket_key" => AnalyticsStorage::size_bucket_key(env, user),
+        "RepayPlanStorage::plan_key" => RepayPlanStorage::plan_key(env, user),
+        "FeatureFlags::flags_key" => FeatureFlags::flags_key(env),
+        "CapStorage::supply_cap_key" => (CapStorage::supply_cap_key(env), asset.clone()),
+        "CapStorage::borrow_cap_key" => CapStorage::borrow_cap_key(env, asset),
+        "CapStorage::borrowed_key" => CapStorage::borrowed_key(env, asset),
+        "CapStorage::group_key" => CapStorage::group_key(env, &Symbol::new(env, "group")),
+        "CapStorage::asset_group_key" => CapStorage::asset_group_key(env, asset),
+        "CapStorage::global_tvl_cap_key" => CapStorage::global_tvl_cap_key(env),
+        "IndexCheckpointStorage::key" => IndexCheckpointStorage::key(env, asset),
+        "StateCommitment::key" => StateCommitment::key(env),
+        "CoSignStorage::policy_key" => CoSignStorage::policy_key(env, user),
+        "CoSignStorage::pending_key" => CoSignStorage::pending_key(env, user),
+        "CoSignStorage::approval_key" => CoSignStorage::approval_key(env, user),
+        "CreditStorage::weights_key" => CreditStorage::weights_key(env),
+        "CreditStorage::bands_key" => CreditStorage::bands_key(env),
+        "CreditStorage::history_key" => CreditStorage::history_key(env, user),
+        "DelegationStorage::allowance_key" => DelegationStorage::allowance_key(env, user, &other, asset),
+        "DelegationStorage::grants_key" => DelegationStorage::grants_key(env, user),
+        "CallTracker::stats_key" => CallTracker::stats_key(env),
+        "InterestForgiveness::pending_key" => InterestForgiveness::pending_key(env),
+        "BudgetStorage::budget_key" => BudgetStorage::budget_key(env),
+        "BudgetStorage::weight_key" => BudgetStorage::weight_key(env, &Symbol::new(env, "category")),
+        "BudgetStorage::usage_key" => BudgetStorage::usage_key(env),
+        "BudgetStorage::carryover_key" => BudgetStorage::carryover_key(env, id),
+        "GovStorage::proposals_key" => GovStorage::proposals_key(env),
+        "GovStorage::receipts_key" => (GovStorage::receipts_key(env), id),
+        "GovStorage::receipt_key" => GovStorage::receipt_key(env, id, user),
+        "GovStorage::receipts_migrated_key" => GovStorage::receipts_migrated_key(env, id),
+        "GovStorage::voters_key" => (GovStorage::voters_key(env), id),
+        "GovStorage::tally_key" => GovStorage::tally_key(env, id),
+        "GovStorage::cancelled_key" => GovStorage::cancelled_key(env, id),
+        "GovStorage::actions_key" => GovStorage::actions_key(env, id),
+        "GovStorage::counter_key" => GovStorage::counter_key(env),
+        "GovStorage::vote_key_key" => GovStorage::vote_key_key(env, user),
+        "GovStorage::vote_key_owner_key" => GovStorage::vote_key_owner_key(env, &pubkey),
+        "GovStorage::vote_nonce_key" => GovStorage::vote_nonce_key(env, user),
+        "GovStorage::quorum_bps_key" => GovStorage::quorum_bps_key(env),
+        "GovStorage::timelock_key" => GovStorage::timelock_key(env),
+        "GovStorage::delegation_key" => (GovStorage::delegation_key(env), user.clone()),
+        "GovStorage::delegators_key" => GovStorage::delegators_key(env, user),
+        "GovStorage::vote_lock_key" => GovStorage::vote_lock_key(env, user),
+        "GovStorage::min_voting_period_key" => GovStorage::min_voting_period_key(env),
+        "GovStorage::cooldown_key" => GovStorage::cooldown_key(env),
+        "GovStorage::max_title_len_key" => GovStorage::max_title_len_key(env),
+        "GovStorage::last_proposal_key" => GovStorage::last_proposal_key(env, user),
+        "ParamHistory::key" => ParamHistory::key(env),
+        "IncentiveStorage::counter_key" => IncentiveStorage::counter_key(env),
+        "IncentiveStorage::program_key" => IncentiveStorage::program_key(env, id),
+        "IncentiveStorage::account_key" => IncentiveStorage::account_key(env, id, user),
+        "IncentiveStorage::active_key" => IncentiveStorage::active_key(env, asset),
+        "LiquidationRecordStorage::records_key" => LiquidationRecordStorage::records_key(env),
+        "LiquidationRecordStorage::counter_key" => LiquidationRecordStorage::counter_key(env),
+        "LiquidationRecordStorage::last_key" => LiquidationRecordStorage::last_key(env, user),
+        "ListingStorage::proposals_key" => ListingStorage::proposals_key(env),
+        "ListingStorage::counter_key" => ListingStorage::counter_key(env),
+        "ListingStorage::config_key" => ListingStorage::config_key(env),
+        "MigrationStorage::target_key" => MigrationStorage::target_key(env, asset),
+        "PenaltyStorage::config_key" => (PenaltyStorage::config_key(env), asset.clone()),
+        "PenaltyStorage::lots_key" => (PenaltyStorage::lots_key(env), user.clone()),
+        "ReasonCodes::key" => ReasonCodes::key(env),
+        "ChangeReason::reason_key" => ChangeReason::reason_key(env),
+        "ChangeReason::proposal_key" => ChangeReason::proposal_key(env),
+        "ReconcileStorage::last_key" => (ReconcileStorage::last_key(env), asset.clone()),
+        "ReconcileStorage::tolerance_key" => ReconcileStorage::tolerance_key(env),
+        "RecoveryStorage::approval_key" => RecoveryStorage::approval_key(env, user),
+        "RecoveryStorage::audit_key" => RecoveryStorage::audit_key(env),
+        "RecoveryStorage::bad_debt_key" => RecoveryStorage::bad_debt_key(env, asset),
+        "ReserveStorage::reserves_key" => (ReserveStorage::reserves_key(env), asset.clone()),
+        "RewardStorage::schedule_key" => RewardStorage::schedule_key(env, asset),
+        "RewardStorage::state_key" => RewardStorage::state_key(env, asset),
+        "TransferStorage::pending_key" => TransferStorage::pending_key(env, user),
+        "TransferStorage::nonce_key" => TransferStorage::nonce_key(env, user),
+        "TransferStorage::window_key" => TransferStorage::window_key(env),
+        "TwaStorage::key" => TwaStorage::key(env, asset),
+        "VotingStorage::power_key" => VotingStorage::power_key(env, user),
+        "VotingStorage::total_key" => VotingStorage::total_key(env),
+    }
+    #[cfg(feature = "amm")]
+    {
+        use crate::amm::{AMMStorage, PairKey};
+        use crate::buyback::Buyback;
+
+        let pair = PairKey {
+            asset_a: asset.clone(),
+            asset_b: other.clone(),
+        };
+        register! {
+            "AMMStorage::pairs_key" => AMMStorage::pairs_key(env),
+            "AMMStorage::pair_count_key" => AMMStorage::pair_count_key(env),
+            "AMMStorage::swap_history_key" => AMMStorage::swap_history_key(env),
+            "AMMStorage::pair_order_key" => AMMStorage::pair_order_key(env),
+            "AMMStorage::lp_shares_key" => AMMStorage::lp_shares_key(env, &pair, user),
+            "AMMStorage::lp_deposit_key" => AMMStorage::lp_deposit_key(env, &pair, user),
+            "Buyback::burn_address_key" => Buyback::burn_address_key(env),
+        }
+    }
+    keys.extend(oracle::OracleStorage::storage_keys(env, asset, &other));
+    keys
+}
+
+#[test]
+fn test_storage_keys_do_not_collide() {
+    use soroban_sdk::xdr::ToXdr;
+
+    let env = Env::default();
+    let user = Address::generate(&env);
+    let asset = Address::generate(&env);
+    let keys = storage_key_registry(&env, &user, &asset);
+
+    let mut seen: alloc::collections::BTreeMap<alloc::vec::Vec<u8>, &str> =
+        alloc::collections::BTreeMap::new();
+    for (name, key) in keys.iter() {
+        let bytes: alloc::vec::Vec<u8> = key.to_xdr(&env).iter().collect();
+        if let Some(first) = seen.insert(bytes, name) {
+            panic!("storage key collision: {} and {}", first, name);
+        }
+    }
+    assert_eq!(seen.len(), keys.len());
+}
+
+#[test]
+fn test_verify_storage_reports_malformed_key() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+
+    let (admin, contract_id, _token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+
+        let mut users = soroban_sdk::Vec::new(&env);
+        users.push_back(user.clone());
+        let issues = Contract::verify_storage(env.clone(), admin.to_string(), users.clone());
+        assert_eq!(issues.unwrap().len(), 0);
+
+        // Simulate a layout change by writing a wrong-shaped value
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, "risk_config"), &42i128);
+
+        let issues = Contract::verify_storage(env.clone(), admin.to_string(), users).unwrap();
+        assert_eq!(issues.len(), 1);
+        let issue = issues.get(0).unwrap();
+        assert_eq!(issue.key, Symbol::new(&env, "risk_config"));
+        assert_eq!(issue.user, None);
+    });
+}
+
+#[test]
+fn test_asset_distribution_tracks_bucket_crossings() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+
+        Contract::deposit_collateral(env.clone(), user.to_string(), 50).unwrap();
+        let dist = Contract::get_asset_distribution(env.clone(), token.clone());
+        assert_eq!(dist.collateral.under_100, 1);
+
+        // Grow across two boundaries
+        Contract::deposit_collateral(env.clone(), user.to_string(), 4950).unwrap();
+        let dist = Contract::get_asset_distribution(env.clone(), token.clone());
+        assert_eq!(dist.collateral.under_100, 0);
+        assert_eq!(dist.collateral.to_10k, 1);
+
+        Contract::borrow(env.clone(), user.to_string(), 500, 0).unwrap();
+        let dist = Contract::get_asset_distribution(env.clone(), token.clone());
+        assert_eq!(dist.debt.to_1k, 1);
+
+        // Shrink back down; repaying in full empties the debt bucket
+        Contract::repay(env.clone(), user.to_string(), 500).unwrap();
+        Contract::withdraw(env.clone(), user.to_string(), 4500, 0).unwrap();
+        let dist = Contract::get_asset_distribution(env.clone(), token.clone());
+        assert_eq!(dist.collateral.to_10k, 0);
+        assert_eq!(dist.collateral.to_1k, 1);
+        assert_eq!(dist.debt.to_1k, 0);
+        assert_eq!(
+            dist.collateral.under_100
+                + dist.collateral.to_1k
+                + dist.collateral.to_10k
+                + dist.collateral.to_100k
+                + dist.collateral.over_100k,
+            1
+        );
+    });
+}
+
+#[test]
+fn test_reconcile_balanced_and_injected_transfer() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = TestUtils::create_admin_address(&env);
+    let user = TestUtils::create_user_address(&env, 0);
+    let contract_id = env.register(Contract, ());
+    #[allow(deprecated)]
+    let token_id = env.register_contract(None, MockToken);
+    env.as_contract(&token_id, || {
+        MockToken::initialize(env.clone(), admin.clone());
+        MockToken::mint(env.clone(), user.clone(), 1_000_000);
+    });
+
+    env.as_contract(&contract_id, || {
+        Contract::initialize(env.clone(), admin.to_string()).unwrap();
+        Contract::set_primary_asset(env.clone(), admin.to_string(), token_id.clone()).unwrap();
+        TestUtils::verify_user(&env, &admin, &user);
+
+        Contract::deposit_collateral(env.clone(), user.to_string(), 5000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 1000, 0).unwrap();
+        Contract::repay(env.clone(), user.to_string(), 400).unwrap();
+        Contract::withdraw(env.clone(), user.to_string(), 1000, 0).unwrap();
+
+        let report = Contract::reconcile(env.clone(), token_id.clone()).unwrap();
+        assert_eq!(report.actual_balance, 3400);
+        assert_eq!(report.expected_balance, 3400);
+        assert_eq!(report.delta, 0);
+    });
+
+    // A direct transfer bypasses accounting
+    env.as_contract(&token_id, || {
+        MockToken::transfer(env.clone(), user.clone(), contract_id.clone(), 250);
+    });
+
+    env.as_contract(&contract_id, || {
+        let report = Contract::reconcile(env.clone(), token_id.clone()).unwrap();
+        assert_eq!(report.delta, 250);
+        let last = Contract::get_last_reconcile(env.clone(), token_id.clone()).unwrap();
+        assert_eq!(last, report);
+    });
+}
+
+#[test]
+fn test_reconcile_counts_liquidation_fee_in_insurance() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let liquidator = TestUtils::create_user_address(&env, 1);
+    let supplier = Address::generate(&env);
+    let (admin, contract_id, token) = TestUtils::setup_contract_with_token(
+        &env,
+        &[user.clone(), liquidator.clone(), supplier.clone()],
+    );
+    // Take back the unaccounted seed liquidity so the books start balanced
+    env.as_contract(&token, || {
+        MockToken::transfer(env.clone(), contract_id.clone(), admin.clone(), 1_000_000);
+    });
+
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        TestUtils::verify_user(&env, &admin, &liquidator);
+        TestUtils::verify_user(&env, &admin, &supplier);
+        Contract::deposit_collateral(env.clone(), supplier.to_string(), 5000).unwrap();
+        setup_liquidatable_position(&env, &admin, &user);
+        Contract::set_liquidation_protocol_fee(env.clone(), admin.to_string(), 500, None).unwrap();
+        assert_eq!(
+            Contract::reconcile(env.clone(), token.clone())
+                .unwrap()
+                .delta,
+            0
+        );
+
+        let result = Contract::liquidate(
+            env.clone(),
+            liquidator.to_string(),
+            user.to_string(),
+            200,
+            0,
+            false,
+            0,
+            false,
+        )
+        .unwrap();
+        assert!(result.protocol_fee > 0);
+
+        // The fee stays in the contract, earmarked for insurance
+        let report = Contract::reconcile(env.clone(), token.clone()).unwrap();
+        assert_eq!(report.delta, 0);
+        assert_eq!(report.expected_balance, report.actual_balance);
+    });
+}
+
+#[test]
+fn test_withdrawals_cannot_draw_on_earmarked_funds() {
+    use crate::reserves::ReserveStorage;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = TestUtils::create_admin_address(&env);
+    let user = TestUtils::create_user_address(&env, 0);
+    let contract_id = env.register(Contract, ());
+    #[allow(deprecated)]
+    let token_id = env.register_contract(None, MockToken);
+    env.as_contract(&token_id, || {
+        MockToken::initialize(env.clone(), admin.clone());
+        MockToken::mint(env.clone(), user.clone(), 1_000_000);
+    });
+
+    env.as_contract(&contract_id, || {
+        Contract::initialize(env.clone(), admin.to_string()).unwrap();
+        Contract::set_primary_asset(env.clone(), admin.to_string(), token_id.clone()).unwrap();
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 5000).unwrap();
+
+        // 4500 of the 5000 held belong to the treasury and the insurance fund
+        ReserveStorage::add(&env, &token_id, 3000);
+        EmergencyManager::credit_fund(&env, &token_id, 1500);
+        let liquidity = Contract::get_available_liquidity(env.clone(), token_id.clone());
+        assert_eq!(
+            (
+                liquidity.balance,
+                liquidity.earmarked(),
+                liquidity.available
+            ),
+            (5000, 4500, 500)
+        );
+
+        assert_eq!(
+            Contract::borrow(env.clone(), user.to_string(), 600, 0),
+            Err(ProtocolError::InsufficientLiquidity)
+        );
+        assert_eq!(
+            Contract::borrow_asset(env.clone(), user.to_string(), token_id.clone(), 600, 0),
+            Err(ProtocolError::InsufficientLiquidity)
+        );
+        assert_eq!(
+            Contract::withdraw(env.clone(), user.to_string(), 501, 0),
+            Err(ProtocolError::InsufficientLiquidity)
+        );
+        Contract::withdraw(env.clone(), user.to_string(), 500, 0).unwrap();
+        assert_eq!(
+            Contract::get_available_liquidity(env.clone(), token_id.clone()).available,
+            0
+        );
+    });
+}
+
+#[test]
+fn test_reconcile_rejects_earmarks_beyond_balance() {
+    use crate::reserves::ReserveStorage;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+        Contract::reconcile(env.clone(), token.clone()).unwrap();
+
+        // Reserves recorded without the tokens to back them
+        ReserveStorage::add(&env, &token, 1_001_001);
+        assert_eq!(
+            Contract::reconcile(env.clone(), token.clone()),
+            Err(ProtocolError::BalanceInvariantViolation)
+        );
+    });
+}
+
+#[test]
+fn test_accrual_catch_up_converges_over_multiple_calls() {
+    use crate::accrual::{AccrualManager, DEFAULT_MAX_ACCRUAL_GAP_SECS, INDEX_SCALE};
+    use soroban_sdk::{testutils::Events, TryFromVal};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+
+    let (admin, contract_id, _token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        env.ledger().with_mut(|l| l.timestamp = 1_000);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 5000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 1000, 0).unwrap();
+        let rate = InterestRateStorage::get_state(&env).current_borrow_rate;
+
+        // Two years without any interaction
+        let gap: u64 = 2 * 365 * 24 * 60 * 60;
+        env.ledger().with_mut(|l| l.timestamp = 1_000 + gap);
+
+        let first = Contract::accrue(env.clone());
+        assert!(first.last_accrual_time < 1_000 + gap);
+        assert!(AccrualManager::in_progress(&env));
+
+        let mut state = first;
+        let mut calls = 1;
+        while AccrualManager::in_progress(&env) {
+            state = Contract::accrue(env.clone());
+            calls += 1;
+        }
+        assert_eq!(calls, 3);
+        assert_eq!(state.last_accrual_time, 1_000 + gap);
+        // Each call short of the target reported how far it got
+        let partial = Symbol::new(&env, "accrual_catch_up_partial");
+        let partials = env
+            .events()
+            .all()
+            .iter()
+            .filter(|(_, topics, _)| {
+                topics
+                    .get(0)
+                    .and_then(|topic| Symbol::try_from_val(&env, &topic).ok())
+                    == Some(partial.clone())
+            })
+            .count();
+        assert_eq!(partials, 2);
+
+        // Closed form: compounding once per full chunk plus the remainder
+        let chunks = gap / DEFAULT_MAX_ACCRUAL_GAP_SECS;
+        let mut expected = INDEX_SCALE;
+        for _ in 0..chunks {
+            expected = AccrualManager::grow_index(expected, rate, DEFAULT_MAX_ACCRUAL_GAP_SECS);
+        }
+        expected = AccrualManager::grow_index(expected, rate, gap % DEFAULT_MAX_ACCRUAL_GAP_SECS);
+        assert_eq!(state.borrow_index, expected);
+        assert!(state.borrow_index > INDEX_SCALE);
+    });
+}
+
+#[test]
+fn test_accrual_catch_up_blocks_withdraw_allows_repay() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        env.ledger().with_mut(|l| l.timestamp = 1_000);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 5000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 1000, 0).unwrap();
+
+        env.ledger()
+            .with_mut(|l| l.timestamp = 1_000 + 4 * 365 * 24 * 60 * 60);
+
+        let result = Contract::withdraw(env.clone(), user.to_string(), 100, 0);
+        assert_eq!(result.unwrap_err(), ProtocolError::AccrualInProgress);
+        let result = Contract::borrow(env.clone(), user.to_string(), 100, 0);
+        assert_eq!(result.unwrap_err(), ProtocolError::AccrualInProgress);
+        let result = Contract::borrow_asset(env.clone(), user.to_string(), token.clone(), 100, 0);
+        assert_eq!(result.unwrap_err(), ProtocolError::AccrualInProgress);
+
+        assert!(Contract::repay(env.clone(), user.to_string(), 100).is_ok());
+
+        // Once caught up, withdrawals work again
+        while crate::accrual::AccrualManager::in_progress(&env) {
+            Contract::accrue(env.clone());
+        }
+        assert!(Contract::withdraw(env.clone(), user.to_string(), 100, 0).is_ok());
+    });
+}
+
+#[test]
+fn test_rate_changed_emitted_only_past_threshold() {
+    use crate::accrual::{AccrualManager, AccrualStorage};
+    use soroban_sdk::{testutils::Events, IntoVal};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    env.as_contract(&contract_id, || {
+        env.ledger().with_mut(|l| l.timestamp = 1_000);
+        let asset = TokenRegistry::primary_asset(&env);
+        let rate_changes = || {
+            env.events()
+                .all()
+                .iter()
+                .filter(|(_, topics, _)| {
+                    let name: Symbol = topics.get(0).unwrap().into_val(&env);
+                    name == Symbol::new(&env, "rate_changed")
+                })
+                .count()
+        };
+        let set_rate = |bps: i128| {
+            let mut state = InterestRateStorage::get_state(&env);
+            state.current_borrow_rate = bps * 10_000;
+            InterestRateStorage::save_state(&env, &state);
+            AccrualManager::accrue(&env);
+        };
+
+        // The first observation only seeds the baseline
+        set_rate(500);
+        assert_eq!(
+            AccrualStorage::get_last_reported_rate(&env, &asset),
+            Some(500)
+        );
+
+        // Wiggles within the default 25 bps band stay quiet
+        set_rate(510);
+        assert_eq!(rate_changes(), 0);
+        set_rate(490);
+        assert_eq!(rate_changes(), 0);
+        assert_eq!(
+            AccrualStorage::get_last_reported_rate(&env, &asset),
+            Some(500)
+        );
+
+        // A material move reports once, against the last reported rate
+        set_rate(530);
+        assert_eq!(rate_changes(), 1);
+        let (_, _, data) = env.events().all().last().unwrap();
+        let moved: (i128, i128) = data.into_val(&env);
+        assert_eq!(moved, (500, 530));
+        assert_eq!(
+            AccrualStorage::get_last_reported_rate(&env, &asset),
+            Some(530)
+        );
+
+        // With a zero threshold any move is reported
+        Contract::set_rate_event_threshold(env.clone(), admin.to_string(), 0).unwrap();
+        set_rate(531);
+        assert_eq!(rate_changes(), 2);
+        let (_, _, data) = env.events().all().last().unwrap();
+        let moved: (i128, i128) = data.into_val(&env);
+        assert_eq!(moved, (530, 531));
+        let result = Contract::set_rate_event_threshold(env.clone(), admin.to_string(), 10_001);
+        assert_eq!(result.unwrap_err(), ProtocolError::InvalidParameters);
+    });
+}
+
+#[test]
+fn test_zero_debt_fast_path_eligibility() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, _token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        env.ledger().with_mut(|l| l.timestamp = 1_000);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 5000).unwrap();
+        Contract::set_accrual_fresh_window(env.clone(), admin.to_string(), 60).unwrap();
+
+        // Within the window: zero debt reuses the stored rates, debt does not
+        env.ledger().with_mut(|l| l.timestamp = 1_030);
+        assert!(accrual::AccrualManager::is_fresh(&env));
+        accrual::AccrualManager::rates_for_position(&env, 0);
+        assert_eq!(
+            accrual::AccrualStorage::get_state(&env).last_accrual_time,
+            1_000
+        );
+        accrual::AccrualManager::rates_for_position(&env, 1);
+        assert_eq!(
+            accrual::AccrualStorage::get_state(&env).last_accrual_time,
+            1_030
+        );
+
+        // Past the window the zero-debt path accrues globally again
+        env.ledger().with_mut(|l| l.timestamp = 1_100);
+        assert!(!accrual::AccrualManager::is_fresh(&env));
+        accrual::AccrualManager::rates_for_position(&env, 0);
+        assert_eq!(
+            accrual::AccrualStorage::get_state(&env).last_accrual_time,
+            1_100
+        );
+
+        // A stale index still blocks zero-debt withdrawals during catch-up
+        env.ledger()
+            .with_mut(|l| l.timestamp = 1_100 + 2 * 365 * 24 * 60 * 60);
+        assert_eq!(
+            Contract::withdraw(env.clone(), user.to_string(), 100, 0).unwrap_err(),
+            ProtocolError::AccrualInProgress
+        );
+        Contract::accrue(env.clone());
+
+        // Fresh again: the withdrawal goes through and supply interest still accrues
+        assert!(Contract::withdraw(env.clone(), user.to_string(), 100, 0).is_ok());
+        let position = StateHelper::get_position(&env, &user).unwrap();
+        assert_eq!(position.collateral, 4900);
+    });
+}
+
+#[test]
+fn test_zero_debt_withdraw_fast_path_saves_budget() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, _token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        env.ledger().with_mut(|l| l.timestamp = 1_000);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 5000).unwrap();
+        Contract::withdraw(env.clone(), user.to_string(), 100, 0).unwrap();
+
+        // Stale index: full global update
+        env.ledger().with_mut(|l| l.timestamp = 2_000);
+        env.cost_estimate().budget().reset_unlimited();
+        Contract::withdraw(env.clone(), user.to_string(), 100, 0).unwrap();
+        let slow = env.cost_estimate().budget().cpu_instruction_cost();
+
+        // Same ledger, index already fresh: fast path
+        env.cost_estimate().budget().reset_unlimited();
+        Contract::withdraw(env.clone(), user.to_string(), 100, 0).unwrap();
+        let fast = env.cost_estimate().budget().cpu_instruction_cost();
+
+        assert!(fast < slow, "fast {} >= slow {}", fast, slow);
+    });
+}
+
+#[test]
+fn test_repay_plan_installments_until_completed() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        env.ledger().with_mut(|l| l.timestamp = 1_000);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 5000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 1000, 0).unwrap();
+
+        let day = 24 * 60 * 60;
+        Contract::create_repay_plan(env.clone(), user.to_string(), token.clone(), 400, day)
+            .unwrap();
+        assert_eq!(
+            Contract::execute_repay_plan(env.clone(), user.clone()),
+            Err(ProtocolError::InvalidOperation)
+        );
+
+        env.ledger().with_mut(|l| l.timestamp = 1_000 + day);
+        assert_eq!(
+            Contract::execute_repay_plan(env.clone(), user.clone()),
+            Ok(autorepay::RepayPlanOutcome::Repaid)
+        );
+        env.ledger().with_mut(|l| l.timestamp = 1_000 + 2 * day);
+        assert_eq!(
+            Contract::execute_repay_plan(env.clone(), user.clone()),
+            Ok(autorepay::RepayPlanOutcome::Repaid)
+        );
+        let position = StateHelper::get_position(&env, &user).unwrap();
+        assert_eq!((position.collateral, position.debt), (4200, 200));
+        let plan = Contract::get_repay_plan(env.clone(), user.clone()).unwrap();
+        assert_eq!(plan.installments_paid, 2);
+        assert_eq!(plan.next_due, 1_000 + 3 * day);
+
+        // The last installment is capped at the remaining debt and ends the plan
+        env.ledger().with_mut(|l| l.timestamp = 1_000 + 3 * day);
+        assert_eq!(
+            Contract::execute_repay_plan(env.clone(), user.clone()),
+            Ok(autorepay::RepayPlanOutcome::Completed)
+        );
+        let position = StateHelper::get_position(&env, &user).unwrap();
+        assert_eq!((position.collateral, position.debt), (4000, 0));
+        assert!(Contract::get_repay_plan(env.clone(), user.clone()).is_none());
+    });
+}
+
+#[test]
+fn test_repay_plan_skips_without_supply_and_ends_on_repay() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        env.ledger().with_mut(|l| l.timestamp = 1_000);
+        Contract::set_min_collateral_ratio(env.clone(), admin.to_string(), 50).unwrap();
+        Contract::deposit_collateral(env.clone(), user.to_string(), 500).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 1000, 0).unwrap();
+
+        Contract::create_repay_plan(env.clone(), user.to_string(), token.clone(), 800, 100)
+            .unwrap();
+        env.ledger().with_mut(|l| l.timestamp = 1_100);
+        assert_eq!(
+            Contract::execute_repay_plan(env.clone(), user.clone()),
+            Ok(autorepay::RepayPlanOutcome::Skipped)
+        );
+        let position = StateHelper::get_position(&env, &user).unwrap();
+        assert_eq!((position.collateral, position.debt), (500, 1000));
+        let plan = Contract::get_repay_plan(env.clone(), user.clone()).unwrap();
+        assert_eq!((plan.installments_skipped, plan.next_due), (1, 1_200));
+
+        // Debt cleared outside the plan ends it at the next execution
+        Contract::repay(env.clone(), user.to_string(), 1000).unwrap();
+        env.ledger().with_mut(|l| l.timestamp = 1_200);
+        assert_eq!(
+            Contract::execute_repay_plan(env.clone(), user.clone()),
+            Ok(autorepay::RepayPlanOutcome::Completed)
+        );
+        assert!(Contract::get_repay_plan(env.clone(), user.clone()).is_none());
+        assert_eq!(
+            Contract::cancel_repay_plan(env.clone(), user.to_string()),
+            Err(ProtocolError::NotFound)
+        );
+    });
+}
+
+/// Rebuild the state commitment using only public getters
+fn commitment_from_getters(env: &Env) -> BytesN<32> {
+    use soroban_sdk::xdr::ToXdr;
+
+    let mut assets = Vec::new(env);
+    for (_, asset) in Contract::list_registered_assets(env.clone()).iter() {
+        if assets
+            .iter()
+            .any(|a: commitment::AssetCommitment| a.asset == asset)
+        {
+            continue;
+        }
+        let (total_supplied, total_borrowed) =
+            match Contract::get_asset_state(env.clone(), asset.clone()) {
+                Ok(state) => (state.total_supplied, state.total_borrowed),
+                Err(_) => (0, 0),
+            };
+        assets.push_back(commitment::AssetCommitment {
+            reserves: Contract::get_protocol_reserves(env.clone(), asset.clone()),
+            asset,
+            total_supplied,
+            total_borrowed,
+        });
+    }
+    let accrual = Contract::get_accrual_state(env.clone());
+    let (_, _, pause_borrow, pause_deposit, pause_withdraw, pause_liquidate) =
+        Contract::get_risk_config(env.clone()).unwrap();
+    let state = commitment::CommittedState {
+        version: Contract::get_capabilities(env.clone()).version,
+        assets,
+        borrow_index: accrual.borrow_index,
+        accrued_to: accrual.last_accrual_time,
+        emergency_status: Contract::get_emergency_state(env.clone()).unwrap().status,
+        pause_borrow,
+        pause_deposit,
+        pause_withdraw,
+        pause_liquidate,
+    };
+    env.crypto().sha256(&state.to_xdr(env)).to_bytes()
+}
+
+#[test]
+fn test_state_commitment_reproducible_and_tracks_components() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        env.ledger().with_mut(|l| l.timestamp = 1_000);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 5000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 1000, 0).unwrap();
+
+        let mut seen: Vec<BytesN<32>> = Vec::new(&env);
+        let mut check = |env: &Env| {
+            let hash = Contract::state_commitment(env.clone());
+            assert_eq!(hash, commitment_from_getters(env));
+            assert!(!seen.contains(&hash));
+            seen.push_back(hash);
+        };
+        check(&env);
+
+        // Totals
+        Contract::deposit_collateral(env.clone(), user.to_string(), 100).unwrap();
+        check(&env);
+        // Reserves
+        reserves::ReserveStorage::add(&env, &token, 50);
+        check(&env);
+        // Borrow index
+        env.ledger().with_mut(|l| l.timestamp = 100_000);
+        Contract::accrue(env.clone());
+        check(&env);
+        // Pause switches
+        Contract::set_pause_switches(
+            env.clone(),
+            admin.to_string(),
+            false,
+            true,
+            false,
+            false,
+            None,
+        )
+        .unwrap();
+        check(&env);
+        // Emergency status
+        Contract::trigger_emergency_pause(env.clone(), admin.to_string(), None, None).unwrap();
+        check(&env);
+
+        env.ledger().with_mut(|l| l.sequence_number = 77);
+        let stored = Contract::commit_state(env.clone());
+        assert_eq!(stored.hash, commitment_from_getters(&env));
+        assert_eq!(stored.ledger_seq, 77);
+        assert_eq!(Contract::get_state_commitment(env.clone()), Some(stored));
+    });
+}
+
+fn setup_liquidatable_position(env: &Env, admin: &Address, user: &Address) {
+    Contract::set_min_collateral_ratio(env.clone(), admin.to_string(), 50).unwrap();
+    Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+    Contract::borrow(env.clone(), user.to_string(), 1000, 0).unwrap();
+    Contract::set_min_collateral_ratio(env.clone(), admin.to_string(), 150).unwrap();
+}
+
+#[test]
+fn test_deadline_passed_between_simulation_and_execution() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let liquidator = TestUtils::create_user_address(&env, 1);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, &[user.clone(), liquidator.clone()]);
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        TestUtils::verify_user(&env, &admin, &liquidator);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 2000).unwrap();
+    });
+    env.ledger().with_mut(|l| l.timestamp = 1_000);
+    let deadline = 1_060;
+
+    // Simulated at 1_000, executed after the deadline: nothing moves
+    let client = ContractClient::new(&env, &contract_id);
+    let balance =
+        |who: &Address| env.as_contract(&token, || MockToken::balance(env.clone(), who.clone()));
+    let before = (client.get_position(&user.to_string()), balance(&user));
+    env.ledger().with_mut(|l| l.timestamp = deadline + 1);
+    assert_eq!(
+        client.try_withdraw(&user.to_string(), &500, &deadline),
+        Err(Ok(ProtocolError::DeadlineExceeded))
+    );
+    assert_eq!(
+        client.try_borrow(&user.to_string(), &500, &deadline),
+        Err(Ok(ProtocolError::DeadlineExceeded))
+    );
+    assert_eq!(
+        (client.get_position(&user.to_string()), balance(&user)),
+        before
+    );
+
+    // The deadline itself is still in time; 0 means no deadline
+    env.ledger().with_mut(|l| l.timestamp = deadline);
+    client.withdraw(&user.to_string(), &500, &deadline);
+    client.withdraw(&user.to_string(), &500, &0);
+    assert_eq!(balance(&user), before.1 + 1000);
+
+    env.as_contract(&contract_id, || {
+        Contract::set_min_collateral_ratio(env.clone(), admin.to_string(), 50).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 1000, 0).unwrap();
+        Contract::set_min_collateral_ratio(env.clone(), admin.to_string(), 150).unwrap();
+        let liquidate = |deadline_ts: u64| {
+            Contract::liquidate(
+                env.clone(),
+                liquidator.to_string(),
+                user.to_string(),
+                100,
+                0,
+                false,
+                deadline_ts,
+                false,
+            )
+        };
+        assert_eq!(
+            liquidate(deadline - 1),
+            Err(ProtocolError::DeadlineExceeded)
+        );
+        assert_eq!(liquidate(deadline).unwrap().deadline_ts, deadline);
+    });
+}
+
+#[test]
+fn test_multicall_checks_health_once_and_reverts_on_failure() {
+    use crate::multicall::UserOp;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 2000).unwrap();
+    });
+    let client = ContractClient::new(&env, &contract_id);
+    let balance = || env.as_contract(&token, || MockToken::balance(env.clone(), user.clone()));
+
+    // Borrowing 1500 against 2000 dips below 150% until the deposit lands
+    let ops = Vec::from_array(&env, [UserOp::Borrow(1500), UserOp::Deposit(1000)]);
+    client.multicall(&user.to_string(), &ops, &0);
+    let position = client.get_position(&user.to_string());
+    assert_eq!((position.0, position.1), (3000, 1500));
+
+    // Ends at 4000 / 4000: every step is rolled back
+    let before = (client.get_position(&user.to_string()), balance());
+    let ops = Vec::from_array(&env, [UserOp::Deposit(1000), UserOp::Borrow(2500)]);
+    assert_eq!(
+        client.try_multicall(&user.to_string(), &ops, &0),
+        Err(Ok(ProtocolError::InsufficientCollateralRatio))
+    );
+    assert_eq!((client.get_position(&user.to_string()), balance()), before);
+
+    let mut too_many = Vec::new(&env);
+    for _ in 0..7 {
+        too_many.push_back(UserOp::Repay(1));
+    }
+    assert_eq!(
+        client.try_multicall(&user.to_string(), &too_many, &0),
+        Err(Ok(ProtocolError::InvalidParameters))
+    );
+
+    // The guard is released afterwards
+    client.repay(&user.to_string(), &500);
+}
+#[test]
+fn test_collateral_backing_debt_keeps_earning_supply_interest() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, _token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        env.ledger().with_mut(|l| l.timestamp = 1_000);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 10_000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 5_000, 0).unwrap();
+
+        env.ledger()
+            .with_mut(|l| l.timestamp = 1_000 + 365 * 24 * 60 * 60);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1).unwrap();
+
+        let position = StateHelper::get_position(&env, &user).unwrap();
+        assert!(position.supply_interest > 0);
+        assert!(position.borrow_interest > 0);
+        assert_eq!(position.collateral, 10_001);
+    });
+}
+
+#[test]
+fn test_liquidate_receive_as_shares_matches_transfer_value() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let liquidator = TestUtils::create_user_address(&env, 1);
+
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, &[user.clone(), liquidator.clone()]);
+    let (raw, shares) = env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        TestUtils::verify_user(&env, &admin, &liquidator);
+        setup_liquidatable_position(&env, &admin, &user);
+
+        let raw = Contract::liquidate(
+            env.clone(),
+            liquidator.to_string(),
+            user.to_string(),
+            200,
+            0,
+            false,
+            0,
+            false,
+        )
+        .unwrap();
+        assert!(!raw.received_as_shares);
+
+        let shares = Contract::liquidate(
+            env.clone(),
+            liquidator.to_string(),
+            user.to_string(),
+            200,
+            0,
+            true,
+            0,
+            false,
+        )
+        .unwrap();
+        assert!(shares.received_as_shares);
+        assert!(!shares.share_cap_fallback);
+
+        // Credited shares are the liquidator's collateral position
+        let position = Contract::get_position(env.clone(), liquidator.to_string()).unwrap();
+        assert_eq!(position.0, shares.collateral_seized);
+        (raw, shares)
+    });
+    assert_eq!(raw.collateral_seized, shares.collateral_seized);
+
+    // Only the raw-mode seizure reached the liquidator's wallet
+    env.as_contract(&token, || {
+        let balance = MockToken::balance(env.clone(), liquidator.clone());
+        assert_eq!(
+            balance,
+            1_000_000 - raw.debt_repaid - shares.debt_repaid + raw.collateral_seized
+        );
+    });
+}
+
+#[test]
+fn test_liquidate_receive_as_shares_falls_back_over_cap() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let liquidator = TestUtils::create_user_address(&env, 1);
+
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, &[user.clone(), liquidator.clone()]);
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        TestUtils::verify_user(&env, &admin, &liquidator);
+        setup_liquidatable_position(&env, &admin, &user);
+
+        // Cap lowered below current supply: no new shares can be minted
+        Contract::set_supply_cap(env.clone(), admin.to_string(), token.clone(), 500).unwrap();
+
+        let result = Contract::liquidate(
+            env.clone(),
+            liquidator.to_string(),
+            user.to_string(),
+            200,
+            0,
+            true,
+            0,
+            false,
+        )
+        .unwrap();
+        assert!(!result.received_as_shares);
+        assert!(result.share_cap_fallback);
+        assert!(Contract::get_position(env.clone(), liquidator.to_string()).is_err());
+    });
+}
+
+#[test]
+fn test_liquidate_receive_as_shares_falls_back_over_global_tvl_cap() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let liquidator = TestUtils::create_user_address(&env, 1);
+
+    let (admin, contract_id, _token) =
+        TestUtils::setup_contract_with_token(&env, &[user.clone(), liquidator.clone()]);
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        TestUtils::verify_user(&env, &admin, &liquidator);
+        setup_liquidatable_position(&env, &admin, &user);
+
+        // Global cap already exhausted: the seized share cannot stay as supply
+        crate::caps::CapStorage::set_global_tvl_cap(&env, 1).unwrap();
+
+        let result = Contract::liquidate(
+            env.clone(),
+            liquidator.to_string(),
+            user.to_string(),
+            200,
+            0,
+            true,
+            0,
+            false,
+        )
+        .unwrap();
+        assert!(!result.received_as_shares);
+        assert!(result.share_cap_fallback);
+        assert!(Contract::get_position(env.clone(), liquidator.to_string()).is_err());
+    });
+}
+
+fn setup_listing(env: &Env) -> (Address, Address, Address, Address, Address) {
+    let proposer = TestUtils::create_user_address(env, 0);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(env, core::slice::from_ref(&proposer));
+    let treasury = Address::generate(env);
+    env.as_contract(&contract_id, || {
+        Contract::set_treasury(env.clone(), admin.to_string(), treasury.clone()).unwrap();
+        Contract::set_listing_config(
+            env.clone(),
+            admin.to_string(),
+            crate::listing::ListingConfig {
+                bond_asset: token.clone(),
+                min_bond: 1000,
+                slash_bps: 2000,
+                expiry_secs: 1000,
+            },
+        )
+        .unwrap();
+    });
+    (admin, contract_id, token, proposer, treasury)
+}
+
+fn propose_test_listing(env: &Env, proposer: &Address) -> (u64, Address) {
+    let new_token = Address::generate(env);
+    let id = Contract::propose_listing(
+        env.clone(),
+        proposer.to_string(),
+        crate::listing::ListingTokenInfo {
+            key: Symbol::new(env, "newasset"),
+            token: new_token.clone(),
+        },
+        crate::listing::ListingRiskParams {
+            collateral_factor: 50_000_000,
+            supply_cap: 10_000,
+        },
+        5000,
+    )
+    .unwrap();
+    (id, new_token)
+}
+
+#[test]
+fn test_listing_approval_lists_asset_and_returns_bond() {
+    use crate::governance::Action;
+    use crate::listing::ListingStatus;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let (admin, contract_id, token, proposer, _treasury) = setup_listing(&env);
+
+    env.as_contract(&contract_id, || {
+        let (id, new_token) = propose_test_listing(&env, &proposer);
+        govern(&env, &admin, Action::ApproveListing(id)).unwrap();
+
+        let listing = Contract::get_listing(env.clone(), id).unwrap();
+        assert_eq!(listing.status, ListingStatus::Approved);
+        assert_eq!(
+            Contract::get_registered_asset(env.clone(), Symbol::new(&env, "newasset")).unwrap(),
+            Some(new_token.clone())
+        );
+        assert_eq!(
+            crate::caps::CapStorage::get_supply_cap(&env, &new_token),
+            10_000
+        );
+
+        // Decided proposals cannot be decided again
+        let again = govern(&env, &admin, Action::RejectListing(id));
+        assert_eq!(again.unwrap_err(), ProtocolError::InvalidOperation);
+    });
+
+    env.as_contract(&token, || {
+        assert_eq!(MockToken::balance(env.clone(), proposer.clone()), 1_000_000);
+    });
+}
+
+#[test]
+fn test_listing_rejection_slashes_partial_bond() {
+    use crate::governance::Action;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let (admin, contract_id, token, proposer, treasury) = setup_listing(&env);
+
+    env.as_contract(&contract_id, || {
+        let (id, _) = propose_test_listing(&env, &proposer);
+        govern(&env, &admin, Action::RejectListing(id)).unwrap();
+        assert!(
+            Contract::get_registered_asset(env.clone(), Symbol::new(&env, "newasset"))
+                .unwrap()
+                .is_none()
+        );
+    });
+
+    env.as_contract(&token, || {
+        assert_eq!(MockToken::balance(env.clone(), treasury.clone()), 1000);
+        assert_eq!(
+            MockToken::balance(env.clone(), proposer.clone()),
+            1_000_000 - 1000
+        );
+    });
+}
+
+#[test]
+fn test_listing_expiry_claimable_by_proposer() {
+    use crate::listing::ListingStatus;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let (admin, contract_id, token, proposer, treasury) = setup_listing(&env);
+
+    env.as_contract(&contract_id, || {
+        env.ledger().with_mut(|l| l.timestamp = 100);
+        let (id, _) = propose_test_listing(&env, &proposer);
+
+        // Not yet expired, and only the proposer may claim
+        let early = Contract::claim_expired_listing(env.clone(), proposer.to_string(), id);
+        assert_eq!(early.unwrap_err(), ProtocolError::InvalidOperation);
+        env.ledger().with_mut(|l| l.timestamp = 1_100);
+        let other = Contract::claim_expired_listing(env.clone(), admin.to_string(), id);
+        assert_eq!(other.unwrap_err(), ProtocolError::Unauthorized);
+
+        let closed =
+            Contract::claim_expired_listing(env.clone(), proposer.to_string(), id).unwrap();
+        assert_eq!(closed.status, ListingStatus::Expired);
+    });
+
+    env.as_contract(&token, || {
+        assert_eq!(MockToken::balance(env.clone(), treasury.clone()), 1000);
+        assert_eq!(
+            MockToken::balance(env.clone(), proposer.clone()),
+            1_000_000 - 1000
+        );
+    });
+}
+
+#[test]
+fn test_capabilities_reflect_feature_flags() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = TestUtils::create_admin_address(&env);
+    let contract_id = env.register(Contract, ());
+    env.as_contract(&contract_id, || {
+        Contract::initialize(env.clone(), admin.to_string()).unwrap();
+
+        let caps = Contract::get_capabilities(env.clone());
+        assert_eq!(caps.version, crate::capabilities::CONTRACT_VERSION);
+        assert_eq!(caps.enabled_flags.len(), 0);
+        assert_eq!(caps.oracle_modes.get(1).unwrap(), Symbol::new(&env, "twap"));
+        let gated = if cfg!(feature = "amm") {
+            0
+        } else {
+            crate::governance::Action::AMM_KINDS.len()
+        };
+        assert_eq!(
+            caps.governance_actions.len(),
+            (crate::governance::Action::KINDS.len() - gated) as u32
+        );
+
+        let flag = Symbol::new(&env, "diagnostics_enabled");
+        Contract::set_feature_flag(env.clone(), admin.to_string(), flag.clone(), true).unwrap();
+        let caps = Contract::get_capabilities(env.clone());
+        assert_eq!(caps.enabled_flags.len(), 1);
+        assert_eq!(caps.enabled_flags.get(0).unwrap(), flag.clone());
+
+        Contract::set_feature_flag(env.clone(), admin.to_string(), flag, false).unwrap();
+        assert_eq!(
+            Contract::get_capabilities(env.clone()).enabled_flags.len(),
+            0
+        );
+
+        let unknown = Contract::set_feature_flag(
+            env.clone(),
+            admin.to_string(),
+            Symbol::new(&env, "made_up"),
+            true,
+        );
+        assert_eq!(unknown.unwrap_err(), ProtocolError::InvalidParameters);
+    });
+}
+
+#[test]
+fn test_origination_buffer_applies_only_to_new_borrows() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let other = TestUtils::create_user_address(&env, 1);
+    let (admin, contract_id, _token) =
+        TestUtils::setup_contract_with_token(&env, &[user.clone(), other.clone()]);
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        TestUtils::verify_user(&env, &admin, &other);
+
+        // Opened at 155% before any buffer exists
+        Contract::deposit_collateral(env.clone(), other.to_string(), 1550).unwrap();
+        Contract::borrow(env.clone(), other.to_string(), 1000, 0).unwrap();
+
+        // 150% maintenance + 10% origination buffer
+        Contract::set_origination_buffer(env.clone(), admin.to_string(), 1000).unwrap();
+
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1600).unwrap();
+        let between = Contract::borrow(env.clone(), user.to_string(), 1010, 0);
+        assert_eq!(
+            between.unwrap_err(),
+            ProtocolError::InsufficientCollateralRatio
+        );
+        Contract::borrow(env.clone(), user.to_string(), 1000, 0).unwrap();
+
+        let data = Contract::get_user_account_data(env.clone(), user.to_string()).unwrap();
+        assert_eq!(data.collateral_ratio_bps, 16000);
+        assert_eq!(data.ltv_bps, 15000);
+        assert_eq!(data.liquidation_threshold_bps, 15000);
+        assert_eq!(data.origination_ratio_bps, 16000);
+
+        // The older position sits between the thresholds but stays healthy
+        let result = Contract::liquidate(
+            env.clone(),
+            user.to_string(),
+            other.to_string(),
+            100,
+            0,
+            false,
+            0,
+            false,
+        );
+        assert_eq!(
+            result.unwrap_err(),
+            ProtocolError::NotEligibleForLiquidation
+        );
+    });
+}
+
+#[test]
+fn test_band_between_ltv_and_liquidation_threshold() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let liquidator = TestUtils::create_user_address(&env, 1);
+    let (admin, contract_id, _token) =
+        TestUtils::setup_contract_with_token(&env, &[user.clone(), liquidator.clone()]);
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        TestUtils::verify_user(&env, &admin, &liquidator);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 3000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 2000, 0).unwrap();
+
+        // The threshold may not exceed the LTV bound, nor the bound drop below it
+        assert_eq!(
+            Contract::set_liquidation_threshold(env.clone(), admin.to_string(), 15001),
+            Err(ProtocolError::InvalidInput)
+        );
+        Contract::set_min_collateral_ratio(env.clone(), admin.to_string(), 200).unwrap();
+        Contract::set_liquidation_threshold(env.clone(), admin.to_string(), 15000).unwrap();
+        assert_eq!(
+            Contract::set_min_collateral_ratio(env.clone(), admin.to_string(), 149),
+            Err(ProtocolError::InvalidInput)
+        );
+
+        // At 150%, inside [threshold, LTV): no new borrows or withdrawals, and
+        // sitting exactly on the threshold is not yet liquidatable
+        let data = Contract::get_user_account_data(env.clone(), user.to_string()).unwrap();
+        assert_eq!(
+            (data.ltv_bps, data.liquidation_threshold_bps),
+            (20000, 15000)
+        );
+        assert_eq!((data.ltv_health_bps, data.health_factor_bps), (7500, 10000));
+        assert_eq!(
+            Contract::borrow(env.clone(), user.to_string(), 1, 0),
+            Err(ProtocolError::InsufficientCollateralRatio)
+        );
+        assert!(Contract::withdraw(env.clone(), user.to_string(), 1, 0).is_err());
+        let liquidate = || {
+            Contract::liquidate(
+                env.clone(),
+                liquidator.to_string(),
+                user.to_string(),
+                100,
+                0,
+                false,
+                0,
+                false,
+            )
+        };
+        assert_eq!(
+            liquidate().unwrap_err(),
+            ProtocolError::NotEligibleForLiquidation
+        );
+
+        // One bps above the position's ratio it becomes liquidatable
+        Contract::set_liquidation_threshold(env.clone(), admin.to_string(), 15001).unwrap();
+        liquidate().unwrap();
+    });
+}
+
+#[test]
+fn test_emission_schedule_integrates_across_rate_change() {
+    use crate::governance::Action;
+    use crate::rewards::EmissionSegment;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (admin, contract_id, token) = TestUtils::setup_contract_with_token(&env, &[]);
+    env.ledger().with_mut(|l| l.timestamp = 500);
+    env.as_contract(&contract_id, || {
+        let mut schedule = Vec::new(&env);
+        schedule.push_back(EmissionSegment {
+            start_ts: 1000,
+            rate_per_sec: 10,
+        });
+        schedule.push_back(EmissionSegment {
+            start_ts: 2000,
+            rate_per_sec: 20,
+        });
+        govern(
+            &env,
+            &admin,
+            Action::SetEmissionSchedule(token.clone(), schedule.clone()),
+        )
+        .unwrap();
+        assert_eq!(
+            Contract::get_emission_schedule(env.clone(), token.clone()),
+            schedule
+        );
+
+        // Nothing before t=1000, 1000s at 10/s, then 500s at 20/s
+        env.ledger().with_mut(|l| l.timestamp = 2500);
+        Contract::accrue(env.clone());
+        let state = Contract::accrue_rewards(env.clone(), token.clone());
+        assert_eq!(state.emitted, 1000 * 10 + 500 * 20);
+        assert_eq!(state.last_update, 2500);
+
+        let mut unordered = Vec::new(&env);
+        unordered.push_back(EmissionSegment {
+            start_ts: 3000,
+            rate_per_sec: 5,
+        });
+        unordered.push_back(EmissionSegment {
+            start_ts: 3000,
+            rate_per_sec: 1,
+        });
+        let result = govern(
+            &env,
+            &admin,
+            Action::SetEmissionSchedule(token.clone(), unordered),
+        );
+        assert_eq!(result.unwrap_err(), ProtocolError::InvalidParameters);
+    });
+}
+
+#[test]
+fn test_cap_group_limits_combined_borrows() {
+    use crate::oracle::{Oracle, OracleSource, PRICE_SCALE};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, token_a) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+
+    #[allow(deprecated)]
+    let token_b = env.register_contract(None, MockToken);
+    env.as_contract(&token_b, || {
+        MockToken::mint(env.clone(), contract_id.clone(), 1_000_000);
+    });
+    let oracle = env.register(MockPriceOracle, ());
+    env.as_contract(&oracle, || {
+        MockPriceOracle::set_price(env.clone(), token_a.clone(), PRICE_SCALE);
+        MockPriceOracle::set_price(env.clone(), token_b.clone(), 2 * PRICE_SCALE);
+    });
+
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        for asset in [token_a.clone(), token_b.clone()] {
+            Oracle::set_source(
+                &env,
+                &admin,
+                &asset,
+                OracleSource::new(oracle.clone(), 1, 0),
+            )
+            .unwrap();
+        }
+        Contract::set_borrow_cap(env.clone(), admin.to_string(), token_b.clone(), 5000).unwrap();
+
+        let group_id = Symbol::new(&env, "stables");
+        let mut members = Vec::new(&env);
+        members.push_back(token_a.clone());
+        members.push_back(token_b.clone());
+        Contract::set_cap_group(
+            env.clone(),
+            admin.to_string(),
+            group_id.clone(),
+            members,
+            3000,
+        )
+        .unwrap();
+
+        Contract::deposit_collateral(env.clone(), user.to_string(), 10_000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 2000, 0).unwrap();
+
+        // 600 of B is worth 1200: under B's own cap but over the group's
+        let result = Contract::borrow_asset(env.clone(), user.to_string(), token_b.clone(), 600, 0);
+        assert_eq!(result.unwrap_err(), ProtocolError::GroupCapExceeded);
+
+        Contract::borrow_asset(env.clone(), user.to_string(), token_b.clone(), 400, 0).unwrap();
+        let group = Contract::get_cap_group(env.clone(), group_id).unwrap();
+        assert_eq!(group.total_usd, 2000);
+    });
+
+    env.as_contract(&token_b, || {
+        assert_eq!(MockToken::balance(env.clone(), user.clone()), 400);
+    });
+}
+
+#[test]
+fn test_oracle_freeze_serves_locked_price_then_fails_closed() {
+    use crate::oracle::{Oracle, OracleSource, PRICE_SCALE};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let guardian = Address::generate(&env);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    let oracle = env.register(MockPriceOracle, ());
+    env.as_contract(&oracle, || {
+        MockPriceOracle::set_price(env.clone(), token.clone(), PRICE_SCALE);
+    });
+
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        env.ledger().with_mut(|l| l.timestamp = 1_000);
+        Contract::set_guardian(env.clone(), admin.to_string(), guardian.clone()).unwrap();
+        Oracle::set_source(
+            &env,
+            &admin,
+            &token,
+            OracleSource::new(oracle.clone(), 1, 0),
+        )
+        .unwrap();
+        // Deliberate price jumps: keep the deviation breaker out of the way
+        crate::oracle::OracleStorage::set_max_deviation_bps(&env, 1_000_000);
+        crate::oracle::OracleStorage::set_heartbeat_ttl(&env, &admin, 1_000_000).unwrap();
+        let mut members = Vec::new(&env);
+        members.push_back(token.clone());
+        Contract::set_cap_group(
+            env.clone(),
+            admin.to_string(),
+            Symbol::new(&env, "primary"),
+            members,
+            3000,
+        )
+        .unwrap();
+
+        Contract::deposit_collateral(env.clone(), user.to_string(), 10_000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 1000, 0).unwrap();
+
+        Contract::freeze_oracle(env.clone(), guardian.to_string(), Some(token.clone()), None)
+            .unwrap();
+        Contract::freeze_oracle(env.clone(), guardian.to_string(), None, None).unwrap();
+        assert_eq!(
+            Contract::freeze_oracle(env.clone(), guardian.to_string(), None, None).unwrap_err(),
+            ProtocolError::InvalidOperation
+        );
+        assert_eq!(
+            Contract::freeze_oracle(env.clone(), user.to_string(), Some(token.clone()), None)
+                .unwrap_err(),
+            ProtocolError::Unauthorized
+        );
+    });
+
+    // The feed jumps tenfold, but borrows keep using the locked price
+    env.as_contract(&oracle, || {
+        MockPriceOracle::set_price(env.clone(), token.clone(), 10 * PRICE_SCALE);
+    });
+    env.as_contract(&contract_id, || {
+        env.ledger().with_mut(|l| l.timestamp = 1_600);
+        let details = Oracle::aggregate_price_detailed(&env, &token).unwrap();
+        assert!(details.frozen);
+        assert_eq!(details.price, PRICE_SCALE);
+        Contract::borrow(env.clone(), user.to_string(), 500, 0).unwrap();
+
+        // Past the window pricing fails closed
+        env.ledger().with_mut(|l| l.timestamp = 1_000 + 3_601);
+        assert_eq!(
+            Contract::borrow(env.clone(), user.to_string(), 100, 0).unwrap_err(),
+            ProtocolError::OracleFailure
+        );
+        assert_eq!(
+            Contract::unfreeze_oracle(env.clone(), guardian.to_string(), None, None).unwrap_err(),
+            ProtocolError::Unauthorized
+        );
+
+        // Lifting both freezes restores live aggregation
+        Contract::unfreeze_oracle(env.clone(), admin.to_string(), Some(token.clone()), None)
+            .unwrap();
+        assert_eq!(Oracle::aggregate_price(&env, &token), None);
+        Contract::unfreeze_oracle(env.clone(), admin.to_string(), None, None).unwrap();
+        assert_eq!(
+            Oracle::aggregate_price(&env, &token),
+            Some(10 * PRICE_SCALE)
+        );
+        assert_eq!(
+            Contract::borrow(env.clone(), user.to_string(), 100, 0).unwrap_err(),
+            ProtocolError::GroupCapExceeded
+        );
+    });
+}
+
+#[test]
+fn test_risk_snapshots_on_transitions_and_interval() {
+    use crate::oracle::{OracleStorage, PRICE_SCALE};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        env.ledger().with_mut(|l| l.timestamp = 1_000);
+        Contract::set_risk_snapshot_interval(env.clone(), admin.to_string(), 100).unwrap();
+        Contract::set_min_collateral_ratio(env.clone(), admin.to_string(), 50).unwrap();
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 1000, 0).unwrap();
+        assert!(Contract::get_risk_snapshots(env.clone(), user.clone()).is_empty());
+
+        let set_price = |price: i128| {
+            let mut cache = OracleStorage::get_price_cache(&env);
+            cache.set(token.clone(), (price, env.ledger().timestamp()));
+            OracleStorage::put_price_cache(&env, &cache);
+        };
+        let at = |ts: u64| env.ledger().with_mut(|l| l.timestamp = ts);
+        let set_ratio = |ratio: i128| {
+            Contract::set_min_collateral_ratio(env.clone(), admin.to_string(), ratio).unwrap();
+        };
+        let check = || Contract::check_position_risk(env.clone(), user.clone()).unwrap();
+
+        // Unhealthy transition, then one more snapshot per interval
+        set_price(PRICE_SCALE);
+        set_ratio(150);
+        assert!(check());
+        at(1_050);
+        assert!(check());
+        at(1_100);
+        assert!(check());
+        assert_eq!(
+            Contract::get_risk_snapshots(env.clone(), user.clone()).len(),
+            2
+        );
+
+        // Recovery writes nothing; each relapse writes one snapshot
+        for i in 0..4u64 {
+            at(1_200 + i * 10);
+            set_price(PRICE_SCALE + i as i128);
+            set_ratio(50);
+            assert!(!check());
+            assert!(!check());
+            set_ratio(150);
+            assert!(check());
+            assert!(check());
+        }
+
+        let snapshots = Contract::get_risk_snapshots(env.clone(), user.clone());
+        assert_eq!(snapshots.len(), alerts::MAX_RISK_SNAPSHOTS);
+        assert_eq!(snapshots.get(0).unwrap().timestamp, 1_100);
+        let last = snapshots.last().unwrap();
+        assert_eq!(last.timestamp, 1_230);
+        assert_eq!(last.price, Some(PRICE_SCALE + 3));
+        assert_eq!(last.maintenance_ratio_bps, 15_000);
+        assert_eq!(last.collateral_ratio_bps, 10_000);
+        assert!(last.health_factor_bps < 10_000);
+    });
+}
+
+#[test]
+fn test_analytics_opt_out_keeps_protocol_totals() {
+    use crate::analytics::AnalyticsStorage;
+    use crate::privacy::{PRIVACY_ANALYTICS_OPT_OUT, PRIVACY_REFERRAL_OPT_OUT};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let other = TestUtils::create_user_address(&env, 1);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, &[user.clone(), other.clone()]);
+
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        TestUtils::verify_user(&env, &admin, &other);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 500).unwrap();
+        Contract::deposit_collateral(env.clone(), other.to_string(), 500).unwrap();
+        let report = Contract::get_user_report(env.clone(), user.to_string()).unwrap();
+        assert_eq!(report.recent_activities.len(), 1);
+        assert_eq!(
+            Contract::get_asset_distribution(env.clone(), token.clone())
+                .collateral
+                .to_1k,
+            2
+        );
+
+        assert_eq!(
+            Contract::set_privacy_flags(env.clone(), user.to_string(), 4),
+            Err(ProtocolError::InvalidParameters)
+        );
+        Contract::set_privacy_flags(
+            env.clone(),
+            user.to_string(),
+            PRIVACY_ANALYTICS_OPT_OUT | PRIVACY_REFERRAL_OPT_OUT,
+        )
+        .unwrap();
+        assert_eq!(Contract::get_privacy_flags(env.clone(), user.clone()), 3);
+
+        // Existing activity is cleared, other users' is kept
+        let report = Contract::get_user_report(env.clone(), user.to_string()).unwrap();
+        assert!(report.recent_activities.is_empty());
+        assert_eq!(report.analytics.total_deposits, 0);
+        assert_eq!(AnalyticsStorage::get_activity_log(&env).len(), 1);
+        assert_eq!(
+            Contract::get_asset_distribution(env.clone(), token.clone())
+                .collateral
+                .to_1k,
+            1
+        );
+
+        // New actions move protocol totals only
+        let before = AnalyticsStorage::get_protocol_metrics(&env).total_deposits;
+        Contract::deposit_collateral(env.clone(), user.to_string(), 700).unwrap();
+        assert_eq!(
+            AnalyticsStorage::get_protocol_metrics(&env).total_deposits,
+            before + 700
+        );
+        let report = Contract::get_user_report(env.clone(), user.to_string()).unwrap();
+        assert!(report.recent_activities.is_empty());
+        let distribution = Contract::get_asset_distribution(env.clone(), token.clone());
+        assert_eq!(distribution.collateral.to_1k, 1);
+        assert_eq!(distribution.collateral.to_10k, 0);
+    });
+}
+#[test]
+fn test_large_withdrawal_needs_co_signer_approval() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let co_signer = Address::generate(&env);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    let client = ContractClient::new(&env, &contract_id);
+    env.ledger().with_mut(|l| l.timestamp = 1_000);
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+    });
+    client.set_withdrawal_policy(&user, &100, &Some(co_signer.clone()));
+
+    // At or below the threshold needs no approval
+    client.withdraw(&user.to_string(), &100, &0);
+    assert_eq!(
+        client.try_withdraw(&user.to_string(), &300, &0),
+        Err(Ok(ProtocolError::CoSignerApprovalRequired))
+    );
+
+    // Only the co-signer may approve, and only the approved amount passes
+    assert_eq!(
+        client.try_approve_withdrawal(&user, &user, &token, &300, &2_000),
+        Err(Ok(ProtocolError::Unauthorized))
+    );
+    client.approve_withdrawal(&co_signer, &user, &token, &300, &2_000);
+    assert_eq!(
+        client.try_withdraw(&user.to_string(), &200, &0),
+        Err(Ok(ProtocolError::CoSignerApprovalRequired))
+    );
+    client.withdraw(&user.to_string(), &300, &0);
+
+    // The approval is one-time
+    assert_eq!(
+        client.try_withdraw(&user.to_string(), &300, &0),
+        Err(Ok(ProtocolError::CoSignerApprovalRequired))
+    );
+
+    // Expired approvals are rejected
+    client.approve_withdrawal(&co_signer, &user, &token, &300, &1_500);
+    env.ledger().with_mut(|l| l.timestamp = 1_501);
+    assert_eq!(
+        client.try_withdraw(&user.to_string(), &300, &0),
+        Err(Ok(ProtocolError::CoSignerApprovalRequired))
+    );
+}
+
+#[test]
+fn test_withdrawal_policy_removal_is_delayed() {
+    use crate::cosign::POLICY_CHANGE_DELAY_SECS;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let co_signer = Address::generate(&env);
+    let (admin, contract_id, _token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    let client = ContractClient::new(&env, &contract_id);
+    env.ledger().with_mut(|l| l.timestamp = 1_000);
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+    });
+    client.set_withdrawal_policy(&user, &100, &Some(co_signer.clone()));
+
+    // Removal is queued, so the policy still binds until the delay passes
+    client.set_withdrawal_policy(&user, &0, &None);
+    assert!(client.get_withdrawal_policy(&user).is_some());
+    env.ledger()
+        .with_mut(|l| l.timestamp = 1_000 + POLICY_CHANGE_DELAY_SECS - 1);
+    assert_eq!(
+        client.try_withdraw(&user.to_string(), &500, &0),
+        Err(Ok(ProtocolError::CoSignerApprovalRequired))
+    );
+
+    env.ledger()
+        .with_mut(|l| l.timestamp = 1_000 + POLICY_CHANGE_DELAY_SECS);
+    assert_eq!(client.get_withdrawal_policy(&user), None);
+    client.withdraw(&user.to_string(), &500, &0);
+}
+
+#[test]
+#[should_panic(expected = "Error(Auth, InvalidAction)")]
+fn test_withdrawal_policy_requires_owner_auth() {
+    let env = Env::default();
+    let contract_id = env.register(Contract, ());
+    env.as_contract(&contract_id, || {
+        let _ = Contract::set_withdrawal_policy(
+            env.clone(),
+            Address::generate(&env),
+            100,
+            Some(Address::generate(&env)),
+        );
+    });
+}
+
+#[test]
+#[should_panic(expected = "Error(Auth, InvalidAction)")]
+fn test_withdrawal_approval_requires_co_signer_auth() {
+    let env = Env::default();
+    let contract_id = env.register(Contract, ());
+    env.as_contract(&contract_id, || {
+        let _ = Contract::approve_withdrawal(
+            env.clone(),
+            Address::generate(&env),
+            Address::generate(&env),
+            Address::generate(&env),
+            300,
+            2_000,
+        );
+    });
+}
+
+#[test]
+fn test_liquidation_refuses_frozen_oracle() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let liquidator = TestUtils::create_user_address(&env, 1);
+    let (admin, contract_id, _token) =
+        TestUtils::setup_contract_with_token(&env, &[user.clone(), liquidator.clone()]);
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        TestUtils::verify_user(&env, &admin, &liquidator);
+        setup_liquidatable_position(&env, &admin, &user);
+
+        Contract::freeze_oracle(env.clone(), admin.to_string(), None, None).unwrap();
+        let result = Contract::liquidate(
+            env.clone(),
+            liquidator.to_string(),
+            user.to_string(),
+            200,
+            0,
+            false,
+            0,
+            false,
+        );
+        assert_eq!(result.unwrap_err(), ProtocolError::OracleFrozen);
+
+        Contract::unfreeze_oracle(env.clone(), admin.to_string(), None, None).unwrap();
+        assert!(Contract::liquidate(
+            env.clone(),
+            liquidator.to_string(),
+            user.to_string(),
+            200,
+            0,
+            false,
+            0,
+            false,
+        )
+        .is_ok());
+    });
+}
+
+#[test]
+fn test_call_stats_only_tracked_when_diagnostics_enabled() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, _token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        let stats_key = Symbol::new(&env, "call_stats");
+
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+        assert!(!env.storage().instance().has(&stats_key));
+        assert_eq!(Contract::get_call_stats(env.clone()).len(), 0);
+
+        Contract::set_feature_flag(
+            env.clone(),
+            admin.to_string(),
+            Symbol::new(&env, "diagnostics_enabled"),
+            true,
+        )
+        .unwrap();
+        env.ledger().with_mut(|l| l.timestamp = 42);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+        Contract::deposit_collateral(env.clone(), user.to_string(), 500).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 100, 0).unwrap();
+
+        let stats = Contract::get_call_stats(env.clone());
+        assert_eq!(stats.len(), 2);
+        let deposits = stats.get(Symbol::new(&env, "deposit_collateral")).unwrap();
+        assert_eq!(deposits.count, 2);
+        assert_eq!(deposits.last_caller, user);
+        assert_eq!(deposits.last_ts, 42);
+        assert_eq!(stats.get(Symbol::new(&env, "borrow")).unwrap().count, 1);
+    });
+}
+
+#[test]
+fn test_migrate_position_out_to_accepting_target() {
+    use crate::governance::Action;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    let target = env.register(MockMigrationTarget, ());
+    env.as_contract(&target, || {
+        MockMigrationTarget::set_accepting(env.clone(), true);
+    });
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 400, 0).unwrap();
+
+        // Not yet approved by governance
+        let result = Contract::migrate_position_out(env.clone(), user.to_string(), target.clone());
+        assert_eq!(result.unwrap_err(), ProtocolError::Unauthorized);
+
+        govern(
+            &env,
+            &admin,
+            Action::SetMigrationTarget(target.clone(), true),
+        )
+        .unwrap();
+    });
+
+    let client = ContractClient::new(&env, &contract_id);
+    let (collateral, debt) = client.migrate_position_out(&user.to_string(), &target);
+    assert_eq!(collateral.amount, 1000);
+    assert_eq!(debt.amount, 400);
+
+    let position = client.get_position(&user.to_string());
+    assert_eq!((position.0, position.1), (0, 0));
+    env.as_contract(&token, || {
+        assert_eq!(MockToken::balance(env.clone(), target.clone()), 1000);
+    });
+    env.as_contract(&target, || {
+        let received: (i128, i128) = env.storage().instance().get(&user).unwrap();
+        assert_eq!(received, (1000, 400));
+    });
+}
+
+#[test]
+fn test_migrate_position_out_carries_accrued_interest() {
+    use crate::governance::Action;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1_000);
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    let target = env.register(MockMigrationTarget, ());
+    env.as_contract(&target, || {
+        MockMigrationTarget::set_accepting(env.clone(), true);
+    });
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 100_000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 40_000, 0).unwrap();
+        govern(
+            &env,
+            &admin,
+            Action::SetMigrationTarget(target.clone(), true),
+        )
+        .unwrap();
+    });
+
+    // A month passes without the position being touched
+    env.ledger()
+        .with_mut(|l| l.timestamp = 1_000 + 30 * 24 * 60 * 60);
+    let client = ContractClient::new(&env, &contract_id);
+    let (collateral, debt) = client.migrate_position_out(&user.to_string(), &target);
+    assert!(debt.amount > 40_000);
+    assert!(collateral.amount > 100_000);
+
+    // The interest owed goes with the position rather than being forgiven
+    env.as_contract(&target, || {
+        let received: (i128, i128) = env.storage().instance().get(&user).unwrap();
+        assert_eq!(received, (collateral.amount, debt.amount));
+    });
+    env.as_contract(&token, || {
+        assert_eq!(
+            MockToken::balance(env.clone(), target.clone()),
+            collateral.amount
+        );
+    });
+    env.as_contract(&contract_id, || {
+        let position = StateHelper::get_position(&env, &user).unwrap();
+        assert_eq!((position.borrow_interest, position.supply_interest), (0, 0));
+    });
+}
+
+#[test]
+fn test_migrate_position_out_reverts_when_target_rejects() {
+    use crate::governance::Action;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    let target = env.register(MockMigrationTarget, ());
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 400, 0).unwrap();
+        govern(
+            &env,
+            &admin,
+            Action::SetMigrationTarget(target.clone(), true),
+        )
+        .unwrap();
+    });
+
+    let client = ContractClient::new(&env, &contract_id);
+    let result = client.try_migrate_position_out(&user.to_string(), &target);
+    assert_eq!(result, Err(Ok(ProtocolError::MigrationRejected)));
+
+    // Collateral transfer was rolled back with the rest of the call
+    let position = client.get_position(&user.to_string());
+    assert_eq!((position.0, position.1), (1000, 400));
+    env.as_contract(&token, || {
+        assert_eq!(MockToken::balance(env.clone(), target.clone()), 0);
+    });
+}
+
+#[test]
+fn test_liquidation_record_captures_source_prices() {
+    use crate::oracle::{Oracle, OracleSource, PRICE_SCALE};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let liquidator = TestUtils::create_user_address(&env, 1);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, &[user.clone(), liquidator.clone()]);
+    let oracles = [
+        env.register(MockPriceOracle, ()),
+        env.register(MockPriceOracle, ()),
+        env.register(MockPriceOracle, ()),
+    ];
+    for (i, oracle) in oracles.iter().enumerate() {
+        env.as_contract(oracle, || {
+            MockPriceOracle::set_price(env.clone(), token.clone(), PRICE_SCALE + 2 * i as i128);
+        });
+    }
+
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        TestUtils::verify_user(&env, &admin, &liquidator);
+        for oracle in oracles.iter() {
+            Oracle::set_source(
+                &env,
+                &admin,
+                &token,
+                OracleSource::new(oracle.clone(), 1, 0),
+            )
+            .unwrap();
+        }
+        setup_liquidatable_position(&env, &admin, &user);
+
+        let result = Contract::liquidate(
+            env.clone(),
+            liquidator.to_string(),
+            user.to_string(),
+            200,
+            0,
+            false,
+            0,
+            false,
+        )
+        .unwrap();
+
+        let record = Contract::get_liquidation_record(env.clone(), result.record_id).unwrap();
+        assert_eq!(record.borrower, user);
+        assert_eq!(record.debt_repaid, result.debt_repaid);
+        // Default policy trims the extremes and keeps the median source
+        assert_eq!(record.debt_price, Some(PRICE_SCALE + 2));
+        assert_eq!(record.collateral_price, record.debt_price);
+        assert_eq!(record.samples.len(), 3);
+        assert_eq!(record.samples.get(0).unwrap().source, oracles[0]);
+        assert_eq!(record.samples.get(2).unwrap().price, PRICE_SCALE + 4);
+        assert_eq!(
+            Contract::get_last_liquidation_record(env.clone(), user.clone()),
+            Some(record)
+        );
+    });
+}
+
+#[test]
+fn test_role_address_change_timelock_flow() {
+    use crate::roles::{RoleKind, ROLE_CHANGE_DELAY_SECS};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = TestUtils::create_admin_address(&env);
+    let treasury = Address::generate(&env);
+    let new_treasury = Address::generate(&env);
+    let contract_id = env.register(Contract, ());
+    env.as_contract(&contract_id, || {
+        Contract::initialize(env.clone(), admin.to_string()).unwrap();
+        Contract::set_treasury(env.clone(), admin.to_string(), treasury.clone()).unwrap();
+
+        // Direct replacement is no longer possible once a treasury exists
+        let direct = Contract::set_treasury(env.clone(), admin.to_string(), new_treasury.clone());
+        assert_eq!(direct.unwrap_err(), ProtocolError::InvalidOperation);
+
+        env.ledger().with_mut(|l| l.timestamp = 1000);
+        let pending = Contract::propose_role_address_change(
+            env.clone(),
+            admin.to_string(),
+            RoleKind::Treasury,
+            new_treasury.clone(),
+        )
+        .unwrap();
+        assert_eq!(pending.effective_at, 1000 + ROLE_CHANGE_DELAY_SECS);
+
+        // Premature apply is rejected
+        env.ledger()
+            .with_mut(|l| l.timestamp = 1000 + ROLE_CHANGE_DELAY_SECS - 1);
+        let early =
+            Contract::apply_role_address_change(env.clone(), admin.to_string(), RoleKind::Treasury);
+        assert_eq!(early.unwrap_err(), ProtocolError::InvalidOperation);
+        assert_eq!(ProtocolConfig::get_treasury(&env), Some(treasury.clone()));
+
+        env.ledger()
+            .with_mut(|l| l.timestamp = 1000 + ROLE_CHANGE_DELAY_SECS);
+        Contract::apply_role_address_change(env.clone(), admin.to_string(), RoleKind::Treasury)
+            .unwrap();
+        assert_eq!(
+            ProtocolConfig::get_treasury(&env),
+            Some(new_treasury.clone())
+        );
+        assert_eq!(
+            Contract::get_pending_role_change(env.clone(), RoleKind::Treasury),
+            None
+        );
+
+        let history = Contract::get_param_history(env.clone());
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.get(0).unwrap().action, Symbol::new(&env, "set"));
+        assert_eq!(
+            history.get(1).unwrap().action,
+            Symbol::new(&env, "proposed")
+        );
+        let applied = history.get(2).unwrap();
+        assert_eq!(applied.action, Symbol::new(&env, "applied"));
+        assert_eq!(applied.address_value, Some(new_treasury.clone()));
+    });
+}
+
+#[test]
+fn test_guardian_can_cancel_change_targeting_itself() {
+    use crate::roles::RoleKind;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = TestUtils::create_admin_address(&env);
+    let guardian = Address::generate(&env);
+    let attacker = Address::generate(&env);
+    let contract_id = env.register(Contract, ());
+    env.as_contract(&contract_id, || {
+        Contract::initialize(env.clone(), admin.to_string()).unwrap();
+        Contract::set_guardian(env.clone(), admin.to_string(), guardian.clone()).unwrap();
+
+        // A compromised admin key proposes replacing the guardian
+        Contract::propose_role_address_change(
+            env.clone(),
+            admin.to_string(),
+            RoleKind::Guardian,
+            attacker.clone(),
+        )
+        .unwrap();
+        Contract::cancel_role_address_change(env.clone(), guardian.to_string(), RoleKind::Guardian)
+            .unwrap();
+        assert_eq!(
+            Contract::get_pending_role_change(env.clone(), RoleKind::Guardian),
+            None
+        );
+        assert_eq!(ProtocolConfig::get_guardian(&env), Some(guardian.clone()));
+
+        // The guardian has no say over treasury changes
+        Contract::propose_role_address_change(
+            env.clone(),
+            admin.to_string(),
+            RoleKind::Treasury,
+            attacker.clone(),
+        )
+        .unwrap();
+        let result = Contract::cancel_role_address_change(
+            env.clone(),
+            guardian.to_string(),
+            RoleKind::Treasury,
+        );
+        assert_eq!(result.unwrap_err(), ProtocolError::Unauthorized);
+    });
+}
+
+#[test]
+fn test_price_bounds_clamp_both_directions() {
+    use crate::oracle::{Oracle, OracleSource, PRICE_SCALE};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (admin, contract_id, token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let oracle = env.register(MockPriceOracle, ());
+    env.as_contract(&oracle, || {
+        MockPriceOracle::set_price(env.clone(), token.clone(), 3 * PRICE_SCALE);
+    });
+
+    env.as_contract(&contract_id, || {
+        Oracle::set_source(
+            &env,
+            &admin,
+            &token,
+            OracleSource::new(oracle.clone(), 1, 0),
+        )
+        .unwrap();
+        // Deliberate price jumps: keep the deviation breaker out of the way
+        crate::oracle::OracleStorage::set_max_deviation_bps(&env, 1_000_000);
+        Contract::set_price_bounds(
+            env.clone(),
+            admin.to_string(),
+            token.clone(),
+            PRICE_SCALE / 10,
+            2 * PRICE_SCALE,
+        )
+        .unwrap();
+
+        let high = Oracle::aggregate_price_detailed(&env, &token).unwrap();
+        assert_eq!(high.price, 2 * PRICE_SCALE);
+        assert!(high.clamped);
+    });
+
+    env.as_contract(&oracle, || {
+        MockPriceOracle::set_price(env.clone(), token.clone(), PRICE_SCALE / 20);
+    });
+    env.as_contract(&contract_id, || {
+        let low = Oracle::aggregate_price_detailed(&env, &token).unwrap();
+        assert_eq!(low.price, PRICE_SCALE / 10);
+        assert!(low.clamped);
+
+        // Disabling the bounds passes the raw price through
+        Contract::set_price_bounds(env.clone(), admin.to_string(), token.clone(), 0, 0).unwrap();
+        let raw = Oracle::aggregate_price_detailed(&env, &token).unwrap();
+        assert_eq!(raw.price, PRICE_SCALE / 20);
+        assert!(!raw.clamped);
+
+        let inverted =
+            Contract::set_price_bounds(env.clone(), admin.to_string(), token.clone(), 10, 5);
+        assert_eq!(inverted.unwrap_err(), ProtocolError::InvalidParameters);
+    });
+}
+
+#[test]
+fn test_reading_bounds_drop_out_of_range_sources() {
+    use crate::oracle::{Oracle, OracleSource, OracleStorage, PRICE_SCALE};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (admin, contract_id, token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let sane = env.register(MockPriceOracle, ());
+    let broken = env.register(MockPriceOracle, ());
+    env.as_contract(&sane, || {
+        MockPriceOracle::set_price(env.clone(), token.clone(), 2 * PRICE_SCALE);
+    });
+    env.as_contract(&broken, || {
+        MockPriceOracle::set_price(env.clone(), token.clone(), 1_000 * PRICE_SCALE);
+    });
+
+    env.as_contract(&contract_id, || {
+        let unset = Oracle::get_reading_bounds(&env, &token);
+        assert_eq!((unset.min, unset.max), (0, i128::MAX));
+
+        Oracle::set_source(&env, &admin, &token, OracleSource::new(sane.clone(), 1, 0)).unwrap();
+        Oracle::set_source(
+            &env,
+            &admin,
+            &token,
+            OracleSource::new(broken.clone(), 1, 0),
+        )
+        .unwrap();
+        OracleStorage::set_max_deviation_bps(&env, 1_000_000);
+        Oracle::set_reading_bounds(&env, &admin, &token, PRICE_SCALE, 10 * PRICE_SCALE).unwrap();
+
+        // The broken source is dropped before aggregation instead of skewing it
+        let agg = Oracle::aggregate_with_confidence(&env, &token).unwrap();
+        assert_eq!(agg.price, 2 * PRICE_SCALE);
+        assert_eq!(agg.num_sources, 1);
+        assert_eq!(OracleStorage::get_rejected_readings(&env, &token), 1);
+    });
+}
+
+#[test]
+fn test_reading_bounds_validation() {
+    use crate::oracle::{Oracle, PRICE_SCALE};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (admin, contract_id, token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let client = ContractClient::new(&env, &contract_id);
+    let admin_s = admin.to_string();
+
+    let zero_min = client.try_set_oracle_reading_bounds(&admin_s, &token, &0, &PRICE_SCALE);
+    assert_eq!(zero_min, Err(Ok(ProtocolError::InvalidParameters)));
+    let equal = client.try_set_oracle_reading_bounds(&admin_s, &token, &PRICE_SCALE, &PRICE_SCALE);
+    assert_eq!(equal, Err(Ok(ProtocolError::InvalidParameters)));
+
+    let stranger = TestUtils::create_user_address(&env, 1).to_string();
+    let denied = client.try_set_oracle_reading_bounds(&stranger, &token, &1, &PRICE_SCALE);
+    assert_eq!(denied, Err(Ok(ProtocolError::Unauthorized)));
+
+    client.set_oracle_reading_bounds(&admin_s, &token, &1, &PRICE_SCALE);
+    let (bounds, rejected) = client.get_oracle_reading_bounds(&token);
+    assert_eq!((bounds.min, bounds.max, rejected), (1, PRICE_SCALE, 0));
+
+    // (0, i128::MAX) removes the bounds again
+    client.set_oracle_reading_bounds(&admin_s, &token, &0, &i128::MAX);
+    env.as_contract(&contract_id, || {
+        let bounds = Oracle::get_reading_bounds(&env, &token);
+        assert_eq!((bounds.min, bounds.max), (0, i128::MAX));
+    });
+}
+
+#[test]
+fn test_derive_price_composes_one_hop_route() {
+    use crate::oracle::{Oracle, OracleSource, OracleStorage, PRICE_SCALE};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (admin, contract_id, token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let client = ContractClient::new(&env, &contract_id);
+    let xlm = Address::generate(&env);
+    let usdc = Address::generate(&env);
+    let feed = env.register(MockPriceOracle, ());
+    env.as_contract(&feed, || {
+        // TOKEN = 2 XLM, 1 USDC = 10 XLM
+        MockPriceOracle::set_price(env.clone(), token.clone(), 2 * PRICE_SCALE);
+        MockPriceOracle::set_price(env.clone(), usdc.clone(), 10 * PRICE_SCALE);
+    });
+    env.as_contract(&contract_id, || {
+        OracleStorage::set_max_deviation_bps(&env, 1_000_000);
+        let in_xlm = OracleSource::new(feed.clone(), 1, 0).with_quote(xlm.clone());
+        Oracle::set_source(&env, &admin, &token, in_xlm.clone()).unwrap();
+        Oracle::set_source(&env, &admin, &usdc, in_xlm).unwrap();
+
+        // A second source quoting in another currency can't join the asset
+        let mixed = OracleSource::new(Address::generate(&env), 1, 0);
+        assert_eq!(
+            Oracle::set_source(&env, &admin, &token, mixed),
+            Err(ProtocolError::InvalidParameters)
+        );
+    });
+
+    // Direct and inverted single legs
+    assert_eq!(
+        client.get_derived_price(&token, &xlm),
+        Some(2 * PRICE_SCALE)
+    );
+    assert_eq!(
+        client.get_derived_price(&xlm, &usdc),
+        Some(PRICE_SCALE / 10)
+    );
+    // No route configured yet
+    assert_eq!(client.get_derived_price(&token, &usdc), None);
+
+    let admin_s = admin.to_string();
+    client.set_oracle_price_route(&admin_s, &token, &usdc, &Some(xlm.clone()));
+    assert_eq!(
+        client.get_derived_price(&token, &usdc),
+        Some(PRICE_SCALE / 5)
+    );
+
+    // Overflow in the composition yields None instead of trapping
+    env.as_contract(&feed, || {
+        MockPriceOracle::set_price(env.clone(), token.clone(), 10i128.pow(32));
+    });
+    assert_eq!(client.get_derived_price(&token, &xlm), Some(10i128.pow(32)));
+    assert_eq!(client.get_derived_price(&token, &usdc), None);
+
+    client.set_oracle_price_route(&admin_s, &token, &usdc, &None);
+    assert_eq!(
+        client.try_set_oracle_price_route(&admin_s, &token, &usdc, &None),
+        Err(Ok(ProtocolError::NotFound))
+    );
+    assert_eq!(
+        client.try_set_oracle_price_route(&admin_s, &token, &usdc, &Some(usdc.clone())),
+        Err(Ok(ProtocolError::InvalidParameters))
+    );
+}
+
+#[test]
+fn test_liquidation_refused_on_clamped_collateral_price() {
+    use crate::oracle::{Oracle, OracleSource, PRICE_SCALE};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let liquidator = TestUtils::create_user_address(&env, 1);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, &[user.clone(), liquidator.clone()]);
+    let oracle = env.register(MockPriceOracle, ());
+    env.as_contract(&oracle, || {
+        MockPriceOracle::set_price(env.clone(), token.clone(), 5 * PRICE_SCALE);
+    });
+
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        TestUtils::verify_user(&env, &admin, &liquidator);
+        Oracle::set_source(
+            &env,
+            &admin,
+            &token,
+            OracleSource::new(oracle.clone(), 1, 0),
+        )
+        .unwrap();
+        Contract::set_price_bounds(
+            env.clone(),
+            admin.to_string(),
+            token.clone(),
+            PRICE_SCALE / 10,
+            2 * PRICE_SCALE,
+        )
+        .unwrap();
+        setup_liquidatable_position(&env, &admin, &user);
+
+        let refused = Contract::liquidate(
+            env.clone(),
+            liquidator.to_string(),
+            user.to_string(),
+            200,
+            0,
+            false,
+            0,
+            false,
+        );
+        assert_eq!(refused.unwrap_err(), ProtocolError::OracleFailure);
+
+        Contract::set_block_liquidation_on_clamp(env.clone(), admin.to_string(), false).unwrap();
+        let result = Contract::liquidate(
+            env.clone(),
+            liquidator.to_string(),
+            user.to_string(),
+            200,
+            0,
+            false,
+            0,
+            false,
+        )
+        .unwrap();
+        let record = Contract::get_liquidation_record(env.clone(), result.record_id).unwrap();
+        assert_eq!(record.collateral_price, Some(2 * PRICE_SCALE));
+    });
+}
+
+#[test]
+fn test_emergency_price_needs_guardian_and_admin() {
+    use crate::oracle::{Oracle, OracleSource, OracleStorage, PRICE_SCALE};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let guardian = Address::generate(&env);
+    let (admin, contract_id, token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let oracle = env.register(MockPriceOracle, ());
+    env.as_contract(&oracle, || {
+        MockPriceOracle::set_price(env.clone(), token.clone(), 5 * PRICE_SCALE);
+    });
+
+    env.as_contract(&contract_id, || {
+        env.ledger().with_mut(|l| l.timestamp = 1_000);
+        Contract::set_guardian(env.clone(), admin.to_string(), guardian.clone()).unwrap();
+        Oracle::set_source(
+            &env,
+            &admin,
+            &token,
+            OracleSource::new(oracle.clone(), 1, 0),
+        )
+        .unwrap();
+        OracleStorage::set_heartbeat_ttl(&env, &admin, 1_000_000).unwrap();
+        let set = |caller: &Address, price: i128, expires_at: u64| {
+            Oracle::set_emergency_price(&env, caller, &token, price, expires_at)
+        };
+
+        assert_eq!(
+            set(&admin, PRICE_SCALE, 1_000 + 24 * 60 * 60 + 1),
+            Err(ProtocolError::InvalidParameters)
+        );
+        assert_eq!(
+            set(&Address::generate(&env), PRICE_SCALE, 2_000),
+            Err(ProtocolError::Unauthorized)
+        );
+
+        // A proposal alone, or repeated by the same role, changes nothing
+        set(&admin, PRICE_SCALE, 2_000).unwrap();
+        set(&admin, PRICE_SCALE, 2_000).unwrap();
+        assert!(Oracle::active_override(&env, &token, false).is_none());
+        assert_eq!(Oracle::aggregate_price(&env, &token), Some(5 * PRICE_SCALE));
+
+        // A differing call replaces the proposal instead of confirming it
+        let proposal = set(&guardian, 2 * PRICE_SCALE, 2_000).unwrap();
+        assert_eq!(proposal.confirmed_by, None);
+        assert!(Oracle::active_override(&env, &token, false).is_none());
+
+        let active = set(&admin, 2 * PRICE_SCALE, 2_000).unwrap();
+        assert_eq!(active.proposed_by, guardian);
+        assert_eq!(active.confirmed_by, Some(admin.clone()));
+        let pinned = Oracle::aggregate_with_confidence(&env, &token).unwrap();
+        assert_eq!(pinned.price, 2 * PRICE_SCALE);
+        assert!(pinned.overridden);
+        assert!(
+            Oracle::aggregate_price_detailed(&env, &token)
+                .unwrap()
+                .overridden
+        );
+
+        // Expiry restores live prices without any further call
+        env.ledger().with_mut(|l| l.timestamp = 2_001);
+        let live = Oracle::aggregate_with_confidence(&env, &token).unwrap();
+        assert_eq!(live.price, 5 * PRICE_SCALE);
+        assert!(!live.overridden);
+        assert!(OracleStorage::get_emergency_price(&env, &token, false).is_none());
+    });
+}
+
+#[test]
+fn test_liquidation_on_emergency_price_needs_acknowledgement() {
+    use crate::oracle::{Oracle, OracleSource, PRICE_SCALE};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let liquidator = TestUtils::create_user_address(&env, 1);
+    let guardian = Address::generate(&env);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, &[user.clone(), liquidator.clone()]);
+    let oracle = env.register(MockPriceOracle, ());
+    env.as_contract(&oracle, || {
+        MockPriceOracle::set_price(env.clone(), token.clone(), PRICE_SCALE);
+    });
+
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        TestUtils::verify_user(&env, &admin, &liquidator);
+        Contract::set_guardian(env.clone(), admin.to_string(), guardian.clone()).unwrap();
+        Oracle::set_source(
+            &env,
+            &admin,
+            &token,
+            OracleSource::new(oracle.clone(), 1, 0),
+        )
+        .unwrap();
+        setup_liquidatable_position(&env, &admin, &user);
+        for caller in [&guardian, &admin] {
+            Contract::set_emergency_price(
+                env.clone(),
+                caller.to_string(),
+                token.clone(),
+                3 * PRICE_SCALE,
+                3_600,
+            )
+            .unwrap();
+        }
+
+        let liquidate = |acknowledge: bool| {
+            Contract::liquidate(
+                env.clone(),
+                liquidator.to_string(),
+                user.to_string(),
+                200,
+                0,
+                false,
+                0,
+                acknowledge,
+            )
+        };
+        assert_eq!(
+            liquidate(false).unwrap_err(),
+            ProtocolError::InvalidOperation
+        );
+        let result = liquidate(true).unwrap();
+        let record = Contract::get_liquidation_record(env.clone(), result.record_id).unwrap();
+        assert_eq!(record.collateral_price, Some(3 * PRICE_SCALE));
+    });
+}
+
+#[cfg(feature = "amm")]
+fn setup_amm_pool(env: &Env) -> (Address, Address, Address, crate::amm::PairKey) {
+    let user = TestUtils::create_user_address(env, 0);
+    let (admin, contract_id, token_a) =
+        TestUtils::setup_contract_with_token(env, core::slice::from_ref(&user));
+    #[allow(deprecated)]
+    let token_b = env.register_contract(None, MockToken);
+    env.as_contract(&token_b, || {
+        MockToken::mint(env.clone(), user.clone(), 1_000_000);
+    });
+
+    let pair_id = crate::amm::PairKey::new(token_a.clone(), token_b.clone());
+    env.as_contract(&contract_id, || {
+        Contract::register_amm_pair(
+            env.clone(),
+            admin.clone(),
+            token_a.clone(),
+            token_b.clone(),
+            Address::generate(env),
+            None,
+        )
+        .unwrap();
+        Contract::add_amm_liquidity(env.clone(), user.clone(), pair_id.clone(), 100_000, 100_000)
+            .unwrap();
+    });
+    (admin, contract_id, user, pair_id)
+}
+
+#[cfg(feature = "amm")]
+#[test]
+fn test_repay_with_swaps_into_debt_and_refunds_surplus() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (admin, contract_id, user, pair_id) = setup_amm_pool(&env);
+    let client = ContractClient::new(&env, &contract_id);
+    let (debt_token, pay_token) = (pair_id.asset_a.clone(), pair_id.asset_b.clone());
+    let balance =
+        |token: &Address| env.as_contract(token, || MockToken::balance(env.clone(), user.clone()));
+
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 2000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 1000, 0).unwrap();
+    });
+    let debt = || client.get_position(&user.to_string()).1;
+
+    // Only the primary asset the debt is denominated in can be repaid into
+    assert_eq!(
+        client.try_repay_with(&user.to_string(), &debt_token, &500, &pay_token, &0, &0),
+        Err(Ok(ProtocolError::AssetNotSupported))
+    );
+
+    // A swap landing past its deadline is refused before any funds move
+    env.ledger().with_mut(|l| l.timestamp = 1_000);
+    assert_eq!(
+        client.try_repay_with(&user.to_string(), &pay_token, &500, &debt_token, &0, &999),
+        Err(Ok(ProtocolError::DeadlineExceeded))
+    );
+    assert_eq!(debt(), 1000);
+
+    // The swap must deliver at least min_debt_repaid
+    assert_eq!(
+        client.try_repay_with(&user.to_string(), &pay_token, &500, &debt_token, &497, &0),
+        Err(Ok(ProtocolError::SlippageProtectionTriggered))
+    );
+
+    let pay_before = balance(&pay_token);
+    let partial = client.repay_with(&user.to_string(), &pay_token, &500, &debt_token, &490, &0);
+    assert_eq!(partial.swapped_out, 496);
+    assert_eq!((partial.repaid, partial.refunded), (496, 0));
+    assert_eq!(debt(), 504);
+    assert_eq!(balance(&pay_token), pay_before - 500);
+
+    // Paying in more than the remaining debt refunds the rest in the debt asset
+    let debt_before = balance(&debt_token);
+    let full = client.repay_with(&user.to_string(), &pay_token, &1_000, &debt_token, &0, &0);
+    assert_eq!(full.repaid, 504);
+    assert_eq!(full.refunded, full.swapped_out - 504);
+    assert!(full.refunded > 0);
+    assert_eq!(debt(), 0);
+    assert_eq!(balance(&debt_token), debt_before + full.refunded);
+}
+
+#[cfg(feature = "amm")]
+#[test]
+fn test_repay_with_requires_a_route() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (admin, contract_id, user, pair_id) = setup_amm_pool(&env);
+    let client = ContractClient::new(&env, &contract_id);
+    #[allow(deprecated)]
+    let unpaired = env.register_contract(None, MockToken);
+    env.as_contract(&unpaired, || {
+        MockToken::mint(env.clone(), user.clone(), 10_000);
+    });
+
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 2000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 1000, 0).unwrap();
+    });
+
+    assert_eq!(
+        client.try_repay_with(&user.to_string(), &unpaired, &500, &pair_id.asset_a, &0, &0),
+        Err(Ok(ProtocolError::NotFound))
+    );
+    assert_eq!(
+        env.as_contract(&unpaired, || MockToken::balance(env.clone(), user.clone())),
+        10_000
+    );
+    assert_eq!(client.get_position(&user.to_string()).1, 1000);
+}
+
+#[test]
+fn test_payouts_accrue_in_kind_without_settlement_asset() {
+    use crate::reserves::ReserveStorage;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let (admin, contract_id, token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let client = ContractClient::new(&env, &contract_id);
+    let recipient = Address::generate(&env);
+    env.as_contract(&token, || {
+        MockToken::mint(env.clone(), contract_id.clone(), 1_000);
+    });
+    env.as_contract(&contract_id, || ReserveStorage::add(&env, &token, 1_000));
+
+    assert_eq!(
+        client.try_fund_payout(&admin.to_string(), &recipient, &token, &1_001),
+        Err(Ok(ProtocolError::InsufficientLiquidity))
+    );
+    client.fund_payout(&admin.to_string(), &recipient, &token, &400);
+    assert_eq!(client.get_protocol_reserves(&token), 600);
+    assert_eq!(client.get_payout_balance(&recipient, &token), 400);
+    assert!(client.get_payout_queue(&token).is_empty());
+
+    // Shares already in the settlement asset are never queued
+    client.set_reward_settlement(&admin.to_string(), &token, &100);
+    client.fund_payout(&admin.to_string(), &recipient, &token, &100);
+    assert_eq!(client.get_payout_balance(&recipient, &token), 500);
+
+    assert_eq!(client.claim_payout(&recipient, &token), 500);
+    assert_eq!(client.get_payout_balance(&recipient, &token), 0);
+    let paid = env.as_contract(&token, || {
+        MockToken::balance(env.clone(), recipient.clone())
+    });
+    assert_eq!(paid, 500);
+    assert_eq!(
+        client.try_claim_payout(&recipient, &token),
+        Err(Ok(ProtocolError::InvalidAmount))
+    );
+}
+
+#[test]
+fn test_payouts_skip_referral_opted_out_recipient() {
+    use crate::privacy::PRIVACY_REFERRAL_OPT_OUT;
+    use crate::reserves::ReserveStorage;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let (admin, contract_id, token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let client = ContractClient::new(&env, &contract_id);
+    let recipient = Address::generate(&env);
+    env.as_contract(&token, || {
+        MockToken::mint(env.clone(), contract_id.clone(), 1_000);
+    });
+    env.as_contract(&contract_id, || ReserveStorage::add(&env, &token, 1_000));
+
+    client.set_privacy_flags(&recipient.to_string(), &PRIVACY_REFERRAL_OPT_OUT);
+    client.fund_payout(&admin.to_string(), &recipient, &token, &400);
+    assert_eq!(client.get_payout_balance(&recipient, &token), 0);
+    assert_eq!(client.get_protocol_reserves(&token), 1_000);
+
+    // Opting back in lets rewards accrue again
+    client.set_privacy_flags(&recipient.to_string(), &0);
+    client.fund_payout(&admin.to_string(), &recipient, &token, &400);
+    assert_eq!(client.get_payout_balance(&recipient, &token), 400);
+    assert_eq!(client.get_protocol_reserves(&token), 600);
+}
+
+#[cfg(feature = "amm")]
+#[test]
+fn test_settle_rewards_converts_queued_payouts() {
+    use crate::reserves::ReserveStorage;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let (admin, contract_id, _user, pair_id) = setup_amm_pool(&env);
+    let client = ContractClient::new(&env, &contract_id);
+    let (fee_asset, settlement) = (pair_id.asset_a.clone(), pair_id.asset_b.clone());
+    let [first, second] = [0; 2].map(|_| Address::generate(&env));
+    env.as_contract(&contract_id, || {
+        ReserveStorage::add(&env, &fee_asset, 5_000)
+    });
+
+    client.set_reward_settlement(&admin.to_string(), &settlement, &500);
+    client.fund_payout(&admin.to_string(), &first, &fee_asset, &300);
+    client.fund_payout(&admin.to_string(), &second, &fee_asset, &700);
+    assert_eq!(client.get_payout_queue(&fee_asset).len(), 2);
+    assert_eq!(client.get_payout_balance(&first, &fee_asset), 0);
+
+    // The batch stops at max_amount, splitting the second share
+    let result = client.settle_rewards(&fee_asset, &600);
+    assert_eq!(result.amount_in, 600);
+    assert!(!result.in_kind);
+    let first_out = client.get_payout_balance(&first, &settlement);
+    let second_out = client.get_payout_balance(&second, &settlement);
+    assert_eq!(first_out, result.amount_out / 2);
+    assert_eq!(first_out + second_out, result.amount_out);
+    let queue = client.get_payout_queue(&fee_asset);
+    assert_eq!(queue.len(), 1);
+    assert_eq!(queue.get(0).unwrap().amount, 400);
+
+    assert_eq!(client.claim_payout(&first, &settlement), first_out);
+    let paid = env.as_contract(&settlement, || {
+        MockToken::balance(env.clone(), first.clone())
+    });
+    assert_eq!(paid, first_out);
+}
+
+#[cfg(feature = "amm")]
+#[test]
+fn test_settle_rewards_falls_back_in_kind_past_max_slippage() {
+    use crate::reserves::ReserveStorage;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let (admin, contract_id, _user, pair_id) = setup_amm_pool(&env);
+    let client = ContractClient::new(&env, &contract_id);
+    let (fee_asset, settlement) = (pair_id.asset_a.clone(), pair_id.asset_b.clone());
+    let recipient = Address::generate(&env);
+    env.as_contract(&contract_id, || {
+        ReserveStorage::add(&env, &fee_asset, 5_000)
+    });
+    let pool_before = client.get_pool_info(&pair_id);
+
+    // 5000 into a 100k pool moves its price far more than 0.1%
+    client.set_reward_settlement(&admin.to_string(), &settlement, &10);
+    client.fund_payout(&admin.to_string(), &recipient, &fee_asset, &5_000);
+    let result = client.settle_rewards(&fee_asset, &5_000);
+    assert_eq!(
+        result,
+        crate::payouts::SettlementResult {
+            amount_in: 5_000,
+            amount_out: 0,
+            in_kind: true
+        }
+    );
+    assert_eq!(client.get_payout_balance(&recipient, &fee_asset), 5_000);
+    assert_eq!(client.get_payout_balance(&recipient, &settlement), 0);
+    assert!(client.get_payout_queue(&fee_asset).is_empty());
+    assert_eq!(client.get_pool_info(&pair_id), pool_before);
+    assert_eq!(
+        client.try_settle_rewards(&fee_asset, &0),
+        Err(Ok(ProtocolError::InvalidAmount))
+    );
+}
+
+#[cfg(feature = "amm")]
+#[test]
+fn test_pair_fee_change_applies_to_next_swap() {
+    use crate::amm::{AMMRegistry, SwapParams};
+    use crate::governance::Action;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let (admin, contract_id, user, pair_id) = setup_amm_pool(&env);
+
+    env.as_contract(&contract_id, || {
+        let params = SwapParams::new(
+            user.clone(),
+            pair_id.asset_a.clone(),
+            pair_id.asset_b.clone(),
+            1_000,
+            0,
+        );
+        let first = Contract::execute_amm_swap(env.clone(), params.clone()).unwrap();
+        assert_eq!(first.fee_paid, 3);
+
+        assert_eq!(
+            Contract::set_pair_fee(env.clone(), admin.clone(), pair_id.clone(), 2_000, None),
+            Err(ProtocolError::InvalidParameters)
+        );
+        govern(&env, &admin, Action::SetPairFee(pair_id.clone(), 500)).unwrap();
+
+        let pool = Contract::get_pool_info(env.clone(), pair_id.clone()).unwrap();
+        assert_eq!(pool.fee_bps, 500);
+        let (reserve_in, reserve_out) = if pool.asset_a == pair_id.asset_a {
+            (pool.reserve_a, pool.reserve_b)
+        } else {
+            (pool.reserve_b, pool.reserve_a)
+        };
+        let (expected_out, expected_fee) =
+            AMMRegistry::compute_swap_out(1_000, reserve_in, reserve_out, 500);
+
+        let second = Contract::execute_amm_swap(env.clone(), params).unwrap();
+        assert_eq!(second.fee_paid, 50);
+        assert_eq!(second.fee_paid, expected_fee);
+        assert_eq!(second.amount_out, expected_out);
+        assert!(second.amount_out < first.amount_out);
+    });
+}
+
+#[cfg(feature = "amm")]
+#[test]
+fn test_swap_quotes_match_execution() {
+    use crate::amm::SwapParams;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_admin, contract_id, user, pair_id) = setup_amm_pool(&env);
+    let (token_in, token_out) = (pair_id.asset_a.clone(), pair_id.asset_b.clone());
+
+    env.as_contract(&contract_id, || {
+        let swap = |amount_in: i128| {
+            let params = SwapParams::new(
+                user.clone(),
+                token_in.clone(),
+                token_out.clone(),
+                amount_in,
+                0,
+            );
+            Contract::execute_amm_swap(env.clone(), params).unwrap()
+        };
+
+        // Exact input, up to a swap moving the price by more than 10%
+        for amount_in in [100i128, 1_000, 5_000, 20_000] {
+            let quote =
+                Contract::quote_swap_in(env.clone(), pair_id.clone(), token_in.clone(), amount_in)
+                    .unwrap();
+            assert!(quote.sufficient_liquidity);
+            let result = swap(amount_in);
+            assert_eq!(quote.amount_out, result.amount_out);
+            assert_eq!(quote.fee_paid, result.fee_paid);
+            if amount_in == 20_000 {
+                assert!(quote.price_impact_bps > 1000);
+            }
+        }
+
+        // Exact output: the quoted input is the smallest that pays enough
+        let quote =
+            Contract::quote_swap_out(env.clone(), pair_id.clone(), token_out.clone(), 1_000)
+                .unwrap();
+        let short = Contract::quote_swap_in(
+            env.clone(),
+            pair_id.clone(),
+            token_in.clone(),
+            quote.amount_in - 1,
+        )
+        .unwrap();
+        assert!(short.amount_out < 1_000);
+        let result = swap(quote.amount_in);
+        assert_eq!(result.amount_out, quote.amount_out);
+        assert!(result.amount_out >= 1_000);
+
+        // More than the pool holds cannot be quoted
+        let pool = Contract::get_pool_info(env.clone(), pair_id.clone()).unwrap();
+        let reserve_out = if pool.asset_b == token_out {
+            pool.reserve_b
+        } else {
+            pool.reserve_a
+        };
+        let too_much =
+            Contract::quote_swap_out(env.clone(), pair_id.clone(), token_out.clone(), reserve_out)
+                .unwrap();
+        assert!(!too_much.sufficient_liquidity);
+    });
+}
+
+#[cfg(feature = "amm")]
+#[test]
+fn test_route_quotes_chain_hops() {
+    use crate::amm::{PairKey, SwapParams};
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let (admin, contract_id, user, pair_id) = setup_amm_pool(&env);
+    #[allow(deprecated)]
+    let token_c = env.register_contract(None, MockToken);
+    env.as_contract(&token_c, || {
+        MockToken::mint(env.clone(), user.clone(), 1_000_000);
+    });
+    let (token_a, token_b) = (pair_id.asset_a.clone(), pair_id.asset_b.clone());
+    let second = PairKey::new(token_b.clone(), token_c.clone());
+
+    env.as_contract(&contract_id, || {
+        Contract::register_amm_pair(
+            env.clone(),
+            admin.clone(),
+            token_b.clone(),
+            token_c.clone(),
+            Address::generate(&env),
+            None,
+        )
+        .unwrap();
+        Contract::add_amm_liquidity(env.clone(), user.clone(), second.clone(), 50_000, 200_000)
+            .unwrap();
+
+        let path = Vec::from_array(&env, [token_a.clone(), token_b.clone(), token_c.clone()]);
+        let quotes = Contract::quote_route_in(env.clone(), path.clone(), 4_000).unwrap();
+        assert_eq!(quotes.len(), 2);
+        let first_hop = quotes.get_unchecked(0);
+        assert_eq!(
+            first_hop,
+            Contract::quote_swap_in(env.clone(), pair_id.clone(), token_a.clone(), 4_000).unwrap()
+        );
+        assert_eq!(quotes.get_unchecked(1).amount_in, first_hop.amount_out);
+
+        let backwards = Contract::quote_route_out(env.clone(), path, 10_000).unwrap();
+        let out = backwards.get_unchecked(1).amount_out;
+        assert!(out >= 10_000);
+
+        // Executing the hops in turn pays what the route quoted
+        let hop = |asset_in: &Address, asset_out: &Address, amount_in: i128| {
+            let params = SwapParams::new(
+                user.clone(),
+                asset_in.clone(),
+                asset_out.clone(),
+                amount_in,
+                0,
+            );
+            Contract::execute_amm_swap(env.clone(), params)
+                .unwrap()
+                .amount_out
+        };
+        let mid = hop(&token_a, &token_b, backwards.get_unchecked(0).amount_in);
+        assert_eq!(hop(&token_b, &token_c, mid), out);
+    });
+}
+
+#[cfg(feature = "amm")]
+#[test]
+fn test_paused_pair_rejects_swaps_but_allows_removal() {
+    use crate::amm::SwapParams;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let (admin, contract_id, user, pair_id) = setup_amm_pool(&env);
+
+    env.as_contract(&contract_id, || {
+        Contract::set_pair_paused(env.clone(), admin.clone(), pair_id.clone(), true, None).unwrap();
+        assert!(
+            Contract::get_pool_info(env.clone(), pair_id.clone())
+                .unwrap()
+                .paused
+        );
+
+        let params = SwapParams::new(
+            user.clone(),
+            pair_id.asset_a.clone(),
+            pair_id.asset_b.clone(),
+            1_000,
+            0,
+        );
+        assert_eq!(
+            Contract::execute_amm_swap(env.clone(), params),
+            Err(ProtocolError::ProtocolPaused)
+        );
+        assert_eq!(
+            Contract::add_amm_liquidity(env.clone(), user.clone(), pair_id.clone(), 1_000, 1_000),
+            Err(ProtocolError::ProtocolPaused)
+        );
+
+        let (out_a, out_b) =
+            Contract::remove_amm_liquidity(env.clone(), user.clone(), pair_id.clone(), 50_000)
+                .unwrap();
+        assert_eq!((out_a, out_b), (50_000, 50_000));
+        let pool = Contract::get_pool_info(env.clone(), pair_id.clone()).unwrap();
+        assert_eq!(pool.total_shares, 50_000);
+        assert_eq!(pool.reserve_a, 50_000);
+    });
+}
+
+#[cfg(feature = "amm")]
+#[test]
+fn test_multicall_requires_caller_auth() {
+    use crate::multicall::UserOp;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, _token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 2000).unwrap();
+    });
+    let client = ContractClient::new(&env, &contract_id);
+
+    // Nobody signs for the user: the batch must not run on their position
+    env.set_auths(&[]);
+    let ops = Vec::from_array(&env, [UserOp::Borrow(500)]);
+    assert!(client.try_multicall(&user.to_string(), &ops, &0).is_err());
+    assert_eq!(client.get_position(&user.to_string()).1, 0);
+
+    env.mock_all_auths();
+    client.multicall(&user.to_string(), &ops, &0);
+    assert_eq!(env.auths()[0].0, user);
+    assert_eq!(client.get_position(&user.to_string()).1, 500);
+}
+
+#[test]
+fn test_multicall_deposit_borrow_swap() {
+    use crate::multicall::UserOp;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let (admin, contract_id, user, pair_id) = setup_amm_pool(&env);
+    env.as_contract(&contract_id, || TestUtils::verify_user(&env, &admin, &user));
+    let client = ContractClient::new(&env, &contract_id);
+    let balance =
+        |token: &Address| env.as_contract(token, || MockToken::balance(env.clone(), user.clone()));
+    let (a_before, b_before) = (balance(&pair_id.asset_a), balance(&pair_id.asset_b));
+
+    let ops = Vec::from_array(
+        &env,
+        [
+            UserOp::Deposit(3000),
+            UserOp::Borrow(1000),
+            UserOp::Swap(pair_id.asset_a.clone(), pair_id.asset_b.clone(), 1000, 1),
+        ],
+    );
+    client.multicall(&user.to_string(), &ops, &0);
+
+    let position = client.get_position(&user.to_string());
+    assert_eq!((position.0, position.1), (3000, 1000));
+    assert_eq!(balance(&pair_id.asset_a), a_before - 3000);
+    assert!(balance(&pair_id.asset_b) > b_before);
+    let pool = client.get_pool_info(&pair_id);
+    assert_eq!(pool.reserve_a, 101_000);
+}
+#[cfg(feature = "amm")]
+#[test]
+fn test_expired_swap_deadline_leaves_pool_untouched() {
+    use crate::amm::SwapParams;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_admin, contract_id, user, pair_id) = setup_amm_pool(&env);
+    env.ledger().with_mut(|l| l.timestamp = 5_000);
+
+    env.as_contract(&contract_id, || {
+        let before = Contract::get_pool_info(env.clone(), pair_id.clone()).unwrap();
+        let params = SwapParams::new(
+            user.clone(),
+            pair_id.asset_a.clone(),
+            pair_id.asset_b.clone(),
+            1_000,
+            0,
+        )
+        .with_deadline(4_999);
+        assert_eq!(
+            Contract::execute_amm_swap(env.clone(), params.clone()),
+            Err(ProtocolError::DeadlineExceeded)
+        );
+        assert_eq!(
+            Contract::get_pool_info(env.clone(), pair_id.clone()).unwrap(),
+            before
+        );
+
+        let params = params.with_deadline(5_000);
+        assert!(Contract::execute_amm_swap(env.clone(), params).is_ok());
+    });
+}
+
+#[test]
+fn test_scan_positions_matches_account_data() {
+    use crate::oracle::{Oracle, OracleSource, PRICE_SCALE};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user0 = TestUtils::create_user_address(&env, 0);
+    let user1 = TestUtils::create_user_address(&env, 1);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, &[user0.clone(), user1.clone()]);
+    let oracle = env.register(MockPriceOracle, ());
+    env.as_contract(&oracle, || {
+        MockPriceOracle::set_price(env.clone(), token.clone(), 2 * PRICE_SCALE);
+    });
+
+    env.as_contract(&contract_id, || {
+        Oracle::set_source(
+            &env,
+            &admin,
+            &token,
+            OracleSource::new(oracle.clone(), 1, 0),
+        )
+        .unwrap();
+        for (user, debt) in [(user0.clone(), 400), (user1.clone(), 0)] {
+            TestUtils::verify_user(&env, &admin, &user);
+            Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+            if debt > 0 {
+                Contract::borrow(env.clone(), user.to_string(), debt, 0).unwrap();
+            }
+        }
+        Oracle::aggregate_price(&env, &token).unwrap();
+        env.ledger().with_mut(|l| l.timestamp += 20);
+
+        let scan = Contract::scan_positions(env.clone(), 0, 50);
+        assert_eq!(scan.total, 2);
+        assert_eq!(scan.cursor, 2);
+        let rest = Contract::scan_positions(env.clone(), scan.cursor, 10);
+        assert_eq!(rest.items.len(), 0);
+        let page = scan.items;
+
+        for entry in page.iter() {
+            let data =
+                Contract::get_user_account_data(env.clone(), entry.borrower.to_string()).unwrap();
+            assert_eq!(entry.collateral, data.collateral);
+            assert_eq!(entry.debt, data.debt);
+            assert_eq!(entry.collateral_ratio_bps, data.collateral_ratio_bps);
+            assert_eq!(entry.price, Some(2 * PRICE_SCALE));
+            assert_eq!(entry.oldest_price_age, Some(20));
+        }
+        let borrower = page.get(0).unwrap();
+        assert_eq!(borrower.borrower, user0);
+        assert_eq!(
+            borrower.health_factor_bps,
+            borrower.collateral_ratio_bps * 10000
+                / ProtocolConfig::get_liquidation_threshold_bps(&env)
+        );
+        assert_eq!(page.get(1).unwrap().health_factor_bps, i128::MAX);
+    });
+}
+
+#[test]
+fn test_forgive_interest_funded_from_reserves() {
+    use crate::governance::Action;
+    use crate::reserves::ReserveStorage;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 400, 0).unwrap();
+        let mut position = StateHelper::get_position(&env, &user).unwrap();
+        position.borrow_interest = 200;
+        position.last_accrual_time = env.ledger().timestamp();
+        StateHelper::save_position(&env, &position);
+        ReserveStorage::add(&env, &token, 50);
+
+        let users = Vec::from_array(&env, [user.clone()]);
+        let short = govern(
+            &env,
+            &admin,
+            Action::ForgiveInterest(token.clone(), users.clone(), 5000),
+        );
+        assert_eq!(short, Err(ProtocolError::InsufficientLiquidity));
+        assert_eq!(
+            StateHelper::get_position(&env, &user)
+                .unwrap()
+                .borrow_interest,
+            200
+        );
+
+        ReserveStorage::add(&env, &token, 100);
+        govern(
+            &env,
+            &admin,
+            Action::ForgiveInterest(token.clone(), users, 5000),
+        )
+        .unwrap();
+
+        let position = StateHelper::get_position(&env, &user).unwrap();
+        assert_eq!(position.borrow_interest, 100);
+        assert_eq!(position.debt, 400);
+        assert_eq!(
+            Contract::get_protocol_reserves(env.clone(), token.clone()),
+            50
+        );
+    });
+}
+
+#[test]
+fn test_force_close_corrupted_position() {
+    use crate::governance::Action;
+    use crate::recovery::ForceCloseResolution;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let lender = TestUtils::create_user_address(&env, 1);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, &[user.clone(), lender.clone()]);
+
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        TestUtils::verify_user(&env, &admin, &lender);
+        Contract::deposit_collateral(env.clone(), lender.to_string(), 2000).unwrap();
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 300, 0).unwrap();
+
+        let resolution = ForceCloseResolution {
+            collateral: 1000,
+            debt: 300,
+            collateral_returned: 1000,
+        };
+
+        // Healthy positions are refused both by governance and the admin
+        assert_eq!(
+            govern(
+                &env,
+                &admin,
+                Action::ApproveForceClose(user.clone(), resolution.clone())
+            ),
+            Err(ProtocolError::InvalidOperation)
+        );
+        assert_eq!(
+            Contract::force_close_position(
+                env.clone(),
+                admin.to_string(),
+                user.clone(),
+                resolution.clone(),
+            ),
+            Err(ProtocolError::InvalidOperation)
+        );
+
+        // Corrupt the entry; the admin still needs governance approval
+        env.storage().instance().set(
+            &StateHelper::position_key(&env, &user),
+            &Symbol::new(&env, "garbage"),
+        );
+        assert_eq!(
+            Contract::force_close_position(
+                env.clone(),
+                admin.to_string(),
+                user.clone(),
+                resolution.clone(),
+            ),
+            Err(ProtocolError::NotFound)
+        );
+        govern(
+            &env,
+            &admin,
+            Action::ApproveForceClose(user.clone(), resolution.clone()),
+        )
+        .unwrap();
+        assert_eq!(
+            Contract::force_close_position(
+                env.clone(),
+                admin.to_string(),
+                user.clone(),
+                ForceCloseResolution {
+                    collateral_returned: 900,
+                    ..resolution.clone()
+                },
+            ),
+            Err(ProtocolError::InvalidParameters)
+        );
+
+        let before = TokenClient::new(&env, &token).balance(&user);
+        let totals_before = InterestRateStorage::get_state(&env);
+        let record = Contract::force_close_position(
+            env.clone(),
+            admin.to_string(),
+            user.clone(),
+            resolution.clone(),
+        )
+        .unwrap();
+        assert_eq!(record.bad_debt, 300);
+        assert_eq!(TokenClient::new(&env, &token).balance(&user), before + 1000);
+        assert_eq!(StateHelper::get_position(&env, &user), None);
+        assert_eq!(Contract::get_bad_debt(env.clone(), token.clone()), 300);
+        let totals = InterestRateStorage::get_state(&env);
+        assert_eq!(totals.total_supplied, totals_before.total_supplied - 1000);
+        assert_eq!(totals.total_borrowed, totals_before.total_borrowed - 300);
+        assert_eq!(
+            Contract::get_force_close_audit(env.clone()),
+            Vec::from_array(&env, [record])
+        );
+
+        // The approval was consumed with the entry
+        assert_eq!(
+            Contract::force_close_position(
+                env.clone(),
+                admin.to_string(),
+                user.clone(),
+                resolution
+            ),
+            Err(ProtocolError::InvalidOperation)
+        );
+    });
+}
+#[test]
+fn test_emergency_forgiveness_waits_for_delay() {
+    use crate::forgiveness::EMERGENCY_FORGIVENESS_DELAY_SECS;
+    use crate::reserves::ReserveStorage;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 400, 0).unwrap();
+        let mut position = StateHelper::get_position(&env, &user).unwrap();
+        position.borrow_interest = 80;
+        StateHelper::save_position(&env, &position);
+        ReserveStorage::add(&env, &token, 1000);
+
+        Contract::schedule_emergency_forgiveness(
+            env.clone(),
+            admin.to_string(),
+            token.clone(),
+            Vec::from_array(&env, [user.clone()]),
+            10000,
+        )
+        .unwrap();
+        assert_eq!(
+            Contract::execute_emergency_forgiveness(env.clone(), admin.to_string()),
+            Err(ProtocolError::InvalidOperation)
+        );
+
+        env.ledger()
+            .with_mut(|l| l.timestamp += EMERGENCY_FORGIVENESS_DELAY_SECS);
+        let forgiven =
+            Contract::execute_emergency_forgiveness(env.clone(), admin.to_string()).unwrap();
+        let position = StateHelper::get_position(&env, &user).unwrap();
+        assert_eq!(position.borrow_interest, 0);
+        assert_eq!(position.debt, 400);
+        assert_eq!(
+            Contract::get_protocol_reserves(env.clone(), token.clone()),
+            1000 - forgiven
+        );
+        assert!(Contract::get_pending_forgiveness(env.clone()).is_none());
+    });
+}
+
+#[cfg(not(feature = "amm"))]
+#[test]
+fn test_swap_hooks_report_feature_disabled() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let user = TestUtils::create_user_address(&env, 0);
+    let (_admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+
+    env.as_contract(&contract_id, || {
+        let result = Contract::deleverage_swap_hook(
+            env.clone(),
+            user.clone(),
+            token.clone(),
+            token.clone(),
+            100,
+            0,
+            0,
+        );
+        assert_eq!(result, Err(ProtocolError::FeatureDisabled));
+        let caps = Contract::get_capabilities(env.clone());
+        assert!(!caps.amm);
+        assert!(!caps
+            .governance_actions
+            .contains(Symbol::new(&env, "set_pair_fee")));
+    });
+}
+
+#[test]
+fn test_utilization_ema_smooths_spikes_and_converges() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (_admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+
+    env.as_contract(&contract_id, || {
+        env.ledger().with_mut(|l| l.timestamp = 1_000);
+        InterestRateStorage::adjust_totals(&env, 1_000_000, 100_000);
+        let baseline = InterestRateStorage::update_state(&env);
+        assert_eq!(baseline.utilization_ema, 10_000_000);
+
+        // Whale borrows and repays within one block
+        env.ledger().with_mut(|l| l.timestamp += 5);
+        InterestRateStorage::adjust_totals(&env, 0, 800_000);
+        InterestRateStorage::update_state(&env);
+        let spiked = Contract::get_asset_state(env.clone(), token.clone()).unwrap();
+        assert_eq!(spiked.spot_utilization, 90_000_000);
+        assert!(spiked.smoothed_utilization - 10_000_000 < 100_000);
+        assert!(spiked.borrow_rate - baseline.current_borrow_rate < 10_000);
+
+        // Sustained high utilization converges toward spot
+        for _ in 0..60 {
+            env.ledger().with_mut(|l| l.timestamp += 3_600);
+            InterestRateStorage::update_state(&env);
+        }
+        let sustained = Contract::get_asset_state(env.clone(), token.clone()).unwrap();
+        assert!(90_000_000 - sustained.smoothed_utilization < 200_000);
+    });
+}
+
+#[test]
+fn test_daily_snapshot_reports_time_weighted_utilization() {
+    use crate::analytics::AnalyticsModule;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (_admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+
+    env.as_contract(&contract_id, || {
+        let day = 86_400u64;
+        let snapshot_at = |ts: u64| {
+            env.ledger().with_mut(|l| l.timestamp = ts);
+            AnalyticsModule::record_activity(&env, &user, "deposit", 0, None).unwrap();
+            Contract::get_daily_snapshot(env.clone(), ts / day).unwrap()
+        };
+
+        // First snapshot has no history and falls back to spot
+        env.ledger().with_mut(|l| l.timestamp = 10 * day);
+        InterestRateStorage::adjust_totals(&env, 1_000_000, 200_000);
+        InterestRateStorage::update_state(&env);
+        assert_eq!(snapshot_at(10 * day).avg_utilization, 20_000_000);
+        assert_eq!(snapshot_at(11 * day).avg_utilization, 20_000_000);
+
+        // 20% for half the day, 80% for the other half
+        env.ledger().with_mut(|l| l.timestamp = 11 * day + day / 2);
+        InterestRateStorage::adjust_totals(&env, 0, 600_000);
+        InterestRateStorage::update_state(&env);
+        let point = snapshot_at(12 * day);
+        assert_eq!(point.avg_utilization, 50_000_000);
+        assert!(point.twa.utilization_seconds > 0);
+
+        let state = Contract::get_asset_state(env.clone(), token.clone()).unwrap();
+        assert_eq!(state.spot_utilization, 80_000_000);
+        assert_eq!(state.avg_utilization, 50_000_000);
+    });
+}
+
+#[test]
+fn test_index_checkpoints_follow_daily_accruals() {
+    use crate::checkpoints::MAX_INDEX_CHECKPOINTS;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (_admin, contract_id, token) = TestUtils::setup_contract_with_token(&env, &[]);
+
+    env.as_contract(&contract_id, || {
+        let day = 86_400u64;
+        env.ledger().with_mut(|l| l.timestamp = 10 * day + 100);
+        InterestRateStorage::adjust_totals(&env, 1_000_000, 500_000);
+        InterestRateStorage::update_state(&env);
+
+        // One checkpoint per day, written by that day's first accrual
+        for d in 11..14u64 {
+            env.ledger().with_mut(|l| l.timestamp = d * day + 100);
+            Contract::accrue(env.clone());
+            env.ledger().with_mut(|l| l.timestamp = d * day + 5_000);
+            Contract::accrue(env.clone());
+        }
+        let first = Contract::get_index_checkpoint(env.clone(), token.clone(), 11).unwrap();
+        let second = Contract::get_index_checkpoint(env.clone(), token.clone(), 12).unwrap();
+        assert_eq!(first.2, 11 * day + 100);
+        assert_eq!(second.2, 12 * day + 100);
+        assert!(second.0 > first.0 && second.1 > first.1);
+        assert!(second.0 > second.1);
+        assert_eq!(
+            Contract::get_index_checkpoint(env.clone(), token.clone(), 14),
+            None
+        );
+
+        // Interpolation hits checkpoints exactly and lies between them
+        let at = |ts| Contract::interpolate_index(env.clone(), token.clone(), ts);
+        assert_eq!(at(first.2), Some((first.0, first.1)));
+        let (borrow, supply) = at(first.2 + day / 2).unwrap();
+        assert_eq!(borrow, first.0 + (second.0 - first.0) / 2);
+        assert!(supply > first.1 && supply < second.1);
+        // The live index extends the range past the last checkpoint
+        assert!(at(13 * day + 1_000).is_some());
+        assert_eq!(at(13 * day + 5_001), None);
+        assert_eq!(at(day), None);
+
+        // Bounded to the most recent days
+        for d in 14..(14 + MAX_INDEX_CHECKPOINTS as u64) {
+            env.ledger().with_mut(|l| l.timestamp = d * day);
+            Contract::accrue(env.clone());
+        }
+        assert_eq!(
+            Contract::get_index_checkpoint(env.clone(), token.clone(), 11),
+            None
+        );
+        assert!(Contract::get_index_checkpoint(env.clone(), token, 14).is_some());
+    });
+}
+#[test]
+fn test_initialize_persists_every_default() {
+    use crate::defaults;
+    use crate::governance::GovStorage;
+    use crate::oracle::OracleStorage;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let admin = TestUtils::create_admin_address(&env);
+    let contract_id = env.register(Contract, ());
+
+    env.as_contract(&contract_id, || {
+        Contract::initialize(env.clone(), admin.to_string()).unwrap();
+
+        for key in [
+            "min_ratio",
+            "flash_fee_bps",
+            "origination_buffer_bps",
+            "gov_quorum_bps",
+            "gov_timelock",
+            "gov_min_voting_period",
+            "gov_proposer_cooldown",
+            "gov_epoch_budget",
+            "oracle_heartbeat_ttl",
+            "oracle_mode",
+            "oracle_deviation_bps",
+            "oracle_trim_count",
+            "oracle_twap_window",
+            "oracle_twap_buffer_len",
+            "oracle_ema_alpha",
+            "oracle_min_sources",
+            "oracle_max_sources",
+            "oracle_max_change_bps",
+            "oracle_max_src_failures",
+            "oracle_price_cache_ttl",
+            "oracle_clamp_blocks_liq",
+            "oracle_max_freeze",
+            "credit_weights",
+            "risk_snapshot_interval",
+            "sweep_deviation_bps",
+            "payout_max_slip_bps",
+            "risk_max_spread_bps",
+            "position_transfer_window",
+            "rate_event_threshold",
+            "interest_config",
+            "risk_config",
+        ] {
+            assert!(
+                env.storage().instance().has(&Symbol::new(&env, key)),
+                "{key} not persisted"
+            );
+        }
+
+        assert_eq!(
+            ProtocolConfig::get_min_collateral_ratio(&env),
+            defaults::MIN_COLLATERAL_RATIO
+        );
+        assert_eq!(
+            ProtocolConfig::get_flash_loan_fee_bps(&env),
+            defaults::FLASH_LOAN_FEE_BPS
+        );
+        assert_eq!(
+            ProtocolConfig::get_origination_buffer_bps(&env),
+            defaults::ORIGINATION_BUFFER_BPS
+        );
+        assert_eq!(GovStorage::get_quorum_bps(&env), defaults::GOV_QUORUM_BPS);
+        assert_eq!(GovStorage::get_timelock(&env), defaults::GOV_TIMELOCK_SECS);
+        assert_eq!(
+            GovStorage::get_min_voting_period(&env),
+            defaults::GOV_MIN_VOTING_PERIOD_SECS
+        );
+        assert_eq!(
+            GovStorage::get_proposer_cooldown(&env),
+            defaults::GOV_PROPOSER_COOLDOWN_SECS
+        );
+        assert_eq!(
+            OracleStorage::get_heartbeat_ttl(&env),
+            defaults::ORACLE_HEARTBEAT_TTL_SECS
+        );
+        assert_eq!(OracleStorage::get_mode(&env), defaults::ORACLE_MODE);
+        assert_eq!(
+            OracleStorage::get_deviation_bps(&env),
+            defaults::ORACLE_DEVIATION_BPS
+        );
+        assert_eq!(
+            OracleStorage::get_trim_count(&env),
+            defaults::ORACLE_TRIM_COUNT
+        );
+        assert_eq!(
+            OracleStorage::get_twap_window(&env),
+            defaults::ORACLE_TWAP_WINDOW
+        );
+        assert_eq!(
+            OracleStorage::get_price_cache_ttl(&env),
+            defaults::ORACLE_PRICE_CACHE_TTL_SECS
+        );
+        assert_eq!(
+            OracleStorage::get_block_liquidation_on_clamp(&env),
+            defaults::ORACLE_BLOCK_LIQUIDATION_ON_CLAMP
+        );
+    });
+}
+
+#[cfg(feature = "amm")]
+fn run_flash_swap(
+    repay_a: i128,
+    repay_b: i128,
+) -> Result<(i128, i128), Result<ProtocolError, soroban_sdk::InvokeError>> {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_admin, contract_id, _user, pair_id) = setup_amm_pool(&env);
+    let receiver = env.register(MockFlashSwapReceiver, ());
+    for token in [pair_id.asset_a.clone(), pair_id.asset_b.clone()] {
+        env.as_contract(&token, || {
+            MockToken::mint(env.clone(), receiver.clone(), 10_000);
+        });
+    }
+    env.as_contract(&receiver, || {
+        MockFlashSwapReceiver::configure(env.clone(), contract_id.clone(), repay_a, repay_b);
+    });
+
+    let client = ContractClient::new(&env, &contract_id);
+    let result = client.try_flash_swap(
+        &receiver,
+        &pair_id,
+        &1_000,
+        &0,
+        &receiver,
+        &soroban_sdk::Bytes::new(&env),
+        &0,
+    );
+    let pool = client.get_pool_info(&pair_id);
+    if result.is_err() {
+        // Reverted swaps leave the pool untouched
+        assert_eq!((pool.reserve_a, pool.reserve_b), (100_000, 100_000));
+    }
+    result.map(|r| r.unwrap())
+}
+
+#[cfg(feature = "amm")]
+#[test]
+fn test_flash_swap_repaid_in_kind() {
+    // 1000 out needs ceil(1000 / 0.997) = 1004 back at the 0.3% fee
+    assert_eq!(run_flash_swap(1_004, 0), Ok((1_004, 0)));
+}
+
+#[cfg(feature = "amm")]
+#[test]
+fn test_flash_swap_repaid_in_other_token() {
+    assert_eq!(run_flash_swap(0, 1_020), Ok((0, 1_020)));
+}
+
+#[cfg(feature = "amm")]
+#[test]
+fn test_flash_swap_under_repaid_reverts() {
+    assert_eq!(
+        run_flash_swap(1_000, 0),
+        Err(Ok(ProtocolError::FlashSwapInvariantViolated))
+    );
+}
+
+#[test]
+fn test_freeze_user_records_reason_and_case_ref() {
+    use soroban_sdk::BytesN;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, _token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    let case_ref = BytesN::from_array(&env, &[7u8; 32]);
+
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::freeze_user(
+            env.clone(),
+            admin.to_string(),
+            user.clone(),
+            42,
+            Some(case_ref.clone()),
+            None,
+        )
+        .unwrap();
+
+        let info = Contract::get_freeze_info(env.clone(), user.clone()).unwrap();
+        assert_eq!(info.reason_code, 42);
+        assert_eq!(info.case_ref, Some(case_ref.clone()));
+        assert_eq!(info.frozen_by, admin);
+        let profile = Contract::get_user_profile(env.clone(), user.clone()).unwrap();
+        assert!(profile.is_frozen);
+        assert_eq!(profile.freeze_case_ref, Some(case_ref.clone()));
+        assert_eq!(profile.freeze_info(), Some(info));
+
+        Contract::unfreeze_user(env.clone(), admin.to_string(), user.clone(), None).unwrap();
+        assert_eq!(Contract::get_freeze_info(env.clone(), user.clone()), None);
+    });
+}
+
+#[test]
+fn test_frozen_borrow_emits_blocked_event_with_reason() {
+    use soroban_sdk::testutils::Events;
+    use soroban_sdk::{IntoVal, Val};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, _token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+        Contract::freeze_user(env.clone(), admin.to_string(), user.clone(), 9, None, None).unwrap();
+
+        let result = Contract::borrow(env.clone(), user.to_string(), 100, 0);
+        assert_eq!(result, Err(ProtocolError::UserSuspended));
+
+        let (_, topics, data) = env.events().all().last().unwrap();
+        let name: Symbol = topics.get(0).unwrap().into_val(&env);
+        assert_eq!(name, Symbol::new(&env, "user_blocked"));
+        let data: Vec<Val> = data.into_val(&env);
+        let operation: Symbol = data.get(3).unwrap().into_val(&env);
+        let reason: u32 = data.get(5).unwrap().into_val(&env);
+        assert_eq!(operation, Symbol::new(&env, "borrow"));
+        assert_eq!(reason, 9);
+    });
+}
+
+#[test]
+fn test_batch_freeze_and_unfreeze() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let users = [
+        TestUtils::create_user_address(&env, 0),
+        TestUtils::create_user_address(&env, 1),
+    ];
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &users);
+
+    env.as_contract(&contract_id, || {
+        let batch = Vec::from_array(&env, users.clone());
+        Contract::freeze_users(env.clone(), admin.to_string(), batch.clone(), 3, None, None)
+            .unwrap();
+        for user in users.iter() {
+            assert_eq!(
+                Contract::get_freeze_info(env.clone(), user.clone()).map(|i| i.reason_code),
+                Some(3)
+            );
+        }
+
+        Contract::unfreeze_users(env.clone(), admin.to_string(), batch, None).unwrap();
+        for user in users.iter() {
+            assert!(
+                !Contract::get_user_profile(env.clone(), user.clone())
+                    .unwrap()
+                    .is_frozen
+            );
+        }
+
+        let mut oversized = Vec::new(&env);
+        for _ in 0..=MAX_FREEZE_BATCH {
+            oversized.push_back(Address::generate(&env));
+        }
+        assert_eq!(
+            Contract::freeze_users(env.clone(), admin.to_string(), oversized, 3, None, None),
+            Err(ProtocolError::InvalidParameters)
+        );
+    });
+}
+
+#[test]
+fn test_propose_enforces_min_voting_period_and_cooldown() {
+    use crate::defaults;
+    use crate::governance::{Action, Governance};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let proposer = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, _token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&proposer));
+
+    env.as_contract(&contract_id, || {
+        let title = String::from_str(&env, "raise caps");
+        let min = defaults::GOV_MIN_VOTING_PERIOD_SECS;
+        assert_eq!(
+            Governance::propose(&env, &proposer, title.clone(), gov_actions(&env), min - 1),
+            Err(ProtocolError::InvalidParameters)
+        );
+
+        Governance::propose(&env, &proposer, title.clone(), gov_actions(&env), min).unwrap();
+        assert_eq!(
+            Governance::propose(&env, &proposer, title.clone(), gov_actions(&env), min),
+            Err(ProtocolError::ProposalCooldown)
+        );
+        // The admin is exempt from the cooldown
+        Governance::propose(&env, &admin, title.clone(), gov_actions(&env), min).unwrap();
+        Governance::propose(&env, &admin, title.clone(), gov_actions(&env), min).unwrap();
+
+        env.ledger()
+            .with_mut(|l| l.timestamp += defaults::GOV_PROPOSER_COOLDOWN_SECS);
+        Governance::propose(&env, &proposer, title.clone(), gov_actions(&env), min).unwrap();
+
+        govern(&env, &admin, Action::SetProposerCooldown(0)).unwrap();
+        Governance::propose(&env, &proposer, title, gov_actions(&env), min).unwrap();
+
+        let too_long = crate::governance::MAX_GOV_PERIOD_SECS + 1;
+        assert_eq!(
+            govern(&env, &admin, Action::SetMinVotingPeriod(too_long)),
+            Err(ProtocolError::InvalidParameters)
+        );
+    });
+}
+
+#[cfg(feature = "amm")]
+#[test]
+fn test_buyback_and_burn_spends_reserves() {
+    use crate::buyback::BuybackOrder;
+    use crate::governance::Action;
+    use crate::reserves::ReserveStorage;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let (admin, contract_id, _user, pair_id) = setup_amm_pool(&env);
+    let burn = Address::generate(&env);
+
+    let (reserve_asset, token) = env.as_contract(&contract_id, || {
+        let asset = TokenRegistry::primary_asset(&env).unwrap();
+        ReserveStorage::add(&env, &asset, 5_000);
+        Contract::set_burn_address(env.clone(), admin.clone(), burn.clone()).unwrap();
+        let token = if asset == pair_id.asset_a {
+            pair_id.asset_b.clone()
+        } else {
+            pair_id.asset_a.clone()
+        };
+        (asset, token)
+    });
+    let order = BuybackOrder {
+        asset: reserve_asset.clone(),
+        amount: 1_000,
+        min_tokens_out: 0,
+        pair_id: pair_id.clone(),
+    };
+
+    env.as_contract(&contract_id, || {
+        govern(&env, &admin, Action::BuybackAndBurn(order.clone())).unwrap();
+        assert_eq!(ReserveStorage::get(&env, &reserve_asset), 4_000);
+    });
+    // 1000 in at 0.3% fee against 100k/100k
+    let burned = env.as_contract(&token, || MockToken::balance(env.clone(), burn.clone()));
+    assert_eq!(burned, 987);
+}
+
+#[cfg(feature = "amm")]
+#[test]
+fn test_buyback_slippage_leaves_reserves_untouched() {
+    use crate::buyback::BuybackOrder;
+    use crate::governance::Action;
+    use crate::reserves::ReserveStorage;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let (admin, contract_id, _user, pair_id) = setup_amm_pool(&env);
+    let burn = Address::generate(&env);
+
+    env.as_contract(&contract_id, || {
+        let asset = TokenRegistry::primary_asset(&env).unwrap();
+        ReserveStorage::add(&env, &asset, 5_000);
+        Contract::set_burn_address(env.clone(), admin.clone(), burn.clone()).unwrap();
+        let pool_before = Contract::get_pool_info(env.clone(), pair_id.clone()).unwrap();
+
+        let mut order = BuybackOrder {
+            asset: asset.clone(),
+            amount: 1_000,
+            min_tokens_out: 1_000,
+            pair_id: pair_id.clone(),
+        };
+        assert_eq!(
+            govern(&env, &admin, Action::BuybackAndBurn(order.clone())),
+            Err(ProtocolError::SlippageProtectionTriggered)
+        );
+        order.amount = 6_000;
+        order.min_tokens_out = 0;
+        assert_eq!(
+            govern(&env, &admin, Action::BuybackAndBurn(order)),
+            Err(ProtocolError::InsufficientLiquidity)
+        );
+        assert_eq!(ReserveStorage::get(&env, &asset), 5_000);
+        assert_eq!(
+            Contract::get_pool_info(env.clone(), pair_id.clone()).unwrap(),
+            pool_before
+        );
+    });
+}
+
+#[test]
+fn test_global_tvl_cap_limits_deposits() {
+    use crate::governance::Action;
+    use crate::oracle::{Oracle, OracleSource, PRICE_SCALE};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    let oracle = env.register(MockPriceOracle, ());
+    env.as_contract(&oracle, || {
+        MockPriceOracle::set_price(env.clone(), token.clone(), 2 * PRICE_SCALE);
+    });
+
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Oracle::set_source(
+            &env,
+            &admin,
+            &token,
+            OracleSource::new(oracle.clone(), 1, 0),
+        )
+        .unwrap();
+        govern(&env, &admin, Action::SetGlobalTvlCap(3000)).unwrap();
+
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+        Contract::deposit_collateral(env.clone(), user.to_string(), 500).unwrap();
+        let status = Contract::get_system_status(env.clone()).unwrap();
+        assert_eq!(status.total_value_locked, 1500);
+        assert_eq!(status.tvl_usd, 3000);
+        assert_eq!(status.tvl_cap_headroom, 0);
+        assert_eq!(
+            Contract::deposit_collateral(env.clone(), user.to_string(), 1),
+            Err(ProtocolError::GlobalCapExceeded)
+        );
+
+        govern(&env, &admin, Action::SetGlobalTvlCap(0)).unwrap();
+        // Passing the proposal took longer than the heartbeat allows
+        Oracle::heartbeat(&env, &oracle, &token).unwrap();
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1).unwrap();
+        let status = Contract::get_system_status(env.clone()).unwrap();
+        assert_eq!(status.global_tvl_cap, 0);
+        assert_eq!(status.tvl_cap_headroom, i128::MAX);
+    });
+}
+
+#[test]
+fn test_liquidation_protocol_fee_splits_seized_collateral() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let liquidator = TestUtils::create_user_address(&env, 1);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, &[user.clone(), liquidator.clone()]);
+
+    let result = env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        TestUtils::verify_user(&env, &admin, &liquidator);
+        setup_liquidatable_position(&env, &admin, &user);
+        // 5% of the repaid amount out of the 10% bonus
+        Contract::set_liquidation_protocol_fee(env.clone(), admin.to_string(), 500, None).unwrap();
+
+        let quote = Contract::preview_liquidation(env.clone(), user.clone(), 200).unwrap();
+        assert_eq!(quote.collateral_seized, 220);
+        assert_eq!(quote.protocol_fee, 10);
+        assert_eq!(quote.liquidator_collateral, 210);
+
+        let fund_before = Contract::get_emergency_state(env.clone())
+            .unwrap()
+            .fund
+            .balance;
+        let result = Contract::liquidate(
+            env.clone(),
+            liquidator.to_string(),
+            user.to_string(),
+            200,
+            0,
+            false,
+            0,
+            false,
+        )
+        .unwrap();
+        assert_eq!(result.collateral_seized, quote.collateral_seized);
+        assert_eq!(result.protocol_fee, quote.protocol_fee);
+        assert_eq!(result.liquidator_collateral, quote.liquidator_collateral);
+
+        // Borrower loses X = liquidator Y + insurance Z
+        let borrower = Contract::get_position(env.clone(), user.to_string()).unwrap();
+        assert_eq!(borrower.0, 1000 - result.collateral_seized);
+        let fund_after = Contract::get_emergency_state(env.clone())
+            .unwrap()
+            .fund
+            .balance;
+        assert_eq!(fund_after - fund_before, result.protocol_fee);
+        assert_eq!(
+            result.collateral_seized,
+            result.liquidator_collateral + result.protocol_fee
+        );
+        result
+    });
+
+    env.as_contract(&token, || {
+        assert_eq!(
+            MockToken::balance(env.clone(), liquidator.clone()),
+            1_000_000 - result.debt_repaid + result.liquidator_collateral
+        );
+    });
+}
+
+#[test]
+fn test_liquidation_protocol_fee_bounded_by_incentive() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    env.as_contract(&contract_id, || {
+        // Default incentive is 10%
+        assert_eq!(
+            Contract::set_liquidation_protocol_fee(env.clone(), admin.to_string(), 1001, None),
+            Err(ProtocolError::InvalidParameters)
+        );
+        Contract::set_liquidation_protocol_fee(env.clone(), admin.to_string(), 1000, None).unwrap();
+        // Lowering the incentive below the fee is refused
+        assert_eq!(
+            Contract::set_risk_params(env.clone(), admin.to_string(), 50000000, 5000000),
+            Err(ProtocolError::InvalidParameters)
+        );
+    });
+}
+
+#[test]
+fn test_proposal_pages_report_growing_total() {
+    use crate::governance::{GovStorage, Governance};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(Contract, ());
+    env.as_contract(&contract_id, || {
+        let title = String::from_str(&env, "p");
+        for _ in 0..3 {
+            Governance::propose(
+                &env,
+                &Address::generate(&env),
+                title.clone(),
+                gov_actions(&env),
+                100,
+            )
+            .unwrap();
+        }
+
+        let first = GovStorage::list_proposals(&env, 0, 2);
+        assert_eq!(first.items.len(), 2);
+        assert_eq!(first.total, 3);
+        assert_eq!(first.cursor, 2);
+
+        // A proposal created mid-pagination is picked up by the next page
+        Governance::propose(
+            &env,
+            &Address::generate(&env),
+            title,
+            gov_actions(&env),
+            100,
+        )
+        .unwrap();
+        let second = GovStorage::list_proposals(&env, first.cursor, 2);
+        assert_eq!(second.total, 4);
+        assert_eq!(second.items.len(), 2);
+        assert_eq!(second.items.get(0).unwrap().id, 3);
+        assert_eq!(second.cursor as u64, second.total);
+
+        let done = GovStorage::list_proposals(&env, second.cursor, 2);
+        assert!(done.items.is_empty());
+        assert_eq!(done.cursor, second.cursor);
+    });
+}
+
+#[test]
+fn test_votes_write_tally_without_rewriting_proposal() {
+    use crate::governance::{GovStorage, Governance, Proposal};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(Contract, ());
+    env.as_contract(&contract_id, || {
+        let title = String::from_str(&env, "a reasonably long proposal title for sizing");
+        let mut ids = [0u64; 8];
+        for id in ids.iter_mut() {
+            *id = Governance::propose(
+                &env,
+                &Address::generate(&env),
+                title.clone(),
+                gov_actions(&env),
+                100,
+            )
+            .unwrap()
+            .id;
+        }
+        let metadata = |id: u64| {
+            let map: Map<u64, Proposal> = env
+                .storage()
+                .instance()
+                .get(&Symbol::new(&env, "gov_proposals"))
+                .unwrap();
+            map.get(id).unwrap()
+        };
+
+        // The metadata entry keeps its propose-time counts; views merge the tally
+        let before = metadata(ids[0]);
+        cast_vote(&env, ids[0], &Address::generate(&env), true, 10).unwrap();
+        cast_vote(&env, ids[0], &Address::generate(&env), false, 4).unwrap();
+        assert_eq!(metadata(ids[0]), before);
+        let tally = Contract::get_proposal_tally(env.clone(), ids[0]);
+        assert_eq!((tally.for_votes, tally.against_votes), (10, 4));
+        let merged = Contract::get_proposal(env.clone(), ids[0]).unwrap();
+        assert_eq!((merged.for_votes, merged.against_votes), (10, 4));
+        let listed = GovStorage::list_proposals(&env, 0, 1).items.get(0).unwrap();
+        assert_eq!(listed, merged);
+
+        // A proposal with votes embedded in its metadata moves them over
+        let mut legacy = metadata(ids[1]);
+        legacy.for_votes = 7;
+        GovStorage::save_proposal(&env, &legacy);
+        assert_eq!(
+            Contract::get_proposal_tally(env.clone(), ids[1]).for_votes,
+            7
+        );
+        cast_vote(&env, ids[1], &Address::generate(&env), true, 3).unwrap();
+        assert_eq!(
+            Contract::get_proposal_tally(env.clone(), ids[1]).for_votes,
+            10
+        );
+
+        // Per-vote cost against the old vote, which also rewrote the proposals
+        let mut budget = env.cost_estimate().budget();
+        budget.reset_unlimited();
+        cast_vote(&env, ids[2], &Address::generate(&env), true, 1).unwrap();
+        let split = (budget.cpu_instruction_cost(), budget.memory_bytes_cost());
+        budget.reset_unlimited();
+        let p = cast_vote(&env, ids[3], &Address::generate(&env), true, 1).unwrap();
+        GovStorage::save_proposal(&env, &p);
+        let rewrite = (budget.cpu_instruction_cost(), budget.memory_bytes_cost());
+        assert!(split.0 < rewrite.0, "cpu {} >= {}", split.0, rewrite.0);
+        assert!(split.1 < rewrite.1, "memory {} >= {}", split.1, rewrite.1);
+    });
+}
+
+#[test]
+fn test_scan_pages_report_index_total() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let users = [
+        Address::generate(&env),
+        Address::generate(&env),
+        Address::generate(&env),
+    ];
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &users);
+
+    env.as_contract(&contract_id, || {
+        for user in users[..2].iter() {
+            TestUtils::verify_user(&env, &admin, user);
+            Contract::deposit_collateral(env.clone(), user.to_string(), 100).unwrap();
+        }
+        let first = Contract::scan_positions(env.clone(), 0, 1);
+        assert_eq!(first.total, 2);
+        assert_eq!(first.items.len(), 1);
+
+        TestUtils::verify_user(&env, &admin, &users[2]);
+        Contract::deposit_collateral(env.clone(), users[2].to_string(), 100).unwrap();
+        let rest = Contract::scan_positions(env.clone(), first.cursor, 10);
+        assert_eq!(rest.total, 3);
+        assert_eq!(rest.items.len(), 2);
+        assert_eq!(rest.items.get(1).unwrap().borrower, users[2]);
+        assert_eq!(rest.cursor as u64, rest.total);
+    });
+}
+
+/// The most recent `config_changed` event, skipping events emitted after it
+fn last_config_change(env: &Env) -> crate::config::ConfigChanged {
+    use soroban_sdk::testutils::Events;
+    use soroban_sdk::{IntoVal, TryFromVal};
+
+    let name = Symbol::new(env, "config_changed");
+    let (_, _, data) = env
+        .events()
+        .all()
+        .iter()
+        .rev()
+        .find(|(_, topics, _)| {
+            topics
+                .get(0)
+                .and_then(|topic| Symbol::try_from_val(env, &topic).ok())
+                == Some(name.clone())
+        })
+        .unwrap();
+    data.into_val(env)
+}
+
+#[test]
+fn test_parameter_setters_emit_config_changed() {
+    use crate::config::ConfigChanged;
+    use crate::governance::Action;
+    use soroban_sdk::testutils::Events;
+    use soroban_sdk::IntoVal;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    env.as_contract(&contract_id, || {
+        Contract::set_liquidation_protocol_fee(env.clone(), admin.to_string(), 300, None).unwrap();
+        let (_, topics, data) = env.events().all().last().unwrap();
+        let name: Symbol = topics.get(0).unwrap().into_val(&env);
+        assert_eq!(name, Symbol::new(&env, "config_changed"));
+        let change: ConfigChanged = data.into_val(&env);
+        assert_eq!(change.scope, Symbol::new(&env, "risk"));
+        assert_eq!(
+            change.key,
+            Symbol::new(&env, "liquidation_protocol_fee_bps")
+        );
+        assert_eq!((change.old, change.new), (0, 300));
+        assert_eq!(change.via_proposal, None);
+
+        // Governance-executed changes go through the same setters
+        let id = govern(&env, &admin, Action::SetGlobalTvlCap(5000)).unwrap();
+        let change = last_config_change(&env);
+        assert_eq!(change.scope, Symbol::new(&env, "caps"));
+        assert_eq!(change.key, Symbol::new(&env, "global_tvl_cap"));
+        assert_eq!((change.old, change.new), (0, 5000));
+        assert_eq!(change.via_proposal, Some(id));
+    });
+}
+
+#[test]
+fn test_strict_mode_requires_registered_reason() {
+    use crate::governance::Action;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, _token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    env.as_contract(&contract_id, || {
+        let incident = Symbol::new(&env, "incident");
+        let last_change = || last_config_change(&env);
+
+        // Strict mode off: a reason is optional, but must be registered
+        Contract::set_liquidation_protocol_fee(env.clone(), admin.to_string(), 100, None).unwrap();
+        assert_eq!(
+            Contract::freeze_user(
+                env.clone(),
+                admin.to_string(),
+                user.clone(),
+                1,
+                None,
+                Some(incident.clone()),
+            ),
+            Err(ProtocolError::ReasonRequired)
+        );
+        Contract::register_reason_code(env.clone(), admin.to_string(), incident.clone()).unwrap();
+        assert_eq!(
+            Contract::get_reason_codes(env.clone()),
+            Vec::from_array(&env, [incident.clone()])
+        );
+
+        // Strict mode on: the reason is mandatory and lands in the event
+        Contract::set_feature_flag(
+            env.clone(),
+            admin.to_string(),
+            Symbol::new(&env, "strict_admin_reasons"),
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            Contract::set_liquidation_protocol_fee(env.clone(), admin.to_string(), 200, None),
+            Err(ProtocolError::ReasonRequired)
+        );
+        assert_eq!(
+            Contract::unfreeze_users(env.clone(), admin.to_string(), Vec::new(&env), None),
+            Err(ProtocolError::ReasonRequired)
+        );
+        Contract::set_liquidation_protocol_fee(
+            env.clone(),
+            admin.to_string(),
+            200,
+            Some(incident.clone()),
+        )
+        .unwrap();
+        let change = last_change();
+        assert_eq!((change.old, change.new), (100, 200));
+        assert_eq!(change.reason, Some(incident.clone()));
+        assert_eq!(change.via_proposal, None);
+        Contract::freeze_user(
+            env.clone(),
+            admin.to_string(),
+            user.clone(),
+            1,
+            None,
+            Some(incident.clone()),
+        )
+        .unwrap();
+
+        // Governance records the executed proposal instead of a reason
+        let id = govern(&env, &admin, Action::SetGlobalTvlCap(5000)).unwrap();
+        let change = last_config_change(&env);
+        assert_eq!(change.new, 5000);
+        assert_eq!(change.via_proposal, Some(id));
+        assert_eq!(change.reason, None);
+
+        // Removing the code makes it unusable again
+        Contract::remove_reason_code(env.clone(), admin.to_string(), incident.clone()).unwrap();
+        assert_eq!(
+            Contract::unfreeze_user(env.clone(), admin.to_string(), user.clone(), Some(incident)),
+            Err(ProtocolError::ReasonRequired)
+        );
+    });
+}
+#[test]
+fn test_address_setter_emits_config_addr_changed() {
+    use crate::config::ConfigAddressChanged;
+    use soroban_sdk::testutils::Events;
+    use soroban_sdk::IntoVal;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let treasury = Address::generate(&env);
+    env.as_contract(&contract_id, || {
+        Contract::set_treasury(env.clone(), admin.to_string(), treasury.clone()).unwrap();
+        let (_, topics, data) = env.events().all().last().unwrap();
+        let name: Symbol = topics.get(0).unwrap().into_val(&env);
+        assert_eq!(name, Symbol::new(&env, "config_addr_changed"));
+        let change: ConfigAddressChanged = data.into_val(&env);
+        assert_eq!(change.key, Symbol::new(&env, "treasury"));
+        assert_eq!(change.old, None);
+        assert_eq!(change.new, treasury);
+    });
+}
+
+#[test]
+fn test_overlapping_incentive_programs_pay_pro_rata_and_sweep_leftovers() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let u0 = TestUtils::create_user_address(&env, 0);
+    let u1 = TestUtils::create_user_address(&env, 1);
+    let sponsor = Address::generate(&env);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, &[u0.clone(), u1.clone()]);
+    #[allow(deprecated)]
+    let reward = env.register_contract(None, MockToken);
+    env.as_contract(&reward, || {
+        MockToken::mint(env.clone(), sponsor.clone(), 1_000_000);
+    });
+
+    env.ledger().with_mut(|l| l.timestamp = 1000);
+    let (a, b) = env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &u0);
+        TestUtils::verify_user(&env, &admin, &u1);
+        let fund = |amount: i128| {
+            Contract::fund_incentive(
+                env.clone(),
+                sponsor.to_string(),
+                token.clone(),
+                reward.clone(),
+                amount,
+                100,
+            )
+            .unwrap()
+        };
+
+        // Program A: 800 over [1000, 1100)
+        let a = fund(800);
+        Contract::deposit_collateral(env.clone(), u0.to_string(), 300).unwrap();
+        Contract::deposit_collateral(env.clone(), u1.to_string(), 100).unwrap();
+
+        // Program B: 400 over [1050, 1150), nobody participating until 1075
+        env.ledger().with_mut(|l| l.timestamp = 1050);
+        let b = fund(400);
+
+        env.ledger().with_mut(|l| l.timestamp = 1075);
+        Contract::deposit_collateral(env.clone(), u1.to_string(), 100).unwrap();
+        // u0 joins B through a claim; nothing is owed yet
+        assert_eq!(
+            Contract::claim_incentive(env.clone(), u0.to_string(), b).unwrap(),
+            0
+        );
+
+        env.ledger().with_mut(|l| l.timestamp = 1100);
+        assert_eq!(
+            Contract::sweep_incentive(env.clone(), sponsor.to_string(), b),
+            Err(ProtocolError::InvalidOperation)
+        );
+        (a, b)
+    });
+
+    env.ledger().with_mut(|l| l.timestamp = 1200);
+    env.as_contract(&contract_id, || {
+        // A: [1000,1075) 600 split 300:100, [1075,1100) 200 split 300:200
+        let claim = |user: &Address, id: u64| {
+            Contract::claim_incentive(env.clone(), user.to_string(), id).unwrap()
+        };
+        assert_eq!(claim(&u0, a), 450 + 120);
+        assert_eq!(claim(&u1, a), 150 + 80);
+        // B: [1075,1150) 300 split 300:200
+        assert_eq!(claim(&u0, b), 180);
+        assert_eq!(claim(&u1, b), 120);
+        assert_eq!(claim(&u0, b), 0);
+
+        assert_eq!(
+            Contract::sweep_incentive(env.clone(), u0.to_string(), b),
+            Err(ProtocolError::Unauthorized)
+        );
+        assert_eq!(
+            Contract::sweep_incentive(env.clone(), sponsor.to_string(), a).unwrap(),
+            0
+        );
+        // B emitted 100 before anyone was participating
+        assert_eq!(
+            Contract::sweep_incentive(env.clone(), sponsor.to_string(), b).unwrap(),
+            100
+        );
+        assert!(
+            Contract::get_incentive_program(env.clone(), b)
+                .unwrap()
+                .swept
+        );
+    });
+
+    env.as_contract(&reward, || {
+        assert_eq!(MockToken::balance(env.clone(), u0.clone()), 750);
+        assert_eq!(MockToken::balance(env.clone(), u1.clone()), 350);
+        assert_eq!(
+            MockToken::balance(env.clone(), sponsor.clone()),
+            1_000_000 - 1200 + 100
+        );
+    });
+}
+
+#[test]
+fn test_incentive_programs_per_asset_are_bounded() {
+    use crate::incentives::MAX_PROGRAMS_PER_ASSET;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let sponsor = TestUtils::create_user_address(&env, 0);
+    let (_admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&sponsor));
+    env.as_contract(&contract_id, || {
+        let fund = || {
+            Contract::fund_incentive(
+                env.clone(),
+                sponsor.to_string(),
+                token.clone(),
+                token.clone(),
+                10,
+                100,
+            )
+        };
+        for _ in 0..MAX_PROGRAMS_PER_ASSET {
+            fund().unwrap();
+        }
+        assert_eq!(fund(), Err(ProtocolError::StorageLimitExceeded));
+    });
+}
+
+#[test]
+fn test_failed_token_transfers_surface_as_typed_errors() {
+    use soroban_sdk::testutils::Events;
+    use soroban_sdk::{IntoVal, Val};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 100, 0).unwrap();
+    });
+    env.as_contract(&token, || MockToken::set_failing(env.clone(), true));
+
+    env.as_contract(&contract_id, || {
+        let before = Contract::get_position(env.clone(), user.to_string()).unwrap();
+        let failures = [
+            Contract::deposit_collateral(env.clone(), user.to_string(), 50),
+            Contract::withdraw(env.clone(), user.to_string(), 50, 0),
+            Contract::borrow(env.clone(), user.to_string(), 50, 0),
+            Contract::repay(env.clone(), user.to_string(), 50),
+            Contract::fund_incentive(
+                env.clone(),
+                user.to_string(),
+                token.clone(),
+                token.clone(),
+                50,
+                100,
+            )
+            .map(|_| ()),
+        ];
+        for result in failures {
+            assert_eq!(result, Err(ProtocolError::TokenTransferFailed));
+        }
+
+        let (_, topics, data) = env.events().all().last().unwrap();
+        let name: Symbol = topics.get(0).unwrap().into_val(&env);
+        assert_eq!(name, Symbol::new(&env, "transfer_failure"));
+        let data: Vec<Val> = data.into_val(&env);
+        let asset: Address = data.get(5).unwrap().into_val(&env);
+        let amount: i128 = data.get(7).unwrap().into_val(&env);
+        let reason: Symbol = data.get(9).unwrap().into_val(&env);
+        assert_eq!(asset, token);
+        assert_eq!(amount, 50);
+        assert_eq!(reason, Symbol::new(&env, "transfer_failed"));
+
+        // Nothing was written for any of the failed operations
+        assert_eq!(
+            Contract::get_position(env.clone(), user.to_string()).unwrap(),
+            before
+        );
+        assert_eq!(
+            crate::penalty::PenaltyStorage::get_lots(&env, &user)
+                .get(0)
+                .unwrap()
+                .amount,
+            1000
+        );
+        assert!(Contract::get_incentive_program(env.clone(), 1).is_none());
+    });
+}
+
+/// A borrower who repays on time next to one who was liquidated, both
+/// 200 days old
+fn build_credit_histories(env: &Env) -> (Address, Address, Address) {
+    let good = TestUtils::create_user_address(env, 0);
+    let risky = TestUtils::create_user_address(env, 1);
+    let liquidator = Address::generate(env);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(env, &[good.clone(), risky.clone()]);
+    env.as_contract(&token, || {
+        MockToken::mint(env.clone(), liquidator.clone(), 1_000_000);
+    });
+
+    env.ledger().with_mut(|l| l.timestamp = 1_000);
+    env.as_contract(&contract_id, || {
+        for user in [&good, &risky, &liquidator] {
+            TestUtils::verify_user(env, &admin, user);
+        }
+        Contract::deposit_collateral(env.clone(), good.to_string(), 1000).unwrap();
+        Contract::borrow(env.clone(), good.to_string(), 100, 0).unwrap();
+        setup_liquidatable_position(env, &admin, &risky);
+        Contract::liquidate(
+            env.clone(),
+            liquidator.to_string(),
+            risky.to_string(),
+            200,
+            0,
+            false,
+            0,
+            false,
+        )
+        .unwrap();
+    });
+
+    env.ledger().with_mut(|l| l.timestamp += 200 * 24 * 60 * 60);
+    env.as_contract(&contract_id, || {
+        Contract::repay(env.clone(), good.to_string(), 50).unwrap();
+        Contract::repay(env.clone(), good.to_string(), 50).unwrap();
+    });
+    (admin, contract_id, good)
+}
+
+#[test]
+fn test_credit_score_orders_borrowers_by_history() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (_admin, contract_id, good) = build_credit_histories(&env);
+    let risky = TestUtils::create_user_address(&env, 1);
+    env.as_contract(&contract_id, || {
+        let good_score = Contract::get_credit_score(env.clone(), good.clone());
+        let risky_score = Contract::get_credit_score(env.clone(), risky.clone());
+        // Full age, repayment and buffer points; interest is tiny
+        assert!(good_score >= 850, "good score {good_score}");
+        assert!(good_score <= 1000);
+        assert!(risky_score < good_score - 300, "risky score {risky_score}");
+        let fresh = Address::generate(&env);
+        assert_eq!(Contract::get_credit_score(env.clone(), fresh), 0);
+    });
+}
+
+#[test]
+fn test_credit_band_discount_applies_to_borrow_accrual() {
+    use crate::credit::{CreditBand, MAX_CREDIT_DISCOUNT_BPS};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (admin, contract_id, good) = build_credit_histories(&env);
+    let year = 365 * 24 * 60 * 60;
+    env.ledger().with_mut(|l| l.timestamp += year);
+    env.as_contract(&contract_id, || {
+        let bands = Vec::from_array(
+            &env,
+            [
+                CreditBand {
+                    min_score: 300,
+                    discount_bps: 50,
+                },
+                CreditBand {
+                    min_score: 800,
+                    discount_bps: MAX_CREDIT_DISCOUNT_BPS,
+                },
+            ],
+        );
+        Contract::set_credit_bands(env.clone(), admin.to_string(), bands).unwrap();
+        let too_deep = Vec::from_array(
+            &env,
+            [CreditBand {
+                min_score: 900,
+                discount_bps: MAX_CREDIT_DISCOUNT_BPS + 1,
+            }],
+        );
+        assert_eq!(
+            Contract::set_credit_bands(env.clone(), admin.to_string(), too_deep),
+            Err(ProtocolError::InvalidParameters)
+        );
+
+        // One year at 10% on 100_000 of debt
+        let accrue = |user: &Address| {
+            let mut position = Position::new(user.clone(), 1_000_000, 100_000);
+            position.last_accrual_time = env.ledger().timestamp() - year;
+            InterestRateManager::accrue_interest_for_position(&env, &mut position, 10_000_000, 0);
+            position.borrow_interest
+        };
+        let stranger = Address::generate(&env);
+
+        // Bands are ignored until discounts are switched on
+        assert_eq!(accrue(&good), 10_000);
+        Contract::set_credit_discounts(env.clone(), admin.to_string(), true).unwrap();
+        assert_eq!(accrue(&good), 9_800);
+        assert_eq!(accrue(&stranger), 10_000);
+    });
+}
+
+#[test]
+fn test_proposal_title_length_bounds() {
+    use crate::defaults;
+    use crate::governance::{Action, GovStorage, Governance};
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+
+    env.as_contract(&contract_id, || {
+        let period = defaults::GOV_MIN_VOTING_PERIOD_SECS;
+        let title = |len: usize| String::from_bytes(&env, &[b'a'; 256][..len]);
+        let max = defaults::GOV_MAX_TITLE_LEN as usize;
+        assert_eq!(GovStorage::get_max_title_len(&env), max as u32);
+
+        assert_eq!(
+            Governance::propose(&env, &admin, title(0), gov_actions(&env), period),
+            Err(ProtocolError::EmptyString)
+        );
+        Governance::propose(&env, &admin, title(1), gov_actions(&env), period).unwrap();
+        Governance::propose(&env, &admin, title(max), gov_actions(&env), period).unwrap();
+        assert_eq!(
+            Governance::propose(&env, &admin, title(max + 1), gov_actions(&env), period),
+            Err(ProtocolError::StringTooLong)
+        );
+
+        govern(&env, &admin, Action::SetMaxTitleLength(200)).unwrap();
+        Governance::propose(&env, &admin, title(200), gov_actions(&env), period).unwrap();
+        assert_eq!(
+            Governance::propose(&env, &admin, title(201), gov_actions(&env), period),
+            Err(ProtocolError::StringTooLong)
+        );
+
+        for len in [0, stellarlend_core::validation::MAX_STRING_LEN + 1] {
+            assert_eq!(
+                govern(&env, &admin, Action::SetMaxTitleLength(len)),
+                Err(ProtocolError::InvalidParameters)
+            );
+        }
+    });
+}
+
+#[test]
+fn test_stale_accrual_blocks_propose_until_poked() {
+    use crate::governance::Governance;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1000);
+
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    env.as_contract(&contract_id, || Contract::accrue(env.clone()));
+    env.ledger().with_mut(|l| l.timestamp += 2 * 60 * 60);
+    env.as_contract(&contract_id, || {
+        let title = String::from_str(&env, "rate change");
+        assert_eq!(
+            Governance::propose(
+                &env,
+                &admin,
+                title.clone(),
+                gov_actions(&env),
+                7 * 24 * 60 * 60
+            ),
+            Err(ProtocolError::StaleAccrual)
+        );
+        Contract::accrue(env.clone());
+        Governance::propose(&env, &admin, title, gov_actions(&env), 7 * 24 * 60 * 60).unwrap();
+    });
+}
+
+#[test]
+fn test_sync_positions_refreshes_voting_power() {
+    use crate::voting::VotingStorage;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1000);
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, _token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 2000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 1000, 0).unwrap();
+
+        let users = Vec::from_array(&env, [user.clone()]);
+        assert_eq!(
+            Contract::sync_positions(env.clone(), users.clone()),
+            Ok(1000)
+        );
+        assert_eq!(VotingStorage::get_power(&env, &user), 1000);
+
+        // A year of interest revalues the debt on the next sync
+        env.ledger().with_mut(|l| l.timestamp += 365 * 24 * 60 * 60);
+        let total = Contract::sync_positions(env.clone(), users).unwrap();
+        let position = StateHelper::get_position(&env, &user).unwrap();
+        assert_eq!(position.last_accrual_time, env.ledger().timestamp());
+        assert_eq!(total, crate::voting::VotingPower::of_position(&position));
+        assert_ne!(total, 1000);
+        assert_eq!(VotingStorage::get_total_power(&env), total);
+
+        let mut too_many = Vec::new(&env);
+        for _ in 0..11 {
+            too_many.push_back(Address::generate(&env));
+        }
+        assert_eq!(
+            Contract::sync_positions(env.clone(), too_many),
+            Err(ProtocolError::InvalidParameters)
+        );
+    });
+}
+
+/// Give `voter` `power` synced voting power and cast their vote with it
+fn cast_vote(
+    env: &Env,
+    id: u64,
+    voter: &Address,
+    support: bool,
+    power: i128,
+) -> Result<crate::governance::Proposal, ProtocolError> {
+    crate::voting::VotingStorage::set_power(env, voter, power);
+    crate::governance::Governance::vote(env, id, voter, support)
+}
+
+/// A single action that leaves governance as configured, for proposals
+/// whose effect does not matter
+fn gov_actions(env: &Env) -> Vec<crate::governance::Action> {
+    soroban_sdk::vec![
+        env,
+        crate::governance::Action::SetQuorumBps(crate::defaults::GOV_QUORUM_BPS)
+    ]
+}
+
+/// Propose `actions`, vote with the given weights, and execute the proposal
+fn pass_actions(
+    env: &Env,
+    admin: &Address,
+    actions: Vec<crate::governance::Action>,
+    for_votes: i128,
+    against_votes: i128,
+) -> Result<u64, ProtocolError> {
+    use crate::governance::Governance;
+
+    let proposal = Governance::propose(env, admin, String::from_str(env, "changes"), actions, 100)?;
+    cast_vote(env, proposal.id, &Address::generate(env), true, for_votes)?;
+    if against_votes > 0 {
+        cast_vote(
+            env,
+            proposal.id,
+            &Address::generate(env),
+            false,
+            against_votes,
+        )?;
+    }
+    env.ledger().with_mut(|l| l.timestamp += 100);
+    Governance::queue(env, proposal.id)?;
+    env.ledger().with_mut(|l| l.timestamp += 100);
+    assert!(Governance::execute(env, proposal.id)?.executed);
+    Ok(proposal.id)
+}
+
+/// Apply `action` the way governance does, through a passed proposal
+fn govern(
+    env: &Env,
+    admin: &Address,
+    action: crate::governance::Action,
+) -> Result<u64, ProtocolError> {
+    pass_actions(env, admin, soroban_sdk::vec![env, action], 10, 0)
+}
+
+#[test]
+fn test_change_budget_settings_need_supermajority() {
+    use crate::govbudget::BudgetStorage;
+    use crate::governance::Action;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1000);
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+
+    env.as_contract(&contract_id, || {
+        // 60% in favour is a majority but not a super-majority
+        let budget = soroban_sdk::vec![&env, Action::SetEpochBudget(30)];
+        assert_eq!(
+            pass_actions(&env, &admin, budget, 60, 40),
+            Err(ProtocolError::Unauthorized)
+        );
+        assert_eq!(
+            BudgetStorage::get_epoch_budget(&env),
+            crate::defaults::GOV_EPOCH_BUDGET
+        );
+
+        let actions = soroban_sdk::vec![
+            &env,
+            Action::SetEpochBudget(30),
+            Action::SetActionWeight(Symbol::new(&env, "risk"), 5),
+        ];
+        pass_actions(&env, &admin, actions, 70, 30).unwrap();
+        assert_eq!(BudgetStorage::get_epoch_budget(&env), 30);
+        assert_eq!(
+            BudgetStorage::get_weight(&env, &Symbol::new(&env, "risk")),
+            5
+        );
+    });
+}
+
+/// Propose `actions`, pass and queue the proposal, and wait out its timelock
+fn queue_with_actions(
+    env: &Env,
+    contract_id: &Address,
+    admin: &Address,
+    actions: Vec<crate::governance::Action>,
+) -> u64 {
+    use crate::governance::Governance;
+
+    let id = env.as_contract(contract_id, || {
+        let title = String::from_str(env, "actions");
+        let id = Governance::propose(env, admin, title, actions, 100)
+            .unwrap()
+            .id;
+        cast_vote(env, id, &Address::generate(env), true, 10).unwrap();
+        id
+    });
+    env.ledger().with_mut(|l| l.timestamp += 100);
+    env.as_contract(contract_id, || Governance::queue(env, id).unwrap());
+    env.ledger().with_mut(|l| l.timestamp += 100);
+    id
+}
+
+#[test]
+fn test_executed_proposal_applies_its_actions() {
+    use crate::governance::{Action, GovStorage, Governance, MAX_PROPOSAL_ACTIONS};
+    use crate::oracle::OracleStorage;
+    use soroban_sdk::{testutils::Events, IntoVal};
+
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1000);
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let client = ContractClient::new(&env, &contract_id);
+
+    env.as_contract(&contract_id, || {
+        let title = String::from_str(&env, "bounds");
+        let mut actions = Vec::new(&env);
+        assert_eq!(
+            Governance::propose(&env, &admin, title.clone(), actions.clone(), 100),
+            Err(ProtocolError::InvalidParameters)
+        );
+        for _ in 0..=MAX_PROPOSAL_ACTIONS {
+            actions.push_back(Action::SetTimelock(60));
+        }
+        assert_eq!(
+            Governance::propose(&env, &admin, title, actions, 100),
+            Err(ProtocolError::InvalidParameters)
+        );
+    });
+
+    let actions = soroban_sdk::vec![
+        &env,
+        Action::SetMinCollateralRatio(200),
+        Action::SetFlashLoanFeeBps(25),
+        Action::SetOracleHeartbeatTtl(500),
+        Action::SetQuorumBps(2000),
+        Action::SetTimelock(120),
+    ];
+    let id = queue_with_actions(&env, &contract_id, &admin, actions.clone());
+    assert_eq!(client.get_proposal_actions(&id), actions);
+    assert!(client.execute_proposal(&id).executed);
+
+    let (_, topics, data) = env.events().all().last().unwrap();
+    let name: Symbol = topics.get(1).unwrap().into_val(&env);
+    assert_eq!(name, Symbol::new(&env, "set_timelock"));
+    let executed: (u64, u32) = data.into_val(&env);
+    assert_eq!(executed, (id, 4));
+    env.as_contract(&contract_id, || {
+        assert_eq!(ProtocolConfig::get_min_collateral_ratio(&env), 200);
+        assert_eq!(ProtocolConfig::get_flash_loan_fee_bps(&env), 25);
+        assert_eq!(OracleStorage::get_heartbeat_ttl(&env), 500);
+        assert_eq!(GovStorage::get_quorum_bps(&env), 2000);
+        assert_eq!(GovStorage::get_timelock(&env), 120);
+    });
+}
+
+#[test]
+fn test_failing_proposal_action_reverts_the_batch() {
+    use crate::governance::{Action, ProposalState};
+    use soroban_sdk::IntoVal;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1000);
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let client = ContractClient::new(&env, &contract_id);
+    let oracle = env.register(MockPriceOracle, ());
+    let oracle_client = MockPriceOracleClient::new(&env, &oracle);
+    let asset = Address::generate(&env);
+    oracle_client.set_price(&asset, &100);
+
+    // The invoked contract's write is rolled back along with the fee change
+    let set_price = Action::InvokeContract(
+        oracle.clone(),
+        Symbol::new(&env, "set_price"),
+        soroban_sdk::vec![&env, asset.into_val(&env), 42i128.into_val(&env)],
+    );
+    let actions = soroban_sdk::vec![
+        &env,
+        Action::SetFlashLoanFeeBps(50),
+        set_price.clone(),
+        Action::SetQuorumBps(0),
+    ];
+    let id = queue_with_actions(&env, &contract_id, &admin, actions);
+    assert_eq!(
+        client.try_execute_proposal(&id),
+        Err(Ok(ProtocolError::InvalidParameters))
+    );
+    assert_eq!(oracle_client.get_price(&asset), 100);
+    assert_eq!(client.get_proposal_state(&id), Some(ProposalState::Queued));
+    env.as_contract(&contract_id, || {
+        assert_eq!(
+            ProtocolConfig::get_flash_loan_fee_bps(&env),
+            crate::defaults::FLASH_LOAN_FEE_BPS
+        );
+    });
+
+    // A call that traps fails its action
+    oracle_client.set_price(&asset, &-1);
+    let get_price = Action::InvokeContract(
+        oracle.clone(),
+        Symbol::new(&env, "get_price"),
+        soroban_sdk::vec![&env, asset.into_val(&env)],
+    );
+    let id = queue_with_actions(
+        &env,
+        &contract_id,
+        &admin,
+        soroban_sdk::vec![&env, get_price],
+    );
+    assert_eq!(
+        client.try_execute_proposal(&id),
+        Err(Ok(ProtocolError::InvalidOperation))
+    );
+
+    let id = queue_with_actions(
+        &env,
+        &contract_id,
+        &admin,
+        soroban_sdk::vec![&env, set_price],
+    );
+    client.execute_proposal(&id);
+    assert_eq!(oracle_client.get_price(&asset), 42);
+}
+
+#[test]
+fn test_proposal_heavier_than_budget_carries_over() {
+    use crate::govbudget::EPOCH_SECS;
+    use crate::governance::{Action, ProposalState};
+
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1000);
+    let (admin, contract_id, _token) = TestUtils::setup_contract_with_token(&env, &[]);
+    let client = ContractClient::new(&env, &contract_id);
+    let admin_s = admin.to_string();
+    let tvl_cap = || {
+        env.as_contract(&contract_id, || {
+            crate::caps::CapStorage::get_global_tvl_cap(&env)
+        })
+    };
+
+    // Ten risk changes at weight 3 against a budget of 12 per epoch
+    let mut actions = Vec::new(&env);
+    for cap in 1..=10 {
+        actions.push_back(Action::SetGlobalTvlCap(cap * 1000));
+    }
+    let id = queue_with_actions(&env, &contract_id, &admin, actions);
+    assert!(client.execute_proposal(&id).executed);
+    assert_eq!(
+        client.get_proposal_state(&id),
+        Some(ProposalState::Executed)
+    );
+    assert_eq!(tvl_cap(), 4000);
+    assert_eq!(client.get_change_budget_remaining(), 0);
+    let carryover = client.get_carryover(&id).unwrap();
+    assert_eq!(carryover.actions.len(), 6);
+
+    // The carryover waits for the next epoch, then runs within its budget
+    assert_eq!(
+        client.try_execute_carryover(&admin_s, &id),
+        Err(Ok(ProtocolError::InvalidOperation))
+    );
+    env.ledger()
+        .with_mut(|l| l.timestamp = carryover.not_before_epoch * EPOCH_SECS);
+    assert_eq!(client.execute_carryover(&admin_s, &id), 2);
+    assert_eq!(tvl_cap(), 8000);
+
+    env.ledger().with_mut(|l| l.timestamp += EPOCH_SECS);
+    assert_eq!(client.execute_carryover(&admin_s, &id), 0);
+    assert_eq!(tvl_cap(), 10_000);
+    assert!(client.get_carryover(&id).is_none());
+    assert_eq!(client.get_change_budget_remaining(), 6);
+}
+
+#[test]
+fn test_credit_delegation_borrows_within_allowance() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let owner = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&owner));
+    let client = ContractClient::new(&env, &contract_id);
+    let vault = Address::generate(&env);
+    let recipient = Address::generate(&env);
+
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &owner);
+        Contract::deposit_collateral(env.clone(), owner.to_string(), 600).unwrap();
+    });
+    client.approve_credit(&owner, &vault, &token, &500);
+
+    // Debt lands on the owner, funds on the recipient
+    client.borrow_from(&vault, &owner, &token, &200, &recipient);
+    assert_eq!(client.get_position(&owner.to_string()).1, 200);
+    assert_eq!(client.get_credit_allowance(&owner, &vault, &token), 300);
+
+    // More than the allowance is refused
+    assert_eq!(
+        client.try_borrow_from(&vault, &owner, &token, &301, &recipient),
+        Err(Ok(ProtocolError::Unauthorized))
+    );
+
+    // The owner's collateral ratio still applies within the allowance
+    assert_eq!(
+        client.try_borrow_from(&vault, &owner, &token, &300, &recipient),
+        Err(Ok(ProtocolError::InsufficientCollateralRatio))
+    );
+
+    // Revocation blocks further draws
+    client.revoke_credit(&owner, &vault, &token);
+    assert_eq!(
+        client.try_borrow_from(&vault, &owner, &token, &50, &recipient),
+        Err(Ok(ProtocolError::Unauthorized))
+    );
+
+    env.as_contract(&token, || {
+        assert_eq!(MockToken::balance(env.clone(), recipient.clone()), 200);
+    });
+}
+
+#[test]
+#[should_panic(expected = "Error(Auth, InvalidAction)")]
+fn test_approve_credit_requires_owner_auth() {
+    let env = Env::default();
+    let contract_id = env.register(Contract, ());
+    let token = Address::generate(&env);
+    env.as_contract(&contract_id, || {
+        let _ = Contract::approve_credit(
+            env.clone(),
+            Address::generate(&env),
+            Address::generate(&env),
+            token.clone(),
+            500,
+        );
+    });
+}
+
+#[test]
+#[should_panic(expected = "Error(Auth, InvalidAction)")]
+fn test_revoke_credit_requires_owner_auth() {
+    let env = Env::default();
+    let contract_id = env.register(Contract, ());
+    let token = Address::generate(&env);
+    env.as_contract(&contract_id, || {
+        let _ = Contract::revoke_credit(
+            env.clone(),
+            Address::generate(&env),
+            Address::generate(&env),
+            token.clone(),
+        );
+    });
+}
+
+#[test]
+#[should_panic(expected = "Error(Auth, InvalidAction)")]
+fn test_borrow_from_requires_spender_auth() {
+    let env = Env::default();
+    let contract_id = env.register(Contract, ());
+    let token = Address::generate(&env);
+    env.as_contract(&contract_id, || {
+        let _ = Contract::borrow_from(
+            env.clone(),
+            Address::generate(&env),
+            Address::generate(&env),
+            token.clone(),
+            100,
+            Address::generate(&env),
+        );
+    });
+}
+
+#[test]
+fn test_collateral_exposure_attributes_debt_pro_rata() {
+    use crate::deposit::DepositModule;
+    use crate::oracle::{OracleStorage, PRICE_SCALE};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let users = [
+        TestUtils::create_user_address(&env, 0),
+        TestUtils::create_user_address(&env, 1),
+    ];
+    let (admin, contract_id, token) = TestUtils::setup_contract_with_token(&env, &users);
+    let other = Address::generate(&env);
+
+    env.as_contract(&contract_id, || {
+        let mut prices = Map::new(&env);
+        prices.set(token.clone(), (PRICE_SCALE, 0u64));
+        prices.set(other.clone(), (2 * PRICE_SCALE, 0u64));
+        OracleStorage::put_price_cache(&env, &prices);
+        for user in users.iter() {
+            TestUtils::verify_user(&env, &admin, user);
+            Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+        }
+
+        // $1000 of the primary asset and $1000 of the other back 300 of debt
+        Contract::borrow(env.clone(), users[0].to_string(), 300, 0).unwrap();
+        DepositModule::_deposit_collateral_asset(&env, &users[0].to_string(), &other, 500).unwrap();
+        assert_eq!(
+            Contract::get_collateral_exposure(env.clone(), token.clone()),
+            150
+        );
+        assert_eq!(
+            Contract::get_collateral_exposure(env.clone(), other.clone()),
+            150
+        );
+
+        // A single-asset position attributes everything to the primary asset
+        Contract::borrow(env.clone(), users[1].to_string(), 200, 0).unwrap();
+        assert_eq!(
+            Contract::get_collateral_exposure(env.clone(), token.clone()),
+            350
+        );
+        let status = Contract::get_system_status(env.clone()).unwrap();
+        assert_eq!(status.collateral_concentration_bps, 7000);
+
+        // Repaying moves only the repaying position's contribution
+        Contract::repay(env.clone(), users[0].to_string(), 100).unwrap();
+        assert_eq!(
+            Contract::get_collateral_exposure(env.clone(), token.clone()),
+            300
+        );
+        assert_eq!(
+            Contract::get_collateral_exposure(env.clone(), other.clone()),
+            100
+        );
+    });
+}
+
+/// Calls view entry points through the client and fails when one of them
+/// writes storage or extends a TTL, which would break simulate-only reads
+/// from RPC and inflate their footprint
+struct ViewAudit<'a> {
+    env: &'a Env,
+    covered: alloc::vec::Vec<&'static str>,
+}
+
+impl ViewAudit<'_> {
+    fn check<T>(&mut self, name: &'static str, view: impl FnOnce() -> T) {
+        let before = self.env.to_ledger_snapshot().ledger_entries;
+        let _ = view();
+        let after = self.env.to_ledger_snapshot().ledger_entries;
+        assert!(before == after, "view `{}` modified the ledger", name);
+        self.covered.push(name);
+    }
+
+    /// Every contract method named as a view (`get_`, `list_`, `is_`,
+    /// `quote_`, `preview_`) must have been checked
+    fn assert_complete(&self) {
+        let (mut amm, mut exported) = (false, false);
+        let mut lines = include_str!("lib.rs").lines().peekable();
+        while let Some(line) = lines.next() {
+            match line {
+                "#[cfg(feature = \"amm\")]" => {
+                    amm = lines.peek() == Some(&"#[contractimpl]");
+                }
+                "#[contractimpl]" => exported = true,
+                "}" => (amm, exported) = (false, false),
+                _ => {}
+            }
+            let Some(rest) = line.strip_prefix("    pub fn ").filter(|_| exported) else {
+                continue;
+            };
+            let name = &rest[..rest.find('(').unwrap()];
+            let is_view = ["get_", "list_", "is_", "quote_", "preview_"]
+                .iter()
+                .any(|prefix| name.starts_with(prefix));
+            if is_view && (cfg!(feature = "amm") || !amm) {
+                assert!(self.covered.contains(&name), "view `{}` not audited", name);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_views_do_not_write_storage() {
+    use crate::oracle::{OracleSource, OracleStorage};
+    use crate::roles::RoleKind;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1000);
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    let client = ContractClient::new(&env, &contract_id);
+    let other = Address::generate(&env);
+    let source = env.register(MockPriceOracle, ());
+    let fallback = env.register(MockPriceOracle, ());
+    env.as_contract(&source, || {
+        MockPriceOracle::set_price(env.clone(), token.clone(), 100);
+    });
+    env.as_contract(&fallback, || {
+        MockPriceOracle::set_price(env.clone(), other.clone(), 100);
+    });
+
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 200, 0).unwrap();
+        crate::oracle::Oracle::set_source(
+            &env,
+            &admin,
+            &token,
+            OracleSource::new(source.clone(), 1, 10_000),
+        )
+        .unwrap();
+        OracleStorage::set_max_deviation_bps(&env, 1_000_000);
+        Contract::set_oracle_fallback(
+            env.clone(),
+            admin.to_string(),
+            other.clone(),
+            Some(fallback.clone()),
+        )
+        .unwrap();
+    });
+    // Let the price cache and accrual go stale so views would refresh them
+    env.ledger().with_mut(|l| l.timestamp = 5000);
+
+    let mut audit = ViewAudit {
+        env: &env,
+        covered: alloc::vec::Vec::new(),
+    };
+    let user_str = user.to_string();
+    let group = Symbol::new(&env, "group");
+    let kind = Symbol::new(&env, "deposit");
+
+    audit.check("get_cap_group", || client.get_cap_group(&group));
+    audit.check("preview_liquidation", || {
+        client.try_preview_liquidation(&user, &100)
+    });
+    audit.check("get_oracle_effective_mode", || {
+        client.get_oracle_effective_mode(&token)
+    });
+    audit.check("get_oracle_max_deviation", || {
+        client.get_oracle_max_deviation()
+    });
+    audit.check("get_oracle_reading_bounds", || {
+        client.get_oracle_reading_bounds(&token)
+    });
+    audit.check("get_oracle_source_status", || {
+        client.get_oracle_source_status(&token, &true)
+    });
+    audit.check("get_oracle_config", || client.get_oracle_config());
+    audit.check("get_oracle_source_count", || {
+        client.get_oracle_source_count(&token)
+    });
+    audit.check("get_oracle_admin", || client.get_oracle_admin());
+    audit.check("get_oracle_ema", || client.get_oracle_ema(&token));
+    audit.check("get_borrow_surge_limit", || {
+        client.get_borrow_surge_limit(&Some(token.clone()))
+    });
+    audit.check("get_borrow_surge_window", || {
+        client.get_borrow_surge_window(&Some(token.clone()))
+    });
+    audit.check("get_beta_config", || client.get_beta_config());
+    audit.check("is_beta_user", || client.is_beta_user(&user));
+    audit.check("get_oracle_source_health", || {
+        client.get_oracle_source_health(&token)
+    });
+    audit.check("get_oracle_twap", || client.get_oracle_twap(&token, &3600));
+    audit.check("get_price_detailed", || {
+        (
+            client.get_price_detailed(&token),
+            client.get_price_detailed(&other),
+        )
+    });
+    audit.check("get_liquidation_record", || {
+        client.get_liquidation_record(&0)
+    });
+    audit.check("get_last_liquidation_record", || {
+        client.get_last_liquidation_record(&user)
+    });
+    audit.check("get_position", || client.try_get_position(&user_str));
+    audit.check("get_user_account_data", || {
+        client.try_get_user_account_data(&user_str)
+    });
+    audit.check("get_credit_allowance", || {
+        client.get_credit_allowance(&user, &other, &token)
+    });
+    audit.check("get_withdrawal_policy", || {
+        client.get_withdrawal_policy(&user)
+    });
+    audit.check("get_privacy_flags", || client.get_privacy_flags(&user));
+    audit.check("get_risk_snapshots", || client.get_risk_snapshots(&user));
+    audit.check("get_credit_score", || client.get_credit_score(&user));
+    audit.check("get_withdrawal_penalty", || {
+        client.get_withdrawal_penalty(&token)
+    });
+    audit.check("get_protocol_reserves", || {
+        client.get_protocol_reserves(&token)
+    });
+    audit.check("get_available_liquidity", || {
+        client.get_available_liquidity(&token)
+    });
+    audit.check("get_force_close_audit", || client.get_force_close_audit());
+    audit.check("get_bad_debt", || client.get_bad_debt(&token));
+    audit.check("get_pending_forgiveness", || {
+        client.get_pending_forgiveness()
+    });
+    audit.check("get_capabilities", || client.get_capabilities());
+    audit.check("get_pending_role_change", || {
+        client.get_pending_role_change(&RoleKind::Treasury)
+    });
+    audit.check("get_param_history", || client.get_param_history());
+    audit.check("get_listing", || client.get_listing(&0));
+    audit.check("get_incentive_program", || client.get_incentive_program(&0));
+    audit.check("get_repay_plan", || client.get_repay_plan(&user));
+    audit.check("get_pending_transfer", || {
+        client.get_pending_transfer(&user)
+    });
+    audit.check("get_carryover", || client.get_carryover(&0));
+    audit.check("get_change_budget_remaining", || {
+        client.get_change_budget_remaining()
+    });
+    audit.check("get_reason_codes", || client.get_reason_codes());
+    audit.check("get_voting_power", || client.get_voting_power(&user));
+    audit.check("get_total_voting_power", || client.get_total_voting_power());
+    audit.check("get_proposal", || client.get_proposal(&0));
+    audit.check("get_proposal_tally", || client.get_proposal_tally(&0));
+    audit.check("get_vote_receipt", || client.get_vote_receipt(&0, &user));
+    audit.check("is_proposal_cancelled", || client.is_proposal_cancelled(&0));
+    audit.check("get_proposal_state", || client.get_proposal_state(&0));
+    audit.check("get_vote_key", || client.get_vote_key(&user));
+    audit.check("get_vote_nonce", || client.get_vote_nonce(&user));
+    audit.check("get_reward_settlement_asset", || {
+        client.get_reward_settlement_asset()
+    });
+    audit.check("get_reward_max_slippage", || {
+        client.get_reward_max_slippage()
+    });
+    audit.check("get_payout_balance", || {
+        client.get_payout_balance(&user, &token)
+    });
+    audit.check("get_payout_queue", || client.get_payout_queue(&token));
+    audit.check("get_vote_delegate", || client.get_vote_delegate(&user));
+    audit.check("get_proposal_actions", || client.get_proposal_actions(&0));
+    audit.check("get_price_sweep_deviation", || {
+        client.get_price_sweep_deviation()
+    });
+    audit.check("get_price_sweep", || client.get_price_sweep(&token));
+    audit.check("get_vote_message", || {
+        client.get_vote_message(&0, &user, &true, &0, &user)
+    });
+    audit.check("get_vote_key_message", || {
+        client.get_vote_key_message(&user)
+    });
+    audit.check("get_accrual_state", || client.get_accrual_state());
+    audit.check("get_state_commitment", || client.get_state_commitment());
+    audit.check("get_emission_schedule", || {
+        client.get_emission_schedule(&token)
+    });
+    audit.check("get_last_reconcile", || client.get_last_reconcile(&token));
+    audit.check("get_call_stats", || client.get_call_stats());
+    audit.check("get_protocol_params", || client.try_get_protocol_params());
+    audit.check("get_risk_config", || client.try_get_risk_config());
+    audit.check("get_system_stats", || client.try_get_system_stats());
+    audit.check("get_system_status", || client.try_get_system_status());
+    audit.check("get_collateral_exposure", || {
+        client.get_collateral_exposure(&token)
+    });
+    audit.check("get_asset_state", || client.try_get_asset_state(&token));
+    audit.check("get_emergency_state", || client.try_get_emergency_state());
+    audit.check("get_event_summary", || client.try_get_event_summary());
+    audit.check("get_event_aggregates", || client.try_get_event_aggregates());
+    audit.check("get_events_for_type", || {
+        client.try_get_events_for_type(&kind, &0, &10)
+    });
+    audit.check("get_events_since", || client.try_get_events_since(&0, &10));
+    audit.check("get_recent_event_types", || {
+        client.try_get_recent_event_types()
+    });
+    audit.check("get_registered_asset", || {
+        client.try_get_registered_asset(&Symbol::new(&env, "primary"))
+    });
+    audit.check("list_registered_assets", || client.list_registered_assets());
+    audit.check("get_freeze_info", || client.get_freeze_info(&user));
+    audit.check("get_user_profile", || client.try_get_user_profile(&user));
+    audit.check("get_protocol_report", || client.try_get_protocol_report());
+    audit.check("get_user_report", || client.try_get_user_report(&user_str));
+    audit.check("get_asset_report", || client.try_get_asset_report(&token));
+    audit.check("get_daily_snapshot", || client.get_daily_snapshot(&0));
+    audit.check("get_emergency_price", || client.get_emergency_price(&token));
+    audit.check("get_derived_price", || {
+        client.get_derived_price(&token, &Address::generate(&env))
+    });
+    audit.check("get_index_checkpoint", || {
+        client.get_index_checkpoint(&token, &0)
+    });
+    audit.check("get_asset_distribution", || {
+        client.get_asset_distribution(&token)
+    });
+    audit.check("get_recent_activity", || {
+        client.try_get_recent_activity(&10)
+    });
+    #[cfg(feature = "amm")]
+    {
+        let pair = crate::amm::PairKey {
+            asset_a: token.clone(),
+            asset_b: other.clone(),
+        };
+        let path = soroban_sdk::vec![&env, token.clone(), other.clone()];
+        audit.check("is_amm_pair_registered", || {
+            client.is_amm_pair_registered(&token, &other)
+        });
+        audit.check("get_amm_pair_info", || {
+            client.try_get_amm_pair_info(&token, &other)
+        });
+        audit.check("get_total_amm_pairs", || client.get_total_amm_pairs());
+        audit.check("get_all_amm_pairs", || client.get_all_amm_pairs());
+        audit.check("get_amm_swap_history", || {
+            client.get_amm_swap_history(&0, &10)
+        });
+        audit.check("get_pool_info", || client.try_get_pool_info(&pair));
+        audit.check("get_lp_position", || {
+            client.try_get_lp_position(&pair, &user)
+        });
+        audit.check("quote_swap_in", || {
+            client.try_quote_swap_in(&pair, &token, &100)
+        });
+        audit.check("quote_swap_out", || {
+            client.try_quote_swap_out(&pair, &other, &100)
+        });
+        audit.check("quote_route_in", || client.try_quote_route_in(&path, &100));
+        audit.check("quote_route_out", || {
+            client.try_quote_route_out(&path, &100)
+        });
+        audit.check("get_burn_address", || client.get_burn_address());
+    }
+    audit.assert_complete();
+}
+
+#[test]
+fn test_position_transfer_completes_across_two_transactions() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    let client = ContractClient::new(&env, &contract_id);
+    let wallet = Address::generate(&env);
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 200, 0).unwrap();
+    });
+    client.create_repay_plan(&user.to_string(), &token, &50, &(24 * 60 * 60));
+
+    let pending = client.initiate_transfer(&user, &wallet, &0);
+    assert_eq!(pending.to, wallet);
+    let stranger = Address::generate(&env);
+    assert_eq!(
+        client.try_complete_transfer(&stranger, &user, &0),
+        Err(Ok(ProtocolError::Unauthorized))
+    );
+    assert_eq!(
+        client.try_complete_transfer(&wallet, &user, &1),
+        Err(Ok(ProtocolError::InvalidInput))
+    );
+
+    client.complete_transfer(&wallet, &user, &0);
+    let (collateral, debt, _) = client.get_position(&wallet.to_string());
+    assert_eq!((collateral, debt), (1000, 200));
+    let (collateral, debt, _) = client.get_position(&user.to_string());
+    assert_eq!((collateral, debt), (0, 0));
+    assert_eq!(client.get_pending_transfer(&user), None);
+
+    // The plan was scheduled by the old account and does not follow
+    assert_eq!(client.get_repay_plan(&user), None);
+    assert_eq!(client.get_repay_plan(&wallet), None);
+}
+
+#[test]
+fn test_position_transfer_expires_or_cancels_on_activity() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1000);
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    let client = ContractClient::new(&env, &contract_id);
+    let wallet = Address::generate(&env);
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+        Contract::set_transfer_window(env.clone(), admin.to_string(), 600).unwrap();
+    });
+
+    // An offer left unanswered past the window can no longer be taken
+    client.initiate_transfer(&user, &wallet, &0);
+    env.ledger().with_mut(|l| l.timestamp = 1601);
+    assert_eq!(
+        client.try_complete_transfer(&wallet, &user, &0),
+        Err(Ok(ProtocolError::DeadlineExceeded))
+    );
+    // Nonces are single-use
+    assert_eq!(
+        client.try_initiate_transfer(&user, &wallet, &0),
+        Err(Ok(ProtocolError::InvalidInput))
+    );
+
+    // Using the position meanwhile cancels the offer
+    client.initiate_transfer(&user, &wallet, &1);
+    env.as_contract(&contract_id, || {
+        Contract::deposit_collateral(env.clone(), user.to_string(), 100).unwrap();
+    });
+    assert_eq!(client.get_pending_transfer(&user), None);
+    assert_eq!(
+        client.try_complete_transfer(&wallet, &user, &1),
+        Err(Ok(ProtocolError::NotFound))
+    );
+    let (collateral, _, _) = client.get_position(&user.to_string());
+    assert_eq!(collateral, 1100);
+
+    // Saves nobody on the owner's side asked for leave it in place, and so
+    // does a credit delegate borrowing on its allowance
+    client.initiate_transfer(&user, &wallet, &2);
+    client.sync_positions(&soroban_sdk::vec![&env, user.clone()]);
+    let vault = Address::generate(&env);
+    client.approve_credit(&user, &vault, &token, &100);
+    client.borrow_from(&vault, &user, &token, &100, &vault);
+    assert_eq!(client.get_pending_transfer(&user).unwrap().nonce, 2);
+    client.complete_transfer(&wallet, &user, &2);
+    let (collateral, debt, _) = client.get_position(&wallet.to_string());
+    assert_eq!((collateral, debt), (1100, 100));
+}
+
+#[test]
+fn test_position_transfer_moves_linked_state() {
+    use crate::governance::Governance;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1000);
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    let client = ContractClient::new(&env, &contract_id);
+    let [wallet, vault, co_signer] = [0; 3].map(|_| Address::generate(&env));
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        TestUtils::verify_user(&env, &admin, &wallet);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 1000).unwrap();
+    });
+    client.approve_credit(&user, &vault, &token, &300);
+    client.set_withdrawal_policy(&user, &100, &Some(co_signer.clone()));
+    client.approve_withdrawal(&co_signer, &user, &token, &500, &2_000);
+    client.sync_positions(&soroban_sdk::vec![&env, user.clone()]);
+    assert_eq!(client.get_voting_power(&user), 1000);
+
+    // Power counted in an open vote cannot be carried over for a recount
+    let id = env.as_contract(&contract_id, || {
+        let title = String::from_str(&env, "transfer");
+        let id = Governance::propose(&env, &admin, title, gov_actions(&env), 100)
+            .unwrap()
+            .id;
+        Governance::vote(&env, id, &user, true).unwrap();
+        id
+    });
+    client.initiate_transfer(&user, &wallet, &0);
+    assert_eq!(
+        client.try_complete_transfer(&wallet, &user, &0),
+        Err(Ok(ProtocolError::ProposalCooldown))
+    );
+    env.ledger().with_mut(|l| l.timestamp = 1100);
+    client.complete_transfer(&wallet, &user, &0);
+    assert_eq!(client.get_proposal_tally(&id).for_votes, 1000);
+
+    // Voting power and the co-signing policy follow the position
+    assert_eq!(client.get_voting_power(&user), 0);
+    assert_eq!(client.get_voting_power(&wallet), 1000);
+    assert_eq!(client.get_withdrawal_policy(&user), None);
+    assert_eq!(
+        client.get_withdrawal_policy(&wallet).unwrap().co_signer,
+        co_signer
+    );
+    assert_eq!(
+        client.try_withdraw(&wallet.to_string(), &500, &0),
+        Err(Ok(ProtocolError::CoSignerApprovalRequired))
+    );
+
+    // Allowances granted by the old account are gone
+    assert_eq!(client.get_credit_allowance(&user, &vault, &token), 0);
+    assert_eq!(
+        client.try_borrow_from(&vault, &user, &token, &100, &vault),
+        Err(Ok(ProtocolError::Unauthorized))
+    );
+}
+
+#[test]
+fn test_beta_gates_new_positions_until_expiry() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let tester = TestUtils::create_user_address(&env, 0);
+    let outsider = TestUtils::create_user_address(&env, 1);
+    let (admin, contract_id, _token) =
+        TestUtils::setup_contract_with_token(&env, &[tester.clone(), outsider.clone()]);
+
+    // A fresh contract launched with a beta
+    let launched = env.register(Contract, ());
+    env.as_contract(&launched, || {
+        assert_eq!(
+            Contract::initialize_with_beta(env.clone(), admin.to_string(), 0),
+            Err(ProtocolError::InvalidParameters)
+        );
+        Contract::initialize_with_beta(env.clone(), admin.to_string(), 1_000).unwrap();
+        let config = Contract::get_beta_config(env.clone()).unwrap();
+        assert!(config.enabled);
+        assert_eq!(config.expires_at, 1_000);
+    });
+
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &tester);
+        TestUtils::verify_user(&env, &admin, &outsider);
+        crate::beta::BetaAccess::configure(&env, 1_000).unwrap();
+
+        let users = soroban_sdk::vec![&env, tester.clone()];
+        assert_eq!(
+            Contract::add_beta_users(env.clone(), tester.to_string(), users.clone()),
+            Err(ProtocolError::Unauthorized)
+        );
+        assert_eq!(
+            Contract::add_beta_users(env.clone(), admin.to_string(), users.clone()),
+            Ok(1)
+        );
+        assert!(Contract::is_beta_user(env.clone(), tester.clone()));
+
+        assert_eq!(
+            Contract::deposit_collateral(env.clone(), outsider.to_string(), 2000),
+            Err(ProtocolError::Unauthorized)
+        );
+        Contract::deposit_collateral(env.clone(), tester.to_string(), 2000).unwrap();
+        Contract::borrow(env.clone(), tester.to_string(), 500, 0).unwrap();
+
+        // Dropping a tester from the list never traps an open position
+        assert_eq!(
+            Contract::remove_beta_users(env.clone(), admin.to_string(), users),
+            Ok(1)
+        );
+        assert_eq!(
+            Contract::borrow(env.clone(), tester.to_string(), 100, 0),
+            Err(ProtocolError::Unauthorized)
+        );
+        Contract::repay(env.clone(), tester.to_string(), 500).unwrap();
+        Contract::withdraw(env.clone(), tester.to_string(), 500, 0).unwrap();
+
+        // Past the expiry the allowlist is no longer read
+        env.ledger().with_mut(|li| li.timestamp = 1_000);
+        Contract::deposit_collateral(env.clone(), outsider.to_string(), 2000).unwrap();
+        Contract::borrow(env.clone(), tester.to_string(), 100, 0).unwrap();
+        assert_eq!(Contract::get_beta_config(env.clone()).unwrap().users, 0);
+    });
+}
+
+#[cfg(feature = "amm")]
+#[test]
+fn test_lp_position_reports_impermanent_loss() {
+    use crate::amm::SwapParams;
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let (_admin, contract_id, user, pair_id) = setup_amm_pool(&env);
+
+    env.as_contract(&contract_id, || {
+        let entry = Contract::get_lp_position(env.clone(), pair_id.clone(), user.clone()).unwrap();
+        assert_eq!(entry.shares, 100_000);
+        assert_eq!((entry.deposited_a, entry.deposited_b), (100_000, 100_000));
+        assert_eq!((entry.redeemable_a, entry.redeemable_b), (100_000, 100_000));
+        assert_eq!(entry.impermanent_loss_bps, 0);
+
+        // Halve the price of asset_a: ~41.4% more of it in the pool. By hand,
+        // k ~= 0.5 and 2 * sqrt(k) / (1 + k) - 1 ~= -572 bps.
+        let params = SwapParams::new(
+            user.clone(),
+            pair_id.asset_a.clone(),
+            pair_id.asset_b.clone(),
+            41_421,
+            0,
+        );
+        Contract::execute_amm_swap(env.clone(), params).unwrap();
+        let moved = Contract::get_lp_position(env.clone(), pair_id.clone(), user.clone()).unwrap();
+        assert_eq!(moved.redeemable_a, 141_421);
+        assert!(moved.redeemable_b < 71_000);
+        assert!(
+            (-590..=-550).contains(&moved.impermanent_loss_bps),
+            "il {}",
+            moved.impermanent_loss_bps
+        );
+
+        // Removing half keeps half of the recorded cost and the same estimate
+        Contract::remove_amm_liquidity(env.clone(), user.clone(), pair_id.clone(), 50_000).unwrap();
+        let half = Contract::get_lp_position(env.clone(), pair_id.clone(), user.clone()).unwrap();
+        assert_eq!((half.deposited_a, half.deposited_b), (50_000, 50_000));
+        assert_eq!(half.impermanent_loss_bps, moved.impermanent_loss_bps);
+    });
+}
+
+#[test]
+fn test_borrow_surge_trips_and_resets() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let user = TestUtils::create_user_address(&env, 0);
+    let (admin, contract_id, token) =
+        TestUtils::setup_contract_with_token(&env, core::slice::from_ref(&user));
+    env.as_contract(&contract_id, || {
+        TestUtils::verify_user(&env, &admin, &user);
+        Contract::deposit_collateral(env.clone(), user.to_string(), 5000).unwrap();
+        let scope = Some(token.clone());
+        assert_eq!(
+            Contract::set_borrow_surge_limit(env.clone(), user.to_string(), scope.clone(), 500),
+            Err(ProtocolError::Unauthorized)
+        );
+        Contract::set_borrow_surge_limit(env.clone(), admin.to_string(), scope.clone(), 500)
+            .unwrap();
+
+        // The borrow that crosses the limit settles and trips the breaker
+        Contract::borrow(env.clone(), user.to_string(), 300, 0).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 300, 0).unwrap();
+        let window = Contract::get_borrow_surge_window(env.clone(), scope.clone());
+        assert!(window.tripped);
+        assert_eq!(window.borrowed, 600);
+        assert_eq!(
+            Contract::borrow(env.clone(), user.to_string(), 10, 0),
+            Err(ProtocolError::ProtocolPaused)
+        );
+
+        // Repayments are unaffected
+        Contract::repay(env.clone(), user.to_string(), 200).unwrap();
+
+        // The guardian may clear it early; otherwise the window rolls
+        assert_eq!(
+            Contract::clear_borrow_surge(env.clone(), user.to_string(), scope.clone()),
+            Err(ProtocolError::Unauthorized)
+        );
+        Contract::clear_borrow_surge(env.clone(), admin.to_string(), scope.clone()).unwrap();
+        Contract::borrow(env.clone(), user.to_string(), 600, 0).unwrap();
+        assert_eq!(
+            Contract::borrow(env.clone(), user.to_string(), 10, 0),
+            Err(ProtocolError::ProtocolPaused)
+        );
+        env.ledger()
+            .with_mut(|li| li.timestamp += crate::surge::SURGE_WINDOW_SECS);
+        assert!(!Contract::get_borrow_surge_window(env.clone(), scope).tripped);
+        Contract::borrow(env.clone(), user.to_string(), 10, 0).unwrap();
+    });
+}
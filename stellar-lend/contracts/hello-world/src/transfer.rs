@@ -0,0 +1,234 @@
+//! Two-step position transfer between accounts
+//!
+//! Built for smart-wallet users moving to a new contract account they also
+//! control: the current owner initiates a transfer to the new account, which
+//! completes it in a separate transaction, so each side authorizes on its
+//! own. A pending transfer expires after a configurable window, and the
+//! owner depositing, withdrawing, borrowing or repaying before completion
+//! cancels it, so a half-completed transfer never locks the position.
+//! Saves by anyone else, such as permissionless syncs, keeper sweeps or a
+//! credit delegate borrowing on an allowance, leave it in place.
+//!
+//! Completing moves the state tied to the position along with it: the
+//! recorded voting power and any co-signing policy go to the receiver, while
+//! credit allowances, a scheduled repayment plan and an unused co-signer
+//! approval, given for the old account, are dropped.
+
+use crate::autorepay::RepayPlans;
+use crate::config;
+use crate::cosign::CoSign;
+use crate::defaults;
+use crate::delegation::CreditDelegation;
+use crate::governance::Governance;
+use crate::penalty::PenaltyStorage;
+use crate::voting::VotingStorage;
+use crate::{EventTracker, Position, ProtocolConfig, ProtocolError, StateHelper};
+use soroban_sdk::{contracttype, Address, Env, Symbol, Vec};
+
+/// Upper bound for the transfer completion window
+pub const MAX_TRANSFER_WINDOW_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// A transfer initiated by the position owner, awaiting the receiver
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PendingTransfer {
+    pub to: Address,
+    pub nonce: u64,
+    /// Last timestamp the receiver may complete at
+    pub expires_at: u64,
+}
+
+/// Position transfer storage management
+pub struct TransferStorage;
+
+impl TransferStorage {
+    pub(crate) fn pending_key(env: &Env, from: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "pos_transfer"), from.clone())
+    }
+    pub(crate) fn nonce_key(env: &Env, from: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "pos_transfer_nonce"), from.clone())
+    }
+    pub(crate) fn window_key(env: &Env) -> Symbol {
+        Symbol::new(env, "position_transfer_window")
+    }
+
+    pub fn get_pending(env: &Env, from: &Address) -> Option<PendingTransfer> {
+        env.storage().instance().get(&Self::pending_key(env, from))
+    }
+    fn set_pending(env: &Env, from: &Address, pending: Option<&PendingTransfer>) {
+        let key = Self::pending_key(env, from);
+        match pending {
+            Some(p) => env.storage().instance().set(&key, p),
+            None => env.storage().instance().remove(&key),
+        }
+    }
+
+    /// Nonce the owner's next transfer must use
+    pub fn get_next_nonce(env: &Env, from: &Address) -> u64 {
+        env.storage()
+            .instance()
+            .get(&Self::nonce_key(env, from))
+            .unwrap_or(0)
+    }
+
+    /// How long the receiver has to complete a transfer
+    pub fn get_window(env: &Env) -> u64 {
+        env.storage()
+            .instance()
+            .get(&Self::window_key(env))
+            .unwrap_or(defaults::POSITION_TRANSFER_WINDOW_SECS)
+    }
+    pub fn set_window(env: &Env, secs: u64) -> Result<(), ProtocolError> {
+        if secs == 0 || secs > MAX_TRANSFER_WINDOW_SECS {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        config::set_param(
+            env,
+            "protocol",
+            "transfer_window",
+            &Self::window_key(env),
+            None,
+            defaults::POSITION_TRANSFER_WINDOW_SECS,
+            secs,
+        );
+        Ok(())
+    }
+}
+
+/// Position transfer logic
+pub struct PositionTransfer;
+
+impl PositionTransfer {
+    /// Offer `from`'s position to `to`, replacing any pending offer. `nonce`
+    /// must be the owner's next transfer nonce.
+    pub fn initiate(
+        env: &Env,
+        from: &Address,
+        to: &Address,
+        nonce: u64,
+    ) -> Result<PendingTransfer, ProtocolError> {
+        from.require_auth();
+        if from == to {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        let position =
+            StateHelper::get_position(env, from).ok_or(ProtocolError::PositionNotFound)?;
+        if position.collateral <= 0 && position.debt <= 0 {
+            return Err(ProtocolError::PositionNotFound);
+        }
+        if nonce != TransferStorage::get_next_nonce(env, from) {
+            return Err(ProtocolError::InvalidInput);
+        }
+        env.storage()
+            .instance()
+            .set(&TransferStorage::nonce_key(env, from), &(nonce + 1));
+
+        let pending = PendingTransfer {
+            to: to.clone(),
+            nonce,
+            expires_at: env
+                .ledger()
+                .timestamp()
+                .saturating_add(TransferStorage::get_window(env)),
+        };
+        TransferStorage::set_pending(env, from, Some(&pending));
+        Self::emit(env, "position_transfer_initiated", from, to, nonce);
+        Ok(pending)
+    }
+
+    /// Take over `from`'s position as the receiver of its pending transfer.
+    /// The receiver must not hold an open position. Fails with
+    /// `ProposalCooldown` while `from`'s voting power is counted in a vote
+    /// still open, which the receiver could otherwise count again.
+    pub fn complete(
+        env: &Env,
+        to: &Address,
+        from: &Address,
+        nonce: u64,
+    ) -> Result<(), ProtocolError> {
+        to.require_auth();
+        let pending = TransferStorage::get_pending(env, from).ok_or(ProtocolError::NotFound)?;
+        if pending.to != *to {
+            return Err(ProtocolError::Unauthorized);
+        }
+        if pending.nonce != nonce {
+            return Err(ProtocolError::InvalidInput);
+        }
+        if env.ledger().timestamp() > pending.expires_at {
+            return Err(ProtocolError::DeadlineExceeded);
+        }
+        if StateHelper::get_position(env, to).is_some_and(|p| p.collateral > 0 || p.debt > 0) {
+            return Err(ProtocolError::AlreadyExists);
+        }
+        let position =
+            StateHelper::get_position(env, from).ok_or(ProtocolError::PositionNotFound)?;
+        Governance::ensure_power_unlocked(env, from)?;
+        TransferStorage::set_pending(env, from, None);
+
+        StateHelper::save_position(
+            env,
+            &Position {
+                user: to.clone(),
+                ..position.clone()
+            },
+        );
+        StateHelper::save_position(
+            env,
+            &Position {
+                collateral: 0,
+                debt: 0,
+                borrow_interest: 0,
+                supply_interest: 0,
+                ..position
+            },
+        );
+        PenaltyStorage::save_lots(env, to, &PenaltyStorage::get_lots(env, from));
+        PenaltyStorage::save_lots(env, from, &Vec::new(env));
+        VotingStorage::set_power(env, to, VotingStorage::get_power(env, from));
+        VotingStorage::set_power(env, from, 0);
+        CreditDelegation::revoke_all(env, from);
+        RepayPlans::revoke(env, from);
+        CoSign::transfer_policy(env, from, to);
+        Self::emit(env, "position_transferred", from, to, nonce);
+        Ok(())
+    }
+
+    /// Cancel the pending transfer of `user`'s position, if any. Called by
+    /// the owner's own deposits, withdrawals, borrows and repayments.
+    pub fn cancel_on_activity(env: &Env, user: &Address) {
+        let Some(pending) = TransferStorage::get_pending(env, user) else {
+            return;
+        };
+        TransferStorage::set_pending(env, user, None);
+        Self::emit(
+            env,
+            "position_transfer_cancelled",
+            user,
+            &pending.to,
+            pending.nonce,
+        );
+    }
+
+    /// Set how long receivers have to complete a transfer (admin only)
+    pub fn set_window(env: &Env, caller: &Address, secs: u64) -> Result<(), ProtocolError> {
+        ProtocolConfig::require_admin(env, caller)?;
+        TransferStorage::set_window(env, secs)
+    }
+
+    fn emit(env: &Env, name: &str, from: &Address, to: &Address, nonce: u64) {
+        let event_type = Symbol::new(env, name);
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        topics.push_back(Symbol::new(env, "user"));
+        EventTracker::record(
+            env,
+            event_type.clone(),
+            topics,
+            Some(from.clone()),
+            None,
+            nonce as i128,
+        );
+        env.events()
+            .publish((event_type, from.clone()), (to.clone(), nonce));
+    }
+}
@@ -0,0 +1,143 @@
+//! Time-weighted utilization and borrow rate
+//!
+//! Per asset, two monotonic accumulators integrate spot utilization and the
+//! borrow rate over time (value x seconds). They are advanced before every
+//! rate update and every change to the supply/borrow totals, so each term
+//! covers an interval over which both values were constant.
+//!
+//! Accumulators are never reset: the average over any window is the
+//! difference between two readings divided by the time between them.
+//! Daily analytics snapshots store their reading alongside the averages
+//! since the previous day's snapshot.
+
+use crate::{InterestRateState, InterestRateStorage, TokenRegistry};
+use soroban_sdk::{contracttype, Address, Env, Symbol};
+
+const UTILIZATION_SCALE: i128 = 100_000_000;
+
+/// Accumulator reading for one asset
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct TwaAccumulator {
+    /// Sum of spot utilization (scaled by 1e8) x seconds
+    pub utilization_seconds: i128,
+    /// Sum of borrow rate (scaled by 1e8) x seconds
+    pub borrow_rate_seconds: i128,
+    /// Time the sums have been advanced to
+    pub updated_at: u64,
+    /// Time accumulation started
+    pub started_at: u64,
+}
+
+impl TwaAccumulator {
+    fn empty() -> Self {
+        Self {
+            utilization_seconds: 0,
+            borrow_rate_seconds: 0,
+            updated_at: 0,
+            started_at: 0,
+        }
+    }
+
+    /// Average utilization and borrow rate between `earlier` and this
+    /// reading, or `None` if no time separates them
+    pub fn average_since(&self, earlier: &TwaAccumulator) -> Option<(i128, i128)> {
+        let elapsed = self.updated_at.saturating_sub(earlier.updated_at) as i128;
+        if elapsed == 0 {
+            return None;
+        }
+        Some((
+            self.utilization_seconds
+                .saturating_sub(earlier.utilization_seconds)
+                / elapsed,
+            self.borrow_rate_seconds
+                .saturating_sub(earlier.borrow_rate_seconds)
+                / elapsed,
+        ))
+    }
+
+    /// A zero reading at the start of accumulation
+    pub fn origin(&self) -> TwaAccumulator {
+        TwaAccumulator {
+            utilization_seconds: 0,
+            borrow_rate_seconds: 0,
+            updated_at: self.started_at,
+            started_at: self.started_at,
+        }
+    }
+}
+
+/// Accumulator storage management
+pub struct TwaStorage;
+
+impl TwaStorage {
+    pub(crate) fn key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "twa_accumulator"), asset.clone())
+    }
+
+    pub fn get(env: &Env, asset: &Address) -> TwaAccumulator {
+        env.storage()
+            .instance()
+            .get(&Self::key(env, asset))
+            .unwrap_or_else(TwaAccumulator::empty)
+    }
+
+    fn save(env: &Env, asset: &Address, acc: &TwaAccumulator) {
+        env.storage().instance().set(&Self::key(env, asset), acc);
+    }
+}
+
+/// Time-weighted averaging logic
+pub struct TimeWeighted;
+
+impl TimeWeighted {
+    fn spot_utilization(state: &InterestRateState) -> i128 {
+        if state.total_supplied > 0 {
+            state
+                .total_borrowed
+                .saturating_mul(UTILIZATION_SCALE)
+                .saturating_div(state.total_supplied)
+        } else {
+            0
+        }
+    }
+
+    fn advanced(env: &Env, mut acc: TwaAccumulator, state: &InterestRateState) -> TwaAccumulator {
+        let now = env.ledger().timestamp();
+        if acc.started_at == 0 {
+            acc.started_at = now;
+        } else {
+            let elapsed = now.saturating_sub(acc.updated_at) as i128;
+            acc.utilization_seconds = acc
+                .utilization_seconds
+                .saturating_add(Self::spot_utilization(state).saturating_mul(elapsed));
+            acc.borrow_rate_seconds = acc
+                .borrow_rate_seconds
+                .saturating_add(state.current_borrow_rate.saturating_mul(elapsed));
+        }
+        acc.updated_at = now;
+        acc
+    }
+
+    /// Advance the primary asset's accumulators up to now using the values
+    /// in `state`, which must be those in effect since the last call
+    pub fn touch(env: &Env, state: &InterestRateState) {
+        let Some(asset) = TokenRegistry::primary_asset(env) else {
+            return;
+        };
+        let acc = TwaStorage::get(env, &asset);
+        if acc.started_at != 0 && acc.updated_at == env.ledger().timestamp() {
+            return;
+        }
+        TwaStorage::save(env, &asset, &Self::advanced(env, acc, state));
+    }
+
+    /// Current reading of `asset`'s accumulators, without persisting it
+    pub fn reading(env: &Env, asset: &Address) -> TwaAccumulator {
+        let acc = TwaStorage::get(env, asset);
+        if TokenRegistry::primary_asset(env).as_ref() != Some(asset) || acc.started_at == 0 {
+            return acc;
+        }
+        Self::advanced(env, acc, &InterestRateStorage::get_state(env))
+    }
+}
@@ -0,0 +1,108 @@
+//! Collateral-based voting power
+//!
+//! A user's voting power is their position's net value: collateral plus
+//! supply interest, less debt and borrow interest, floored at zero. It is
+//! recorded when the position is synced through [`VotingPower::sync_positions`]
+//! and summed into a protocol-wide total, so positions that have not been
+//! accrued in a long time can be brought up to date by anyone before a
+//! governance snapshot.
+
+use crate::{
+    EventTracker, InterestRateManager, InterestRateStorage, Position, ProtocolError, StateHelper,
+};
+use soroban_sdk::{Address, Env, Symbol, Vec};
+
+/// Most users a single `sync_positions` call may list
+pub const MAX_SYNC_USERS: u32 = 10;
+
+/// Voting power storage management
+pub struct VotingStorage;
+
+impl VotingStorage {
+    pub(crate) fn power_key(env: &Env, user: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "gov_power"), user.clone())
+    }
+
+    pub(crate) fn total_key(env: &Env) -> Symbol {
+        Symbol::new(env, "gov_total_power")
+    }
+
+    /// Voting power recorded at the user's last sync
+    pub fn get_power(env: &Env, user: &Address) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::power_key(env, user))
+            .unwrap_or(0)
+    }
+
+    /// Sum of every user's recorded voting power
+    pub fn get_total_power(env: &Env) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::total_key(env))
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn set_power(env: &Env, user: &Address, power: i128) {
+        let old = Self::get_power(env, user);
+        let key = Self::power_key(env, user);
+        if power == 0 {
+            env.storage().instance().remove(&key);
+        } else {
+            env.storage().instance().set(&key, &power);
+        }
+        let total = Self::get_total_power(env) - old + power;
+        env.storage().instance().set(&Self::total_key(env), &total);
+    }
+}
+
+/// Voting power logic
+pub struct VotingPower;
+
+impl VotingPower {
+    /// Net value of a position, floored at zero
+    pub fn of_position(position: &Position) -> i128 {
+        position
+            .collateral
+            .saturating_add(position.supply_interest)
+            .saturating_sub(position.debt)
+            .saturating_sub(position.borrow_interest)
+            .max(0)
+    }
+
+    /// Accrue the global indices (via the rate update), then interest on
+    /// each listed user's position, and refresh their recorded voting power
+    /// (permissionless). Users without a position are recorded with no
+    /// power. Returns the new total voting power.
+    pub fn sync_positions(env: &Env, users: &Vec<Address>) -> Result<i128, ProtocolError> {
+        if users.is_empty() || users.len() > MAX_SYNC_USERS {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        let state = InterestRateStorage::update_state(env);
+        for user in users.iter() {
+            let power = match StateHelper::get_position(env, &user) {
+                Some(mut position) => {
+                    InterestRateManager::accrue_interest_for_position(
+                        env,
+                        &mut position,
+                        state.current_borrow_rate,
+                        state.current_supply_rate,
+                    );
+                    StateHelper::save_position(env, &position);
+                    Self::of_position(&position)
+                }
+                None => 0,
+            };
+            VotingStorage::set_power(env, &user, power);
+        }
+
+        let total = VotingStorage::get_total_power(env);
+        let event_type = Symbol::new(env, "positions_synced");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        EventTracker::record(env, event_type.clone(), topics, None, None, total);
+        env.events()
+            .publish((event_type, Symbol::new(env, "governance")), users.len());
+        Ok(total)
+    }
+}
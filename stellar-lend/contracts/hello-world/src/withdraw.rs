@@ -1,10 +1,15 @@
 //! Withdraw module for StellarLend protocol
 //! Handles collateral withdrawal functionality and related operations
 
+use crate::accrual::AccrualManager;
 use crate::analytics::AnalyticsModule;
+use crate::cosign::CoSign;
+use crate::liquidity::Liquidity;
+use crate::penalty::WithdrawalPenalty;
+use crate::transfer::PositionTransfer;
 use crate::{
     EmergencyManager, InterestRateManager, InterestRateStorage, OperationKind, ProtocolConfig,
-    ProtocolError, ProtocolEvent, ReentrancyGuard, RiskConfigStorage, StateHelper,
+    ProtocolError, ProtocolEvent, ReentrancyGuard, RiskConfigStorage, StateHelper, TokenRegistry,
     TransferEnforcer, UserManager,
 };
 use soroban_sdk::{contracterror, contracttype, Address, Env, String, Symbol};
@@ -105,8 +110,18 @@ impl WithdrawModule {
                 return Err(WithdrawError::InsufficientCollateral.into());
             }
 
-            // Accrue interest
-            let state = InterestRateStorage::update_state(env);
+            let asset = TokenRegistry::require_primary_asset(env)?;
+            let co_signed = CoSign::check_withdrawal(env, withdrawer, &asset, amount)?;
+
+            // Accrue interest. Zero-debt withdrawals skip the global update
+            // and catch-up check while the index is fresh.
+            let state = if position.debt <= 0 && AccrualManager::is_fresh(env) {
+                InterestRateStorage::get_state(env)
+            } else {
+                let state = InterestRateStorage::update_state(env);
+                AccrualManager::ensure_caught_up(env)?;
+                state
+            };
             InterestRateManager::accrue_interest_for_position(
                 env,
                 &mut position,
@@ -114,12 +129,13 @@ impl WithdrawModule {
                 state.current_supply_rate,
             );
 
-            // Check collateral ratio after withdrawal (only if there's debt)
+            // Check collateral ratio after withdrawal (only if there's debt).
+            // Inside a multicall, health is checked once the batch completes.
             let new_collateral = position.collateral - amount;
             let collateral_ratio = if position.debt > 0 {
                 let min_ratio = ProtocolConfig::get_min_collateral_ratio(env);
                 let ratio = (new_collateral * 100) / position.debt;
-                if ratio < min_ratio {
+                if ratio < min_ratio && !ReentrancyGuard::in_batch(env) {
                     return Err(WithdrawError::InsufficientCollateralRatio.into());
                 }
                 ratio
@@ -127,10 +143,35 @@ impl WithdrawModule {
                 0
             };
 
+            // Early withdrawal penalty only applies to pure supply (no outstanding debt)
+            let settlement = WithdrawalPenalty::plan_withdrawal(
+                env,
+                withdrawer,
+                &asset,
+                amount,
+                position.debt == 0,
+            );
+
+            // Pay out before writing anything so a failed transfer leaves no trace
+            if amount > settlement.penalty {
+                Liquidity::require_available(env, &asset, amount - settlement.penalty)?;
+                TransferEnforcer::transfer_out(
+                    env,
+                    withdrawer,
+                    amount - settlement.penalty,
+                    Symbol::new(env, "withdraw"),
+                )?;
+            }
+            WithdrawalPenalty::apply_withdrawal(env, withdrawer, &asset, &settlement);
+            if co_signed {
+                CoSign::consume_approval(env, withdrawer);
+            }
+
             // Update position
             position.collateral = new_collateral;
-            TransferEnforcer::transfer_out(env, withdrawer, amount, Symbol::new(env, "withdraw"))?;
             StateHelper::save_position(env, &position);
+            PositionTransfer::cancel_on_activity(env, &position.user);
+            InterestRateStorage::adjust_totals(env, -amount, 0);
 
             // Emit event
             ProtocolEvent::PositionUpdated(
@@ -199,6 +240,7 @@ impl WithdrawModule {
             // Update position
             position.collateral = new_collateral;
             StateHelper::save_position(env, &position);
+            PositionTransfer::cancel_on_activity(env, &position.user);
 
             // Emit cross-asset withdraw event
             ProtocolEvent::CrossWithdraw(user_addr, asset.clone(), amount).emit(env);
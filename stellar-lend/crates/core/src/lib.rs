@@ -0,0 +1,79 @@
+//! Types shared by the StellarLend crates
+//!
+//! Holds what every subsystem crate needs without depending on the contract
+//! crate: the protocol error codes, fixed-point helpers, cursor paging and
+//! string validation. The contract crate re-exports these, so their paths
+//! there are unchanged.
+
+#![no_std]
+
+pub mod math;
+pub mod pagination;
+pub mod validation;
+
+use soroban_sdk::contracterror;
+
+/// Protocol errors
+///
+/// Not exported to the contract spec: spec error enums cap out at 50 cases.
+/// Codes are stable, so clients match on the numeric value.
+#[contracterror(export = false)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum ProtocolError {
+    Unauthorized = 1,
+    InsufficientCollateral = 2,
+    InsufficientCollateralRatio = 3,
+    InvalidAmount = 4,
+    InvalidAddress = 5,
+    PositionNotFound = 6,
+    AlreadyInitialized = 7,
+    NotInitialized = 8,
+    InvalidInput = 9,
+    NotEligibleForLiquidation = 10,
+    ProtocolPaused = 11,
+    AssetNotSupported = 12,
+    OracleFailure = 13,
+    ReentrancyDetected = 14,
+    StorageError = 15,
+    ConfigurationError = 16,
+    NotFound = 17,
+    AlreadyExists = 18,
+    InvalidOperation = 19,
+    RecoveryFailed = 20,
+    InvalidParameters = 21,
+    StorageLimitExceeded = 22,
+    RecoveryModeRestricted = 23,
+    EmergencyFundInsufficient = 24,
+    UserNotVerified = 25,
+    UserSuspended = 26,
+    UserLimitExceeded = 27,
+    UserRoleViolation = 28,
+    BalanceInvariantViolation = 29,
+    InsufficientLiquidity = 30,
+    SlippageProtectionTriggered = 31,
+    AccrualInProgress = 32,
+    SupplyCapExceeded = 33,
+    BorrowCapExceeded = 34,
+    GroupCapExceeded = 35,
+    MigrationRejected = 36,
+    FeatureDisabled = 37,
+    FlashSwapInvariantViolated = 38,
+    ProposalCooldown = 39,
+    GlobalCapExceeded = 40,
+    TokenTransferFailed = 41,
+    OracleFrozen = 42,
+    CoSignerApprovalRequired = 43,
+    DeadlineExceeded = 44,
+    StringTooLong = 45,
+    EmptyString = 46,
+    ReasonRequired = 47,
+    StaleAccrual = 48,
+    PriceDeviationTooHigh = 49,
+    TooManySources = 50,
+    ProposalNotSucceeded = 51,
+    TimelockNotElapsed = 52,
+    AlreadyExecuted = 53,
+    ProposalCancelled = 54,
+    DelegationLoop = 55,
+}
@@ -0,0 +1,42 @@
+//! Fixed-point helpers
+
+use crate::ProtocolError;
+
+/// Rounding direction for a division
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Rounding {
+    Down,
+    Up,
+}
+
+/// `a * b / denominator` in the given rounding direction; `InvalidAmount`
+/// when the product overflows
+pub fn mul_div(
+    a: i128,
+    b: i128,
+    denominator: i128,
+    rounding: Rounding,
+) -> Result<i128, ProtocolError> {
+    let product = a.checked_mul(b).ok_or(ProtocolError::InvalidAmount)?;
+    let quotient = product / denominator;
+    if rounding == Rounding::Up && product % denominator != 0 {
+        return Ok(quotient + 1);
+    }
+    Ok(quotient)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounds_in_the_requested_direction() {
+        assert_eq!(mul_div(10, 3, 4, Rounding::Down), Ok(7));
+        assert_eq!(mul_div(10, 3, 4, Rounding::Up), Ok(8));
+        assert_eq!(mul_div(10, 4, 4, Rounding::Up), Ok(10));
+        assert_eq!(
+            mul_div(i128::MAX, 2, 1, Rounding::Down),
+            Err(ProtocolError::InvalidAmount)
+        );
+    }
+}
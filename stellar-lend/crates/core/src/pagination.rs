@@ -0,0 +1,11 @@
+//! Cursor paging shared by every paginated getter
+
+/// Largest page returned by getters without a tighter bound of their own
+pub const MAX_PAGE_LIMIT: u32 = 50;
+
+/// Index range `[start, end)` of the page at `cursor`, with `limit` capped at `max`
+pub fn bounds(total: u32, cursor: u32, limit: u32, max: u32) -> (u32, u32) {
+    let start = cursor.min(total);
+    let end = start.saturating_add(limit.min(max)).min(total);
+    (start, end)
+}
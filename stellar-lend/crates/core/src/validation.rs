@@ -0,0 +1,23 @@
+//! Validation of user-supplied strings
+//!
+//! Soroban `String`s cannot be inspected character by character on-chain
+//! without copying them out, so only length is checked: strings must be
+//! non-empty and no longer than the limit for their field. Filtering
+//! control characters or other content is left to clients.
+
+use crate::ProtocolError;
+use soroban_sdk::String;
+
+/// Hard upper bound for any configurable string limit, in bytes
+pub const MAX_STRING_LEN: u32 = 1024;
+
+/// Reject `s` if it is empty or longer than `max_len` bytes
+pub fn check_string(s: &String, max_len: u32) -> Result<(), ProtocolError> {
+    if s.is_empty() {
+        return Err(ProtocolError::EmptyString);
+    }
+    if s.len() > max_len {
+        return Err(ProtocolError::StringTooLong);
+    }
+    Ok(())
+}
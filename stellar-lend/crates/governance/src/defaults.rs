@@ -0,0 +1,12 @@
+//! Default governance parameters, persisted by the contract at initialization
+
+/// Share of votes in bps a proposal needs in favour
+pub const GOV_QUORUM_BPS: i128 = 1000;
+/// Delay between queueing and executing a proposal
+pub const GOV_TIMELOCK_SECS: u64 = 60;
+/// Shortest voting period a proposal may request
+pub const GOV_MIN_VOTING_PERIOD_SECS: u64 = 60;
+/// Time a non-admin proposer must wait between proposals
+pub const GOV_PROPOSER_COOLDOWN_SECS: u64 = 60 * 60;
+/// Longest proposal title, in bytes
+pub const GOV_MAX_TITLE_LEN: u32 = 120;
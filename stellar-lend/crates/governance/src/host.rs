@@ -0,0 +1,84 @@
+//! What governance needs from the contract embedding it
+
+use soroban_sdk::{Address, Env, IntoVal, Symbol, TryFromVal, Val, Vec};
+use stellarlend_core::ProtocolError;
+
+/// Contract-side services governance calls into. Implemented once by the
+/// lending contract; every method is static so no host value is threaded
+/// through governance.
+pub trait GovernanceHost {
+    /// Actions a proposal carries. Governance only stores them; the host
+    /// applies them once the proposal executes.
+    type Action: IntoVal<Env, Val> + TryFromVal<Env, Val> + Clone;
+
+    /// Fail with `Unauthorized` unless `caller` is the protocol admin
+    fn require_admin(env: &Env, caller: &Address) -> Result<(), ProtocolError>;
+
+    /// Current protocol admin, if initialized
+    fn admin(env: &Env) -> Option<Address>;
+
+    /// Current guardian, if one is set
+    fn guardian(env: &Env) -> Option<Address>;
+
+    /// Voting power `account` holds itself, before delegation
+    fn voting_power(env: &Env, account: &Address) -> i128;
+
+    /// Fail unless interest was accrued within the last `max_age_secs`, so
+    /// voting power reflects current debt
+    fn ensure_accrued_within(env: &Env, max_age_secs: u64) -> Result<(), ProtocolError>;
+
+    /// Apply the actions of executed proposal `id` in order, with
+    /// `executor` acting for the protocol. Governance marks the proposal
+    /// executed before calling this.
+    fn execute_actions(
+        env: &Env,
+        executor: &Address,
+        id: u64,
+        actions: Vec<Self::Action>,
+    ) -> Result<(), ProtocolError>;
+
+    /// Record an event in the contract's event analytics
+    fn track_event(
+        env: &Env,
+        event_type: Symbol,
+        topics: Vec<Symbol>,
+        user: Option<Address>,
+        asset: Option<Address>,
+        amount: i128,
+    );
+
+    /// Emit the contract's `config_changed` event for a numeric parameter
+    fn record_change(
+        env: &Env,
+        scope: &str,
+        key: &str,
+        asset: Option<&Address>,
+        old: i128,
+        new: i128,
+    );
+
+    /// Write a numeric parameter stored directly under `storage_key`,
+    /// reporting it when the value actually changes. Returns the previous
+    /// value (or `default` if unset).
+    fn set_param<K, V>(
+        env: &Env,
+        scope: &str,
+        key: &str,
+        storage_key: &K,
+        asset: Option<&Address>,
+        default: V,
+        new: V,
+    ) -> V
+    where
+        K: IntoVal<Env, Val>,
+        V: IntoVal<Env, Val> + TryFromVal<Env, Val> + Into<i128> + Copy,
+    {
+        let old: V = env.storage().instance().get(storage_key).unwrap_or(default);
+        env.storage().instance().set(storage_key, &new);
+        let (old_raw, new_raw): (i128, i128) = (old.into(), new.into());
+        if old_raw != new_raw {
+            Self::record_change(env, scope, key, asset, old_raw, new_raw);
+        }
+        old
+    }
+}
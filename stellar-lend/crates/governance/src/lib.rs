@@ -0,0 +1,1057 @@
+//! On-chain governance for StellarLend
+//!
+//! Proposals, voting (direct, delegated and signed), the queue/timelock
+//! lifecycle and cancellation. What a proposal does is up to the contract:
+//! proposals carry [`GovernanceHost::Action`]s, which governance stores and
+//! hands back to the host to apply once the proposal executes. Everything
+//! else governance needs from the lending contract (admin and guardian,
+//! voting power, accrual freshness, event analytics, config-change
+//! reporting) also comes through [`GovernanceHost`]; the contract exposes
+//! `Governance<Host>` as its `governance::Governance`.
+
+#![no_std]
+
+pub mod defaults;
+mod host;
+#[cfg(test)]
+mod test;
+
+pub use host::GovernanceHost;
+
+use core::marker::PhantomData;
+use soroban_sdk::{contracttype, xdr::ToXdr, Address, Bytes, BytesN, Env, Map, Symbol, Vec};
+use stellarlend_core::pagination::{self, MAX_PAGE_LIMIT};
+use stellarlend_core::validation::{self, MAX_STRING_LEN};
+use stellarlend_core::ProtocolError;
+
+/// Upper bound for both the minimum voting period and the proposer cooldown (30 days)
+pub const MAX_GOV_PERIOD_SECS: u64 = 30 * 24 * 60 * 60;
+/// Longest the global indices may go unaccrued before proposing (1 hour)
+pub const MAX_PROPOSAL_ACCRUAL_AGE_SECS: u64 = 60 * 60;
+/// How long a queued proposal stays executable once its timelock ends (14 days)
+pub const EXECUTION_GRACE_SECS: u64 = 14 * 24 * 60 * 60;
+/// Most actions a single proposal may carry
+pub const MAX_PROPOSAL_ACTIONS: u32 = 10;
+/// Most accounts that may delegate to a single delegate
+pub const MAX_DELEGATORS: u32 = 20;
+
+/// Lifecycle of a proposal, derived from its timestamps, votes and flags by
+/// [`Governance::state`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum ProposalState {
+    /// Not yet open for votes; proposals open at creation, so this only
+    /// shows while the ledger is behind `created`
+    Pending,
+    /// Open for votes until `voting_ends`, exclusive
+    Active,
+    /// Voting ended without quorum
+    Defeated,
+    /// Voting ended with quorum, not yet queued
+    Succeeded,
+    /// Queued; executable once `queued_until` is reached
+    Queued,
+    /// Queued but not executed within [`EXECUTION_GRACE_SECS`] of its timelock
+    Expired,
+    Executed,
+    Cancelled,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct Proposal {
+    pub id: u64,
+    pub proposer: Address,
+    pub title: soroban_sdk::String,
+    pub created: u64,
+    pub voting_ends: u64,
+    pub queued_until: u64,
+    pub for_votes: i128,
+    pub against_votes: i128,
+    pub executed: bool,
+}
+
+/// Votes cast on a proposal, stored apart from its metadata so a vote only
+/// writes this small entry
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct Tally {
+    pub for_votes: i128,
+    pub against_votes: i128,
+    pub abstain_votes: i128,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct VoteReceipt {
+    pub voter: Address,
+    pub support: bool,
+    pub weight: i128,
+}
+
+/// Result of one [`Governance::migrate_receipts`] call
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct MigrationProgress {
+    /// Receipts moved by this call
+    pub migrated: u32,
+    /// Receipts still in the legacy map
+    pub remaining: u32,
+    pub complete: bool,
+}
+
+/// Page of proposals in id order
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct ProposalPage {
+    pub items: Vec<Proposal>,
+    /// Size of the whole collection when the page was read
+    pub total: u64,
+    /// Cursor for the next page; equals `total` once exhausted
+    pub cursor: u32,
+}
+
+/// Governance storage, bound to the contract through `H`
+pub struct GovStorage<H>(PhantomData<H>);
+
+impl<H: GovernanceHost> GovStorage<H> {
+    pub fn proposals_key(env: &Env) -> Symbol {
+        Symbol::new(env, "gov_proposals")
+    }
+    pub fn receipts_key(env: &Env) -> Symbol {
+        Symbol::new(env, "gov_receipts")
+    }
+    /// One persistent entry per voter, replacing the per-proposal map
+    pub fn receipt_key(env: &Env, id: u64, voter: &Address) -> (Symbol, u64, Address) {
+        (Symbol::new(env, "gov_receipt"), id, voter.clone())
+    }
+    pub fn receipts_migrated_key(env: &Env, id: u64) -> (Symbol, u64) {
+        (Symbol::new(env, "gov_receipts_migrated"), id)
+    }
+    pub fn voters_key(env: &Env) -> Symbol {
+        Symbol::new(env, "gov_voters")
+    }
+    pub fn tally_key(env: &Env, id: u64) -> (Symbol, u64) {
+        (Symbol::new(env, "gov_tally"), id)
+    }
+    pub fn cancelled_key(env: &Env, id: u64) -> (Symbol, u64) {
+        (Symbol::new(env, "gov_cancelled"), id)
+    }
+    pub fn actions_key(env: &Env, id: u64) -> (Symbol, u64) {
+        (Symbol::new(env, "gov_actions"), id)
+    }
+    pub fn vote_key_key(env: &Env, voter: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "gov_vote_key"), voter.clone())
+    }
+    pub fn vote_key_owner_key(env: &Env, pubkey: &BytesN<32>) -> (Symbol, BytesN<32>) {
+        (Symbol::new(env, "gov_vote_key_owner"), pubkey.clone())
+    }
+    pub fn vote_nonce_key(env: &Env, voter: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "gov_vote_nonce"), voter.clone())
+    }
+    pub fn counter_key(env: &Env) -> Symbol {
+        Symbol::new(env, "gov_counter")
+    }
+    pub fn quorum_bps_key(env: &Env) -> Symbol {
+        Symbol::new(env, "gov_quorum_bps")
+    }
+    pub fn timelock_key(env: &Env) -> Symbol {
+        Symbol::new(env, "gov_timelock")
+    }
+    pub fn delegation_key(env: &Env) -> Symbol {
+        Symbol::new(env, "gov_delegation")
+    }
+    pub fn delegators_key(env: &Env, delegate: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "gov_delegators"), delegate.clone())
+    }
+    pub fn vote_lock_key(env: &Env, voter: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "gov_vote_lock"), voter.clone())
+    }
+    pub fn min_voting_period_key(env: &Env) -> Symbol {
+        Symbol::new(env, "gov_min_voting_period")
+    }
+    pub fn cooldown_key(env: &Env) -> Symbol {
+        Symbol::new(env, "gov_proposer_cooldown")
+    }
+    pub fn max_title_len_key(env: &Env) -> Symbol {
+        Symbol::new(env, "gov_max_title_len")
+    }
+    pub fn last_proposal_key(env: &Env, proposer: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "gov_last_proposal"), proposer.clone())
+    }
+
+    pub fn next_id(env: &Env) -> u64 {
+        let id: u64 = env
+            .storage()
+            .instance()
+            .get(&Self::counter_key(env))
+            .unwrap_or(0);
+        env.storage()
+            .instance()
+            .set(&Self::counter_key(env), &(id + 1));
+        id + 1
+    }
+
+    pub fn save_proposal(env: &Env, p: &Proposal) {
+        let mut map: Map<u64, Proposal> = env
+            .storage()
+            .instance()
+            .get(&Self::proposals_key(env))
+            .unwrap_or_else(|| Map::new(env));
+        map.set(p.id, p.clone());
+        env.storage()
+            .instance()
+            .set(&Self::proposals_key(env), &map);
+    }
+
+    /// ed25519 key `voter` signs relayed votes with, if registered
+    pub fn get_vote_key(env: &Env, voter: &Address) -> Option<BytesN<32>> {
+        env.storage()
+            .instance()
+            .get(&Self::vote_key_key(env, voter))
+    }
+
+    pub fn get_vote_key_owner(env: &Env, pubkey: &BytesN<32>) -> Option<Address> {
+        env.storage()
+            .instance()
+            .get(&Self::vote_key_owner_key(env, pubkey))
+    }
+
+    /// Nonce the next signed vote of `voter` must carry
+    pub fn get_vote_nonce(env: &Env, voter: &Address) -> u64 {
+        env.storage()
+            .instance()
+            .get(&Self::vote_nonce_key(env, voter))
+            .unwrap_or(0)
+    }
+
+    /// Accounts currently delegating to `delegate`
+    pub fn get_delegators(env: &Env, delegate: &Address) -> Vec<Address> {
+        env.storage()
+            .instance()
+            .get(&Self::delegators_key(env, delegate))
+            .unwrap_or_else(|| Vec::new(env))
+    }
+
+    /// End of the latest voting period `voter` has cast a vote in; until
+    /// then the power behind that vote cannot be delegated elsewhere
+    pub fn get_vote_lock(env: &Env, voter: &Address) -> u64 {
+        env.storage()
+            .instance()
+            .get(&Self::vote_lock_key(env, voter))
+            .unwrap_or(0)
+    }
+
+    fn extend_vote_lock(env: &Env, voter: &Address, until: u64) {
+        if until > Self::get_vote_lock(env, voter) {
+            env.storage()
+                .instance()
+                .set(&Self::vote_lock_key(env, voter), &until);
+        }
+    }
+
+    fn set_delegators(env: &Env, delegate: &Address, delegators: &Vec<Address>) {
+        let key = Self::delegators_key(env, delegate);
+        if delegators.is_empty() {
+            env.storage().instance().remove(&key);
+        } else {
+            env.storage().instance().set(&key, delegators);
+        }
+    }
+
+    /// Actions proposal `id` applies when executed, in order
+    pub fn get_actions(env: &Env, id: u64) -> Vec<H::Action> {
+        env.storage()
+            .instance()
+            .get(&Self::actions_key(env, id))
+            .unwrap_or_else(|| Vec::new(env))
+    }
+
+    pub fn is_cancelled(env: &Env, id: u64) -> bool {
+        env.storage()
+            .instance()
+            .get(&Self::cancelled_key(env, id))
+            .unwrap_or(false)
+    }
+
+    /// Proposal metadata merged with its tally
+    pub fn get_proposal(env: &Env, id: u64) -> Option<Proposal> {
+        Self::get_metadata(env, id).map(|p| Self::with_tally(env, p))
+    }
+
+    /// Proposal as last written at propose/queue/execute; its embedded
+    /// votes are stale once a tally has been stored
+    fn get_metadata(env: &Env, id: u64) -> Option<Proposal> {
+        let map: Map<u64, Proposal> = env
+            .storage()
+            .instance()
+            .get(&Self::proposals_key(env))
+            .unwrap_or_else(|| Map::new(env));
+        map.get(id)
+    }
+
+    fn with_tally(env: &Env, mut p: Proposal) -> Proposal {
+        if let Some(tally) = Self::get_stored_tally(env, p.id) {
+            p.for_votes = tally.for_votes;
+            p.against_votes = tally.against_votes;
+        }
+        p
+    }
+
+    fn get_stored_tally(env: &Env, id: u64) -> Option<Tally> {
+        env.storage().instance().get(&Self::tally_key(env, id))
+    }
+
+    /// Votes cast on proposal `id`. Proposals from before tallies were
+    /// split out report the votes embedded in their metadata until their
+    /// next vote moves them over.
+    pub fn get_tally(env: &Env, id: u64) -> Tally {
+        Self::get_stored_tally(env, id).unwrap_or_else(|| {
+            let (for_votes, against_votes) = Self::get_metadata(env, id)
+                .map(|p| (p.for_votes, p.against_votes))
+                .unwrap_or((0, 0));
+            Tally {
+                for_votes,
+                against_votes,
+                abstain_votes: 0,
+            }
+        })
+    }
+
+    fn save_tally(env: &Env, id: u64, tally: &Tally) {
+        env.storage()
+            .instance()
+            .set(&Self::tally_key(env, id), tally);
+    }
+
+    /// Proposals in ascending id order (creation order). The cursor is the
+    /// number of ids already read and the total is the id counter.
+    pub fn list_proposals(env: &Env, cursor: u32, limit: u32) -> ProposalPage {
+        let map: Map<u64, Proposal> = env
+            .storage()
+            .instance()
+            .get(&Self::proposals_key(env))
+            .unwrap_or_else(|| Map::new(env));
+        // Ids are allocated sequentially, so walking the counter pins the
+        // order independently of how the map sorts its keys.
+        let last: u64 = env
+            .storage()
+            .instance()
+            .get(&Self::counter_key(env))
+            .unwrap_or(0);
+        let (start, end) = pagination::bounds(last as u32, cursor, limit, MAX_PAGE_LIMIT);
+        let mut items = Vec::new(env);
+        for id in (start + 1)..=end {
+            if let Some(p) = map.get(id as u64) {
+                items.push_back(Self::with_tally(env, p));
+            }
+        }
+        ProposalPage {
+            items,
+            total: last,
+            cursor: end,
+        }
+    }
+
+    /// Write a receipt to its per-voter entry, dropping any legacy copy
+    pub fn save_receipt(env: &Env, id: u64, r: &VoteReceipt) {
+        if Self::get_receipt(env, id, &r.voter).is_none() {
+            let voters_key = (Self::voters_key(env), id);
+            let mut voters: Vec<Address> = env
+                .storage()
+                .instance()
+                .get(&voters_key)
+                .unwrap_or_else(|| Vec::new(env));
+            voters.push_back(r.voter.clone());
+            env.storage().instance().set(&voters_key, &voters);
+        }
+        env.storage()
+            .persistent()
+            .set(&Self::receipt_key(env, id, &r.voter), r);
+        let mut legacy = Self::legacy_receipts(env, id);
+        if legacy.contains_key(r.voter.clone()) {
+            legacy.remove(r.voter.clone());
+            Self::put_legacy_receipts(env, id, &legacy);
+        }
+    }
+
+    /// A voter's receipt, from its own entry or, until the proposal is
+    /// fully migrated, the legacy map
+    pub fn get_receipt(env: &Env, id: u64, voter: &Address) -> Option<VoteReceipt> {
+        let receipt = env
+            .storage()
+            .persistent()
+            .get(&Self::receipt_key(env, id, voter));
+        if receipt.is_some() || Self::receipts_migrated(env, id) {
+            return receipt;
+        }
+        Self::legacy_receipts(env, id).get(voter.clone())
+    }
+
+    /// Receipts for a proposal in the order voters first voted.
+    pub fn list_receipts(env: &Env, id: u64) -> Vec<VoteReceipt> {
+        let voters: Vec<Address> = env
+            .storage()
+            .instance()
+            .get(&(Self::voters_key(env), id))
+            .unwrap_or_else(|| Vec::new(env));
+        let legacy = if Self::receipts_migrated(env, id) {
+            Map::new(env)
+        } else {
+            Self::legacy_receipts(env, id)
+        };
+        let mut out = Vec::new(env);
+        for voter in voters.iter() {
+            let receipt = env
+                .storage()
+                .persistent()
+                .get(&Self::receipt_key(env, id, &voter))
+                .or_else(|| legacy.get(voter));
+            if let Some(r) = receipt {
+                out.push_back(r);
+            }
+        }
+        out
+    }
+
+    /// Whether every receipt of `id` has left the legacy map
+    pub fn receipts_migrated(env: &Env, id: u64) -> bool {
+        env.storage()
+            .instance()
+            .get(&Self::receipts_migrated_key(env, id))
+            .unwrap_or(false)
+    }
+
+    /// Receipts of `id` still held in its instance-storage map
+    fn legacy_receipts(env: &Env, id: u64) -> Map<Address, VoteReceipt> {
+        env.storage()
+            .instance()
+            .get(&(Self::receipts_key(env), id))
+            .unwrap_or_else(|| Map::new(env))
+    }
+
+    fn put_legacy_receipts(env: &Env, id: u64, map: &Map<Address, VoteReceipt>) {
+        let key = (Self::receipts_key(env), id);
+        if map.is_empty() {
+            env.storage().instance().remove(&key);
+        } else {
+            env.storage().instance().set(&key, map);
+        }
+    }
+
+    /// Move up to `max_entries` receipts of `id` from the legacy map to
+    /// their own entries, marking the proposal migrated once none are left
+    pub fn migrate_receipts(env: &Env, id: u64, max_entries: u32) -> MigrationProgress {
+        let mut legacy = Self::legacy_receipts(env, id);
+        let batch: Vec<Address> = legacy.keys().slice(0..max_entries.min(legacy.len()));
+        for voter in batch.iter() {
+            let key = Self::receipt_key(env, id, &voter);
+            // A vote cast since the map was written already lives in its entry
+            if !env.storage().persistent().has(&key) {
+                env.storage()
+                    .persistent()
+                    .set(&key, &legacy.get(voter.clone()).unwrap());
+            }
+            legacy.remove(voter);
+        }
+        Self::put_legacy_receipts(env, id, &legacy);
+        let complete = legacy.is_empty();
+        if complete {
+            env.storage()
+                .instance()
+                .set(&Self::receipts_migrated_key(env, id), &true);
+        }
+        MigrationProgress {
+            migrated: batch.len(),
+            remaining: legacy.len(),
+            complete,
+        }
+    }
+
+    pub fn get_quorum_bps(env: &Env) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::quorum_bps_key(env))
+            .unwrap_or(defaults::GOV_QUORUM_BPS)
+    }
+    pub fn set_quorum_bps(env: &Env, bps: i128) -> Result<(), ProtocolError> {
+        if bps <= 0 || bps > 10000 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        H::set_param(
+            env,
+            "governance",
+            "quorum_bps",
+            &Self::quorum_bps_key(env),
+            None,
+            defaults::GOV_QUORUM_BPS,
+            bps,
+        );
+        Ok(())
+    }
+    pub fn get_timelock(env: &Env) -> u64 {
+        env.storage()
+            .instance()
+            .get(&Self::timelock_key(env))
+            .unwrap_or(defaults::GOV_TIMELOCK_SECS)
+    }
+    pub fn set_timelock(env: &Env, secs: u64) -> Result<(), ProtocolError> {
+        if secs > MAX_GOV_PERIOD_SECS {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        H::set_param(
+            env,
+            "governance",
+            "timelock",
+            &Self::timelock_key(env),
+            None,
+            defaults::GOV_TIMELOCK_SECS,
+            secs,
+        );
+        Ok(())
+    }
+    pub fn get_min_voting_period(env: &Env) -> u64 {
+        env.storage()
+            .instance()
+            .get(&Self::min_voting_period_key(env))
+            .unwrap_or(defaults::GOV_MIN_VOTING_PERIOD_SECS)
+    }
+    pub fn set_min_voting_period(env: &Env, secs: u64) -> Result<(), ProtocolError> {
+        if secs > MAX_GOV_PERIOD_SECS {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        H::set_param(
+            env,
+            "governance",
+            "min_voting_period",
+            &Self::min_voting_period_key(env),
+            None,
+            defaults::GOV_MIN_VOTING_PERIOD_SECS,
+            secs,
+        );
+        Ok(())
+    }
+    pub fn get_proposer_cooldown(env: &Env) -> u64 {
+        env.storage()
+            .instance()
+            .get(&Self::cooldown_key(env))
+            .unwrap_or(defaults::GOV_PROPOSER_COOLDOWN_SECS)
+    }
+    pub fn set_proposer_cooldown(env: &Env, secs: u64) -> Result<(), ProtocolError> {
+        if secs > MAX_GOV_PERIOD_SECS {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        H::set_param(
+            env,
+            "governance",
+            "proposer_cooldown",
+            &Self::cooldown_key(env),
+            None,
+            defaults::GOV_PROPOSER_COOLDOWN_SECS,
+            secs,
+        );
+        Ok(())
+    }
+    pub fn get_max_title_len(env: &Env) -> u32 {
+        env.storage()
+            .instance()
+            .get(&Self::max_title_len_key(env))
+            .unwrap_or(defaults::GOV_MAX_TITLE_LEN)
+    }
+    pub fn set_max_title_len(env: &Env, len: u32) -> Result<(), ProtocolError> {
+        if len == 0 || len > MAX_STRING_LEN {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        H::set_param(
+            env,
+            "governance",
+            "max_title_len",
+            &Self::max_title_len_key(env),
+            None,
+            defaults::GOV_MAX_TITLE_LEN,
+            len,
+        );
+        Ok(())
+    }
+    /// Timestamp of the proposer's most recent proposal
+    pub fn get_last_proposal(env: &Env, proposer: &Address) -> Option<u64> {
+        env.storage()
+            .instance()
+            .get(&Self::last_proposal_key(env, proposer))
+    }
+    fn set_last_proposal(env: &Env, proposer: &Address, ts: u64) {
+        env.storage()
+            .instance()
+            .set(&Self::last_proposal_key(env, proposer), &ts);
+    }
+}
+
+/// Proposal lifecycle, bound to the contract through `H`
+pub struct Governance<H>(PhantomData<H>);
+
+impl<H: GovernanceHost> Governance<H> {
+    /// Create a proposal applying `actions` once executed. The title must be
+    /// non-empty and within the configured maximum length, there must be
+    /// between one and [`MAX_PROPOSAL_ACTIONS`] actions, the voting period
+    /// must be at least the
+    /// configured minimum, and a proposer other than the admin must wait out
+    /// the cooldown since their previous proposal. Interest must have been
+    /// accrued within the last hour so voting power reflects current debt;
+    /// otherwise call `accrue` first.
+    pub fn propose(
+        env: &Env,
+        proposer: &Address,
+        title: soroban_sdk::String,
+        actions: Vec<H::Action>,
+        voting_period_secs: u64,
+    ) -> Result<Proposal, ProtocolError> {
+        validation::check_string(&title, GovStorage::<H>::get_max_title_len(env))?;
+        if actions.is_empty() || actions.len() > MAX_PROPOSAL_ACTIONS {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        H::ensure_accrued_within(env, MAX_PROPOSAL_ACCRUAL_AGE_SECS)?;
+        if voting_period_secs < GovStorage::<H>::get_min_voting_period(env) {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        let now = env.ledger().timestamp();
+        if H::admin(env).as_ref() != Some(proposer) {
+            if let Some(last) = GovStorage::<H>::get_last_proposal(env, proposer) {
+                if now < last.saturating_add(GovStorage::<H>::get_proposer_cooldown(env)) {
+                    return Err(ProtocolError::ProposalCooldown);
+                }
+            }
+        }
+        GovStorage::<H>::set_last_proposal(env, proposer, now);
+        let id = GovStorage::<H>::next_id(env);
+        let p = Proposal {
+            id,
+            proposer: proposer.clone(),
+            title,
+            created: now,
+            voting_ends: now + voting_period_secs,
+            queued_until: 0,
+            for_votes: 0,
+            against_votes: 0,
+            executed: false,
+        };
+        GovStorage::<H>::save_proposal(env, &p);
+        env.storage()
+            .instance()
+            .set(&GovStorage::<H>::actions_key(env, id), &actions);
+        Ok(p)
+    }
+
+    /// Record `voter`'s vote, which they must authorize, with their
+    /// [`Self::get_voting_power`]. Voting again replaces their previous vote
+    /// rather than adding to it. Only the proposal's tally is written; its
+    /// metadata entry is left untouched. Fails with `DeadlineExceeded` once
+    /// voting has ended and `InvalidOperation` without voting power.
+    pub fn vote(
+        env: &Env,
+        id: u64,
+        voter: &Address,
+        support: bool,
+    ) -> Result<Proposal, ProtocolError> {
+        voter.require_auth();
+        let weight = Self::get_voting_power(env, voter);
+        if weight <= 0 {
+            return Err(ProtocolError::InvalidOperation);
+        }
+        Self::record_vote(env, id, voter, support, weight)
+    }
+
+    /// Weight `voter` votes with: their synced power unless they delegated
+    /// it away, plus the synced power of everyone delegating to them.
+    /// Delegation is one level deep; power delegated to an account that has
+    /// itself delegated stays with that account.
+    pub fn get_voting_power(env: &Env, voter: &Address) -> i128 {
+        let own = if Self::get_delegate(env, voter).is_some() {
+            0
+        } else {
+            H::voting_power(env, voter)
+        };
+        GovStorage::<H>::get_delegators(env, voter)
+            .iter()
+            .fold(own, |total, delegator| {
+                total.saturating_add(H::voting_power(env, &delegator))
+            })
+    }
+
+    fn record_vote(
+        env: &Env,
+        id: u64,
+        voter: &Address,
+        support: bool,
+        weight: i128,
+    ) -> Result<Proposal, ProtocolError> {
+        let mut p = Self::get_live(env, id)?;
+        if env.ledger().timestamp() >= p.voting_ends {
+            return Err(ProtocolError::DeadlineExceeded);
+        }
+        let mut tally = GovStorage::<H>::get_tally(env, id);
+        if let Some(previous) = GovStorage::<H>::get_receipt(env, id, voter) {
+            if previous.support {
+                tally.for_votes -= previous.weight;
+            } else {
+                tally.against_votes -= previous.weight;
+            }
+        }
+        if support {
+            tally.for_votes += weight;
+        } else {
+            tally.against_votes += weight;
+        }
+        GovStorage::<H>::save_tally(env, id, &tally);
+        GovStorage::<H>::extend_vote_lock(env, voter, p.voting_ends);
+        p.for_votes = tally.for_votes;
+        p.against_votes = tally.against_votes;
+        GovStorage::<H>::save_receipt(
+            env,
+            id,
+            &VoteReceipt {
+                voter: voter.clone(),
+                support,
+                weight,
+            },
+        );
+        Ok(p)
+    }
+
+    /// Link `voter` to the ed25519 key it signs relayed votes with. Both
+    /// sides must agree: the voter authorizes the call and the key signs
+    /// [`Self::vote_key_message`], so nobody can claim a key they do not
+    /// hold. A key serves one account; registering a new key retires the
+    /// previous one. The voter's nonce is consumed, invalidating votes
+    /// signed but not yet relayed. A bad signature aborts the call.
+    pub fn register_vote_key(
+        env: &Env,
+        voter: &Address,
+        pubkey: &BytesN<32>,
+        signature: &BytesN<64>,
+    ) -> Result<(), ProtocolError> {
+        voter.require_auth();
+        match GovStorage::<H>::get_vote_key_owner(env, pubkey) {
+            Some(owner) if owner == *voter => return Ok(()),
+            Some(_) => return Err(ProtocolError::AlreadyExists),
+            None => {}
+        }
+        let message = Self::vote_key_message(env, voter);
+        env.crypto().ed25519_verify(pubkey, &message, signature);
+        Self::consume_vote_nonce(env, voter);
+        if let Some(old) = GovStorage::<H>::get_vote_key(env, voter) {
+            env.storage()
+                .instance()
+                .remove(&GovStorage::<H>::vote_key_owner_key(env, &old));
+        }
+        env.storage()
+            .instance()
+            .set(&GovStorage::<H>::vote_key_key(env, voter), pubkey);
+        env.storage()
+            .instance()
+            .set(&GovStorage::<H>::vote_key_owner_key(env, pubkey), voter);
+
+        let event_type = Symbol::new(env, "vote_key_registered");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        H::track_event(
+            env,
+            event_type.clone(),
+            topics,
+            Some(voter.clone()),
+            None,
+            0,
+        );
+        env.events()
+            .publish((event_type, voter.clone()), pubkey.clone());
+        Ok(())
+    }
+
+    /// Message a key signs to be registered to `voter`: the XDR of
+    /// (tag, contract, voter, nonce)
+    pub fn vote_key_message(env: &Env, voter: &Address) -> Bytes {
+        (
+            Symbol::new(env, "gov_vote_key"),
+            env.current_contract_address(),
+            voter.clone(),
+            GovStorage::<H>::get_vote_nonce(env, voter),
+        )
+            .to_xdr(env)
+    }
+
+    /// Canonical message `voter`'s registered key signs to have `relayer`
+    /// submit its vote on `id`: the XDR of (tag, contract, proposal id,
+    /// choice, expiry, nonce, relayer)
+    pub fn vote_message(
+        env: &Env,
+        id: u64,
+        voter: &Address,
+        support: bool,
+        expiry: u64,
+        relayer: &Address,
+    ) -> Bytes {
+        (
+            Symbol::new(env, "gov_vote_sig"),
+            env.current_contract_address(),
+            id,
+            support,
+            expiry,
+            GovStorage::<H>::get_vote_nonce(env, voter),
+            relayer.clone(),
+        )
+            .to_xdr(env)
+    }
+
+    fn consume_vote_nonce(env: &Env, voter: &Address) {
+        env.storage().instance().set(
+            &GovStorage::<H>::vote_nonce_key(env, voter),
+            &(GovStorage::<H>::get_vote_nonce(env, voter) + 1),
+        );
+    }
+
+    /// Record a vote signed offline by the key registered to an account and
+    /// submitted by the relayer named in the signed message, who must
+    /// authorize; a leaked signature is useless to anyone else. The account
+    /// votes with its [`Self::get_voting_power`] and its nonce is consumed,
+    /// so the same signature cannot be replayed. A bad signature aborts the
+    /// call.
+    pub fn vote_by_sig(
+        env: &Env,
+        relayer: &Address,
+        id: u64,
+        pubkey: &BytesN<32>,
+        support: bool,
+        expiry: u64,
+        signature: &BytesN<64>,
+    ) -> Result<Proposal, ProtocolError> {
+        relayer.require_auth();
+        let voter =
+            GovStorage::<H>::get_vote_key_owner(env, pubkey).ok_or(ProtocolError::NotFound)?;
+        if env.ledger().timestamp() > expiry {
+            return Err(ProtocolError::DeadlineExceeded);
+        }
+        let message = Self::vote_message(env, id, &voter, support, expiry, relayer);
+        env.crypto().ed25519_verify(pubkey, &message, signature);
+        Self::consume_vote_nonce(env, &voter);
+
+        let weight = Self::get_voting_power(env, &voter);
+        if weight <= 0 {
+            return Err(ProtocolError::InvalidOperation);
+        }
+        Self::record_vote(env, id, &voter, support, weight)
+    }
+
+    /// Move a bounded batch of a proposal's vote receipts out of instance
+    /// storage (admin only); call until `complete`
+    pub fn migrate_receipts(
+        env: &Env,
+        caller: &Address,
+        id: u64,
+        max_entries: u32,
+    ) -> Result<MigrationProgress, ProtocolError> {
+        H::require_admin(env, caller)?;
+        if max_entries == 0 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        if GovStorage::<H>::get_proposal(env, id).is_none() {
+            return Err(ProtocolError::NotFound);
+        }
+        Ok(GovStorage::<H>::migrate_receipts(env, id, max_entries))
+    }
+
+    /// Where proposal `id` is in its lifecycle, or `None` if it does not exist
+    pub fn state(env: &Env, id: u64) -> Option<ProposalState> {
+        let p = GovStorage::<H>::get_proposal(env, id)?;
+        let now = env.ledger().timestamp();
+        Some(if GovStorage::<H>::is_cancelled(env, id) {
+            ProposalState::Cancelled
+        } else if p.executed {
+            ProposalState::Executed
+        } else if p.queued_until != 0 {
+            if now >= p.queued_until.saturating_add(EXECUTION_GRACE_SECS) {
+                ProposalState::Expired
+            } else {
+                ProposalState::Queued
+            }
+        } else if now < p.created {
+            ProposalState::Pending
+        } else if now < p.voting_ends {
+            ProposalState::Active
+        } else if Self::has_quorum(env, &p) {
+            ProposalState::Succeeded
+        } else {
+            ProposalState::Defeated
+        })
+    }
+
+    fn has_quorum(env: &Env, p: &Proposal) -> bool {
+        let total = p.for_votes + p.against_votes;
+        total > 0 && p.for_votes * 10000 / total >= GovStorage::<H>::get_quorum_bps(env)
+    }
+
+    /// Start the timelock of a `Succeeded` proposal. Fails with
+    /// `AlreadyExecuted` once executed and `ProposalNotSucceeded` in any
+    /// other state.
+    pub fn queue(env: &Env, id: u64) -> Result<Proposal, ProtocolError> {
+        let mut p = Self::get_live(env, id)?;
+        match Self::state(env, id) {
+            Some(ProposalState::Succeeded) => {}
+            Some(ProposalState::Executed) => return Err(ProtocolError::AlreadyExecuted),
+            _ => return Err(ProtocolError::ProposalNotSucceeded),
+        }
+        p.queued_until = env.ledger().timestamp() + GovStorage::<H>::get_timelock(env);
+        GovStorage::<H>::save_proposal(env, &p);
+        Ok(p)
+    }
+
+    /// Execute a `Queued` proposal whose timelock has elapsed, handing its
+    /// actions to [`GovernanceHost::execute_actions`] with the admin as
+    /// executor. An action failing fails the call with that action's error,
+    /// reverting the ones applied before it.
+    /// Fails with `TimelockNotElapsed` until it is queued and its timelock
+    /// has run, `DeadlineExceeded` once it has expired, `AlreadyExecuted` if
+    /// already executed, and `ProposalNotSucceeded` if it did not pass.
+    pub fn execute(env: &Env, id: u64) -> Result<Proposal, ProtocolError> {
+        let mut p = Self::get_live(env, id)?;
+        match Self::state(env, id) {
+            Some(ProposalState::Queued) if env.ledger().timestamp() >= p.queued_until => {}
+            Some(ProposalState::Queued | ProposalState::Succeeded) => {
+                return Err(ProtocolError::TimelockNotElapsed)
+            }
+            Some(ProposalState::Expired) => return Err(ProtocolError::DeadlineExceeded),
+            Some(ProposalState::Executed) => return Err(ProtocolError::AlreadyExecuted),
+            _ => return Err(ProtocolError::ProposalNotSucceeded),
+        }
+        p.executed = true;
+        GovStorage::<H>::save_proposal(env, &p);
+
+        let admin = H::admin(env).ok_or(ProtocolError::ConfigurationError)?;
+        H::execute_actions(env, &admin, id, GovStorage::<H>::get_actions(env, id))?;
+        Ok(p)
+    }
+
+    /// Emit `proposal_action_executed` for the action at `index` of proposal
+    /// `id`, as the host applies it
+    pub fn emit_action_executed(env: &Env, id: u64, index: u32, kind: &str) {
+        let event_type = Symbol::new(env, "proposal_action_executed");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        topics.push_back(Symbol::new(env, kind));
+        H::track_event(env, event_type.clone(), topics, None, None, id as i128);
+        env.events()
+            .publish((event_type, Symbol::new(env, kind)), (id, index));
+    }
+
+    /// Withdraw a proposal before it executes. Its proposer may cancel until
+    /// voting ends; the guardian or admin may cancel at any point, including
+    /// while it is queued. Cancelled proposals fail to vote, queue, execute
+    /// or cancel again with `ProposalCancelled`.
+    pub fn cancel(env: &Env, caller: &Address, id: u64) -> Result<Proposal, ProtocolError> {
+        let p = Self::get_live(env, id)?;
+        if p.executed {
+            return Err(ProtocolError::AlreadyExecuted);
+        }
+        let by_proposer = *caller == p.proposer && env.ledger().timestamp() < p.voting_ends;
+        if !by_proposer && H::guardian(env).as_ref() != Some(caller) {
+            H::require_admin(env, caller)?;
+        }
+        env.storage()
+            .instance()
+            .set(&GovStorage::<H>::cancelled_key(env, id), &true);
+
+        let event_type = Symbol::new(env, "proposal_cancelled");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        H::track_event(
+            env,
+            event_type.clone(),
+            topics,
+            Some(caller.clone()),
+            None,
+            id as i128,
+        );
+        env.events()
+            .publish((event_type, caller.clone()), (id, p.queued_until));
+        Ok(p)
+    }
+
+    /// A proposal that exists and has not been cancelled
+    fn get_live(env: &Env, id: u64) -> Result<Proposal, ProtocolError> {
+        let p = GovStorage::<H>::get_proposal(env, id).ok_or(ProtocolError::NotFound)?;
+        if GovStorage::<H>::is_cancelled(env, id) {
+            return Err(ProtocolError::ProposalCancelled);
+        }
+        Ok(p)
+    }
+
+    /// Delegate `from`'s voting power to `to`, replacing any previous
+    /// delegate (requires `from`'s auth). `from` can then no longer vote
+    /// with its own power. Fails with `DelegationLoop` if `to` is `from`
+    /// or delegates back to it, directly or through a chain, and with
+    /// `UserLimitExceeded` once `to` has [`MAX_DELEGATORS`] delegators.
+    ///
+    /// Vote weight is read when the vote is cast, so power already counted
+    /// on a proposal still open for votes must not move: while `from`, or
+    /// its current delegate, has voted on such a proposal the call fails
+    /// with `ProposalCooldown`.
+    pub fn delegate(env: &Env, from: &Address, to: &Address) -> Result<(), ProtocolError> {
+        from.require_auth();
+        let mut next = Some(to.clone());
+        while let Some(account) = next {
+            if account == *from {
+                return Err(ProtocolError::DelegationLoop);
+            }
+            next = Self::get_delegate(env, &account);
+        }
+
+        let previous = Self::get_delegate(env, from);
+        if previous.as_ref() == Some(to) {
+            return Ok(());
+        }
+        Self::ensure_power_unlocked(env, from)?;
+        let mut delegators = GovStorage::<H>::get_delegators(env, to);
+        if delegators.len() >= MAX_DELEGATORS {
+            return Err(ProtocolError::UserLimitExceeded);
+        }
+        if let Some(previous) = previous {
+            let mut old = GovStorage::<H>::get_delegators(env, &previous);
+            if let Some(index) = old.first_index_of(from) {
+                old.remove(index);
+            }
+            GovStorage::<H>::set_delegators(env, &previous, &old);
+        }
+        delegators.push_back(from.clone());
+        GovStorage::<H>::set_delegators(env, to, &delegators);
+        let key = (GovStorage::<H>::delegation_key(env), from.clone());
+        env.storage().instance().set(&key, to);
+
+        let event_type = Symbol::new(env, "votes_delegated");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        H::track_event(
+            env,
+            event_type.clone(),
+            topics,
+            Some(from.clone()),
+            None,
+            H::voting_power(env, from),
+        );
+        env.events().publish((event_type, from.clone()), to.clone());
+        Ok(())
+    }
+
+    /// Fail with `ProposalCooldown` while `account`'s own power is counted
+    /// in a vote still open, whether cast by `account` or by its delegate
+    pub fn ensure_power_unlocked(env: &Env, account: &Address) -> Result<(), ProtocolError> {
+        let now = env.ledger().timestamp();
+        let locked = |voter: &Address| now < GovStorage::<H>::get_vote_lock(env, voter);
+        if locked(account) || Self::get_delegate(env, account).is_some_and(|d| locked(&d)) {
+            return Err(ProtocolError::ProposalCooldown);
+        }
+        Ok(())
+    }
+
+    pub fn get_delegate(env: &Env, from: &Address) -> Option<Address> {
+        let key = (GovStorage::<H>::delegation_key(env), from.clone());
+        env.storage().instance().get(&key)
+    }
+}
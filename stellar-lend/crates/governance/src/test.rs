@@ -0,0 +1,192 @@
+use crate::{GovStorage, Governance, GovernanceHost, ProposalState};
+use soroban_sdk::testutils::{Address as _, Ledger};
+use soroban_sdk::{contract, contractimpl, vec, Address, Env, String, Symbol, Vec};
+use stellarlend_core::ProtocolError;
+
+/// Contract governance storage lives in during tests
+#[contract]
+pub struct Harness;
+
+#[contractimpl]
+impl Harness {
+    pub fn noop(_env: Env) {}
+}
+
+/// Host keeping the admin and voting power in storage and recording the
+/// actions it is asked to apply
+struct TestHost;
+
+impl TestHost {
+    fn set_admin(env: &Env, admin: &Address) {
+        env.storage()
+            .instance()
+            .set(&Symbol::new(env, "admin"), admin);
+    }
+
+    fn set_power(env: &Env, account: &Address, power: i128) {
+        env.storage().instance().set(account, &power);
+    }
+
+    fn applied(env: &Env) -> Vec<u32> {
+        env.storage()
+            .instance()
+            .get(&Symbol::new(env, "applied"))
+            .unwrap_or_else(|| Vec::new(env))
+    }
+}
+
+impl GovernanceHost for TestHost {
+    type Action = u32;
+
+    fn require_admin(env: &Env, caller: &Address) -> Result<(), ProtocolError> {
+        if Self::admin(env).as_ref() != Some(caller) {
+            return Err(ProtocolError::Unauthorized);
+        }
+        Ok(())
+    }
+
+    fn admin(env: &Env) -> Option<Address> {
+        env.storage().instance().get(&Symbol::new(env, "admin"))
+    }
+
+    fn guardian(_env: &Env) -> Option<Address> {
+        None
+    }
+
+    fn voting_power(env: &Env, account: &Address) -> i128 {
+        env.storage().instance().get(account).unwrap_or(0)
+    }
+
+    fn ensure_accrued_within(_env: &Env, _max_age_secs: u64) -> Result<(), ProtocolError> {
+        Ok(())
+    }
+
+    fn execute_actions(
+        env: &Env,
+        _executor: &Address,
+        _id: u64,
+        actions: Vec<u32>,
+    ) -> Result<(), ProtocolError> {
+        let mut applied = Self::applied(env);
+        applied.append(&actions);
+        env.storage()
+            .instance()
+            .set(&Symbol::new(env, "applied"), &applied);
+        Ok(())
+    }
+
+    fn track_event(
+        _env: &Env,
+        _event_type: Symbol,
+        _topics: Vec<Symbol>,
+        _user: Option<Address>,
+        _asset: Option<Address>,
+        _amount: i128,
+    ) {
+    }
+
+    fn record_change(
+        _env: &Env,
+        _scope: &str,
+        _key: &str,
+        _asset: Option<&Address>,
+        _old: i128,
+        _new: i128,
+    ) {
+    }
+}
+
+type Gov = Governance<TestHost>;
+type Storage = GovStorage<TestHost>;
+
+fn setup(env: &Env) -> (Address, Address) {
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1_000);
+    let contract_id = env.register(Harness, ());
+    let admin = Address::generate(env);
+    env.as_contract(&contract_id, || TestHost::set_admin(env, &admin));
+    (contract_id, admin)
+}
+
+#[test]
+fn test_executed_proposal_hands_actions_to_the_host() {
+    let env = Env::default();
+    let (contract_id, admin) = setup(&env);
+    let voter = Address::generate(&env);
+
+    env.as_contract(&contract_id, || {
+        TestHost::set_power(&env, &voter, 10);
+        let title = String::from_str(&env, "apply");
+        let id = Gov::propose(&env, &admin, title, vec![&env, 1, 2, 3], 100)
+            .unwrap()
+            .id;
+        assert_eq!(Storage::get_actions(&env, id), vec![&env, 1, 2, 3]);
+        Gov::vote(&env, id, &voter, true).unwrap();
+        assert_eq!(
+            Gov::queue(&env, id),
+            Err(ProtocolError::ProposalNotSucceeded)
+        );
+
+        env.ledger().with_mut(|l| l.timestamp += 100);
+        Gov::queue(&env, id).unwrap();
+        assert_eq!(
+            Gov::execute(&env, id),
+            Err(ProtocolError::TimelockNotElapsed)
+        );
+        assert!(TestHost::applied(&env).is_empty());
+
+        env.ledger()
+            .with_mut(|l| l.timestamp += Storage::get_timelock(&env));
+        assert!(Gov::execute(&env, id).unwrap().executed);
+        assert_eq!(TestHost::applied(&env), vec![&env, 1, 2, 3]);
+        assert_eq!(Gov::state(&env, id), Some(ProposalState::Executed));
+        assert_eq!(Gov::execute(&env, id), Err(ProtocolError::AlreadyExecuted));
+    });
+}
+
+#[test]
+fn test_cancelled_proposal_never_reaches_the_host() {
+    let env = Env::default();
+    let (contract_id, admin) = setup(&env);
+    let voter = Address::generate(&env);
+
+    env.as_contract(&contract_id, || {
+        TestHost::set_power(&env, &voter, 10);
+        let title = String::from_str(&env, "cancel");
+        let id = Gov::propose(&env, &admin, title, vec![&env, 7], 100)
+            .unwrap()
+            .id;
+        Gov::vote(&env, id, &voter, true).unwrap();
+        env.ledger().with_mut(|l| l.timestamp += 100);
+        Gov::queue(&env, id).unwrap();
+
+        Gov::cancel(&env, &admin, id).unwrap();
+        env.ledger()
+            .with_mut(|l| l.timestamp += Storage::get_timelock(&env));
+        assert_eq!(
+            Gov::execute(&env, id),
+            Err(ProtocolError::ProposalCancelled)
+        );
+        assert_eq!(Gov::state(&env, id), Some(ProposalState::Cancelled));
+        assert!(TestHost::applied(&env).is_empty());
+    });
+}
+
+#[test]
+fn test_delegation_uses_host_voting_power() {
+    let env = Env::default();
+    let (contract_id, _admin) = setup(&env);
+    let [a, b] = [0; 2].map(|_| Address::generate(&env));
+
+    env.as_contract(&contract_id, || {
+        TestHost::set_power(&env, &a, 30);
+        TestHost::set_power(&env, &b, 50);
+        Gov::delegate(&env, &a, &b).unwrap();
+        assert_eq!(Gov::get_voting_power(&env, &a), 0);
+        assert_eq!(Gov::get_voting_power(&env, &b), 80);
+        assert_eq!(
+            Gov::delegate(&env, &b, &a),
+            Err(ProtocolError::DelegationLoop)
+        );
+    });
+}
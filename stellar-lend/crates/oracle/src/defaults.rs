@@ -0,0 +1,32 @@
+//! Default oracle parameters, persisted by the contract at initialization
+
+/// Maximum age of an oracle source price
+pub const ORACLE_HEARTBEAT_TTL_SECS: u64 = 300;
+/// Aggregation mode (0 = median, 1 = twap, 2 = ema)
+pub const ORACLE_MODE: i128 = 0;
+/// Maximum deviation from the median before a sample is rejected, in bps
+pub const ORACLE_DEVIATION_BPS: i128 = 500;
+/// Highest/lowest samples trimmed in median mode
+pub const ORACLE_TRIM_COUNT: i128 = 1;
+/// Window averaged over in TWAP mode, in seconds
+pub const ORACLE_TWAP_WINDOW: i128 = 30 * 60;
+/// Aggregated prices kept per asset for TWAP
+pub const ORACLE_TWAP_BUFFER_LEN: u32 = 32;
+/// Weight of each new price in the EMA, in bps
+pub const ORACLE_EMA_ALPHA_BPS: i128 = 2000;
+/// Healthy oracle sources an aggregated price needs
+pub const ORACLE_MIN_SOURCES: u32 = 1;
+/// Largest move of an aggregated price from the last good one, in bps
+pub const ORACLE_MAX_DEVIATION_BPS: i128 = 2000;
+/// Sources an asset may register; each is a cross-contract call per fetch
+pub const ORACLE_MAX_SOURCES_PER_ASSET: u32 = 10;
+/// Assets one batch price query may cover, bounding its instruction budget
+pub const ORACLE_MAX_BATCH_ASSETS: u32 = 20;
+/// Consecutive failed fetches that deactivate an oracle source
+pub const ORACLE_MAX_SOURCE_FAILURES: u32 = 3;
+/// Lifetime of an aggregated price in the cache
+pub const ORACLE_PRICE_CACHE_TTL_SECS: u64 = 30;
+/// Whether liquidations refuse prices clamped to their bounds
+pub const ORACLE_BLOCK_LIQUIDATION_ON_CLAMP: bool = true;
+/// How long prices locked by an oracle freeze remain usable
+pub const ORACLE_MAX_FREEZE_SECS: u64 = 60 * 60;
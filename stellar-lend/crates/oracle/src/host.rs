@@ -0,0 +1,97 @@
+//! What the oracle needs from the contract embedding it
+
+use soroban_sdk::{Address, Env, IntoVal, Symbol, TryFromVal, Val, Vec};
+use stellarlend_core::ProtocolError;
+
+/// Contract-side services the oracle calls into. Implemented once by the
+/// lending contract; every method is static so no host value is threaded
+/// through the oracle.
+pub trait OracleHost {
+    /// Fail with `Unauthorized` unless `caller` is the protocol admin
+    fn require_admin(env: &Env, caller: &Address) -> Result<(), ProtocolError>;
+
+    /// Current guardian, if one is set
+    fn guardian(env: &Env) -> Option<Address>;
+
+    /// Largest spread between sources a liquidation or borrow may run on,
+    /// in bps; 0 disables the check
+    fn max_price_spread_bps(env: &Env) -> i128;
+
+    /// Record an event in the contract's event analytics
+    fn track_event(
+        env: &Env,
+        event_type: Symbol,
+        topics: Vec<Symbol>,
+        user: Option<Address>,
+        asset: Option<Address>,
+        amount: i128,
+    );
+
+    /// Report a price cache hit, eviction or write
+    fn cache_updated(env: &Env, cache: Symbol, action: Symbol);
+
+    /// Emit the contract's `config_changed` event for a numeric parameter
+    fn record_change(
+        env: &Env,
+        scope: &str,
+        key: &str,
+        asset: Option<&Address>,
+        old: i128,
+        new: i128,
+    );
+
+    /// Emit the contract's `config_addr_changed` event for an address
+    /// parameter
+    fn record_address_change(
+        env: &Env,
+        scope: &str,
+        key: &str,
+        asset: Option<&Address>,
+        old: Option<Address>,
+        new: &Address,
+    );
+
+    /// Write a numeric parameter stored directly under `storage_key`,
+    /// reporting it when the value actually changes. Returns the previous
+    /// value (or `default` if unset).
+    fn set_param<K, V>(
+        env: &Env,
+        scope: &str,
+        key: &str,
+        storage_key: &K,
+        asset: Option<&Address>,
+        default: V,
+        new: V,
+    ) -> V
+    where
+        K: IntoVal<Env, Val>,
+        V: IntoVal<Env, Val> + TryFromVal<Env, Val> + Into<i128> + Copy,
+    {
+        let old: V = env.storage().instance().get(storage_key).unwrap_or(default);
+        env.storage().instance().set(storage_key, &new);
+        let (old_raw, new_raw): (i128, i128) = (old.into(), new.into());
+        if old_raw != new_raw {
+            Self::record_change(env, scope, key, asset, old_raw, new_raw);
+        }
+        old
+    }
+
+    /// Write an address parameter stored directly under `storage_key` and
+    /// report it. Returns the previous value, if any.
+    fn set_address_param<K>(
+        env: &Env,
+        scope: &str,
+        key: &str,
+        storage_key: &K,
+        asset: Option<&Address>,
+        new: &Address,
+    ) -> Option<Address>
+    where
+        K: IntoVal<Env, Val>,
+    {
+        let old: Option<Address> = env.storage().instance().get(storage_key);
+        env.storage().instance().set(storage_key, new);
+        Self::record_address_change(env, scope, key, asset, old.clone(), new);
+        old
+    }
+}
@@ -0,0 +1,2777 @@
+//! Price oracle for StellarLend
+//!
+//! Aggregates weighted external price sources per asset into one cached
+//! price, with TWAP, bounds, freezes, circuit breakers and emergency
+//! overrides. Everything the oracle needs from the lending contract (admin
+//! and guardian checks, event analytics, config-change reporting, the risk
+//! spread limit) comes through [`OracleHost`], which the contract implements;
+//! the contract exposes `Oracle<Host>` as its `oracle::Oracle`.
+
+#![no_std]
+#![allow(dead_code)]
+
+#[cfg(any(test, feature = "testutils"))]
+extern crate alloc;
+
+pub mod defaults;
+mod host;
+#[cfg(test)]
+mod test;
+
+pub use host::OracleHost;
+
+use core::marker::PhantomData;
+use soroban_sdk::{contracttype, vec, Address, Env, IntoVal, Map, Symbol, Vec};
+use stellarlend_core::ProtocolError;
+
+/// Fixed-point scale of source prices (1.0 USD = 1e8)
+pub const PRICE_SCALE: i128 = 100_000_000;
+
+/// Decimal places of `PRICE_SCALE`, to which every source price is normalized
+pub const PRICE_DECIMALS: u32 = 8;
+
+/// Most decimal places a source may report with
+pub const MAX_SOURCE_DECIMALS: u32 = 18;
+
+/// Aggregation modes understood by `aggregate_price`, indexed by mode value
+pub const SUPPORTED_MODES: [&str; 3] = ["median", "twap", "ema"];
+
+/// Per-asset mode value meaning "use the global mode"
+pub const USE_GLOBAL_MODE: i128 = -1;
+
+/// Most assets that may have oracle sources registered
+pub const MAX_ORACLE_ASSETS: u32 = 64;
+
+/// Upper bound on the configurable TWAP buffer length
+pub const MAX_TWAP_OBSERVATIONS: u32 = 128;
+
+/// Longest an emergency price override may stay in effect
+pub const MAX_EMERGENCY_PRICE_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct OracleSource {
+    pub addr: Address,
+    pub weight: i128, // relative weight (sum can be arbitrary)
+    pub last_heartbeat: u64,
+    /// Asset the source quotes prices in; `None` for USD
+    pub quote_asset: Option<Address>,
+}
+
+impl OracleSource {
+    pub fn new(addr: Address, weight: i128, last_heartbeat: u64) -> Self {
+        Self {
+            addr,
+            weight,
+            last_heartbeat,
+            quote_asset: None,
+        }
+    }
+
+    /// Quote prices in `quote` instead of USD
+    pub fn with_quote(mut self, quote: Address) -> Self {
+        self.quote_asset = Some(quote);
+        self
+    }
+}
+
+/// A price reported by one source
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PriceSample {
+    pub source: Address,
+    pub price: i128,
+}
+
+/// Aggregated price together with the samples that produced it
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PriceDetails {
+    pub price: i128,
+    pub samples: Vec<PriceSample>,
+    /// The aggregated price fell outside the asset's bounds and was clamped
+    pub clamped: bool,
+    /// The price is the one locked by an oracle freeze; `samples` is empty
+    pub frozen: bool,
+    /// The price is an emergency override; `samples` is empty
+    pub overridden: bool,
+}
+
+/// Price pinned by governance during an incident. Proposed by the guardian
+/// or the admin and only in effect once the other one confirms it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct EmergencyPrice {
+    pub price: i128,
+    /// Last timestamp the override applies at
+    pub expires_at: u64,
+    pub proposed_by: Address,
+    /// Second approver; `None` while the override awaits confirmation
+    pub confirmed_by: Option<Address>,
+}
+
+/// Aggregated price with how far its sources disagree
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct AggregatedPrice {
+    pub price: i128,
+    /// (max - min) * 10000 / median over the healthy samples; 0 with a
+    /// single source or a frozen price
+    pub spread_bps: i128,
+    /// Healthy sources the price was aggregated from
+    pub num_sources: u32,
+    /// The price is an emergency override rather than an aggregate
+    pub overridden: bool,
+    /// When the price was aggregated
+    pub timestamp: u64,
+}
+
+/// An active oracle freeze, for one asset or for every asset
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct OracleFreeze {
+    pub frozen_by: Address,
+    pub frozen_at: u64,
+}
+
+/// Hard floor and ceiling for an asset's price; (0, 0) disables the bounds
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PriceBounds {
+    pub min: i128,
+    pub max: i128,
+}
+
+/// Fetch health of one source; sources without an entry are healthy
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct SourceHealth {
+    /// Fetches that trapped since the last successful one
+    pub consecutive_failures: u32,
+    /// Skipped by aggregation until an admin reactivates it
+    pub deactivated: bool,
+}
+
+/// Configuration and liveness of one source, as reported by
+/// [`Oracle::get_source_status`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct SourceStatus {
+    pub addr: Address,
+    pub weight: i128,
+    /// Last heartbeat, or in push mode the last submission's timestamp
+    pub last_heartbeat: u64,
+    /// Older than the heartbeat TTL, so skipped by aggregation
+    pub is_stale: bool,
+    /// Normalized price the source reports; `None` unless submitted in push
+    /// mode or fetched by a dry run
+    pub last_price: Option<i128>,
+}
+
+/// Oracle settings that shape every aggregate
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct OracleConfig {
+    pub heartbeat_ttl: u64,
+    /// 0 = median, 1 = TWAP, 2 = EMA; assets may override it
+    pub mode: i128,
+    pub min_sources: u32,
+    /// Outlier rejection distance from the median, in bps
+    pub deviation_bps: i128,
+    /// Largest accepted move from the last good price, in bps
+    pub max_deviation_bps: i128,
+    /// Weight of each new price in the EMA, in bps
+    pub ema_alpha_bps: i128,
+}
+
+/// Where a price came from
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum PriceSource {
+    /// Aggregated from the asset's registered sources (or its freeze)
+    Primary,
+    /// Read from the asset's fallback oracle after aggregation failed
+    Fallback,
+}
+
+/// A price with its provenance
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PricePoint {
+    pub price: i128,
+    pub source: PriceSource,
+    pub timestamp: u64,
+}
+
+/// A price pushed by a source, with the time it was observed at
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PriceSubmission {
+    pub price: i128,
+    pub timestamp: u64,
+}
+
+/// An aggregated price recorded for TWAP
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PriceObservation {
+    pub timestamp: u64,
+    pub price: i128,
+}
+
+pub struct OracleStorage<H>(PhantomData<H>);
+
+impl<H: OracleHost> OracleStorage<H> {
+    fn sources_key(env: &Env) -> Symbol {
+        Symbol::new(env, "oracle_sources")
+    }
+    fn assets_key(env: &Env) -> Symbol {
+        Symbol::new(env, "oracle_assets")
+    }
+    fn heartbeat_ttl_key(env: &Env) -> Symbol {
+        Symbol::new(env, "oracle_heartbeat_ttl")
+    }
+    fn mode_key(env: &Env) -> Symbol {
+        Symbol::new(env, "oracle_mode")
+    }
+    fn oracle_admin_key(env: &Env) -> Symbol {
+        Symbol::new(env, "oracle_admin")
+    }
+    fn asset_mode_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "oracle_mode_asset"), asset.clone())
+    }
+    fn deviation_bps_key(env: &Env) -> Symbol {
+        Symbol::new(env, "oracle_deviation_bps")
+    }
+    fn trim_count_key(env: &Env) -> Symbol {
+        Symbol::new(env, "oracle_trim_count")
+    }
+    fn twap_window_key(env: &Env) -> Symbol {
+        Symbol::new(env, "oracle_twap_window")
+    }
+    fn twap_buffer_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "oracle_twap_buffer"), asset.clone())
+    }
+    fn twap_buffer_len_key(env: &Env) -> Symbol {
+        Symbol::new(env, "oracle_twap_buffer_len")
+    }
+    fn ema_alpha_key(env: &Env) -> Symbol {
+        Symbol::new(env, "oracle_ema_alpha")
+    }
+    fn ema_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "oracle_ema"), asset.clone())
+    }
+    fn source_health_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "oracle_source_health"), asset.clone())
+    }
+    fn max_source_failures_key(env: &Env) -> Symbol {
+        Symbol::new(env, "oracle_max_src_failures")
+    }
+    fn last_spread_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "oracle_last_spread"), asset.clone())
+    }
+    fn last_good_price_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "oracle_last_good"), asset.clone())
+    }
+    fn last_good_at_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "oracle_last_good_at"), asset.clone())
+    }
+    fn fallback_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "oracle_fallback"), asset.clone())
+    }
+    fn push_mode_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "oracle_push_mode"), asset.clone())
+    }
+    fn submission_key(env: &Env, asset: &Address, source: &Address) -> (Symbol, Address, Address) {
+        (
+            Symbol::new(env, "oracle_submission"),
+            asset.clone(),
+            source.clone(),
+        )
+    }
+    fn source_decimals_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "oracle_source_decimals"), asset.clone())
+    }
+    fn min_sources_key(env: &Env) -> Symbol {
+        Symbol::new(env, "oracle_min_sources")
+    }
+    fn max_sources_key(env: &Env) -> Symbol {
+        Symbol::new(env, "oracle_max_sources")
+    }
+    fn max_change_bps_key(env: &Env) -> Symbol {
+        Symbol::new(env, "oracle_max_change_bps")
+    }
+    fn price_cache_key(env: &Env) -> Symbol {
+        Symbol::new(env, "oracle_price_cache")
+    }
+    fn price_cache_ttl_key(env: &Env) -> Symbol {
+        Symbol::new(env, "oracle_price_cache_ttl")
+    }
+    fn freeze_key(env: &Env, asset: &Option<Address>) -> (Symbol, Option<Address>) {
+        (Symbol::new(env, "oracle_freeze"), asset.clone())
+    }
+    fn max_freeze_key(env: &Env) -> Symbol {
+        Symbol::new(env, "oracle_max_freeze")
+    }
+    fn price_bounds_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "oracle_price_bounds"), asset.clone())
+    }
+    fn clamp_blocks_liq_key(env: &Env) -> Symbol {
+        Symbol::new(env, "oracle_clamp_blocks_liq")
+    }
+
+    pub fn get_sources(env: &Env, asset: &Address) -> Vec<OracleSource> {
+        let key = (Self::sources_key(env), asset.clone());
+        env.storage()
+            .instance()
+            .get(&key)
+            .unwrap_or_else(|| Vec::new(env))
+    }
+
+    /// Assets with at least one registered source, in registration order
+    pub fn get_assets(env: &Env) -> Vec<Address> {
+        env.storage()
+            .instance()
+            .get(&Self::assets_key(env))
+            .unwrap_or_else(|| Vec::new(env))
+    }
+    fn put_assets(env: &Env, assets: &Vec<Address>) {
+        env.storage().instance().set(&Self::assets_key(env), assets);
+    }
+
+    pub fn put_sources(env: &Env, asset: &Address, sources: &Vec<OracleSource>) {
+        let key = (Self::sources_key(env), asset.clone());
+        env.storage().instance().set(&key, sources);
+    }
+
+    /// Address allowed to manage feeds alongside the protocol admin
+    pub fn get_oracle_admin(env: &Env) -> Option<Address> {
+        env.storage().instance().get(&Self::oracle_admin_key(env))
+    }
+
+    pub fn get_heartbeat_ttl(env: &Env) -> u64 {
+        env.storage()
+            .instance()
+            .get(&Self::heartbeat_ttl_key(env))
+            .unwrap_or(defaults::ORACLE_HEARTBEAT_TTL_SECS)
+    }
+
+    pub fn set_heartbeat_ttl(env: &Env, caller: &Address, ttl: u64) -> Result<(), ProtocolError> {
+        Oracle::<H>::require_oracle_admin(env, caller)?;
+        H::set_param(
+            env,
+            "oracle",
+            "heartbeat_ttl",
+            &Self::heartbeat_ttl_key(env),
+            None,
+            defaults::ORACLE_HEARTBEAT_TTL_SECS,
+            ttl,
+        );
+        Ok(())
+    }
+
+    pub fn set_mode(env: &Env, caller: &Address, mode: i128) -> Result<(), ProtocolError> {
+        Oracle::<H>::require_oracle_admin(env, caller)?;
+        H::set_param(
+            env,
+            "oracle",
+            "mode",
+            &Self::mode_key(env),
+            None,
+            defaults::ORACLE_MODE,
+            mode,
+        );
+        Ok(())
+    }
+    pub fn get_mode(env: &Env) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::mode_key(env))
+            .unwrap_or(defaults::ORACLE_MODE)
+    } // 0=median,1=twap
+
+    /// Mode override for `asset`; -1 (the default) defers to the global mode
+    pub fn get_asset_mode(env: &Env, asset: &Address) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::asset_mode_key(env, asset))
+            .unwrap_or(USE_GLOBAL_MODE)
+    }
+    pub fn set_asset_mode(env: &Env, asset: &Address, mode: i128) {
+        H::set_param(
+            env,
+            "oracle",
+            "asset_mode",
+            &Self::asset_mode_key(env, asset),
+            Some(asset),
+            USE_GLOBAL_MODE,
+            mode,
+        );
+    }
+
+    /// Maximum deviation from the median allowed before outlier rejection, in bps (1/10000)
+    pub fn get_deviation_bps(env: &Env) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::deviation_bps_key(env))
+            .unwrap_or(defaults::ORACLE_DEVIATION_BPS)
+    }
+    pub fn set_deviation_bps(env: &Env, bps: i128) {
+        H::set_param(
+            env,
+            "oracle",
+            "deviation_bps",
+            &Self::deviation_bps_key(env),
+            None,
+            defaults::ORACLE_DEVIATION_BPS,
+            bps,
+        );
+    }
+
+    /// Number of highest/lowest samples to trim before aggregation when using median mode
+    pub fn get_trim_count(env: &Env) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::trim_count_key(env))
+            .unwrap_or(defaults::ORACLE_TRIM_COUNT)
+    }
+    pub fn set_trim_count(env: &Env, count: i128) {
+        H::set_param(
+            env,
+            "oracle",
+            "trim_count",
+            &Self::trim_count_key(env),
+            None,
+            defaults::ORACLE_TRIM_COUNT,
+            count,
+        );
+    }
+
+    /// Window averaged over by mode 1, in seconds
+    pub fn get_twap_window(env: &Env) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::twap_window_key(env))
+            .unwrap_or(defaults::ORACLE_TWAP_WINDOW)
+    }
+    pub fn set_twap_window(env: &Env, window: i128) {
+        H::set_param(
+            env,
+            "oracle",
+            "twap_window",
+            &Self::twap_window_key(env),
+            None,
+            defaults::ORACLE_TWAP_WINDOW,
+            window,
+        );
+    }
+
+    /// Oracle queried when aggregation yields no price for `asset`
+    pub fn get_fallback(env: &Env, asset: &Address) -> Option<Address> {
+        env.storage()
+            .instance()
+            .get(&Self::fallback_key(env, asset))
+    }
+
+    /// Whether `asset` aggregates the prices its sources submit instead of
+    /// calling them
+    pub fn get_push_mode(env: &Env, asset: &Address) -> bool {
+        env.storage()
+            .instance()
+            .get(&Self::push_mode_key(env, asset))
+            .unwrap_or(false)
+    }
+    pub fn set_push_mode(env: &Env, asset: &Address, enabled: bool) {
+        let key = Self::push_mode_key(env, asset);
+        let old = Self::get_push_mode(env, asset);
+        if enabled {
+            env.storage().instance().set(&key, &true);
+        } else {
+            env.storage().instance().remove(&key);
+        }
+        if old != enabled {
+            H::record_change(
+                env,
+                "oracle",
+                "push_mode",
+                Some(asset),
+                old as i128,
+                enabled as i128,
+            );
+        }
+    }
+
+    /// Latest price `source` submitted for `asset`
+    pub fn get_submission(env: &Env, asset: &Address, source: &Address) -> Option<PriceSubmission> {
+        env.storage()
+            .instance()
+            .get(&Self::submission_key(env, asset, source))
+    }
+    fn put_submission(
+        env: &Env,
+        asset: &Address,
+        source: &Address,
+        submission: Option<&PriceSubmission>,
+    ) {
+        let key = Self::submission_key(env, asset, source);
+        match submission {
+            Some(s) => env.storage().instance().set(&key, s),
+            None => env.storage().instance().remove(&key),
+        }
+    }
+
+    /// Decimal places of `asset`'s sources that do not report at
+    /// [`PRICE_DECIMALS`]; sources without an entry report at it
+    pub fn get_source_decimals(env: &Env, asset: &Address) -> Map<Address, u32> {
+        env.storage()
+            .instance()
+            .get(&Self::source_decimals_key(env, asset))
+            .unwrap_or_else(|| Map::new(env))
+    }
+    fn put_source_decimals(env: &Env, asset: &Address, decimals: &Map<Address, u32>) {
+        let key = Self::source_decimals_key(env, asset);
+        if decimals.is_empty() {
+            env.storage().instance().remove(&key);
+        } else {
+            env.storage().instance().set(&key, decimals);
+        }
+    }
+
+    /// Health of `asset`'s sources that have failed since their last success
+    pub fn get_source_health(env: &Env, asset: &Address) -> Map<Address, SourceHealth> {
+        env.storage()
+            .instance()
+            .get(&Self::source_health_key(env, asset))
+            .unwrap_or_else(|| Map::new(env))
+    }
+    fn put_source_health(env: &Env, asset: &Address, health: &Map<Address, SourceHealth>) {
+        let key = Self::source_health_key(env, asset);
+        if health.is_empty() {
+            env.storage().instance().remove(&key);
+        } else {
+            env.storage().instance().set(&key, health);
+        }
+    }
+
+    /// Healthy sources an aggregate needs
+    pub fn get_min_sources(env: &Env) -> u32 {
+        env.storage()
+            .instance()
+            .get(&Self::min_sources_key(env))
+            .unwrap_or(defaults::ORACLE_MIN_SOURCES)
+    }
+    pub fn set_min_sources(env: &Env, count: u32) {
+        H::set_param(
+            env,
+            "oracle",
+            "min_sources",
+            &Self::min_sources_key(env),
+            None,
+            defaults::ORACLE_MIN_SOURCES,
+            count,
+        );
+    }
+
+    /// Sources one asset may register
+    pub fn get_max_sources_per_asset(env: &Env) -> u32 {
+        env.storage()
+            .instance()
+            .get(&Self::max_sources_key(env))
+            .unwrap_or(defaults::ORACLE_MAX_SOURCES_PER_ASSET)
+    }
+    pub fn set_max_sources_per_asset(env: &Env, count: u32) {
+        H::set_param(
+            env,
+            "oracle",
+            "max_sources_per_asset",
+            &Self::max_sources_key(env),
+            None,
+            defaults::ORACLE_MAX_SOURCES_PER_ASSET,
+            count,
+        );
+    }
+
+    /// Last aggregate that passed the deviation check, the baseline for the next
+    pub fn get_last_good_price(env: &Env, asset: &Address) -> Option<i128> {
+        env.storage()
+            .instance()
+            .get(&Self::last_good_price_key(env, asset))
+    }
+    /// Spread in bps and source count of the cached price of `asset`
+    pub fn get_last_spread(env: &Env, asset: &Address) -> (i128, u32) {
+        env.storage()
+            .instance()
+            .get(&Self::last_spread_key(env, asset))
+            .unwrap_or((0, 0))
+    }
+    fn put_last_spread(env: &Env, asset: &Address, spread_bps: i128, num_sources: u32) {
+        env.storage().instance().set(
+            &Self::last_spread_key(env, asset),
+            &(spread_bps, num_sources),
+        );
+    }
+
+    /// [`OracleStorage::get_last_good_price`] with when it was aggregated;
+    /// `None` for prices recorded before timestamps were kept
+    pub fn get_last_good_price_at(env: &Env, asset: &Address) -> Option<(i128, u64)> {
+        let price = Self::get_last_good_price(env, asset)?;
+        let at: u64 = env
+            .storage()
+            .instance()
+            .get(&Self::last_good_at_key(env, asset))?;
+        Some((price, at))
+    }
+
+    fn put_last_good_price(env: &Env, asset: &Address, price: i128) {
+        env.storage()
+            .instance()
+            .set(&Self::last_good_price_key(env, asset), &price);
+        env.storage().instance().set(
+            &Self::last_good_at_key(env, asset),
+            &env.ledger().timestamp(),
+        );
+    }
+
+    /// Largest move from the last good aggregate accepted, in bps
+    pub fn get_max_deviation_bps(env: &Env) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::max_change_bps_key(env))
+            .unwrap_or(defaults::ORACLE_MAX_DEVIATION_BPS)
+    }
+    pub fn set_max_deviation_bps(env: &Env, bps: i128) {
+        H::set_param(
+            env,
+            "oracle",
+            "max_deviation_bps",
+            &Self::max_change_bps_key(env),
+            None,
+            defaults::ORACLE_MAX_DEVIATION_BPS,
+            bps,
+        );
+    }
+
+    /// Consecutive failed fetches after which a source is deactivated
+    pub fn get_max_source_failures(env: &Env) -> u32 {
+        env.storage()
+            .instance()
+            .get(&Self::max_source_failures_key(env))
+            .unwrap_or(defaults::ORACLE_MAX_SOURCE_FAILURES)
+    }
+    pub fn set_max_source_failures(env: &Env, failures: u32) {
+        H::set_param(
+            env,
+            "oracle",
+            "max_source_failures",
+            &Self::max_source_failures_key(env),
+            None,
+            defaults::ORACLE_MAX_SOURCE_FAILURES,
+            failures,
+        );
+    }
+
+    /// Recorded observations for `asset`, oldest first
+    pub fn get_twap_buffer(env: &Env, asset: &Address) -> Vec<PriceObservation> {
+        env.storage()
+            .instance()
+            .get(&Self::twap_buffer_key(env, asset))
+            .unwrap_or_else(|| Vec::new(env))
+    }
+    fn put_twap_buffer(env: &Env, asset: &Address, buffer: &Vec<PriceObservation>) {
+        env.storage()
+            .instance()
+            .set(&Self::twap_buffer_key(env, asset), buffer);
+    }
+
+    /// Observations kept per asset
+    pub fn get_twap_buffer_len(env: &Env) -> u32 {
+        env.storage()
+            .instance()
+            .get(&Self::twap_buffer_len_key(env))
+            .unwrap_or(defaults::ORACLE_TWAP_BUFFER_LEN)
+    }
+    pub fn set_twap_buffer_len(env: &Env, len: u32) {
+        H::set_param(
+            env,
+            "oracle",
+            "twap_buffer_len",
+            &Self::twap_buffer_len_key(env),
+            None,
+            defaults::ORACLE_TWAP_BUFFER_LEN,
+            len,
+        );
+    }
+
+    pub fn get_ema_alpha_bps(env: &Env) -> i128 {
+        env.storage()
+            .instance()
+            .get(&Self::ema_alpha_key(env))
+            .unwrap_or(defaults::ORACLE_EMA_ALPHA_BPS)
+    }
+    pub fn set_ema_alpha_bps(env: &Env, alpha_bps: i128) {
+        H::set_param(
+            env,
+            "oracle",
+            "ema_alpha_bps",
+            &Self::ema_alpha_key(env),
+            None,
+            defaults::ORACLE_EMA_ALPHA_BPS,
+            alpha_bps,
+        );
+    }
+
+    /// Exponential moving average of `asset`'s aggregates under mode 2
+    pub fn get_ema(env: &Env, asset: &Address) -> Option<i128> {
+        env.storage().instance().get(&Self::ema_key(env, asset))
+    }
+    fn put_ema(env: &Env, asset: &Address, ema: i128) {
+        env.storage()
+            .instance()
+            .set(&Self::ema_key(env, asset), &ema);
+    }
+
+    // Aggregated price cache helpers
+    pub fn get_price_cache(env: &Env) -> soroban_sdk::Map<Address, (i128, u64)> {
+        env.storage()
+            .instance()
+            .get(&Self::price_cache_key(env))
+            .unwrap_or_else(|| soroban_sdk::Map::new(env))
+    }
+    pub fn put_price_cache(env: &Env, map: &soroban_sdk::Map<Address, (i128, u64)>) {
+        env.storage()
+            .instance()
+            .set(&Self::price_cache_key(env), map);
+    }
+    pub fn get_price_cache_ttl(env: &Env) -> u64 {
+        env.storage()
+            .instance()
+            .get(&Self::price_cache_ttl_key(env))
+            .unwrap_or(defaults::ORACLE_PRICE_CACHE_TTL_SECS)
+    }
+    pub fn set_price_cache_ttl(env: &Env, ttl: u64) {
+        H::set_param(
+            env,
+            "oracle",
+            "price_cache_ttl",
+            &Self::price_cache_ttl_key(env),
+            None,
+            defaults::ORACLE_PRICE_CACHE_TTL_SECS,
+            ttl,
+        );
+    }
+
+    fn reading_bounds_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "oracle_reading_bounds"), asset.clone())
+    }
+    fn rejected_readings_key(env: &Env, asset: &Address) -> (Symbol, Address) {
+        (Symbol::new(env, "oracle_rejected_readings"), asset.clone())
+    }
+
+    /// Range a single source reading of `asset` must fall in to be
+    /// aggregated; (0, i128::MAX) when unset
+    pub fn get_reading_bounds(env: &Env, asset: &Address) -> PriceBounds {
+        env.storage()
+            .instance()
+            .get(&Self::reading_bounds_key(env, asset))
+            .unwrap_or(PriceBounds {
+                min: 0,
+                max: i128::MAX,
+            })
+    }
+    fn put_reading_bounds(env: &Env, asset: &Address, bounds: &PriceBounds) {
+        let key = Self::reading_bounds_key(env, asset);
+        if bounds.min == 0 && bounds.max == i128::MAX {
+            env.storage().instance().remove(&key);
+        } else {
+            env.storage().instance().set(&key, bounds);
+        }
+    }
+
+    /// Source readings of `asset` dropped for falling outside its bounds
+    pub fn get_rejected_readings(env: &Env, asset: &Address) -> u64 {
+        env.storage()
+            .instance()
+            .get(&Self::rejected_readings_key(env, asset))
+            .unwrap_or(0)
+    }
+    fn inc_rejected_readings(env: &Env, asset: &Address) {
+        let count = Self::get_rejected_readings(env, asset) + 1;
+        env.storage()
+            .instance()
+            .set(&Self::rejected_readings_key(env, asset), &count);
+    }
+
+    fn emergency_key(env: &Env, asset: &Address, pending: bool) -> (Symbol, Address) {
+        let name = if pending {
+            "oracle_emergency_pending"
+        } else {
+            "oracle_emergency_price"
+        };
+        (Symbol::new(env, name), asset.clone())
+    }
+
+    /// Emergency override of `asset`; the awaiting proposal for `pending`
+    pub fn get_emergency_price(
+        env: &Env,
+        asset: &Address,
+        pending: bool,
+    ) -> Option<EmergencyPrice> {
+        env.storage()
+            .instance()
+            .get(&Self::emergency_key(env, asset, pending))
+    }
+
+    fn put_emergency_price(
+        env: &Env,
+        asset: &Address,
+        pending: bool,
+        value: Option<&EmergencyPrice>,
+    ) {
+        let key = Self::emergency_key(env, asset, pending);
+        match value {
+            Some(value) => env.storage().instance().set(&key, value),
+            None => env.storage().instance().remove(&key),
+        }
+    }
+
+    fn route_key(env: &Env, base: &Address, quote: &Address) -> (Symbol, Address, Address) {
+        (
+            Symbol::new(env, "oracle_route"),
+            base.clone(),
+            quote.clone(),
+        )
+    }
+
+    /// Intermediate asset a `base`/`quote` price is derived through
+    pub fn get_route(env: &Env, base: &Address, quote: &Address) -> Option<Address> {
+        env.storage()
+            .instance()
+            .get(&Self::route_key(env, base, quote))
+    }
+
+    /// Asset the sources of `asset` quote in; `None` for USD or no sources
+    pub fn get_quote_asset(env: &Env, asset: &Address) -> Option<Address> {
+        Self::get_sources(env, asset)
+            .first()
+            .and_then(|s| s.quote_asset)
+    }
+
+    pub fn get_price_bounds(env: &Env, asset: &Address) -> Option<PriceBounds> {
+        env.storage()
+            .instance()
+            .get(&Self::price_bounds_key(env, asset))
+    }
+
+    pub fn put_price_bounds(env: &Env, asset: &Address, bounds: &PriceBounds) {
+        let key = Self::price_bounds_key(env, asset);
+        if bounds.min == 0 && bounds.max == 0 {
+            env.storage().instance().remove(&key);
+        } else {
+            env.storage().instance().set(&key, bounds);
+        }
+    }
+
+    /// Freeze covering exactly `asset`, or every asset for `None`
+    pub fn get_freeze_entry(env: &Env, asset: &Option<Address>) -> Option<OracleFreeze> {
+        env.storage().instance().get(&Self::freeze_key(env, asset))
+    }
+
+    pub fn put_freeze_entry(env: &Env, asset: &Option<Address>, freeze: Option<&OracleFreeze>) {
+        let key = Self::freeze_key(env, asset);
+        match freeze {
+            Some(freeze) => env.storage().instance().set(&key, freeze),
+            None => env.storage().instance().remove(&key),
+        }
+    }
+
+    /// Freeze in effect for `asset`: the earlier of its own and the global one
+    pub fn get_freeze(env: &Env, asset: &Address) -> Option<OracleFreeze> {
+        let own = Self::get_freeze_entry(env, &Some(asset.clone()));
+        let all = Self::get_freeze_entry(env, &None);
+        match (own, all) {
+            (Some(a), Some(b)) => Some(if a.frozen_at <= b.frozen_at { a } else { b }),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// How long frozen prices stay usable before pricing fails closed
+    pub fn get_max_freeze_secs(env: &Env) -> u64 {
+        env.storage()
+            .instance()
+            .get(&Self::max_freeze_key(env))
+            .unwrap_or(defaults::ORACLE_MAX_FREEZE_SECS)
+    }
+
+    pub fn set_max_freeze_secs(env: &Env, secs: u64) {
+        H::set_param(
+            env,
+            "oracle",
+            "max_freeze_secs",
+            &Self::max_freeze_key(env),
+            None,
+            defaults::ORACLE_MAX_FREEZE_SECS,
+            secs,
+        );
+    }
+
+    /// Whether liquidations refuse to run on a clamped collateral price (default on)
+    pub fn get_block_liquidation_on_clamp(env: &Env) -> bool {
+        env.storage()
+            .instance()
+            .get(&Self::clamp_blocks_liq_key(env))
+            .unwrap_or(defaults::ORACLE_BLOCK_LIQUIDATION_ON_CLAMP)
+    }
+
+    pub fn set_block_liquidation_on_clamp(env: &Env, enabled: bool) {
+        H::set_param(
+            env,
+            "oracle",
+            "block_liquidation_on_clamp",
+            &Self::clamp_blocks_liq_key(env),
+            None,
+            defaults::ORACLE_BLOCK_LIQUIDATION_ON_CLAMP,
+            enabled,
+        );
+    }
+
+    /// One key of every shape the oracle stores under, labelled with its
+    /// constructor, for the contract's storage key collision audit
+    #[cfg(any(test, feature = "testutils"))]
+    pub fn storage_keys(
+        env: &Env,
+        asset: &Address,
+        other: &Address,
+    ) -> alloc::vec::Vec<(&'static str, soroban_sdk::Val)> {
+        alloc::vec![
+            (
+                "oracle::sources_key",
+                (Self::sources_key(env), asset.clone()).into_val(env)
+            ),
+            ("oracle::assets_key", Self::assets_key(env).into_val(env)),
+            (
+                "oracle::heartbeat_ttl_key",
+                Self::heartbeat_ttl_key(env).into_val(env)
+            ),
+            ("oracle::mode_key", Self::mode_key(env).into_val(env)),
+            (
+                "oracle::oracle_admin_key",
+                Self::oracle_admin_key(env).into_val(env)
+            ),
+            (
+                "oracle::asset_mode_key",
+                Self::asset_mode_key(env, asset).into_val(env)
+            ),
+            (
+                "oracle::deviation_bps_key",
+                Self::deviation_bps_key(env).into_val(env)
+            ),
+            (
+                "oracle::trim_count_key",
+                Self::trim_count_key(env).into_val(env)
+            ),
+            (
+                "oracle::twap_window_key",
+                Self::twap_window_key(env).into_val(env)
+            ),
+            (
+                "oracle::twap_buffer_key",
+                Self::twap_buffer_key(env, asset).into_val(env)
+            ),
+            (
+                "oracle::twap_buffer_len_key",
+                Self::twap_buffer_len_key(env).into_val(env)
+            ),
+            (
+                "oracle::ema_alpha_key",
+                Self::ema_alpha_key(env).into_val(env)
+            ),
+            ("oracle::ema_key", Self::ema_key(env, asset).into_val(env)),
+            (
+                "oracle::source_health_key",
+                Self::source_health_key(env, asset).into_val(env)
+            ),
+            (
+                "oracle::max_source_failures_key",
+                Self::max_source_failures_key(env).into_val(env),
+            ),
+            (
+                "oracle::last_spread_key",
+                Self::last_spread_key(env, asset).into_val(env)
+            ),
+            (
+                "oracle::last_good_price_key",
+                Self::last_good_price_key(env, asset).into_val(env)
+            ),
+            (
+                "oracle::last_good_at_key",
+                Self::last_good_at_key(env, asset).into_val(env)
+            ),
+            (
+                "oracle::fallback_key",
+                Self::fallback_key(env, asset).into_val(env)
+            ),
+            (
+                "oracle::push_mode_key",
+                Self::push_mode_key(env, asset).into_val(env)
+            ),
+            (
+                "oracle::submission_key",
+                Self::submission_key(env, asset, other).into_val(env)
+            ),
+            (
+                "oracle::source_decimals_key",
+                Self::source_decimals_key(env, asset).into_val(env)
+            ),
+            (
+                "oracle::min_sources_key",
+                Self::min_sources_key(env).into_val(env)
+            ),
+            (
+                "oracle::max_sources_key",
+                Self::max_sources_key(env).into_val(env)
+            ),
+            (
+                "oracle::max_change_bps_key",
+                Self::max_change_bps_key(env).into_val(env)
+            ),
+            (
+                "oracle::price_cache_key",
+                Self::price_cache_key(env).into_val(env)
+            ),
+            (
+                "oracle::price_cache_ttl_key",
+                Self::price_cache_ttl_key(env).into_val(env)
+            ),
+            (
+                "oracle::freeze_key",
+                Self::freeze_key(env, &Some(asset.clone())).into_val(env)
+            ),
+            (
+                "oracle::freeze_key(all)",
+                Self::freeze_key(env, &None).into_val(env)
+            ),
+            (
+                "oracle::max_freeze_key",
+                Self::max_freeze_key(env).into_val(env)
+            ),
+            (
+                "oracle::price_bounds_key",
+                Self::price_bounds_key(env, asset).into_val(env)
+            ),
+            (
+                "oracle::clamp_blocks_liq_key",
+                Self::clamp_blocks_liq_key(env).into_val(env)
+            ),
+            (
+                "oracle::reading_bounds_key",
+                Self::reading_bounds_key(env, asset).into_val(env)
+            ),
+            (
+                "oracle::rejected_readings_key",
+                Self::rejected_readings_key(env, asset).into_val(env),
+            ),
+            (
+                "oracle::emergency_key(pending)",
+                Self::emergency_key(env, asset, true).into_val(env)
+            ),
+            (
+                "oracle::emergency_key",
+                Self::emergency_key(env, asset, false).into_val(env)
+            ),
+            (
+                "oracle::route_key",
+                Self::route_key(env, asset, other).into_val(env)
+            ),
+        ]
+    }
+}
+
+pub struct Oracle<H>(PhantomData<H>);
+
+impl<H: OracleHost> Oracle<H> {
+    /// Accept the oracle admin or the protocol admin
+    pub fn require_oracle_admin(env: &Env, caller: &Address) -> Result<(), ProtocolError> {
+        if OracleStorage::<H>::get_oracle_admin(env).as_ref() == Some(caller) {
+            return Ok(());
+        }
+        H::require_admin(env, caller)
+    }
+
+    /// Appoint the oracle admin, or revoke it with `None` (protocol admin only)
+    pub fn set_oracle_admin(
+        env: &Env,
+        caller: &Address,
+        new_admin: Option<Address>,
+    ) -> Result<(), ProtocolError> {
+        H::require_admin(env, caller)?;
+        let key = OracleStorage::<H>::oracle_admin_key(env);
+        let old: Option<Address> = env.storage().instance().get(&key);
+        match &new_admin {
+            Some(addr) => env.storage().instance().set(&key, addr),
+            None => {
+                if old.is_none() {
+                    return Err(ProtocolError::NotFound);
+                }
+                env.storage().instance().remove(&key);
+            }
+        }
+        let event_type = Symbol::new(env, "oracle_admin_changed");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        H::track_event(
+            env,
+            event_type.clone(),
+            topics,
+            Some(caller.clone()),
+            None,
+            0,
+        );
+        env.events().publish(
+            (event_type, caller.clone()),
+            (
+                Symbol::new(env, "old"),
+                old,
+                Symbol::new(env, "new"),
+                new_admin,
+            ),
+        );
+        Ok(())
+    }
+
+    /// Register or update an oracle source for an asset
+    pub fn set_source(
+        env: &Env,
+        caller: &Address,
+        asset: &Address,
+        source: OracleSource,
+    ) -> Result<(), ProtocolError> {
+        Self::require_oracle_admin(env, caller)?;
+        let list = OracleStorage::<H>::get_sources(env, asset);
+        // Samples are averaged, so every source of an asset quotes alike
+        if list
+            .iter()
+            .any(|s| s.addr != source.addr && s.quote_asset != source.quote_asset)
+        {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        // Replace if exists
+        let mut replaced = false;
+        let mut out: Vec<OracleSource> = Vec::new(env);
+        for s in list.iter() {
+            if s.addr == source.addr {
+                out.push_back(source.clone());
+                replaced = true;
+            } else {
+                out.push_back(s);
+            }
+        }
+        if !replaced {
+            if list.len() >= OracleStorage::<H>::get_max_sources_per_asset(env) {
+                return Err(ProtocolError::TooManySources);
+            }
+            out.push_back(source);
+        }
+        let mut assets = OracleStorage::<H>::get_assets(env);
+        if !assets.contains(asset) {
+            if assets.len() >= MAX_ORACLE_ASSETS {
+                return Err(ProtocolError::StorageLimitExceeded);
+            }
+            assets.push_back(asset.clone());
+            OracleStorage::<H>::put_assets(env, &assets);
+        }
+        OracleStorage::<H>::put_sources(env, asset, &out);
+        Ok(())
+    }
+
+    /// Set hard price bounds for an asset (admin only); (0, 0) disables them
+    pub fn set_price_bounds(
+        env: &Env,
+        caller: &Address,
+        asset: &Address,
+        min: i128,
+        max: i128,
+    ) -> Result<(), ProtocolError> {
+        H::require_admin(env, caller)?;
+        let disabled = min == 0 && max == 0;
+        if !disabled && (min <= 0 || max < min) {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        let old = OracleStorage::<H>::get_price_bounds(env, asset)
+            .unwrap_or(PriceBounds { min: 0, max: 0 });
+        OracleStorage::<H>::put_price_bounds(env, asset, &PriceBounds { min, max });
+        if old.min != min {
+            H::record_change(env, "oracle", "price_min", Some(asset), old.min, min);
+        }
+        if old.max != max {
+            H::record_change(env, "oracle", "price_max", Some(asset), old.max, max);
+        }
+        Ok(())
+    }
+
+    /// Set the range single source readings of `asset` must fall in (admin
+    /// only). Unlike the clamp bounds, readings outside it are dropped before
+    /// aggregation. `min` must be positive and below `max`; (0, i128::MAX)
+    /// removes the bounds.
+    pub fn set_reading_bounds(
+        env: &Env,
+        caller: &Address,
+        asset: &Address,
+        min: i128,
+        max: i128,
+    ) -> Result<(), ProtocolError> {
+        H::require_admin(env, caller)?;
+        let reset = min == 0 && max == i128::MAX;
+        if !reset && (min <= 0 || max <= min) {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        let old = OracleStorage::<H>::get_reading_bounds(env, asset);
+        OracleStorage::<H>::put_reading_bounds(env, asset, &PriceBounds { min, max });
+        if old.min != min {
+            H::record_change(env, "oracle", "reading_min", Some(asset), old.min, min);
+        }
+        if old.max != max {
+            H::record_change(env, "oracle", "reading_max", Some(asset), old.max, max);
+        }
+        Ok(())
+    }
+
+    /// Bounds single source readings of `asset` are checked against, in
+    /// [`PRICE_DECIMALS`]; (0, i128::MAX) when unset
+    pub fn get_reading_bounds(env: &Env, asset: &Address) -> PriceBounds {
+        OracleStorage::<H>::get_reading_bounds(env, asset)
+    }
+
+    /// Derive `base`/`quote` prices through `via` (admin only), composing
+    /// the `base`/`via` and `via`/`quote` aggregates; `None` removes the route
+    pub fn set_price_route(
+        env: &Env,
+        caller: &Address,
+        base: &Address,
+        quote: &Address,
+        via: Option<Address>,
+    ) -> Result<(), ProtocolError> {
+        H::require_admin(env, caller)?;
+        if base == quote {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        let key = OracleStorage::<H>::route_key(env, base, quote);
+        match via {
+            Some(via) => {
+                if via == *base || via == *quote {
+                    return Err(ProtocolError::InvalidParameters);
+                }
+                H::set_address_param(env, "oracle", "price_route", &key, Some(base), &via);
+            }
+            None => {
+                if !env.storage().instance().has(&key) {
+                    return Err(ProtocolError::NotFound);
+                }
+                env.storage().instance().remove(&key);
+                let event_type = Symbol::new(env, "oracle_route_removed");
+                let mut topics = Vec::new(env);
+                topics.push_back(event_type.clone());
+                H::track_event(env, event_type.clone(), topics, None, Some(base.clone()), 0);
+                env.events()
+                    .publish((event_type, base.clone()), quote.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Price of one unit of `base` in `quote`, in [`PRICE_DECIMALS`]
+    ///
+    /// Uses `base`'s own aggregate when its sources quote in `quote` (or the
+    /// inverse of `quote`'s), otherwise composes two aggregates through the
+    /// configured route, e.g. TOKEN/XLM * XLM/USDC. `None` when no route
+    /// applies, a leg has no price, or the arithmetic would divide by zero
+    /// or overflow. Never writes storage.
+    pub fn derive_price(env: &Env, base: &Address, quote: &Address) -> Option<i128> {
+        if base == quote {
+            return Some(PRICE_SCALE);
+        }
+        if let Some(price) = Self::quote_leg(env, base, quote) {
+            return Some(price);
+        }
+        let via = OracleStorage::<H>::get_route(env, base, quote)?;
+        let first = Self::quote_leg(env, base, &via)?;
+        let second = Self::quote_leg(env, &via, quote)?;
+        first.checked_mul(second)?.checked_div(PRICE_SCALE)
+    }
+
+    /// Price of `from` in `to` from a single aggregate, inverting `to`'s
+    /// price when it is the one quoted in `from`
+    fn quote_leg(env: &Env, from: &Address, to: &Address) -> Option<i128> {
+        if OracleStorage::<H>::get_quote_asset(env, from).as_ref() == Some(to) {
+            return Self::peek_price(env, from).map(|(price, _)| price);
+        }
+        if OracleStorage::<H>::get_quote_asset(env, to).as_ref() == Some(from) {
+            let (price, _) = Self::peek_price(env, to)?;
+            return PRICE_SCALE.checked_mul(PRICE_SCALE)?.checked_div(price);
+        }
+        None
+    }
+
+    /// Count and report a source reading dropped for falling outside bounds
+    fn reject_reading(
+        env: &Env,
+        asset: &Address,
+        source: &Address,
+        price: i128,
+        bounds: &PriceBounds,
+    ) {
+        OracleStorage::<H>::inc_rejected_readings(env, asset);
+        let event_type = Symbol::new(env, "oracle_reading_rejected");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        H::track_event(
+            env,
+            event_type.clone(),
+            topics,
+            None,
+            Some(asset.clone()),
+            price,
+        );
+        env.events().publish(
+            (event_type, asset.clone()),
+            (source.clone(), price, bounds.min, bounds.max),
+        );
+    }
+
+    /// Clamp `raw` into the asset's bounds, emitting `price_clamped` when it moves
+    pub fn clamp_to_bounds(env: &Env, asset: &Address, raw: i128) -> (i128, bool) {
+        let Some(bounds) = OracleStorage::<H>::get_price_bounds(env, asset) else {
+            return (raw, false);
+        };
+        let clamped = raw.clamp(bounds.min, bounds.max);
+        if clamped == raw {
+            return (raw, false);
+        }
+        let event_type = Symbol::new(env, "price_clamped");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        H::track_event(
+            env,
+            event_type.clone(),
+            topics,
+            None,
+            Some(asset.clone()),
+            clamped,
+        );
+        env.events().publish(
+            (event_type, asset.clone()),
+            (
+                Symbol::new(env, "raw"),
+                raw,
+                Symbol::new(env, "clamped"),
+                clamped,
+            ),
+        );
+        (clamped, true)
+    }
+
+    /// Lock the last aggregated price of `asset` (every asset for `None`)
+    /// so it is served instead of live aggregation (guardian or admin)
+    pub fn freeze(
+        env: &Env,
+        caller: &Address,
+        asset: Option<Address>,
+    ) -> Result<OracleFreeze, ProtocolError> {
+        if H::guardian(env).as_ref() != Some(caller) {
+            H::require_admin(env, caller)?;
+        }
+        // Re-freezing would restart the window
+        if OracleStorage::<H>::get_freeze_entry(env, &asset).is_some() {
+            return Err(ProtocolError::InvalidOperation);
+        }
+        let freeze = OracleFreeze {
+            frozen_by: caller.clone(),
+            frozen_at: env.ledger().timestamp(),
+        };
+        OracleStorage::<H>::put_freeze_entry(env, &asset, Some(&freeze));
+        Self::emit_freeze(env, "oracle_frozen", &asset, caller);
+        Ok(freeze)
+    }
+
+    /// Propose or confirm an emergency price for `asset` (guardian and
+    /// admin). The first call proposes; the override takes effect once the
+    /// other role repeats the call with the same `price` and `expires_at`.
+    /// Any other call replaces the proposal. `expires_at` may be at most
+    /// [`MAX_EMERGENCY_PRICE_SECS`] away.
+    pub fn set_emergency_price(
+        env: &Env,
+        caller: &Address,
+        asset: &Address,
+        price: i128,
+        expires_at: u64,
+    ) -> Result<EmergencyPrice, ProtocolError> {
+        let is_guardian = H::guardian(env).as_ref() == Some(caller);
+        if !is_guardian {
+            H::require_admin(env, caller)?;
+        }
+        if price <= 0 {
+            return Err(ProtocolError::InvalidAmount);
+        }
+        let now = env.ledger().timestamp();
+        if expires_at <= now || expires_at - now > MAX_EMERGENCY_PRICE_SECS {
+            return Err(ProtocolError::InvalidParameters);
+        }
+
+        let pending = OracleStorage::<H>::get_emergency_price(env, asset, true);
+        let confirms = pending.as_ref().is_some_and(|p| {
+            let proposer_is_guardian = H::guardian(env).as_ref() == Some(&p.proposed_by);
+            p.price == price
+                && p.expires_at == expires_at
+                && p.proposed_by != *caller
+                && proposer_is_guardian != is_guardian
+        });
+        if let (true, Some(proposal)) = (confirms, pending) {
+            let active = EmergencyPrice {
+                confirmed_by: Some(caller.clone()),
+                ..proposal
+            };
+            OracleStorage::<H>::put_emergency_price(env, asset, true, None);
+            OracleStorage::<H>::put_emergency_price(env, asset, false, Some(&active));
+            Self::emit_emergency(env, "emergency_price_set", asset, caller, price);
+            return Ok(active);
+        }
+
+        let proposal = EmergencyPrice {
+            price,
+            expires_at,
+            proposed_by: caller.clone(),
+            confirmed_by: None,
+        };
+        OracleStorage::<H>::put_emergency_price(env, asset, true, Some(&proposal));
+        Self::emit_emergency(env, "emergency_price_proposed", asset, caller, price);
+        Ok(proposal)
+    }
+
+    /// Confirmed emergency price of `asset` while it has not expired. With
+    /// `persist`, an expired override is dropped and `emergency_price_expired`
+    /// emitted.
+    pub fn active_override(env: &Env, asset: &Address, persist: bool) -> Option<EmergencyPrice> {
+        let active = OracleStorage::<H>::get_emergency_price(env, asset, false)?;
+        if env.ledger().timestamp() <= active.expires_at {
+            return Some(active);
+        }
+        if persist {
+            OracleStorage::<H>::put_emergency_price(env, asset, false, None);
+            Self::emit_emergency(
+                env,
+                "emergency_price_expired",
+                asset,
+                &active.proposed_by,
+                active.price,
+            );
+        }
+        None
+    }
+
+    /// Record that a liquidation ran on the emergency price of `asset`
+    pub fn note_override_used(env: &Env, asset: &Address, liquidator: &Address, price: i128) {
+        Self::emit_emergency(env, "emergency_price_used", asset, liquidator, price);
+    }
+
+    fn emit_emergency(env: &Env, name: &str, asset: &Address, by: &Address, price: i128) {
+        let event_type = Symbol::new(env, name);
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        H::track_event(
+            env,
+            event_type.clone(),
+            topics,
+            Some(by.clone()),
+            Some(asset.clone()),
+            price,
+        );
+        env.events()
+            .publish((event_type, asset.clone()), (by.clone(), price));
+    }
+
+    /// Lift a freeze and drop the locked prices so the next read aggregates
+    /// live (admin only)
+    pub fn unfreeze(
+        env: &Env,
+        caller: &Address,
+        asset: Option<Address>,
+    ) -> Result<(), ProtocolError> {
+        H::require_admin(env, caller)?;
+        if OracleStorage::<H>::get_freeze_entry(env, &asset).is_none() {
+            return Err(ProtocolError::NotFound);
+        }
+        OracleStorage::<H>::put_freeze_entry(env, &asset, None);
+        let mut cache = OracleStorage::<H>::get_price_cache(env);
+        match &asset {
+            Some(asset) => {
+                cache.remove(asset.clone());
+            }
+            None => cache = soroban_sdk::Map::new(env),
+        }
+        OracleStorage::<H>::put_price_cache(env, &cache);
+        Self::emit_freeze(env, "oracle_unfrozen", &asset, caller);
+        Ok(())
+    }
+
+    /// Set how many observations are kept per asset for TWAP (admin only).
+    /// Buffers already longer are trimmed on their next observation.
+    pub fn set_twap_buffer_len(env: &Env, caller: &Address, len: u32) -> Result<(), ProtocolError> {
+        H::require_admin(env, caller)?;
+        if len == 0 || len > MAX_TWAP_OBSERVATIONS {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        OracleStorage::<H>::set_twap_buffer_len(env, len);
+        Ok(())
+    }
+
+    /// Set the weight of each new price in the EMA, in bps (admin only).
+    /// Must be in (0, 10000]; 10000 makes the EMA track the spot aggregate.
+    pub fn set_ema_alpha_bps(
+        env: &Env,
+        caller: &Address,
+        alpha_bps: i128,
+    ) -> Result<(), ProtocolError> {
+        H::require_admin(env, caller)?;
+        if alpha_bps <= 0 || alpha_bps > 10000 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        OracleStorage::<H>::set_ema_alpha_bps(env, alpha_bps);
+        Ok(())
+    }
+
+    /// Current EMA of `asset`, without updating it; `None` until a mode 2
+    /// aggregation seeds it
+    pub fn get_ema(env: &Env, asset: &Address) -> Option<i128> {
+        OracleStorage::<H>::get_ema(env, asset)
+    }
+
+    /// Fold `price` into `asset`'s EMA, seeding it with `price` the first
+    /// time. Only persisted aggregations move the stored EMA.
+    fn update_ema(env: &Env, asset: &Address, price: i128, persist: bool) -> i128 {
+        let ema = match OracleStorage::<H>::get_ema(env, asset) {
+            Some(old) => {
+                let alpha = OracleStorage::<H>::get_ema_alpha_bps(env);
+                price
+                    .saturating_mul(alpha)
+                    .saturating_add(old.saturating_mul(10000 - alpha))
+                    / 10000
+            }
+            None => price,
+        };
+        if persist {
+            OracleStorage::<H>::put_ema(env, asset, ema);
+        }
+        ema
+    }
+
+    /// Override the aggregation mode for one asset (admin only); pass
+    /// [`USE_GLOBAL_MODE`] to fall back to the global mode again
+    pub fn set_asset_mode(
+        env: &Env,
+        caller: &Address,
+        asset: &Address,
+        mode: i128,
+    ) -> Result<(), ProtocolError> {
+        H::require_admin(env, caller)?;
+        if mode != USE_GLOBAL_MODE && !(0..SUPPORTED_MODES.len() as i128).contains(&mode) {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        OracleStorage::<H>::set_asset_mode(env, asset, mode);
+        Ok(())
+    }
+
+    /// Mode `aggregate_price` uses for `asset`: its override, else the global mode
+    pub fn get_effective_mode(env: &Env, asset: &Address) -> i128 {
+        match OracleStorage::<H>::get_asset_mode(env, asset) {
+            USE_GLOBAL_MODE => OracleStorage::<H>::get_mode(env),
+            mode => mode,
+        }
+    }
+
+    /// Append an aggregated price to `asset`'s buffer, dropping the oldest
+    /// observations beyond the configured length. A second observation in
+    /// the same ledger second replaces the first.
+    fn record_observation(env: &Env, asset: &Address, price: i128) {
+        let now = env.ledger().timestamp();
+        let mut buffer = OracleStorage::<H>::get_twap_buffer(env, asset);
+        if buffer.last().is_some_and(|last| last.timestamp == now) {
+            buffer.pop_back();
+        }
+        buffer.push_back(PriceObservation {
+            timestamp: now,
+            price,
+        });
+        while buffer.len() > OracleStorage::<H>::get_twap_buffer_len(env) {
+            buffer.pop_front();
+        }
+        OracleStorage::<H>::put_twap_buffer(env, asset, &buffer);
+    }
+
+    /// Time-weighted average of `asset`'s observations over the last
+    /// `window_secs`. Each observation holds until the next one (the last
+    /// until now); observations older than the window are ignored. `None`
+    /// if there is none inside the window.
+    pub fn twap(env: &Env, asset: &Address, window_secs: u64) -> Option<i128> {
+        let now = env.ledger().timestamp();
+        let start = now.saturating_sub(window_secs);
+        let buffer = OracleStorage::<H>::get_twap_buffer(env, asset);
+        let mut weighted: i128 = 0;
+        let mut elapsed: i128 = 0;
+        let mut latest = None;
+        for i in 0..buffer.len() {
+            let obs = buffer.get(i).unwrap();
+            if obs.timestamp < start {
+                continue;
+            }
+            let until = buffer.get(i + 1).map_or(now, |next| next.timestamp);
+            let held = until.saturating_sub(obs.timestamp) as i128;
+            weighted = weighted.saturating_add(obs.price.saturating_mul(held));
+            elapsed += held;
+            latest = Some(obs.price);
+        }
+        if elapsed == 0 {
+            return latest;
+        }
+        Some(weighted / elapsed)
+    }
+
+    /// Set how long frozen prices stay usable (admin only)
+    pub fn set_max_freeze_secs(
+        env: &Env,
+        caller: &Address,
+        secs: u64,
+    ) -> Result<(), ProtocolError> {
+        H::require_admin(env, caller)?;
+        if secs == 0 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        OracleStorage::<H>::set_max_freeze_secs(env, secs);
+        Ok(())
+    }
+
+    /// Last aggregated price of `asset` and when it was computed, read from
+    /// the price cache without refreshing it
+    pub fn last_price(env: &Env, asset: &Address) -> Option<(i128, u64)> {
+        OracleStorage::<H>::get_price_cache(env).get(asset.clone())
+    }
+
+    /// [`Oracle::aggregate_price`], or when it yields nothing, the last good
+    /// aggregate if it is at most `max_age_secs` old (emitting
+    /// `stale_price_used`). Fallback oracle prices are never recorded as good,
+    /// so this only returns prices the sources agreed on.
+    pub fn get_price_with_grace(env: &Env, asset: &Address, max_age_secs: u64) -> Option<i128> {
+        if let Some(price) = Self::aggregate_price(env, asset) {
+            return Some(price);
+        }
+        let (price, at) = OracleStorage::<H>::get_last_good_price_at(env, asset)?;
+        let age = env.ledger().timestamp().saturating_sub(at);
+        if age > max_age_secs {
+            return None;
+        }
+        let event_type = Symbol::new(env, "stale_price_used");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        H::track_event(
+            env,
+            event_type.clone(),
+            topics,
+            None,
+            Some(asset.clone()),
+            price,
+        );
+        env.events().publish(
+            (event_type, asset.clone()),
+            (price, Symbol::new(env, "age"), age),
+        );
+        Some(price)
+    }
+
+    /// Whether any freeze covers `asset`, expired or not
+    pub fn is_frozen(env: &Env, asset: &Address) -> bool {
+        OracleStorage::<H>::get_freeze(env, asset).is_some()
+    }
+
+    /// The locked price while the freeze window lasts, `None` once it has
+    /// passed (or if nothing was cached when the freeze began)
+    fn frozen_price(env: &Env, asset: &Address, freeze: &OracleFreeze) -> Option<i128> {
+        let age = env.ledger().timestamp().saturating_sub(freeze.frozen_at);
+        if age > OracleStorage::<H>::get_max_freeze_secs(env) {
+            return None;
+        }
+        OracleStorage::<H>::get_price_cache(env)
+            .get(asset.clone())
+            .map(|(price, _)| price)
+    }
+
+    fn emit_freeze(env: &Env, name: &str, asset: &Option<Address>, caller: &Address) {
+        let event_type = Symbol::new(env, name);
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        H::track_event(
+            env,
+            event_type.clone(),
+            topics,
+            Some(caller.clone()),
+            asset.clone(),
+            0,
+        );
+        env.events().publish(
+            (event_type, Symbol::new(env, "oracle")),
+            (
+                Symbol::new(env, "asset"),
+                asset.clone(),
+                Symbol::new(env, "by"),
+                caller.clone(),
+            ),
+        );
+    }
+
+    /// Remove a source
+    pub fn remove_source(
+        env: &Env,
+        caller: &Address,
+        asset: &Address,
+        addr: &Address,
+    ) -> Result<(), ProtocolError> {
+        Self::require_oracle_admin(env, caller)?;
+        let list = OracleStorage::<H>::get_sources(env, asset);
+        let mut out: Vec<OracleSource> = Vec::new(env);
+        for s in list.iter() {
+            if s.addr != *addr {
+                out.push_back(s);
+            }
+        }
+        if out.len() == list.len() {
+            return Err(ProtocolError::NotFound);
+        }
+        OracleStorage::<H>::put_sources(env, asset, &out);
+        if out.is_empty() {
+            let mut assets = OracleStorage::<H>::get_assets(env);
+            if let Some(i) = assets.first_index_of(asset) {
+                assets.remove(i);
+                OracleStorage::<H>::put_assets(env, &assets);
+            }
+        }
+        let mut health = OracleStorage::<H>::get_source_health(env, asset);
+        if health.remove(addr.clone()).is_some() {
+            OracleStorage::<H>::put_source_health(env, asset, &health);
+        }
+        let mut decimals = OracleStorage::<H>::get_source_decimals(env, asset);
+        if decimals.remove(addr.clone()).is_some() {
+            OracleStorage::<H>::put_source_decimals(env, asset, &decimals);
+        }
+        OracleStorage::<H>::put_submission(env, asset, addr, None);
+        Ok(())
+    }
+
+    /// Declare the decimal places a registered source reports `asset` with
+    /// (admin only); its prices are normalized to [`PRICE_DECIMALS`]
+    pub fn set_source_decimals(
+        env: &Env,
+        caller: &Address,
+        asset: &Address,
+        addr: &Address,
+        decimals: u32,
+    ) -> Result<(), ProtocolError> {
+        H::require_admin(env, caller)?;
+        if decimals > MAX_SOURCE_DECIMALS {
+            return Err(ProtocolError::InvalidInput);
+        }
+        if !OracleStorage::<H>::get_sources(env, asset)
+            .iter()
+            .any(|s| s.addr == *addr)
+        {
+            return Err(ProtocolError::NotFound);
+        }
+        let mut map = OracleStorage::<H>::get_source_decimals(env, asset);
+        let old = map.get(addr.clone()).unwrap_or(PRICE_DECIMALS);
+        if decimals == PRICE_DECIMALS {
+            map.remove(addr.clone());
+        } else {
+            map.set(addr.clone(), decimals);
+        }
+        OracleStorage::<H>::put_source_decimals(env, asset, &map);
+        if old != decimals {
+            H::record_change(
+                env,
+                "oracle",
+                "source_decimals",
+                Some(asset),
+                old as i128,
+                decimals as i128,
+            );
+        }
+        Ok(())
+    }
+
+    /// Rescale a price reported with `decimals` places to [`PRICE_DECIMALS`];
+    /// `None` on overflow
+    pub fn normalize_price(price: i128, decimals: u32) -> Option<i128> {
+        if decimals >= PRICE_DECIMALS {
+            Some(price / 10i128.pow(decimals - PRICE_DECIMALS))
+        } else {
+            price.checked_mul(10i128.pow(PRICE_DECIMALS - decimals))
+        }
+    }
+
+    /// Number of sources registered for `asset`
+    pub fn get_source_count(env: &Env, asset: &Address) -> u32 {
+        OracleStorage::<H>::get_sources(env, asset).len()
+    }
+
+    /// Set how many sources one asset may register (admin only). Lowering it
+    /// keeps existing sources but blocks new ones until the count fits.
+    pub fn set_max_sources_per_asset(
+        env: &Env,
+        caller: &Address,
+        count: u32,
+    ) -> Result<(), ProtocolError> {
+        H::require_admin(env, caller)?;
+        if count == 0 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        OracleStorage::<H>::set_max_sources_per_asset(env, count);
+        Ok(())
+    }
+
+    /// Set how many healthy (fresh, positive-price) sources an aggregate
+    /// needs (admin only)
+    pub fn set_min_sources(env: &Env, caller: &Address, count: u32) -> Result<(), ProtocolError> {
+        H::require_admin(env, caller)?;
+        if count == 0 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        OracleStorage::<H>::set_min_sources(env, count);
+        Ok(())
+    }
+
+    /// Set the largest move from the last good aggregate accepted, in bps
+    /// (admin only)
+    pub fn set_max_deviation_bps(
+        env: &Env,
+        caller: &Address,
+        bps: i128,
+    ) -> Result<(), ProtocolError> {
+        H::require_admin(env, caller)?;
+        if bps <= 0 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        OracleStorage::<H>::set_max_deviation_bps(env, bps);
+        Ok(())
+    }
+
+    /// Set how many consecutive failed fetches deactivate a source (admin only)
+    pub fn set_max_source_failures(
+        env: &Env,
+        caller: &Address,
+        failures: u32,
+    ) -> Result<(), ProtocolError> {
+        H::require_admin(env, caller)?;
+        if failures == 0 {
+            return Err(ProtocolError::InvalidParameters);
+        }
+        OracleStorage::<H>::set_max_source_failures(env, failures);
+        Ok(())
+    }
+
+    /// Return a deactivated source to aggregation with a clean failure
+    /// count (admin only)
+    pub fn reactivate_source(
+        env: &Env,
+        caller: &Address,
+        asset: &Address,
+        addr: &Address,
+    ) -> Result<(), ProtocolError> {
+        H::require_admin(env, caller)?;
+        let mut health = OracleStorage::<H>::get_source_health(env, asset);
+        match health.get(addr.clone()) {
+            Some(entry) if entry.deactivated => {}
+            _ => return Err(ProtocolError::NotFound),
+        }
+        health.remove(addr.clone());
+        OracleStorage::<H>::put_source_health(env, asset, &health);
+        Self::emit_source(env, "oracle_source_reactivated", asset, addr, 0);
+        Ok(())
+    }
+
+    /// Mark `addr` as live for `asset` as of now. Authorized by the source
+    /// address, so the source contract or a relayer it delegates to can call
+    /// it. Returns the new heartbeat timestamp.
+    pub fn heartbeat(env: &Env, addr: &Address, asset: &Address) -> Result<u64, ProtocolError> {
+        addr.require_auth();
+        let now = env.ledger().timestamp();
+        let mut list = OracleStorage::<H>::get_sources(env, asset);
+        let index = list
+            .iter()
+            .position(|s| s.addr == *addr)
+            .ok_or(ProtocolError::NotFound)? as u32;
+        let mut source = list.get(index).unwrap();
+        source.last_heartbeat = now;
+        list.set(index, source);
+        OracleStorage::<H>::put_sources(env, asset, &list);
+
+        let event_type = Symbol::new(env, "oracle_heartbeat");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        H::track_event(
+            env,
+            event_type.clone(),
+            topics,
+            None,
+            Some(asset.clone()),
+            now as i128,
+        );
+        env.events().publish(
+            (event_type, asset.clone()),
+            (
+                Symbol::new(env, "source"),
+                addr.clone(),
+                Symbol::new(env, "at"),
+                now,
+            ),
+        );
+        Ok(now)
+    }
+
+    /// Record a price observed by registered source `reporter` at
+    /// `timestamp`, read instead of calling the source while `asset` is in
+    /// push mode. The price must be positive and the timestamp neither in
+    /// the future nor older than the source's last submission.
+    pub fn submit_price(
+        env: &Env,
+        reporter: &Address,
+        asset: &Address,
+        price: i128,
+        timestamp: u64,
+    ) -> Result<(), ProtocolError> {
+        reporter.require_auth();
+        if price <= 0 {
+            return Err(ProtocolError::InvalidAmount);
+        }
+        if !OracleStorage::<H>::get_sources(env, asset)
+            .iter()
+            .any(|s| s.addr == *reporter)
+        {
+            return Err(ProtocolError::NotFound);
+        }
+        let previous = OracleStorage::<H>::get_submission(env, asset, reporter);
+        if timestamp > env.ledger().timestamp() || previous.is_some_and(|p| timestamp < p.timestamp)
+        {
+            return Err(ProtocolError::InvalidInput);
+        }
+        OracleStorage::<H>::put_submission(
+            env,
+            asset,
+            reporter,
+            Some(&PriceSubmission { price, timestamp }),
+        );
+
+        let event_type = Symbol::new(env, "oracle_price_submitted");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        H::track_event(
+            env,
+            event_type.clone(),
+            topics,
+            None,
+            Some(asset.clone()),
+            price,
+        );
+        env.events().publish(
+            (event_type, asset.clone()),
+            (
+                Symbol::new(env, "source"),
+                reporter.clone(),
+                price,
+                timestamp,
+            ),
+        );
+        Ok(())
+    }
+
+    /// Switch `asset` between calling its sources and reading their
+    /// submissions (admin only)
+    pub fn set_push_mode(
+        env: &Env,
+        caller: &Address,
+        asset: &Address,
+        enabled: bool,
+    ) -> Result<(), ProtocolError> {
+        H::require_admin(env, caller)?;
+        OracleStorage::<H>::set_push_mode(env, asset, enabled);
+        Ok(())
+    }
+
+    fn emit_source(env: &Env, name: &str, asset: &Address, addr: &Address, failures: u32) {
+        let event_type = Symbol::new(env, name);
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        H::track_event(
+            env,
+            event_type.clone(),
+            topics,
+            None,
+            Some(asset.clone()),
+            failures as i128,
+        );
+        env.events().publish(
+            (event_type, asset.clone()),
+            (
+                Symbol::new(env, "source"),
+                addr.clone(),
+                Symbol::new(env, "failures"),
+                failures,
+            ),
+        );
+    }
+
+    /// Fetch prices from all sources (stubbed as calling `get_price()` on source contracts)
+    /// Policies:
+    /// - Staleness: drop sources whose last_heartbeat is older than TTL
+    /// - Push mode: read each source's latest submission instead of calling
+    ///   it, dropping it when the submitted timestamp is older than TTL
+    /// - Non-positive prices are ignored
+    /// - Readings outside the asset's reading bounds are dropped and counted
+    /// - A source whose call traps counts a failure; after the configured
+    ///   number in a row it is deactivated and skipped until reactivated
+    ///
+    /// Also returns how many sources were skipped as stale, so "no sources
+    /// registered" can be told apart from "all stale".
+    pub fn fetch_prices(env: &Env, asset: &Address) -> (Vec<i128>, u32) {
+        let (weighted, stale) = Self::fetch_weighted_samples(env, asset, true);
+        let mut prices: Vec<i128> = Vec::new(env);
+        for (sample, _) in weighted.iter() {
+            prices.push_back(sample.price);
+        }
+        (prices, stale)
+    }
+
+    /// Same policies as `fetch_prices`, keeping the source of each sample
+    pub fn fetch_samples(env: &Env, asset: &Address) -> Vec<PriceSample> {
+        let mut samples: Vec<PriceSample> = Vec::new(env);
+        for (sample, _) in Self::fetch_weighted_samples(env, asset, true).0.iter() {
+            samples.push_back(sample);
+        }
+        samples
+    }
+
+    /// Samples paired with their source's weight, and the number of sources
+    /// skipped as stale. Zero-weight sources are skipped without being called.
+    /// Prices are normalized to [`PRICE_DECIMALS`] from each source's decimals.
+    /// Unless `persist` is set, failures are skipped without being counted.
+    fn fetch_weighted_samples(
+        env: &Env,
+        asset: &Address,
+        persist: bool,
+    ) -> (Vec<(PriceSample, i128)>, u32) {
+        let list = OracleStorage::<H>::get_sources(env, asset);
+        let ttl = OracleStorage::<H>::get_heartbeat_ttl(env);
+        let now = env.ledger().timestamp();
+        let mut health = OracleStorage::<H>::get_source_health(env, asset);
+        let health_before = health.clone();
+        let decimals = OracleStorage::<H>::get_source_decimals(env, asset);
+        let push = OracleStorage::<H>::get_push_mode(env, asset);
+        let bounds = OracleStorage::<H>::get_reading_bounds(env, asset);
+        let mut samples: Vec<(PriceSample, i128)> = Vec::new(env);
+        let mut stale = 0u32;
+        for s in list.iter() {
+            if s.weight <= 0 {
+                continue;
+            }
+            let submission = if push {
+                OracleStorage::<H>::get_submission(env, asset, &s.addr)
+            } else {
+                None
+            };
+            if Self::is_stale(&s, &submission, push, now, ttl) {
+                stale += 1;
+                continue;
+            }
+            if health.get(s.addr.clone()).is_some_and(|h| h.deactivated) {
+                continue;
+            }
+            let price = match submission {
+                Some(sub) => sub.price,
+                None => {
+                    // Try calling a standard oracle interface: fn get_price(asset: Address) -> i128
+                    let args = vec![env, asset.clone().into_val(env)];
+                    let fetched = env.try_invoke_contract::<i128, soroban_sdk::Error>(
+                        &s.addr,
+                        &Symbol::new(env, "get_price"),
+                        args,
+                    );
+                    let Ok(Ok(price)) = fetched else {
+                        if persist {
+                            Self::record_source_failure(env, asset, &s.addr, &mut health);
+                        }
+                        continue;
+                    };
+                    health.remove(s.addr.clone());
+                    price
+                }
+            };
+            let places = decimals.get(s.addr.clone()).unwrap_or(PRICE_DECIMALS);
+            let price = Self::normalize_price(price, places).unwrap_or(0);
+            if price > 0 && (price < bounds.min || price > bounds.max) {
+                if persist {
+                    Self::reject_reading(env, asset, &s.addr, price, &bounds);
+                }
+                continue;
+            }
+            if price > 0 {
+                samples.push_back((
+                    PriceSample {
+                        source: s.addr.clone(),
+                        price,
+                    },
+                    s.weight,
+                ));
+            }
+        }
+        if persist && health != health_before {
+            OracleStorage::<H>::put_source_health(env, asset, &health);
+        }
+        (samples, stale)
+    }
+
+    /// Whether `source` is past the heartbeat TTL. In push mode the
+    /// submission's timestamp stands in for the heartbeat; a source that
+    /// never submitted counts as stale.
+    fn is_stale(
+        source: &OracleSource,
+        submission: &Option<PriceSubmission>,
+        push: bool,
+        now: u64,
+        ttl: u64,
+    ) -> bool {
+        let seen = match submission {
+            Some(sub) => Some(sub.timestamp),
+            None if push => None,
+            None => Some(source.last_heartbeat),
+        };
+        seen.is_none_or(|seen| now.saturating_sub(seen) > ttl)
+    }
+
+    /// Status of every source registered for `asset`. With `dry_run` each
+    /// live source of a pull-mode asset is asked for its price; failures
+    /// leave `last_price` empty and are not counted against the source.
+    /// Read only.
+    pub fn get_source_status(env: &Env, asset: &Address, dry_run: bool) -> Vec<SourceStatus> {
+        let ttl = OracleStorage::<H>::get_heartbeat_ttl(env);
+        let now = env.ledger().timestamp();
+        let decimals = OracleStorage::<H>::get_source_decimals(env, asset);
+        let push = OracleStorage::<H>::get_push_mode(env, asset);
+        let mut statuses = Vec::new(env);
+        for s in OracleStorage::<H>::get_sources(env, asset).iter() {
+            let submission = if push {
+                OracleStorage::<H>::get_submission(env, asset, &s.addr)
+            } else {
+                None
+            };
+            let is_stale = Self::is_stale(&s, &submission, push, now, ttl);
+            let raw = match &submission {
+                Some(sub) => Some(sub.price),
+                None if dry_run && !push && !is_stale => {
+                    let args = vec![env, asset.clone().into_val(env)];
+                    env.try_invoke_contract::<i128, soroban_sdk::Error>(
+                        &s.addr,
+                        &Symbol::new(env, "get_price"),
+                        args,
+                    )
+                    .ok()
+                    .and_then(|fetched| fetched.ok())
+                }
+                None => None,
+            };
+            let places = decimals.get(s.addr.clone()).unwrap_or(PRICE_DECIMALS);
+            statuses.push_back(SourceStatus {
+                addr: s.addr.clone(),
+                weight: s.weight,
+                last_heartbeat: submission.map_or(s.last_heartbeat, |sub| sub.timestamp),
+                is_stale,
+                last_price: raw.and_then(|price| Self::normalize_price(price, places)),
+            });
+        }
+        statuses
+    }
+
+    /// Global oracle settings in one read
+    pub fn get_config(env: &Env) -> OracleConfig {
+        OracleConfig {
+            heartbeat_ttl: OracleStorage::<H>::get_heartbeat_ttl(env),
+            mode: OracleStorage::<H>::get_mode(env),
+            min_sources: OracleStorage::<H>::get_min_sources(env),
+            deviation_bps: OracleStorage::<H>::get_deviation_bps(env),
+            max_deviation_bps: OracleStorage::<H>::get_max_deviation_bps(env),
+            ema_alpha_bps: OracleStorage::<H>::get_ema_alpha_bps(env),
+        }
+    }
+
+    /// Count a failed fetch, deactivating the source once it reaches the
+    /// configured number of consecutive failures
+    fn record_source_failure(
+        env: &Env,
+        asset: &Address,
+        addr: &Address,
+        health: &mut Map<Address, SourceHealth>,
+    ) {
+        let mut entry = health.get(addr.clone()).unwrap_or(SourceHealth {
+            consecutive_failures: 0,
+            deactivated: false,
+        });
+        entry.consecutive_failures += 1;
+        if entry.consecutive_failures >= OracleStorage::<H>::get_max_source_failures(env) {
+            entry.deactivated = true;
+            Self::emit_source(
+                env,
+                "oracle_source_deactivated",
+                asset,
+                addr,
+                entry.consecutive_failures,
+            );
+        }
+        health.set(addr.clone(), entry);
+    }
+
+    /// Set or (with `None`) clear the fallback oracle for `asset` (admin only)
+    pub fn set_fallback(
+        env: &Env,
+        caller: &Address,
+        asset: &Address,
+        fallback: Option<Address>,
+    ) -> Result<(), ProtocolError> {
+        H::require_admin(env, caller)?;
+        let key = OracleStorage::<H>::fallback_key(env, asset);
+        match fallback {
+            Some(addr) => {
+                H::set_address_param(env, "oracle", "fallback", &key, Some(asset), &addr);
+            }
+            None => {
+                if OracleStorage::<H>::get_fallback(env, asset).is_none() {
+                    return Err(ProtocolError::NotFound);
+                }
+                env.storage().instance().remove(&key);
+            }
+        }
+        Ok(())
+    }
+
+    /// Price of `asset` with its provenance: the aggregated price, or when
+    /// aggregation yields nothing, the positive price of the asset's
+    /// fallback oracle (emitting `oracle_fallback_used`). Frozen assets never
+    /// fall back. Fallback prices are not cached.
+    pub fn get_price_detailed(env: &Env, asset: &Address) -> Option<PricePoint> {
+        let primary = Self::aggregate_price(env, asset).map(|price| {
+            let timestamp = Self::last_price(env, asset)
+                .map(|(_, ts)| ts)
+                .unwrap_or_else(|| env.ledger().timestamp());
+            (price, timestamp)
+        });
+        Self::price_point(env, asset, primary, true)
+    }
+
+    /// [`Oracle::get_price_detailed`] without side effects on storage: a
+    /// stale price is aggregated from the sources but neither cached nor
+    /// recorded, and failing sources are not counted
+    pub fn peek_price_detailed(env: &Env, asset: &Address) -> Option<PricePoint> {
+        let primary = Self::peek_price(env, asset);
+        Self::price_point(env, asset, primary, false)
+    }
+
+    /// Current price of `asset` and its timestamp, read from the cache while
+    /// fresh (or frozen) and otherwise aggregated without writing anything
+    pub fn peek_price(env: &Env, asset: &Address) -> Option<(i128, u64)> {
+        if let Some(pinned) = Self::active_override(env, asset, false) {
+            return Some((pinned.price, env.ledger().timestamp()));
+        }
+        let cached = Self::last_price(env, asset);
+        if let Some(freeze) = OracleStorage::<H>::get_freeze(env, asset) {
+            Self::frozen_price(env, asset, &freeze)?;
+            return cached;
+        }
+        let now = env.ledger().timestamp();
+        if let Some((price, ts)) = cached {
+            if now.saturating_sub(ts) <= OracleStorage::<H>::get_price_cache_ttl(env) {
+                return Some((price, ts));
+            }
+        }
+        Self::compute_price(env, asset, false)
+            .ok()
+            .map(|details| (details.price, now))
+    }
+
+    fn price_point(
+        env: &Env,
+        asset: &Address,
+        primary: Option<(i128, u64)>,
+        persist: bool,
+    ) -> Option<PricePoint> {
+        if let Some((price, timestamp)) = primary {
+            return Some(PricePoint {
+                price,
+                source: PriceSource::Primary,
+                timestamp,
+            });
+        }
+        if Self::is_frozen(env, asset) {
+            return None;
+        }
+        let fallback = OracleStorage::<H>::get_fallback(env, asset)?;
+        let args = vec![env, asset.clone().into_val(env)];
+        let fetched = env.try_invoke_contract::<i128, soroban_sdk::Error>(
+            &fallback,
+            &Symbol::new(env, "get_price"),
+            args,
+        );
+        let Ok(Ok(price)) = fetched else {
+            return None;
+        };
+        if price <= 0 {
+            return None;
+        }
+
+        let event_type = Symbol::new(env, "oracle_fallback_used");
+        if persist {
+            let mut topics = Vec::new(env);
+            topics.push_back(event_type.clone());
+            H::track_event(
+                env,
+                event_type.clone(),
+                topics,
+                None,
+                Some(asset.clone()),
+                price,
+            );
+        }
+        env.events().publish(
+            (event_type, asset.clone()),
+            (Symbol::new(env, "fallback"), fallback, price),
+        );
+        Some(PricePoint {
+            price,
+            source: PriceSource::Fallback,
+            timestamp: env.ledger().timestamp(),
+        })
+    }
+
+    /// Aggregate prices using configured policy; the mode is the asset's
+    /// override when set, else the global one.
+    /// - mode 0: median weighted by source weight, with configurable trim
+    ///   (only with at least three sources) and deviation filter
+    /// - mode 1: time-weighted average of the recorded mode 0 prices over the
+    ///   configured window (see [`Oracle::twap`])
+    /// - mode 2: exponential moving average of the mode 0 prices, weighting
+    ///   each new one by the configured alpha (see [`Oracle::get_ema`])
+    ///
+    /// While frozen, the locked price is returned instead (see [`Oracle::freeze`]).
+    pub fn aggregate_price(env: &Env, asset: &Address) -> Option<i128> {
+        Self::aggregate_with_confidence(env, asset).map(|aggregated| aggregated.price)
+    }
+
+    /// [`Oracle::aggregate_price`] for several assets in one call. Assets
+    /// without a healthy price are left out rather than failing the batch.
+    /// Fails with `InvalidInput` past `ORACLE_MAX_BATCH_ASSETS` assets.
+    pub fn aggregate_prices(
+        env: &Env,
+        assets: Vec<Address>,
+    ) -> Result<Map<Address, i128>, ProtocolError> {
+        if assets.len() > defaults::ORACLE_MAX_BATCH_ASSETS {
+            return Err(ProtocolError::InvalidInput);
+        }
+        let mut prices = Map::new(env);
+        for asset in assets.iter() {
+            if let Some(price) = Self::aggregate_price(env, &asset) {
+                prices.set(asset, price);
+            }
+        }
+        // One metric per batch, so it counts queries rather than assets
+        let event_type = Symbol::new(env, "oracle_batch_priced");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        H::track_event(env, event_type, topics, None, None, prices.len() as i128);
+        Ok(prices)
+    }
+
+    /// [`Oracle::aggregate_price`] together with the spread of the samples
+    /// it came from. A cached price reports the spread it was aggregated
+    /// with; a frozen one reports no sources.
+    pub fn aggregate_with_confidence(env: &Env, asset: &Address) -> Option<AggregatedPrice> {
+        if let Some(pinned) = Self::active_override(env, asset, true) {
+            return Some(AggregatedPrice {
+                price: pinned.price,
+                spread_bps: 0,
+                num_sources: 0,
+                overridden: true,
+                timestamp: env.ledger().timestamp(),
+            });
+        }
+        if let Some(freeze) = OracleStorage::<H>::get_freeze(env, asset) {
+            let price = Self::frozen_price(env, asset, &freeze)?;
+            return Some(AggregatedPrice {
+                price,
+                spread_bps: 0,
+                num_sources: 0,
+                overridden: false,
+                timestamp: freeze.frozen_at,
+            });
+        }
+
+        // Cache check
+        let ttl = OracleStorage::<H>::get_price_cache_ttl(env);
+        let now = env.ledger().timestamp();
+        let mut cache = OracleStorage::<H>::get_price_cache(env);
+        if let Some((cached, ts)) = cache.get(asset.clone()) {
+            if now.saturating_sub(ts) <= ttl {
+                // cache hit
+                H::cache_updated(
+                    env,
+                    Symbol::new(env, "oracle_price_cache"),
+                    Symbol::new(env, "hit"),
+                );
+                let (spread_bps, num_sources) = OracleStorage::<H>::get_last_spread(env, asset);
+                return Some(AggregatedPrice {
+                    price: cached,
+                    spread_bps,
+                    num_sources,
+                    overridden: false,
+                    timestamp: ts,
+                });
+            } else {
+                // evict stale
+                cache.remove(asset.clone());
+                OracleStorage::<H>::put_price_cache(env, &cache);
+                H::cache_updated(
+                    env,
+                    Symbol::new(env, "oracle_price_cache"),
+                    Symbol::new(env, "evict"),
+                );
+            }
+        }
+
+        Self::aggregate_price_detailed(env, asset).map(|details| AggregatedPrice {
+            price: details.price,
+            spread_bps: Self::spread_bps(env, &details.samples),
+            num_sources: details.samples.len(),
+            overridden: false,
+            timestamp: now,
+        })
+    }
+
+    /// (max - min) * 10000 / median of the sample prices; 0 with fewer than
+    /// two samples
+    pub fn spread_bps(env: &Env, samples: &Vec<PriceSample>) -> i128 {
+        if samples.len() < 2 {
+            return 0;
+        }
+        let mut prices: Vec<(i128, i128)> = Vec::new(env);
+        for sample in samples.iter() {
+            prices.push_back((sample.price, 1));
+        }
+        Self::sort_by_price(&mut prices);
+        let median = Self::weighted_median(&prices);
+        let (min, max) = (prices.first().unwrap().0, prices.last().unwrap().0);
+        if median <= 0 {
+            return 0;
+        }
+        (max - min).saturating_mul(10000) / median
+    }
+
+    /// Fail with `PriceDeviationTooHigh` when the sources of `asset`'s
+    /// current price disagree by more than the risk config's maximum spread
+    /// (0 disables the check). An asset without a price passes.
+    pub fn require_agreement(env: &Env, asset: &Address) -> Result<(), ProtocolError> {
+        if H::max_price_spread_bps(env) == 0 {
+            return Ok(());
+        }
+        match Self::aggregate_with_confidence(env, asset) {
+            Some(aggregated) => Self::check_spread(env, asset, aggregated.spread_bps),
+            None => Ok(()),
+        }
+    }
+
+    /// Fail with `PriceDeviationTooHigh` when `spread_bps` exceeds the risk
+    /// config's maximum spread (0 disables the check)
+    pub fn check_spread(env: &Env, asset: &Address, spread_bps: i128) -> Result<(), ProtocolError> {
+        let max = H::max_price_spread_bps(env);
+        if max == 0 || spread_bps <= max {
+            return Ok(());
+        }
+        let event_type = Symbol::new(env, "oracle_spread_exceeded");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        H::track_event(
+            env,
+            event_type.clone(),
+            topics,
+            None,
+            Some(asset.clone()),
+            spread_bps,
+        );
+        env.events()
+            .publish((event_type, asset.clone()), (spread_bps, max));
+        Err(ProtocolError::PriceDeviationTooHigh)
+    }
+
+    /// Aggregate a fresh price, bypassing the cache, and report the samples it
+    /// was computed from. The result still refreshes the cache. While frozen,
+    /// the locked price is returned with `frozen` set. `None` when no source
+    /// answers or the deviation check rejects the price.
+    pub fn aggregate_price_detailed(env: &Env, asset: &Address) -> Option<PriceDetails> {
+        Self::try_aggregate_price_detailed(env, asset).ok()
+    }
+
+    /// [`Oracle::aggregate_price_detailed`], failing with `OracleFailure`
+    /// when fewer healthy sources than the configured minimum answer (at
+    /// least one is always needed) and with `PriceDeviationTooHigh` when the new
+    /// aggregate moved more than the configured maximum from the last good
+    /// one. A rejected price is neither cached nor recorded for TWAP.
+    pub fn try_aggregate_price_detailed(
+        env: &Env,
+        asset: &Address,
+    ) -> Result<PriceDetails, ProtocolError> {
+        Self::compute_price(env, asset, true)
+    }
+
+    /// Aggregation behind [`Oracle::try_aggregate_price_detailed`]. Unless
+    /// `persist` is set nothing is written: the price is not cached nor
+    /// recorded, source failures are not counted and events are published
+    /// without being tracked.
+    fn compute_price(
+        env: &Env,
+        asset: &Address,
+        persist: bool,
+    ) -> Result<PriceDetails, ProtocolError> {
+        if let Some(pinned) = Self::active_override(env, asset, persist) {
+            return Ok(PriceDetails {
+                price: pinned.price,
+                samples: Vec::new(env),
+                clamped: false,
+                frozen: false,
+                overridden: true,
+            });
+        }
+        if let Some(freeze) = OracleStorage::<H>::get_freeze(env, asset) {
+            return Self::frozen_price(env, asset, &freeze)
+                .map(|price| PriceDetails {
+                    price,
+                    samples: Vec::new(env),
+                    clamped: false,
+                    frozen: true,
+                    overridden: false,
+                })
+                .ok_or(ProtocolError::OracleFailure);
+        }
+        let (weighted, stale) = Self::fetch_weighted_samples(env, asset, persist);
+        let min_sources = OracleStorage::<H>::get_min_sources(env);
+        if weighted.is_empty() || weighted.len() < min_sources {
+            if persist {
+                Self::emit_insufficient_sources(env, asset, weighted.len(), stale);
+            }
+            return Err(ProtocolError::OracleFailure);
+        }
+        let mut samples: Vec<PriceSample> = Vec::new(env);
+        let mut prices: Vec<(i128, i128)> = Vec::new(env);
+        for (sample, weight) in weighted.iter() {
+            prices.push_back((sample.price, weight));
+            samples.push_back(sample);
+        }
+        let (mut price, clamped) =
+            Self::clamp_to_bounds(env, asset, Self::aggregate_samples(env, prices));
+        Self::check_deviation(env, asset, price, persist)?;
+        if persist {
+            OracleStorage::<H>::put_last_good_price(env, asset, price);
+            Self::record_observation(env, asset, price);
+        }
+        match Self::get_effective_mode(env, asset) {
+            1 => {
+                let window = OracleStorage::<H>::get_twap_window(env).max(0) as u64;
+                price = Self::twap(env, asset, window).unwrap_or(price);
+            }
+            2 => price = Self::update_ema(env, asset, price, persist),
+            _ => {}
+        }
+        if !persist {
+            return Ok(PriceDetails {
+                price,
+                samples,
+                clamped,
+                frozen: false,
+                overridden: false,
+            });
+        }
+
+        let mut cache = OracleStorage::<H>::get_price_cache(env);
+        cache.set(asset.clone(), (price, env.ledger().timestamp()));
+        OracleStorage::<H>::put_price_cache(env, &cache);
+        OracleStorage::<H>::put_last_spread(
+            env,
+            asset,
+            Self::spread_bps(env, &samples),
+            samples.len(),
+        );
+        H::cache_updated(
+            env,
+            Symbol::new(env, "oracle_price_cache"),
+            Symbol::new(env, "set"),
+        );
+        Ok(PriceDetails {
+            price,
+            samples,
+            clamped,
+            frozen: false,
+            overridden: false,
+        })
+    }
+
+    fn emit_insufficient_sources(env: &Env, asset: &Address, healthy: u32, stale: u32) {
+        let event_type = Symbol::new(env, "oracle_insufficient_sources");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        H::track_event(
+            env,
+            event_type.clone(),
+            topics,
+            None,
+            Some(asset.clone()),
+            healthy as i128,
+        );
+        env.events().publish(
+            (event_type, asset.clone()),
+            (
+                Symbol::new(env, "healthy"),
+                healthy,
+                Symbol::new(env, "stale"),
+                stale,
+                Symbol::new(env, "registered"),
+                OracleStorage::<H>::get_sources(env, asset).len(),
+            ),
+        );
+    }
+
+    /// Reject `price` if it moved more than the configured maximum from the
+    /// last good aggregate; a move of exactly the maximum passes. The first
+    /// price for an asset has no baseline and always passes.
+    fn check_deviation(
+        env: &Env,
+        asset: &Address,
+        price: i128,
+        persist: bool,
+    ) -> Result<(), ProtocolError> {
+        let Some(last) = OracleStorage::<H>::get_last_good_price(env, asset) else {
+            return Ok(());
+        };
+        if last <= 0 {
+            return Ok(());
+        }
+        let deviation_bps = (price - last).abs().saturating_mul(10000) / last;
+        if deviation_bps <= OracleStorage::<H>::get_max_deviation_bps(env) {
+            return Ok(());
+        }
+        if !persist {
+            return Err(ProtocolError::PriceDeviationTooHigh);
+        }
+
+        let event_type = Symbol::new(env, "price_deviation_detected");
+        let mut topics = Vec::new(env);
+        topics.push_back(event_type.clone());
+        H::track_event(
+            env,
+            event_type.clone(),
+            topics,
+            None,
+            Some(asset.clone()),
+            deviation_bps,
+        );
+        env.events().publish(
+            (event_type, asset.clone()),
+            (
+                Symbol::new(env, "old_price"),
+                last,
+                Symbol::new(env, "new_price"),
+                price,
+                Symbol::new(env, "deviation_bps"),
+                deviation_bps,
+            ),
+        );
+        Err(ProtocolError::PriceDeviationTooHigh)
+    }
+
+    /// Aggregate `(price, weight)` samples; weights are positive
+    fn aggregate_samples(env: &Env, mut prices: Vec<(i128, i128)>) -> i128 {
+        let n_usize = prices.len() as usize;
+
+        // Sort ascending by price (simple O(n^2) acceptable for small n)
+        Self::sort_by_price(&mut prices);
+
+        // Trim highest and lowest samples per configuration, only once at
+        // least three weighted sources are left
+        let trim = OracleStorage::<H>::get_trim_count(env).max(0) as usize;
+        let (start, end) = if n_usize >= 3 && n_usize > trim {
+            (trim, n_usize - trim)
+        } else {
+            (0, n_usize)
+        };
+        if end <= start {
+            return prices.get((n_usize / 2) as u32).unwrap().0;
+        }
+
+        // Deviation filter: compute the weighted median of the trimmed set,
+        // then remove values beyond deviation_bps
+        let trimmed = prices.slice(start as u32..end as u32);
+        let med = Self::weighted_median(&trimmed);
+
+        let deviation_bps = OracleStorage::<H>::get_deviation_bps(env).max(0);
+        let mut filtered: Vec<(i128, i128)> = Vec::new(env);
+        for (p, weight) in trimmed.iter() {
+            let diff = (p - med).abs();
+            // allow within med * deviation_bps / 10000
+            let max_diff = (med.abs().saturating_mul(deviation_bps)).saturating_div(10000);
+            if diff <= max_diff {
+                filtered.push_back((p, weight));
+            }
+        }
+        if filtered.is_empty() {
+            med
+        } else {
+            Self::weighted_median(&filtered)
+        }
+    }
+
+    fn sort_by_price(prices: &mut Vec<(i128, i128)>) {
+        let n = prices.len();
+        for i in 0..n {
+            for j in i + 1..n {
+                let (a, b) = (prices.get(i).unwrap(), prices.get(j).unwrap());
+                if a.0 > b.0 {
+                    prices.set(i, b);
+                    prices.set(j, a);
+                }
+            }
+        }
+    }
+
+    /// Weighted median of `(price, weight)` samples sorted by price: the price
+    /// at which the cumulative weight crosses half the total. Landing exactly
+    /// on half averages with the next price, so equal weights give the plain
+    /// median.
+    fn weighted_median(prices: &Vec<(i128, i128)>) -> i128 {
+        let total = prices
+            .iter()
+            .fold(0i128, |sum, (_, weight)| sum.saturating_add(weight));
+        let mut cumulative: i128 = 0;
+        for i in 0..prices.len() {
+            let (price, weight) = prices.get(i).unwrap();
+            cumulative = cumulative.saturating_add(weight);
+            let doubled = cumulative.saturating_mul(2);
+            if doubled == total {
+                if let Some((next, _)) = prices.get(i + 1) {
+                    return (price + next) / 2;
+                }
+                return price;
+            }
+            if doubled > total {
+                return price;
+            }
+        }
+        prices.last().map(|(price, _)| price).unwrap_or(0)
+    }
+}
@@ -0,0 +1,315 @@
+use crate::{Oracle, OracleHost, OracleSource, OracleStorage, PRICE_SCALE};
+use soroban_sdk::testutils::{Address as _, Ledger};
+use soroban_sdk::{contract, contractimpl, vec, Address, Env, Symbol, Vec};
+use stellarlend_core::ProtocolError;
+
+/// Contract the oracle's storage lives in during tests
+#[contract]
+pub struct Harness;
+
+#[contractimpl]
+impl Harness {
+    pub fn noop(_env: Env) {}
+}
+
+#[contract]
+pub struct MockSource;
+
+#[contractimpl]
+impl MockSource {
+    pub fn set_price(env: Env, asset: Address, price: i128) {
+        env.storage().instance().set(&asset, &price);
+    }
+
+    pub fn get_price(env: Env, asset: Address) -> i128 {
+        env.storage().instance().get(&asset).unwrap_or(0)
+    }
+}
+
+/// Host keeping the admin in storage and counting reported changes and
+/// tracked events
+struct TestHost;
+
+impl TestHost {
+    fn changes(env: &Env) -> u32 {
+        env.storage()
+            .instance()
+            .get(&Symbol::new(env, "changes"))
+            .unwrap_or(0)
+    }
+
+    fn events(env: &Env, event_type: &Symbol) -> u32 {
+        env.storage().instance().get(event_type).unwrap_or(0)
+    }
+}
+
+impl OracleHost for TestHost {
+    fn require_admin(env: &Env, caller: &Address) -> Result<(), ProtocolError> {
+        let admin: Option<Address> = env.storage().instance().get(&Symbol::new(env, "admin"));
+        if admin.as_ref() != Some(caller) {
+            return Err(ProtocolError::Unauthorized);
+        }
+        Ok(())
+    }
+
+    fn guardian(_env: &Env) -> Option<Address> {
+        None
+    }
+
+    fn max_price_spread_bps(_env: &Env) -> i128 {
+        0
+    }
+
+    fn track_event(
+        env: &Env,
+        event_type: Symbol,
+        _topics: Vec<Symbol>,
+        _user: Option<Address>,
+        _asset: Option<Address>,
+        _amount: i128,
+    ) {
+        let count = Self::events(env, &event_type) + 1;
+        env.storage().instance().set(&event_type, &count);
+    }
+
+    fn cache_updated(_env: &Env, _cache: Symbol, _action: Symbol) {}
+
+    fn record_change(
+        env: &Env,
+        _scope: &str,
+        _key: &str,
+        _asset: Option<&Address>,
+        _old: i128,
+        _new: i128,
+    ) {
+        let count = Self::changes(env) + 1;
+        env.storage()
+            .instance()
+            .set(&Symbol::new(env, "changes"), &count);
+    }
+
+    fn record_address_change(
+        env: &Env,
+        scope: &str,
+        key: &str,
+        asset: Option<&Address>,
+        _old: Option<Address>,
+        _new: &Address,
+    ) {
+        Self::record_change(env, scope, key, asset, 0, 0);
+    }
+}
+
+type TestOracle = Oracle<TestHost>;
+type TestStorage = OracleStorage<TestHost>;
+
+#[test]
+fn test_aggregates_through_the_host() {
+    let env = Env::default();
+    let harness = env.register(Harness, ());
+    let admin = Address::generate(&env);
+    let asset = Address::generate(&env);
+    let source = env.register(MockSource, ());
+    env.as_contract(&source, || {
+        MockSource::set_price(env.clone(), asset.clone(), 2 * PRICE_SCALE);
+    });
+
+    env.as_contract(&harness, || {
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, "admin"), &admin);
+
+        let stranger = Address::generate(&env);
+        assert_eq!(
+            TestOracle::set_source(
+                &env,
+                &stranger,
+                &asset,
+                OracleSource::new(source.clone(), 1, 0)
+            ),
+            Err(ProtocolError::Unauthorized)
+        );
+        TestOracle::set_source(
+            &env,
+            &admin,
+            &asset,
+            OracleSource::new(source.clone(), 1, 0),
+        )
+        .unwrap();
+        assert_eq!(
+            TestOracle::aggregate_price(&env, &asset),
+            Some(2 * PRICE_SCALE)
+        );
+        assert_eq!(
+            TestOracle::last_price(&env, &asset),
+            Some((2 * PRICE_SCALE, 0))
+        );
+
+        // Parameter writes report through the host only when the value moves
+        let before = TestHost::changes(&env);
+        TestStorage::set_max_deviation_bps(&env, 100);
+        TestStorage::set_max_deviation_bps(&env, 100);
+        assert_eq!(TestHost::changes(&env), before + 1);
+    });
+}
+
+#[test]
+fn test_normalize_price_rescales_to_price_decimals() {
+    assert_eq!(TestOracle::normalize_price(123, 6), Some(12_300));
+    assert_eq!(
+        TestOracle::normalize_price(PRICE_SCALE * 100, 10),
+        Some(PRICE_SCALE)
+    );
+    assert_eq!(TestOracle::normalize_price(42, 8), Some(42));
+    assert_eq!(TestOracle::normalize_price(i128::MAX, 0), None);
+}
+
+#[test]
+fn test_aggregate_prices_skips_unpriced_assets() {
+    let env = Env::default();
+    let harness = env.register(Harness, ());
+    let admin = Address::generate(&env);
+    let priced = Address::generate(&env);
+    let unpriced = Address::generate(&env);
+    let source = env.register(MockSource, ());
+    env.as_contract(&source, || {
+        MockSource::set_price(env.clone(), priced.clone(), 3 * PRICE_SCALE);
+    });
+
+    env.as_contract(&harness, || {
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, "admin"), &admin);
+        TestOracle::set_source(
+            &env,
+            &admin,
+            &priced,
+            OracleSource::new(source.clone(), 1, 0),
+        )
+        .unwrap();
+
+        let batch = Symbol::new(&env, "oracle_batch_priced");
+        let prices =
+            TestOracle::aggregate_prices(&env, vec![&env, priced.clone(), unpriced.clone()])
+                .unwrap();
+        assert_eq!(prices.len(), 1);
+        assert_eq!(prices.get(priced.clone()), Some(3 * PRICE_SCALE));
+        assert!(!prices.contains_key(unpriced.clone()));
+        assert_eq!(TestHost::events(&env, &batch), 1);
+
+        let mut oversized = Vec::new(&env);
+        for _ in 0..=crate::defaults::ORACLE_MAX_BATCH_ASSETS {
+            oversized.push_back(priced.clone());
+        }
+        assert_eq!(
+            TestOracle::aggregate_prices(&env, oversized),
+            Err(ProtocolError::InvalidInput)
+        );
+        assert_eq!(TestHost::events(&env, &batch), 1);
+    });
+}
+
+#[test]
+fn test_grace_price_covers_stale_sources() {
+    let env = Env::default();
+    let harness = env.register(Harness, ());
+    let admin = Address::generate(&env);
+    let asset = Address::generate(&env);
+    let source = env.register(MockSource, ());
+    env.as_contract(&source, || {
+        MockSource::set_price(env.clone(), asset.clone(), 5 * PRICE_SCALE);
+    });
+
+    env.as_contract(&harness, || {
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, "admin"), &admin);
+        TestOracle::set_source(
+            &env,
+            &admin,
+            &asset,
+            OracleSource::new(source.clone(), 1, 0),
+        )
+        .unwrap();
+        assert_eq!(
+            TestOracle::get_price_with_grace(&env, &asset, 60),
+            Some(5 * PRICE_SCALE)
+        );
+        assert_eq!(
+            TestStorage::get_last_good_price_at(&env, &asset),
+            Some((5 * PRICE_SCALE, 0))
+        );
+
+        // Past the heartbeat TTL the only source is stale
+        env.ledger()
+            .with_mut(|li| li.timestamp = crate::defaults::ORACLE_HEARTBEAT_TTL_SECS + 1);
+        assert_eq!(TestOracle::aggregate_price(&env, &asset), None);
+        let stale = Symbol::new(&env, "stale_price_used");
+        assert_eq!(TestOracle::get_price_with_grace(&env, &asset, 60), None);
+        assert_eq!(TestHost::events(&env, &stale), 0);
+        assert_eq!(
+            TestOracle::get_price_with_grace(&env, &asset, 3600),
+            Some(5 * PRICE_SCALE)
+        );
+        assert_eq!(TestHost::events(&env, &stale), 1);
+    });
+}
+
+#[test]
+fn test_ema_mode_smooths_aggregates() {
+    let env = Env::default();
+    let harness = env.register(Harness, ());
+    let admin = Address::generate(&env);
+    let asset = Address::generate(&env);
+    let source = env.register(MockSource, ());
+    let quote = |price: i128| {
+        env.as_contract(&source, || {
+            MockSource::set_price(env.clone(), asset.clone(), price);
+        });
+    };
+    quote(100 * PRICE_SCALE);
+
+    env.as_contract(&harness, || {
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, "admin"), &admin);
+        TestOracle::set_source(
+            &env,
+            &admin,
+            &asset,
+            OracleSource::new(source.clone(), 1, 0),
+        )
+        .unwrap();
+        TestOracle::set_asset_mode(&env, &admin, &asset, 2).unwrap();
+        for alpha in [0, 10001] {
+            assert_eq!(
+                TestOracle::set_ema_alpha_bps(&env, &admin, alpha),
+                Err(ProtocolError::InvalidParameters)
+            );
+        }
+
+        // The first observation seeds the EMA
+        assert_eq!(TestOracle::get_ema(&env, &asset), None);
+        assert_eq!(
+            TestOracle::aggregate_price(&env, &asset),
+            Some(100 * PRICE_SCALE)
+        );
+        assert_eq!(TestOracle::get_ema(&env, &asset), Some(100 * PRICE_SCALE));
+    });
+
+    // Past the cache TTL, a 10% jump moves the EMA by alpha (20%) of it
+    quote(110 * PRICE_SCALE);
+    env.ledger()
+        .with_mut(|li| li.timestamp = crate::defaults::ORACLE_PRICE_CACHE_TTL_SECS + 1);
+    env.as_contract(&harness, || {
+        assert_eq!(
+            TestOracle::aggregate_price(&env, &asset),
+            Some(102 * PRICE_SCALE)
+        );
+        assert_eq!(TestOracle::get_ema(&env, &asset), Some(102 * PRICE_SCALE));
+        assert_eq!(
+            TestStorage::get_last_good_price(&env, &asset),
+            Some(110 * PRICE_SCALE)
+        );
+    });
+}